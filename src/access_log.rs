@@ -0,0 +1,229 @@
+use {
+    crate::{
+        config::{AccessLogConfig, AccessLogFormat},
+        error::Error,
+        request::client_ip,
+        state::AppState,
+    },
+    actix_web::{
+        body::{BodySize, MessageBody},
+        dev::{ServiceRequest, ServiceResponse},
+        http::header::{REFERER, USER_AGENT},
+        middleware::Next,
+        web, Error as ActixError, HttpRequest,
+    },
+    chrono::{DateTime, Utc},
+    log::{error, warn},
+    std::{
+        fs::{self, File, OpenOptions},
+        io::Write,
+        path::{Path, PathBuf},
+        sync::{Mutex, MutexGuard},
+    },
+};
+
+/// File with a line per request, in a format web log analyzers like GoAccess
+/// and AWStats understand, rotated once it gets too big
+pub struct AccessLog {
+    config: AccessLogConfig,
+    path: PathBuf,
+    file: Mutex<File>,
+}
+impl AccessLog {
+    /// The log configured, or `None` if it's off
+    pub fn open(config: &AccessLogConfig) -> Result<Option<Self>, Error> {
+        let path = match &config.path {
+            Some(path) => PathBuf::from(path),
+            None => return Ok(None),
+        };
+        Ok(Some(Self {
+            config: config.clone(),
+            file: Mutex::new(append(&path)?),
+            path,
+        }))
+    }
+
+    /// Add a line, then rotate if the file has grown past the limit
+    pub fn write(&self, line: &str) -> Result<(), Error> {
+        let mut file = self.file();
+        file.write_all(format!("{}\n", line).as_bytes())?;
+        if self.config.max_bytes > 0 && file.metadata()?.len() >= self.config.max_bytes {
+            *file = self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// Shift `access.log.1` to `access.log.2` and so on, dropping the oldest,
+    /// and start a new file
+    fn rotate(&self) -> Result<File, Error> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        if self.config.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.config.keep).rev() {
+                if rotated(n).exists() {
+                    fs::rename(rotated(n), rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        Ok(append(&self.path)?)
+    }
+
+    /// The file, even if a request panicked while writing, since lines are
+    /// written whole
+    fn file(&self) -> MutexGuard<'_, File> {
+        self.file.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the access log after a panic");
+            poisoned.into_inner()
+        })
+    }
+}
+
+fn append(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new().append(true).create(true).open(path)
+}
+
+/// Middleware writing each request to the access log, if there is one
+pub async fn log_access(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, ActixError> {
+    let state = req.app_data::<web::Data<AppState<'_>>>().cloned();
+    let res = next.call(req).await?;
+    let state = match &state {
+        Some(state) => state,
+        None => return Ok(res),
+    };
+    if let Some(access_log) = &state.access_log {
+        let bytes = match res.response().body().size() {
+            BodySize::Sized(bytes) => Some(bytes),
+            _ => None,
+        };
+        let line = format_line(
+            access_log.config.format,
+            res.request(),
+            res.status().as_u16(),
+            bytes,
+            state.clock.now(),
+        );
+        if let Err(e) = access_log.write(&line) {
+            error!("Could not write to the access log: {}", e);
+        }
+    }
+    Ok(res)
+}
+
+/// One request as a line of the Common or Combined Log Format
+pub fn format_line(
+    format: AccessLogFormat,
+    req: &HttpRequest,
+    status: u16,
+    bytes: Option<u64>,
+    at: DateTime<Utc>,
+) -> String {
+    let header = |name| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.replace('"', "\\\""))
+            .unwrap_or_else(|| "-".to_string())
+    };
+    let target = redact_secret(req.uri().path_and_query().map_or("/", |p| p.as_str()));
+    let common = format!(
+        "{} - - [{}] \"{} {} {:?}\" {} {}",
+        client_ip(req),
+        at.format("%d/%b/%Y:%H:%M:%S %z"),
+        req.method(),
+        target,
+        req.version(),
+        status,
+        bytes.map_or_else(|| "-".to_string(), |bytes| bytes.to_string()),
+    );
+    match format {
+        AccessLogFormat::Common => common,
+        AccessLogFormat::Combined => format!(
+            "{} \"{}\" \"{}\"",
+            common,
+            header(REFERER),
+            header(USER_AGENT)
+        ),
+    }
+}
+
+/// The request's path and query with a webhook's `secret` parameter hidden,
+/// so the log doesn't hand it to whoever reads it
+fn redact_secret(target: &str) -> String {
+    let (path, query) = match target.split_once('?') {
+        Some(split) => split,
+        None => return target.to_string(),
+    };
+    let query = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some(("secret", _)) => "secret=redacted",
+            _ => pair,
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("{}?{}", path, query)
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        actix_web::{http::Version, test::TestRequest},
+        chrono::TimeZone,
+    };
+
+    #[test]
+    fn line() {
+        let req = TestRequest::get()
+            .uri("/photos?page=2")
+            .peer_addr("203.0.113.7:51234".parse().unwrap())
+            .insert_header((USER_AGENT, "Mozilla/5.0 \"test\""))
+            .to_http_request();
+        let at = Utc.with_ymd_and_hms(2024, 6, 1, 13, 55, 36).unwrap();
+        assert_eq!(
+            format_line(AccessLogFormat::Common, &req, 200, Some(2326), at),
+            "203.0.113.7 - - [01/Jun/2024:13:55:36 +0000] \"GET /photos?page=2 HTTP/1.1\" 200 2326"
+        );
+        let req = TestRequest::post()
+            .uri("/rsvp")
+            .version(Version::HTTP_2)
+            .insert_header((REFERER, "https://example.com/"))
+            .to_http_request();
+        assert!(format_line(AccessLogFormat::Combined, &req, 303, None, at)
+            .ends_with("\"POST /rsvp HTTP/2.0\" 303 - \"https://example.com/\" \"-\""));
+        let req = TestRequest::post()
+            .uri("/webhooks/bounce?a=1&secret=change-me")
+            .to_http_request();
+        assert!(format_line(AccessLogFormat::Common, &req, 200, None, at)
+            .contains("\"POST /webhooks/bounce?a=1&secret=redacted HTTP/1.1\""));
+    }
+
+    #[test]
+    fn rotate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let config = AccessLogConfig {
+            path: Some(path.to_str().unwrap().to_string()),
+            max_bytes: 10,
+            keep: 2,
+            ..AccessLogConfig::default()
+        };
+        let log = AccessLog::open(&config).unwrap().unwrap();
+        for line in ["first line", "second", "third line", "fourth"] {
+            log.write(line).unwrap();
+        }
+        let read = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("access.log"), "fourth\n");
+        assert_eq!(read("access.log.1"), "second\nthird line\n");
+        assert_eq!(read("access.log.2"), "first line\n");
+        assert!(!dir.path().join("access.log.3").exists());
+        assert!(AccessLog::open(&AccessLogConfig::default())
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,713 @@
+use {
+    crate::{
+        config::TlsConfig,
+        error::Error,
+        tls::{new_key, write_private, Certificate, Certificates},
+    },
+    actix_web::{
+        rt::time::sleep,
+        web::{self, Bytes},
+        HttpRequest, HttpResponse,
+    },
+    awc::{
+        http::header::{CONTENT_TYPE, LOCATION},
+        Client, ClientResponse,
+    },
+    base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine},
+    log::{info, warn},
+    openssl::{
+        bn::BigNumContext,
+        ec::EcKey,
+        ecdsa::EcdsaSig,
+        hash::{hash, MessageDigest},
+        pkey::{PKey, Private},
+        stack::Stack,
+        x509::{extension::SubjectAlternativeName, X509Req, X509},
+    },
+    serde::{de::DeserializeOwned, Deserialize},
+    serde_json::{json, Value},
+    std::{
+        collections::HashMap,
+        fs,
+        path::PathBuf,
+        sync::{Arc, Mutex, MutexGuard},
+        time::Duration,
+    },
+};
+
+/// Times to check whether the CA is done validating or issuing
+const POLL_ATTEMPTS: usize = 30;
+
+/// Where the CA fetches the response to each HTTP-01 challenge
+const CHALLENGE_PATH: &str = "/.well-known/acme-challenge/";
+
+/// Endpoints of the CA
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+#[derive(Deserialize)]
+struct Order {
+    #[serde(default)]
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Authorization {
+    status: String,
+    challenges: Vec<Challenge>,
+}
+
+#[derive(Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    #[serde(default)]
+    token: String,
+}
+
+/// Gets certificates for the configured domains from an ACME CA like
+/// Let's Encrypt, answering its HTTP-01 challenges, and keeps them renewed
+pub struct Acme {
+    config: TlsConfig,
+    /// Served over HTTPS, starting with the last certificate saved, or a
+    /// self-signed one until the first is issued
+    pub certificates: Arc<Certificates>,
+    /// Key authorizations by token, for the CA to fetch while validating
+    challenges: Mutex<HashMap<String, String>>,
+    poll_interval: Duration,
+}
+impl Acme {
+    pub fn new(config: &TlsConfig) -> Result<Self, Error> {
+        fs::create_dir_all(&config.dir)?;
+        let acme = Self {
+            config: config.clone(),
+            certificates: Arc::new(Certificates::new(Certificate::self_signed(
+                &config.domains,
+            )?)),
+            challenges: Mutex::default(),
+            poll_interval: Duration::from_secs(2),
+        };
+        if let Some(certificate) = Certificate::load(&acme.cert_path(), &acme.key_path())? {
+            acme.certificates.set(certificate);
+        }
+        Ok(acme)
+    }
+
+    /// Response to an HTTP-01 challenge from the CA, served at
+    /// `/.well-known/acme-challenge/{token}`
+    pub fn challenge(&self, token: &str) -> Option<String> {
+        self.challenges().get(token).cloned()
+    }
+
+    /// The same page on the HTTPS site, keeping the host asked for if it's
+    /// one of the domains, so the redirect can't be pointed elsewhere
+    pub fn https_url(&self, host: &str, path: &str) -> String {
+        let host = host.rsplit_once(':').map_or(host, |(host, _)| host);
+        let host = match self.config.domains.iter().find(|d| d.as_str() == host) {
+            Some(domain) => domain,
+            None => self.config.domains.first().map_or(host, String::as_str),
+        };
+        match self.config.bind.rsplit_once(':') {
+            Some((_, "443")) | None => format!("https://{}{}", host, path),
+            Some((_, port)) => format!("https://{}:{}{}", host, port, path),
+        }
+    }
+
+    /// Get a new certificate if there isn't one yet, or it expires soon,
+    /// returning whether it was renewed
+    pub async fn renew_if_needed(&self) -> Result<bool, Error> {
+        if let Some(certificate) = Certificate::load(&self.cert_path(), &self.key_path())? {
+            if !certificate.expires_within(self.config.renew_before_days)? {
+                return Ok(false);
+            }
+        }
+        let certificate = self.issue().await?;
+        certificate.save(&self.cert_path(), &self.key_path())?;
+        self.certificates.set(certificate);
+        info!(
+            "Got a new certificate for {}",
+            self.config.domains.join(", ")
+        );
+        Ok(true)
+    }
+
+    /// Order a certificate for the domains, following RFC 8555
+    async fn issue(&self) -> Result<Certificate, Error> {
+        let client = Client::builder().timeout(Duration::from_secs(30)).finish();
+        let mut response = client
+            .get(&self.config.acme_directory)
+            .send()
+            .await
+            .map_err(acme_error)?;
+        let directory = response.json::<Directory>().await.map_err(acme_error)?;
+        let mut session = Session::new(client, self.account_key()?, directory.new_nonce);
+
+        let contact = match self.config.contact_email.as_str() {
+            "" => vec![],
+            email => vec![format!("mailto:{}", email)],
+        };
+        let response = session
+            .post(
+                &directory.new_account,
+                Some(json!({ "termsOfServiceAgreed": true, "contact": contact })),
+            )
+            .await?;
+        session.kid = Some(location(&response)?);
+
+        let identifiers = self
+            .config
+            .domains
+            .iter()
+            .map(|domain| json!({ "type": "dns", "value": domain }))
+            .collect::<Vec<_>>();
+        let response = session
+            .post(
+                &directory.new_order,
+                Some(json!({ "identifiers": identifiers })),
+            )
+            .await?;
+        let order_url = location(&response)?;
+        let order: Order = parse(&response.1)?;
+
+        for url in &order.authorizations {
+            let authorization: Authorization = parse(&session.post(url, None).await?.1)?;
+            if authorization.status == "valid" {
+                continue;
+            }
+            let challenge = authorization
+                .challenges
+                .iter()
+                .find(|c| c.kind == "http-01")
+                .ok_or_else(|| Error::Acme(format!("no HTTP-01 challenge in {}", url)))?;
+            let key_authorization = format!("{}.{}", challenge.token, session.thumbprint()?);
+            self.challenges()
+                .insert(challenge.token.clone(), key_authorization);
+            let result: Result<Value, Error> = async {
+                session.post(&challenge.url, Some(json!({}))).await?;
+                self.poll(&mut session, url).await
+            }
+            .await;
+            self.challenges().remove(&challenge.token);
+            result?;
+        }
+
+        let key = PKey::from_ec_key(new_key()?)?;
+        let csr = csr(&self.config.domains, &key)?;
+        session
+            .post(
+                &order.finalize,
+                Some(json!({ "csr": URL_SAFE_NO_PAD.encode(csr) })),
+            )
+            .await?;
+        let order: Order = self.poll(&mut session, &order_url).await?;
+        let url = order
+            .certificate
+            .ok_or_else(|| Error::Acme("no certificate in the finished order".to_string()))?;
+        let (_, chain) = session.post(&url, None).await?;
+        let certificate = Certificate {
+            chain: X509::stack_from_pem(&chain)?,
+            key,
+        };
+        certificate.check()?;
+        Ok(certificate)
+    }
+
+    /// Check on an authorization or order until it's valid
+    async fn poll<T: DeserializeOwned>(
+        &self,
+        session: &mut Session,
+        url: &str,
+    ) -> Result<T, Error> {
+        for _ in 0..POLL_ATTEMPTS {
+            let value: Value = parse(&session.post(url, None).await?.1)?;
+            match value["status"].as_str() {
+                Some("valid") => return Ok(serde_json::from_value(value)?),
+                Some("invalid") => {
+                    return Err(Error::Acme(format!("{} is invalid: {}", url, value)));
+                }
+                _ => sleep(self.poll_interval).await,
+            }
+        }
+        Err(Error::Acme(format!("gave up waiting on {}", url)))
+    }
+
+    /// The ACME account's key, created the first time
+    fn account_key(&self) -> Result<EcKey<Private>, Error> {
+        let path = PathBuf::from(&self.config.dir).join("account.pem");
+        match fs::read(&path) {
+            Ok(pem) => Ok(EcKey::private_key_from_pem(&pem)?),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                let key = new_key()?;
+                write_private(&path, &key.private_key_to_pem()?)?;
+                Ok(key)
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn cert_path(&self) -> PathBuf {
+        PathBuf::from(&self.config.dir).join("cert.pem")
+    }
+
+    fn key_path(&self) -> PathBuf {
+        PathBuf::from(&self.config.dir).join("key.pem")
+    }
+
+    fn challenges(&self) -> MutexGuard<'_, HashMap<String, String>> {
+        self.challenges.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the ACME challenges after a panic");
+            poisoned.into_inner()
+        })
+    }
+}
+
+/// Everything served on the plain HTTP redirect listener: the CA's challenges,
+/// and a permanent redirect to HTTPS for anything else
+pub async fn redirect_to_https(req: HttpRequest, acme: web::Data<Acme>) -> HttpResponse {
+    if let Some(token) = req.path().strip_prefix(CHALLENGE_PATH) {
+        return match acme.challenge(token) {
+            Some(key_authorization) => HttpResponse::Ok()
+                .content_type("application/octet-stream")
+                .body(key_authorization),
+            None => HttpResponse::NotFound().finish(),
+        };
+    }
+    let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+    HttpResponse::MovedPermanently()
+        .insert_header((LOCATION, acme.https_url(req.connection_info().host(), path)))
+        .finish()
+}
+
+/// Location header and body of a response from the CA
+type Response = (Option<String>, Bytes);
+
+/// Signed requests to the CA with the account key
+struct Session {
+    client: Client,
+    key: EcKey<Private>,
+    new_nonce: String,
+    nonce: Option<String>,
+    /// Account URL, identifying the key once the account exists
+    kid: Option<String>,
+}
+impl Session {
+    fn new(client: Client, key: EcKey<Private>, new_nonce: String) -> Self {
+        Self {
+            client,
+            key,
+            new_nonce,
+            nonce: None,
+            kid: None,
+        }
+    }
+
+    /// Send a JWS-signed request, or a POST-as-GET without a payload,
+    /// retrying once if the CA rejects the nonce
+    async fn post(&mut self, url: &str, payload: Option<Value>) -> Result<Response, Error> {
+        let mut retried = false;
+        loop {
+            let nonce = match self.nonce.take() {
+                Some(nonce) => nonce,
+                None => {
+                    let response = self
+                        .client
+                        .head(&self.new_nonce)
+                        .send()
+                        .await
+                        .map_err(acme_error)?;
+                    header(&response, "replay-nonce")
+                        .ok_or_else(|| Error::Acme("no nonce from the CA".to_string()))?
+                }
+            };
+            let body = self.sign(url, &nonce, payload.as_ref())?;
+            let mut response = self
+                .client
+                .post(url)
+                .insert_header((CONTENT_TYPE, "application/jose+json"))
+                .send_body(body)
+                .await
+                .map_err(acme_error)?;
+            self.nonce = header(&response, "replay-nonce");
+            let location = header(&response, LOCATION.as_str());
+            let body = response.body().limit(1 << 20).await.map_err(acme_error)?;
+            if response.status().is_success() {
+                return Ok((location, body));
+            }
+            let problem: Value = serde_json::from_slice(&body).unwrap_or_default();
+            if !retried && problem["type"] == "urn:ietf:params:acme:error:badNonce" {
+                retried = true;
+                continue;
+            }
+            return Err(Error::Acme(format!(
+                "{} from {}: {}",
+                response.status(),
+                url,
+                problem["detail"].as_str().unwrap_or_default()
+            )));
+        }
+    }
+
+    /// Flattened JWS with the account key, identified by its URL once known
+    fn sign(&self, url: &str, nonce: &str, payload: Option<&Value>) -> Result<String, Error> {
+        let mut protected = json!({ "alg": "ES256", "nonce": nonce, "url": url });
+        match &self.kid {
+            Some(kid) => protected["kid"] = json!(kid),
+            None => protected["jwk"] = self.jwk()?,
+        }
+        let protected = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&protected)?);
+        let payload = match payload {
+            Some(payload) => URL_SAFE_NO_PAD.encode(serde_json::to_vec(payload)?),
+            None => String::default(),
+        };
+        let digest = hash(
+            MessageDigest::sha256(),
+            format!("{}.{}", protected, payload).as_bytes(),
+        )?;
+        let signature = EcdsaSig::sign(&digest, &self.key)?;
+        let mut bytes = signature.r().to_vec_padded(32)?;
+        bytes.extend(signature.s().to_vec_padded(32)?);
+        Ok(json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": URL_SAFE_NO_PAD.encode(bytes),
+        })
+        .to_string())
+    }
+
+    /// Public account key, with its members in the order required for the
+    /// thumbprint
+    fn jwk(&self) -> Result<Value, Error> {
+        let mut ctx = BigNumContext::new()?;
+        let (mut x, mut y) = (openssl::bn::BigNum::new()?, openssl::bn::BigNum::new()?);
+        self.key
+            .public_key()
+            .affine_coordinates(self.key.group(), &mut x, &mut y, &mut ctx)?;
+        Ok(json!({
+            "crv": "P-256",
+            "kty": "EC",
+            "x": URL_SAFE_NO_PAD.encode(x.to_vec_padded(32)?),
+            "y": URL_SAFE_NO_PAD.encode(y.to_vec_padded(32)?),
+        }))
+    }
+
+    /// RFC 7638 thumbprint of the account key, for key authorizations
+    fn thumbprint(&self) -> Result<String, Error> {
+        let jwk = serde_json::to_vec(&self.jwk()?)?;
+        Ok(URL_SAFE_NO_PAD.encode(hash(MessageDigest::sha256(), &jwk)?))
+    }
+}
+
+/// Certificate signing request for the domains, in DER
+fn csr(domains: &[String], key: &PKey<Private>) -> Result<Vec<u8>, Error> {
+    let mut builder = X509Req::builder()?;
+    builder.set_pubkey(key)?;
+    let mut names = SubjectAlternativeName::new();
+    for domain in domains {
+        names.dns(domain);
+    }
+    let mut extensions = Stack::new()?;
+    extensions.push(names.build(&builder.x509v3_context(None))?)?;
+    builder.add_extensions(&extensions)?;
+    builder.sign(key, MessageDigest::sha256())?;
+    Ok(builder.build().to_der()?)
+}
+
+fn header<T>(response: &ClientResponse<T>, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+fn location(response: &Response) -> Result<String, Error> {
+    response
+        .0
+        .clone()
+        .ok_or_else(|| Error::Acme("no Location from the CA".to_string()))
+}
+
+fn parse<T: DeserializeOwned>(body: &Bytes) -> Result<T, Error> {
+    Ok(serde_json::from_slice(body)?)
+}
+
+fn acme_error<E: std::fmt::Display>(error: E) -> Error {
+    Error::Acme(error.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        actix_web::{
+            http::{Method, StatusCode},
+            App, HttpServer,
+        },
+        openssl::{asn1::Asn1Time, bn::BigNum, ec::EcGroup, nid::Nid, x509::X509NameBuilder},
+        std::sync::Mutex as StdMutex,
+    };
+
+    /// A CA that checks each request's signature, and validates the
+    /// challenge by asking the client for its key authorization directly
+    #[derive(Default)]
+    struct MockCa {
+        base: StdMutex<String>,
+        acme: StdMutex<Option<Arc<Acme>>>,
+        validated: StdMutex<bool>,
+        certificate: StdMutex<Option<String>>,
+        requests: StdMutex<Vec<String>>,
+    }
+
+    /// Payload of a JWS, after checking it's signed by the key it names
+    fn verify(body: &[u8]) -> (Value, Value) {
+        let jws: Value = serde_json::from_slice(body).unwrap();
+        let decode = |field: &str| {
+            URL_SAFE_NO_PAD
+                .decode(jws[field].as_str().unwrap())
+                .unwrap()
+        };
+        let protected: Value = serde_json::from_slice(&decode("protected")).unwrap();
+        assert_eq!(protected["alg"], "ES256");
+        assert!(protected["nonce"].is_string());
+        if let Some(jwk) = protected.get("jwk") {
+            let coordinate = |name: &str| {
+                BigNum::from_slice(&URL_SAFE_NO_PAD.decode(jwk[name].as_str().unwrap()).unwrap())
+                    .unwrap()
+            };
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+            let key = EcKey::from_public_key_affine_coordinates(
+                &group,
+                &coordinate("x"),
+                &coordinate("y"),
+            )
+            .unwrap();
+            let signature = decode("signature");
+            let signature = EcdsaSig::from_private_components(
+                BigNum::from_slice(&signature[..32]).unwrap(),
+                BigNum::from_slice(&signature[32..]).unwrap(),
+            )
+            .unwrap();
+            let signed = format!(
+                "{}.{}",
+                jws["protected"].as_str().unwrap(),
+                jws["payload"].as_str().unwrap()
+            );
+            let digest = hash(MessageDigest::sha256(), signed.as_bytes()).unwrap();
+            assert!(signature.verify(&digest, &key).unwrap());
+        } else {
+            assert!(protected["kid"].as_str().unwrap().ends_with("/account/1"));
+        }
+        let payload = match decode("payload") {
+            payload if payload.is_empty() => Value::Null,
+            payload => serde_json::from_slice(&payload).unwrap(),
+        };
+        (protected, payload)
+    }
+
+    /// Certificate for the CSR's key, signed by a throwaway CA
+    fn sign(csr: &[u8]) -> String {
+        let csr = X509Req::from_der(csr).unwrap();
+        let ca_key = PKey::from_ec_key(new_key().unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, "Mock CA")
+            .unwrap();
+        let name = name.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&csr.public_key().unwrap()).unwrap();
+        let not_before = Asn1Time::days_from_now(0).unwrap();
+        let not_after = Asn1Time::days_from_now(90).unwrap();
+        builder.set_not_before(&not_before).unwrap();
+        builder.set_not_after(&not_after).unwrap();
+        builder.sign(&ca_key, MessageDigest::sha256()).unwrap();
+        String::from_utf8(builder.build().to_pem().unwrap()).unwrap()
+    }
+
+    async fn mock_ca(req: HttpRequest, body: web::Bytes, ca: web::Data<MockCa>) -> HttpResponse {
+        let base = ca.base.lock().unwrap().clone();
+        let path = req.path().to_string();
+        ca.requests.lock().unwrap().push(path.clone());
+        let mut response = HttpResponse::Ok();
+        response.insert_header(("Replay-Nonce", format!("nonce-{}", body.len())));
+        if req.method() == Method::HEAD {
+            return response.finish();
+        }
+        if path == "/directory" {
+            return response.json(json!({
+                "newNonce": format!("{}/nonce", base),
+                "newAccount": format!("{}/account", base),
+                "newOrder": format!("{}/order", base),
+            }));
+        }
+        let (_, payload) = verify(&body);
+        let order = |status: &str| {
+            json!({
+                "status": status,
+                "authorizations": [format!("{}/authz/1", base)],
+                "finalize": format!("{}/finalize/1", base),
+                "certificate": format!("{}/cert/1", base),
+            })
+        };
+        match path.as_str() {
+            "/account" => {
+                assert_eq!(payload["contact"], json!(["mailto:us@example.com"]));
+                response
+                    .insert_header((LOCATION, format!("{}/account/1", base)))
+                    .json(json!({ "status": "valid" }))
+            }
+            "/order" => {
+                assert_eq!(payload["identifiers"][0]["value"], "example.com");
+                response
+                    .insert_header((LOCATION, format!("{}/order/1", base)))
+                    .json(order("pending"))
+            }
+            "/authz/1" => {
+                let status = match *ca.validated.lock().unwrap() {
+                    true => "valid",
+                    false => "pending",
+                };
+                response.json(json!({
+                    "status": status,
+                    "challenges": [{
+                        "type": "http-01",
+                        "url": format!("{}/challenge/1", base),
+                        "token": "token1",
+                    }],
+                }))
+            }
+            "/challenge/1" => {
+                let acme = ca.acme.lock().unwrap().clone().unwrap();
+                let key_authorization = acme.challenge("token1").unwrap();
+                assert!(key_authorization.starts_with("token1."));
+                *ca.validated.lock().unwrap() = true;
+                response.json(json!({ "status": "processing" }))
+            }
+            "/finalize/1" => {
+                let csr = URL_SAFE_NO_PAD
+                    .decode(payload["csr"].as_str().unwrap())
+                    .unwrap();
+                *ca.certificate.lock().unwrap() = Some(sign(&csr));
+                response.json(order("processing"))
+            }
+            "/order/1" => match ca.certificate.lock().unwrap().is_some() {
+                true => response.json(order("valid")),
+                false => response.json(order("pending")),
+            },
+            "/cert/1" => response
+                .content_type("application/pem-certificate-chain")
+                .body(ca.certificate.lock().unwrap().clone().unwrap()),
+            _ => HttpResponse::NotFound().finish(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn redirect() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = TlsConfig {
+            domains: vec!["example.com".to_string(), "www.example.com".to_string()],
+            dir: dir.path().to_str().unwrap().to_string(),
+            ..TlsConfig::default()
+        };
+        let acme = Acme::new(&config).unwrap();
+        assert_eq!(
+            acme.https_url("www.example.com", "/fetch?name=Maria"),
+            "https://www.example.com/fetch?name=Maria"
+        );
+        assert_eq!(
+            acme.https_url("evil.example.net:80", "/"),
+            "https://example.com/"
+        );
+        acme.challenges()
+            .insert("token1".to_string(), "token1.thumbprint".to_string());
+
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(acme))
+                .default_service(web::to(redirect_to_https)),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::get()
+            .uri("/photos")
+            .insert_header(("Host", "www.example.com"))
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            resp.headers().get(LOCATION).unwrap(),
+            "https://www.example.com/photos"
+        );
+        let req = actix_web::test::TestRequest::get()
+            .uri("/.well-known/acme-challenge/token1")
+            .to_request();
+        let body = actix_web::test::call_and_read_body(&app, req).await;
+        assert_eq!(body, "token1.thumbprint");
+        let req = actix_web::test::TestRequest::get()
+            .uri("/.well-known/acme-challenge/token2")
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn issue() {
+        let ca = web::Data::new(MockCa::default());
+        let server = HttpServer::new({
+            let ca = ca.clone();
+            move || {
+                App::new()
+                    .app_data(ca.clone())
+                    .default_service(web::to(mock_ca))
+            }
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let base = format!("http://{}", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+        *ca.base.lock().unwrap() = base.clone();
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = TlsConfig {
+            domains: vec!["example.com".to_string()],
+            contact_email: "us@example.com".to_string(),
+            acme_directory: format!("{}/directory", base),
+            dir: dir.path().to_str().unwrap().to_string(),
+            ..TlsConfig::default()
+        };
+        let acme = Arc::new(Acme {
+            poll_interval: Duration::from_millis(10),
+            ..Acme::new(&config).unwrap()
+        });
+        *ca.acme.lock().unwrap() = Some(Arc::clone(&acme));
+        let placeholder = acme.certificates.current().chain[0].to_der().unwrap();
+
+        assert!(acme.renew_if_needed().await.unwrap());
+        let issued = acme.certificates.current();
+        assert_ne!(issued.chain[0].to_der().unwrap(), placeholder);
+        assert!(acme.challenge("token1").is_none());
+        assert!(dir.path().join("account.pem").exists());
+        assert!(dir.path().join("cert.pem").exists());
+
+        // the saved certificate is good for 90 days, so isn't renewed again
+        assert!(!acme.renew_if_needed().await.unwrap());
+        let reloaded = Acme::new(&config).unwrap();
+        assert_eq!(
+            reloaded.certificates.current().chain[0].to_der().unwrap(),
+            issued.chain[0].to_der().unwrap()
+        );
+        assert_eq!(
+            ca.requests.lock().unwrap().first().map(String::as_str),
+            Some("/directory")
+        );
+    }
+}
@@ -0,0 +1,1464 @@
+use {
+    crate::{
+        analytics,
+        announcement::{self, Announcement, AnnouncementParams, Segment},
+        archive::{self, Frozen},
+        audit::EnteredBy,
+        backup::{self, Backups},
+        banner::BannerParams,
+        childcare,
+        csvdb::CsvDb,
+        dedupe, dietary, doctor,
+        email::{render_email, EMAIL_TEMPLATES},
+        error::Error,
+        export::{self, MailingList},
+        fake, feeds, flash,
+        form::{self, Form, RsvpForm},
+        inserts::{self, Insert, InsertsParams},
+        lockout,
+        model::{
+            name_key, AdminContext, AnnouncementContext, ChangesParams, CheckinContext,
+            CheckinGuest, EmailStatus, ExportParams, FormFor, ListParams, LoginParams, MergeParams,
+            NameParams, NotesParams, PageLinks, QuickContext, RecentResponse, RsvpModel,
+            SeedParams, ShortLink, TableParams,
+        },
+        preview::sample_context,
+        request::{client_ip, feature_enabled},
+        seating, session, shuttle,
+        site_password::local_path,
+        songs,
+        source::{self, Source},
+        state::{AppState, PAGE_TEMPLATES},
+        tasks::Task,
+        thanks::{self, ThanksParams},
+        waves,
+    },
+    actix_session::{Session, SessionExt},
+    actix_web::{
+        dev::{Payload, ServiceRequest},
+        error::{ErrorForbidden, InternalError},
+        http::{
+            header::{ContentDisposition, HeaderName, ACCEPT, LOCATION, ORIGIN},
+            Method,
+        },
+        web, Error as ActixError, HttpRequest, HttpResponse, HttpResponseBuilder, Result,
+    },
+    actix_web_httpauth::{
+        extractors::{
+            basic::{BasicAuth, Config as BasicConfig},
+            AuthenticationError,
+        },
+        middleware::HttpAuthentication,
+    },
+    chrono::SecondsFormat,
+    log::{error, info, warn},
+    serde::Serialize,
+    serde_json::{json, Value},
+    std::{cmp::Reverse, collections::BTreeSet, sync::Arc},
+    tracing::instrument,
+};
+
+/// Most audit log entries shown on the dashboard
+const RECENT_CHANGES: usize = 20;
+
+/// Most requested songs shown on the dashboard
+const TOP_SONGS: usize = 10;
+
+/// Latest responses shown on the condensed page
+const QUICK_RESPONSES: usize = 10;
+
+/// Shown on the sign in page to an IP address locked out for guessing
+static LOCKED_OUT_MESSAGE: &str = "Too many wrong passwords, please try again later";
+
+/// Request header where browsers say which site a request came from
+static SEC_FETCH_SITE: HeaderName = HeaderName::from_static("sec-fetch-site");
+
+/// Routes for the admin pages, all behind basic auth or the login page
+pub fn admin_config(config: &mut web::ServiceConfig) {
+    config
+        .service(
+            web::resource("/admin/login")
+                .guard(feature_enabled(|features| features.admin))
+                .route(web::get().to(login))
+                .route(web::post().to(handle_login)),
+        )
+        .service(
+            web::resource("/admin/logout")
+                .guard(feature_enabled(|features| features.admin))
+                .route(web::post().to(handle_logout)),
+        );
+    config.service(
+        web::scope("/admin")
+            .guard(feature_enabled(|features| features.admin))
+            .wrap(HttpAuthentication::with_fn(authenticate))
+            .service(web::resource("").route(web::get().to(dashboard)))
+            .service(web::resource("/quick").route(web::get().to(quick)))
+            .service(
+                web::resource("/announce")
+                    .route(web::get().to(announce))
+                    .route(web::post().to(handle_announce)),
+            )
+            .service(web::resource("/preview/{template:.*}").route(web::get().to(preview)))
+            .service(web::resource("/rsvp").route(web::post().to(handle_rsvp)))
+            .service(web::resource("/rsvp/{token}").route(web::get().to(rsvp)))
+            .service(
+                web::resource("/checkin")
+                    .route(web::get().to(checkin))
+                    .route(web::post().to(handle_checkin)),
+            )
+            .service(web::resource("/checkin/count").route(web::get().to(checkin_count)))
+            .service(web::resource("/duplicates").route(web::get().to(duplicates)))
+            .service(web::resource("/duplicates.json").route(web::get().to(duplicates_json)))
+            .service(web::resource("/merge").route(web::post().to(handle_merge)))
+            .service(web::resource("/export/{list}").route(web::get().to(export)))
+            .service(web::resource("/rsvps.csv").route(web::get().to(rsvps_csv)))
+            .service(web::resource("/links").route(web::get().to(links)))
+            .service(web::resource("/inserts.pdf").route(web::get().to(inserts_pdf)))
+            .service(web::resource("/rsvps.ics").route(web::get().to(rsvps_ics)))
+            .service(web::resource("/feed.xml").route(web::get().to(feed)))
+            .service(web::resource("/notes").route(web::post().to(handle_notes)))
+            .service(web::resource("/table").route(web::post().to(handle_table)))
+            .service(web::resource("/seating.csv").route(web::get().to(seating_csv)))
+            .service(web::resource("/seating.pdf").route(web::get().to(seating_pdf)))
+            .service(web::resource("/kitchen-alert.csv").route(web::get().to(kitchen_alert_csv)))
+            .service(web::resource("/kitchen-alert.pdf").route(web::get().to(kitchen_alert_pdf)))
+            .service(web::resource("/shuttle/{name}.csv").route(web::get().to(shuttle_csv)))
+            .service(web::resource("/childcare.csv").route(web::get().to(childcare_csv)))
+            .service(web::resource("/songs.csv").route(web::get().to(songs_csv)))
+            .service(
+                web::resource("/thanks")
+                    .route(web::get().to(thanks))
+                    .route(web::post().to(handle_thanks)),
+            )
+            .service(web::resource("/thanks.csv").route(web::get().to(thanks_csv)))
+            .service(web::resource("/archive").route(web::post().to(handle_archive)))
+            .service(web::resource("/doctor.json").route(web::get().to(doctor_json)))
+            .service(web::resource("/reload").route(web::post().to(handle_reload)))
+            .service(
+                web::resource("/quarantine/{id}/approve").route(web::post().to(handle_approve)),
+            )
+            .service(web::resource("/quarantine/{id}/reject").route(web::post().to(handle_reject)))
+            .service(web::resource("/seed").route(web::post().to(seed)))
+            .service(web::resource("/undo").route(web::post().to(handle_undo)))
+            .service(
+                web::resource("/snapshots")
+                    .route(web::get().to(snapshots))
+                    .route(web::post().to(handle_snapshot)),
+            )
+            .service(web::resource("/snapshots/{name}").route(web::get().to(snapshot)))
+            .service(web::resource("/changes.csv").route(web::get().to(changes_csv)))
+            .service(
+                web::resource("/snapshots/{name}/restore").route(web::post().to(handle_restore)),
+            )
+            .service(web::resource("/tasks").route(web::get().to(tasks)))
+            .service(web::resource("/tasks/{name}/run").route(web::post().to(handle_run_task)))
+            .service(web::resource("/metrics").route(web::get().to(metrics)))
+            .service(web::resource("/analytics").route(web::get().to(analytics)))
+            .service(
+                web::resource("/banners")
+                    .route(web::get().to(banners))
+                    .route(web::post().to(handle_add_banner)),
+            )
+            .service(
+                web::resource("/banners/{index}/remove")
+                    .route(web::post().to(handle_remove_banner)),
+            ),
+    );
+}
+
+/// Check the basic auth credentials against the configured admin credentials,
+/// counting wrong ones towards the IP address's lockout
+pub async fn validator(
+    req: ServiceRequest,
+    credentials: BasicAuth,
+) -> Result<ServiceRequest, (ActixError, ServiceRequest)> {
+    let challenge = || AuthenticationError::from(BasicConfig::default().realm("admin")).into();
+    let state = match req.app_data::<web::Data<AppState<'_>>>() {
+        Some(state) => state.clone(),
+        None => return Err((challenge(), req)),
+    };
+    let ip = client_ip(req.request());
+    let now = state.clock.now();
+    if let Some(until) = state.password_lockout.locked_until(&ip, now) {
+        warn!("Refusing admin credentials from {}, locked out", ip);
+        let res = lockout::too_many_requests(until, now).body(LOCKED_OUT_MESSAGE);
+        return Err((
+            InternalError::from_response(LOCKED_OUT_MESSAGE, res).into(),
+            req,
+        ));
+    }
+    if state
+        .config
+        .admin
+        .authorizes(credentials.user_id(), credentials.password())
+    {
+        return Ok(req);
+    }
+    warn!("Failed admin login for user {:?}", credentials.user_id());
+    let config = &state.config.fetch_lockout;
+    if let Some(until) = state.password_lockout.miss(&ip, config, now) {
+        let params = [("username", credentials.user_id())];
+        state.lock_out(req.request(), &ip, &params, until).await;
+    }
+    Err((challenge(), req))
+}
+
+/// Let the admin in with basic auth, as scripts and feed readers do, or with
+/// the session they signed in to at `/admin/login`.  Forms sent with the
+/// session must carry its CSRF token, so other sites can't submit them, and
+/// browsers remembering basic auth can't be made to send changes from other
+/// sites either.
+async fn authenticate(
+    mut req: ServiceRequest,
+    credentials: Option<BasicAuth>,
+) -> Result<ServiceRequest, (ActixError, ServiceRequest)> {
+    if let Some(credentials) = credentials {
+        if !matches!(*req.method(), Method::GET | Method::HEAD) && from_other_site(&req) {
+            warn!("Refusing a change to {} sent from another site", req.path());
+            let error = ErrorForbidden("Changes can't be sent from other sites");
+            return Err((error, req));
+        }
+        return validator(req, credentials).await;
+    }
+    let signed_in = req
+        .app_data::<web::Data<AppState<'_>>>()
+        .map(|state| session::is_admin(&req.get_session(), &state.config.admin))
+        .unwrap_or(false);
+    if !signed_in {
+        let error = not_signed_in(&req);
+        return Err((error, req));
+    }
+    if matches!(*req.method(), Method::GET | Method::HEAD) {
+        return Ok(req);
+    }
+    let token = match req.headers().get(session::CSRF_HEADER) {
+        Some(token) => token.to_str().unwrap_or_default().to_string(),
+        None => match form_field(&mut req, session::CSRF_FIELD).await {
+            Ok(token) => token,
+            Err(error) => return Err((error, req)),
+        },
+    };
+    if session::verify_csrf(&req.get_session(), &token) {
+        Ok(req)
+    } else {
+        warn!("Missing or wrong CSRF token for {}", req.path());
+        let error = ErrorForbidden("Missing or wrong CSRF token, reload the page and try again");
+        Err((error, req))
+    }
+}
+
+/// Whether a browser says the request came from another site, by its
+/// `Sec-Fetch-Site` header or an `Origin` that isn't this host.  Scripts send
+/// neither, so they're let through.
+fn from_other_site(req: &ServiceRequest) -> bool {
+    let header = |name: &HeaderName| {
+        req.headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap_or_default())
+    };
+    if header(&SEC_FETCH_SITE).is_some_and(|site| site == "cross-site" || site == "same-site") {
+        return true;
+    }
+    match header(&ORIGIN) {
+        Some(origin) => {
+            let host = origin.split_once("://").map(|(_, host)| host);
+            host != Some(req.connection_info().host())
+        }
+        None => false,
+    }
+}
+
+/// Send a browser to the login page, or ask anything else for basic auth
+fn not_signed_in(req: &ServiceRequest) -> ActixError {
+    let wants_page = req.method() == Method::GET
+        && req
+            .headers()
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/html"));
+    if !wants_page {
+        let challenge = BasicConfig::default().realm("admin");
+        return AuthenticationError::from(challenge).into();
+    }
+    let target = req.uri().path_and_query().map_or("/admin", |p| p.as_str());
+    let location = format!(
+        "/admin/login?{}",
+        serde_urlencoded::to_string([("next", target)]).unwrap_or_default()
+    );
+    let res = HttpResponse::SeeOther()
+        .insert_header((LOCATION, location))
+        .finish();
+    InternalError::from_response("not signed in", res).into()
+}
+
+/// A field of the request's form, putting the body back for the handler
+async fn form_field(req: &mut ServiceRequest, field: &str) -> Result<String, ActixError> {
+    let body = req.extract::<web::Bytes>().await?;
+    let value = serde_urlencoded::from_bytes::<Vec<(String, String)>>(&body)
+        .unwrap_or_default()
+        .into_iter()
+        .find(|(name, _)| name == field)
+        .map(|(_, value)| value)
+        .unwrap_or_default();
+    req.set_payload(Payload::from(body));
+    Ok(value)
+}
+
+/// Where to go once signed in: the admin page asked for, or the dashboard
+fn after_login(next: &str) -> &str {
+    match next.is_empty() {
+        true => "/admin",
+        false => local_path(next),
+    }
+}
+
+/// Return the admin login page
+async fn login(
+    state: web::Data<AppState<'_>>,
+    query: web::Query<LoginParams>,
+) -> Result<HttpResponse> {
+    render_login(&state, HttpResponse::Ok(), after_login(&query.next), None)
+}
+
+/// Check the admin credentials, keeping the admin signed in on this browser
+/// if they're right, and counting wrong ones towards the IP address's lockout
+#[instrument(skip_all, fields(username = %params.username))]
+async fn handle_login(
+    state: web::Data<AppState<'_>>,
+    req: HttpRequest,
+    session: Session,
+    params: web::Form<LoginParams>,
+) -> Result<HttpResponse> {
+    let next = after_login(&params.next);
+    let ip = client_ip(&req);
+    let now = state.clock.now();
+    if let Some(until) = state.password_lockout.locked_until(&ip, now) {
+        warn!("Refusing admin login from {}, locked out", ip);
+        let res = lockout::too_many_requests(until, now);
+        return render_login(&state, res, next, Some(LOCKED_OUT_MESSAGE));
+    }
+    if !state
+        .config
+        .admin
+        .authorizes(&params.username, Some(&params.password))
+    {
+        warn!("Failed admin login for user {:?}", params.username);
+        let config = &state.config.fetch_lockout;
+        if let Some(until) = state.password_lockout.miss(&ip, config, now) {
+            let username = [("username", &params.username)];
+            state.lock_out(&req, &ip, &username, until).await;
+        }
+        return render_login(
+            &state,
+            HttpResponse::Unauthorized(),
+            next,
+            Some("Sorry, that's not the username and password"),
+        );
+    }
+    session::log_in_admin(&session, &state.config.admin)?;
+    info!("Admin signed in");
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, next))
+        .finish())
+}
+
+/// Sign the admin out on this browser
+async fn handle_logout(session: Session) -> Result<HttpResponse> {
+    session::log_out_admin(&session);
+    flash::success(&session, "Signed out")?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/login"))
+        .finish())
+}
+
+fn render_login(
+    state: &AppState<'_>,
+    mut res: HttpResponseBuilder,
+    next: &str,
+    error: Option<&str>,
+) -> Result<HttpResponse> {
+    let ctx = json!({
+        "next": next,
+        "has_error": error.is_some(),
+        "error": error.unwrap_or_default(),
+    });
+    let body = state.tt.render("login.html", &ctx)?;
+    Ok(res.content_type("text/html").body(body))
+}
+
+/// Render an admin page, with the session's CSRF token for its forms
+fn render_page(
+    state: &AppState<'_>,
+    session: &Session,
+    name: &str,
+    ctx: impl Serialize,
+) -> Result<String, Error> {
+    let mut ctx = serde_json::to_value(ctx)?;
+    if let Value::Object(fields) = &mut ctx {
+        fields.insert("csrf".to_string(), session::csrf_token(session)?.into());
+    }
+    state.tt.render(name, &ctx)
+}
+
+/// Return the admin dashboard
+#[instrument(skip_all)]
+async fn dashboard(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    params: web::Query<ListParams>,
+) -> Result<HttpResponse> {
+    let db = state.read_db().await?;
+    let records = db.get_all()?;
+    let guests = db.list(&params)?;
+    let ctx = serde_json::to_value(AdminContext {
+        responses: records.len(),
+        attendance: db.attendance()?,
+        headcount: db.headcount()?,
+        dietary: dietary::counts(&records, &state.config.fields.dietary_options),
+        waves: waves::stats(&records),
+        sources: source::counts(&records),
+        registries: state.registry_clicks.counts(&state.config.registry.links),
+        shuttle: shuttle::counts(&records, &state.config.shuttle.stops),
+        childcare: childcare::summary(&records),
+        songs: state
+            .songs
+            .ranked(None)
+            .into_iter()
+            .take(TOP_SONGS)
+            .collect(),
+        quarantine: state.quarantine.list(),
+        event_over: thanks::event_over(&state.config, state.clock.now()),
+        archive: state.archive.frozen(),
+        reloads: state.reloader.generation(),
+        last_change: db
+            .history()
+            .last()
+            .map(|change| {
+                format!(
+                    "{} for {} at {}",
+                    change.action,
+                    change.name,
+                    change.at.to_rfc3339_opts(SecondsFormat::Secs, true)
+                )
+            })
+            .unwrap_or_default(),
+        limits_warning: db.limits_warning()?,
+        bad_emails: records
+            .iter()
+            .filter(|r| r.email_status != EmailStatus::Ok)
+            .cloned()
+            .collect(),
+        guest_pages: PageLinks::new(&guests, &params),
+        guests: guests.items,
+        recent_changes: state
+            .audit
+            .entries()?
+            .into_iter()
+            .rev()
+            .take(RECENT_CHANGES)
+            .collect(),
+        templates: PAGE_TEMPLATES
+            .iter()
+            .chain(EMAIL_TEMPLATES)
+            .map(|(name, _)| name.to_string())
+            .collect(),
+        export_presets: state.config.export.presets.keys().cloned().collect(),
+        build: state.build_info(),
+    })?;
+    let body = render_page(&state, &session, "admin.html", ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Return the headline numbers and latest responses, condensed for checking
+/// in from a phone
+async fn quick(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let db = state.read_db().await?;
+    let records = db.get_all()?;
+    let mut responded = records
+        .iter()
+        .filter(|r| r.responded_at.is_some())
+        .collect::<Vec<_>>();
+    responded.sort_by_key(|r| Reverse(r.responded_at));
+    let ctx = serde_json::to_value(QuickContext {
+        headcount: db.headcount()?,
+        declined: responded.iter().filter(|r| !r.is_attending_any()).count(),
+        recent: responded
+            .iter()
+            .take(QUICK_RESPONSES)
+            .map(|r| RecentResponse::from(*r))
+            .collect(),
+        held: state.quarantine.list().len(),
+        bad_emails: records
+            .iter()
+            .filter(|r| r.email_status != EmailStatus::Ok)
+            .count(),
+    })?;
+    let body = state.tt.render("quick.html", &ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Return a guest's RSVP form, to fill in for them
+async fn rsvp(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    token: web::Path<String>,
+) -> Result<HttpResponse> {
+    let db = state.read_db().await?;
+    let record = match db.find_by_token(&token)? {
+        Some(record) => record,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let body = state.render_rsvp_form(
+        &db,
+        record,
+        FormFor::Admin {
+            csrf: &session::csrf_token(&session)?,
+        },
+        vec![],
+    )?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Save an RSVP on a guest's behalf, e.g. from a phone call or paper card.
+/// Unlike the guest form, it's saved even if identical and doesn't ask the
+/// guest to verify their email.
+#[instrument(skip_all, fields(name = %params.name))]
+async fn handle_rsvp(
+    state: web::Data<AppState<'_>>,
+    req: HttpRequest,
+    session: Session,
+    params: RsvpForm,
+) -> Result<HttpResponse> {
+    let mut params = params.into_inner();
+    params.source = Some(Source::entered(params.source));
+    Form::new(&state.config.fields).clear_unseen(&mut params);
+    let mut db = state.write_db().await?;
+    if db.get(&params.name)?.is_none() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    info!("RSVP entered by the admin for {}", params.name);
+    let record = match db.upsert(&params) {
+        Err(error @ (Error::MealFull(_) | Error::ShuttleFull(_))) => {
+            let problems = vec![(form::field_of(&error, &params), error)];
+            let csrf = session::csrf_token(&session)?;
+            let form_for = FormFor::Admin { csrf: &csrf };
+            return Ok(state.rsvp_form_error(&db, &params, form_for, problems)?);
+        }
+        result => {
+            state
+                .notify_on_failure(&req, "admin RSVP", &params, result)
+                .await?
+        }
+    };
+    state.audit(&req, "RSVP", &record.name, EnteredBy::Admin);
+    state.hooks.deliver_in_background(&record);
+    flash::success(&session, format!("Saved the RSVP for {}", record.name))?;
+    let contents = db.dump();
+    let attendance = db.attendance()?;
+    if let Err(error) = state
+        .email
+        .send_csv(
+            &params,
+            &record,
+            &attendance,
+            contents,
+            state.clock.now(),
+            state.test,
+        )
+        .await
+    {
+        error!("Could not send confirmation email: {:?}", error);
+    }
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin"))
+        .finish())
+}
+
+/// Everyone attending, and how many have arrived
+fn checkin_context(records: &[RsvpModel]) -> CheckinContext {
+    let guests = records
+        .iter()
+        .filter(|r| r.attending)
+        .map(CheckinGuest::from)
+        .collect::<Vec<_>>();
+    CheckinContext {
+        attending: guests.len(),
+        checked_in: guests.iter().filter(|g| g.checked_in).count(),
+        guests,
+    }
+}
+
+/// Return the door list for the event day
+async fn checkin(state: web::Data<AppState<'_>>, session: Session) -> Result<HttpResponse> {
+    let mut records = state.write_db().await?.get_all()?;
+    records.sort_by_key(|r| r.name.to_lowercase());
+    let ctx = serde_json::to_value(checkin_context(&records))?;
+    let body = render_page(&state, &session, "checkin.html", ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Return the number of guests checked in so far, to refresh the page's count
+async fn checkin_count(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let records = state.write_db().await?.get_all()?;
+    let ctx = checkin_context(&records);
+    Ok(HttpResponse::Ok().json(json!({
+        "attending": ctx.attending,
+        "checked_in": ctx.checked_in,
+    })))
+}
+
+/// Check a guest in, or undo it if they were already, then go back to the
+/// door list
+#[instrument(skip_all, fields(name = %params.name))]
+async fn handle_checkin(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    req: HttpRequest,
+    params: web::Form<NameParams>,
+) -> Result<HttpResponse> {
+    let now = state.clock.now();
+    let mut db = state.write_db().await?;
+    let result = db.update_with(&params.name, |r| {
+        r.checked_in_at = match r.checked_in_at {
+            Some(_) => None,
+            None => Some(now),
+        }
+    });
+    let record = state
+        .notify_on_failure(&req, "check-in", &params.0, result)
+        .await?;
+    drop(db);
+    let record = match record {
+        Some(record) => record,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let action = if record.checked_in_at.is_some() {
+        "check-in"
+    } else {
+        "undo check-in"
+    };
+    info!("{} for {}", action, record.name);
+    state.audit(&req, action, &record.name, EnteredBy::Admin);
+    let notice = match record.checked_in_at {
+        Some(_) => format!("Checked in {}", record.name),
+        None => format!("Undid the check-in for {}", record.name),
+    };
+    flash::success(&session, notice)?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/checkin"))
+        .finish())
+}
+
+/// Return the report of records that may be the same guest
+async fn duplicates(state: web::Data<AppState<'_>>, session: Session) -> Result<HttpResponse> {
+    let records = state.write_db().await?.get_all()?;
+    let ctx = json!({ "pairs": dedupe::find(&records) });
+    let body = render_page(&state, &session, "duplicates.html", ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Return the same report as JSON, for `rsvp-admin dedupe`
+async fn duplicates_json(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let records = state.write_db().await?.get_all()?;
+    Ok(HttpResponse::Ok().json(dedupe::find(&records)))
+}
+
+/// Check the running server's setup, as `--check` does before starting
+async fn doctor_json(state: web::Data<AppState<'_>>) -> HttpResponse {
+    HttpResponse::Ok().json(doctor::diagnose(
+        &state.config,
+        &state.csv,
+        &state.email.from,
+        &state.email.admin,
+        state.test,
+    ))
+}
+
+/// Merge one guest's record into another's and remove it
+#[instrument(skip_all, fields(keep = %params.keep, remove = %params.remove))]
+async fn handle_merge(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    req: HttpRequest,
+    params: web::Form<MergeParams>,
+) -> Result<HttpResponse> {
+    let mut db = state.write_db().await?;
+    let records = db.get_all()?;
+    let find = |name: &str| {
+        let key = name_key(name);
+        records.iter().find(|r| r.name_key == key).cloned()
+    };
+    let (keep, remove) = match (find(&params.keep), find(&params.remove)) {
+        (Some(keep), Some(remove)) if keep.name_key != remove.name_key => (keep, remove),
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let merged = dedupe::merge(&keep, &remove, params.answers_from);
+    let result = db.undoable("merge", &keep.name, |db| {
+        db.remove(&remove.name)?;
+        db.update_with(&keep.name, |r| *r = merged)
+    });
+    state
+        .notify_on_failure(&req, "merge", &params.0, result)
+        .await?;
+    info!("Merged {} into {}", remove.name, keep.name);
+    state.audit(
+        &req,
+        &format!("merge from {}", remove.name),
+        &keep.name,
+        EnteredBy::Admin,
+    );
+    flash::success(
+        &session,
+        format!("Merged {} into {}", remove.name, keep.name),
+    )?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/duplicates"))
+        .finish())
+}
+
+/// Download the guests to import into a mailing list service
+async fn export(
+    state: web::Data<AppState<'_>>,
+    list: web::Path<MailingList>,
+) -> Result<HttpResponse> {
+    let records = state.write_db().await?.get_all()?;
+    let list = list.into_inner();
+    let body = export::export(&records, list)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header(ContentDisposition::attachment(list.filename()))
+        .body(body))
+}
+
+/// Download the whole CSV file, for when it's too big to attach to emails,
+/// or only the columns chosen in the query or a preset
+async fn rsvps_csv(
+    state: web::Data<AppState<'_>>,
+    params: web::Query<ExportParams>,
+) -> Result<HttpResponse> {
+    let mut db = state.write_db().await?;
+    let body = match export::selected_columns(&state.config.export, &params)? {
+        Some(columns) => export::columns_csv(&db.get_all()?, &columns)?,
+        None => db.dump(),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header(ContentDisposition::attachment("rsvp.csv"))
+        .body(body))
+}
+
+/// Return a calendar of responses, to subscribe to from a calendar app
+async fn rsvps_ics(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let records = state.write_db().await?.get_all()?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(feeds::ics(&records)))
+}
+
+/// Return an Atom feed of recent RSVPs and changes, for feed readers
+async fn feed(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let records = state.write_db().await?.get_all()?;
+    let entries = state.audit.entries()?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(feeds::atom(&entries, &records, state.started_at)))
+}
+
+/// Save the admin's private notes on a guest, then go back to the dashboard
+#[instrument(skip_all, fields(name = %params.name))]
+async fn handle_notes(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    req: HttpRequest,
+    params: web::Form<NotesParams>,
+) -> Result<HttpResponse> {
+    let params = params.into_inner();
+    let mut db = state.write_db().await?;
+    let result = db.update_with(&params.name, |r| r.notes = params.notes.trim().to_string());
+    let record = state
+        .notify_on_failure(&req, "notes", &params, result)
+        .await?;
+    if record.is_none() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    flash::success(&session, format!("Saved the notes for {}", params.name))?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin"))
+        .finish())
+}
+
+/// Seat a guest and their plus-one at a table, then go back to the dashboard
+async fn handle_table(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    req: HttpRequest,
+    params: web::Form<TableParams>,
+) -> Result<HttpResponse> {
+    let params = params.into_inner();
+    let mut db = state.write_db().await?;
+    let result = db.update_with(&params.name, |r| r.table = params.table.trim().to_string());
+    let record = state
+        .notify_on_failure(&req, "table", &params, result)
+        .await?;
+    if record.is_none() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    flash::success(&session, format!("Saved the table for {}", params.name))?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin"))
+        .finish())
+}
+
+/// Download a row per table with its meal counts and dietary restrictions,
+/// for the caterer
+async fn seating_csv(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let records = state.write_db().await?.get_all()?;
+    let body = seating::csv(&seating::tables(&records))?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header(ContentDisposition::attachment("seating.csv"))
+        .body(body))
+}
+
+/// Download the manifest of who's riding the shuttle from a stop, for the
+/// transport company
+async fn shuttle_csv(
+    state: web::Data<AppState<'_>>,
+    name: web::Path<String>,
+) -> Result<HttpResponse> {
+    let stop = match state
+        .config
+        .shuttle
+        .stops
+        .iter()
+        .find(|stop| stop.name == *name)
+    {
+        Some(stop) => stop,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let records = state.write_db().await?.get_all()?;
+    let body = shuttle::manifest(&records, stop)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header(ContentDisposition::attachment(format!(
+            "shuttle-{}.csv",
+            stop.name
+        )))
+        .body(body))
+}
+
+/// Download every song requested, most votes first, for the DJ
+async fn songs_csv(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let body = songs::csv(&state.songs.ranked(None))?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header(ContentDisposition::attachment("songs.csv"))
+        .body(body))
+}
+
+/// Return the parties who came, to tick off thank-you cards and note gifts
+async fn thanks(state: web::Data<AppState<'_>>, session: Session) -> Result<HttpResponse> {
+    let records = state.write_db().await?.get_all()?;
+    let event_over = thanks::event_over(&state.config, state.clock.now());
+    let ctx = serde_json::to_value(thanks::context(&records, event_over))?;
+    let body = render_page(&state, &session, "thanks.html", ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Save a party's gift and whether they've been thanked, then go back to the
+/// list
+async fn handle_thanks(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    req: HttpRequest,
+    params: web::Form<ThanksParams>,
+) -> Result<HttpResponse> {
+    let params = params.into_inner();
+    let mut db = state.write_db().await?;
+    let result = db.update_with(&params.name, |r| {
+        r.thank_you_sent = params.thank_you_sent;
+        r.gift = params.gift.trim().to_string();
+    });
+    let record = state
+        .notify_on_failure(&req, "thanks", &params, result)
+        .await?;
+    if record.is_none() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    flash::success(&session, format!("Saved the gift for {}", params.name))?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/thanks"))
+        .finish())
+}
+
+/// Download the thank-you list, to write the cards from
+async fn thanks_csv(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let records = state.write_db().await?.get_all()?;
+    let body = thanks::csv(&records)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header(ContentDisposition::attachment("thanks.csv"))
+        .body(body))
+}
+
+/// Save the final export bundle, scrub the guests' personal details if the
+/// config asks to, and make the site read-only for good
+#[instrument(skip_all)]
+async fn handle_archive(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let config = &state.config.archive;
+    let mut db = state.write_db().await?;
+    let now = state.clock.now();
+    let files = archive::bundle_files(&mut db, &state.audit.entries()?, &state.config)?;
+    let bundle = state
+        .archive
+        .save_bundle(&files, config.key.as_deref(), now)?;
+    info!("Saved the final export bundle {}", bundle);
+    if config.scrub_pii {
+        let records = db.get_all()?;
+        let result = db.scrub(&records.iter().map(archive::scrub).collect::<Vec<_>>());
+        state
+            .notify_on_failure(&req, "archive", &(), result)
+            .await?;
+        Backups::new(&state.config.backups).clear()?;
+        info!("Scrubbed the guests' personal details");
+    }
+    flash::success(
+        &session,
+        format!("Archived the site, with the final export in {}", bundle),
+    )?;
+    state.archive.freeze(Frozen {
+        bundle,
+        frozen_at: now,
+        scrubbed: config.scrub_pii,
+    })?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin"))
+        .finish())
+}
+
+/// Read the config file again, as on SIGHUP, for requests from now on
+#[instrument(skip_all)]
+async fn handle_reload(state: web::Data<AppState<'_>>, session: Session) -> Result<HttpResponse> {
+    let pending = state.reloader.reload(&state).await?;
+    let notice = match pending.is_empty() {
+        true => "Reloaded the config".to_string(),
+        false => format!(
+            "Reloaded the config, but changes to {} take effect after a restart",
+            pending.join(", ")
+        ),
+    };
+    flash::success(&session, notice)?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin"))
+        .finish())
+}
+
+/// Download the kids signed up for childcare, for the babysitting service
+async fn childcare_csv(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let records = state.write_db().await?.get_all()?;
+    let body = childcare::csv(&records)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header(ContentDisposition::attachment("childcare.csv"))
+        .body(body))
+}
+
+/// Download the caterer's seating chart to print, a page per table
+async fn seating_pdf(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let records = state.write_db().await?.get_all()?;
+    let tables = seating::tables(&records);
+    match seating::pdf(&tables, state.config.seating.page_size)? {
+        Some(body) => Ok(HttpResponse::Ok()
+            .content_type("application/pdf")
+            .insert_header(ContentDisposition::attachment("seating.pdf"))
+            .body(body)),
+        None => Ok(HttpResponse::NotFound()
+            .body("The seating chart needs the server built with the pdf feature")),
+    }
+}
+
+/// Download everyone with a severe allergy and their table, for the kitchen
+async fn kitchen_alert_csv(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let records = state.write_db().await?.get_all()?;
+    let body = seating::alert_csv(&seating::alerts(&seating::tables(&records)))?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header(ContentDisposition::attachment("kitchen-alert.csv"))
+        .body(body))
+}
+
+/// Download the one-page kitchen alert sheet to print
+async fn kitchen_alert_pdf(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let records = state.write_db().await?.get_all()?;
+    let alerts = seating::alerts(&seating::tables(&records));
+    match seating::alert_pdf(&alerts, state.config.seating.page_size)? {
+        Some(body) => Ok(HttpResponse::Ok()
+            .content_type("application/pdf")
+            .insert_header(ContentDisposition::attachment("kitchen-alert.pdf"))
+            .body(body)),
+        None => Ok(HttpResponse::NotFound()
+            .body("The kitchen alert sheet needs the server built with the pdf feature")),
+    }
+}
+
+/// Revert the most recent change to the CSV file, then go back to the
+/// dashboard
+#[instrument(skip_all)]
+async fn handle_undo(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let mut db = state.write_db().await?;
+    let result = db.undo();
+    let change = state.notify_on_failure(&req, "undo", &(), result).await?;
+    drop(db);
+    match change {
+        Some(change) => {
+            info!("Undid {} for {}", change.action, change.name);
+            state.audit(
+                &req,
+                &format!("undo {}", change.action),
+                &change.name,
+                EnteredBy::Admin,
+            );
+            flash::success(
+                &session,
+                format!("Undid the {} for {}", change.action, change.name),
+            )?;
+            Ok(HttpResponse::SeeOther()
+                .insert_header((LOCATION, "/admin"))
+                .finish())
+        }
+        None => Ok(HttpResponse::Conflict().body("Nothing to undo")),
+    }
+}
+
+/// List the snapshots of the CSV file, newest first
+async fn snapshots(state: web::Data<AppState<'_>>, session: Session) -> Result<HttpResponse> {
+    let snapshots = Backups::new(&state.config.backups).list()?;
+    let ctx = json!({ "snapshots": snapshots });
+    let body = render_page(&state, &session, "snapshots.html", ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Take a snapshot of the CSV file now, then go back to the list
+async fn handle_snapshot(state: web::Data<AppState<'_>>, session: Session) -> Result<HttpResponse> {
+    let mut db = state.write_db().await?;
+    let snapshot = Backups::new(&state.config.backups).take(&db.dump(), db.now(), "manual")?;
+    info!("Took snapshot {}", snapshot.name);
+    flash::success(&session, "Took a snapshot")?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/snapshots"))
+        .finish())
+}
+
+/// Show how the guest list changed since a snapshot, with a button to
+/// restore it
+async fn snapshot(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    name: web::Path<String>,
+) -> Result<HttpResponse> {
+    let contents = match Backups::new(&state.config.backups).read(&name)? {
+        Some(contents) => contents,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let current = state.write_db().await?.get_all()?;
+    let snapshot = CsvDb::parse(&contents)?;
+    let ctx = json!({
+        "name": name.into_inner(),
+        "records": snapshot.len(),
+        "changes": backup::diff(&snapshot, &current)?,
+    });
+    let body = render_page(&state, &session, "snapshot.html", ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Download only the guests added, removed or changed since a snapshot, or
+/// since the newest snapshot from before a date
+async fn changes_csv(
+    state: web::Data<AppState<'_>>,
+    params: web::Query<ChangesParams>,
+) -> Result<HttpResponse> {
+    let backups = Backups::new(&state.config.backups);
+    let name = match (&params.snapshot, params.since) {
+        (Some(name), _) => name.clone(),
+        (None, Some(since)) => match backups.before(state.config.timezone.start_of_day(since))? {
+            Some(snapshot) => snapshot.name,
+            None => {
+                return Ok(HttpResponse::NotFound()
+                    .body(format!("There's no snapshot from before {}", since)))
+            }
+        },
+        (None, None) => {
+            return Ok(HttpResponse::BadRequest().body("Pick a snapshot or a date to start from"))
+        }
+    };
+    let contents = match backups.read(&name)? {
+        Some(contents) => contents,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let current = state.write_db().await?.get_all()?;
+    let snapshot = CsvDb::parse(&contents)?;
+    let body = backup::changes_csv(&backup::changes(&snapshot, &current)?)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header(ContentDisposition::attachment("changes.csv"))
+        .body(body))
+}
+
+/// Replace the CSV file with a snapshot, after taking a snapshot of the
+/// current file in case the wrong one was picked
+#[instrument(skip_all, fields(name = %name))]
+async fn handle_restore(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> Result<HttpResponse> {
+    let backups = Backups::new(&state.config.backups);
+    let contents = match backups.read(&name)? {
+        Some(contents) => contents,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let records = CsvDb::parse(&contents)?;
+    let mut db = state.write_db().await?;
+    let result = backups
+        .take(&db.dump(), db.now(), "before-restore")
+        .and_then(|_| db.restore(&name, &records));
+    let params = [("snapshot", name.as_str())];
+    state
+        .notify_on_failure(&req, "restore", &params, result)
+        .await?;
+    info!("Restored snapshot {}", name);
+    state.audit(&req, "restore", &name, EnteredBy::Admin);
+    flash::success(&session, format!("Restored snapshot {}", name))?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin"))
+        .finish())
+}
+
+/// List the scheduled tasks with how they last went
+async fn tasks(state: web::Data<AppState<'_>>, session: Session) -> Result<HttpResponse> {
+    let ctx = json!({ "tasks": state.scheduler.statuses() });
+    let body = render_page(&state, &session, "tasks.html", ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Every guest's short link and the RSVP link it leads to
+async fn links(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let mut records = state.write_db().await?.get_all()?;
+    records.sort_by_key(|r| r.name_key.clone());
+    let links = records
+        .iter()
+        .map(|r| ShortLink {
+            name: r.name.clone(),
+            short_url: state.config.short_url(&r.short_code),
+            rsvp_url: state.config.rsvp_url(&r.token),
+        })
+        .collect::<Vec<_>>();
+    let ctx = json!({ "links": links });
+    let body = state.tt.render("links.html", &ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Download a sheet of invitation inserts to print, one card per guest in
+/// the segment, sorted by name
+async fn inserts_pdf(
+    state: web::Data<AppState<'_>>,
+    params: web::Query<InsertsParams>,
+) -> Result<HttpResponse> {
+    let mut records = state.write_db().await?.get_all()?;
+    records.sort_by_key(|r| r.name_key.clone());
+    let segment = Segment::parse(&params.segment);
+    let cards = records
+        .iter()
+        .filter(|r| segment.includes(r))
+        .map(|r| Insert::new(r, &state.config))
+        .collect::<Vec<_>>();
+    let config = &state.config.inserts;
+    let heading = config
+        .heading
+        .as_ref()
+        .unwrap_or(&state.config.theme.couple_names);
+    match inserts::pdf(&cards, config, heading)? {
+        Some(body) => Ok(HttpResponse::Ok()
+            .content_type("application/pdf")
+            .insert_header(ContentDisposition::attachment("inserts.pdf"))
+            .body(body)),
+        None => Ok(HttpResponse::NotFound()
+            .body("Invitation inserts need the server built with the pdf feature")),
+    }
+}
+
+/// Chart of page views and visitors per day, with totals for each page
+async fn analytics(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let (days, routes) = match &state.analytics {
+        Some(analytics) => analytics::report(&analytics.days(), state.config.analytics.days_shown),
+        None => (vec![], vec![]),
+    };
+    let ctx = json!({
+        "enabled": state.analytics.is_some(),
+        "days": days,
+        "routes": routes,
+    });
+    let body = state.tt.render("analytics.html", &ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// List the banners on the guests' pages, with a form to add one
+async fn banners(state: web::Data<AppState<'_>>, session: Session) -> Result<HttpResponse> {
+    let now = state.clock.now();
+    let banners = state
+        .banners
+        .all()
+        .into_iter()
+        .enumerate()
+        .map(|(index, banner)| {
+            json!({
+                "index": index,
+                "text": banner.text,
+                "severity": banner.severity.as_str(),
+                "expires": banner.expires.map(|e| e.format("%Y-%m-%d %H:%M UTC").to_string()),
+                "expired": banner.expires.is_some_and(|e| e <= now),
+                "paths": banner.paths.join(", "),
+            })
+        })
+        .collect::<Vec<_>>();
+    let body = render_page(
+        &state,
+        &session,
+        "banners.html",
+        json!({ "banners": banners }),
+    )?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Put up a banner on the guests' pages, until the server restarts
+async fn handle_add_banner(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    params: web::Form<BannerParams>,
+) -> Result<HttpResponse> {
+    match params.into_inner().into_banner() {
+        Ok(banner) => {
+            info!("Adding banner {:?}", banner.text);
+            state.banners.add(banner);
+            flash::success(&session, "Put up the banner")?;
+        }
+        Err(error) => flash::error(&session, format!("Could not put up the banner: {}", error))?,
+    }
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/banners"))
+        .finish())
+}
+
+/// Take down a banner, then go back to the list
+async fn handle_remove_banner(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    index: web::Path<usize>,
+) -> Result<HttpResponse> {
+    if !state.banners.remove(index.into_inner()) {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    flash::success(&session, "Took the banner down")?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/banners"))
+        .finish())
+}
+
+/// Send on a message held as spam that came from a real guest, then go back
+/// to the dashboard
+#[instrument(skip_all, fields(id = %id))]
+async fn handle_approve(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    id: web::Path<String>,
+) -> Result<HttpResponse> {
+    let held = match state.quarantine.get(&id) {
+        Some(held) => held,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    state.relay_contact(&held.message).await?;
+    state.quarantine.remove(&id)?;
+    info!("Approved a message held as spam from {}", held.message.name);
+    flash::success(
+        &session,
+        format!("Sent on the message from {}", held.message.name),
+    )?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin"))
+        .finish())
+}
+
+/// Throw away a message held as spam, then go back to the dashboard
+#[instrument(skip_all, fields(id = %id))]
+async fn handle_reject(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    id: web::Path<String>,
+) -> Result<HttpResponse> {
+    if !state.quarantine.remove(&id)? {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    flash::success(&session, "Deleted the message")?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin"))
+        .finish())
+}
+
+/// Counters since the server started, for Prometheus to scrape
+async fn metrics(state: web::Data<AppState<'_>>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(state.metrics.render())
+}
+
+/// Run a scheduled task now, then go back to the list
+#[instrument(skip_all, fields(name = %name))]
+async fn handle_run_task(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    name: web::Path<String>,
+) -> Result<HttpResponse> {
+    let task = match Task::parse(&name).filter(|task| state.scheduler.has(*task)) {
+        Some(task) => task,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    state.scheduler.run(task, &state).await;
+    flash::success(&session, format!("Ran {}", name))?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/tasks"))
+        .finish())
+}
+
+/// Segments to suggest on the announcement form: everyone, attendees, each
+/// tag, and each wave
+fn segments(records: &[RsvpModel]) -> Vec<String> {
+    let tags = records
+        .iter()
+        .flat_map(|r| r.tags.split(','))
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect::<BTreeSet<_>>();
+    let waves = records
+        .iter()
+        .map(|r| r.wave.to_lowercase())
+        .filter(|w| !w.is_empty())
+        .collect::<BTreeSet<_>>();
+    ["all".to_string(), "attending".to_string()]
+        .into_iter()
+        .chain(tags.into_iter().map(|t| format!("tag:{}", t)))
+        .chain(waves.into_iter().map(|w| format!("wave:{}", w)))
+        .collect()
+}
+
+/// Return the form to compose an announcement
+async fn announce(state: web::Data<AppState<'_>>, session: Session) -> Result<HttpResponse> {
+    let records = state.write_db().await?.get_all()?;
+    let ctx = serde_json::to_value(AnnouncementContext {
+        segment: "all".to_string(),
+        segments: segments(&records),
+        ..AnnouncementContext::default()
+    })?;
+    let body = render_page(&state, &session, "announce.html", ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Preview an announcement, or start sending it in the background
+#[instrument(skip_all)]
+async fn handle_announce(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    params: web::Form<AnnouncementParams>,
+) -> Result<HttpResponse> {
+    let params = params.into_inner();
+    let records = state.write_db().await?.get_all()?;
+    let recipients = announcement::recipients(records.clone(), &Segment::parse(&params.segment));
+    let mut ctx = AnnouncementContext {
+        segment: params.segment.clone(),
+        segments: segments(&records),
+        subject: params.subject.clone(),
+        body: params.body.clone(),
+        recipients: recipients.len(),
+        ..AnnouncementContext::default()
+    };
+    let announcements = recipients
+        .iter()
+        .map(|r| announcement::render(&params, r, &state.config))
+        .collect::<Result<Vec<Announcement>, Error>>();
+    match announcements {
+        Err(error) => {
+            ctx.has_error = true;
+            ctx.error = error.to_string();
+        }
+        Ok(announcements) if params.action == "send" => {
+            info!(
+                "Sending announcement {:?} to {} guests",
+                params.subject,
+                announcements.len()
+            );
+            let email = state.email.clone();
+            let throttle = Arc::clone(&state.email_throttle);
+            let test = state.test;
+            actix_web::rt::spawn(async move {
+                email
+                    .send_announcements(announcements, &throttle, test)
+                    .await
+            });
+            ctx.sent = true;
+        }
+        Ok(announcements) => {
+            if let Some(first) = announcements.into_iter().next() {
+                ctx.has_preview = true;
+                ctx.preview_to = first.recipient.email_addresses().join(", ");
+                ctx.preview_subject = first.subject;
+                ctx.preview_body = first.body;
+            }
+        }
+    }
+    let ctx = serde_json::to_value(ctx)?;
+    let body = render_page(&state, &session, "announce.html", ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Render any page or email template with sample data
+async fn preview(
+    state: web::Data<AppState<'_>>,
+    template: web::Path<String>,
+) -> Result<HttpResponse> {
+    let name = template.into_inner();
+    let ctx = match sample_context(&name) {
+        Some(ctx) => ctx,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    if EMAIL_TEMPLATES.iter().any(|(email, _)| *email == name) {
+        let body = render_email(&name, &ctx)?;
+        Ok(HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .body(body))
+    } else {
+        let body = state.tt.render(&name, &ctx)?;
+        Ok(HttpResponse::Ok().content_type("text/html").body(body))
+    }
+}
+
+/// Add fake guests, only in test mode so real RSVPs can't get mixed in
+#[instrument(skip_all, fields(count = params.count))]
+async fn seed(
+    state: web::Data<AppState<'_>>,
+    params: web::Form<SeedParams>,
+) -> Result<HttpResponse> {
+    if !state.test {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let records = fake::seed(&mut *state.write_db().await?, params.count)?;
+    info!("Added {} fake guests", records.len());
+    Ok(HttpResponse::Ok().json(json!({ "added": records.len() })))
+}
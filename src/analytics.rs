@@ -0,0 +1,305 @@
+use {
+    crate::{config::AnalyticsConfig, error::Error, request::client_ip, state::AppState},
+    actix_web::{
+        body::MessageBody,
+        dev::{ServiceRequest, ServiceResponse},
+        http::{header::CONTENT_TYPE, Method},
+        middleware::Next,
+        web, Error as ActixError,
+    },
+    chrono::{DateTime, NaiveDate, Utc},
+    log::warn,
+    rand::Rng,
+    serde::{Deserialize, Serialize},
+    sha2::{Digest, Sha256},
+    std::{
+        collections::{BTreeMap, HashSet},
+        fs,
+        path::PathBuf,
+        sync::{Mutex, MutexGuard},
+    },
+};
+
+/// Views and distinct visitors of one route, or of the whole site, in a day
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Counts {
+    pub views: u64,
+    pub visitors: u64,
+}
+
+/// Everything counted on one day
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Day {
+    pub total: Counts,
+    /// By route pattern, like "/unsubscribe/{token}", so guests' tokens
+    /// aren't kept
+    pub routes: BTreeMap<String, Counts>,
+}
+
+/// Which visitors were already counted today
+#[derive(Default)]
+struct Seen {
+    date: Option<NaiveDate>,
+    /// Hashes of visitors to the site
+    site: HashSet<String>,
+    /// Hashes of visitors to each route
+    routes: HashSet<(String, String)>,
+}
+
+struct Inner {
+    days: BTreeMap<NaiveDate, Day>,
+    seen: Seen,
+    /// Whether there are views not saved yet
+    dirty: bool,
+}
+
+/// First-party page view counts, without cookies.  Visitors are told apart
+/// by a hash of their IP address with a random salt, which is only kept in
+/// memory for the current day, so they can't be followed across days or
+/// after a restart.  Counts are kept in memory and saved by the
+/// `save_analytics` task, so page views don't wait on the disk.
+pub struct Analytics {
+    path: PathBuf,
+    salt: [u8; 16],
+    inner: Mutex<Inner>,
+}
+impl Analytics {
+    /// The counts saved so far, or `None` if analytics are off
+    pub fn open(config: &AnalyticsConfig) -> Result<Option<Self>, Error> {
+        let path = match &config.path {
+            Some(path) => PathBuf::from(path),
+            None => return Ok(None),
+        };
+        let days = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => BTreeMap::default(),
+            Err(error) => return Err(error.into()),
+        };
+        Ok(Some(Self {
+            path,
+            salt: rand::thread_rng().gen(),
+            inner: Mutex::new(Inner {
+                days,
+                seen: Seen::default(),
+                dirty: false,
+            }),
+        }))
+    }
+
+    /// Count a view of a page, in memory until the counts are next saved
+    pub fn record(&self, route: &str, ip: &str, now: DateTime<Utc>) {
+        let date = now.date_naive();
+        let visitor = self.hash(ip, date);
+        let mut inner = self.lock();
+        if inner.seen.date != Some(date) {
+            inner.seen = Seen {
+                date: Some(date),
+                ..Seen::default()
+            };
+        }
+        let new_to_site = inner.seen.site.insert(visitor.clone());
+        let new_to_route = inner.seen.routes.insert((route.to_string(), visitor));
+        let day = inner.days.entry(date).or_default();
+        day.total.views += 1;
+        day.total.visitors += new_to_site as u64;
+        let counts = day.routes.entry(route.to_string()).or_default();
+        counts.views += 1;
+        counts.visitors += new_to_route as u64;
+        inner.dirty = true;
+    }
+
+    /// Write the counts to the file if there are views since they were last
+    /// saved, returning whether there were
+    pub fn save(&self) -> Result<bool, Error> {
+        let contents = {
+            let mut inner = self.lock();
+            if !inner.dirty {
+                return Ok(false);
+            }
+            inner.dirty = false;
+            serde_json::to_string(&inner.days)?
+        };
+        if let Err(error) = fs::write(&self.path, contents) {
+            self.lock().dirty = true;
+            return Err(error.into());
+        }
+        Ok(true)
+    }
+
+    /// Counts for each day, oldest first
+    pub fn days(&self) -> BTreeMap<NaiveDate, Day> {
+        self.lock().days.clone()
+    }
+
+    fn hash(&self, ip: &str, date: NaiveDate) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt);
+        hasher.update(date.to_string());
+        hasher.update(ip);
+        hex::encode(&hasher.finalize()[..16])
+    }
+
+    /// The counts, even if a request panicked while updating them, since
+    /// they're only for display
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the analytics after a panic");
+            poisoned.into_inner()
+        })
+    }
+}
+
+/// Middleware counting successful views of HTML pages, leaving out the admin
+/// pages
+pub async fn count_views(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, ActixError> {
+    let state = req.app_data::<web::Data<AppState<'_>>>().cloned();
+    let res = next.call(req).await?;
+    let state = match &state {
+        Some(state) => state,
+        None => return Ok(res),
+    };
+    let analytics = match &state.analytics {
+        Some(analytics) => analytics,
+        None => return Ok(res),
+    };
+    let req = res.request();
+    let is_page = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    if req.method() == Method::GET
+        && res.status().is_success()
+        && is_page
+        && !req.path().starts_with("/admin")
+    {
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| req.path().to_string());
+        analytics.record(&route, &client_ip(req), state.clock.now());
+    }
+    Ok(res)
+}
+
+/// One row of the chart on `/admin/analytics`
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct DayRow {
+    pub date: NaiveDate,
+    pub views: u64,
+    pub visitors: u64,
+    /// Width of the bar, as a percentage of the busiest day
+    pub percent: u64,
+}
+
+/// Totals of one route over every day
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct RouteRow {
+    pub route: String,
+    pub views: u64,
+    pub visitors: u64,
+}
+
+/// The most recent days, newest first, and each route's totals, busiest
+/// first
+pub fn report(days: &BTreeMap<NaiveDate, Day>, recent: usize) -> (Vec<DayRow>, Vec<RouteRow>) {
+    let recent_days = days.iter().rev().take(recent).collect::<Vec<_>>();
+    let most = recent_days
+        .iter()
+        .map(|(_, day)| day.total.views)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let day_rows = recent_days
+        .into_iter()
+        .map(|(date, day)| DayRow {
+            date: *date,
+            views: day.total.views,
+            visitors: day.total.visitors,
+            percent: day.total.views * 100 / most,
+        })
+        .collect();
+    let mut routes = BTreeMap::<&str, Counts>::new();
+    for day in days.values() {
+        for (route, counts) in &day.routes {
+            let totals = routes.entry(route).or_default();
+            totals.views += counts.views;
+            totals.visitors += counts.visitors;
+        }
+    }
+    let mut route_rows = routes
+        .into_iter()
+        .map(|(route, counts)| RouteRow {
+            route: route.to_string(),
+            views: counts.views,
+            visitors: counts.visitors,
+        })
+        .collect::<Vec<_>>();
+    route_rows.sort_by_key(|row| std::cmp::Reverse(row.views));
+    (day_rows, route_rows)
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, chrono::TimeZone, std::path::Path};
+
+    #[test]
+    fn counts() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AnalyticsConfig {
+            path: Some(
+                dir.path()
+                    .join("analytics.json")
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            ),
+            ..AnalyticsConfig::default()
+        };
+        let analytics = Analytics::open(&config).unwrap().unwrap();
+        let day1 = Utc.with_ymd_and_hms(2024, 6, 1, 10, 0, 0).unwrap();
+        let day2 = Utc.with_ymd_and_hms(2024, 6, 2, 10, 0, 0).unwrap();
+        analytics.record("/", "203.0.113.7", day1);
+        analytics.record("/", "203.0.113.7", day1);
+        analytics.record("/photos", "203.0.113.7", day1);
+        analytics.record("/", "198.51.100.1", day1);
+        analytics.record("/", "203.0.113.7", day2);
+
+        let days = analytics.days();
+        let first = &days[&day1.date_naive()];
+        assert_eq!(
+            first.total,
+            Counts {
+                views: 4,
+                visitors: 2
+            }
+        );
+        assert_eq!(
+            first.routes["/"],
+            Counts {
+                views: 3,
+                visitors: 2
+            }
+        );
+        assert_eq!(first.routes["/photos"].visitors, 1);
+        assert_eq!(days[&day2.date_naive()].total.visitors, 1);
+
+        // counts are saved, but not who visited, and only when there are new ones
+        assert!(!config.path.as_ref().map(Path::new).unwrap().exists());
+        assert!(analytics.save().unwrap());
+        assert!(!analytics.save().unwrap());
+        let contents = fs::read_to_string(config.path.as_ref().unwrap()).unwrap();
+        assert!(!contents.contains("203.0.113.7"));
+        let reopened = Analytics::open(&config).unwrap().unwrap();
+        assert_eq!(reopened.days(), days);
+
+        let (day_rows, route_rows) = report(&days, 1);
+        assert_eq!(day_rows.len(), 1);
+        assert_eq!(day_rows[0].date, day2.date_naive());
+        assert_eq!(day_rows[0].percent, 100);
+        assert_eq!(route_rows[0].route, "/");
+        assert_eq!(route_rows[0].views, 4);
+    }
+}
@@ -0,0 +1,187 @@
+use {
+    crate::{
+        config::Config,
+        error::Error,
+        model::{EmailKind, RsvpModel},
+    },
+    serde::{Deserialize, Serialize},
+    tinytemplate::{format_unescaped, TinyTemplate},
+};
+
+/// Which guests receive an announcement
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Segment {
+    All,
+    Attending,
+    Tag(String),
+    Wave(String),
+}
+impl Segment {
+    /// Parse "all", "attending", "tag:<name>", or "wave:<name>", defaulting
+    /// to everyone
+    pub fn parse(segment: &str) -> Self {
+        let segment = segment.trim();
+        if segment == "attending" {
+            Self::Attending
+        } else if let Some(tag) = segment.strip_prefix("tag:") {
+            Self::Tag(tag.trim().to_string())
+        } else if let Some(wave) = segment.strip_prefix("wave:") {
+            Self::Wave(wave.trim().to_string())
+        } else {
+            Self::All
+        }
+    }
+
+    pub fn includes(&self, record: &RsvpModel) -> bool {
+        match self {
+            Self::All => true,
+            Self::Attending => record.is_attending_any(),
+            Self::Tag(tag) => record.has_tag(tag),
+            Self::Wave(wave) => record.wave.eq_ignore_ascii_case(wave),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct AnnouncementParams {
+    pub segment: String,
+    pub subject: String,
+    pub body: String,
+    /// "preview" or "send", from the button used to submit the form
+    pub action: String,
+}
+
+/// A fully rendered announcement for one guest
+#[derive(Clone, Debug, PartialEq)]
+pub struct Announcement {
+    pub recipient: RsvpModel,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Serialize)]
+struct AnnouncementTemplateContext<'a> {
+    #[serde(flatten)]
+    record: &'a RsvpModel,
+    rsvp_url: String,
+    short_url: String,
+    unsubscribe_url: String,
+}
+
+/// Guests in the segment that can and want to receive announcements
+pub fn recipients(records: Vec<RsvpModel>, segment: &Segment) -> Vec<RsvpModel> {
+    records
+        .into_iter()
+        .filter(|r| segment.includes(r) && r.accepts_email(EmailKind::Announcement))
+        .collect()
+}
+
+/// Render the subject and body for a guest.  Both may use any record field,
+/// e.g. `{name}`, the guest's `{rsvp_url}` and `{short_url}`, and
+/// `{unsubscribe_url}`.  The unsubscribe link is always
+/// added to the end of the body.
+pub fn render(
+    params: &AnnouncementParams,
+    record: &RsvpModel,
+    config: &Config,
+) -> Result<Announcement, Error> {
+    let mut tt = TinyTemplate::new();
+    tt.set_default_formatter(&format_unescaped);
+    tt.add_template("subject", &params.subject)?;
+    tt.add_template("body", &params.body)?;
+    let ctx = AnnouncementTemplateContext {
+        record,
+        rsvp_url: config.rsvp_url(&record.token),
+        short_url: config.short_url(&record.short_code),
+        unsubscribe_url: config.url(&format!("/unsubscribe/{}", record.token)),
+    };
+    let body = tt.render("body", &ctx)?;
+    Ok(Announcement {
+        recipient: record.clone(),
+        subject: tt.render("subject", &ctx)?,
+        body: format!(
+            "{}\n\n--\nTo stop receiving these updates, visit {}\n",
+            body.trim_end(),
+            ctx.unsubscribe_url
+        ),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::csvdb::test::test_db};
+
+    #[test]
+    fn segments() {
+        let records = test_db(10).get_all().unwrap();
+        assert_eq!(recipients(records.clone(), &Segment::All).len(), 10);
+        // every n where n % 2 == 0 || n % 3 == 0 || n % 5 == 0
+        assert_eq!(recipients(records.clone(), &Segment::Attending).len(), 8);
+        assert_eq!(
+            recipients(records, &Segment::Tag("family".to_string())).len(),
+            0
+        );
+        assert_eq!(Segment::parse("attending"), Segment::Attending);
+        assert_eq!(
+            Segment::parse("tag: family"),
+            Segment::Tag("family".to_string())
+        );
+        assert_eq!(Segment::parse("wave:B"), Segment::Wave("B".to_string()));
+        assert_eq!(Segment::parse(""), Segment::All);
+    }
+
+    #[test]
+    fn opted_out() {
+        let mut db = test_db(3);
+        db.update_with("John-1", |r| r.announcements_opt_out = true)
+            .unwrap();
+        let records = recipients(db.get_all().unwrap(), &Segment::All);
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|r| r.name != "John-1"));
+    }
+
+    #[test]
+    fn render_template() {
+        let record = test_db(1).get_all().unwrap().remove(0);
+        let params = AnnouncementParams {
+            segment: "all".to_string(),
+            subject: "News for {name}".to_string(),
+            body: "Hi {name} & {plus_one_name}, the venue changed!".to_string(),
+            action: "preview".to_string(),
+        };
+        let config = Config::default();
+        let announcement = render(&params, &record, &config).unwrap();
+        assert_eq!(announcement.recipient, record);
+        assert_eq!(announcement.subject, "News for John-0");
+        assert!(announcement
+            .body
+            .starts_with("Hi John-0 & Johnson-0, the venue changed!"));
+        assert!(announcement
+            .body
+            .contains(&format!("/unsubscribe/{}", record.token)));
+    }
+
+    #[test]
+    fn plus_one_addresses() {
+        let record = |email: &str, plus_one_email: &str| RsvpModel {
+            email: email.to_string(),
+            plus_one_email: plus_one_email.to_string(),
+            ..test_db(1).get_all().unwrap().remove(0)
+        };
+        let couple = record("jane@example.com", "john@example.com");
+        assert_eq!(
+            couple.email_addresses(),
+            vec!["jane@example.com", "john@example.com"]
+        );
+        assert_eq!(couple.plus_one_inbox(), Some("john@example.com"));
+        // a shared inbox only gets one copy
+        let shared = record("us@example.com", " US@example.com ");
+        assert_eq!(shared.email_addresses(), vec!["us@example.com"]);
+        assert_eq!(shared.plus_one_inbox(), None);
+        assert_eq!(
+            record("", "john@example.com").email_addresses(),
+            vec!["john@example.com"]
+        );
+        assert!(record("", "").email_addresses().is_empty());
+    }
+}
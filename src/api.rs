@@ -0,0 +1,340 @@
+use {
+    crate::{
+        admin::validator,
+        audit::EnteredBy,
+        form::Form,
+        hooks,
+        idempotency::{Replay, IDEMPOTENCY_KEY},
+        inbound::{self, TYPEFORM_SIGNATURE},
+        model::{
+            ListParams, RsvpParams, RsvpResponse, SearchParams, SubscribeParams, WebhookParams,
+        },
+        request::{client_ip, feature_enabled, limit_writes, request_id},
+        source::Source,
+        state::AppState,
+    },
+    actix_web::{
+        body::{EitherBody, MessageBody},
+        dev::{ServiceRequest, ServiceResponse},
+        guard,
+        http::{
+            header::{
+                HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+                ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE, ORIGIN, RETRY_AFTER, VARY,
+            },
+            Method,
+        },
+        middleware::{from_fn, Next},
+        web, Error as ActixError, HttpRequest, HttpResponse, Result,
+    },
+    actix_web_httpauth::middleware::HttpAuthentication,
+    log::{error, info, warn},
+    serde_json::json,
+    tracing::instrument,
+};
+
+/// Routes for the JSON API, which other sites may call if allowed by the
+/// CORS config
+pub fn api_config(config: &mut web::ServiceConfig) {
+    config.service(
+        web::scope("/api/v1")
+            .guard(feature_enabled(|features| features.api))
+            .wrap(from_fn(cors))
+            .service(
+                web::resource("/rsvps")
+                    .guard(guard::Get())
+                    .wrap(HttpAuthentication::basic(validator))
+                    .route(web::get().to(list)),
+            )
+            .service(
+                web::resource("/rsvps")
+                    .wrap(from_fn(limit_writes))
+                    .route(web::post().to(submit)),
+            )
+            .service(web::resource("/rsvps/{name}").route(web::get().to(get_rsvp)))
+            .service(
+                web::resource("/search")
+                    .wrap(HttpAuthentication::basic(validator))
+                    .route(web::get().to(search)),
+            )
+            .service(
+                web::resource("/hooks")
+                    .wrap(HttpAuthentication::basic(validator))
+                    .route(web::get().to(list_hooks))
+                    .route(web::post().to(subscribe)),
+            )
+            .service(
+                web::resource("/hooks/sample")
+                    .wrap(HttpAuthentication::basic(validator))
+                    .route(web::get().to(sample_hooks)),
+            )
+            .service(
+                web::resource("/hooks/{id}")
+                    .wrap(HttpAuthentication::basic(validator))
+                    .route(web::delete().to(unsubscribe)),
+            ),
+    );
+    config.service(
+        web::resource("/webhooks/rsvp")
+            .wrap(from_fn(limit_writes))
+            .route(web::post().to(handle_form)),
+    );
+}
+
+/// Answer preflight requests and add the CORS headers for allowed origins
+async fn cors<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, ActixError> {
+    let config = req
+        .app_data::<web::Data<AppState<'_>>>()
+        .map(|state| state.config.cors.clone())
+        .unwrap_or_default();
+    let origin = req
+        .headers()
+        .get(ORIGIN)
+        .and_then(|origin| origin.to_str().ok())
+        .filter(|origin| config.allows(origin))
+        .and_then(|origin| HeaderValue::from_str(origin).ok());
+
+    if req.method() == Method::OPTIONS {
+        let mut resp = HttpResponse::NoContent();
+        if let Some(origin) = origin {
+            resp.insert_header((ACCESS_CONTROL_ALLOW_ORIGIN, origin))
+                .insert_header((
+                    ACCESS_CONTROL_ALLOW_METHODS,
+                    config.allowed_methods.join(", "),
+                ))
+                .insert_header((
+                    ACCESS_CONTROL_ALLOW_HEADERS,
+                    format!("Content-Type, {}", IDEMPOTENCY_KEY),
+                ))
+                .insert_header((ACCESS_CONTROL_MAX_AGE, config.max_age_secs.to_string()));
+        }
+        resp.insert_header((VARY, "Origin"));
+        return Ok(req.into_response(resp.finish()).map_into_right_body());
+    }
+
+    let mut res = next.call(req).await?;
+    if let Some(origin) = origin {
+        res.headers_mut()
+            .insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    }
+    res.headers_mut()
+        .insert(VARY, HeaderValue::from_static("Origin"));
+    Ok(res.map_into_left_body())
+}
+
+/// Return a page of guests' RSVPs, for the admin only
+#[instrument(skip_all)]
+async fn list(
+    state: web::Data<AppState<'_>>,
+    params: web::Query<ListParams>,
+) -> Result<HttpResponse> {
+    let page = state.read_db().await?.list(&params)?;
+    Ok(HttpResponse::Ok().json(page.map(RsvpResponse::from)))
+}
+
+/// Return the RSVPs matching a search, for the admin only
+#[instrument(skip_all, fields(q = %params.q))]
+async fn search(
+    state: web::Data<AppState<'_>>,
+    params: web::Query<SearchParams>,
+) -> Result<HttpResponse> {
+    let records = state.read_db().await?.search(&params.q)?;
+    let rsvps = records
+        .into_iter()
+        .map(RsvpResponse::from)
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().json(rsvps))
+}
+
+/// Return a guest's RSVP by name.  Names not found count towards the same
+/// lockout as `/fetch`, so the API can't be used to go through a list of
+/// names instead.
+#[instrument(skip_all, fields(name = %name))]
+async fn get_rsvp(
+    state: web::Data<AppState<'_>>,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> Result<HttpResponse> {
+    let client_ip = client_ip(&req);
+    let now = state.clock.now();
+    if let Some(until) = state.fetch_lockout.locked_until(&client_ip, now) {
+        return Ok(HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, (until - now).num_seconds().to_string()))
+            .json(json!({ "error": "too many names not found, try again later" })));
+    }
+    let record = state.read_db().await?.get(&name)?;
+    match record {
+        Some(record) => Ok(HttpResponse::Ok().json(RsvpResponse::from(record))),
+        None => {
+            let config = &state.config.fetch_lockout;
+            if let Some(until) = state.fetch_lockout.miss(&client_ip, config, now) {
+                let params = [("name", name.as_str())];
+                state.lock_out(&req, &client_ip, &params, until).await;
+            }
+            Ok(HttpResponse::NotFound().json(json!({ "error": "name not found" })))
+        }
+    }
+}
+
+/// Add or update an RSVP, just like the form.  Requests with an
+/// `Idempotency-Key` header can be retried, getting back the original
+/// response instead of saving again.
+#[instrument(skip_all, fields(name = %params.name))]
+async fn submit(
+    state: web::Data<AppState<'_>>,
+    req: HttpRequest,
+    params: web::Json<RsvpParams>,
+) -> Result<HttpResponse> {
+    let params = params.into_inner();
+    let key = req
+        .headers()
+        .get(IDEMPOTENCY_KEY)
+        .and_then(|key| key.to_str().ok())
+        .map(str::to_string);
+    let key = match key {
+        Some(key) => key,
+        None => {
+            let rsvp = save(&state, &req, params, Source::Api).await?;
+            return Ok(HttpResponse::Ok().json(rsvp));
+        }
+    };
+
+    let request = serde_json::to_string(&params)?;
+    let replay = state.replays().get(&key, &request, state.clock.now());
+    match replay {
+        Replay::Hit(response) => {
+            info!("Replaying response for idempotency key {}", key);
+            Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .body(response))
+        }
+        Replay::Mismatch => Ok(HttpResponse::UnprocessableEntity().json(json!({
+            "error": "idempotency key was already used for a different request"
+        }))),
+        Replay::Miss => {
+            let response = serde_json::to_string(&save(&state, &req, params, Source::Api).await?)?;
+            state
+                .replays()
+                .insert(&key, request, response.clone(), state.clock.now());
+            Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .body(response))
+        }
+    }
+}
+
+/// Every REST hook subscription, for the admin only
+async fn list_hooks(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(state.hooks.list()))
+}
+
+/// Subscribe a URL to new RSVPs, as Zapier or Make do when a workflow is
+/// turned on, for the admin only
+#[instrument(skip_all, fields(target_url = %params.target_url))]
+async fn subscribe(
+    state: web::Data<AppState<'_>>,
+    params: web::Json<SubscribeParams>,
+) -> Result<HttpResponse> {
+    let subscription = state
+        .hooks
+        .subscribe(&params.target_url, params.event, state.clock.now())
+        .await?;
+    Ok(HttpResponse::Created().json(subscription))
+}
+
+/// Stop sending new RSVPs to a subscription, for the admin only
+#[instrument(skip_all, fields(id = %id))]
+async fn unsubscribe(
+    state: web::Data<AppState<'_>>,
+    id: web::Path<String>,
+) -> Result<HttpResponse> {
+    if state.hooks.unsubscribe(&id)? {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().json(json!({ "error": "subscription not found" })))
+    }
+}
+
+/// Payloads like subscribers get, from the latest RSVPs, for setting up an
+/// automation, for the admin only
+async fn sample_hooks(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let records = state.read_db().await?.get_all()?;
+    Ok(HttpResponse::Ok().json(hooks::samples(&records, state.clock.now())))
+}
+
+/// Add or update an RSVP collected by another form tool, like Typeform or
+/// a Google Form through Apps Script, with its answers mapped to fields by
+/// `form_webhook.fields`
+#[instrument(skip_all)]
+async fn handle_form(
+    state: web::Data<AppState<'_>>,
+    req: HttpRequest,
+    query: web::Query<WebhookParams>,
+    body: web::Bytes,
+) -> Result<HttpResponse> {
+    let config = &state.config.form_webhook;
+    let signature = req
+        .headers()
+        .get(TYPEFORM_SIGNATURE)
+        .and_then(|signature| signature.to_str().ok());
+    if !inbound::authorized(config, query.secret.as_deref(), signature, &body) {
+        warn!("Rejected form webhook with a missing or wrong secret");
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let body = match serde_json::from_slice(&body) {
+        Ok(body) => body,
+        Err(error) => {
+            warn!("Could not read form webhook: {}", error);
+            return Ok(HttpResponse::BadRequest().json(json!({ "error": "expected JSON" })));
+        }
+    };
+    let params = inbound::params(&inbound::answers(&body), &config.fields)?;
+    Ok(HttpResponse::Ok().json(save(&state, &req, params, Source::External).await?))
+}
+
+async fn save(
+    state: &AppState<'_>,
+    req: &HttpRequest,
+    mut params: RsvpParams,
+    source: Source,
+) -> Result<RsvpResponse> {
+    params.source = Some(source);
+    Form::new(&state.config.fields).validate(&mut params)?;
+    let mut db = state.write_db().await?;
+    info!(
+        "New RSVP from the {}! request {}: {:?}",
+        source.value(),
+        request_id(req),
+        params
+    );
+    if let Some(record) = state.find_duplicate(&db, &params)? {
+        return Ok(RsvpResponse::from(record));
+    }
+    let record = state
+        .notify_on_failure(req, "RSVP", &params, db.upsert(&params))
+        .await?;
+    state.audit(req, "RSVP", &record.name, EnteredBy::Guest);
+    state.hooks.deliver_in_background(&record);
+    let contents = db.dump();
+    let attendance = db.attendance()?;
+    if let Err(error) = state
+        .email
+        .send_csv(
+            &params,
+            &record,
+            &attendance,
+            contents,
+            state.clock.now(),
+            state.test,
+        )
+        .await
+    {
+        error!("Could not send confirmation email: {:?}", error);
+    }
+    state.confirm_plus_one(&record).await;
+    let record = state.start_verification(&mut db, record).await?;
+    Ok(RsvpResponse::from(record))
+}
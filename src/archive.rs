@@ -0,0 +1,298 @@
+use {
+    crate::{
+        audit::AuditEntry,
+        config::{ArchiveConfig, Config},
+        csvdb::CsvDb,
+        dietary::{self, Severity},
+        error::Error,
+        export,
+        model::RsvpModel,
+        photos,
+        state::AppState,
+    },
+    actix_web::{
+        body::{EitherBody, MessageBody},
+        dev::{ServiceRequest, ServiceResponse},
+        http::Method,
+        middleware::Next,
+        web, Error as ActixError, HttpResponse,
+    },
+    chrono::{DateTime, Utc},
+    hmac::{Hmac, Mac},
+    log::warn,
+    serde::{Deserialize, Serialize},
+    serde_json::json,
+    sha2::{Digest, Sha256},
+    std::{
+        fs,
+        io::ErrorKind,
+        path::PathBuf,
+        sync::{Mutex, MutexGuard},
+    },
+};
+
+/// Time in bundle names, which sort oldest first
+const TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Checksums of the bundle's files, in the format `sha256sum -c` reads
+const CHECKSUMS: &str = "SHA256SUMS";
+
+/// Pages with a form for guests, which have nothing left to take once the
+/// site is archived
+const GUEST_FORMS: &[&str] = &[
+    "/fetch",
+    "/rsvp",
+    "/i",
+    "/songs",
+    "/unsubscribe",
+    "/contact",
+];
+
+/// When the site was archived, and the bundle taken then
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Frozen {
+    /// Directory of the bundle, e.g. "archive-20240602T120000Z"
+    pub bundle: String,
+    pub frozen_at: DateTime<Utc>,
+    /// Whether guests' personal details were scrubbed afterwards
+    pub scrubbed: bool,
+}
+
+/// The directory of final export bundles, and whether the site is archived,
+/// which is saved there so it stays read-only after a restart
+#[derive(Default)]
+pub struct Archive {
+    dir: PathBuf,
+    frozen: Mutex<Option<Frozen>>,
+}
+impl Archive {
+    pub fn open(config: &ArchiveConfig) -> Result<Self, Error> {
+        let dir = PathBuf::from(&config.dir);
+        let frozen = match fs::read_to_string(dir.join("frozen.json")) {
+            Ok(contents) => Some(serde_json::from_str(&contents)?),
+            Err(error) if error.kind() == ErrorKind::NotFound => None,
+            Err(error) => return Err(error.into()),
+        };
+        Ok(Self {
+            dir,
+            frozen: Mutex::new(frozen),
+        })
+    }
+
+    /// When the site was archived, or `None` if it's still open
+    pub fn frozen(&self) -> Option<Frozen> {
+        self.lock().clone()
+    }
+
+    /// Save the files in a new bundle directory with their checksums, signed
+    /// with the key if there is one, returning the bundle's name
+    pub fn save_bundle(
+        &self,
+        files: &[(&str, String)],
+        key: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<String, Error> {
+        let name = format!("archive-{}", now.format(TIME_FORMAT));
+        let dir = self.dir.join(&name);
+        fs::create_dir_all(&dir)?;
+        let mut checksums = String::new();
+        for (file, contents) in files {
+            fs::write(dir.join(file), contents)?;
+            checksums += &format!("{}  {}\n", hex::encode(Sha256::digest(contents)), file);
+        }
+        fs::write(dir.join(CHECKSUMS), &checksums)?;
+        if let Some(key) = key {
+            fs::write(
+                dir.join(format!("{}.sig", CHECKSUMS)),
+                format!("{}\n", sign(key, &checksums)),
+            )?;
+        }
+        Ok(name)
+    }
+
+    /// Make the site read-only from now on
+    pub fn freeze(&self, frozen: Frozen) -> Result<(), Error> {
+        let mut current = self.lock();
+        fs::create_dir_all(&self.dir)?;
+        fs::write(
+            self.dir.join("frozen.json"),
+            serde_json::to_string(&frozen)?,
+        )?;
+        *current = Some(frozen);
+        Ok(())
+    }
+
+    /// Whether the site is archived, even if a request panicked while
+    /// archiving it
+    fn lock(&self) -> MutexGuard<'_, Option<Frozen>> {
+        self.frozen.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the archive state after a panic");
+            poisoned.into_inner()
+        })
+    }
+}
+
+/// HMAC-SHA256 of the checksums, in hex, to check with
+/// `openssl dgst -sha256 -hmac <key> SHA256SUMS`
+fn sign(key: &str, checksums: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(checksums.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Everything worth keeping about the event: the guest list, scrubbed if
+/// `scrub_pii` is set, the audit log, the final numbers, and what's in each
+/// photo album
+pub fn bundle_files(
+    db: &mut CsvDb,
+    audit: &[AuditEntry],
+    config: &Config,
+) -> Result<Vec<(&'static str, String)>, Error> {
+    let records = db.get_all()?;
+    let audit = audit
+        .iter()
+        .map(|entry| Ok(serde_json::to_string(entry)? + "\n"))
+        .collect::<Result<String, Error>>()?;
+    let stats = json!({
+        "responses": records.len(),
+        "headcount": db.headcount()?,
+        "attendance": db.attendance()?,
+        "dietary": dietary::counts(&records, &config.fields.dietary_options),
+    });
+    let mut albums = vec![];
+    for album in photos::albums(&config.photos)? {
+        let photos = photos::photos(&config.photos, &album.name)?.unwrap_or_default();
+        albums.push(json!({
+            "name": album.name,
+            "title": album.title,
+            "photos": photos.into_iter().map(|photo| photo.url).collect::<Vec<_>>(),
+        }));
+    }
+    let guests = match config.archive.scrub_pii {
+        true => export::csv(&records.iter().map(scrub).collect::<Vec<_>>())?,
+        false => db.dump(),
+    };
+    Ok(vec![
+        ("rsvp.csv", guests),
+        ("audit.jsonl", audit),
+        ("stats.json", serde_json::to_string_pretty(&stats)?),
+        ("photos.json", serde_json::to_string_pretty(&albums)?),
+    ])
+}
+
+/// A guest's record with only what a keepsake needs: who came to what, and
+/// the thank-you list
+pub fn scrub(record: &RsvpModel) -> RsvpModel {
+    RsvpModel {
+        email: String::default(),
+        plus_one_email: String::default(),
+        verification_code: String::default(),
+        dietary_restrictions: String::default(),
+        plus_one_dietary_restrictions: String::default(),
+        dietary: String::default(),
+        plus_one_dietary: String::default(),
+        dietary_severity: Severity::default(),
+        plus_one_dietary_severity: Severity::default(),
+        comments: String::default(),
+        notes: String::default(),
+        kids_ages: String::default(),
+        ..record.clone()
+    }
+}
+
+/// Once the site is archived, turn away anything that would change it and
+/// the guests' forms with the memorial page, or an error for the API
+pub async fn read_only(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, ActixError> {
+    let state = match req.app_data::<web::Data<AppState<'_>>>() {
+        Some(state) => state.clone(),
+        None => return next.call(req).await.map(|res| res.map_into_left_body()),
+    };
+    if state.archive.frozen().is_none() || !is_blocked(req.method(), req.path()) {
+        return next.call(req).await.map(|res| res.map_into_left_body());
+    }
+    let resp = if req.path().starts_with("/api/") {
+        HttpResponse::Gone().json(json!({ "error": "the site is archived" }))
+    } else {
+        let body = state.tt.render("archived.html", &json!({}))?;
+        HttpResponse::Gone().content_type("text/html").body(body)
+    };
+    Ok(req.into_response(resp).map_into_right_body())
+}
+
+/// Anything but reading a page, except entering the site password or signing
+/// the admin in and out, and the guests' forms
+fn is_blocked(method: &Method, path: &str) -> bool {
+    match *method {
+        Method::GET | Method::HEAD => GUEST_FORMS
+            .iter()
+            .any(|form| path == *form || path.starts_with(&format!("{}/", form))),
+        // Calendar and contacts apps read with these, and can't change anything
+        _ if path.starts_with("/dav") => {
+            !matches!(method.as_str(), "OPTIONS" | "PROPFIND" | "REPORT")
+        }
+        _ => !matches!(path, "/enter" | "/admin/login" | "/admin/logout"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, tempfile::tempdir};
+
+    #[test]
+    fn bundle() {
+        let dir = tempdir().unwrap();
+        let config = ArchiveConfig {
+            dir: dir.path().to_str().unwrap().to_string(),
+            ..ArchiveConfig::default()
+        };
+        let archive = Archive::open(&config).unwrap();
+        assert_eq!(archive.frozen(), None);
+        let now = DateTime::parse_from_rfc3339("2024-06-02T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let files = [("rsvp.csv", "name\nJane\n".to_string())];
+        let name = archive.save_bundle(&files, Some("secret"), now).unwrap();
+        assert_eq!(name, "archive-20240602T120000Z");
+        let bundle = dir.path().join(&name);
+        let checksums = fs::read_to_string(bundle.join(CHECKSUMS)).unwrap();
+        assert_eq!(
+            checksums,
+            format!(
+                "{}  rsvp.csv\n",
+                hex::encode(Sha256::digest("name\nJane\n"))
+            )
+        );
+        assert_eq!(
+            fs::read_to_string(bundle.join("SHA256SUMS.sig")).unwrap(),
+            format!("{}\n", sign("secret", &checksums))
+        );
+        assert_ne!(sign("secret", &checksums), sign("other", &checksums));
+
+        let frozen = Frozen {
+            bundle: name,
+            frozen_at: now,
+            scrubbed: false,
+        };
+        archive.freeze(frozen.clone()).unwrap();
+        assert_eq!(Archive::open(&config).unwrap().frozen(), Some(frozen));
+
+        assert!(is_blocked(&Method::POST, "/rsvp"));
+        assert!(is_blocked(&Method::POST, "/admin/notes"));
+        assert!(!is_blocked(&Method::POST, "/enter"));
+        assert!(is_blocked(&Method::GET, "/rsvp/token"));
+        assert!(is_blocked(&Method::GET, "/fetch"));
+        assert!(!is_blocked(&Method::GET, "/fetched"));
+        assert!(!is_blocked(&Method::GET, "/photos"));
+        assert!(!is_blocked(&Method::GET, "/admin"));
+        let propfind = Method::from_bytes(b"PROPFIND").unwrap();
+        assert!(!is_blocked(&propfind, "/dav/contacts/guests/"));
+        assert!(is_blocked(
+            &Method::PUT,
+            "/dav/calendars/events/event-1.ics"
+        ));
+    }
+}
@@ -0,0 +1,113 @@
+use {
+    crate::error::Error,
+    chrono::{DateTime, Utc},
+    derive_more::Display,
+    log::warn,
+    serde::{Deserialize, Serialize},
+    std::{
+        fs::{File, OpenOptions},
+        io::{BufRead, BufReader, Seek, SeekFrom, Write},
+        sync::{Mutex, MutexGuard},
+    },
+    tempfile::tempfile,
+};
+
+/// Who made a change to a guest's record
+#[derive(Clone, Copy, Debug, Display, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EnteredBy {
+    #[display(fmt = "guest")]
+    Guest,
+    /// On the guest's behalf, e.g. for an answer by phone or paper card
+    #[display(fmt = "admin")]
+    Admin,
+}
+
+/// One change to a guest's record, as a line of the audit log
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct AuditEntry {
+    pub at: DateTime<Utc>,
+    pub request_id: String,
+    pub action: String,
+    pub name: String,
+    pub entered_by: EnteredBy,
+}
+
+/// Append-only file with one JSON entry per line
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+impl AuditLog {
+    pub fn open(path: &str) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(&self, entry: &AuditEntry) -> Result<(), Error> {
+        let line = format!("{}\n", serde_json::to_string(entry)?);
+        let mut file = self.file();
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Every entry, oldest first
+    pub fn entries(&self) -> Result<Vec<AuditEntry>, Error> {
+        let mut file = self.file();
+        file.seek(SeekFrom::Start(0))?;
+        BufReader::new(&*file)
+            .lines()
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect()
+    }
+
+    /// The file, even if a request panicked while writing, since entries
+    /// are whole lines
+    fn file(&self) -> MutexGuard<'_, File> {
+        self.file.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the audit log after a panic");
+            poisoned.into_inner()
+        })
+    }
+}
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self {
+            file: Mutex::new(tempfile().unwrap()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn record() {
+        let log = AuditLog::default();
+        assert!(log.entries().unwrap().is_empty());
+        let entry = AuditEntry {
+            at: Utc::now(),
+            request_id: "Ab12Cd34".to_string(),
+            action: "RSVP".to_string(),
+            name: "John".to_string(),
+            entered_by: EnteredBy::Admin,
+        };
+        log.record(&entry).unwrap();
+        log.record(&AuditEntry {
+            entered_by: EnteredBy::Guest,
+            ..entry.clone()
+        })
+        .unwrap();
+        let entries = log.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], entry);
+        assert_eq!(entries[1].entered_by, EnteredBy::Guest);
+    }
+}
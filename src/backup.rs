@@ -0,0 +1,308 @@
+use {
+    crate::{config::BackupConfig, csvdb::HEADER_LINE, error::Error, model::RsvpModel},
+    chrono::{DateTime, NaiveDateTime, SubsecRound, Utc},
+    csv::WriterBuilder,
+    serde::Serialize,
+    serde_json::Value,
+    std::{fs, io::ErrorKind, path::PathBuf},
+};
+
+/// Time in snapshot file names, which sort oldest first
+const TIME_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+/// A copy of the CSV file saved in the backup directory
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Snapshot {
+    /// File name, e.g. "rsvp-20230528T181500.000Z-manual.csv"
+    pub name: String,
+    pub taken_at: DateTime<Utc>,
+    /// Why it was taken, e.g. "startup" or "before-restore"
+    pub reason: String,
+}
+
+/// How a guest's record changed since a snapshot
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct RecordDiff {
+    pub name: String,
+    /// "added", "removed" or "changed"
+    pub change: String,
+    /// Columns that differ, comma-separated, for changed records
+    pub fields: String,
+}
+
+/// Directory of snapshots, keeping only the newest ones
+pub struct Backups {
+    dir: PathBuf,
+    keep: usize,
+}
+impl Backups {
+    pub fn new(config: &BackupConfig) -> Self {
+        Self {
+            dir: PathBuf::from(&config.dir),
+            keep: config.keep,
+        }
+    }
+
+    /// Save the contents of the CSV file, then drop the oldest snapshots
+    /// past the number to keep
+    pub fn take(
+        &self,
+        contents: &str,
+        now: DateTime<Utc>,
+        reason: &str,
+    ) -> Result<Snapshot, Error> {
+        fs::create_dir_all(&self.dir)?;
+        // only milliseconds fit in the name
+        let now = now.trunc_subsecs(3);
+        let name = format!("rsvp-{}-{}.csv", now.format(TIME_FORMAT), reason);
+        fs::write(self.dir.join(&name), contents)?;
+        for old in self.list()?.iter().skip(self.keep) {
+            fs::remove_file(self.dir.join(&old.name))?;
+        }
+        Ok(Snapshot {
+            name,
+            taken_at: now,
+            reason: reason.to_string(),
+        })
+    }
+
+    /// Every snapshot, newest first
+    pub fn list(&self) -> Result<Vec<Snapshot>, Error> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => return Err(error.into()),
+        };
+        let mut snapshots = entries
+            .filter_map(|entry| parse_name(&entry.ok()?.file_name().to_string_lossy()))
+            .collect::<Vec<_>>();
+        snapshots.sort_by(|a, b| b.taken_at.cmp(&a.taken_at).then(b.name.cmp(&a.name)));
+        Ok(snapshots)
+    }
+
+    /// The newest snapshot taken before a time, to compare against for
+    /// everything that changed since then
+    pub fn before(&self, at: DateTime<Utc>) -> Result<Option<Snapshot>, Error> {
+        Ok(self.list()?.into_iter().find(|s| s.taken_at < at))
+    }
+
+    /// Delete every snapshot
+    pub fn clear(&self) -> Result<(), Error> {
+        for snapshot in self.list()? {
+            fs::remove_file(self.dir.join(&snapshot.name))?;
+        }
+        Ok(())
+    }
+
+    /// Contents of a snapshot, or `None` if there's no snapshot by that name.
+    /// Only snapshot file names are accepted, never paths.
+    pub fn read(&self, name: &str) -> Result<Option<String>, Error> {
+        if parse_name(name).is_none() {
+            return Ok(None);
+        }
+        match fs::read_to_string(self.dir.join(name)) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+fn parse_name(name: &str) -> Option<Snapshot> {
+    let stem = name.strip_prefix("rsvp-")?.strip_suffix(".csv")?;
+    let (time, reason) = stem.split_once('-')?;
+    if reason.is_empty()
+        || !reason
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return None;
+    }
+    let taken_at = NaiveDateTime::parse_from_str(time, TIME_FORMAT)
+        .ok()?
+        .and_utc();
+    Some(Snapshot {
+        name: name.to_string(),
+        taken_at,
+        reason: reason.to_string(),
+    })
+}
+
+/// Records added, removed or changed between a snapshot and now, matching
+/// guests by name
+pub fn diff(snapshot: &[RsvpModel], current: &[RsvpModel]) -> Result<Vec<RecordDiff>, Error> {
+    Ok(changes(snapshot, current)?
+        .into_iter()
+        .map(|(diff, _)| diff)
+        .collect())
+}
+
+/// Each record that differs from a snapshot, with the guest's record as it
+/// is now, or as it was in the snapshot if they were removed
+pub fn changes<'a>(
+    snapshot: &'a [RsvpModel],
+    current: &'a [RsvpModel],
+) -> Result<Vec<(RecordDiff, &'a RsvpModel)>, Error> {
+    let mut changes = vec![];
+    for record in current {
+        match snapshot.iter().find(|r| r.name_key == record.name_key) {
+            None => changes.push((
+                RecordDiff {
+                    name: record.name.clone(),
+                    change: "added".to_string(),
+                    fields: String::default(),
+                },
+                record,
+            )),
+            Some(old) => {
+                let fields = changed_fields(old, record)?;
+                if !fields.is_empty() {
+                    changes.push((
+                        RecordDiff {
+                            name: record.name.clone(),
+                            change: "changed".to_string(),
+                            fields: fields.join(", "),
+                        },
+                        record,
+                    ));
+                }
+            }
+        }
+    }
+    for old in snapshot {
+        if !current.iter().any(|r| r.name_key == old.name_key) {
+            changes.push((
+                RecordDiff {
+                    name: old.name.clone(),
+                    change: "removed".to_string(),
+                    fields: String::default(),
+                },
+                old,
+            ));
+        }
+    }
+    Ok(changes)
+}
+
+/// What changed about a record, in the first columns of the export
+#[derive(Serialize)]
+struct ChangeColumns<'a> {
+    change: &'a str,
+    changed_fields: &'a str,
+}
+
+/// CSV of only the changed records, with what changed and which columns
+/// before the database file's own columns, e.g. to send the caterer the
+/// updates since the last full list
+pub fn changes_csv(changes: &[(RecordDiff, &RsvpModel)]) -> Result<String, Error> {
+    let mut wtr = WriterBuilder::new().has_headers(false).from_writer(vec![]);
+    wtr.write_record(
+        ["change", "changed_fields"]
+            .into_iter()
+            .chain(HEADER_LINE.split(',')),
+    )?;
+    for (diff, record) in changes {
+        let columns = ChangeColumns {
+            change: &diff.change,
+            changed_fields: &diff.fields,
+        };
+        wtr.serialize((columns, record))?;
+    }
+    let bytes = wtr.into_inner().map_err(|e| Error::from(e.into_error()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn changed_fields(old: &RsvpModel, new: &RsvpModel) -> Result<Vec<String>, Error> {
+    let (Value::Object(old), Value::Object(new)) =
+        (serde_json::to_value(old)?, serde_json::to_value(new)?)
+    else {
+        return Ok(vec![]);
+    };
+    Ok(new
+        .iter()
+        .filter(|(field, value)| old.get(*field) != Some(value))
+        .map(|(field, _)| field.clone())
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::csvdb::test::test_db, chrono::Duration};
+
+    #[test]
+    fn take_and_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = Backups::new(&BackupConfig {
+            dir: dir.path().to_str().unwrap().to_string(),
+            keep: 2,
+        });
+        assert!(backups.list().unwrap().is_empty());
+        let now = Utc::now();
+        let first = backups.take("first", now, "startup").unwrap();
+        backups
+            .take("second", now + Duration::seconds(1), "manual")
+            .unwrap();
+        let third = backups
+            .take("third", now + Duration::seconds(2), "before-restore")
+            .unwrap();
+        let snapshots = backups.list().unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0], third);
+        assert_eq!(backups.read(&third.name).unwrap().unwrap(), "third");
+        assert!(backups.read(&first.name).unwrap().is_none());
+        assert!(backups.read("../rsvp.csv").unwrap().is_none());
+        assert!(backups.read("rsvp-x-../../etc.csv").unwrap().is_none());
+
+        assert_eq!(
+            backups.before(third.taken_at).unwrap(),
+            Some(snapshots[1].clone())
+        );
+        assert_eq!(backups.before(now).unwrap(), None);
+    }
+
+    #[test]
+    fn diff_records() {
+        let snapshot = test_db(3).get_all().unwrap();
+        let mut current = snapshot[1..].to_vec();
+        current[0].comments = "Changed".to_string();
+        current[0].attending = true;
+        current.push(RsvpModel {
+            name: "New".to_string(),
+            name_key: "new".to_string(),
+            ..snapshot[0].clone()
+        });
+        let diffs = diff(&snapshot, &current).unwrap();
+        let summary = diffs
+            .iter()
+            .map(|d| (d.name.as_str(), d.change.as_str(), d.fields.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("John-1", "changed", "attending, comments"),
+                ("New", "added", ""),
+                ("John-0", "removed", ""),
+            ]
+        );
+        assert!(diff(&snapshot, &snapshot).unwrap().is_empty());
+
+        let csv = changes_csv(&changes(&snapshot, &current).unwrap()).unwrap();
+        let mut lines = csv.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("change,changed_fields,name,email,attending,"));
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("changed,\"attending, comments\",John-1,"));
+        assert!(lines.next().unwrap().starts_with("added,,New,"));
+        assert!(lines.next().unwrap().starts_with("removed,,John-0,"));
+        assert!(lines.next().is_none());
+        assert_eq!(
+            changes_csv(&[]).unwrap().lines().count(),
+            1,
+            "the header is there even with nothing changed"
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use {
+    crate::{
+        config::{BannerConfig, BannerSeverity},
+        error::Error,
+        state::AppState,
+    },
+    actix_web::{
+        body::{self, EitherBody, MessageBody},
+        dev::{ServiceRequest, ServiceResponse},
+        error::ErrorInternalServerError,
+        http::header::CONTENT_TYPE,
+        middleware::Next,
+        web, Error as ActixError,
+    },
+    chrono::{DateTime, NaiveDateTime, Utc},
+    log::warn,
+    serde::{Deserialize, Serialize},
+    std::sync::{Mutex, MutexGuard},
+};
+
+/// Notices shown on the guests' pages, starting from the configured ones and
+/// changed by the admin while running
+#[derive(Default)]
+pub struct Banners {
+    banners: Mutex<Vec<BannerConfig>>,
+}
+impl Banners {
+    pub fn new(banners: &[BannerConfig]) -> Self {
+        Self {
+            banners: Mutex::new(banners.to_vec()),
+        }
+    }
+
+    /// Every banner, including expired ones, in the order they were added
+    pub fn all(&self) -> Vec<BannerConfig> {
+        self.lock().clone()
+    }
+
+    /// Banners to show on a page now
+    pub fn active(&self, path: &str, now: DateTime<Utc>) -> Vec<BannerConfig> {
+        self.lock()
+            .iter()
+            .filter(|banner| banner.expires.is_none_or(|expires| now < expires))
+            .filter(|banner| {
+                banner.paths.is_empty() || banner.paths.iter().any(|p| path.starts_with(p.as_str()))
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn add(&self, banner: BannerConfig) {
+        self.lock().push(banner);
+    }
+
+    /// Take down a banner by its place in `all`, returning whether there was
+    /// one
+    pub fn remove(&self, index: usize) -> bool {
+        let mut banners = self.lock();
+        match index < banners.len() {
+            true => {
+                banners.remove(index);
+                true
+            }
+            false => false,
+        }
+    }
+
+    /// The banners, even if a request panicked while changing them, since
+    /// each change is a single push or remove
+    fn lock(&self) -> MutexGuard<'_, Vec<BannerConfig>> {
+        self.banners.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the banners after a panic");
+            poisoned.into_inner()
+        })
+    }
+}
+
+/// The admin's form to put up a banner
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct BannerParams {
+    pub text: String,
+    pub severity: BannerSeverity,
+    /// From a `datetime-local` input, in UTC, or empty to never expire
+    #[serde(default)]
+    pub expires: String,
+    /// Comma-separated path prefixes, or empty for every page
+    #[serde(default)]
+    pub paths: String,
+}
+impl BannerParams {
+    pub fn into_banner(self) -> Result<BannerConfig, Error> {
+        let expires = match self.expires.trim() {
+            "" => None,
+            expires => Some(
+                NaiveDateTime::parse_from_str(expires, "%Y-%m-%dT%H:%M")
+                    .map_err(|e| Error::Query(format!("expires: {}", e)))?
+                    .and_utc(),
+            ),
+        };
+        Ok(BannerConfig {
+            text: self.text.trim().to_string(),
+            severity: self.severity,
+            expires,
+            paths: self
+                .paths
+                .split(',')
+                .map(str::trim)
+                .filter(|path| !path.is_empty())
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+}
+
+/// Middleware adding the active banners to the top of every guest page,
+/// whichever template or engine rendered it
+pub async fn show_banners(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, ActixError> {
+    let state = req.app_data::<web::Data<AppState<'_>>>().cloned();
+    let res = next.call(req).await?;
+    let state = match &state {
+        Some(state) => state,
+        None => return Ok(res.map_into_left_body()),
+    };
+    let is_page = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    let path = res.request().path();
+    if !is_page || path.starts_with("/admin") {
+        return Ok(res.map_into_left_body());
+    }
+    let banners = state.banners.active(path, state.clock.now());
+    if banners.is_empty() {
+        return Ok(res.map_into_left_body());
+    }
+    let (req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let bytes = body::to_bytes(body)
+        .await
+        .map_err(|e| ErrorInternalServerError(e.into().to_string()))?;
+    let page = insert(&String::from_utf8_lossy(&bytes), &render(&banners));
+    let res = res.set_body(page).map_into_boxed_body();
+    Ok(ServiceResponse::new(req, res).map_into_right_body())
+}
+
+/// The banners as HTML, styled by `/theme.css`
+pub fn render(banners: &[BannerConfig]) -> String {
+    banners
+        .iter()
+        .map(|banner| {
+            let role = match banner.severity {
+                BannerSeverity::Info => "status",
+                _ => "alert",
+            };
+            format!(
+                "<div class=\"site-banner site-banner--{}\" role=\"{}\">{}</div>\n",
+                banner.severity.as_str(),
+                role,
+                html_escape(&banner.text)
+            )
+        })
+        .collect()
+}
+
+/// The page with the banners, or other notices, right after its opening
+/// `<body>` tag, or unchanged if it has none
+pub fn insert(page: &str, banners: &str) -> String {
+    let at = page
+        .find("<body")
+        .and_then(|start| page[start..].find('>').map(|end| start + end + 1));
+    match at {
+        Some(at) => format!("{}\n{}{}", &page[..at], banners, &page[at..]),
+        None => page.to_string(),
+    }
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, chrono::TimeZone};
+
+    #[test]
+    fn active() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let banners = Banners::new(&[
+            BannerConfig {
+                text: "Parking changed".to_string(),
+                severity: BannerSeverity::Warning,
+                paths: vec!["/photos".to_string()],
+                ..BannerConfig::default()
+            },
+            BannerConfig {
+                text: "RSVPs close soon".to_string(),
+                expires: Some(now - chrono::Duration::hours(1)),
+                ..BannerConfig::default()
+            },
+        ]);
+        let texts = |path| {
+            banners
+                .active(path, now)
+                .into_iter()
+                .map(|banner| banner.text)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(texts("/photos/venue"), vec!["Parking changed"]);
+        assert!(texts("/").is_empty());
+
+        banners.add(BannerConfig {
+            text: "Welcome <all>".to_string(),
+            ..BannerConfig::default()
+        });
+        assert_eq!(texts("/"), vec!["Welcome <all>"]);
+        assert!(banners.remove(0));
+        assert!(!banners.remove(5));
+        assert_eq!(banners.all().len(), 2);
+
+        let html = render(&banners.active("/", now));
+        assert_eq!(
+            html,
+            "<div class=\"site-banner site-banner--info\" role=\"status\">Welcome &lt;all&gt;</div>\n"
+        );
+        assert_eq!(
+            insert("<html><body class=\"x\"><p>Hi</p></body>", "<b>!</b>"),
+            "<html><body class=\"x\">\n<b>!</b><p>Hi</p></body>"
+        );
+        assert_eq!(insert("plain", "<b>!</b>"), "plain");
+
+        let params = BannerParams {
+            text: " Shuttle leaves at 5 ".to_string(),
+            severity: BannerSeverity::Critical,
+            expires: "2024-06-02T08:30".to_string(),
+            paths: "/, /photos ,".to_string(),
+        };
+        let banner = params.clone().into_banner().unwrap();
+        assert_eq!(banner.text, "Shuttle leaves at 5");
+        assert_eq!(
+            banner.expires,
+            Some(Utc.with_ymd_and_hms(2024, 6, 2, 8, 30, 0).unwrap())
+        );
+        assert_eq!(banner.paths, vec!["/", "/photos"]);
+        let params = BannerParams {
+            expires: "tomorrow".to_string(),
+            ..params
+        };
+        assert!(params.into_banner().is_err());
+    }
+}
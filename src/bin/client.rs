@@ -19,6 +19,23 @@ struct Args {
     /// New person's plus-one's name
     #[arg()]
     plus_one: String,
+
+    /// Comma-separated tags for the new person, e.g. "family"
+    #[arg(short, long, default_value_t = String::new())]
+    tags: String,
+
+    /// Seats for the whole party, by default 1, or 2 with a plus-one
+    #[arg(short, long)]
+    invited: Option<u32>,
+
+    /// Language code to email the new person in, like "fr", by default the
+    /// server's
+    #[arg(short, long, default_value_t = String::new())]
+    language: String,
+
+    /// Round of invitations the new person is in, e.g. "A" or "2"
+    #[arg(short, long, default_value_t = String::new())]
+    wave: String,
 }
 
 #[actix_web::main]
@@ -31,6 +48,10 @@ async fn main() {
         name: matches.name,
         email: matches.email,
         plus_one_name: matches.plus_one,
+        tags: matches.tags,
+        invited: matches.invited,
+        language: matches.language,
+        wave: matches.wave,
     };
 
     // Create request builder and send request
@@ -0,0 +1,145 @@
+use {
+    actix_web_rsvp::{
+        dedupe::DuplicatePair,
+        doctor::Report,
+        model::{AnswersFrom, MergeParams},
+    },
+    awc::Client,
+    clap::{Parser, Subcommand},
+};
+
+/// Admin tasks on a running RSVP server
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// URL hosting the RSVP server
+    #[arg(short, long, default_value_t = String::from("http://127.0.0.1:8080"))]
+    url: String,
+
+    /// Admin username from the server's config
+    #[arg(long, default_value_t = String::from("admin"))]
+    username: String,
+
+    /// Admin password from the server's config
+    #[arg(long)]
+    password: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List records that may be the same guest
+    Dedupe,
+    /// Merge the second guest's record into the first's and remove it
+    Merge {
+        /// Name of the record to keep
+        keep: String,
+        /// Name of the record to merge and remove
+        remove: String,
+        /// Whose RSVP answers to keep
+        #[arg(long, value_enum, default_value_t = AnswersFrom::Latest)]
+        answers_from: AnswersFrom,
+    },
+    /// Save the final export bundle after the event and make the site
+    /// read-only for good, scrubbing personal details if the server's config
+    /// asks to
+    Archive,
+    /// Check the server's config, files, templates and email setup, exiting
+    /// with an error if anything would fail for guests
+    Doctor,
+}
+
+#[actix_web::main]
+async fn main() {
+    let matches = Args::parse();
+    let client = Client::default();
+
+    match matches.command {
+        Command::Dedupe => {
+            let response = client
+                .get(format!("{}/admin/duplicates.json", matches.url))
+                .basic_auth(&matches.username, &matches.password)
+                .send()
+                .await;
+            let pairs = match response {
+                Ok(mut response) if response.status().is_success() => {
+                    response.json::<Vec<DuplicatePair>>().await
+                }
+                response => {
+                    println!("Response: {:?}", response);
+                    return;
+                }
+            };
+            match pairs {
+                Ok(pairs) if pairs.is_empty() => println!("No possible duplicates found"),
+                Ok(pairs) => {
+                    for pair in pairs {
+                        println!(
+                            "{} <{}> / {} <{}>: {}",
+                            pair.first,
+                            pair.first_email,
+                            pair.second,
+                            pair.second_email,
+                            pair.reason
+                        );
+                    }
+                }
+                Err(error) => println!("Could not read response: {:?}", error),
+            }
+        }
+        Command::Merge {
+            keep,
+            remove,
+            answers_from,
+        } => {
+            let response = client
+                .post(format!("{}/admin/merge", matches.url))
+                .basic_auth(&matches.username, &matches.password)
+                .send_form(&MergeParams {
+                    keep,
+                    remove,
+                    answers_from,
+                })
+                .await;
+            println!("Response: {:?}", response);
+        }
+        Command::Archive => {
+            let response = client
+                .post(format!("{}/admin/archive", matches.url))
+                .basic_auth(&matches.username, &matches.password)
+                .send()
+                .await;
+            println!("Response: {:?}", response);
+        }
+        Command::Doctor => {
+            let response = client
+                .get(format!("{}/admin/doctor.json", matches.url))
+                .basic_auth(&matches.username, &matches.password)
+                .send()
+                .await;
+            let report = match response {
+                Ok(mut response) if response.status().is_success() => {
+                    response.json::<Report>().await
+                }
+                response => {
+                    println!("Response: {:?}", response);
+                    std::process::exit(1);
+                }
+            };
+            match report {
+                Ok(report) => {
+                    print!("{}", report);
+                    if !report.ok() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(error) => {
+                    println!("Could not read response: {:?}", error);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use {crate::model::EmailStatus, log::info, serde_json::Value};
+
+/// An email address that the provider could not deliver to, or that complained
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bounce {
+    pub email: String,
+    pub status: EmailStatus,
+}
+impl Bounce {
+    fn new(email: &str, status: EmailStatus) -> Self {
+        Self {
+            email: email.to_string(),
+            status,
+        }
+    }
+}
+
+/// Extract all bounces and complaints from a provider's notification body.
+///
+/// Supports Amazon SES (through SNS, with or without raw message delivery),
+/// SendGrid's event webhook, and Mailgun's webhooks.  Only permanent failures
+/// are reported, since the provider retries temporary ones.
+pub fn parse_notification(body: &Value) -> Vec<Bounce> {
+    match body {
+        Value::Array(events) => events.iter().filter_map(parse_sendgrid).collect(),
+        Value::Object(map) if map.contains_key("event-data") => {
+            parse_mailgun(&map["event-data"]).into_iter().collect()
+        }
+        Value::Object(map) if map.get("Type").and_then(Value::as_str) == Some("Notification") => {
+            map.get("Message")
+                .and_then(Value::as_str)
+                .and_then(|message| serde_json::from_str(message).ok())
+                .map(|message| parse_ses(&message))
+                .unwrap_or_default()
+        }
+        Value::Object(map)
+            if map.get("Type").and_then(Value::as_str) == Some("SubscriptionConfirmation") =>
+        {
+            info!(
+                "SNS subscription needs confirmation, visit: {}",
+                map.get("SubscribeURL")
+                    .and_then(Value::as_str)
+                    .unwrap_or("")
+            );
+            vec![]
+        }
+        Value::Object(_) => parse_ses(body),
+        _ => vec![],
+    }
+}
+
+fn parse_ses(message: &Value) -> Vec<Bounce> {
+    let recipients = |list: &Value, status| {
+        list.as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|r| r["emailAddress"].as_str())
+            .map(|email| Bounce::new(email, status))
+            .collect::<Vec<_>>()
+    };
+    let notification_type = message["notificationType"]
+        .as_str()
+        .or_else(|| message["eventType"].as_str());
+    match notification_type {
+        Some("Bounce") if message["bounce"]["bounceType"] == "Permanent" => recipients(
+            &message["bounce"]["bouncedRecipients"],
+            EmailStatus::Bounced,
+        ),
+        Some("Complaint") => recipients(
+            &message["complaint"]["complainedRecipients"],
+            EmailStatus::Complained,
+        ),
+        _ => vec![],
+    }
+}
+
+fn parse_sendgrid(event: &Value) -> Option<Bounce> {
+    let email = event["email"].as_str()?;
+    match event["event"].as_str()? {
+        "bounce" if event["type"] != "blocked" => Some(Bounce::new(email, EmailStatus::Bounced)),
+        "dropped" => Some(Bounce::new(email, EmailStatus::Bounced)),
+        "spamreport" => Some(Bounce::new(email, EmailStatus::Complained)),
+        _ => None,
+    }
+}
+
+fn parse_mailgun(event: &Value) -> Option<Bounce> {
+    let email = event["recipient"].as_str()?;
+    match event["event"].as_str()? {
+        "failed" if event["severity"] == "permanent" => {
+            Some(Bounce::new(email, EmailStatus::Bounced))
+        }
+        "complained" => Some(Bounce::new(email, EmailStatus::Complained)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, serde_json::json};
+
+    #[test]
+    fn ses() {
+        let message = json!({
+            "notificationType": "Bounce",
+            "bounce": {
+                "bounceType": "Permanent",
+                "bouncedRecipients": [{ "emailAddress": "john@john.john" }],
+            },
+        });
+        let sns = json!({
+            "Type": "Notification",
+            "Message": message.to_string(),
+        });
+        let expected = vec![Bounce::new("john@john.john", EmailStatus::Bounced)];
+        assert_eq!(parse_notification(&sns), expected);
+        assert_eq!(parse_notification(&message), expected);
+
+        let transient = json!({
+            "notificationType": "Bounce",
+            "bounce": {
+                "bounceType": "Transient",
+                "bouncedRecipients": [{ "emailAddress": "john@john.john" }],
+            },
+        });
+        assert!(parse_notification(&transient).is_empty());
+
+        let complaint = json!({
+            "notificationType": "Complaint",
+            "complaint": {
+                "complainedRecipients": [{ "emailAddress": "john@john.john" }],
+            },
+        });
+        assert_eq!(
+            parse_notification(&complaint),
+            vec![Bounce::new("john@john.john", EmailStatus::Complained)]
+        );
+    }
+
+    #[test]
+    fn sendgrid() {
+        let events = json!([
+            { "email": "a@example.com", "event": "bounce", "type": "bounce" },
+            { "email": "b@example.com", "event": "bounce", "type": "blocked" },
+            { "email": "c@example.com", "event": "spamreport" },
+            { "email": "d@example.com", "event": "delivered" },
+        ]);
+        assert_eq!(
+            parse_notification(&events),
+            vec![
+                Bounce::new("a@example.com", EmailStatus::Bounced),
+                Bounce::new("c@example.com", EmailStatus::Complained),
+            ]
+        );
+    }
+
+    #[test]
+    fn mailgun() {
+        let failed = json!({
+            "signature": {},
+            "event-data": {
+                "event": "failed",
+                "severity": "permanent",
+                "recipient": "a@example.com",
+            },
+        });
+        assert_eq!(
+            parse_notification(&failed),
+            vec![Bounce::new("a@example.com", EmailStatus::Bounced)]
+        );
+        let temporary = json!({
+            "event-data": {
+                "event": "failed",
+                "severity": "temporary",
+                "recipient": "a@example.com",
+            },
+        });
+        assert!(parse_notification(&temporary).is_empty());
+    }
+}
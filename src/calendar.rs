@@ -0,0 +1,335 @@
+use {
+    crate::{
+        config::{Config, EventConfig},
+        feeds::{ics_escape, ics_fold},
+        model::RsvpModel,
+        timezone::Timezone,
+    },
+    chrono::{DateTime, NaiveDateTime, SecondsFormat, TimeZone, Utc},
+    serde::{Deserialize, Serialize},
+};
+
+/// Times in Google Calendar links and iCalendar files, always in UTC
+const COMPACT_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Ready-made links for adding one event to a guest's calendar, so the
+/// templates only have to show them
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct CalendarLinks {
+    pub title: String,
+    pub google: String,
+    pub outlook: String,
+    /// The event's `.ics` file on the site, for Apple Calendar and the rest
+    pub ics: String,
+}
+
+/// An event's start and end in UTC, or `None` until both are set.  A local
+/// time skipped by daylight saving is also `None`, and one that happens
+/// twice is taken the first time.
+pub fn times(event: &EventConfig, timezone: Timezone) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let utc = |local: NaiveDateTime| {
+        timezone
+            .0
+            .from_local_datetime(&local)
+            .earliest()
+            .map(|at| at.with_timezone(&Utc))
+    };
+    Some((utc(event.starts_at?)?, utc(event.ends_at?)?))
+}
+
+/// Link that opens a new Google Calendar event filled in with this one
+pub fn google_url(event: &EventConfig, starts_at: DateTime<Utc>, ends_at: DateTime<Utc>) -> String {
+    let dates = format!(
+        "{}/{}",
+        starts_at.format(COMPACT_FORMAT),
+        ends_at.format(COMPACT_FORMAT)
+    );
+    let query = serde_urlencoded::to_string([
+        ("action", "TEMPLATE"),
+        ("text", &event.title),
+        ("dates", &dates),
+        ("details", &event.description),
+        ("location", &event.location),
+    ])
+    .unwrap_or_default();
+    format!("https://calendar.google.com/calendar/render?{}", query)
+}
+
+/// Link that opens a new Outlook event filled in with this one
+pub fn outlook_url(
+    event: &EventConfig,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+) -> String {
+    let query = serde_urlencoded::to_string([
+        ("path", "/calendar/action/compose"),
+        ("rru", "addevent"),
+        ("subject", &event.title),
+        (
+            "startdt",
+            &starts_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        ),
+        ("enddt", &ends_at.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        ("body", &event.description),
+        ("location", &event.location),
+    ])
+    .unwrap_or_default();
+    format!(
+        "https://outlook.live.com/calendar/0/deeplink/compose?{}",
+        query
+    )
+}
+
+/// iCalendar file with just this event, numbered from 1 like its URL, or
+/// `None` if there's no such event or it has no times yet
+pub fn ics(config: &Config, number: usize, now: DateTime<Utc>) -> Option<String> {
+    calendar_file(config, number, now, vec![])
+}
+
+/// The same iCalendar file with everyone coming to the event as attendees,
+/// for the admin's own calendar only
+pub fn ics_with_guests(
+    config: &Config,
+    number: usize,
+    records: &[RsvpModel],
+    stamp: DateTime<Utc>,
+) -> Option<String> {
+    let attendees = records
+        .iter()
+        .flat_map(|record| {
+            let (guest, plus_one) = coming_to(record, number);
+            let plus_one_address = record.plus_one_inbox().unwrap_or_default();
+            [
+                guest.then(|| attendee(&record.name, record.email.trim(), &record.name_key)),
+                (plus_one && !record.plus_one_name.trim().is_empty()).then(|| {
+                    attendee(
+                        &record.plus_one_name,
+                        plus_one_address,
+                        &format!("{}+1", record.name_key),
+                    )
+                }),
+            ]
+        })
+        .flatten()
+        .collect();
+    calendar_file(config, number, stamp, attendees)
+}
+
+/// Whether the guest and their plus-one are coming to an event, numbered
+/// from 1
+fn coming_to(record: &RsvpModel, number: usize) -> (bool, bool) {
+    match number {
+        1 => (
+            record.attending,
+            record.attending && record.plus_one_attending,
+        ),
+        2 => (
+            record.attending_secondary,
+            record.attending_secondary && record.plus_one_attending_secondary,
+        ),
+        3 => (
+            record.attending_tertiary,
+            record.attending_tertiary && record.plus_one_attending_tertiary,
+        ),
+        _ => (false, false),
+    }
+}
+
+/// An accepted attendee, by email address if they have one
+fn attendee(name: &str, email: &str, key: &str) -> String {
+    let address = if email.is_empty() {
+        format!("urn:actix-web-rsvp:guest:{}", key.replace(' ', "-"))
+    } else {
+        format!("mailto:{}", email)
+    };
+    format!(
+        "ATTENDEE;CN=\"{}\";ROLE=REQ-PARTICIPANT;PARTSTAT=ACCEPTED:{}",
+        name.trim().replace('"', ""),
+        address
+    )
+}
+
+fn calendar_file(
+    config: &Config,
+    number: usize,
+    now: DateTime<Utc>,
+    attendees: Vec<String>,
+) -> Option<String> {
+    let event = config.events.get(number.checked_sub(1)?)?;
+    let (starts_at, ends_at) = times(event, config.timezone)?;
+    let lines = [
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//actix-web-rsvp//Events//EN".to_string(),
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:event-{}@actix-web-rsvp", number),
+        format!("DTSTAMP:{}", now.format(COMPACT_FORMAT)),
+        format!("DTSTART:{}", starts_at.format(COMPACT_FORMAT)),
+        format!("DTEND:{}", ends_at.format(COMPACT_FORMAT)),
+        format!("SUMMARY:{}", ics_escape(&event.title)),
+        format!("LOCATION:{}", ics_escape(&event.location)),
+        format!("DESCRIPTION:{}", ics_escape(&event.description)),
+    ]
+    .into_iter()
+    .chain(attendees)
+    .chain(["END:VEVENT".to_string(), "END:VCALENDAR".to_string()]);
+    Some(lines.map(|line| ics_fold(&line)).collect())
+}
+
+/// Links for each event the guest is coming to that has its times set
+pub fn links(config: &Config, record: &RsvpModel) -> Vec<CalendarLinks> {
+    links_for(
+        config,
+        [
+            record.attending,
+            record.attending_secondary,
+            record.attending_tertiary,
+        ],
+    )
+}
+
+/// Links for each event the plus-one is coming to with the guest
+pub fn plus_one_links(config: &Config, record: &RsvpModel) -> Vec<CalendarLinks> {
+    links_for(
+        config,
+        [
+            record.attending && record.plus_one_attending,
+            record.attending_secondary && record.plus_one_attending_secondary,
+            record.attending_tertiary && record.plus_one_attending_tertiary,
+        ],
+    )
+}
+
+/// Links for whichever of the main, secondary and tertiary events are
+/// `attending`
+fn links_for(config: &Config, attending: [bool; 3]) -> Vec<CalendarLinks> {
+    config
+        .events
+        .iter()
+        .zip(attending)
+        .enumerate()
+        .filter(|(_, (_, attending))| *attending)
+        .filter_map(|(index, (event, _))| {
+            let (starts_at, ends_at) = times(event, config.timezone)?;
+            Some(CalendarLinks {
+                title: event.title.clone(),
+                google: google_url(event, starts_at, ends_at),
+                outlook: outlook_url(event, starts_at, ends_at),
+                ics: config.url(&format!("/calendar/{}.ics", index + 1)),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            csvdb::test::{test_db, test_rsvp},
+            model::RsvpParams,
+        },
+    };
+
+    fn config() -> Config {
+        Config::from_toml(
+            r#"
+base_url = "https://example.com"
+timezone = "America/New_York"
+[[events]]
+title = "Ceremony & reception"
+starts_at = "2024-06-01T16:00:00"
+ends_at = "2024-06-01T23:00:00"
+location = "The Grand Hall, 1 Main St"
+description = "Dinner; dancing"
+[[events]]
+title = "Brunch"
+[[events]]
+title = "Hike"
+starts_at = "2024-06-02T09:00:00"
+ends_at = "2024-06-02T12:00:00"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn event_links() {
+        let config = config();
+        let (starts_at, ends_at) = times(&config.events[0], config.timezone).unwrap();
+        assert_eq!(starts_at.to_rfc3339(), "2024-06-01T20:00:00+00:00");
+        assert_eq!(ends_at.to_rfc3339(), "2024-06-02T03:00:00+00:00");
+        assert_eq!(times(&config.events[1], config.timezone), None);
+
+        let record = RsvpModel::new_with_rsvp(
+            &RsvpParams {
+                attending: true,
+                attending_secondary: true,
+                attending_tertiary: false,
+                ..test_rsvp()
+            },
+            Utc::now(),
+        );
+        // brunch has no times and the guest isn't hiking
+        let links = links(&config, &record);
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].title, "Ceremony & reception");
+        assert_eq!(
+            links[0].google,
+            "https://calendar.google.com/calendar/render?action=TEMPLATE\
+             &text=Ceremony+%26+reception&dates=20240601T200000Z%2F20240602T030000Z\
+             &details=Dinner%3B+dancing&location=The+Grand+Hall%2C+1+Main+St"
+        );
+        assert!(links[0]
+            .outlook
+            .contains("&startdt=2024-06-01T20%3A00%3A00Z&enddt=2024-06-02T03%3A00%3A00Z"));
+        assert_eq!(links[0].ics, "https://example.com/calendar/1.ics");
+
+        let declined = RsvpModel::new_with_rsvp(
+            &RsvpParams {
+                attending: false,
+                attending_secondary: false,
+                attending_tertiary: false,
+                ..test_rsvp()
+            },
+            Utc::now(),
+        );
+        assert!(super::links(&config, &declined).is_empty());
+
+        let plus_one = RsvpModel {
+            plus_one_attending: false,
+            plus_one_attending_secondary: true,
+            ..record
+        };
+        assert!(plus_one_links(&config, &plus_one).is_empty());
+        let plus_one = RsvpModel {
+            plus_one_attending: true,
+            ..plus_one
+        };
+        assert_eq!(plus_one_links(&config, &plus_one).len(), 1);
+    }
+
+    #[test]
+    fn event_ics() {
+        let config = config();
+        let ics = ics(&config, 1, Utc::now()).unwrap();
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.contains("DTSTART:20240601T200000Z\r\n"));
+        assert!(ics.contains("SUMMARY:Ceremony & reception\r\n"));
+        assert!(ics.contains("LOCATION:The Grand Hall\\, 1 Main St\r\n"));
+        assert_eq!(super::ics(&config, 0, Utc::now()), None);
+        assert_eq!(super::ics(&config, 2, Utc::now()), None);
+        assert_eq!(super::ics(&config, 4, Utc::now()), None);
+        assert!(!ics.contains("ATTENDEE"));
+
+        let records = test_db(2).get_all().unwrap();
+        let ics = ics_with_guests(&config, 1, &records, Utc::now())
+            .unwrap()
+            .replace("\r\n ", "");
+        assert!(ics.contains(
+            "ATTENDEE;CN=\"John-0\";ROLE=REQ-PARTICIPANT;PARTSTAT=ACCEPTED:mailto:john0@john.john\r\n"
+        ));
+        assert_eq!(ics.matches("ATTENDEE").count(), 2);
+        assert!(!ics.contains("John-1"));
+    }
+}
@@ -0,0 +1,129 @@
+use {
+    crate::{dietary::Choice, error::Error, model::RsvpModel},
+    csv::WriterBuilder,
+    serde::{Deserialize, Serialize},
+};
+
+/// How many kids the party is signing up, for the form, from none up to the
+/// most allowed
+pub fn choices(max_kids: u32, selected: u32) -> Vec<Choice> {
+    (0..=max_kids.max(selected))
+        .map(|kids| Choice {
+            value: kids.to_string(),
+            label: match kids {
+                0 => "None".to_string(),
+                _ => kids.to_string(),
+            },
+            checked: kids == selected,
+        })
+        .collect()
+}
+
+/// Kids signed up for childcare, for the admin
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ChildcareSummary {
+    pub parties: usize,
+    pub kids: u32,
+}
+
+/// Parties coming with kids signed up, by name
+fn signed_up(records: &[RsvpModel]) -> Vec<&RsvpModel> {
+    let mut parties = records
+        .iter()
+        .filter(|r| r.kids > 0 && r.is_attending_any())
+        .collect::<Vec<_>>();
+    parties.sort_by_key(|r| r.name_key.clone());
+    parties
+}
+
+pub fn summary(records: &[RsvpModel]) -> ChildcareSummary {
+    let parties = signed_up(records);
+    ChildcareSummary {
+        parties: parties.len(),
+        kids: parties.iter().map(|r| r.kids).sum(),
+    }
+}
+
+/// CSV for the babysitting service with a row per party: how many kids,
+/// how old, and who to reach
+pub fn csv(records: &[RsvpModel]) -> Result<String, Error> {
+    let mut wtr = WriterBuilder::new().from_writer(vec![]);
+    wtr.write_record(["Parent", "Kids", "Ages", "Email", "Table"])?;
+    for record in signed_up(records) {
+        wtr.write_record([
+            record.name.as_str(),
+            &record.kids.to_string(),
+            record.kids_ages.as_str(),
+            record.email.as_str(),
+            record.table.as_str(),
+        ])?;
+    }
+    let bytes = wtr.into_inner().map_err(|e| Error::from(e.into_error()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            csvdb::test::{test_add, test_db, test_rsvp},
+            model::{AddParams, RsvpParams},
+        },
+    };
+
+    #[test]
+    fn report() {
+        let choices = choices(2, 1)
+            .into_iter()
+            .map(|c| (c.label, c.checked))
+            .collect::<Vec<_>>();
+        let expected = [("None", false), ("1", true), ("2", false)]
+            .map(|(label, checked)| (label.to_string(), checked));
+        assert_eq!(choices, expected);
+
+        let mut db = test_db(0);
+        db.childcare.tag = "kids".to_string();
+        db.childcare.max_kids = 3;
+        for name in ["Jane", "John", "Jim"] {
+            db.insert(&AddParams {
+                name: name.to_string(),
+                email: format!("{}@example.com", name.to_lowercase()),
+                tags: "kids".to_string(),
+                ..test_add()
+            })
+            .unwrap();
+        }
+        let mut rsvp = |name: &str, attending: bool, kids: u32| {
+            db.upsert(&RsvpParams {
+                name: name.to_string(),
+                email: format!("{}@example.com", name.to_lowercase()),
+                attending,
+                attending_secondary: attending,
+                kids,
+                kids_ages: "2, 5, 9, 11".to_string(),
+                ..test_rsvp()
+            })
+            .unwrap()
+        };
+        // capped at the most allowed
+        assert_eq!(rsvp("Jane", true, 5).kids, 3);
+        assert_eq!(rsvp("John", true, 0).kids_ages, "");
+        assert_eq!(rsvp("Jim", false, 2).kids, 0);
+        // not tagged as having children
+        assert_eq!(rsvp("Joan", true, 2).kids, 0);
+
+        let records = db.get_all().unwrap();
+        assert_eq!(
+            summary(&records),
+            ChildcareSummary {
+                parties: 1,
+                kids: 3
+            }
+        );
+        assert_eq!(
+            csv(&records).unwrap(),
+            "Parent,Kids,Ages,Email,Table\nJane,3,\"2, 5, 9, 11\",jane@example.com,\n"
+        );
+    }
+}
@@ -0,0 +1,2316 @@
+use {
+    crate::{
+        csvdb::{DEFAULT_SHORT_CODE_LENGTH, DEFAULT_UNDO_HISTORY},
+        error::Error,
+        timezone::Timezone,
+    },
+    actix_web::http::KeepAlive,
+    chrono::{DateTime, NaiveDate, NaiveDateTime, Utc},
+    serde::{Deserialize, Serialize},
+    sha2::{Digest, Sha256},
+    std::{collections::BTreeMap, fs, time::Duration},
+};
+
+/// Optional deployment configuration, loaded from a TOML file.
+///
+/// Every field has a default, so an empty or partial file is valid.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// Send a code to the email entered on the RSVP form, and only mark the
+    /// record verified once the guest enters it back
+    pub verify_email: bool,
+
+    /// Credentials for the admin pages under `/admin`
+    pub admin: AdminConfig,
+
+    /// Shared secret that email providers must pass in the `X-Webhook-Secret`
+    /// header, or as the `secret` query parameter, when calling the bounce
+    /// webhook
+    pub webhook_secret: Option<String>,
+
+    /// RSVPs collected by other form tools, like Typeform or a Google Form,
+    /// sent to `/webhooks/rsvp`
+    pub form_webhook: FormWebhookConfig,
+
+    /// Automation services like Zapier or Make subscribed to new RSVPs
+    /// through the JSON API's REST hooks
+    pub hooks: HooksConfig,
+
+    /// Saved column choices for the guest list export
+    pub export: ExportConfig,
+
+    /// Public address of the site, used for links in emails to guests
+    pub base_url: String,
+
+    /// Short links to each guest's RSVP, `/i/{code}`, for printed
+    /// invitations and texts
+    pub short_links: ShortLinksConfig,
+
+    /// Printed cards with each guest's short link and a QR code of it, to
+    /// tuck into paper invitations
+    pub inserts: InsertsConfig,
+
+    /// Printing the seating chart for the caterer
+    pub seating: SeatingConfig,
+
+    /// What the confirmation page says after an RSVP, depending on whether
+    /// the guest is coming
+    pub confirmation: ConfirmationConfig,
+
+    /// The contact page, which emails guests' messages to the admin
+    pub contact: ContactConfig,
+
+    /// Locking out IP addresses that look up too many names that aren't on
+    /// the guest list, like someone trying names to find who's invited
+    pub fetch_lockout: FetchLockoutConfig,
+
+    /// The main, secondary and tertiary events, in that order, for guests to
+    /// add to their calendars once they RSVP
+    pub events: Vec<EventConfig>,
+
+    /// Gift registries linked from the home and confirmation pages, through
+    /// `/go/{name}` so clicks on each are counted
+    pub registry: RegistryConfig,
+
+    /// Hotels, airports and parking on the travel page, with reminders
+    /// before the hotel blocks' cutoffs for guests who need somewhere to
+    /// stay
+    pub travel: TravelConfig,
+
+    /// Shuttle pickups guests can sign up for on the RSVP form
+    pub shuttle: ShuttleConfig,
+
+    /// An extra event, like a rehearsal dinner, that only some guests are
+    /// invited to
+    pub private_event: PrivateEventConfig,
+
+    /// Childcare during the event, which parties with children can sign
+    /// their kids up for
+    pub childcare: ChildcareConfig,
+
+    /// Songs guests request for the DJ and vote up
+    pub songs: SongsConfig,
+
+    /// The final export bundle taken when the admin freezes the site after
+    /// the event
+    pub archive: ArchiveConfig,
+
+    /// Subsystems to turn off for a smaller site, on top of those left out
+    /// when building
+    pub features: FeaturesConfig,
+
+    /// The event's time zone, like "America/New_York", for showing times
+    /// to guests and the admin.  Times are still saved in UTC.
+    pub timezone: Timezone,
+
+    /// Pacing of emails sent to many guests at once, like announcements, to
+    /// stay under the provider's sending limits
+    pub bulk_email: BulkEmailConfig,
+
+    /// Largest CSV file attached whole to the new RSVP email.  Past this,
+    /// only the guest's own row is attached, with a link to download the
+    /// rest, so mail servers don't start rejecting it.
+    pub max_attachment_bytes: usize,
+
+    /// File the guest list is attached to the new RSVP email as.  Workbooks
+    /// need the `xlsx` feature.
+    pub attachment_format: AttachmentFormat,
+
+    /// Signing of outgoing email, so it isn't taken for spam
+    pub dkim: DkimConfig,
+
+    /// Emails and pages for guests in other languages than the built-in ones
+    pub languages: LanguagesConfig,
+
+    /// Treat an RSVP identical to the guest's last one within this many
+    /// seconds as a double submission, which is confirmed again without
+    /// saving or notifying the admin.  0 turns this off.
+    pub duplicate_window_secs: i64,
+
+    /// Most RSVPs saved or waiting to be saved at once.  Past that, guests
+    /// get a "busy, try again" page instead of piling up.
+    pub max_pending_writes: usize,
+
+    /// Seconds guests are asked to wait before trying again when busy
+    pub busy_retry_after_secs: u64,
+
+    /// File recording who changed each guest's record, one JSON entry per line
+    pub audit_log: String,
+
+    /// File with a line per request, for web log analyzers
+    pub access_log: AccessLogConfig,
+
+    /// Addresses of the proxies in front of the server, like nginx on
+    /// `127.0.0.1`, or "unix" for one on the `--uds` socket, whose
+    /// `Forwarded` and `X-Forwarded-For` headers say who the client is
+    pub trusted_proxies: Vec<String>,
+
+    /// Page views and visitors per day, charted at `/admin/analytics`
+    pub analytics: AnalyticsConfig,
+
+    /// How long a request waits for the CSV file before giving up with an
+    /// error, in case a write is stuck
+    pub db_lock_timeout_ms: u64,
+
+    /// Most recent changes to the CSV file the admin can undo, kept in memory
+    /// until the server restarts.  0 turns undo off.
+    pub undo_history: usize,
+
+    /// Albums on the photos page
+    pub photos: PhotosConfig,
+
+    /// Copies of the CSV file kept on disk, to browse and restore from the
+    /// admin pages
+    pub backups: BackupConfig,
+
+    /// Local git repository to commit the CSV file to after each change
+    pub git: GitConfig,
+
+    /// S3-compatible bucket to upload the CSV file and audit log to after
+    /// each change
+    pub s3: S3Config,
+
+    /// How often each background task runs, see `/admin/tasks`
+    pub tasks: TasksConfig,
+
+    /// Most of each meal that can be chosen, counting guests and plus-ones,
+    /// e.g. `Fish = 20` when the caterer has fixed counts.  Meals not listed
+    /// have no limit.
+    pub meal_limits: BTreeMap<String, usize>,
+
+    /// What each meal on the RSVP form is, by its value, e.g. `[meals.Fish]`
+    /// with `label = "Option B"`, shown on the form and the confirmations
+    pub meals: BTreeMap<String, MealConfig>,
+
+    /// Caps on the size of the guest list, so a flood of spam RSVPs can't
+    /// fill the disk
+    pub limits: LimitsConfig,
+
+    /// Which RSVP form fields guests must fill in, may leave empty, or never
+    /// see, available to every page as `fields`
+    pub fields: FieldsConfig,
+
+    /// Engine used to render the pages
+    pub template_engine: TemplateEngine,
+
+    /// Look of the site, available to every page as `theme`
+    pub theme: ThemeConfig,
+
+    /// Search engine and link preview settings, available to every page as
+    /// `site`
+    pub site: SiteConfig,
+
+    /// Shared password guests enter before seeing any page
+    pub site_password: SitePasswordConfig,
+
+    /// Each browser's session, kept in a signed and encrypted cookie, which
+    /// remembers the site password, the guest who looked up their RSVP, and
+    /// the admin's login
+    pub session: SessionConfig,
+
+    /// Notices shown at the top of the guests' pages when the server starts,
+    /// which the admin can change at `/admin/banners`
+    pub banners: Vec<BannerConfig>,
+
+    /// Cross-origin access to the JSON API under `/api/v1`
+    pub cors: CorsConfig,
+
+    /// Message or page shown for an error status, by its code as a string,
+    /// e.g. `[error_pages."404"]`
+    pub error_pages: BTreeMap<String, ErrorPageConfig>,
+
+    /// Telling probes from scanners apart from real missing pages, so only
+    /// the latter are logged as warnings
+    pub not_found: NotFoundConfig,
+
+    /// Tuning for the web server's workers and connections
+    pub server: ServerConfig,
+
+    /// HTTPS with certificates from Let's Encrypt or another ACME CA
+    pub tls: TlsConfig,
+
+    /// Export of request traces to a collector like Jaeger or Tempo
+    pub tracing: TracingConfig,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            verify_email: false,
+            admin: AdminConfig::default(),
+            webhook_secret: None,
+            form_webhook: FormWebhookConfig::default(),
+            hooks: HooksConfig::default(),
+            export: ExportConfig::default(),
+            base_url: "http://127.0.0.1:8080".to_string(),
+            short_links: ShortLinksConfig::default(),
+            inserts: InsertsConfig::default(),
+            seating: SeatingConfig::default(),
+            confirmation: ConfirmationConfig::default(),
+            contact: ContactConfig::default(),
+            fetch_lockout: FetchLockoutConfig::default(),
+            events: vec![],
+            registry: RegistryConfig::default(),
+            travel: TravelConfig::default(),
+            shuttle: ShuttleConfig::default(),
+            private_event: PrivateEventConfig::default(),
+            childcare: ChildcareConfig::default(),
+            songs: SongsConfig::default(),
+            archive: ArchiveConfig::default(),
+            features: FeaturesConfig::default(),
+            timezone: Timezone::default(),
+            bulk_email: BulkEmailConfig::default(),
+            max_attachment_bytes: 1_000_000,
+            attachment_format: AttachmentFormat::default(),
+            dkim: DkimConfig::default(),
+            languages: LanguagesConfig::default(),
+            duplicate_window_secs: 60,
+            max_pending_writes: 32,
+            busy_retry_after_secs: 5,
+            audit_log: "audit.jsonl".to_string(),
+            access_log: AccessLogConfig::default(),
+            trusted_proxies: vec![],
+            analytics: AnalyticsConfig::default(),
+            db_lock_timeout_ms: 10_000,
+            undo_history: DEFAULT_UNDO_HISTORY,
+            photos: PhotosConfig::default(),
+            backups: BackupConfig::default(),
+            git: GitConfig::default(),
+            s3: S3Config::default(),
+            tasks: TasksConfig::default(),
+            meal_limits: BTreeMap::default(),
+            meals: BTreeMap::default(),
+            limits: LimitsConfig::default(),
+            fields: FieldsConfig::default(),
+            template_engine: TemplateEngine::default(),
+            theme: ThemeConfig::default(),
+            site: SiteConfig::default(),
+            site_password: SitePasswordConfig::default(),
+            session: SessionConfig::default(),
+            banners: vec![],
+            cors: CorsConfig::default(),
+            error_pages: BTreeMap::default(),
+            not_found: NotFoundConfig::default(),
+            server: ServerConfig::default(),
+            tls: TlsConfig::default(),
+            tracing: TracingConfig::default(),
+        }
+    }
+}
+impl Config {
+    pub fn from_file(path: &str) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path)?;
+        Self::from_toml(&contents)
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self, Error> {
+        toml::from_str(contents).map_err(Error::from)
+    }
+
+    /// Whether guests have to enter a code sent to their email, which they
+    /// can't without email
+    pub fn verifies_email(&self) -> bool {
+        self.verify_email && self.features.email
+    }
+
+    /// Build a link to a page on the site
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url.trim_end_matches('/'), path)
+    }
+
+    /// Build a guest's link straight to their RSVP form
+    pub fn rsvp_url(&self, token: &str) -> String {
+        self.url(&format!("/rsvp/{}", token))
+    }
+
+    /// Build a guest's short link, on the short domain if there is one
+    pub fn short_url(&self, code: &str) -> String {
+        let base_url = self.short_links.base_url.as_ref().unwrap_or(&self.base_url);
+        format!("{}/i/{}", base_url.trim_end_matches('/'), code)
+    }
+
+    /// Build a link to a page on the site, unless it's already a full URL
+    pub fn absolute_url(&self, path: &str) -> String {
+        if path.starts_with("http://") || path.starts_with("https://") {
+            path.to_string()
+        } else {
+            self.url(path)
+        }
+    }
+}
+
+/// File format of the guest list attached to the new RSVP email
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AttachmentFormat {
+    /// The CSV file as it is
+    #[default]
+    Csv,
+    /// An Excel workbook with a sheet of every guest and one for each event
+    Xlsx,
+}
+impl AttachmentFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Xlsx => "xlsx",
+        }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv",
+            Self::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        }
+    }
+}
+
+/// How guests' short links look
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ShortLinksConfig {
+    /// Characters in new codes, which get longer on their own if this many
+    /// keep colliding
+    pub length: usize,
+    /// Shorter domain pointing at this site, used instead of `base_url` in
+    /// short links, like "https://jnj.wed"
+    pub base_url: Option<String>,
+}
+impl Default for ShortLinksConfig {
+    fn default() -> Self {
+        Self {
+            length: DEFAULT_SHORT_CODE_LENGTH,
+            base_url: None,
+        }
+    }
+}
+
+/// How invitation inserts are laid out for printing, at
+/// `/admin/inserts.pdf`.  Needs the `pdf` feature.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct InsertsConfig {
+    /// Card stock sheet size
+    pub page_size: PageSize,
+    /// Cards across each sheet
+    pub columns: usize,
+    /// Cards down each sheet
+    pub rows: usize,
+    /// Printed at the top of every card, defaulting to the theme's
+    /// `couple_names`
+    pub heading: Option<String>,
+    /// Printed above the guest's short link
+    pub message: String,
+}
+impl Default for InsertsConfig {
+    fn default() -> Self {
+        Self {
+            page_size: PageSize::default(),
+            columns: 2,
+            rows: 2,
+            heading: None,
+            message: "Kindly reply by scanning the code or visiting".to_string(),
+        }
+    }
+}
+
+/// How the seating chart for the caterer is printed, at
+/// `/admin/seating.pdf`.  Needs the `pdf` feature.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SeatingConfig {
+    /// Paper size, one table to a page
+    pub page_size: PageSize,
+}
+
+/// A message and next steps on the confirmation page, like the hotel block's
+/// code or the registry, each for guests coming, guests not coming, or both
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ConfirmationConfig {
+    /// Shown to guests coming to any event
+    pub attending_message: String,
+    /// Shown to guests not coming to anything
+    pub declined_message: String,
+    pub next_steps: Vec<NextStep>,
+}
+impl ConfirmationConfig {
+    pub fn message(&self, attending: bool) -> &str {
+        if attending {
+            &self.attending_message
+        } else {
+            &self.declined_message
+        }
+    }
+
+    /// The next steps for a guest who is or isn't coming, in order
+    pub fn next_steps(&self, attending: bool) -> Vec<NextStep> {
+        self.next_steps
+            .iter()
+            .filter(|step| step.show_to.includes(attending))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Something to do after RSVPing, with a link if there's one to follow
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct NextStep {
+    pub title: String,
+    /// Details, like the code for booking the hotel block
+    pub text: String,
+    pub url: String,
+    pub show_to: Audience,
+}
+
+/// Which guests see a next step
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Audience {
+    #[default]
+    Attending,
+    Declined,
+    Everyone,
+}
+impl Audience {
+    pub fn includes(&self, attending: bool) -> bool {
+        match self {
+            Self::Attending => attending,
+            Self::Declined => !attending,
+            Self::Everyone => true,
+        }
+    }
+}
+
+/// The contact page at `/contact`, whose messages are emailed to the admin,
+/// so guests don't need the couple's address and it stays off the site
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ContactConfig {
+    pub enabled: bool,
+    /// Most messages from one IP address in an hour, or 0 for no limit
+    pub max_per_hour: usize,
+    /// Longest message, in characters
+    pub max_chars: usize,
+    /// Messages with more links than this are taken for spam and held for
+    /// the admin
+    pub max_links: usize,
+    /// File to also keep every message in, one JSON object per line
+    pub log: Option<String>,
+    /// File to keep the messages held as spam in, so they're still there
+    /// after a restart, or only in memory if not set
+    pub quarantine: Option<String>,
+}
+impl Default for ContactConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_per_hour: 3,
+            max_chars: 5000,
+            max_links: 2,
+            log: None,
+            quarantine: None,
+        }
+    }
+}
+
+/// Turning away IP addresses from `/fetch` after too many names that aren't
+/// on the guest list, since a guest mistyping their name only needs a few
+/// tries
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FetchLockoutConfig {
+    /// Most names not found from one IP address in the window before it's
+    /// locked out, or 0 to never lock anyone out
+    pub max_misses: usize,
+    pub window_secs: i64,
+    /// How long a locked out IP address is turned away for
+    pub lockout_secs: i64,
+    /// Email the admin about each lockout
+    pub alert: bool,
+    /// Most wrong verification codes for one guest, from any address, before
+    /// the code is thrown away and a new one has to be sent, or 0 for no limit
+    pub max_code_attempts: usize,
+}
+impl Default for FetchLockoutConfig {
+    fn default() -> Self {
+        Self {
+            max_misses: 10,
+            window_secs: 3600,
+            lockout_secs: 3600,
+            alert: true,
+            max_code_attempts: 5,
+        }
+    }
+}
+
+/// Where another form tool's answers go.  Questions not in `fields` fill in
+/// the RSVP field with the question's own name, if there is one.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FormWebhookConfig {
+    /// Shared secret the form tool must pass as the `secret` query
+    /// parameter, or sign Typeform payloads with, or `None` to turn the
+    /// webhook off
+    pub secret: Option<String>,
+    /// RSVP field each question fills in, by Typeform's question `ref` or
+    /// the question's title, e.g. `"Will you be there?" = "attending"`
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Where REST hook subscriptions are kept, and how long a subscriber gets
+/// to answer each delivery
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// JSON file with the subscriptions, or only kept in memory until the
+    /// server restarts if unset, when every automation has to subscribe
+    /// again
+    pub file: Option<String>,
+    pub timeout_secs: u64,
+    /// Let subscriptions send to loopback, private and link-local addresses,
+    /// like an automation on the same network, which are refused otherwise
+    /// so guests' details can't be sent to services inside it
+    pub allow_private: bool,
+}
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            timeout_secs: 10,
+            allow_private: false,
+        }
+    }
+}
+
+/// How a meal is described to guests.  Without a label, the meal's value,
+/// like "Fish", is shown.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct MealConfig {
+    /// Shown instead of the meal's value, like "Option B: Salmon"
+    pub label: String,
+    pub description: String,
+    /// What the meal contains for guests with allergies, like "Fish, dairy"
+    pub allergens: String,
+}
+
+/// Column lists for `/admin/rsvps.csv?preset=...`, by name, e.g.
+/// `caterer = ["name", "headcount", "meals"]`
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ExportConfig {
+    pub presets: BTreeMap<String, Vec<String>>,
+}
+
+/// When and where one of the events is.  Guests only get links to add it to
+/// their calendars once both times are set.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct EventConfig {
+    pub title: String,
+    /// Local times in `timezone`, like "2024-06-01T16:00:00"
+    pub starts_at: Option<NaiveDateTime>,
+    pub ends_at: Option<NaiveDateTime>,
+    pub location: String,
+    pub description: String,
+}
+
+/// The gift registries and where clicks on them are counted
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RegistryConfig {
+    pub links: Vec<RegistryLink>,
+    /// JSON file with the clicks on each registry, or only kept in memory
+    /// until the server restarts if unset
+    pub clicks: Option<String>,
+}
+
+/// One gift registry.  Guests go to it through `/go/{name}`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct RegistryLink {
+    /// Short name in the link, like "zola"
+    pub name: String,
+    pub title: String,
+    pub url: String,
+}
+
+/// What the travel page lists.  The RSVP form only asks whether guests need
+/// lodging once there are hotels.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct TravelConfig {
+    pub hotels: Vec<HotelConfig>,
+    pub airports: Vec<AirportConfig>,
+    /// Where to park at the venue
+    pub parking: String,
+    /// Days before a hotel block's cutoff to remind guests who need lodging,
+    /// or 0 for no reminders
+    pub reminder_days: i64,
+}
+impl Default for TravelConfig {
+    fn default() -> Self {
+        Self {
+            hotels: vec![],
+            airports: vec![],
+            parking: String::default(),
+            reminder_days: 14,
+        }
+    }
+}
+
+/// A hotel with a block of rooms held for guests
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct HotelConfig {
+    pub name: String,
+    pub address: String,
+    pub url: String,
+    pub phone: String,
+    /// Code or group name to give when booking
+    pub block_code: String,
+    /// Last day to book at the block rate, after which unbooked rooms are
+    /// released
+    pub cutoff: Option<NaiveDate>,
+    pub notes: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct AirportConfig {
+    pub name: String,
+    /// Like "SFO"
+    pub code: String,
+    /// How far it is and how to get to the venue from there
+    pub notes: String,
+}
+
+/// Where the shuttle picks guests up.  The RSVP form only asks guests who
+/// are coming to pick one once there are stops.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ShuttleConfig {
+    pub stops: Vec<ShuttleStop>,
+}
+
+/// One pickup point, with its manifest at `/admin/shuttle/{name}.csv`
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct ShuttleStop {
+    /// Short name saved with each guest's answer, like "downtown"
+    pub name: String,
+    pub title: String,
+    /// When the shuttle leaves, as shown to guests, like "3:30pm"
+    pub time: String,
+    /// Most riders, counting plus-ones, or 0 for no limit
+    pub capacity: usize,
+}
+
+/// An event only guests tagged with `tag` are asked about, on their own RSVP
+/// form.  Off unless the tag is set.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct PrivateEventConfig {
+    pub title: String,
+    pub tag: String,
+}
+impl Default for PrivateEventConfig {
+    fn default() -> Self {
+        Self {
+            title: "rehearsal dinner".to_string(),
+            tag: String::default(),
+        }
+    }
+}
+
+/// Childcare signup, asked of the parties tagged with `tag` as having
+/// children.  Off unless the tag is set.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ChildcareConfig {
+    pub tag: String,
+    /// Most kids a party can sign up
+    pub max_kids: u32,
+}
+impl Default for ChildcareConfig {
+    fn default() -> Self {
+        Self {
+            tag: String::default(),
+            max_kids: 6,
+        }
+    }
+}
+
+/// Song requests, on each guest's own `/songs/{token}` page.  Off unless
+/// enabled.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SongsConfig {
+    pub enabled: bool,
+    /// JSON file with the songs and who voted for each, or only kept in
+    /// memory until the server restarts if unset
+    pub file: Option<String>,
+    /// Most songs one guest can add, though they can vote for any
+    pub max_per_guest: usize,
+}
+impl Default for SongsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            file: None,
+            max_per_guest: 5,
+        }
+    }
+}
+
+/// Where the final export bundle goes when the admin archives the site,
+/// which then stays read-only
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ArchiveConfig {
+    pub dir: String,
+    /// Secret for signing the bundle's checksums with HMAC-SHA256, left
+    /// unsigned if unset
+    pub key: Option<String>,
+    /// Clear guests' contact details, dietary needs, comments and notes from
+    /// the bundle and, once it's saved, the guest list, along with the
+    /// snapshots and undo history that still have them
+    pub scrub_pii: bool,
+}
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            dir: "archive".to_string(),
+            key: None,
+            scrub_pii: false,
+        }
+    }
+}
+
+/// Subsystems that can be turned off at runtime.  The admin dashboard, the
+/// API and the photo albums can also be left out of the build with
+/// `--no-default-features`, and their routes are only there when both allow
+/// them.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FeaturesConfig {
+    pub admin: bool,
+    pub api: bool,
+    pub photos: bool,
+    /// Send emails to guests and the admin, or only log them if off
+    pub email: bool,
+}
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        Self {
+            admin: true,
+            api: true,
+            photos: true,
+            email: true,
+        }
+    }
+}
+
+/// Sheet sizes for printing
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PageSize {
+    #[default]
+    Letter,
+    A4,
+}
+
+/// How fast emails to many guests go out: one every `interval_ms`, with a
+/// longer pause after every `batch_size`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct BulkEmailConfig {
+    pub interval_ms: u64,
+    /// Emails sent before each batch pause, or 0 for no batches
+    pub batch_size: usize,
+    pub batch_pause_ms: u64,
+    /// Most random time added to each pause, so the sends aren't evenly
+    /// spaced like a bot's
+    pub jitter_ms: u64,
+}
+impl Default for BulkEmailConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 1000,
+            batch_size: 0,
+            batch_pause_ms: 60_000,
+            jitter_ms: 0,
+        }
+    }
+}
+
+/// DKIM key to sign every outgoing email with, whose public half is
+/// published at `{selector}._domainkey.{domain}`.  Off unless a key file is
+/// set.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct DkimConfig {
+    /// Domain the messages are signed for, usually the from address's
+    pub domain: String,
+    pub selector: String,
+    /// File with the private key, PEM-encoded PKCS#1 for RSA or base64 for
+    /// Ed25519
+    pub private_key_file: Option<String>,
+    pub algorithm: DkimAlgorithm,
+}
+impl Default for DkimConfig {
+    fn default() -> Self {
+        Self {
+            domain: String::default(),
+            selector: "default".to_string(),
+            private_key_file: None,
+            algorithm: DkimAlgorithm::default(),
+        }
+    }
+}
+
+/// Kind of key used for DKIM signatures
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DkimAlgorithm {
+    #[default]
+    Rsa,
+    Ed25519,
+}
+
+/// Languages guests get email in, picked from their record, which is set at
+/// `/add` or from the browser's `Accept-Language` when they RSVP
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct LanguagesConfig {
+    /// Language of the built-in templates, for guests without one or in a
+    /// language with no translation
+    pub default: String,
+    /// Translated templates by language code then template name, e.g.
+    /// `[languages.emails.fr."email/verification.txt"]`
+    pub emails: BTreeMap<String, BTreeMap<String, EmailTemplateConfig>>,
+    /// Translated TinyTemplate pages by language code then page name, served
+    /// under `/{lang}/`, e.g. `[languages.pages.fr] "index.html" = "..."`
+    pub pages: BTreeMap<String, BTreeMap<String, String>>,
+}
+impl Default for LanguagesConfig {
+    fn default() -> Self {
+        Self {
+            default: "en".to_string(),
+            emails: BTreeMap::default(),
+            pages: BTreeMap::default(),
+        }
+    }
+}
+impl LanguagesConfig {
+    /// Every language guests can get email in, the default first
+    pub fn supported(&self) -> Vec<&str> {
+        let mut languages = vec![self.default.as_str()];
+        languages.extend(
+            self.emails
+                .keys()
+                .map(String::as_str)
+                .filter(|language| *language != self.default),
+        );
+        languages
+    }
+
+    /// Every language the pages are in, the default first
+    pub fn page_languages(&self) -> Vec<&str> {
+        let mut languages = vec![self.default.as_str()];
+        languages.extend(
+            self.pages
+                .keys()
+                .map(String::as_str)
+                .filter(|language| *language != self.default),
+        );
+        languages
+    }
+}
+
+/// One email template in another language
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct EmailTemplateConfig {
+    /// Subject line, or the built-in English one if not set
+    pub subject: Option<String>,
+    /// TinyTemplate file rendered instead of the built-in body, with the same
+    /// context
+    pub template: String,
+}
+
+/// Whether guests must fill in a form field
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldRule {
+    Required,
+    #[default]
+    Optional,
+    /// Left off the form, and ignored if submitted anyway
+    Hidden,
+}
+
+/// Rules for the optional fields of the RSVP form, see `form::Form` for
+/// when each is shown
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FieldsConfig {
+    pub email: FieldRule,
+    pub meal_choice: FieldRule,
+    /// Covers both the checklist and the "Other" text
+    pub dietary_restrictions: FieldRule,
+    pub plus_one_name: FieldRule,
+    /// Where the plus-one gets their own confirmation and announcements
+    pub plus_one_email: FieldRule,
+    pub plus_one_meal_choice: FieldRule,
+    pub plus_one_dietary_restrictions: FieldRule,
+    pub comments: FieldRule,
+    /// Dietary restrictions guests can check off, with anything else written
+    /// in under "Other"
+    pub dietary_options: Vec<String>,
+}
+impl Default for FieldsConfig {
+    fn default() -> Self {
+        Self {
+            email: FieldRule::default(),
+            meal_choice: FieldRule::default(),
+            dietary_restrictions: FieldRule::default(),
+            plus_one_name: FieldRule::default(),
+            plus_one_email: FieldRule::default(),
+            plus_one_meal_choice: FieldRule::default(),
+            plus_one_dietary_restrictions: FieldRule::default(),
+            comments: FieldRule::default(),
+            dietary_options: ["Vegetarian", "Vegan", "Gluten-free", "Nut allergy"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+/// Where to write the access log, apart from the application's own logs.  Off
+/// unless a path is set.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct AccessLogConfig {
+    pub path: Option<String>,
+    pub format: AccessLogFormat,
+    /// Size at which the file is rotated, or 0 to let it grow
+    pub max_bytes: u64,
+    /// Rotated files kept, as `access.log.1` for the newest and so on
+    pub keep: usize,
+}
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            format: AccessLogFormat::default(),
+            max_bytes: 10 * 1024 * 1024,
+            keep: 5,
+        }
+    }
+}
+
+/// Where visitor counts are kept.  Off unless a path is set.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct AnalyticsConfig {
+    /// JSON file with the counts for each day and page
+    pub path: Option<String>,
+    /// Most recent days charted
+    pub days_shown: usize,
+}
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            days_shown: 60,
+        }
+    }
+}
+
+/// Layout of each access log line
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// Client, time, request, status and size
+    Common,
+    /// Common, with the referer and user agent
+    #[default]
+    Combined,
+}
+
+/// Template engines that can render the pages.  Tera is only available when
+/// built with the `tera` feature.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TemplateEngine {
+    #[default]
+    TinyTemplate,
+    Tera,
+}
+
+/// Per-deployment look of the site, used by the pages and `/theme.css`
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// Any CSS color, used for the header and buttons
+    pub primary_color: String,
+    /// Background of the banner on the home page
+    pub hero_image: String,
+    /// Font family for all text
+    pub font: String,
+    /// Shown in the home page banner and page titles, e.g. "Jane & John"
+    pub couple_names: String,
+}
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            primary_color: "rgb(0, 150, 136)".to_string(),
+            hero_image: "/static/transparent.jpg".to_string(),
+            font: "Roboto".to_string(),
+            couple_names: String::default(),
+        }
+    }
+}
+impl ThemeConfig {
+    /// Stylesheet applying the theme on top of the default styles
+    pub fn css(&self) -> String {
+        format!(
+            r#":root {{
+  --primary-color: {color};
+}}
+
+body, .mdl-layout, button, input, textarea {{
+  font-family: "{font}", "Helvetica", "Arial", sans-serif;
+}}
+
+.mdl-layout__header, .mdl-button--accent.mdl-button--raised {{
+  background-color: var(--primary-color);
+}}
+
+.banner-section {{
+  background-image: url("{hero}");
+}}
+
+.site-banner {{
+  padding: 12px 16px;
+  text-align: center;
+  color: #fff;
+  background-color: var(--primary-color);
+}}
+
+.site-banner--warning {{
+  color: #000;
+  background-color: #ffc107;
+}}
+
+.site-banner--critical {{
+  background-color: #d32f2f;
+}}
+
+.flash {{
+  margin: 0;
+  padding: 12px 16px;
+  text-align: center;
+  background-color: #e8f5e9;
+}}
+
+.flash--error {{
+  color: #b71c1c;
+  background-color: #ffebee;
+}}
+"#,
+            color = self.primary_color,
+            font = self.font,
+            hero = self.hero_image,
+        )
+    }
+}
+
+/// Crawling and link preview settings.  It's a private event, so search
+/// engines are turned away by default.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct SiteConfig {
+    /// Contents of `/robots.txt`
+    pub robots_txt: String,
+    /// Serve `/sitemap.xml` with the public pages, and point to it from
+    /// `/robots.txt`
+    pub sitemap: bool,
+    /// Serve `/stats` with how many parties have responded and the headcount
+    /// so far, and nothing about anyone in particular
+    pub stats: bool,
+    /// Open Graph and Twitter card metadata, only added to the pages when a
+    /// title is set
+    pub title: String,
+    pub description: String,
+    /// Path on the site or full URL of the preview image
+    pub image: String,
+}
+impl Default for SiteConfig {
+    fn default() -> Self {
+        Self {
+            robots_txt: "User-agent: *\nDisallow: /\n".to_string(),
+            sitemap: false,
+            stats: false,
+            title: String::default(),
+            description: String::default(),
+            image: "/static/photo1.jpg".to_string(),
+        }
+    }
+}
+
+/// Most records and bytes the CSV file may hold.  New guests are turned away
+/// past either, and the admin dashboard warns as they get close.  No limits
+/// unless set.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct LimitsConfig {
+    pub max_records: Option<usize>,
+    pub max_file_bytes: Option<u64>,
+    /// How full, as a percentage of either limit, before the dashboard warns
+    pub warn_percent: u64,
+}
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_records: None,
+            max_file_bytes: None,
+            warn_percent: 80,
+        }
+    }
+}
+impl LimitsConfig {
+    /// Whether the file can hold this many records, when it's this big
+    /// before adding them
+    pub fn allows(&self, records: usize, bytes: u64) -> bool {
+        self.max_records.is_none_or(|max| records <= max)
+            && self.max_file_bytes.is_none_or(|max| bytes < max)
+    }
+
+    /// What to tell the admin once either limit is close, or `None` if
+    /// neither is
+    pub fn warning(&self, records: usize, bytes: u64) -> Option<String> {
+        let close = |used: u64, max: u64| used * 100 >= max * self.warn_percent;
+        let mut warnings = vec![];
+        if let Some(max) = self
+            .max_records
+            .filter(|max| close(records as u64, *max as u64))
+        {
+            warnings.push(format!("{} of the {} records allowed", records, max));
+        }
+        if let Some(max) = self.max_file_bytes.filter(|max| close(bytes, *max)) {
+            warnings.push(format!("{} of the {} bytes allowed", bytes, max));
+        }
+        if warnings.is_empty() {
+            return None;
+        }
+        let full = match self.allows(records + 1, bytes) {
+            true => "raise `limits` before new guests are turned away",
+            false => "new guests are being turned away until `limits` are raised",
+        };
+        Some(format!(
+            "The guest list has {}: {}",
+            warnings.join(" and "),
+            full
+        ))
+    }
+}
+
+/// Password for the whole site, asked for once on a splash page and then
+/// remembered in a cookie.  Off unless a password is set.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SitePasswordConfig {
+    pub password: Option<String>,
+    /// How long the cookie lasts, so guests aren't asked again
+    pub remember_days: i64,
+}
+impl Default for SitePasswordConfig {
+    fn default() -> Self {
+        Self {
+            password: None,
+            remember_days: 90,
+        }
+    }
+}
+
+/// The session cookie, which holds everything the site remembers about a
+/// browser
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Secret of at least 32 characters that session cookies are signed and
+    /// encrypted with.  If not set, a random one is made at startup, which
+    /// signs everyone out whenever the server restarts.
+    pub key: Option<String>,
+    pub cookie_name: String,
+    /// How long a browser is remembered after its last visit
+    pub remember_days: i64,
+    /// Only send the site's cookies over HTTPS.  Turn off only when serving
+    /// over plain HTTP, since the cookies sign the admin in.
+    pub secure: bool,
+}
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            key: None,
+            cookie_name: "rsvp_session".to_string(),
+            remember_days: 90,
+            secure: true,
+        }
+    }
+}
+
+/// A notice at the top of the guests' pages, like "Venue parking changed,
+/// see the Travel section"
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct BannerConfig {
+    pub text: String,
+    pub severity: BannerSeverity,
+    /// When it stops being shown, or never if not set
+    pub expires: Option<DateTime<Utc>>,
+    /// Starts of the paths it's shown on, like "/photos", or every page if
+    /// empty
+    pub paths: Vec<String>,
+}
+
+/// How a banner stands out, from its color
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BannerSeverity {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+impl BannerSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+/// Which other sites may call the JSON API from a browser, e.g. a static
+/// frontend hosted elsewhere.  No origins are allowed by default.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Full origins like "https://example.netlify.app", or "*" for any
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    /// How long browsers may cache the preflight response
+    pub max_age_secs: u64,
+}
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec![],
+            allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+            max_age_secs: 3600,
+        }
+    }
+}
+impl CorsConfig {
+    pub fn allows(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.trim_end_matches('/') == origin)
+    }
+}
+
+/// What guests see for one error status, on top of the status code.  Covers
+/// 400, 403, 404, 405, 500 and 503.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ErrorPageConfig {
+    /// Shown as `error` instead of the default, like "Page not found"
+    pub message: Option<String>,
+    /// TinyTemplate file rendered instead of `error.html`, with the same
+    /// `error`, `status_code` and `request_id` context
+    pub template: Option<String>,
+}
+
+/// Missing pages that are expected, like bots probing for WordPress, which
+/// are only logged at debug level
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct NotFoundConfig {
+    /// Parts of paths only asked for by scanners, matched anywhere in the
+    /// path and ignoring case.  Setting this replaces the defaults.
+    pub scanner_patterns: Vec<String>,
+}
+impl Default for NotFoundConfig {
+    fn default() -> Self {
+        Self {
+            scanner_patterns: [
+                ".php",
+                ".asp",
+                ".jsp",
+                ".cgi",
+                ".env",
+                "/.git",
+                "/wp-",
+                "/cgi-bin",
+                "/phpmyadmin",
+                "/xmlrpc",
+                "/vendor/",
+                "/actuator",
+                "/boaform",
+                "/owa/",
+            ]
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .collect(),
+        }
+    }
+}
+impl NotFoundConfig {
+    pub fn is_scanner(&self, path: &str) -> bool {
+        let path = path.to_lowercase();
+        self.scanner_patterns
+            .iter()
+            .any(|pattern| path.contains(&pattern.to_lowercase()))
+    }
+}
+
+/// Web server settings, defaulting to actix's own except for a single
+/// worker, which is plenty for a wedding
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Address to listen on with `--port`, e.g. "0.0.0.0" to accept
+    /// connections from other hosts
+    pub host: String,
+    /// Worker threads handling requests, or 0 for one per CPU core.
+    /// `--workers` takes precedence.
+    pub workers: usize,
+    /// How long idle connections are kept open for more requests.  0 closes
+    /// each connection after its response.
+    pub keep_alive_secs: u64,
+    /// How long a client has to send its request headers, or 0 for no limit
+    pub client_request_timeout_ms: u64,
+    /// How long a client has to acknowledge the connection closing, or 0
+    /// for no limit
+    pub client_disconnect_timeout_ms: u64,
+    /// Most connections each worker handles at once
+    pub max_connections: usize,
+    /// Most connections waiting to be accepted
+    pub backlog: u32,
+}
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            workers: 1,
+            keep_alive_secs: 5,
+            client_request_timeout_ms: 5000,
+            client_disconnect_timeout_ms: 0,
+            max_connections: 25_000,
+            backlog: 2048,
+        }
+    }
+}
+impl ServerConfig {
+    pub fn keep_alive(&self) -> KeepAlive {
+        match self.keep_alive_secs {
+            0 => KeepAlive::Disabled,
+            secs => KeepAlive::Timeout(Duration::from_secs(secs)),
+        }
+    }
+}
+
+/// Certificates for HTTPS, obtained and renewed automatically over ACME with
+/// HTTP-01 challenges, so the domains must reach this server on port 80.  Off
+/// unless domains are set.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// Names on the certificate, e.g. `["example.com", "www.example.com"]`
+    pub domains: Vec<String>,
+    /// Address to listen on for HTTPS
+    pub bind: String,
+    /// Address to listen on for plain HTTP that only answers the CA's
+    /// challenges and redirects everything else to HTTPS, e.g. "0.0.0.0:80"
+    pub redirect_bind: Option<String>,
+    /// Where the CA sends notices about the certificate, like expiry
+    pub contact_email: String,
+    /// Directory URL of the CA, e.g. Let's Encrypt's staging one while
+    /// trying things out
+    pub acme_directory: String,
+    /// Directory for the account key, certificate and its key
+    pub dir: String,
+    /// Get a new certificate once the current one expires within this many
+    /// days
+    pub renew_before_days: u32,
+}
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            domains: vec![],
+            bind: "0.0.0.0:443".to_string(),
+            redirect_bind: None,
+            contact_email: String::default(),
+            acme_directory: "https://acme-v02.api.letsencrypt.org/directory".to_string(),
+            dir: "tls".to_string(),
+            renew_before_days: 30,
+        }
+    }
+}
+
+/// Where the photo albums are, each a subdirectory of images, like
+/// `photos/engagement/ring.jpg`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct PhotosConfig {
+    pub dir: String,
+    /// Smaller copies of each photo offered to browsers, most preferred
+    /// first.  Only generated when built with the `images` feature.
+    pub variants: Vec<ImageVariant>,
+    /// Variants wider than this are scaled down, or 0 to keep the size
+    pub variant_max_width: u32,
+    /// How long browsers may cache photos, whose URLs change with the file
+    pub cache_max_age_secs: u64,
+    /// Videos shown along with the photos, like the highlight reel
+    pub videos: Vec<VideoConfig>,
+}
+impl Default for PhotosConfig {
+    fn default() -> Self {
+        Self {
+            dir: "photos".to_string(),
+            variants: vec![],
+            variant_max_width: 1600,
+            cache_max_age_secs: 365 * 24 * 60 * 60,
+            videos: vec![],
+        }
+    }
+}
+
+/// A video in a gallery, either a file like "/static/highlights.mp4" or a
+/// YouTube or Vimeo link
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct VideoConfig {
+    pub title: String,
+    pub url: String,
+    /// Album it's shown in, or on the album index if not set
+    pub album: Option<String>,
+    /// How many photos come before it in the album, or after all of them if
+    /// not set
+    pub position: Option<usize>,
+}
+
+/// Format of a generated copy of a photo
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageVariant {
+    Avif,
+    Webp,
+}
+impl ImageVariant {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Avif => "avif",
+            Self::Webp => "webp",
+        }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Self::Avif => "image/avif",
+            Self::Webp => "image/webp",
+        }
+    }
+}
+
+/// Where snapshots of the CSV file are saved.  One is taken when the server
+/// starts, whenever the admin asks, and before each restore.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct BackupConfig {
+    pub dir: String,
+    /// Most snapshots kept, dropping the oldest first
+    pub keep: usize,
+}
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            dir: "backups".to_string(),
+            keep: 30,
+        }
+    }
+}
+
+/// Commit a copy of the CSV file to a git repository after each change, with
+/// the guest's name and what changed as the message, for a full history
+/// with diffs.  Off unless a directory is set.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct GitConfig {
+    /// Directory of the repository, created if needed.  Best kept apart from
+    /// the CSV file, since only the copy is committed.
+    pub dir: Option<String>,
+    /// Remote name or URL to push to after each commit, e.g. a private
+    /// repository for an offsite copy
+    pub remote: Option<String>,
+    pub branch: String,
+}
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            remote: None,
+            branch: "main".to_string(),
+        }
+    }
+}
+
+/// Bucket for offsite copies of the CSV file and audit log, on AWS or any
+/// S3-compatible service like MinIO, Backblaze B2 or Cloudflare R2.  Off
+/// unless a bucket is set.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct S3Config {
+    pub bucket: Option<String>,
+    /// Base URL of the service, e.g. "https://s3.eu-west-1.amazonaws.com".
+    /// Objects are addressed by path, as "{endpoint}/{bucket}/{key}".
+    pub endpoint: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Start of each object key, e.g. "wedding/"
+    pub prefix: String,
+    /// "AES256" or "aws:kms" to have the service encrypt the objects
+    pub server_side_encryption: Option<String>,
+    /// KMS key to encrypt with, when using "aws:kms"
+    pub kms_key_id: Option<String>,
+}
+impl Default for S3Config {
+    fn default() -> Self {
+        Self {
+            bucket: None,
+            endpoint: "https://s3.us-east-1.amazonaws.com".to_string(),
+            region: "us-east-1".to_string(),
+            access_key_id: String::default(),
+            secret_access_key: String::default(),
+            prefix: String::default(),
+            server_side_encryption: None,
+            kms_key_id: None,
+        }
+    }
+}
+
+/// Seconds between runs of each background task.  0 turns a task off.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct TasksConfig {
+    /// Snapshots of the CSV file, kept with the others in `backups.dir`
+    pub snapshot_every_secs: u64,
+    /// Uploads to the S3 bucket, only when one is configured
+    pub upload_every_secs: u64,
+    /// Clearing out old API responses kept for idempotency keys
+    pub expire_replays_every_secs: u64,
+    /// Checking whether the HTTPS certificate needs renewing, only when TLS
+    /// is configured.  Also runs at startup.
+    pub renew_certificate_every_secs: u64,
+    /// Generating missing photo variants, only when some are configured, so
+    /// new uploads get them.  Also runs at startup.
+    pub photo_variants_every_secs: u64,
+    /// Reminding guests who need lodging of the hotel blocks' cutoffs, only
+    /// when a hotel has one
+    pub lodging_reminders_every_secs: u64,
+    /// Writing the page view counts to their file, only when analytics are
+    /// on.  Views since the last run are lost if the server stops.
+    pub save_analytics_every_secs: u64,
+}
+impl Default for TasksConfig {
+    fn default() -> Self {
+        Self {
+            snapshot_every_secs: 24 * 60 * 60,
+            upload_every_secs: 60 * 60,
+            expire_replays_every_secs: 60 * 60,
+            renew_certificate_every_secs: 12 * 60 * 60,
+            photo_variants_every_secs: 10 * 60,
+            lodging_reminders_every_secs: 24 * 60 * 60,
+            save_analytics_every_secs: 60,
+        }
+    }
+}
+
+/// Where to send traces, only used when built with the `otlp` feature
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct TracingConfig {
+    /// OTLP/HTTP traces endpoint, e.g. "http://localhost:4318/v1/traces".
+    /// Traces are not exported unless this is set.
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: env!("CARGO_PKG_NAME").to_string(),
+        }
+    }
+}
+
+/// Basic auth credentials for the admin pages.  The admin pages are disabled
+/// until a password is set.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct AdminConfig {
+    pub username: String,
+    pub password: String,
+}
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            username: "admin".to_string(),
+            password: String::default(),
+        }
+    }
+}
+impl AdminConfig {
+    /// Whether the credentials are the admin's, comparing digests so how
+    /// long it takes doesn't give away how much of a guess was right
+    pub fn authorizes(&self, username: &str, password: Option<&str>) -> bool {
+        let same = |given: &str, expected: &str| Sha256::digest(given) == Sha256::digest(expected);
+        let username = same(username, &self.username);
+        let password = password.is_some_and(|password| same(password, &self.password));
+        !self.password.is_empty() && username && password
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        assert_eq!(Config::from_toml("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn verify_email() {
+        let config = Config::from_toml("verify_email = true").unwrap();
+        assert!(config.verify_email);
+    }
+
+    #[test]
+    fn fields() {
+        let config =
+            Config::from_toml("[fields]\nemail = \"required\"\ncomments = \"hidden\"").unwrap();
+        assert_eq!(config.fields.email, FieldRule::Required);
+        assert_eq!(config.fields.dietary_restrictions, FieldRule::Optional);
+        assert_eq!(config.fields.comments, FieldRule::Hidden);
+        assert_eq!(config.fields.dietary_options.len(), 4);
+        let config = Config::from_toml("[fields]\ndietary_options = [\"Halal\"]").unwrap();
+        assert_eq!(config.fields.dietary_options, vec!["Halal"]);
+    }
+
+    #[test]
+    fn admin() {
+        assert!(!Config::default().admin.authorizes("admin", Some("")));
+        let config = Config::from_toml("[admin]\npassword = \"secret\"").unwrap();
+        assert!(config.admin.authorizes("admin", Some("secret")));
+        assert!(!config.admin.authorizes("admin", Some("wrong")));
+        assert!(!config.admin.authorizes("other", Some("secret")));
+        assert!(!config.admin.authorizes("admin", None));
+    }
+
+    #[test]
+    fn template_engine() {
+        assert_eq!(
+            Config::default().template_engine,
+            TemplateEngine::TinyTemplate
+        );
+        let config = Config::from_toml("template_engine = \"tera\"").unwrap();
+        assert_eq!(config.template_engine, TemplateEngine::Tera);
+    }
+
+    #[test]
+    fn theme() {
+        let config = Config::from_toml(
+            "[theme]\nprimary_color = \"#336699\"\ncouple_names = \"Jane & John\"",
+        )
+        .unwrap();
+        assert_eq!(config.theme.couple_names, "Jane & John");
+        assert_eq!(config.theme.font, "Roboto");
+        let css = config.theme.css();
+        assert!(css.contains("--primary-color: #336699;"));
+        assert!(css.contains("url(\"/static/transparent.jpg\")"));
+    }
+
+    #[test]
+    fn site() {
+        let config = Config::default();
+        assert_eq!(config.site.robots_txt, "User-agent: *\nDisallow: /\n");
+        assert!(!config.site.stats);
+        assert!(
+            Config::from_toml("[site]\nstats = true")
+                .unwrap()
+                .site
+                .stats
+        );
+        assert_eq!(
+            config.absolute_url(&config.site.image),
+            "http://127.0.0.1:8080/static/photo1.jpg"
+        );
+        assert_eq!(
+            config.absolute_url("https://cdn.example.com/a.jpg"),
+            "https://cdn.example.com/a.jpg"
+        );
+    }
+
+    #[test]
+    fn dkim() {
+        assert_eq!(Config::default().dkim.private_key_file, None);
+        let config =
+            Config::from_toml("[dkim]\ndomain = \"example.com\"\nprivate_key_file = \"dkim.pem\"")
+                .unwrap();
+        assert_eq!(config.dkim.selector, "default");
+        assert_eq!(config.dkim.algorithm, DkimAlgorithm::Rsa);
+        let config = Config::from_toml("[dkim]\nalgorithm = \"ed25519\"").unwrap();
+        assert_eq!(config.dkim.algorithm, DkimAlgorithm::Ed25519);
+    }
+
+    #[test]
+    fn attachment_format() {
+        assert_eq!(Config::default().attachment_format, AttachmentFormat::Csv);
+        let config = Config::from_toml("attachment_format = \"xlsx\"").unwrap();
+        assert_eq!(config.attachment_format, AttachmentFormat::Xlsx);
+        assert_eq!(config.attachment_format.extension(), "xlsx");
+    }
+
+    #[test]
+    fn short_links() {
+        let config = Config::from_toml("base_url = \"https://example.com/\"").unwrap();
+        assert_eq!(config.short_links.length, 6);
+        assert_eq!(config.short_url("K7QX4M"), "https://example.com/i/K7QX4M");
+        assert_eq!(config.rsvp_url("abc"), "https://example.com/rsvp/abc");
+        let config = Config::from_toml("[short_links]\nbase_url = \"https://jnj.wed\"").unwrap();
+        assert_eq!(config.short_url("K7QX4M"), "https://jnj.wed/i/K7QX4M");
+    }
+
+    #[test]
+    fn inserts() {
+        let config = Config::default();
+        assert_eq!(config.inserts.page_size, PageSize::Letter);
+        assert_eq!((config.inserts.columns, config.inserts.rows), (2, 2));
+        let config =
+            Config::from_toml("[inserts]\npage_size = \"a4\"\ncolumns = 1\nheading = \"J & J\"")
+                .unwrap();
+        assert_eq!(config.inserts.page_size, PageSize::A4);
+        assert_eq!(config.inserts.columns, 1);
+        assert_eq!(config.inserts.heading.as_deref(), Some("J & J"));
+        assert_eq!(config.seating.page_size, PageSize::Letter);
+    }
+
+    #[test]
+    fn contact() {
+        let config = Config::default().contact;
+        assert!(config.enabled);
+        assert_eq!(config.max_per_hour, 3);
+        assert_eq!(config.log, None);
+        let config = Config::from_toml(
+            "[contact]\nenabled = false\nmax_per_hour = 0\nlog = \"contact.jsonl\"\n\
+             quarantine = \"held.json\"",
+        )
+        .unwrap()
+        .contact;
+        assert!(!config.enabled);
+        assert_eq!(config.max_per_hour, 0);
+        assert_eq!(config.max_chars, 5000);
+        assert_eq!(config.log.as_deref(), Some("contact.jsonl"));
+        assert_eq!(config.quarantine.as_deref(), Some("held.json"));
+    }
+
+    #[test]
+    fn fetch_lockout() {
+        let config = Config::default().fetch_lockout;
+        assert_eq!(config.max_misses, 10);
+        assert!(config.alert);
+        assert_eq!(config.max_code_attempts, 5);
+        let config = Config::from_toml(
+            "[fetch_lockout]
+max_misses = 0
+lockout_secs = 600
+alert = false
+max_code_attempts = 3",
+        )
+        .unwrap()
+        .fetch_lockout;
+        assert_eq!(config.max_misses, 0);
+        assert_eq!(config.window_secs, 3600);
+        assert_eq!(config.lockout_secs, 600);
+        assert!(!config.alert);
+        assert_eq!(config.max_code_attempts, 3);
+    }
+
+    #[test]
+    fn form_webhook() {
+        assert_eq!(Config::default().form_webhook.secret, None);
+        let config = Config::from_toml(
+            "[form_webhook]
+secret = \"s3cret\"
+[form_webhook.fields]
+\"Will you be there?\" = \"attending\"
+full_name = \"name\"",
+        )
+        .unwrap()
+        .form_webhook;
+        assert_eq!(config.secret.as_deref(), Some("s3cret"));
+        assert_eq!(config.fields["Will you be there?"], "attending");
+        assert_eq!(config.fields["full_name"], "name");
+    }
+
+    #[test]
+    fn hooks() {
+        let config = Config::default().hooks;
+        assert_eq!(config.file, None);
+        assert_eq!(config.timeout_secs, 10);
+        let config = Config::from_toml("[hooks]\nfile = \"hooks.json\"")
+            .unwrap()
+            .hooks;
+        assert_eq!(config.file.as_deref(), Some("hooks.json"));
+        assert_eq!(config.timeout_secs, 10);
+    }
+
+    #[test]
+    fn meals() {
+        assert!(Config::default().meals.is_empty());
+        let config = Config::from_toml(
+            "[meals.Fish]
+label = \"Option B: Salmon\"
+allergens = \"Fish, dairy\"",
+        )
+        .unwrap();
+        assert_eq!(config.meals["Fish"].label, "Option B: Salmon");
+        assert_eq!(config.meals["Fish"].description, "");
+        assert_eq!(config.meals["Fish"].allergens, "Fish, dairy");
+    }
+
+    #[test]
+    fn export() {
+        assert!(Config::default().export.presets.is_empty());
+        let config = Config::from_toml(
+            "[export.presets]
+caterer = [\"name\", \"headcount\", \"meals\"]",
+        )
+        .unwrap()
+        .export;
+        assert_eq!(config.presets["caterer"], ["name", "headcount", "meals"]);
+    }
+
+    #[test]
+    fn session() {
+        let config = Config::default().session;
+        assert_eq!(config.key, None);
+        assert_eq!(config.cookie_name, "rsvp_session");
+        let config = Config::from_toml(
+            "[session]\nkey = \"0123456789abcdef0123456789abcdef\"\nremember_days = 30",
+        )
+        .unwrap()
+        .session;
+        assert_eq!(
+            config.key.as_deref(),
+            Some("0123456789abcdef0123456789abcdef")
+        );
+        assert_eq!(config.cookie_name, "rsvp_session");
+        assert_eq!(config.remember_days, 30);
+    }
+
+    #[test]
+    fn events() {
+        assert!(Config::default().events.is_empty());
+        let config = Config::from_toml(
+            r#"
+[[events]]
+title = "Ceremony"
+starts_at = "2024-06-01T16:00:00"
+ends_at = "2024-06-01T17:00:00"
+location = "The Chapel"
+[[events]]
+title = "Brunch"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.events.len(), 2);
+        assert_eq!(
+            config.events[0].starts_at.unwrap().to_string(),
+            "2024-06-01 16:00:00"
+        );
+        assert_eq!(config.events[0].location, "The Chapel");
+        assert_eq!(config.events[1].ends_at, None);
+        assert!(Config::from_toml("[[events]]\nstarts_at = \"June 1st\"").is_err());
+    }
+
+    #[test]
+    fn private_event() {
+        let config = Config::default().private_event;
+        assert!(config.tag.is_empty());
+        assert_eq!(config.title, "rehearsal dinner");
+        let config = Config::from_toml(
+            r#"
+[private_event]
+title = "welcome drinks"
+tag = "family"
+"#,
+        )
+        .unwrap()
+        .private_event;
+        assert_eq!(config.title, "welcome drinks");
+        assert_eq!(config.tag, "family");
+    }
+
+    #[test]
+    fn childcare() {
+        let config = Config::default().childcare;
+        assert!(config.tag.is_empty());
+        assert_eq!(config.max_kids, 6);
+        let config = Config::from_toml(
+            r#"
+[childcare]
+tag = "kids"
+max_kids = 4
+"#,
+        )
+        .unwrap()
+        .childcare;
+        assert_eq!(config.tag, "kids");
+        assert_eq!(config.max_kids, 4);
+    }
+
+    #[test]
+    fn songs() {
+        let config = Config::default().songs;
+        assert!(!config.enabled);
+        assert_eq!(config.file, None);
+        assert_eq!(config.max_per_guest, 5);
+        let config = Config::from_toml(
+            r#"
+[songs]
+enabled = true
+file = "songs.json"
+max_per_guest = 3
+"#,
+        )
+        .unwrap()
+        .songs;
+        assert!(config.enabled);
+        assert_eq!(config.file.as_deref(), Some("songs.json"));
+        assert_eq!(config.max_per_guest, 3);
+    }
+
+    #[test]
+    fn archive() {
+        let config = Config::default().archive;
+        assert_eq!(config.dir, "archive");
+        assert_eq!(config.key, None);
+        assert!(!config.scrub_pii);
+        let config = Config::from_toml(
+            r#"
+[archive]
+dir = "final"
+key = "secret"
+scrub_pii = true
+"#,
+        )
+        .unwrap()
+        .archive;
+        assert_eq!(config.dir, "final");
+        assert_eq!(config.key.as_deref(), Some("secret"));
+        assert!(config.scrub_pii);
+    }
+
+    #[test]
+    fn features() {
+        let config = Config::default();
+        assert_eq!(config.features, FeaturesConfig::default());
+        assert!(config.features.admin && config.features.email);
+        let config = Config::from_toml(
+            r#"
+verify_email = true
+[features]
+photos = false
+email = false
+"#,
+        )
+        .unwrap();
+        assert!(config.features.admin);
+        assert!(config.features.api);
+        assert!(!config.features.photos);
+        assert!(!config.features.email);
+        assert!(!config.verifies_email());
+    }
+
+    #[test]
+    fn travel() {
+        let config = Config::default().travel;
+        assert!(config.hotels.is_empty());
+        assert_eq!(config.reminder_days, 14);
+        let config = Config::from_toml(
+            r#"
+[travel]
+parking = "Free lot behind the hall"
+reminder_days = 7
+
+[[travel.hotels]]
+name = "The Inn"
+block_code = "SMITHWEDDING"
+cutoff = "2024-05-01"
+
+[[travel.airports]]
+name = "Springfield International"
+code = "SPI"
+"#,
+        )
+        .unwrap()
+        .travel;
+        assert_eq!(config.reminder_days, 7);
+        assert_eq!(config.hotels[0].block_code, "SMITHWEDDING");
+        assert_eq!(config.hotels[0].cutoff, NaiveDate::from_ymd_opt(2024, 5, 1));
+        assert_eq!(config.airports[0].code, "SPI");
+        assert_eq!(config.parking, "Free lot behind the hall");
+    }
+
+    #[test]
+    fn shuttle() {
+        assert!(Config::default().shuttle.stops.is_empty());
+        let config = Config::from_toml(
+            r#"
+[[shuttle.stops]]
+name = "downtown"
+title = "Downtown Hilton"
+time = "3:30pm"
+capacity = 40
+
+[[shuttle.stops]]
+name = "airport"
+title = "Airport"
+"#,
+        )
+        .unwrap()
+        .shuttle;
+        assert_eq!(config.stops.len(), 2);
+        assert_eq!(config.stops[0].title, "Downtown Hilton");
+        assert_eq!(config.stops[0].capacity, 40);
+        assert_eq!(config.stops[1].capacity, 0);
+    }
+
+    #[test]
+    fn registry() {
+        let config = Config::default().registry;
+        assert!(config.links.is_empty());
+        assert_eq!(config.clicks, None);
+        let config = Config::from_toml(
+            r#"
+[registry]
+clicks = "registry.json"
+
+[[registry.links]]
+name = "zola"
+title = "Zola"
+url = "https://www.zola.com/registry/janeandjohn"
+"#,
+        )
+        .unwrap()
+        .registry;
+        assert_eq!(config.clicks.as_deref(), Some("registry.json"));
+        assert_eq!(config.links.len(), 1);
+        assert_eq!(config.links[0].name, "zola");
+        assert_eq!(config.links[0].title, "Zola");
+    }
+
+    #[test]
+    fn confirmation() {
+        let config = Config::from_toml(
+            r#"
+[confirmation]
+attending_message = "See you there!"
+declined_message = "We'll miss you"
+[[confirmation.next_steps]]
+title = "Book a room"
+text = "Use code JJ2024"
+url = "https://hotel.example.com"
+[[confirmation.next_steps]]
+title = "Registry"
+url = "https://registry.example.com"
+show_to = "everyone"
+[[confirmation.next_steps]]
+title = "Send a card"
+show_to = "declined"
+"#,
+        )
+        .unwrap()
+        .confirmation;
+        assert_eq!(config.message(true), "See you there!");
+        assert_eq!(config.message(false), "We'll miss you");
+        let titles = |attending| {
+            config
+                .next_steps(attending)
+                .into_iter()
+                .map(|step| step.title)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles(true), vec!["Book a room", "Registry"]);
+        assert_eq!(titles(false), vec!["Registry", "Send a card"]);
+        assert!(Config::default().confirmation.next_steps(true).is_empty());
+    }
+
+    #[test]
+    fn bulk_email() {
+        let config = Config::from_toml("[bulk_email]\nbatch_size = 100\njitter_ms = 500").unwrap();
+        assert_eq!(
+            config.bulk_email,
+            BulkEmailConfig {
+                batch_size: 100,
+                jitter_ms: 500,
+                ..BulkEmailConfig::default()
+            }
+        );
+    }
+
+    #[test]
+    fn languages() {
+        assert_eq!(Config::default().languages.supported(), vec!["en"]);
+        let config = Config::from_toml(
+            r#"
+            [languages]
+            default = "fr"
+
+            [languages.emails.fr."email/verification.txt"]
+            template = "templates/fr/verification.txt"
+
+            [languages.emails.es."email/verification.txt"]
+            subject = "Tu código de verificación"
+            template = "templates/es/verification.txt"
+
+            [languages.pages.es]
+            "index.html" = "templates/es/index.html"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.languages.supported(), vec!["fr", "es"]);
+        assert_eq!(config.languages.page_languages(), vec!["fr", "es"]);
+        assert_eq!(
+            config.languages.pages["es"]["index.html"],
+            "templates/es/index.html"
+        );
+        let spanish = &config.languages.emails["es"]["email/verification.txt"];
+        assert_eq!(
+            spanish.subject.as_deref(),
+            Some("Tu código de verificación")
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let limits = LimitsConfig::default();
+        assert!(limits.allows(usize::MAX, u64::MAX));
+        assert_eq!(limits.warning(usize::MAX, u64::MAX), None);
+
+        let config =
+            Config::from_toml("[limits]\nmax_records = 100\nmax_file_bytes = 10000").unwrap();
+        let limits = config.limits;
+        assert!(limits.allows(100, 9_999));
+        assert!(!limits.allows(101, 0));
+        assert!(!limits.allows(1, 10_000));
+        assert_eq!(limits.warning(79, 7_999), None);
+        assert_eq!(
+            limits.warning(80, 100).unwrap(),
+            "The guest list has 80 of the 100 records allowed: \
+             raise `limits` before new guests are turned away"
+        );
+        assert_eq!(
+            limits.warning(100, 9_000).unwrap(),
+            "The guest list has 100 of the 100 records allowed and 9000 of the 10000 \
+             bytes allowed: new guests are being turned away until `limits` are raised"
+        );
+    }
+
+    #[test]
+    fn timezone() {
+        assert_eq!(Config::default().timezone.to_string(), "UTC");
+        let config = Config::from_toml("timezone = \"America/New_York\"").unwrap();
+        assert_eq!(config.timezone.to_string(), "America/New_York");
+        assert!(Config::from_toml("timezone = \"Eastern\"").is_err());
+    }
+
+    #[test]
+    fn banners() {
+        let config = Config::from_toml(
+            "[[banners]]\ntext = \"Parking changed\"\nseverity = \"warning\"\n\
+             expires = \"2024-06-01T18:00:00Z\"\npaths = [\"/photos\"]",
+        )
+        .unwrap();
+        let banner = &config.banners[0];
+        assert_eq!(banner.severity, BannerSeverity::Warning);
+        assert_eq!(
+            banner.expires.unwrap().to_rfc3339(),
+            "2024-06-01T18:00:00+00:00"
+        );
+        assert_eq!(banner.paths, vec!["/photos"]);
+    }
+
+    #[test]
+    fn cors() {
+        assert!(!Config::default().cors.allows("https://example.com"));
+        let config =
+            Config::from_toml("[cors]\nallowed_origins = [\"https://example.netlify.app/\"]")
+                .unwrap();
+        assert!(config.cors.allows("https://example.netlify.app"));
+        assert!(!config.cors.allows("https://evil.example.com"));
+        assert_eq!(config.cors.allowed_methods, vec!["GET", "POST"]);
+        let config = Config::from_toml("[cors]\nallowed_origins = [\"*\"]").unwrap();
+        assert!(config.cors.allows("https://evil.example.com"));
+    }
+
+    #[test]
+    fn server() {
+        let config = Config::default();
+        assert_eq!(config.server.workers, 1);
+        assert_eq!(
+            config.server.keep_alive(),
+            KeepAlive::Timeout(Duration::from_secs(5))
+        );
+        let config =
+            Config::from_toml("[server]\nworkers = 4\nkeep_alive_secs = 0\nbacklog = 64").unwrap();
+        assert_eq!(config.server.workers, 4);
+        assert_eq!(config.server.keep_alive(), KeepAlive::Disabled);
+        assert_eq!(config.server.backlog, 64);
+        assert_eq!(config.server.max_connections, 25_000);
+        assert_eq!(config.server.host, "127.0.0.1");
+    }
+
+    #[test]
+    fn not_found() {
+        let config = NotFoundConfig::default();
+        assert!(config.is_scanner("/wp-login.php"));
+        assert!(config.is_scanner("/blog/WP-ADMIN/"));
+        assert!(config.is_scanner("/.env"));
+        assert!(!config.is_scanner("/photos/missing.jpg"));
+        let config = Config::from_toml("[not_found]\nscanner_patterns = [\"/favicon\"]").unwrap();
+        assert!(config.not_found.is_scanner("/favicon.ico"));
+        assert!(!config.not_found.is_scanner("/wp-login.php"));
+    }
+
+    #[test]
+    fn tls() {
+        assert!(Config::default().tls.domains.is_empty());
+        let config = Config::from_toml(
+            "[tls]\ndomains = [\"example.com\"]\ncontact_email = \"us@example.com\"",
+        )
+        .unwrap();
+        assert_eq!(config.tls.domains, vec!["example.com"]);
+        assert_eq!(config.tls.bind, "0.0.0.0:443");
+        assert_eq!(config.tls.renew_before_days, 30);
+    }
+}
@@ -0,0 +1,175 @@
+use {
+    crate::{config::ContactConfig, error::Error},
+    chrono::{DateTime, Utc},
+    serde::{Deserialize, Serialize},
+    std::{fs::OpenOptions, io::Write},
+};
+
+/// Fields of the contact page
+pub const FIELDS: &[&str] = &["name", "email", "message"];
+
+/// What a guest wrote on the contact page
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ContactParams {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub email: String,
+    #[serde(default)]
+    pub message: String,
+    /// Hidden on the page, so only bots fill it in
+    #[serde(default)]
+    pub website: String,
+}
+impl ContactParams {
+    /// Everything wrong with the message, which needs fixing before it's
+    /// relayed, with the field each is about
+    pub fn problems(&self, config: &ContactConfig) -> Vec<(&'static str, Error)> {
+        let mut problems = vec![];
+        if self.name.trim().is_empty() {
+            problems.push(("name", Error::Required("your name".to_string())));
+        }
+        if self.email.trim().is_empty() {
+            problems.push(("email", Error::Required("your email".to_string())));
+        }
+        if self.message.trim().is_empty() {
+            problems.push(("message", Error::Required("a message".to_string())));
+        } else if self.message.chars().count() > config.max_chars {
+            problems.push((
+                "message",
+                Error::Invalid(format!(
+                    "Please keep your message under {} characters",
+                    config.max_chars
+                )),
+            ));
+        }
+        problems
+    }
+
+    /// Why it looks like a bot sent it, if it does: the hidden field is
+    /// filled in, or there are more links than anyone would write to the
+    /// couple
+    pub fn spam_reason(&self, config: &ContactConfig) -> Option<String> {
+        let links =
+            self.message.matches("http://").count() + self.message.matches("https://").count();
+        if !self.website.is_empty() {
+            Some("filled in the hidden field".to_string())
+        } else if links > config.max_links {
+            Some(format!("{} links", links))
+        } else {
+            None
+        }
+    }
+}
+
+/// A message relayed to the admin, as a line of the contact log
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ContactMessage {
+    pub at: DateTime<Utc>,
+    pub request_id: String,
+    pub client_ip: String,
+    pub name: String,
+    pub email: String,
+    pub message: String,
+}
+impl ContactMessage {
+    pub fn new(
+        params: &ContactParams,
+        at: DateTime<Utc>,
+        request_id: &str,
+        client_ip: &str,
+    ) -> Self {
+        Self {
+            at,
+            request_id: request_id.to_string(),
+            client_ip: client_ip.to_string(),
+            name: params.name.trim().to_string(),
+            email: params.email.trim().to_string(),
+            message: params.message.trim().to_string(),
+        }
+    }
+}
+
+/// Add a message to the end of the contact log, one JSON object per line
+pub fn store(path: &str, message: &ContactMessage) -> Result<(), Error> {
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+    file.write_all(format!("{}\n", serde_json::to_string(message)?).as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, std::fs};
+
+    fn params() -> ContactParams {
+        ContactParams {
+            name: "Maria".to_string(),
+            email: "maria@example.com".to_string(),
+            message: "Is there parking at the venue?".to_string(),
+            website: String::default(),
+        }
+    }
+
+    #[test]
+    fn checks() {
+        let config = ContactConfig::default();
+        assert!(params().problems(&config).is_empty());
+        assert_eq!(params().spam_reason(&config), None);
+        let blank = ContactParams {
+            message: " ".to_string(),
+            ..params()
+        };
+        assert!(matches!(
+            blank.problems(&config)[..],
+            [("message", Error::Required(_))]
+        ));
+        let long = ContactParams {
+            message: "a".repeat(config.max_chars + 1),
+            ..params()
+        };
+        assert!(matches!(
+            long.problems(&config)[..],
+            [("message", Error::Invalid(_))]
+        ));
+        let empty = ContactParams::default();
+        assert_eq!(
+            empty
+                .problems(&config)
+                .into_iter()
+                .map(|(field, _)| field)
+                .collect::<Vec<_>>(),
+            FIELDS
+        );
+
+        let bot = ContactParams {
+            website: "https://example.com".to_string(),
+            ..params()
+        };
+        assert_eq!(
+            bot.spam_reason(&config).as_deref(),
+            Some("filled in the hidden field")
+        );
+        let links = ContactParams {
+            message: "https://a.example http://b.example https://c.example".to_string(),
+            ..params()
+        };
+        assert_eq!(links.spam_reason(&config).as_deref(), Some("3 links"));
+    }
+
+    #[test]
+    fn log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contact.jsonl");
+        let path = path.to_str().unwrap();
+        let message = ContactMessage::new(&params(), Utc::now(), "3f9a1c2e", "203.0.113.7");
+        store(path, &message).unwrap();
+        store(path, &message).unwrap();
+        let contents = fs::read_to_string(path).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            serde_json::from_str::<ContactMessage>(lines[1]).unwrap(),
+            message
+        );
+    }
+}
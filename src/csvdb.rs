@@ -1,79 +1,312 @@
 use {
     crate::{
+        clock::{Clock, SystemClock},
+        config::{ChildcareConfig, LimitsConfig, PrivateEventConfig, ShuttleStop},
         error::Error,
-        model::{AddParams, Attendance, RsvpModel, RsvpParams},
+        git::GitHistory,
+        model::{
+            name_key, new_short_code, new_token, normalize_name, normalize_short_code, search_key,
+            AddParams, Attendance, Headcount, ListParams, Page, RsvpModel, RsvpParams,
+            MAX_PER_PAGE, MEALS,
+        },
+        s3::S3Backup,
     },
-    chrono::{DateTime, Utc},
+    chrono::{DateTime, Duration, Utc},
     csv::{ReaderBuilder, WriterBuilder},
-    log::error,
+    log::{error, warn},
     std::{
+        collections::{BTreeMap, HashSet, VecDeque},
         fs::File,
-        io::{BufReader, Read, Seek, SeekFrom, Write},
+        io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+        sync::Arc,
     },
     tempfile::tempfile,
+    tracing::instrument,
 };
 
-const HEADER_LINE: &str = "name,email,attending,attending_secondary,attending_tertiary,meal_choice,dietary_restrictions,plus_one_attending,plus_one_name,plus_one_meal_choice,plus_one_dietary_restrictions,comments,created_at,updated_at";
+pub const HEADER_LINE: &str = "name,email,attending,attending_secondary,attending_tertiary,meal_choice,dietary_restrictions,plus_one_attending,plus_one_name,plus_one_meal_choice,plus_one_dietary_restrictions,comments,created_at,updated_at,email_verified,verification_code,email_status,token,tags,announcements_opt_out,transactional_opt_out,notes,checked_in_at,name_key,plus_one_attending_secondary,plus_one_attending_tertiary,invited,responded_at,language,short_code,table,dietary,plus_one_dietary,dietary_severity,plus_one_dietary_severity,wave,source,plus_one_email,needs_lodging,lodging_reminded,shuttle,attending_private,plus_one_attending_private,kids,kids_ages,thank_you_sent,gift";
+
+/// Changes kept for undo unless the config says otherwise
+pub const DEFAULT_UNDO_HISTORY: usize = 20;
+
+/// Characters in new short codes unless the config says otherwise
+pub const DEFAULT_SHORT_CODE_LENGTH: usize = 6;
+
+/// The file as it was before a change, to be able to undo it
+#[derive(Clone, Debug)]
+pub struct Change {
+    /// What was done, like the audit log's actions, e.g. "RSVP"
+    pub action: String,
+    /// Whose record changed, or a count like "12 guests"
+    pub name: String,
+    pub at: DateTime<Utc>,
+    contents: String,
+}
 
 pub struct CsvDb {
     pub file: File,
-    pub datetime: DateTime<Utc>,
+    pub clock: Arc<dyn Clock>,
+    /// Most of each meal that can be chosen, from the config
+    pub meal_limits: BTreeMap<String, usize>,
+    /// Shuttle stops guests can choose and their capacities, from the config
+    pub shuttle_stops: Vec<ShuttleStop>,
+    /// Who's asked about the private event, from the config
+    pub private_event: PrivateEventConfig,
+    /// Which parties are asked about childcare, from the config
+    pub childcare: ChildcareConfig,
+    /// Most changes kept for undo, from the config
+    pub undo_history: usize,
+    /// Most records and bytes the file may hold, from the config
+    pub limits: LimitsConfig,
+    /// Characters in new short codes, from the config
+    pub short_code_length: usize,
+    /// Changes kept for undo, shared by every worker along with the file
+    history: VecDeque<Change>,
+    /// Repository to commit the file to after each change, from the config
+    pub git: Option<GitHistory>,
+    /// Bucket to upload the file to after each change, from the config
+    pub s3: Option<S3Backup>,
+    /// Whether a change is already being recorded, so the steps of a change
+    /// like `upsert` aren't undone separately
+    recording: bool,
+}
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+/// Give a short code to each of the records without one, or whose code is
+/// already `taken`, adding them all to it
+fn fill_short_codes(records: &mut [RsvpModel], taken: &mut HashSet<String>, length: usize) {
+    for record in records {
+        if record.short_code.is_empty() || !taken.insert(record.short_code.clone()) {
+            record.short_code = new_short_code(length, taken);
+            taken.insert(record.short_code.clone());
+        }
+    }
 }
+
 impl CsvDb {
     pub fn new(file: File) -> Self {
-        Self::new_with_time(file, Utc::now())
+        Self::new_with_clock(file, Arc::new(SystemClock))
     }
 
-    pub fn new_with_time(file: File, datetime: DateTime<Utc>) -> Self {
-        Self { file, datetime }
+    pub fn new_with_clock(file: File, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            file,
+            clock,
+            meal_limits: BTreeMap::default(),
+            shuttle_stops: vec![],
+            private_event: PrivateEventConfig::default(),
+            childcare: ChildcareConfig::default(),
+            undo_history: DEFAULT_UNDO_HISTORY,
+            limits: LimitsConfig::default(),
+            short_code_length: DEFAULT_SHORT_CODE_LENGTH,
+            history: VecDeque::default(),
+            git: None,
+            s3: None,
+            recording: false,
+        }
+    }
+
+    /// Run a change to the file, keeping the contents from before so it can
+    /// be undone.  Changes made inside `f` are undone together with it, and
+    /// nothing is kept if it fails or leaves the file as it was.
+    pub fn undoable<T, F>(&mut self, action: &str, name: &str, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        if self.recording || (self.undo_history == 0 && self.git.is_none() && self.s3.is_none()) {
+            return f(self);
+        }
+        let contents = self.contents()?;
+        self.recording = true;
+        let result = f(self);
+        self.recording = false;
+        let result = result?;
+        let current = self.contents()?;
+        if current != contents {
+            self.changed(&current, &format!("{} for {}", action, name));
+            if self.undo_history > 0 {
+                while self.history.len() >= self.undo_history {
+                    self.history.pop_front();
+                }
+                self.history.push_back(Change {
+                    action: action.to_string(),
+                    name: name.to_string(),
+                    at: self.now(),
+                    contents,
+                });
+            }
+        }
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(result)
+    }
+
+    /// Changes that can be undone, most recent last
+    pub fn history(&self) -> impl Iterator<Item = &Change> {
+        self.history.iter()
     }
 
-    /// Update the time in the CSV file to the given time, useful for testing
-    pub fn update_time(&mut self, new_datetime: DateTime<Utc>) {
-        self.datetime = new_datetime;
+    /// Put the file back as it was before the most recent change, returning
+    /// that change, or `None` if there's nothing left to undo
+    #[instrument(skip_all)]
+    pub fn undo(&mut self) -> Result<Option<Change>, Error> {
+        let change = match self.history.pop_back() {
+            Some(change) => change,
+            None => return Ok(None),
+        };
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(change.contents.as_bytes())?;
+        self.file.flush()?;
+        self.changed(
+            &change.contents,
+            &format!("undo {} for {}", change.action, change.name),
+        );
+        Ok(Some(change))
+    }
+
+    /// Copy the file elsewhere after a change, to the git history and the
+    /// bucket if there are any
+    fn changed(&self, contents: &str, message: &str) {
+        if let Some(git) = &self.git {
+            git.record(contents, message);
+        }
+        if let Some(s3) = &self.s3 {
+            s3.upload_in_background();
+        }
+    }
+
+    fn contents(&mut self) -> Result<String, Error> {
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut contents = String::new();
+        BufReader::new(&self.file).read_to_string(&mut contents)?;
+        Ok(contents)
+    }
+
+    /// Contents of the file, read at each offset without moving the cursor,
+    /// so lookups holding a read lock don't get in each other's way
+    fn shared_contents(&self) -> Result<String, Error> {
+        let mut contents = vec![0; self.file.metadata()?.len() as usize];
+        let mut read = 0;
+        while read < contents.len() {
+            match read_at(&self.file, &mut contents[read..], read as u64)? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        contents.truncate(read);
+        Ok(String::from_utf8_lossy(&contents).into_owned())
+    }
+
+    /// Time used for new and updated records
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
     }
 
     /// Inserts a new record just based on names
+    #[instrument(skip_all, fields(name = %params.name))]
     pub fn insert(&mut self, params: &AddParams) -> Result<RsvpModel, Error> {
-        if let Some(model) = self.get(&params.name)? {
-            self.file.seek(SeekFrom::End(0))?;
-            error!(
-                "Attempted to add {:?}, but {:?} exists already",
-                params, model
-            );
-            Err(Error::Add(params.clone()))
-        } else {
-            self.file.seek(SeekFrom::End(0))?;
-            let record_to_insert = RsvpModel::new_with_add(params, self.datetime);
+        self.undoable("add", &params.name, |db| {
+            if let Some(model) = db.get(&params.name)? {
+                db.file.seek(SeekFrom::End(0))?;
+                error!(
+                    "Attempted to add {:?}, but {:?} exists already",
+                    params, model
+                );
+                Err(Error::Add(Box::new(params.clone())))
+            } else {
+                db.check_limits(1)?;
+                let record_to_insert = RsvpModel {
+                    short_code: db.new_short_code()?,
+                    ..RsvpModel::new_with_add(params, db.now())
+                };
+                db.file.seek(SeekFrom::End(0))?;
+                let mut wtr = WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(&db.file);
+                wtr.serialize(record_to_insert.clone())
+                    .map_err(Error::from)?;
+                wtr.flush()?;
+                Ok(record_to_insert)
+            }
+        })
+    }
+
+    /// Upsert a new record at the end.
+    ///
+    /// Search for a record. If not found, insert a new record at the end. If found,
+    /// erase the previous record and insert a new one.
+    #[instrument(skip_all, fields(name = %params.name))]
+    pub fn upsert(&mut self, params: &RsvpParams) -> Result<RsvpModel, Error> {
+        let params = &self.drop_unasked(params)?;
+        self.undoable("RSVP", &params.name, |db| {
+            db.check_meal_limits(params)?;
+            db.check_shuttle(params)?;
+            let maybe_record = db.remove(&params.name)?; // remove keeps the file in the right place for writing
+            let record_to_insert = if let Some(mut record) = maybe_record {
+                record.update(params, db.now())?;
+                record
+            } else {
+                db.check_limits(1)?;
+                let record = RsvpModel {
+                    short_code: db.new_short_code()?,
+                    ..RsvpModel::new_with_rsvp(params, db.now())
+                };
+                db.file.seek(SeekFrom::End(0))?;
+                record
+            };
             let mut wtr = WriterBuilder::new()
                 .has_headers(false)
-                .from_writer(&self.file);
+                .from_writer(&db.file);
             wtr.serialize(record_to_insert.clone())
                 .map_err(Error::from)?;
             wtr.flush()?;
             Ok(record_to_insert)
-        }
+        })
     }
 
-    /// Upsert a new record at the end.
-    ///
-    /// Search for a record. If not found, insert a new record at the end. If found,
-    /// erase the previous record and insert a new one.
-    pub fn upsert(&mut self, params: &RsvpParams) -> Result<RsvpModel, Error> {
-        let maybe_record = self.remove(&params.name)?; // remove keeps the file in the right place for writing
-        let record_to_insert = if let Some(mut record) = maybe_record {
-            record.update(params, self.datetime)?;
-            record
-        } else {
-            RsvpModel::new_with_rsvp(params, self.datetime)
-        };
-        let mut wtr = WriterBuilder::new()
-            .has_headers(false)
-            .from_writer(&self.file);
-        wtr.serialize(record_to_insert.clone())
-            .map_err(Error::from)?;
-        wtr.flush()?;
-        Ok(record_to_insert)
+    /// Add new records at the end in one go, without checking for existing
+    /// names
+    #[instrument(skip_all, fields(count = records.len()))]
+    pub fn append(&mut self, records: &[RsvpModel]) -> Result<(), Error> {
+        self.undoable("add", &format!("{} guests", records.len()), |db| {
+            db.check_limits(records.len())?;
+            let mut records = records.to_vec();
+            let mut taken = db.short_codes()?;
+            fill_short_codes(&mut records, &mut taken, db.short_code_length);
+            db.file.seek(SeekFrom::End(0))?;
+            let mut wtr = WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(&db.file);
+            for record in records {
+                wtr.serialize(record).map_err(Error::from)?;
+            }
+            wtr.flush()?;
+            Ok(())
+        })
+    }
+
+    /// Short codes already given to guests
+    fn short_codes(&self) -> Result<HashSet<String>, Error> {
+        Ok(self
+            .get_all()?
+            .into_iter()
+            .map(|r| r.short_code)
+            .filter(|code| !code.is_empty())
+            .collect())
+    }
+
+    /// A short code no guest has yet
+    fn new_short_code(&self) -> Result<String, Error> {
+        let taken = self.short_codes()?;
+        Ok(new_short_code(self.short_code_length, &taken))
     }
 
     /// Removes a record by name if found, rewriting the whole file
@@ -81,84 +314,500 @@ impl CsvDb {
     /// Ideally, we could use an memmap, clear just the bytes of the entry,
     /// and append at the end, with some regular compaction.  This is good enough
     /// for v1 and small enough sets.
+    #[instrument(skip(self))]
     pub fn remove(&mut self, name: &str) -> Result<Option<RsvpModel>, Error> {
-        let records = self.get_all()?;
-        let name = name.trim().to_lowercase();
-        if let Some(record) = records.iter().find(|r| r.name.to_lowercase() == name) {
-            self.file.set_len(0)?;
-            let record = record.clone();
-            self.file.seek(SeekFrom::Start(0))?;
-            let mut wtr = WriterBuilder::new()
-                .has_headers(true)
-                .from_writer(&self.file);
-            for record in records {
-                if record.name.to_lowercase() != name {
-                    wtr.serialize(record).map_err(Error::from)?;
-                }
+        self.undoable("remove", name, |db| {
+            let records = db.get_all()?;
+            let key = name_key(name);
+            if let Some(record) = records.iter().find(|r| r.name_key == key) {
+                let record = record.clone();
+                let remaining = records
+                    .into_iter()
+                    .filter(|r| r.name_key != key)
+                    .collect::<Vec<_>>();
+                db.rewrite(&remaining)?;
+                Ok(Some(record))
+            } else {
+                db.file.seek(SeekFrom::End(0))?;
+                Ok(None)
             }
-            wtr.flush()?;
-            Ok(Some(record))
-        } else {
-            self.file.seek(SeekFrom::End(0))?;
-            Ok(None)
-        }
+        })
     }
 
-    /// Get a specific record
-    pub fn get(&mut self, name: &str) -> Result<Option<RsvpModel>, Error> {
+    /// Apply a change to an existing record, moving it to the end of the file
+    /// like `upsert`.  Returns `None` if there's no record with that name.
+    #[instrument(skip(self, f))]
+    pub fn update_with<F>(&mut self, name: &str, f: F) -> Result<Option<RsvpModel>, Error>
+    where
+        F: FnOnce(&mut RsvpModel),
+    {
+        self.undoable("change", name, |db| {
+            if let Some(mut record) = db.remove(name)? {
+                f(&mut record);
+                let mut wtr = WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(&db.file);
+                wtr.serialize(record.clone()).map_err(Error::from)?;
+                wtr.flush()?;
+                Ok(Some(record))
+            } else {
+                Ok(None)
+            }
+        })
+    }
+
+    /// Rewrite the file with the current header if it was created empty, or
+    /// with an older set of columns.  New columns take their default values,
+    /// records without a token or short code get a new one, and names are
+    /// normalized.
+    /// Files from before plus-ones answered per event have their single
+    /// answer copied to each event the guest is coming to, and files from
+    /// before invited headcounts count the guest and any plus-one as invited,
+    /// treating everyone but untouched additions as having responded.
+    #[instrument(skip_all)]
+    pub fn migrate(&mut self) -> Result<(), Error> {
         self.file.seek(SeekFrom::Start(0))?;
-        let mut reader = ReaderBuilder::new()
-            .has_headers(true)
-            .from_reader(&self.file);
-        for result in reader.deserialize() {
-            let rsvp: RsvpModel = result?;
-            for name in name.split('&') {
-                let name = name.trim().to_lowercase();
-                if rsvp.name.to_lowercase() == name || rsvp.plus_one_name.to_lowercase() == name {
-                    return Ok(Some(rsvp));
+        let mut header = String::new();
+        BufReader::new(&self.file).read_line(&mut header)?;
+        let mut records = if header.is_empty() {
+            vec![]
+        } else {
+            self.get_all()?
+        };
+        let missing_tokens = records.iter().any(|r| r.token.is_empty());
+        let mut codes = HashSet::new();
+        let missing_short_codes = records
+            .iter()
+            .any(|r| r.short_code.is_empty() || !codes.insert(r.short_code.as_str()));
+        let unnormalized = records
+            .iter()
+            .any(|r| r.name_key != name_key(&r.name) || r.name != normalize_name(&r.name));
+        let columns = header.trim_end().split(',').collect::<Vec<_>>();
+        let single_plus_one = !columns.contains(&"plus_one_attending_secondary");
+        let no_headcount = !columns.contains(&"invited");
+        if header.trim_end() != HEADER_LINE || missing_tokens || missing_short_codes || unnormalized
+        {
+            for record in records.iter_mut() {
+                if single_plus_one {
+                    let plus_one_attending = record.plus_one_attending;
+                    record.plus_one_attending = plus_one_attending && record.attending;
+                    record.plus_one_attending_secondary =
+                        plus_one_attending && record.attending_secondary;
+                    record.plus_one_attending_tertiary =
+                        plus_one_attending && record.attending_tertiary;
                 }
+                if no_headcount {
+                    record.invited = 1 + u32::from(
+                        !record.plus_one_name.is_empty() || record.plus_one_attending_any(),
+                    );
+                    let untouched = record.created_at == record.updated_at
+                        && !record.is_attending_any()
+                        && record.meal_choice.is_empty()
+                        && record.comments.is_empty();
+                    if !untouched {
+                        record.responded_at = Some(record.updated_at);
+                    }
+                }
+                if record.token.is_empty() {
+                    record.token = new_token();
+                }
+                record.name = normalize_name(&record.name);
+                record.name_key = name_key(&record.name);
             }
+            fill_short_codes(&mut records, &mut HashSet::new(), self.short_code_length);
+            self.rewrite(&records)?;
         }
-        Ok(None)
+        Ok(())
     }
 
-    /// Get all records
-    pub fn get_all(&mut self) -> Result<Vec<RsvpModel>, Error> {
+    /// Replace the whole file with the given records, leaving it positioned
+    /// at the end for writing
+    fn rewrite(&mut self, records: &[RsvpModel]) -> Result<(), Error> {
+        self.file.set_len(0)?;
         self.file.seek(SeekFrom::Start(0))?;
-        let mut reader = ReaderBuilder::new()
+        writeln!(self.file, "{}", HEADER_LINE)?;
+        let mut wtr = WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(&self.file);
+        for record in records {
+            wtr.serialize(record).map_err(Error::from)?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    /// Replace every record with ones from a snapshot, as a change that can
+    /// be undone
+    #[instrument(skip(self, records))]
+    pub fn restore(&mut self, snapshot: &str, records: &[RsvpModel]) -> Result<(), Error> {
+        self.undoable("restore", snapshot, |db| db.rewrite(records))
+    }
+
+    /// Replace every record with scrubbed copies, dropping the undo history
+    /// so what was scrubbed can't be brought back
+    #[instrument(skip_all)]
+    pub fn scrub(&mut self, records: &[RsvpModel]) -> Result<(), Error> {
+        self.rewrite(records)?;
+        self.history.clear();
+        let contents = self.contents()?;
+        self.changed(&contents, "scrub personal details");
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    /// Get a specific record
+    #[instrument(skip(self))]
+    pub fn get(&self, name: &str) -> Result<Option<RsvpModel>, Error> {
+        let keys = name
+            .split('&')
+            .map(name_key)
+            .filter(|k| !k.is_empty())
+            .collect::<Vec<_>>();
+        Ok(self.get_all()?.into_iter().find(|rsvp| {
+            keys.iter()
+                .any(|key| rsvp.name_key == *key || name_key(&rsvp.plus_one_name) == *key)
+        }))
+    }
+
+    /// Get the record if it was last updated with exactly these params within
+    /// the window, e.g. from a double-click or a browser retry
+    #[instrument(skip_all, fields(name = %params.name))]
+    pub fn find_duplicate(
+        &self,
+        params: &RsvpParams,
+        window: Duration,
+    ) -> Result<Option<RsvpModel>, Error> {
+        let key = name_key(&params.name);
+        let params = RsvpParams {
+            plus_one_name: normalize_name(&params.plus_one_name),
+            ..params.clone()
+        };
+        let now = self.now();
+        Ok(self.get_all()?.into_iter().find(|r| {
+            r.name_key == key
+                && RsvpParams {
+                    name: params.name.clone(),
+                    source: params.source,
+                    ..RsvpParams::from(r)
+                } == params
+                && now - r.updated_at <= window
+        }))
+    }
+
+    /// Get all records with the given email address
+    #[instrument(skip(self))]
+    pub fn find_by_email(&self, email: &str) -> Result<Vec<RsvpModel>, Error> {
+        let email = email.trim().to_lowercase();
+        Ok(self
+            .get_all()?
+            .into_iter()
+            .filter(|r| !email.is_empty() && r.email.trim().to_lowercase() == email)
+            .collect())
+    }
+
+    /// Get the record with the given token, used by links sent to guests
+    #[instrument(skip_all)]
+    pub fn find_by_token(&self, token: &str) -> Result<Option<RsvpModel>, Error> {
+        let token = token.trim();
+        Ok(self
+            .get_all()?
+            .into_iter()
+            .find(|r| !token.is_empty() && r.token == token))
+    }
+
+    /// Get the record with a short code, typed in any case
+    #[instrument(skip(self))]
+    pub fn find_by_short_code(&self, code: &str) -> Result<Option<RsvpModel>, Error> {
+        let code = normalize_short_code(code);
+        if code.is_empty() {
+            return Ok(None);
+        }
+        Ok(self.get_all()?.into_iter().find(|r| r.short_code == code))
+    }
+
+    /// Get the records whose names, email or comments contain the query,
+    /// ignoring case and accents
+    #[instrument(skip(self))]
+    pub fn search(&self, query: &str) -> Result<Vec<RsvpModel>, Error> {
+        let query = search_key(query);
+        if query.is_empty() {
+            return Err(Error::Query("nothing to search for".to_string()));
+        }
+        Ok(self
+            .get_all()?
+            .into_iter()
+            .filter(|r| r.matches(&query))
+            .collect())
+    }
+
+    /// Records from the contents of a CSV file, like a snapshot
+    pub fn parse(contents: &str) -> Result<Vec<RsvpModel>, Error> {
+        ReaderBuilder::new()
             .has_headers(true)
-            .from_reader(&self.file);
-        let mut records = vec![];
-        for result in reader.deserialize() {
-            let rsvp: RsvpModel = result?;
-            records.push(rsvp);
+            .from_reader(contents.as_bytes())
+            .deserialize()
+            .map(|result| result.map_err(Error::from))
+            .collect()
+    }
+
+    /// Get all records.  Only reads the file, like the other lookups built
+    /// on it, so they can be called under a read lock.
+    #[instrument(skip_all)]
+    pub fn get_all(&self) -> Result<Vec<RsvpModel>, Error> {
+        Self::parse(&self.shared_contents()?)
+    }
+
+    /// Get one page of records, filtered and sorted as asked
+    #[instrument(skip(self))]
+    pub fn list(&self, params: &ListParams) -> Result<Page<RsvpModel>, Error> {
+        if params.page == 0 || params.per_page == 0 {
+            return Err(Error::Query("page and per_page start at 1".to_string()));
         }
-        Ok(records)
+        let filters = params
+            .filter
+            .split(',')
+            .map(str::trim)
+            .filter(|filter| !filter.is_empty())
+            .map(parse_filter)
+            .collect::<Result<Vec<_>, _>>()?;
+        let query = search_key(&params.q);
+        let mut records = self
+            .get_all()?
+            .into_iter()
+            .filter(|r| filters.iter().all(|filter| filter(r)) && r.matches(&query))
+            .collect::<Vec<_>>();
+
+        let (field, descending) = match params.sort.trim().strip_prefix('-') {
+            Some(field) => (field, true),
+            None => (params.sort.trim(), false),
+        };
+        match field {
+            "" => {}
+            "name" => records.sort_by(|a, b| a.name_key.cmp(&b.name_key)),
+            "email" => records.sort_by_key(|r| r.email.to_lowercase()),
+            "created_at" => records.sort_by_key(|r| r.created_at),
+            "updated_at" => records.sort_by_key(|r| r.updated_at),
+            _ => return Err(Error::Query(format!("can't sort by {:?}", field))),
+        }
+        if descending {
+            records.reverse();
+        }
+
+        let per_page = params.per_page.min(MAX_PER_PAGE);
+        let total = records.len();
+        Ok(Page {
+            items: records
+                .into_iter()
+                .skip((params.page - 1).saturating_mul(per_page))
+                .take(per_page)
+                .collect(),
+            page: params.page,
+            per_page,
+            total,
+            pages: total.div_ceil(per_page).max(1),
+        })
+    }
+
+    /// How many of each meal other guests have chosen, leaving out the
+    /// given guest's own choices since they may be changing them
+    #[instrument(skip(self))]
+    pub fn meal_counts(&self, except: &str) -> Result<BTreeMap<String, usize>, Error> {
+        let except = name_key(except);
+        let mut counts = BTreeMap::new();
+        for record in self.get_all()? {
+            if record.name_key == except {
+                continue;
+            }
+            for meal in record.meals() {
+                *counts.entry(meal.to_string()).or_default() += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    /// How many riders other parties have signed up for at each shuttle
+    /// stop, leaving out the given guest's since they may be changing it
+    pub fn shuttle_counts(&self, except: &str) -> Result<BTreeMap<String, usize>, Error> {
+        let except = name_key(except);
+        let mut counts = BTreeMap::new();
+        for record in self.get_all()? {
+            if record.name_key != except && record.shuttle_riders() > 0 {
+                *counts.entry(record.shuttle.clone()).or_default() += record.shuttle_riders();
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Whether each meal on the form, or with a limit, has none left for
+    /// the given guest
+    pub fn meals_full(&self, name: &str) -> Result<BTreeMap<String, bool>, Error> {
+        let counts = self.meal_counts(name)?;
+        let meals = MEALS
+            .iter()
+            .map(|meal| meal.to_string())
+            .chain(self.meal_limits.keys().cloned());
+        Ok(meals
+            .map(|meal| {
+                let full = self
+                    .meal_limits
+                    .get(&meal)
+                    .is_some_and(|limit| counts.get(&meal).copied().unwrap_or(0) >= *limit);
+                (meal, full)
+            })
+            .collect())
+    }
+
+    /// Turn away new records once the file has as many as `limits` allows
+    fn check_limits(&self, adding: usize) -> Result<(), Error> {
+        if self.limits == LimitsConfig::default() {
+            return Ok(());
+        }
+        let records = self.get_all()?.len() + adding;
+        let bytes = self.file.metadata()?.len();
+        if self.limits.allows(records, bytes) {
+            Ok(())
+        } else {
+            warn!(
+                "The guest list is full at {} bytes, turning away {} new records",
+                bytes, adding
+            );
+            Err(Error::Full)
+        }
+    }
+
+    /// What to tell the admin once the file is close to `limits`, if anything
+    pub fn limits_warning(&self) -> Result<Option<String>, Error> {
+        let records = self.get_all()?.len();
+        let bytes = self.file.metadata()?.len();
+        Ok(self.limits.warning(records, bytes))
+    }
+
+    fn check_meal_limits(&self, params: &RsvpParams) -> Result<(), Error> {
+        if self.meal_limits.is_empty() {
+            return Ok(());
+        }
+        let mut counts = self.meal_counts(&params.name)?;
+        let record = RsvpModel::new_with_rsvp(params, self.now());
+        for meal in record.meals() {
+            let count = counts.entry(meal.to_string()).or_default();
+            *count += 1;
+            if self
+                .meal_limits
+                .get(meal)
+                .is_some_and(|limit| *count > *limit)
+            {
+                error!("No {} meals left for {:?}", meal, params);
+                return Err(Error::MealFull(meal.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the guest is asked about the private event
+    pub fn invited_to_private(&self, name: &str) -> Result<bool, Error> {
+        let private_event = &self.private_event.clone();
+        Ok(self
+            .get(name)?
+            .is_some_and(|record| record.invited_to(private_event)))
+    }
+
+    /// Whether the party is asked about childcare
+    pub fn has_children(&self, name: &str) -> Result<bool, Error> {
+        let childcare = &self.childcare.clone();
+        Ok(self
+            .get(name)?
+            .is_some_and(|record| record.has_children(childcare)))
+    }
+
+    /// The answers without those to questions the guest isn't asked, the
+    /// private event's unless they're invited and childcare's unless they
+    /// have children, so nobody else can end up on those lists
+    fn drop_unasked(&self, params: &RsvpParams) -> Result<RsvpParams, Error> {
+        let mut params = params.clone();
+        if !self.invited_to_private(&params.name)? {
+            params.attending_private = false;
+            params.plus_one_attending_private = false;
+        }
+        if self.has_children(&params.name)? {
+            params.kids = params.kids.min(self.childcare.max_kids);
+        } else {
+            params.kids = 0;
+            params.kids_ages.clear();
+        }
+        Ok(params)
+    }
+
+    /// Turn away a shuttle stop that isn't offered, or that doesn't have
+    /// room for the whole party
+    fn check_shuttle(&self, params: &RsvpParams) -> Result<(), Error> {
+        let name = params.shuttle.trim();
+        if name.is_empty() {
+            return Ok(());
+        }
+        let stop = match self.shuttle_stops.iter().find(|stop| stop.name == name) {
+            Some(stop) => stop.clone(),
+            None => {
+                return Err(Error::Invalid(
+                    "Please choose one of the shuttle pickups".to_string(),
+                ))
+            }
+        };
+        if stop.capacity == 0 {
+            return Ok(());
+        }
+        let taken = self
+            .shuttle_counts(&params.name)?
+            .get(&stop.name)
+            .copied()
+            .unwrap_or(0);
+        let riders = RsvpModel::new_with_rsvp(params, self.now()).shuttle_riders();
+        if taken + riders > stop.capacity {
+            error!("No room on the {} shuttle for {:?}", stop.name, params);
+            return Err(Error::ShuttleFull(stop.title));
+        }
+        Ok(())
     }
 
     /// Get the current attendance numbers
-    pub fn attendance(&mut self) -> Result<Attendance, Error> {
-        self.file.seek(SeekFrom::Start(0))?;
-        let mut reader = ReaderBuilder::new()
-            .has_headers(true)
-            .from_reader(&self.file);
+    #[instrument(skip_all)]
+    pub fn attendance(&self) -> Result<Attendance, Error> {
         let mut attendance = Attendance::default();
-        for result in reader.deserialize() {
-            let rsvp: RsvpModel = result?;
-            let number_attending = if rsvp.plus_one_attending { 2 } else { 1 };
+        for rsvp in self.get_all()? {
             if rsvp.attending {
-                attendance.attending += number_attending;
+                attendance.attending += 1 + u32::from(rsvp.plus_one_attending);
             }
             if rsvp.attending_secondary {
-                attendance.attending_secondary += number_attending;
+                attendance.attending_secondary += 1 + u32::from(rsvp.plus_one_attending_secondary);
             }
             if rsvp.attending_tertiary {
-                attendance.attending_tertiary += number_attending;
+                attendance.attending_tertiary += 1 + u32::from(rsvp.plus_one_attending_tertiary);
+            }
+            if rsvp.attending_private {
+                attendance.attending_private += 1 + u32::from(rsvp.plus_one_attending_private);
             }
         }
         Ok(attendance)
     }
 
+    /// Compare the seats invited with the responses so far
+    #[instrument(skip_all)]
+    pub fn headcount(&self) -> Result<Headcount, Error> {
+        let mut headcount = Headcount::default();
+        for record in self.get_all()? {
+            headcount.parties += 1;
+            headcount.invited += record.invited;
+            if record.responded_at.is_some() {
+                headcount.responded_parties += 1;
+                headcount.responded += record.invited;
+                headcount.attending += u32::from(record.is_attending_any())
+                    + u32::from(record.plus_one_attending_any());
+            } else {
+                headcount.uncertain += record.invited;
+            }
+        }
+        Ok(headcount)
+    }
+
     /// Doesn't implement ToString because it requires a `&mut self`
     pub fn dump(&mut self) -> String {
         self.file.seek(SeekFrom::Start(0)).unwrap();
@@ -174,6 +823,34 @@ impl CsvDb {
         writeln!(self.file, "{}", HEADER_LINE).unwrap();
     }
 }
+type Filter = Box<dyn Fn(&RsvpModel) -> bool>;
+
+/// Parse a `field:value` condition for `list`
+fn parse_filter(filter: &str) -> Result<Filter, Error> {
+    let invalid = || Error::Query(format!("can't filter by {:?}", filter));
+    let (field, value) = filter.split_once(':').ok_or_else(invalid)?;
+    let value = value.trim().to_string();
+    if field == "tag" {
+        return Ok(Box::new(move |r| r.has_tag(&value)));
+    }
+    let flag: fn(&RsvpModel) -> bool = match field {
+        "attending" => |r| r.attending,
+        "attending_secondary" => |r| r.attending_secondary,
+        "attending_tertiary" => |r| r.attending_tertiary,
+        "attending_private" => |r| r.attending_private,
+        "attending_any" => RsvpModel::is_attending_any,
+        "plus_one_attending" => |r| r.plus_one_attending,
+        "plus_one_attending_secondary" => |r| r.plus_one_attending_secondary,
+        "plus_one_attending_tertiary" => |r| r.plus_one_attending_tertiary,
+        "plus_one_attending_private" => |r| r.plus_one_attending_private,
+        "email_verified" => |r| r.email_verified,
+        "checked_in" => |r| r.checked_in_at.is_some(),
+        _ => return Err(invalid()),
+    };
+    let expected = value.parse::<bool>().map_err(|_| invalid())?;
+    Ok(Box::new(move |r| flag(r) == expected))
+}
+
 impl Default for CsvDb {
     fn default() -> Self {
         let mut db = CsvDb::new(tempfile().unwrap());
@@ -184,7 +861,10 @@ impl Default for CsvDb {
 
 #[cfg(test)]
 pub mod test {
-    use super::*;
+    use {
+        super::*,
+        crate::{clock::MockClock, config::GitConfig, dietary::Severity},
+    };
 
     pub fn test_db(num: usize) -> CsvDb {
         let mut db = CsvDb::new(tempfile().unwrap());
@@ -201,6 +881,10 @@ pub mod test {
             name: "John".to_string(),
             email: "john@john.john".to_string(),
             plus_one_name: "Johnson".to_string(),
+            tags: "family".to_string(),
+            invited: None,
+            language: String::default(),
+            wave: String::default(),
         }
     }
 
@@ -214,10 +898,25 @@ pub mod test {
             meal_choice: "Fish".to_string(),
             dietary_restrictions: "Yes".to_string(),
             plus_one_attending: true,
+            plus_one_attending_secondary: true,
+            plus_one_attending_tertiary: false,
             plus_one_name: "Johnson".to_string(),
             plus_one_meal_choice: "Veggies".to_string(),
             plus_one_dietary_restrictions: "No".to_string(),
             comments: "Can't wait!".to_string(),
+            language: String::default(),
+            dietary: String::default(),
+            plus_one_dietary: String::default(),
+            dietary_severity: Severity::default(),
+            plus_one_dietary_severity: Severity::default(),
+            source: None,
+            plus_one_email: String::default(),
+            needs_lodging: false,
+            shuttle: String::default(),
+            attending_private: false,
+            plus_one_attending_private: false,
+            kids: 0,
+            kids_ages: String::default(),
         }
     }
 
@@ -232,18 +931,55 @@ pub mod test {
                 meal_choice: "Meat".to_string(),
                 dietary_restrictions: "".to_string(),
                 plus_one_attending: n % 2 == 0,
+                plus_one_attending_secondary: n % 2 == 0,
+                plus_one_attending_tertiary: n % 2 == 0,
                 plus_one_name: format!("Johnson-{}", n),
                 plus_one_meal_choice: "Veggie".to_string(),
                 plus_one_dietary_restrictions: "Vegetarian".to_string(),
                 comments: format!("{} comments!", n),
+                language: String::default(),
+                dietary: String::default(),
+                plus_one_dietary: String::default(),
+                dietary_severity: Severity::default(),
+                plus_one_dietary_severity: Severity::default(),
+                source: None,
+                plus_one_email: String::default(),
+                needs_lodging: false,
+                shuttle: String::default(),
+                attending_private: false,
+                plus_one_attending_private: false,
+                kids: 0,
+                kids_ages: String::default(),
             })
             .collect()
     }
 
+    #[test]
+    fn find_duplicate() {
+        let datetime = Utc::now();
+        let clock = Arc::new(MockClock::new(datetime));
+        let mut db = CsvDb::new_with_clock(tempfile().unwrap(), clock.clone());
+        db.add_header();
+        let rsvp = test_rsvp();
+        let window = Duration::seconds(60);
+        assert!(db.find_duplicate(&rsvp, window).unwrap().is_none());
+        db.upsert(&rsvp).unwrap();
+        clock.set(datetime + Duration::seconds(30));
+        assert!(db.find_duplicate(&rsvp, window).unwrap().is_some());
+        let changed = RsvpParams {
+            comments: "Changed my mind".to_string(),
+            ..rsvp.clone()
+        };
+        assert!(db.find_duplicate(&changed, window).unwrap().is_none());
+        clock.set(datetime + Duration::seconds(90));
+        assert!(db.find_duplicate(&rsvp, window).unwrap().is_none());
+    }
+
     #[test]
     fn insert() {
         let datetime = Utc::now();
-        let mut db = CsvDb::new_with_time(tempfile().unwrap(), datetime);
+        let clock = Arc::new(MockClock::new(datetime));
+        let mut db = CsvDb::new_with_clock(tempfile().unwrap(), clock.clone());
         db.add_header();
         let add = test_add();
         let model = db.insert(&add).unwrap();
@@ -251,7 +987,7 @@ pub mod test {
         let contents = db.dump();
         assert_eq!(
             format!(
-                "{}\n{},{},{},{},{},{},{},{},{},{},{},{},{:?},{:?}\n",
+                "{}\n{},{},{},{},{},{},{},{},{},{},{},{},{:?},{:?},false,,ok,{},{},false,false,,,{},false,false,{},,,{},,,,preference,preference,,,,false,,,false,false,0,,false,\n",
                 HEADER_LINE,
                 model.name,
                 model.email,
@@ -266,15 +1002,24 @@ pub mod test {
                 model.plus_one_dietary_restrictions,
                 model.comments,
                 datetime,
-                datetime
+                datetime,
+                model.token,
+                model.tags,
+                model.name_key,
+                model.invited,
+                model.short_code,
             ),
             contents
         );
 
         let all_records = db.get_all().unwrap();
         assert_eq!(all_records.len(), 1);
-        let test_record = RsvpModel::new_with_add(&add, datetime);
+        let mut test_record = RsvpModel::new_with_add(&add, datetime);
+        test_record.token = model.token.clone();
+        test_record.short_code = model.short_code.clone();
         assert_eq!(all_records[0], test_record);
+        assert_eq!(db.find_by_token(&model.token).unwrap(), Some(test_record));
+        assert!(db.find_by_token("").unwrap().is_none());
         assert!(db.remove(&add.name).unwrap().is_some());
         assert!(db.remove("Blah").unwrap().is_none());
         assert_eq!(db.attendance().unwrap(), Attendance::default());
@@ -283,15 +1028,16 @@ pub mod test {
     #[test]
     fn upsert_one() {
         let datetime = Utc::now();
-        let mut db = CsvDb::new_with_time(tempfile().unwrap(), datetime);
+        let clock = Arc::new(MockClock::new(datetime));
+        let mut db = CsvDb::new_with_clock(tempfile().unwrap(), clock.clone());
         db.add_header();
         let rsvp = test_rsvp();
-        db.upsert(&rsvp).unwrap();
+        let model = db.upsert(&rsvp).unwrap();
 
         let contents = db.dump();
         assert_eq!(
             format!(
-                "{}\n{},{},{},{},{},{},{},{},{},{},{},{},{:?},{:?}\n",
+                "{}\n{},{},{},{},{},{},{},{},{},{},{},{},{:?},{:?},false,,ok,{},,false,false,,,{},{},{},2,{:?},,{},,,,preference,preference,,,,false,,,false,false,0,,false,\n",
                 HEADER_LINE,
                 rsvp.name,
                 rsvp.email,
@@ -306,14 +1052,22 @@ pub mod test {
                 rsvp.plus_one_dietary_restrictions,
                 rsvp.comments,
                 datetime,
-                datetime
+                datetime,
+                model.token,
+                model.name_key,
+                rsvp.plus_one_attending_secondary,
+                rsvp.plus_one_attending_tertiary,
+                datetime,
+                model.short_code,
             ),
             contents
         );
 
         let all_records = db.get_all().unwrap();
         assert_eq!(all_records.len(), 1);
-        let test_record = RsvpModel::new_with_rsvp(&test_rsvp(), datetime);
+        let mut test_record = RsvpModel::new_with_rsvp(&test_rsvp(), datetime);
+        test_record.token = model.token;
+        test_record.short_code = model.short_code;
         assert_eq!(all_records[0], test_record);
         assert!(db.remove(&test_rsvp().name).unwrap().is_some());
         assert!(db.remove("Blah").unwrap().is_none());
@@ -344,10 +1098,25 @@ pub mod test {
             meal_choice: "".to_string(),
             dietary_restrictions: "".to_string(),
             plus_one_attending: false,
+            plus_one_attending_secondary: false,
+            plus_one_attending_tertiary: false,
             plus_one_name: "".to_string(),
             plus_one_meal_choice: "".to_string(),
             plus_one_dietary_restrictions: "".to_string(),
             comments: "No comment.".to_string(),
+            language: String::default(),
+            dietary: String::default(),
+            plus_one_dietary: String::default(),
+            dietary_severity: Severity::default(),
+            plus_one_dietary_severity: Severity::default(),
+            source: None,
+            plus_one_email: String::default(),
+            needs_lodging: false,
+            shuttle: String::default(),
+            attending_private: false,
+            plus_one_attending_private: false,
+            kids: 0,
+            kids_ages: String::default(),
         };
         db.upsert(&updated).unwrap();
 
@@ -358,21 +1127,28 @@ pub mod test {
 
         let mut attendance = Attendance::default();
         for record in all_records {
-            let number_attending = if record.plus_one_attending { 2 } else { 1 };
             if record.attending {
-                attendance.attending += number_attending;
+                attendance.attending += if record.plus_one_attending { 2 } else { 1 };
             }
             if record.attending_secondary {
-                attendance.attending_secondary += number_attending;
+                attendance.attending_secondary += if record.plus_one_attending_secondary {
+                    2
+                } else {
+                    1
+                };
             }
             if record.attending_tertiary {
-                attendance.attending_tertiary += number_attending;
+                attendance.attending_tertiary += if record.plus_one_attending_tertiary {
+                    2
+                } else {
+                    1
+                };
             }
         }
         assert_eq!(db.attendance().unwrap(), attendance);
     }
 
-    fn check_name(name: &str) {
+    fn check_name(name: &str, expected: &str) {
         let mut db = CsvDb::default();
         db.upsert(&RsvpParams {
             name: name.to_string(),
@@ -383,28 +1159,76 @@ pub mod test {
             meal_choice: "".to_string(),
             dietary_restrictions: "".to_string(),
             plus_one_attending: false,
+            plus_one_attending_secondary: false,
+            plus_one_attending_tertiary: false,
             plus_one_name: "".to_string(),
             plus_one_meal_choice: "".to_string(),
             plus_one_dietary_restrictions: "".to_string(),
             comments: "No comment.".to_string(),
+            language: String::default(),
+            dietary: String::default(),
+            plus_one_dietary: String::default(),
+            dietary_severity: Severity::default(),
+            plus_one_dietary_severity: Severity::default(),
+            source: None,
+            plus_one_email: String::default(),
+            needs_lodging: false,
+            shuttle: String::default(),
+            attending_private: false,
+            plus_one_attending_private: false,
+            kids: 0,
+            kids_ages: String::default(),
         })
         .unwrap();
         let all_records = db.get_all().unwrap();
         assert_eq!(all_records.len(), 1);
-        assert_eq!(all_records[0].name, name);
+        assert_eq!(all_records[0].name, expected);
     }
 
     #[test]
     fn weird_chars() {
-        check_name("comma,");
-        check_name("newline\n");
-        check_name("newline,and comma\n");
+        check_name("comma,", "comma,");
+        check_name("newline\n", "newline");
+        check_name("newline,and comma\n", "newline,and comma");
+        check_name("quote\"", "quote\"");
+    }
+
+    #[test]
+    fn normalized_names() {
+        let mut db = CsvDb::default();
+        let rsvp = RsvpParams {
+            name: "  Jo\u{308}rg   van  Dijk ".to_string(),
+            ..test_rsvp()
+        };
+        let model = db.upsert(&rsvp).unwrap();
+        assert_eq!(model.name, "J\u{f6}rg van Dijk");
+        assert_eq!(model.name_key, "j\u{f6}rg van dijk");
+
+        let found = db.get("J\u{f6}RG VAN DIJK").unwrap().unwrap();
+        assert_eq!(found.name, model.name);
+        assert!(db.get(" & ").unwrap().is_none());
+
+        let rsvp = RsvpParams {
+            name: "j\u{f6}rg van dijk".to_string(),
+            comments: "Changed my mind".to_string(),
+            ..test_rsvp()
+        };
+        let updated = db.upsert(&rsvp).unwrap();
+        assert_eq!(updated.name, "J\u{f6}rg van Dijk");
+        assert_eq!(db.get_all().unwrap().len(), 1);
+        assert!(db
+            .find_duplicate(&rsvp, Duration::seconds(60))
+            .unwrap()
+            .is_some());
+        assert!(db.remove("JORG VAN DIJK").unwrap().is_none());
+        assert!(db.remove("J\u{f6}rg  van Dijk").unwrap().is_some());
     }
 
     #[test]
     fn get() {
         let datetime = Utc::now();
-        let mut db = CsvDb::new_with_time(tempfile().unwrap(), datetime);
+        let clock = Arc::new(MockClock::new(datetime));
+        let mut db = CsvDb::new_with_clock(tempfile().unwrap(), clock.clone());
         db.add_header();
         let rsvp = test_rsvp();
         db.upsert(&rsvp).unwrap();
@@ -417,5 +1241,587 @@ pub mod test {
         db.get(&format!(" {} & {} ", rsvp.name, rsvp.plus_one_name))
             .unwrap()
             .unwrap();
+
+        // lookups share the file without moving its cursor, so writes after
+        // them still go at the end
+        let shared = &db;
+        let found = std::thread::scope(|scope| {
+            let lookups = (0..4)
+                .map(|_| scope.spawn(|| shared.get(&rsvp.name).unwrap()))
+                .collect::<Vec<_>>();
+            lookups
+                .into_iter()
+                .map(|lookup| lookup.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert!(found.iter().all(Option::is_some));
+        db.insert(&AddParams {
+            name: "Jane".to_string(),
+            ..test_add()
+        })
+        .unwrap();
+        assert_eq!(db.get_all().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn update_with() {
+        let mut db = test_db(3);
+        let record = db
+            .update_with("john-1", |r| r.email_verified = true)
+            .unwrap()
+            .unwrap();
+        assert!(record.email_verified);
+        let all_records = db.get_all().unwrap();
+        assert_eq!(all_records.len(), 3);
+        assert_eq!(all_records[2], record);
+        assert!(db.update_with("Blah", |_| {}).unwrap().is_none());
+
+        db.update_with("john-1", |r| r.notes = "Call first".to_string())
+            .unwrap();
+        let rsvp = RsvpParams::from(&record);
+        assert_eq!(db.upsert(&rsvp).unwrap().notes, "Call first");
+    }
+
+    #[test]
+    fn migrate() {
+        let old_header = "name,email,attending,attending_secondary,attending_tertiary,meal_choice,dietary_restrictions,plus_one_attending,plus_one_name,plus_one_meal_choice,plus_one_dietary_restrictions,comments,created_at,updated_at";
+        let mut file = tempfile().unwrap();
+        writeln!(file, "{}", old_header).unwrap();
+        writeln!(
+            file,
+            "Test,test@test.com,true,false,false,Fish,,true,,Fish,,,2022-03-01T00:00:00Z,2022-03-01T00:00:00Z"
+        )
+        .unwrap();
+        writeln!(
+            file,
+            "Other,,false,true,true,Meat,,true,,Fish,,,2022-03-01T00:00:00Z,2022-03-01T00:00:00Z"
+        )
+        .unwrap();
+        writeln!(
+            file,
+            "Fresh,,false,false,false,,,false,Pal,,,,2022-03-01T00:00:00Z,2022-03-01T00:00:00Z"
+        )
+        .unwrap();
+        let mut db = CsvDb::new(file);
+        db.migrate().unwrap();
+        assert!(db.dump().starts_with(HEADER_LINE));
+        let all_records = db.get_all().unwrap();
+        assert_eq!(all_records.len(), 3);
+        assert!(!all_records[0].email_verified);
+        assert_eq!(all_records[0].token.len(), 16);
+        assert_eq!(all_records[0].name_key, "test");
+        assert!(all_records[0].plus_one_attending);
+        assert!(!all_records[0].plus_one_attending_secondary);
+        assert!(!all_records[1].plus_one_attending);
+        assert!(all_records[1].plus_one_attending_secondary);
+        assert!(all_records[1].plus_one_attending_tertiary);
+        assert_eq!(all_records[0].invited, 2);
+        assert!(all_records[1].responded_at.is_some());
+        assert_eq!(all_records[2].invited, 2);
+        assert!(all_records[2].responded_at.is_none());
+
+        let mut db = CsvDb::new(tempfile().unwrap());
+        db.migrate().unwrap();
+        assert_eq!(db.dump(), format!("{}\n", HEADER_LINE));
+    }
+
+    #[test]
+    fn short_codes() {
+        // every one-character code is taken, so the next is longer
+        let taken = "ABCDEFGHJKLMNPQRSTUVWXYZ23456789"
+            .chars()
+            .map(String::from)
+            .collect();
+        assert_eq!(new_short_code(1, &taken).len(), 2);
+
+        let mut db = test_db(20);
+        let records = db.get_all().unwrap();
+        let codes = records
+            .iter()
+            .map(|r| r.short_code.clone())
+            .collect::<HashSet<_>>();
+        assert_eq!(codes.len(), 20);
+        assert!(codes.iter().all(|code| code.len() == 6));
+        let code = records[3].short_code.to_lowercase();
+        let found = db.find_by_short_code(&code).unwrap().unwrap();
+        assert_eq!(found.name, records[3].name);
+        assert!(db.find_by_short_code("").unwrap().is_none());
+
+        // added records keep their codes unless someone has them already
+        let kept = RsvpModel {
+            name: "Kept".to_string(),
+            name_key: name_key("Kept"),
+            short_code: "KEPT22".to_string(),
+            ..records[0].clone()
+        };
+        let clash = RsvpModel {
+            name: "Clash".to_string(),
+            name_key: name_key("Clash"),
+            ..records[0].clone()
+        };
+        db.append(&[kept, clash]).unwrap();
+        assert_eq!(db.get("Kept").unwrap().unwrap().short_code, "KEPT22");
+        let clash = db.get("Clash").unwrap().unwrap().short_code;
+        assert!(!clash.is_empty() && clash != records[0].short_code);
+
+        // as do files from before short codes
+        let mut db = CsvDb::new(tempfile().unwrap());
+        db.add_header();
+        db.upsert(&test_rsvp()).unwrap();
+        let dump = db.dump();
+        let mut reader = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(dump.as_bytes());
+        let rows = reader.records().collect::<Result<Vec<_>, _>>().unwrap();
+        let column = rows[0].iter().position(|h| h == "short_code").unwrap();
+        let mut wtr = WriterBuilder::new().from_writer(vec![]);
+        for row in rows {
+            let row = row
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != column)
+                .map(|(_, field)| field);
+            wtr.write_record(row).unwrap();
+        }
+        let mut file = tempfile().unwrap();
+        file.write_all(&wtr.into_inner().unwrap()).unwrap();
+        let mut db = CsvDb::new(file);
+        db.short_code_length = 4;
+        db.migrate().unwrap();
+        assert_eq!(db.get("John").unwrap().unwrap().short_code.len(), 4);
+    }
+
+    #[test]
+    fn list() {
+        let db = test_db(10);
+        let page = db
+            .list(&ListParams {
+                page: 2,
+                per_page: 2,
+                sort: "-name".to_string(),
+                filter: "attending:true".to_string(),
+                ..ListParams::default()
+            })
+            .unwrap();
+        let names = page
+            .items
+            .iter()
+            .map(|r| r.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["John-4", "John-2"]);
+        assert_eq!((page.total, page.pages), (5, 3));
+
+        let page = db
+            .list(&ListParams {
+                filter: "attending:false, attending_secondary:true".to_string(),
+                ..ListParams::default()
+            })
+            .unwrap();
+        let names = page
+            .items
+            .iter()
+            .map(|r| r.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["John-3", "John-9"]);
+
+        let page = db
+            .list(&ListParams {
+                page: 5,
+                ..ListParams::default()
+            })
+            .unwrap();
+        assert!(page.items.is_empty());
+        assert_eq!((page.total, page.pages), (10, 1));
+        let page = db
+            .list(&ListParams {
+                page: usize::MAX,
+                per_page: 2,
+                ..ListParams::default()
+            })
+            .unwrap();
+        assert!(page.items.is_empty());
+
+        for params in [
+            ListParams {
+                sort: "token".to_string(),
+                ..ListParams::default()
+            },
+            ListParams {
+                filter: "attending:maybe".to_string(),
+                ..ListParams::default()
+            },
+            ListParams {
+                page: 0,
+                ..ListParams::default()
+            },
+        ] {
+            assert!(matches!(db.list(&params), Err(Error::Query(_))));
+        }
+    }
+
+    #[test]
+    fn search() {
+        let mut db = test_db(3);
+        db.upsert(&RsvpParams {
+            name: "Linh Nguy\u{1ec5}n".to_string(),
+            plus_one_name: "Minh".to_string(),
+            comments: "See you there".to_string(),
+            ..test_rsvp()
+        })
+        .unwrap();
+        let names =
+            |records: Vec<RsvpModel>| records.into_iter().map(|r| r.name).collect::<Vec<_>>();
+        assert_eq!(
+            names(db.search("NGUYEN").unwrap()),
+            vec!["Linh Nguy\u{1ec5}n"]
+        );
+        assert_eq!(
+            names(db.search("minh").unwrap()),
+            vec!["Linh Nguy\u{1ec5}n"]
+        );
+        assert_eq!(names(db.search("john2@").unwrap()), vec!["John-2"]);
+        assert_eq!(db.search("you there").unwrap().len(), 1);
+        assert!(db.search("nobody").unwrap().is_empty());
+        assert!(matches!(db.search("  "), Err(Error::Query(_))));
+
+        let page = db
+            .list(&ListParams {
+                q: "nguyen".to_string(),
+                ..ListParams::default()
+            })
+            .unwrap();
+        assert_eq!(page.total, 1);
+    }
+
+    #[test]
+    fn meal_limits() {
+        let mut db = test_db(2);
+        db.meal_limits.insert("Fish".to_string(), 1);
+        assert_eq!(db.meal_counts("").unwrap().get("Meat"), Some(&1));
+        assert_eq!(db.meal_counts("John-0").unwrap().get("Meat"), None);
+        assert!(!db.meals_full("John").unwrap()["Fish"]);
+
+        db.upsert(&test_rsvp()).unwrap();
+        assert!(db.meals_full("Someone").unwrap()["Fish"]);
+        assert!(!db.meals_full("John").unwrap()["Fish"]);
+        assert!(!db.meals_full("Someone").unwrap()["Meat"]);
+
+        let rsvp = RsvpParams {
+            name: "Someone".to_string(),
+            ..test_rsvp()
+        };
+        assert!(matches!(db.upsert(&rsvp), Err(Error::MealFull(meal)) if meal == "Fish"));
+        assert!(db.get("Someone").unwrap().is_none());
+
+        // plus-ones count too, but not guests who aren't coming
+        let rsvp = RsvpParams {
+            meal_choice: "Meat".to_string(),
+            plus_one_meal_choice: "Fish".to_string(),
+            ..rsvp
+        };
+        assert!(matches!(db.upsert(&rsvp), Err(Error::MealFull(_))));
+        let rsvp = RsvpParams {
+            attending: false,
+            attending_secondary: false,
+            ..rsvp
+        };
+        db.upsert(&rsvp).unwrap();
+        db.upsert(&test_rsvp()).unwrap();
+    }
+
+    #[test]
+    fn private_event() {
+        let mut db = test_db(0);
+        db.private_event.tag = "rehearsal".to_string();
+        db.insert(&AddParams {
+            tags: "family, Rehearsal".to_string(),
+            ..test_add()
+        })
+        .unwrap();
+        let rsvp = |name: &str| RsvpParams {
+            name: name.to_string(),
+            attending_private: true,
+            plus_one_attending_private: true,
+            ..test_rsvp()
+        };
+        assert!(db.invited_to_private("John").unwrap());
+        let record = db.upsert(&rsvp("John")).unwrap();
+        assert!(record.attending_private && record.plus_one_attending_private);
+
+        // answers from anyone else are dropped
+        assert!(!db.invited_to_private("Jane").unwrap());
+        let record = db.upsert(&rsvp("Jane")).unwrap();
+        assert!(!record.attending_private && !record.plus_one_attending_private);
+        assert_eq!(db.attendance().unwrap().attending_private, 2);
+
+        db.private_event.tag.clear();
+        assert!(!db.invited_to_private("John").unwrap());
+    }
+
+    #[test]
+    fn shuttle_capacity() {
+        let mut db = test_db(0);
+        db.shuttle_stops = vec![ShuttleStop {
+            name: "downtown".to_string(),
+            title: "Downtown".to_string(),
+            capacity: 3,
+            ..ShuttleStop::default()
+        }];
+        let rsvp = RsvpParams {
+            name: "Jane".to_string(),
+            shuttle: "downtown".to_string(),
+            ..test_rsvp()
+        };
+        assert_eq!(db.upsert(&rsvp).unwrap().shuttle_riders(), 2);
+
+        // the whole party has to fit
+        let john = RsvpParams {
+            shuttle: "downtown".to_string(),
+            ..test_rsvp()
+        };
+        assert!(matches!(db.upsert(&john), Err(Error::ShuttleFull(stop)) if stop == "Downtown"));
+        assert!(db.get("John").unwrap().is_none());
+        let john = RsvpParams {
+            plus_one_attending: false,
+            plus_one_attending_secondary: false,
+            ..john
+        };
+        db.upsert(&john).unwrap();
+
+        // changing their own answer doesn't count against them
+        db.upsert(&rsvp).unwrap();
+        assert_eq!(db.shuttle_counts("Jane").unwrap()["downtown"], 1);
+        let rsvp = RsvpParams {
+            shuttle: "pier".to_string(),
+            ..rsvp
+        };
+        assert!(matches!(db.upsert(&rsvp), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn limits() {
+        let mut db = test_db(2);
+        db.limits.max_records = Some(3);
+        assert_eq!(db.limits_warning().unwrap(), None);
+        db.upsert(&test_rsvp()).unwrap();
+        assert!(db.limits_warning().unwrap().unwrap().contains("3 of the 3"));
+
+        let rsvp = RsvpParams {
+            name: "Someone".to_string(),
+            ..test_rsvp()
+        };
+        assert!(matches!(db.upsert(&rsvp), Err(Error::Full)));
+        let add = AddParams {
+            name: "Someone".to_string(),
+            ..test_add()
+        };
+        assert!(matches!(db.insert(&add), Err(Error::Full)));
+        assert!(db.get("Someone").unwrap().is_none());
+        // guests already on the list can still change their RSVP
+        db.upsert(&RsvpParams {
+            comments: "Changed my mind".to_string(),
+            ..test_rsvp()
+        })
+        .unwrap();
+
+        db.limits.max_records = None;
+        db.limits.max_file_bytes = Some(db.file.metadata().unwrap().len());
+        assert!(matches!(db.upsert(&rsvp), Err(Error::Full)));
+        db.limits.max_file_bytes = Some(1_000_000);
+        db.upsert(&rsvp).unwrap();
+    }
+
+    #[test]
+    fn declines() {
+        let mut db = CsvDb::default();
+        db.upsert(&test_rsvp()).unwrap();
+        let declined = RsvpParams {
+            attending: false,
+            attending_secondary: false,
+            ..test_rsvp()
+        };
+        let record = db.upsert(&declined).unwrap();
+        assert!(record.meal_choice.is_empty());
+        assert!(record.dietary_restrictions.is_empty());
+        assert!(!record.plus_one_attending);
+        assert!(record.plus_one_name.is_empty());
+        assert!(record.plus_one_meal_choice.is_empty());
+        assert!(record.plus_one_dietary_restrictions.is_empty());
+        assert_eq!(record.comments, declined.comments);
+        assert_eq!(db.attendance().unwrap(), Attendance::default());
+
+        // rows written before declines were cleared still count as nobody
+        let mut legacy = record;
+        legacy.plus_one_attending = true;
+        db.rewrite(&[legacy]).unwrap();
+        assert_eq!(db.attendance().unwrap(), Attendance::default());
+    }
+
+    #[test]
+    fn headcount() {
+        let mut db = CsvDb::default();
+        db.insert(&test_add()).unwrap();
+        db.insert(&AddParams {
+            name: "Family".to_string(),
+            invited: Some(4),
+            ..test_add()
+        })
+        .unwrap();
+        db.insert(&AddParams {
+            name: "Solo".to_string(),
+            plus_one_name: String::default(),
+            ..test_add()
+        })
+        .unwrap();
+        db.upsert(&RsvpParams {
+            plus_one_attending: false,
+            plus_one_attending_secondary: false,
+            ..test_rsvp()
+        })
+        .unwrap();
+        db.upsert(&RsvpParams {
+            name: "Solo".to_string(),
+            attending: false,
+            attending_secondary: false,
+            ..test_rsvp()
+        })
+        .unwrap();
+        assert_eq!(
+            db.headcount().unwrap(),
+            Headcount {
+                parties: 3,
+                invited: 7,
+                responded_parties: 2,
+                responded: 3,
+                attending: 1,
+                uncertain: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn undo() {
+        let mut db = test_db(2);
+        let before = db.dump();
+        db.upsert(&test_rsvp()).unwrap();
+        db.update_with("John", |r| r.notes = "VIP".to_string())
+            .unwrap();
+        assert!(db.remove("Nobody").unwrap().is_none());
+        db.meal_limits.insert("Fish".to_string(), 1);
+        assert!(db
+            .upsert(&RsvpParams {
+                name: "John-0".to_string(),
+                meal_choice: "Fish".to_string(),
+                ..test_rsvp()
+            })
+            .is_err());
+        let changes = db
+            .history()
+            .map(|c| (c.action.as_str(), c.name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                ("RSVP", "John-0"),
+                ("RSVP", "John-1"),
+                ("RSVP", "John"),
+                ("change", "John"),
+            ]
+        );
+
+        let change = db.undo().unwrap().unwrap();
+        assert_eq!(change.action, "change");
+        assert_eq!(db.get("John").unwrap().unwrap().notes, "");
+        db.undo().unwrap();
+        assert_eq!(db.dump(), before);
+        assert!(db.get("John").unwrap().is_none());
+
+        // still writable at the end afterwards
+        db.upsert(&test_rsvp()).unwrap();
+        assert_eq!(db.get_all().unwrap().len(), 3);
+
+        db.undo_history = 1;
+        db.upsert(&test_rsvp()).unwrap();
+        db.upsert(&test_rsvp()).unwrap();
+        assert_eq!(db.history().count(), 1);
+        db.undo().unwrap();
+        assert!(db.undo().unwrap().is_none());
+    }
+
+    #[test]
+    fn git_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = GitConfig {
+            dir: Some(dir.path().to_str().unwrap().to_string()),
+            ..GitConfig::default()
+        };
+        let mut db = CsvDb::new(tempfile().unwrap());
+        db.add_header();
+        db.undo_history = 0;
+        db.git = GitHistory::open(&config, "rsvp.csv").unwrap();
+        db.upsert(&test_rsvp()).unwrap();
+        db.update_with("John", |r| r.notes = "VIP".to_string())
+            .unwrap();
+        db.update_with("John", |r| r.notes = "VIP".to_string())
+            .unwrap();
+        let output = std::process::Command::new("git")
+            .args(["log", "--format=%s"])
+            .current_dir(dir.path())
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "change for John\nRSVP for John\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("rsvp.csv")).unwrap(),
+            db.dump()
+        );
+    }
+
+    #[test]
+    fn plus_one_per_event() {
+        let mut db = CsvDb::default();
+        let record = db
+            .upsert(&RsvpParams {
+                attending_tertiary: true,
+                plus_one_attending_secondary: false,
+                plus_one_attending_tertiary: true,
+                ..test_rsvp()
+            })
+            .unwrap();
+        assert!(record.plus_one_attending_any());
+        assert_eq!(
+            db.attendance().unwrap(),
+            Attendance {
+                attending: 2,
+                attending_secondary: 1,
+                attending_tertiary: 2,
+                attending_private: 0,
+            }
+        );
+
+        // the plus-one can't come to an event without the guest
+        let record = db
+            .upsert(&RsvpParams {
+                attending: false,
+                attending_secondary: false,
+                attending_tertiary: true,
+                plus_one_attending: true,
+                plus_one_attending_secondary: true,
+                plus_one_attending_tertiary: false,
+                ..test_rsvp()
+            })
+            .unwrap();
+        assert!(!record.plus_one_attending);
+        assert!(!record.plus_one_attending_secondary);
+        assert!(!record.plus_one_attending_any());
+        assert_eq!(
+            db.attendance().unwrap(),
+            Attendance {
+                attending_tertiary: 1,
+                ..Attendance::default()
+            }
+        );
     }
 }
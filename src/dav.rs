@@ -0,0 +1,507 @@
+use {
+    crate::{
+        admin::validator,
+        calendar,
+        config::Config,
+        feeds::{ics_escape, ics_fold, xml_escape},
+        model::RsvpModel,
+        request::feature_enabled,
+        state::AppState,
+    },
+    actix_web::{
+        http::{
+            header::{ETAG, LOCATION},
+            StatusCode,
+        },
+        web, HttpRequest, HttpResponse, Result,
+    },
+    actix_web_httpauth::middleware::HttpAuthentication,
+    chrono::{DateTime, Utc},
+    sha2::{Digest, Sha256},
+};
+
+/// Where calendar and contacts apps find the admin's principal
+const ROOT: &str = "/dav/";
+
+/// What the read-only endpoints support, for the `DAV` header
+const COMPLIANCE: &str = "1, 3, calendar-access, addressbook";
+
+/// Methods answered, everything else is refused since nothing here can be
+/// changed from a calendar or contacts app
+const ALLOW: &str = "OPTIONS, GET, HEAD, PROPFIND, REPORT";
+
+/// Namespaces used in every response
+const NAMESPACES: &str = concat!(
+    "xmlns:D=\"DAV:\" ",
+    "xmlns:C=\"urn:ietf:params:xml:ns:caldav\" ",
+    "xmlns:A=\"urn:ietf:params:xml:ns:carddav\" ",
+    "xmlns:CS=\"http://calendarserver.org/ns/\"",
+);
+
+/// Read-only CalDAV and CardDAV for the admin, so the events and guests
+/// coming show up in their own calendar and contacts apps
+pub fn dav_config(config: &mut web::ServiceConfig) {
+    config
+        .service(
+            web::resource(["/.well-known/caldav", "/.well-known/carddav"])
+                .guard(feature_enabled(|features| features.admin))
+                .to(well_known),
+        )
+        .service(
+            web::scope("/dav")
+                .guard(feature_enabled(|features| features.admin))
+                .wrap(HttpAuthentication::basic(validator))
+                .default_service(web::to(dav)),
+        );
+}
+
+/// Send apps looking for a calendar or address book to the principal
+async fn well_known() -> HttpResponse {
+    HttpResponse::MovedPermanently()
+        .insert_header((LOCATION, ROOT))
+        .finish()
+}
+
+/// One of the two collections, the events calendar or the guests address
+/// book
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Collection {
+    Events,
+    Guests,
+}
+impl Collection {
+    const ALL: [Self; 2] = [Self::Events, Self::Guests];
+
+    /// Home the collection is in
+    fn home(self) -> &'static str {
+        match self {
+            Self::Events => "/dav/calendars/",
+            Self::Guests => "/dav/contacts/",
+        }
+    }
+
+    fn path(self) -> &'static str {
+        match self {
+            Self::Events => "/dav/calendars/events/",
+            Self::Guests => "/dav/contacts/guests/",
+        }
+    }
+
+    fn display_name(self) -> &'static str {
+        match self {
+            Self::Events => "Events",
+            Self::Guests => "Guests coming",
+        }
+    }
+
+    fn resource_type(self) -> &'static str {
+        match self {
+            Self::Events => "<D:collection/><C:calendar/>",
+            Self::Guests => "<D:collection/><A:addressbook/>",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Events => "text/calendar; charset=utf-8",
+            Self::Guests => "text/vcard; charset=utf-8",
+        }
+    }
+
+    /// Property a REPORT returns each item's contents in
+    fn data(self) -> &'static str {
+        match self {
+            Self::Events => "C:calendar-data",
+            Self::Guests => "A:address-data",
+        }
+    }
+
+    /// Every event with its times set, with its guests, or every party
+    /// coming to something, as a contact
+    fn items(self, config: &Config, records: &[RsvpModel]) -> Vec<Item> {
+        match self {
+            Self::Events => {
+                let stamp = records
+                    .iter()
+                    .map(|r| r.updated_at)
+                    .max()
+                    .unwrap_or(DateTime::<Utc>::UNIX_EPOCH);
+                (1..=config.events.len())
+                    .filter_map(|number| {
+                        Some(Item::new(
+                            format!("event-{}.ics", number),
+                            calendar::ics_with_guests(config, number, records, stamp)?,
+                        ))
+                    })
+                    .collect()
+            }
+            Self::Guests => records
+                .iter()
+                .filter(|r| r.is_attending_any() && !r.short_code.is_empty())
+                .map(|r| Item::new(format!("{}.vcf", r.short_code), vcard(r)))
+                .collect(),
+        }
+    }
+}
+
+/// An event or contact card in a collection
+struct Item {
+    name: String,
+    body: String,
+}
+impl Item {
+    fn new(name: String, body: String) -> Self {
+        Self { name, body }
+    }
+
+    /// Changes whenever the contents do, so apps only download what changed
+    fn etag(&self) -> String {
+        format!("\"{}\"", &hex::encode(Sha256::digest(&self.body))[..16])
+    }
+}
+
+/// Where a request is, from the principal down to one item
+enum Target<'a> {
+    Root,
+    Home(Collection),
+    Collection(Collection),
+    Item(Collection, &'a str),
+}
+
+fn target(path: &str) -> Option<Target<'_>> {
+    if path == ROOT || path == "/dav" {
+        return Some(Target::Root);
+    }
+    for collection in Collection::ALL {
+        if path == collection.home() || path == collection.home().trim_end_matches('/') {
+            return Some(Target::Home(collection));
+        }
+        if path == collection.path() || path == collection.path().trim_end_matches('/') {
+            return Some(Target::Collection(collection));
+        }
+        if let Some(name) = path.strip_prefix(collection.path()) {
+            return Some(Target::Item(collection, name));
+        }
+    }
+    None
+}
+
+/// Answer calendar and contacts apps, which only ever see the latest
+/// events and guests
+async fn dav(
+    req: HttpRequest,
+    state: web::Data<AppState<'_>>,
+    body: String,
+) -> Result<HttpResponse> {
+    let target = match target(req.path()) {
+        Some(target) => target,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let records = state.write_db().await?.get_all()?;
+    let depth_one = req
+        .headers()
+        .get("Depth")
+        .and_then(|depth| depth.to_str().ok())
+        .is_some_and(|depth| depth != "0");
+    match req.method().as_str() {
+        "OPTIONS" => Ok(HttpResponse::Ok()
+            .insert_header(("DAV", COMPLIANCE))
+            .insert_header(("Allow", ALLOW))
+            .finish()),
+        "GET" | "HEAD" => match target {
+            Target::Item(collection, name) => {
+                let items = collection.items(&state.config, &records);
+                match items.into_iter().find(|item| item.name == name) {
+                    Some(item) => Ok(HttpResponse::Ok()
+                        .content_type(collection.content_type())
+                        .insert_header((ETAG, item.etag()))
+                        .body(item.body)),
+                    None => Ok(HttpResponse::NotFound().finish()),
+                }
+            }
+            _ => Ok(HttpResponse::MethodNotAllowed()
+                .insert_header(("Allow", "OPTIONS, PROPFIND"))
+                .finish()),
+        },
+        "PROPFIND" => {
+            let responses = match target {
+                Target::Root => vec![principal()],
+                Target::Home(home) => {
+                    let mut responses = vec![folder(home.home())];
+                    if depth_one {
+                        responses.push(collection(home, &home.items(&state.config, &records)));
+                    }
+                    responses
+                }
+                Target::Collection(found) => {
+                    let items = found.items(&state.config, &records);
+                    let mut responses = vec![collection(found, &items)];
+                    if depth_one {
+                        responses
+                            .extend(items.iter().map(|item| item_response(found, item, false)));
+                    }
+                    responses
+                }
+                Target::Item(found, name) => {
+                    let items = found.items(&state.config, &records);
+                    match items.iter().find(|item| item.name == name) {
+                        Some(item) => vec![item_response(found, item, false)],
+                        None => return Ok(HttpResponse::NotFound().finish()),
+                    }
+                }
+            };
+            Ok(multistatus(&responses))
+        }
+        "REPORT" => {
+            let found = match target {
+                Target::Collection(found) => found,
+                _ => return Ok(HttpResponse::Forbidden().finish()),
+            };
+            let items = found.items(&state.config, &records);
+            let wanted = hrefs(&body);
+            let responses: Vec<String> = if wanted.is_empty() {
+                items
+                    .iter()
+                    .map(|item| item_response(found, item, true))
+                    .collect()
+            } else {
+                wanted
+                    .iter()
+                    .map(|href| {
+                        let name = href.strip_prefix(found.path()).unwrap_or_default();
+                        match items.iter().find(|item| item.name == name) {
+                            Some(item) => item_response(found, item, true),
+                            None => missing(href),
+                        }
+                    })
+                    .collect()
+            };
+            Ok(multistatus(&responses))
+        }
+        _ => Ok(HttpResponse::Forbidden()
+            .insert_header(("Allow", ALLOW))
+            .body("The calendar and contacts are read-only")),
+    }
+}
+
+fn multistatus(responses: &[String]) -> HttpResponse {
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus {}>\n{}</D:multistatus>\n",
+        NAMESPACES,
+        responses.concat()
+    );
+    HttpResponse::build(StatusCode::MULTI_STATUS)
+        .content_type("application/xml; charset=utf-8")
+        .body(body)
+}
+
+fn response(href: &str, props: &str) -> String {
+    format!(
+        concat!(
+            "<D:response><D:href>{}</D:href>",
+            "<D:propstat><D:prop>{}</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat>",
+            "</D:response>\n",
+        ),
+        xml_escape(href),
+        props
+    )
+}
+
+fn missing(href: &str) -> String {
+    format!(
+        "<D:response><D:href>{}</D:href><D:status>HTTP/1.1 404 Not Found</D:status></D:response>\n",
+        xml_escape(href)
+    )
+}
+
+/// The admin, with where their calendars and address books are
+fn principal() -> String {
+    response(
+        ROOT,
+        &format!(
+            concat!(
+                "<D:resourcetype><D:collection/><D:principal/></D:resourcetype>",
+                "<D:displayname>RSVP admin</D:displayname>",
+                "<D:current-user-principal><D:href>{root}</D:href></D:current-user-principal>",
+                "<D:principal-URL><D:href>{root}</D:href></D:principal-URL>",
+                "<C:calendar-home-set><D:href>{events}</D:href></C:calendar-home-set>",
+                "<A:addressbook-home-set><D:href>{guests}</D:href></A:addressbook-home-set>",
+            ),
+            root = ROOT,
+            events = Collection::Events.home(),
+            guests = Collection::Guests.home(),
+        ),
+    )
+}
+
+fn folder(href: &str) -> String {
+    response(
+        href,
+        &format!(
+            concat!(
+                "<D:resourcetype><D:collection/></D:resourcetype>",
+                "<D:current-user-principal><D:href>{}</D:href></D:current-user-principal>",
+            ),
+            ROOT
+        ),
+    )
+}
+
+/// A collection, whose tag changes whenever any item in it does
+fn collection(collection: Collection, items: &[Item]) -> String {
+    let tags = items.iter().map(Item::etag).collect::<String>();
+    let ctag = &hex::encode(Sha256::digest(tags))[..16];
+    let components = match collection {
+        Collection::Events => concat!(
+            "<C:supported-calendar-component-set><C:comp name=\"VEVENT\"/>",
+            "</C:supported-calendar-component-set>",
+        ),
+        Collection::Guests => "",
+    };
+    response(
+        collection.path(),
+        &format!(
+            concat!(
+                "<D:resourcetype>{}</D:resourcetype>",
+                "<D:displayname>{}</D:displayname>",
+                "<D:current-user-principal><D:href>{}</D:href></D:current-user-principal>",
+                "<D:current-user-privilege-set><D:privilege><D:read/></D:privilege>",
+                "</D:current-user-privilege-set>",
+                "<CS:getctag>{}</CS:getctag>",
+                "<D:sync-token>urn:actix-web-rsvp:sync:{}</D:sync-token>",
+                "{}",
+            ),
+            collection.resource_type(),
+            collection.display_name(),
+            ROOT,
+            ctag,
+            ctag,
+            components
+        ),
+    )
+}
+
+/// An item, with its contents for a REPORT
+fn item_response(collection: Collection, item: &Item, with_data: bool) -> String {
+    let data = if with_data {
+        format!(
+            "<{tag}>{}</{tag}>",
+            xml_escape(&item.body),
+            tag = collection.data()
+        )
+    } else {
+        String::default()
+    };
+    response(
+        &format!("{}{}", collection.path(), item.name),
+        &format!(
+            concat!(
+                "<D:resourcetype/>",
+                "<D:getetag>{}</D:getetag>",
+                "<D:getcontenttype>{}</D:getcontenttype>",
+                "{}",
+            ),
+            xml_escape(&item.etag()),
+            collection.content_type(),
+            data
+        ),
+    )
+}
+
+/// The hrefs a multiget REPORT asks for, whatever their namespace prefix
+fn hrefs(body: &str) -> Vec<String> {
+    body.split('<')
+        .filter_map(|tag| {
+            let (name, text) = tag.split_once('>')?;
+            let name = name.rsplit(':').next()?;
+            (name == "href").then(|| text.trim().to_string())
+        })
+        .filter(|href| !href.is_empty())
+        .collect()
+}
+
+/// A contact card for a party coming to something, with their plus-one,
+/// meal and table in the note
+fn vcard(record: &RsvpModel) -> String {
+    let name = record.name.trim();
+    let (given, family) = match name.rsplit_once(' ') {
+        Some((given, family)) => (given, family),
+        None => (name, ""),
+    };
+    let mut note = vec![];
+    if record.plus_one_attending_any() && !record.plus_one_name.trim().is_empty() {
+        note.push(format!("Plus-one: {}", record.plus_one_name.trim()));
+    }
+    if !record.meal_choice.trim().is_empty() {
+        note.push(format!("Meal: {}", record.meal_choice.trim()));
+    }
+    if !record.dietary_restrictions.trim().is_empty() {
+        note.push(format!("Dietary: {}", record.dietary_restrictions.trim()));
+    }
+    if !record.table.trim().is_empty() {
+        note.push(format!("Table: {}", record.table.trim()));
+    }
+    let mut lines = vec![
+        "BEGIN:VCARD".to_string(),
+        "VERSION:3.0".to_string(),
+        "PRODID:-//actix-web-rsvp//Guests//EN".to_string(),
+        format!("UID:guest-{}@actix-web-rsvp", record.short_code),
+        format!("FN:{}", ics_escape(name)),
+        format!("N:{};{};;;", ics_escape(family), ics_escape(given)),
+    ];
+    lines.extend(
+        record
+            .email_addresses()
+            .into_iter()
+            .map(|address| format!("EMAIL;TYPE=INTERNET:{}", ics_escape(address))),
+    );
+    if !record.tags.trim().is_empty() {
+        lines.push(format!("CATEGORIES:{}", record.tags.trim()));
+    }
+    if !note.is_empty() {
+        lines.push(format!("NOTE:{}", ics_escape(&note.join("\n"))));
+    }
+    lines.extend([
+        format!("REV:{}", record.updated_at.format("%Y%m%dT%H%M%SZ")),
+        "END:VCARD".to_string(),
+    ]);
+    lines.iter().map(|line| ics_fold(line)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::csvdb::test::test_db};
+
+    #[test]
+    fn contact_card() {
+        let mut record = test_db(1).get_all().unwrap().remove(0);
+        record.name = "Maria de la Cruz".to_string();
+        record.table = "4".to_string();
+        let card = vcard(&record);
+        assert!(card.starts_with("BEGIN:VCARD\r\nVERSION:3.0\r\n"));
+        assert!(card.contains("FN:Maria de la Cruz\r\n"));
+        assert!(card.contains("N:Cruz;Maria de la;;;\r\n"));
+        assert!(card.contains("EMAIL;TYPE=INTERNET:john0@john.john\r\n"));
+        assert!(card.contains("Table: 4"));
+        assert!(card.ends_with("END:VCARD\r\n"));
+    }
+
+    #[test]
+    fn multiget_hrefs() {
+        let body = concat!(
+            "<?xml version=\"1.0\"?><C:calendar-multiget xmlns:D=\"DAV:\">",
+            "<D:prop><D:getetag/></D:prop>",
+            "<D:href>/dav/calendars/events/event-1.ics</D:href>",
+            "<href>/dav/calendars/events/event-2.ics</href>",
+            "</C:calendar-multiget>",
+        );
+        assert_eq!(
+            hrefs(body),
+            [
+                "/dav/calendars/events/event-1.ics",
+                "/dav/calendars/events/event-2.ics"
+            ]
+        );
+        assert!(hrefs("<D:propfind><D:allprop/></D:propfind>").is_empty());
+    }
+}
@@ -0,0 +1,258 @@
+use {
+    crate::model::{name_key, AnswersFrom, RsvpModel},
+    serde::{Deserialize, Serialize},
+    std::collections::BTreeSet,
+    strsim::normalized_damerau_levenshtein,
+};
+
+/// How alike two name keys must be to flag them, from 0 to 1
+const NAME_SIMILARITY: f64 = 0.8;
+
+/// Two records that may be the same guest
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct DuplicatePair {
+    pub first: String,
+    pub first_email: String,
+    pub second: String,
+    pub second_email: String,
+    pub reason: String,
+}
+
+/// Pairs of records with very similar names or the same email, usually from a
+/// typo when adding a guest
+pub fn find(records: &[RsvpModel]) -> Vec<DuplicatePair> {
+    let mut pairs = vec![];
+    for (i, first) in records.iter().enumerate() {
+        for second in &records[i + 1..] {
+            let first_email = first.email.trim().to_lowercase();
+            let reason = if !first_email.is_empty()
+                && first_email == second.email.trim().to_lowercase()
+            {
+                "same email".to_string()
+            } else {
+                let similarity =
+                    normalized_damerau_levenshtein(&name_key(&first.name), &name_key(&second.name));
+                if similarity < NAME_SIMILARITY {
+                    continue;
+                }
+                format!("similar names ({:.0}%)", similarity * 100.0)
+            };
+            pairs.push(DuplicatePair {
+                first: first.name.clone(),
+                first_email: first.email.clone(),
+                second: second.name.clone(),
+                second_email: second.email.clone(),
+                reason,
+            });
+        }
+    }
+    pairs
+}
+
+/// Combine two records for the same guest into `keep`.  The answers come from
+/// the record chosen by `answers_from`, anything missing is filled in from the
+/// other, comments and notes from both are kept, and the history covers both.
+pub fn merge(keep: &RsvpModel, other: &RsvpModel, answers_from: AnswersFrom) -> RsvpModel {
+    let other_answers = match answers_from {
+        AnswersFrom::Latest => other.updated_at > keep.updated_at,
+        AnswersFrom::Keep => false,
+        AnswersFrom::Remove => true,
+    };
+    let (newer, older) = if other_answers {
+        (other, keep)
+    } else {
+        (keep, other)
+    };
+    let or = |a: &String, b: &String| if a.is_empty() { b.clone() } else { a.clone() };
+    let email = or(&keep.email, &other.email);
+    let email_owner = if email == keep.email { keep } else { other };
+    let tags = keep
+        .tags
+        .split(',')
+        .chain(other.tags.split(','))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>()
+        .join(",");
+    RsvpModel {
+        name: keep.name.clone(),
+        name_key: keep.name_key.clone(),
+        token: keep.token.clone(),
+        email,
+        email_verified: email_owner.email_verified,
+        verification_code: email_owner.verification_code.clone(),
+        email_status: email_owner.email_status,
+        attending: newer.attending,
+        attending_secondary: newer.attending_secondary,
+        attending_tertiary: newer.attending_tertiary,
+        meal_choice: or(&newer.meal_choice, &older.meal_choice),
+        dietary_restrictions: or(&newer.dietary_restrictions, &older.dietary_restrictions),
+        plus_one_attending: newer.plus_one_attending,
+        plus_one_attending_secondary: newer.plus_one_attending_secondary,
+        plus_one_attending_tertiary: newer.plus_one_attending_tertiary,
+        plus_one_name: or(&newer.plus_one_name, &older.plus_one_name),
+        plus_one_meal_choice: or(&newer.plus_one_meal_choice, &older.plus_one_meal_choice),
+        plus_one_dietary_restrictions: or(
+            &newer.plus_one_dietary_restrictions,
+            &older.plus_one_dietary_restrictions,
+        ),
+        comments: combine(&newer.comments, &older.comments),
+        created_at: keep.created_at.min(other.created_at),
+        updated_at: newer.updated_at,
+        tags,
+        announcements_opt_out: keep.announcements_opt_out || other.announcements_opt_out,
+        transactional_opt_out: keep.transactional_opt_out || other.transactional_opt_out,
+        notes: combine(&keep.notes, &other.notes),
+        checked_in_at: keep.checked_in_at.or(other.checked_in_at),
+        // the same party, so the larger invitation wins
+        invited: keep.invited.max(other.invited),
+        responded_at: keep.responded_at.max(other.responded_at),
+        language: or(&keep.language, &other.language),
+        short_code: or(&keep.short_code, &other.short_code),
+        table: or(&keep.table, &other.table),
+        dietary: or(&newer.dietary, &older.dietary),
+        plus_one_dietary: or(&newer.plus_one_dietary, &older.plus_one_dietary),
+        // the more careful answer is safer for the kitchen
+        dietary_severity: keep.dietary_severity.max(other.dietary_severity),
+        plus_one_dietary_severity: keep
+            .plus_one_dietary_severity
+            .max(other.plus_one_dietary_severity),
+        wave: or(&keep.wave, &other.wave),
+        source: newer.source.or(older.source),
+        plus_one_email: or(&newer.plus_one_email, &older.plus_one_email),
+        needs_lodging: newer.needs_lodging,
+        shuttle: or(&newer.shuttle, &older.shuttle),
+        attending_private: newer.attending_private,
+        plus_one_attending_private: newer.plus_one_attending_private,
+        kids: newer.kids,
+        kids_ages: newer.kids_ages.clone(),
+        thank_you_sent: keep.thank_you_sent || other.thank_you_sent,
+        gift: combine(&keep.gift, &other.gift),
+        lodging_reminded: keep.lodging_reminded.max(other.lodging_reminded),
+    }
+}
+
+/// Both texts if they differ, otherwise whichever isn't empty
+fn combine(first: &str, second: &str) -> String {
+    if first.is_empty() || first == second {
+        second.to_string()
+    } else if second.is_empty() {
+        first.to_string()
+    } else {
+        format!("{} / {}", first, second)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            dietary::Severity,
+            model::{AddParams, RsvpParams},
+        },
+        chrono::{Duration, Utc},
+    };
+
+    fn added(name: &str, email: &str) -> RsvpModel {
+        RsvpModel::new_with_add(
+            &AddParams {
+                name: name.to_string(),
+                email: email.to_string(),
+                plus_one_name: String::default(),
+                tags: "family".to_string(),
+                invited: None,
+                language: String::default(),
+                wave: String::default(),
+            },
+            Utc::now(),
+        )
+    }
+
+    #[test]
+    fn find_pairs() {
+        let records = vec![
+            added("Jonathan Smith", "jon@example.com"),
+            added("Jonathon Smith", ""),
+            added("Maria Garcia", "maria@example.com"),
+            added("M. Garcia", "Maria@Example.com "),
+            added("Someone Else", ""),
+        ];
+        let pairs = find(&records);
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[0].first, "Jonathan Smith");
+        assert_eq!(pairs[0].second, "Jonathon Smith");
+        assert!(pairs[0].reason.starts_with("similar names"));
+        assert_eq!(pairs[1].first, "Maria Garcia");
+        assert_eq!(pairs[1].reason, "same email");
+    }
+
+    #[test]
+    fn merge_records() {
+        let keep = RsvpModel {
+            notes: "Called".to_string(),
+            ..added("Jonathan Smith", "")
+        };
+        let mut typo = added("Jonathon Smith", "jon@example.com");
+        typo.update(
+            &RsvpParams {
+                name: typo.name.clone(),
+                email: typo.email.clone(),
+                attending: true,
+                attending_secondary: true,
+                attending_tertiary: false,
+                meal_choice: "Fish".to_string(),
+                dietary_restrictions: String::default(),
+                plus_one_attending: false,
+                plus_one_attending_secondary: false,
+                plus_one_attending_tertiary: false,
+                plus_one_name: String::default(),
+                plus_one_meal_choice: String::default(),
+                plus_one_dietary_restrictions: String::default(),
+                comments: "See you there".to_string(),
+                language: String::default(),
+                dietary: String::default(),
+                plus_one_dietary: String::default(),
+                dietary_severity: Severity::default(),
+                plus_one_dietary_severity: Severity::default(),
+                source: None,
+                plus_one_email: String::default(),
+                needs_lodging: false,
+                shuttle: String::default(),
+                attending_private: false,
+                plus_one_attending_private: false,
+                kids: 0,
+                kids_ages: String::default(),
+            },
+            keep.updated_at + Duration::hours(1),
+        )
+        .unwrap();
+        typo.tags = "friends, family".to_string();
+
+        let merged = merge(&keep, &typo, AnswersFrom::Latest);
+        assert_eq!(merged.name, "Jonathan Smith");
+        assert_eq!(merged.token, keep.token);
+        assert_eq!(merged.email, "jon@example.com");
+        assert!(merged.attending);
+        assert_eq!(merged.meal_choice, "Fish");
+        assert_eq!(merged.comments, "See you there");
+        assert_eq!(merged.tags, "family,friends");
+        assert_eq!(merged.notes, "Called");
+        assert_eq!(merged.created_at, keep.created_at);
+        assert_eq!(merged.updated_at, typo.updated_at);
+
+        let keep = RsvpModel {
+            comments: "Maybe".to_string(),
+            ..keep
+        };
+        let merged = merge(&keep, &typo, AnswersFrom::Keep);
+        assert!(!merged.attending);
+        assert_eq!(merged.meal_choice, "Fish");
+        assert_eq!(merged.comments, "Maybe / See you there");
+        let merged = merge(&keep, &typo, AnswersFrom::Remove);
+        assert!(merged.attending);
+        assert_eq!(merged.comments, "See you there / Maybe");
+    }
+}
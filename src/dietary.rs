@@ -0,0 +1,223 @@
+use {
+    crate::model::RsvpModel,
+    serde::{Deserialize, Serialize},
+};
+
+/// Counted for guests who wrote in restrictions that aren't on the checklist
+pub const OTHER: &str = "Other";
+
+/// One box of the dietary checklist or severity on the RSVP form
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Choice {
+    pub value: String,
+    pub label: String,
+    pub checked: bool,
+}
+
+/// How careful the kitchen has to be with someone's dietary restrictions
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Would rather not, like most vegetarians
+    #[default]
+    Preference,
+    /// Gets sick from it
+    Allergy,
+    /// Can't be near it at all, and on the kitchen alert sheet
+    Severe,
+}
+impl Severity {
+    pub const ALL: [Self; 3] = [Self::Preference, Self::Allergy, Self::Severe];
+
+    pub fn value(&self) -> &'static str {
+        match self {
+            Self::Preference => "preference",
+            Self::Allergy => "allergy",
+            Self::Severe => "severe",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Preference => "Preference",
+            Self::Allergy => "Allergy",
+            Self::Severe => "Severe allergy",
+        }
+    }
+}
+
+/// The severities for the form, with someone's checked
+pub fn severities(selected: Severity) -> Vec<Choice> {
+    Severity::ALL
+        .iter()
+        .map(|severity| Choice {
+            value: severity.value().to_string(),
+            label: severity.label().to_string(),
+            checked: *severity == selected,
+        })
+        .collect()
+}
+
+/// How many guests and plus-ones coming have a dietary restriction
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct DietaryCount {
+    pub label: String,
+    pub count: usize,
+}
+
+/// Checked restrictions in a checklist column, saved comma-separated like
+/// tags
+pub fn choices(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|c| !c.is_empty())
+}
+
+/// The checked restrictions that are on the checklist, in its order and
+/// spelling, with anything else dropped
+pub fn normalize(list: &str, options: &[String]) -> String {
+    options
+        .iter()
+        .filter(|option| choices(list).any(|c| c.eq_ignore_ascii_case(option)))
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The checklist for the form, with a guest's restrictions checked
+pub fn checklist(list: &str, options: &[String]) -> Vec<Choice> {
+    options
+        .iter()
+        .map(|option| Choice {
+            value: option.clone(),
+            label: option.clone(),
+            checked: choices(list).any(|c| c.eq_ignore_ascii_case(option)),
+        })
+        .collect()
+}
+
+/// Someone's restrictions on one line: what they checked, then what they
+/// wrote in
+pub fn describe(list: &str, other: &str) -> String {
+    choices(list)
+        .chain(Some(other.trim()).filter(|o| !o.is_empty()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// How many guests and plus-ones coming checked each restriction, in the
+/// checklist's order, then any no longer on it, then everyone who wrote
+/// something in under `OTHER`
+pub fn counts(records: &[RsvpModel], options: &[String]) -> Vec<DietaryCount> {
+    let mut counts = options
+        .iter()
+        .map(|option| DietaryCount {
+            label: option.clone(),
+            count: 0,
+        })
+        .collect::<Vec<_>>();
+    let mut other = 0;
+    let mut count = |list: &str, written: &str| {
+        for choice in choices(list) {
+            match counts
+                .iter_mut()
+                .find(|c| c.label.eq_ignore_ascii_case(choice))
+            {
+                Some(existing) => existing.count += 1,
+                None => counts.push(DietaryCount {
+                    label: choice.to_string(),
+                    count: 1,
+                }),
+            }
+        }
+        if !written.trim().is_empty() {
+            other += 1;
+        }
+    };
+    for record in records.iter().filter(|r| r.is_attending_any()) {
+        count(&record.dietary, &record.dietary_restrictions);
+        if record.plus_one_attending_any() {
+            count(
+                &record.plus_one_dietary,
+                &record.plus_one_dietary_restrictions,
+            );
+        }
+    }
+    counts.push(DietaryCount {
+        label: OTHER.to_string(),
+        count: other,
+    });
+    counts
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{csvdb::test::test_rsvp, model::RsvpParams},
+        chrono::Utc,
+    };
+
+    fn options() -> Vec<String> {
+        ["Vegetarian", "Vegan", "Nut allergy"]
+            .map(String::from)
+            .to_vec()
+    }
+
+    #[test]
+    fn checklist_answers() {
+        let options = options();
+        assert_eq!(
+            normalize("nut allergy, Paleo,vegetarian,", &options),
+            "Vegetarian, Nut allergy"
+        );
+        assert_eq!(normalize("", &options), "");
+        let checklist = checklist("Vegan", &options);
+        assert_eq!(checklist.len(), 3);
+        assert!(!checklist[0].checked && checklist[1].checked);
+        assert_eq!(
+            describe("Vegan, Nut allergy", " no meat pls "),
+            "Vegan, Nut allergy, no meat pls"
+        );
+        assert_eq!(describe("", "Kosher"), "Kosher");
+
+        let severities = severities(Severity::Allergy);
+        assert_eq!(severities[1].value, "allergy");
+        assert!(!severities[0].checked && severities[1].checked);
+        assert!(Severity::Severe > Severity::Allergy);
+    }
+
+    #[test]
+    fn aggregate_counts() {
+        let record = |dietary: &str, plus_one_dietary: &str, attending: bool| {
+            RsvpModel::new_with_rsvp(
+                &RsvpParams {
+                    attending,
+                    attending_secondary: attending,
+                    dietary: dietary.to_string(),
+                    dietary_restrictions: String::default(),
+                    plus_one_dietary: plus_one_dietary.to_string(),
+                    plus_one_dietary_restrictions: "no meat pls".to_string(),
+                    ..test_rsvp()
+                },
+                Utc::now(),
+            )
+        };
+        let records = vec![
+            record("Vegan", "Vegetarian, Nut allergy", true),
+            record("Vegan, Paleo", "", true),
+            record("Vegan", "Vegan", false),
+        ];
+        let counts = counts(&records, &options())
+            .into_iter()
+            .map(|c| (c.label, c.count))
+            .collect::<Vec<_>>();
+        let expected = [
+            ("Vegetarian", 1),
+            ("Vegan", 2),
+            ("Nut allergy", 1),
+            ("Paleo", 1),
+            (OTHER, 2),
+        ]
+        .map(|(label, count)| (label.to_string(), count));
+        assert_eq!(counts, expected);
+    }
+}
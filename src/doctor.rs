@@ -0,0 +1,394 @@
+use {
+    crate::{
+        clock::SystemClock,
+        config::Config,
+        email::Email,
+        session,
+        state::{check_address, check_templates, templates},
+    },
+    csv::ReaderBuilder,
+    serde::{Deserialize, Serialize},
+    std::{
+        env, fmt,
+        fs::{self, OpenOptions},
+        io::ErrorKind,
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+};
+
+/// Directory served under `/static`, relative to where the server runs
+const STATIC_DIR: &str = "static";
+
+/// How one part of the setup looks
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    /// Works, but probably isn't what a live site wants
+    Warn,
+    /// Will fail for guests
+    Fail,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    /// What was found, or what to fix
+    pub detail: String,
+}
+
+/// Everything the doctor looked at, in order
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+impl Report {
+    /// Whether nothing failed, warnings aside
+    pub fn ok(&self) -> bool {
+        self.checks.iter().all(|check| check.status != Status::Fail)
+    }
+
+    fn add(&mut self, name: &str, status: Status, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    fn add_result(&mut self, name: &str, result: Result<String, String>) {
+        match result {
+            Ok(detail) => self.add(name, Status::Ok, detail),
+            Err(detail) => self.add(name, Status::Fail, detail),
+        }
+    }
+
+    /// A report of a config file that couldn't be loaded, since nothing else
+    /// can be checked without it
+    pub fn bad_config(error: impl fmt::Display) -> Self {
+        let mut report = Self::default();
+        report.add("Config", Status::Fail, error.to_string());
+        report
+    }
+}
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let status = match check.status {
+                Status::Ok => "ok",
+                Status::Warn => "warn",
+                Status::Fail => "FAIL",
+            };
+            writeln!(f, "{:<5} {}: {}", status, check.name, check.detail)?;
+        }
+        let failed = self
+            .checks
+            .iter()
+            .filter(|check| check.status == Status::Fail)
+            .count();
+        match failed {
+            0 => writeln!(f, "Ready to serve guests"),
+            _ => writeln!(f, "{} problem(s) to fix before serving guests", failed),
+        }
+    }
+}
+
+/// Look over the whole setup the server would run with, without changing
+/// anything, so it can be checked before pointing DNS at a new server
+pub fn diagnose(config: &Config, csv: &str, from: &str, admin: &str, test: bool) -> Report {
+    let mut report = Report::default();
+    check_config(&mut report, config);
+    report.add_result(
+        "Sender address",
+        check_address("sender", from)
+            .map(|_| from.to_string())
+            .map_err(|e| e.to_string()),
+    );
+    report.add_result(
+        "Admin address",
+        check_address("admin", admin)
+            .map(|_| admin.to_string())
+            .map_err(|e| e.to_string()),
+    );
+    report.add_result("CSV file", check_csv(csv));
+    report.add_result("Audit log", writable(Path::new(&config.audit_log)));
+    report.add_result("Snapshots", writable_dir(Path::new(&config.backups.dir)));
+    let tt = templates(config, Arc::new(SystemClock));
+    report.add_result(
+        "Templates",
+        check_templates(tt.as_ref(), config)
+            .map(|_| "every page renders".to_string())
+            .map_err(|e| e.to_string()),
+    );
+    check_email(&mut report, config, test);
+    check_static(&mut report, config);
+    report
+}
+
+fn check_config(report: &mut Report, config: &Config) {
+    let host = config
+        .base_url
+        .split("://")
+        .nth(1)
+        .unwrap_or(&config.base_url);
+    if host.starts_with("127.0.0.1") || host.starts_with("localhost") {
+        report.add(
+            "Base URL",
+            Status::Warn,
+            format!(
+                "{} is only reachable from this machine, so links in emails won't work; \
+                 set `base_url` to the site's public address",
+                config.base_url
+            ),
+        );
+    } else {
+        report.add("Base URL", Status::Ok, &config.base_url);
+    }
+    if config.admin.password.is_empty() {
+        report.add(
+            "Admin pages",
+            Status::Warn,
+            "turned off until `admin.password` is set",
+        );
+    } else {
+        report.add("Admin pages", Status::Ok, "password set");
+    }
+    match (session::check(config), &config.session.key) {
+        (Err(error), _) => report.add("Sessions", Status::Fail, error.to_string()),
+        (Ok(_), None) => report.add(
+            "Sessions",
+            Status::Warn,
+            "everyone is signed out when the server restarts until `session.key` is set",
+        ),
+        (Ok(_), Some(_)) => report.add("Sessions", Status::Ok, "key set"),
+    }
+}
+
+/// The guest list opens for writing and reads as a CSV file, or doesn't
+/// exist yet but can be created
+fn check_csv(path: &str) -> Result<String, String> {
+    match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(_) => {
+            // older columns are only filled in when the server starts, so
+            // just the header and row count are checked here
+            let unreadable = |e: csv::Error| {
+                format!(
+                    "Could not read the guest list in {} ({}), check that it's a CSV file \
+                     with a header line",
+                    path, e
+                )
+            };
+            let mut reader = ReaderBuilder::new().from_path(path).map_err(unreadable)?;
+            if !reader
+                .headers()
+                .map_err(unreadable)?
+                .iter()
+                .any(|h| h == "name")
+            {
+                return Err(format!(
+                    "{} has no `name` column, check that it's the guest list",
+                    path
+                ));
+            }
+            let records = reader.records().count();
+            Ok(format!("{} with {} guest(s)", path, records))
+        }
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            writable(Path::new(path)).map(|_| format!("{} will be created", path))
+        }
+        Err(error) => Err(format!(
+            "Could not open {} for writing ({}), check the server's user can write to it",
+            path, error
+        )),
+    }
+}
+
+/// A file that's appended to, which exists and can be written, or else whose
+/// directory does
+fn writable(path: &Path) -> Result<String, String> {
+    match OpenOptions::new().append(true).open(path) {
+        Ok(_) => Ok(path.display().to_string()),
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            let dir = parent(path);
+            match dir.is_dir() {
+                true => Ok(format!("{} will be created", path.display())),
+                false => Err(format!(
+                    "{} can't be created, since {} doesn't exist",
+                    path.display(),
+                    dir.display()
+                )),
+            }
+        }
+        Err(error) => Err(format!(
+            "Could not open {} for writing ({}), check the server's user can write to it",
+            path.display(),
+            error
+        )),
+    }
+}
+
+/// A directory files are saved in, which exists or can be created
+fn writable_dir(dir: &Path) -> Result<String, String> {
+    match fs::metadata(dir) {
+        Ok(metadata) if metadata.is_dir() && !metadata.permissions().readonly() => {
+            Ok(dir.display().to_string())
+        }
+        Ok(_) => Err(format!("{} isn't a writable directory", dir.display())),
+        Err(_) if parent(dir).is_dir() => Ok(format!("{} will be created", dir.display())),
+        Err(error) => Err(format!("Could not find {} ({})", dir.display(), error)),
+    }
+}
+
+fn parent(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Whether emails can go out through sendmail, and be signed if asked to
+fn check_email(report: &mut Report, config: &Config, test: bool) {
+    if !config.features.email {
+        report.add("Email", Status::Warn, "turned off in `[features]`");
+        return;
+    }
+    if test {
+        report.add(
+            "Email",
+            Status::Warn,
+            "test mode, so emails are only logged; start without --test to send them",
+        );
+    } else {
+        report.add_result(
+            "Email",
+            find_on_path("sendmail")
+                .map(|path| format!("sending with {}", path.display()))
+                .ok_or_else(|| {
+                    "No sendmail on the PATH, install a mail transport agent like msmtp or \
+                     postfix"
+                        .to_string()
+                }),
+        );
+    }
+    if config.dkim.private_key_file.is_some() {
+        report.add_result(
+            "DKIM",
+            Email::load_dkim(&config.dkim)
+                .map(|_| format!("signing for {}", config.dkim.domain))
+                .map_err(|e| format!("Could not load the DKIM key ({})", e)),
+        );
+    }
+}
+
+fn find_on_path(command: &str) -> Option<PathBuf> {
+    env::var_os("PATH")
+        .into_iter()
+        .flat_map(|paths| env::split_paths(&paths).collect::<Vec<_>>())
+        .chain(["/usr/sbin", "/usr/lib"].map(PathBuf::from))
+        .map(|dir| dir.join(command))
+        .find(|path| path.is_file())
+}
+
+/// The stylesheet every page links, and the images the config points at
+fn check_static(report: &mut Report, config: &Config) {
+    let mut missing = vec![];
+    let urls = [
+        "/static/styles.css",
+        &config.theme.hero_image,
+        &config.site.image,
+    ];
+    for url in urls {
+        if let Some(file) = url.strip_prefix("/static/") {
+            if !Path::new(STATIC_DIR).join(file).is_file() {
+                missing.push(format!("{}/{}", STATIC_DIR, file));
+            }
+        }
+    }
+    match missing.is_empty() {
+        true => report.add(
+            "Static files",
+            Status::Ok,
+            format!("found in {}", STATIC_DIR),
+        ),
+        false => report.add(
+            "Static files",
+            Status::Fail,
+            format!(
+                "Missing {}, run the server from the directory with `static/`",
+                missing.join(", ")
+            ),
+        ),
+    }
+    if cfg!(feature = "photos") && config.features.photos {
+        match Path::new(&config.photos.dir).is_dir() {
+            true => report.add("Photos", Status::Ok, &config.photos.dir),
+            false => report.add(
+                "Photos",
+                Status::Warn,
+                format!(
+                    "{} doesn't exist, so there are no albums yet",
+                    config.photos.dir
+                ),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, tempfile::tempdir};
+
+    #[test]
+    fn report() {
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("rsvp.csv");
+        let config = Config {
+            audit_log: dir.path().join("audit.jsonl").to_str().unwrap().to_string(),
+            ..Config::default()
+        };
+        let report = diagnose(
+            &config,
+            csv.to_str().unwrap(),
+            "rsvp@example.com",
+            "admin@example.com",
+            true,
+        );
+        assert!(report.ok(), "{}", report);
+        assert!(!csv.exists());
+        let status = |report: &Report, name: &str| {
+            report
+                .checks
+                .iter()
+                .find(|check| check.name == name)
+                .map(|check| check.status)
+        };
+        assert_eq!(status(&report, "Base URL"), Some(Status::Warn));
+        assert_eq!(status(&report, "Static files"), Some(Status::Ok));
+        assert_eq!(status(&report, "Sessions"), Some(Status::Warn));
+
+        fs::write(&csv, "name,email\nJane,jane@example.com\n").unwrap();
+        let missing = dir.path().join("missing").join("audit.jsonl");
+        let config = Config {
+            base_url: "https://example.com".to_string(),
+            audit_log: missing.to_str().unwrap().to_string(),
+            ..config
+        };
+        let report = diagnose(
+            &config,
+            csv.to_str().unwrap(),
+            "rsvp at example.com",
+            "admin@example.com",
+            true,
+        );
+        assert!(!report.ok());
+        assert_eq!(status(&report, "Base URL"), Some(Status::Ok));
+        assert_eq!(status(&report, "Sender address"), Some(Status::Fail));
+        assert_eq!(status(&report, "CSV file"), Some(Status::Ok));
+        assert_eq!(status(&report, "Audit log"), Some(Status::Fail));
+        assert!(report
+            .to_string()
+            .ends_with("2 problem(s) to fix before serving guests\n"));
+    }
+}
@@ -1,30 +1,223 @@
 use {
     crate::{
+        announcement::Announcement,
+        calendar::CalendarLinks,
+        config::{
+            AttachmentFormat, DkimAlgorithm, DkimConfig, FetchLockoutConfig, HotelConfig,
+            LanguagesConfig, MealConfig,
+        },
+        contact::ContactMessage,
         error::Error,
-        model::{Attendance, RsvpParams},
+        export, language, logs,
+        model::{Attendance, EmailKind, RsvpModel, RsvpParams},
+        request::RequestContext,
+        throttle::Throttle,
+        timezone::Timezone,
     },
+    chrono::{DateTime, Utc},
     lettre::{
-        message::{Attachment, Message, MultiPart, SinglePart},
+        message::{
+            dkim::{self, DkimSigningAlgorithm, DkimSigningKey},
+            Attachment, Message, MultiPart, SinglePart,
+        },
         transport::stub::AsyncStubTransport,
         AsyncSendmailTransport, AsyncTransport, Tokio1Executor,
     },
-    log::info,
+    log::{error, info, warn},
+    serde::Serialize,
+    serde_json::json,
+    std::{collections::BTreeMap, fs, slice, sync::Arc},
+    tinytemplate::{format_unescaped, TinyTemplate},
+    tracing::instrument,
 };
 
-#[derive(Default)]
+/// Log lines about the failed request to include in error emails
+const LOG_EXCERPT_LINES: usize = 20;
+
+static NEW_RSVP: &str = include_str!("../templates/email/new_rsvp.txt");
+static WRITE_ERROR: &str = include_str!("../templates/email/write_error.txt");
+static VERIFICATION: &str = include_str!("../templates/email/verification.txt");
+static PLUS_ONE: &str = include_str!("../templates/email/plus_one.txt");
+static CONTACT: &str = include_str!("../templates/email/contact.txt");
+static LODGING_REMINDER: &str = include_str!("../templates/email/lodging_reminder.txt");
+static LOCKOUT: &str = include_str!("../templates/email/lockout.txt");
+
+/// All email body templates, by name
+pub static EMAIL_TEMPLATES: &[(&str, &str)] = &[
+    ("email/new_rsvp.txt", NEW_RSVP),
+    ("email/write_error.txt", WRITE_ERROR),
+    ("email/verification.txt", VERIFICATION),
+    ("email/plus_one.txt", PLUS_ONE),
+    ("email/contact.txt", CONTACT),
+    ("email/lodging_reminder.txt", LODGING_REMINDER),
+    ("email/lockout.txt", LOCKOUT),
+];
+
+/// Render a plain text email body, without any HTML escaping
+pub fn render_email<C: Serialize>(name: &str, ctx: &C) -> Result<String, Error> {
+    let mut tt = TinyTemplate::new();
+    tt.set_default_formatter(&format_unescaped);
+    for (name, template) in EMAIL_TEMPLATES {
+        tt.add_template(name, template)?;
+    }
+    tt.render(name, ctx).map_err(Error::from)
+}
+
+/// An email template in another language, loaded from a file named in the
+/// config
+#[derive(Clone, Debug, PartialEq)]
+pub struct Translation {
+    pub subject: Option<String>,
+    pub body: String,
+}
+
+/// Translated email templates by language then template name
+pub type Translations = BTreeMap<String, BTreeMap<String, Translation>>;
+
+/// The guest list attached to the new RSVP email
+struct GuestList<'a> {
+    filename: String,
+    body: Vec<u8>,
+    mime: &'static str,
+    /// Where to download the whole list, when only the guest's row is attached
+    download_url: Option<&'a str>,
+}
+
+#[derive(Clone)]
 pub struct Email {
     pub from: String,
     pub admin: String,
+    /// For showing when things happened in the event's time zone
+    pub timezone: Timezone,
+    /// Largest CSV file attached whole to the new RSVP email
+    pub max_attachment_bytes: usize,
+    /// What the guest list is attached as
+    pub attachment_format: AttachmentFormat,
+    /// Where the admin can download the whole CSV file when it's too big to
+    /// attach
+    pub download_url: String,
+    /// Key to sign every message with, if any
+    pub dkim: Option<Arc<dkim::DkimConfig>>,
+    /// Language of the built-in templates
+    pub default_language: String,
+    /// Templates for guests who prefer another language
+    pub translations: Arc<Translations>,
+    /// Whether to send anything at all, or only log what would have gone out
+    pub enabled: bool,
+}
+impl Default for Email {
+    fn default() -> Self {
+        Self::new("", "")
+    }
 }
 impl Email {
     pub fn new(from: &str, admin: &str) -> Self {
         Self {
             from: from.to_string(),
             admin: admin.to_string(),
+            timezone: Timezone::default(),
+            max_attachment_bytes: usize::MAX,
+            attachment_format: AttachmentFormat::default(),
+            download_url: String::default(),
+            dkim: None,
+            default_language: LanguagesConfig::default().default,
+            translations: Arc::default(),
+            enabled: true,
+        }
+    }
+
+    /// Load each translated template, leaving out any that can't be read so
+    /// the guest gets the built-in one instead
+    pub fn load_translations(config: &LanguagesConfig) -> Translations {
+        config
+            .emails
+            .iter()
+            .map(|(language, templates)| {
+                let templates = templates
+                    .iter()
+                    .filter_map(
+                        |(name, template)| match fs::read_to_string(&template.template) {
+                            Ok(body) => Some((
+                                name.clone(),
+                                Translation {
+                                    subject: template.subject.clone(),
+                                    body,
+                                },
+                            )),
+                            Err(error) => {
+                                error!("Could not read template {}: {}", template.template, error);
+                                None
+                            }
+                        },
+                    )
+                    .collect();
+                (language::normalize(language), templates)
+            })
+            .collect()
+    }
+
+    /// Render a guest's email in their language, falling back to the same
+    /// language without its region and then the default, returning the
+    /// subject and body
+    fn render_for<C: Serialize>(
+        &self,
+        recipient: &RsvpModel,
+        name: &str,
+        subject: &str,
+        ctx: &C,
+    ) -> Result<(String, String), Error> {
+        let translation = language::fallbacks(&recipient.language, &self.default_language)
+            .into_iter()
+            .find_map(|language| self.translations.get(language)?.get(name));
+        match translation {
+            Some(translation) => {
+                let mut tt = TinyTemplate::new();
+                tt.set_default_formatter(&format_unescaped);
+                tt.add_template(name, &translation.body)?;
+                Ok((
+                    translation
+                        .subject
+                        .as_deref()
+                        .unwrap_or(subject)
+                        .to_string(),
+                    tt.render(name, ctx)?,
+                ))
+            }
+            None => Ok((subject.to_string(), render_email(name, ctx)?)),
         }
     }
 
-    async fn send_message(&self, message: Message, test: bool) -> Result<(), Error> {
+    /// Load the DKIM key from the config, or `None` if signing is off
+    pub fn load_dkim(config: &DkimConfig) -> Result<Option<Arc<dkim::DkimConfig>>, Error> {
+        let path = match &config.private_key_file {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        if config.domain.is_empty() {
+            return Err(Error::Dkim("dkim.domain must be set".to_string()));
+        }
+        let algorithm = match config.algorithm {
+            DkimAlgorithm::Rsa => DkimSigningAlgorithm::Rsa,
+            DkimAlgorithm::Ed25519 => DkimSigningAlgorithm::Ed25519,
+        };
+        let key = DkimSigningKey::new(fs::read_to_string(path)?.trim(), algorithm)
+            .map_err(|error| Error::Dkim(format!("{} in {}", error, path)))?;
+        Ok(Some(Arc::new(dkim::DkimConfig::default_config(
+            config.selector.clone(),
+            config.domain.clone(),
+            key,
+        ))))
+    }
+
+    #[instrument(skip(self, message))]
+    async fn send_message(&self, mut message: Message, test: bool) -> Result<(), Error> {
+        if !self.enabled {
+            info!("Email is turned off, not sending: {:?}", message.headers());
+            return Ok(());
+        }
+        if let Some(dkim) = &self.dkim {
+            message.sign(dkim);
+        }
         // types are gross, we can probably do this better some other time
         if test {
             info!("Sending message: {:?}", message);
@@ -36,12 +229,68 @@ impl Email {
         }
     }
 
+    /// Send a message to a guest, unless they opted out of that kind of
+    /// email or their address is known to be bad
+    async fn send_to_guest(
+        &self,
+        recipient: &RsvpModel,
+        kind: EmailKind,
+        message: Message,
+        test: bool,
+    ) -> Result<(), Error> {
+        if recipient.accepts_email(kind) {
+            self.send_message(message, test).await
+        } else {
+            warn!(
+                "Not sending {:?} email to {}, who opted out or has a bad address",
+                kind, recipient.email
+            );
+            Ok(())
+        }
+    }
+
+    /// The guest list to attach: the whole file while it's small enough, or
+    /// else just the guest's row, with where to download the rest
+    fn guest_list(&self, record: &RsvpModel, csv_contents: String) -> Result<GuestList<'_>, Error> {
+        let (stem, download_url) = match csv_contents.len() <= self.max_attachment_bytes {
+            true => ("rsvp", None),
+            false => ("rsvp-change", Some(self.download_url.as_str())),
+        };
+        let row = slice::from_ref(record);
+        let (format, body) = match (self.attachment_format, download_url) {
+            #[cfg(feature = "xlsx")]
+            (AttachmentFormat::Xlsx, None) => {
+                let records = crate::csvdb::CsvDb::parse(&csv_contents)?;
+                (
+                    AttachmentFormat::Xlsx,
+                    export::workbook(&records, self.timezone)?,
+                )
+            }
+            #[cfg(feature = "xlsx")]
+            (AttachmentFormat::Xlsx, Some(_)) => (
+                AttachmentFormat::Xlsx,
+                export::workbook(row, self.timezone)?,
+            ),
+            (_, None) => (AttachmentFormat::Csv, csv_contents.into_bytes()),
+            (_, Some(_)) => (AttachmentFormat::Csv, export::csv(row)?.into_bytes()),
+        };
+        Ok(GuestList {
+            filename: format!("{}.{}", stem, format.extension()),
+            body,
+            mime: format.mime(),
+            download_url,
+        })
+    }
+
     fn csv_email(
         &self,
         rsvp: &RsvpParams,
+        record: &RsvpModel,
         attendance: &Attendance,
         csv_contents: String,
+        received_at: DateTime<Utc>,
     ) -> Result<Message, Error> {
+        let guest_list = self.guest_list(record, csv_contents)?;
         Message::builder()
             .from(self.from.parse().map_err(Error::from)?)
             .reply_to(self.from.parse().map_err(Error::from)?)
@@ -49,51 +298,584 @@ impl Email {
             .subject("New RSVP!")
             .multipart(
                 MultiPart::mixed()
-                    .singlepart(SinglePart::plain(format!(
-                        "Success on new RSVP!\n{}\n{}",
-                        serde_json::to_string_pretty(attendance).map_err(Error::from)?,
-                        serde_json::to_string_pretty(rsvp).map_err(Error::from)?
-                    )))
+                    .singlepart(SinglePart::plain(render_email(
+                        "email/new_rsvp.txt",
+                        &json!({
+                            "attendance": serde_json::to_string_pretty(attendance)?,
+                            "received_at": self.timezone.format(received_at),
+                            "rsvp": serde_json::to_string_pretty(rsvp)?,
+                            "download_url": guest_list.download_url,
+                        }),
+                    )?))
                     .singlepart(
-                        Attachment::new("rsvp.csv".to_string())
-                            .body(csv_contents, "text/csv".parse().unwrap()),
+                        Attachment::new(guest_list.filename)
+                            .body(guest_list.body, guest_list.mime.parse().unwrap()),
                     ),
             )
             .map_err(Error::from)
     }
 
-    fn error_email(&self, error: &Error, rsvp: &RsvpParams) -> Result<Message, Error> {
+    fn error_email<P: Serialize>(
+        &self,
+        action: &str,
+        error: &Error,
+        params: &P,
+        context: &RequestContext,
+    ) -> Result<Message, Error> {
+        let logs = logs::recent(&[&context.request_id], LOG_EXCERPT_LINES);
         Message::builder()
             .from(self.from.parse().map_err(Error::from)?)
             .reply_to(self.from.parse().map_err(Error::from)?)
             .to(self.admin.parse().map_err(Error::from)?)
-            .subject("Error on RSVP")
+            .subject(format!("Error on {}", action))
             .multipart(
-                MultiPart::mixed()
-                    .singlepart(SinglePart::plain(format!("Error on new RSVP, try to get in touch with them or put it in yourself.\nError: {}\nRSVP: {}", error, serde_json::to_string_pretty(rsvp).map_err(Error::from)?)))
-            ).map_err(Error::from)
+                MultiPart::mixed().singlepart(SinglePart::plain(render_email(
+                    "email/write_error.txt",
+                    &json!({
+                        "action": action,
+                        "error": error.to_string(),
+                        "params": serde_json::to_string_pretty(params)?,
+                        "request_id": context.request_id,
+                        "client_ip": context.client_ip,
+                        "body": context.body,
+                        "logs": logs.join("\n"),
+                    }),
+                )?)),
+            )
+            .map_err(Error::from)
+    }
+
+    /// A guest's message from the contact page, which the admin can answer
+    /// by replying, without the guest ever seeing the admin's address
+    fn contact_email(&self, message: &ContactMessage) -> Result<Message, Error> {
+        // an address that doesn't parse still gets the message through
+        let reply_to = format!("{} <{}>", message.name, message.email)
+            .parse()
+            .or_else(|_| self.from.parse())
+            .map_err(Error::from)?;
+        Message::builder()
+            .from(self.from.parse().map_err(Error::from)?)
+            .reply_to(reply_to)
+            .to(self.admin.parse().map_err(Error::from)?)
+            .subject(format!("Message from {}", message.name))
+            .singlepart(SinglePart::plain(render_email(
+                "email/contact.txt",
+                message,
+            )?))
+            .map_err(Error::from)
+    }
+
+    /// An IP address locked out of `/fetch` for looking up too many names
+    /// that aren't on the list
+    fn lockout_email(
+        &self,
+        config: &FetchLockoutConfig,
+        context: &RequestContext,
+        locked_until: DateTime<Utc>,
+    ) -> Result<Message, Error> {
+        Message::builder()
+            .from(self.from.parse().map_err(Error::from)?)
+            .reply_to(self.from.parse().map_err(Error::from)?)
+            .to(self.admin.parse().map_err(Error::from)?)
+            .subject(format!(
+                "Locked out {} for guessing names or passwords",
+                context.client_ip
+            ))
+            .singlepart(SinglePart::plain(render_email(
+                "email/lockout.txt",
+                &json!({
+                    "client_ip": context.client_ip,
+                    "max_misses": config.max_misses,
+                    "window_minutes": config.window_secs / 60,
+                    "locked_until": self.timezone.format(locked_until),
+                    "request_id": context.request_id,
+                }),
+            )?))
+            .map_err(Error::from)
+    }
+
+    fn verification_email(
+        &self,
+        recipient: &RsvpModel,
+        code: &str,
+        calendar: &[CalendarLinks],
+    ) -> Result<Message, Error> {
+        let (subject, body) = self.render_for(
+            recipient,
+            "email/verification.txt",
+            "Your RSVP verification code",
+            &json!({ "name": recipient.name, "code": code, "calendar": calendar }),
+        )?;
+        Message::builder()
+            .from(self.from.parse().map_err(Error::from)?)
+            .reply_to(self.from.parse().map_err(Error::from)?)
+            .to(recipient.email.parse().map_err(Error::from)?)
+            .subject(subject)
+            .singlepart(SinglePart::plain(body))
+            .map_err(Error::from)
+    }
+
+    /// Tell the plus-one what the guest answered for them, at their own
+    /// address
+    fn plus_one_email(
+        &self,
+        recipient: &RsvpModel,
+        to: &str,
+        calendar: &[CalendarLinks],
+        meal: &MealConfig,
+    ) -> Result<Message, Error> {
+        let (subject, body) = self.render_for(
+            recipient,
+            "email/plus_one.txt",
+            "You're on the RSVP",
+            &json!({
+                "name": recipient.name,
+                "plus_one_name": recipient.plus_one_name,
+                "calendar": calendar,
+                "meal": meal,
+            }),
+        )?;
+        Message::builder()
+            .from(self.from.parse().map_err(Error::from)?)
+            .reply_to(self.from.parse().map_err(Error::from)?)
+            .to(to.parse().map_err(Error::from)?)
+            .subject(subject)
+            .singlepart(SinglePart::plain(body))
+            .map_err(Error::from)
+    }
+
+    /// Remind a guest who needs lodging to book before the hotel blocks
+    /// close
+    fn lodging_reminder_email(
+        &self,
+        recipient: &RsvpModel,
+        hotels: &[HotelConfig],
+        travel_url: &str,
+    ) -> Result<Message, Error> {
+        let (subject, body) = self.render_for(
+            recipient,
+            "email/lodging_reminder.txt",
+            "Book your hotel room soon",
+            &json!({
+                "name": recipient.name,
+                "hotels": hotels,
+                "travel_url": travel_url,
+            }),
+        )?;
+        Message::builder()
+            .from(self.from.parse().map_err(Error::from)?)
+            .reply_to(self.from.parse().map_err(Error::from)?)
+            .to(recipient.email.parse().map_err(Error::from)?)
+            .subject(subject)
+            .singlepart(SinglePart::plain(body))
+            .map_err(Error::from)
+    }
+
+    fn announcement_email(&self, announcement: &Announcement, to: &str) -> Result<Message, Error> {
+        Message::builder()
+            .from(self.from.parse().map_err(Error::from)?)
+            .reply_to(self.from.parse().map_err(Error::from)?)
+            .to(to.parse().map_err(Error::from)?)
+            .subject(announcement.subject.clone())
+            .singlepart(SinglePart::plain(announcement.body.clone()))
+            .map_err(Error::from)
     }
 
+    #[instrument(skip_all)]
     pub async fn send_csv(
         &self,
         rsvp: &RsvpParams,
+        record: &RsvpModel,
         attendance: &Attendance,
         csv_contents: String,
+        received_at: DateTime<Utc>,
         test: bool,
     ) -> Result<(), Error> {
-        let message = self.csv_email(rsvp, attendance, csv_contents)?;
+        let message = self.csv_email(rsvp, record, attendance, csv_contents, received_at)?;
         self.send_message(message, test).await?;
         Ok(())
     }
 
-    pub async fn send_rsvp_error(
+    /// Tell the admin that a write failed, with what was submitted
+    #[instrument(skip_all)]
+    pub async fn send_write_error<P: Serialize>(
         &self,
+        action: &str,
         error: &Error,
-        rsvp: &RsvpParams,
+        params: &P,
+        context: &RequestContext,
         test: bool,
     ) -> Result<(), Error> {
-        let message = self.error_email(error, rsvp)?;
+        let message = self.error_email(action, error, params, context)?;
         self.send_message(message, test).await?;
         Ok(())
     }
+
+    /// Tell the admin an IP address was locked out of `/fetch`
+    #[instrument(skip_all)]
+    pub async fn send_lockout(
+        &self,
+        config: &FetchLockoutConfig,
+        context: &RequestContext,
+        locked_until: DateTime<Utc>,
+        test: bool,
+    ) -> Result<(), Error> {
+        let message = self.lockout_email(config, context, locked_until)?;
+        self.send_message(message, test).await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn send_contact(&self, message: &ContactMessage, test: bool) -> Result<(), Error> {
+        let message = self.contact_email(message)?;
+        self.send_message(message, test).await
+    }
+
+    /// Send the guest their verification code, with links to add the events
+    /// they're coming to to their calendar
+    #[instrument(skip_all)]
+    pub async fn send_verification(
+        &self,
+        recipient: &RsvpModel,
+        code: &str,
+        calendar: &[CalendarLinks],
+        test: bool,
+    ) -> Result<(), Error> {
+        let message = self.verification_email(recipient, code, calendar)?;
+        self.send_to_guest(recipient, EmailKind::Transactional, message, test)
+            .await
+    }
+
+    /// Send the plus-one their own confirmation, with links to add the events
+    /// they're coming to to their calendar, unless they share the guest's
+    /// inbox or aren't coming
+    #[instrument(skip_all)]
+    pub async fn send_plus_one_confirmation(
+        &self,
+        recipient: &RsvpModel,
+        calendar: &[CalendarLinks],
+        meal: &MealConfig,
+        test: bool,
+    ) -> Result<(), Error> {
+        let to = match recipient.plus_one_inbox() {
+            Some(to) if recipient.plus_one_attending_any() => to,
+            _ => return Ok(()),
+        };
+        let message = self.plus_one_email(recipient, to, calendar, meal)?;
+        self.send_to_guest(recipient, EmailKind::Transactional, message, test)
+            .await
+    }
+
+    #[instrument(skip_all)]
+    pub async fn send_lodging_reminder(
+        &self,
+        recipient: &RsvpModel,
+        hotels: &[HotelConfig],
+        travel_url: &str,
+        test: bool,
+    ) -> Result<(), Error> {
+        let message = self.lodging_reminder_email(recipient, hotels, travel_url)?;
+        self.send_to_guest(recipient, EmailKind::Transactional, message, test)
+            .await
+    }
+
+    /// Send each announcement in turn, to the guest and to the plus-one's
+    /// own address if they have one, paced by the throttle to stay under
+    /// the provider's rate limits.  Failures are logged and skipped.
+    #[instrument(skip_all, fields(count = announcements.len()))]
+    pub async fn send_announcements(
+        &self,
+        announcements: Vec<Announcement>,
+        throttle: &Throttle,
+        test: bool,
+    ) {
+        for announcement in &announcements {
+            for to in announcement.recipient.email_addresses() {
+                throttle.wait().await;
+                let result = match self.announcement_email(announcement, to) {
+                    Ok(message) => {
+                        self.send_to_guest(
+                            &announcement.recipient,
+                            EmailKind::Announcement,
+                            message,
+                            test,
+                        )
+                        .await
+                    }
+                    Err(error) => Err(error),
+                };
+                if let Err(error) = result {
+                    error!("Could not send announcement to {}: {:?}", to, error);
+                }
+            }
+        }
+        info!("Finished sending {} announcements", announcements.len());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            config::EmailTemplateConfig,
+            csvdb::{test::test_db, CsvDb},
+            model::describe_meal,
+            preview::sample_record,
+        },
+        std::{collections::BTreeMap, io::Write},
+    };
+
+    #[test]
+    fn translations() {
+        let mut french = tempfile::NamedTempFile::new().unwrap();
+        french
+            .write_all(b"Bonjour {name}, votre code est {code}")
+            .unwrap();
+        let template = |path: &str, subject: Option<&str>| {
+            BTreeMap::from([(
+                "email/verification.txt".to_string(),
+                EmailTemplateConfig {
+                    subject: subject.map(str::to_string),
+                    template: path.to_string(),
+                },
+            )])
+        };
+        let config = LanguagesConfig {
+            emails: BTreeMap::from([
+                (
+                    "FR".to_string(),
+                    template(
+                        &french.path().to_string_lossy(),
+                        Some("Votre code de vérification"),
+                    ),
+                ),
+                ("de".to_string(), template("/no/such/file.txt", None)),
+            ]),
+            ..LanguagesConfig::default()
+        };
+        let translations = Email::load_translations(&config);
+        assert_eq!(translations["fr"].len(), 1);
+        assert!(translations["de"].is_empty());
+        let email = Email {
+            translations: Arc::new(translations),
+            ..Email::new("us@example.com", "admin@example.com")
+        };
+        let subject = |message: &Message| message.headers().get_raw("Subject").unwrap().to_string();
+        let body = |message: Message| String::from_utf8(message.formatted()).unwrap();
+
+        let record = RsvpModel {
+            language: "fr-ca".to_string(),
+            ..sample_record()
+        };
+        let message = email.verification_email(&record, "123456", &[]).unwrap();
+        assert_eq!(subject(&message), "Votre code de vérification");
+        assert!(body(message).contains("Bonjour Jane Guest, votre code est 123456"));
+
+        // no translation, or none that could be read, is the default
+        for language in ["", "de", "es"] {
+            let record = RsvpModel {
+                language: language.to_string(),
+                ..sample_record()
+            };
+            let message = email.verification_email(&record, "123456", &[]).unwrap();
+            assert_eq!(subject(&message), "Your RSVP verification code");
+            assert!(body(message).contains("Thanks for your RSVP! To confirm"));
+        }
+
+        // the default language can be translated too
+        let email = Email {
+            default_language: "fr".to_string(),
+            ..email
+        };
+        let message = body(
+            email
+                .verification_email(&sample_record(), "1", &[])
+                .unwrap(),
+        );
+        assert!(message.contains("votre code est 1"));
+    }
+
+    #[test]
+    fn contact() {
+        let email = Email::new("us@example.com", "admin@example.com");
+        let message = ContactMessage {
+            at: Utc::now(),
+            request_id: "3f9a1c2e".to_string(),
+            client_ip: "203.0.113.7".to_string(),
+            name: "Maria".to_string(),
+            email: "maria@example.com".to_string(),
+            message: "Is there parking?".to_string(),
+        };
+        let formatted = |message: &ContactMessage| {
+            String::from_utf8(email.contact_email(message).unwrap().formatted()).unwrap()
+        };
+        let sent = formatted(&message);
+        assert!(sent.contains("To: admin@example.com\r\n"));
+        assert!(sent.contains("Reply-To: Maria <maria@example.com>\r\n"));
+        assert!(sent.contains("Subject: Message from Maria\r\n"));
+        assert!(sent.contains("Is there parking?"));
+
+        let bad_address = ContactMessage {
+            email: "not an address".to_string(),
+            ..message
+        };
+        assert!(formatted(&bad_address).contains("Reply-To: us@example.com\r\n"));
+    }
+
+    #[test]
+    fn plus_one_confirmation() {
+        let email = Email::new("us@example.com", "admin@example.com");
+        let record = sample_record();
+        let meals = BTreeMap::from([(
+            "Veggie".to_string(),
+            MealConfig {
+                label: "Garden risotto".to_string(),
+                allergens: "Dairy".to_string(),
+                ..MealConfig::default()
+            },
+        )]);
+        let message = email
+            .plus_one_email(
+                &record,
+                record.plus_one_inbox().unwrap(),
+                &[],
+                &describe_meal(&meals, &record.plus_one_meal_choice),
+            )
+            .unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+        assert!(formatted.contains("To: john@example.com\r\n"));
+        assert!(formatted.contains("Hi John Plusone! Jane Guest has RSVPed for the two of you"));
+        assert!(formatted.contains("Your meal: Garden risotto (allergens: Dairy)"));
+        assert!(!formatted.contains("calendar"));
+    }
+
+    #[test]
+    fn lodging_reminder() {
+        let email = Email::new("us@example.com", "admin@example.com");
+        let hotels = [HotelConfig {
+            name: "The Inn".to_string(),
+            block_code: "SMITHWEDDING".to_string(),
+            cutoff: chrono::NaiveDate::from_ymd_opt(2024, 5, 1),
+            ..HotelConfig::default()
+        }];
+        let message = email
+            .lodging_reminder_email(&sample_record(), &hotels, "https://example.com/travel")
+            .unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+        assert!(formatted.contains("To: jane@example.com\r\n"));
+        assert!(formatted.contains("Subject: Book your hotel room soon\r\n"));
+        assert!(formatted.contains("The Inn: book by 2024-05-01"));
+        assert!(formatted.contains("Booking code: SMITHWEDDING"));
+        assert!(formatted.contains("https://example.com/travel"));
+    }
+
+    #[test]
+    fn verification_calendar() {
+        let email = Email::new("us@example.com", "admin@example.com");
+        let body = |calendar: &[CalendarLinks]| {
+            let message = email
+                .verification_email(&sample_record(), "123456", calendar)
+                .unwrap();
+            String::from_utf8(message.formatted()).unwrap()
+        };
+        assert!(!body(&[]).contains("calendar"));
+        let links = CalendarLinks {
+            title: "Ceremony".to_string(),
+            google: "https://calendar.google.com/calendar/render?action=TEMPLATE".to_string(),
+            outlook: "https://outlook.live.com/calendar/0/deeplink/compose".to_string(),
+            ics: "https://example.com/calendar/1.ics".to_string(),
+        };
+        let body = body(&[links]);
+        assert!(body.contains("Add it to your calendar"));
+        assert!(body.contains("Ceremony"));
+        assert!(body.contains("https://example.com/calendar/1.ics"));
+    }
+
+    #[test]
+    fn dkim() {
+        let mut key = tempfile::NamedTempFile::new().unwrap();
+        let rsa = openssl::rsa::Rsa::generate(1024).unwrap();
+        key.write_all(&rsa.private_key_to_pem().unwrap()).unwrap();
+        let config = DkimConfig {
+            domain: "example.com".to_string(),
+            selector: "mail".to_string(),
+            private_key_file: Some(key.path().to_string_lossy().into_owned()),
+            ..DkimConfig::default()
+        };
+        let email = Email {
+            dkim: Email::load_dkim(&config).unwrap(),
+            ..Email::new("us@example.com", "admin@example.com")
+        };
+        let mut message = email
+            .verification_email(&sample_record(), "123456", &[])
+            .unwrap();
+        message.sign(email.dkim.as_ref().unwrap());
+        let signed = String::from_utf8(message.formatted()).unwrap();
+        assert!(signed.contains("\nDKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=mail;"));
+
+        assert!(Email::load_dkim(&DkimConfig::default()).unwrap().is_none());
+        let no_domain = DkimConfig {
+            domain: String::default(),
+            ..config.clone()
+        };
+        assert!(matches!(Email::load_dkim(&no_domain), Err(Error::Dkim(_))));
+        key.as_file().set_len(0).unwrap();
+        assert!(matches!(Email::load_dkim(&config), Err(Error::Dkim(_))));
+    }
+
+    #[test]
+    fn new_rsvp() {
+        let mut db = test_db(3);
+        let record = db.get("John-1").unwrap().unwrap();
+        let email = Email::new("us@example.com", "admin@example.com");
+        let received_at = "2023-05-28T18:15:00Z".parse().unwrap();
+        let message = email
+            .csv_email(
+                &RsvpParams::from(&record),
+                &record,
+                &db.attendance().unwrap(),
+                db.dump(),
+                received_at,
+            )
+            .unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+        assert!(formatted.contains(&format!("Received {}", email.timezone.format(received_at))));
+    }
+
+    #[test]
+    fn guest_list() {
+        let mut db = test_db(3);
+        let record = db.get("John-1").unwrap().unwrap();
+        let contents = db.dump();
+        let email = Email::default();
+        let list = email.guest_list(&record, contents.clone()).unwrap();
+        assert_eq!(list.filename, "rsvp.csv");
+        assert_eq!(list.body, contents.as_bytes());
+        assert_eq!(list.download_url, None);
+
+        let email = Email {
+            max_attachment_bytes: contents.len() - 1,
+            download_url: "https://example.com/admin/rsvps.csv".to_string(),
+            ..Email::default()
+        };
+        let list = email.guest_list(&record, contents.clone()).unwrap();
+        assert_eq!(list.filename, "rsvp-change.csv");
+        let rows = CsvDb::parse(&String::from_utf8(list.body).unwrap()).unwrap();
+        assert_eq!(rows, vec![record.clone()]);
+        assert_eq!(
+            list.download_url,
+            Some("https://example.com/admin/rsvps.csv")
+        );
+
+        #[cfg(feature = "xlsx")]
+        {
+            let email = Email {
+                attachment_format: AttachmentFormat::Xlsx,
+                ..email
+            };
+            let list = email.guest_list(&record, contents).unwrap();
+            assert_eq!(list.filename, "rsvp-change.xlsx");
+            assert!(list.mime.ends_with("spreadsheetml.sheet"));
+        }
+    }
 }
@@ -1,15 +1,20 @@
 use {
     crate::{
         model::{AddParams, RsvpParams},
-        state::AppState,
+        request::request_id,
+        state::{AppState, Templates},
     },
     actix_http::body::BoxBody,
+    actix_session::{SessionGetError, SessionInsertError},
     actix_web::{
         body::MessageBody,
         dev::ServiceResponse,
-        http::StatusCode,
+        http::{
+            header::{CONTENT_TYPE, REFERER, USER_AGENT},
+            StatusCode,
+        },
         middleware::{ErrorHandlerResponse, ErrorHandlers},
-        web, HttpResponse, ResponseError, Result as ActixResult,
+        web, HttpRequest, HttpResponse, ResponseError, Result as ActixResult,
     },
     csv::Error as CsvError,
     derive_more::Display,
@@ -17,10 +22,12 @@ use {
         address::AddressError, error::Error as EmailError,
         transport::sendmail::Error as SendmailError, transport::stub::Error as StubTransportError,
     },
-    log::error,
+    log::{debug, error, warn},
+    openssl::error::ErrorStack,
     serde_json::{json, Error as SerdeError},
     std::io::Error as IoError,
-    tinytemplate::{error::Error as TemplateError, TinyTemplate},
+    tinytemplate::error::Error as TemplateError,
+    toml::de::Error as TomlError,
 };
 
 #[derive(Debug, Display)]
@@ -30,9 +37,9 @@ pub enum Error {
     #[display(fmt = "Error with io: {}", _0)]
     Io(IoError),
     #[display(fmt = "Error inserting record")]
-    Add(AddParams),
+    Add(Box<AddParams>),
     #[display(fmt = "Error updating record")]
-    Update(RsvpParams),
+    Update(Box<RsvpParams>),
     #[display(fmt = "Error on template: {}", _0)]
     Template(TemplateError),
     #[display(fmt = "Error on email: {}", _0)]
@@ -45,6 +52,56 @@ pub enum Error {
     Stub(StubTransportError),
     #[display(fmt = "Error on serde: {}", _0)]
     Serde(SerdeError),
+    #[display(fmt = "Error with config: {}", _0)]
+    Config(TomlError),
+    #[display(fmt = "Error with git: {}", _0)]
+    Git(String),
+    #[display(fmt = "Error uploading backup: {}", _0)]
+    Upload(String),
+    #[display(fmt = "Error with DKIM: {}", _0)]
+    Dkim(String),
+    #[display(fmt = "Error with ACME: {}", _0)]
+    Acme(String),
+    #[display(fmt = "Error with TLS: {}", _0)]
+    Tls(ErrorStack),
+    #[display(fmt = "Timed out waiting for the CSV file")]
+    Lock,
+    #[display(fmt = "Invalid query: {}", _0)]
+    Query(String),
+    #[display(
+        fmt = "Sorry, there are no more {} meals left, please choose another",
+        _0
+    )]
+    MealFull(String),
+    #[display(
+        fmt = "Sorry, the shuttle from {} is full, please choose another pickup",
+        _0
+    )]
+    ShuttleFull(String),
+    #[display(fmt = "The guest list is full")]
+    Full,
+    #[display(fmt = "Please fill in {}", _0)]
+    Required(String),
+    /// Something submitted that can't be accepted, with what to fix
+    #[display(fmt = "{}", _0)]
+    Invalid(String),
+    #[display(fmt = "Error with the session: {}", _0)]
+    Session(String),
+    /// The server can't start with this setup, with what to fix
+    #[display(fmt = "{}", _0)]
+    Startup(String),
+    #[cfg(feature = "tera")]
+    #[display(fmt = "Error on tera template: {}", _0)]
+    Tera(tera::Error),
+    #[cfg(feature = "images")]
+    #[display(fmt = "Error with image: {}", _0)]
+    Image(image::ImageError),
+    #[cfg(feature = "xlsx")]
+    #[display(fmt = "Error with workbook: {}", _0)]
+    Xlsx(rust_xlsxwriter::XlsxError),
+    #[cfg(feature = "pdf")]
+    #[display(fmt = "Error with QR code: {}", _0)]
+    Qr(qrcode::types::QrError),
 }
 
 impl From<CsvError> for Error {
@@ -53,6 +110,18 @@ impl From<CsvError> for Error {
     }
 }
 
+impl From<SessionGetError> for Error {
+    fn from(error: SessionGetError) -> Self {
+        Self::Session(error.to_string())
+    }
+}
+
+impl From<SessionInsertError> for Error {
+    fn from(error: SessionInsertError) -> Self {
+        Self::Session(error.to_string())
+    }
+}
+
 impl From<IoError> for Error {
     fn from(error: IoError) -> Self {
         Self::Io(error)
@@ -95,51 +164,175 @@ impl From<SerdeError> for Error {
     }
 }
 
-impl ResponseError for Error {}
+impl From<TomlError> for Error {
+    fn from(error: TomlError) -> Self {
+        Self::Config(error)
+    }
+}
+
+impl From<ErrorStack> for Error {
+    fn from(error: ErrorStack) -> Self {
+        Self::Tls(error)
+    }
+}
+
+#[cfg(feature = "tera")]
+impl From<tera::Error> for Error {
+    fn from(error: tera::Error) -> Self {
+        Self::Tera(error)
+    }
+}
+
+#[cfg(feature = "images")]
+impl From<image::ImageError> for Error {
+    fn from(error: image::ImageError) -> Self {
+        Self::Image(error)
+    }
+}
+
+#[cfg(feature = "xlsx")]
+impl From<rust_xlsxwriter::XlsxError> for Error {
+    fn from(error: rust_xlsxwriter::XlsxError) -> Self {
+        Self::Xlsx(error)
+    }
+}
+
+#[cfg(feature = "pdf")]
+impl From<qrcode::types::QrError> for Error {
+    fn from(error: qrcode::types::QrError) -> Self {
+        Self::Qr(error)
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Query(_) => StatusCode::BAD_REQUEST,
+            Self::MealFull(_) | Self::ShuttleFull(_) => StatusCode::CONFLICT,
+            Self::Full => StatusCode::INSUFFICIENT_STORAGE,
+            Self::Required(_) | Self::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Statuses given an HTML error page, unless the response already has one or
+/// is JSON
+static ERROR_PAGE_STATUSES: &[StatusCode] = &[
+    StatusCode::BAD_REQUEST,
+    StatusCode::FORBIDDEN,
+    StatusCode::NOT_FOUND,
+    StatusCode::METHOD_NOT_ALLOWED,
+    StatusCode::INTERNAL_SERVER_ERROR,
+    StatusCode::SERVICE_UNAVAILABLE,
+];
 
 // Custom error handlers, to return HTML responses when an error occurs.
 pub fn error_handlers<B: MessageBody + 'static>() -> ErrorHandlers<B> {
-    ErrorHandlers::new()
-        .handler(StatusCode::NOT_FOUND, not_found)
-        .handler(StatusCode::INTERNAL_SERVER_ERROR, internal_server_error)
+    ERROR_PAGE_STATUSES
+        .iter()
+        .fold(ErrorHandlers::new(), |handlers, status| {
+            handlers.handler(*status, error_page)
+        })
 }
 
-// Error handler for a 404 Page not found error.
-fn not_found<B: MessageBody + 'static>(
-    res: ServiceResponse<B>,
-) -> ActixResult<ErrorHandlerResponse<B>> {
-    error!("{:?}", res.request());
-    error!("{:?}", res.response());
-    let status = res.status();
-    let (request, _) = res.into_parts();
-    let tt = request
-        .app_data::<web::Data<AppState<'_>>>()
-        .map(|t| &t.get_ref().tt);
-    let response = get_error_response(tt, status, "Page not found");
-    let res = ServiceResponse::new(request, response).map_into_right_body();
-    Ok(ErrorHandlerResponse::Response(res))
+/// Message shown for a status when the config doesn't have one
+fn default_message(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::NOT_FOUND => "Page not found",
+        StatusCode::INTERNAL_SERVER_ERROR => "Internal error",
+        status => status.canonical_reason().unwrap_or("Error"),
+    }
 }
 
-// Error handler for a 500 Internal Error
-fn internal_server_error<B: MessageBody + 'static>(
+// Error handler rendering the page for any of the statuses above.
+fn error_page<B: MessageBody + 'static>(
     res: ServiceResponse<B>,
 ) -> ActixResult<ErrorHandlerResponse<B>> {
-    error!("{:?}", res.request());
-    error!("{:?}", res.response());
+    let content_type = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if content_type.starts_with("application/json") || content_type.starts_with("text/html") {
+        return Ok(ErrorHandlerResponse::Response(res.map_into_left_body()));
+    }
     let status = res.status();
+    let state = res.request().app_data::<web::Data<AppState<'_>>>();
+    match status {
+        StatusCode::NOT_FOUND => log_not_found(res.request(), state),
+        _ => {
+            error!("{:?}", res.request());
+            error!("{:?}", res.response());
+        }
+    }
+    // the reason for a bad request is worth showing, unlike internal errors
+    let reason = match status.is_client_error() {
+        true => res.response().error().map(|e| e.to_string()),
+        false => None,
+    };
     let (request, _) = res.into_parts();
-    let tt = request
-        .app_data::<web::Data<AppState<'_>>>()
-        .map(|t| &t.get_ref().tt);
-    let response = get_error_response(tt, status, "Internal error");
+    let state = request.app_data::<web::Data<AppState<'_>>>();
+    let page = state.and_then(|s| s.config.error_pages.get(status.as_str()));
+    let message = page
+        .and_then(|page| page.message.clone())
+        .or(reason)
+        .unwrap_or_else(|| default_message(status).to_string());
+    let template = match page.and_then(|page| page.template.as_ref()) {
+        Some(_) => error_template(status),
+        None => "error.html".to_string(),
+    };
+    let tt = state.map(|s| s.get_ref().tt.as_ref());
+    let id = request_id(&request).0;
+    let response = get_error_response(tt, status, &id, &template, &message);
     let res = ServiceResponse::new(request, response).map_into_right_body();
     Ok(ErrorHandlerResponse::Response(res))
 }
 
+/// Log a missing page briefly, quietly if it's a scanner's probe, and count it
+/// as `rsvp_not_found_total` by class
+fn log_not_found(req: &HttpRequest, state: Option<&web::Data<AppState<'_>>>) {
+    let path = req.path();
+    let scanner = state.is_some_and(|s| s.config.not_found.is_scanner(path));
+    let header = |name| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("-")
+    };
+    if scanner {
+        debug!("Scanner probe {} {}", req.method(), path);
+    } else {
+        warn!(
+            "Not found: {} {} (referer {}, user agent {})",
+            req.method(),
+            path,
+            header(REFERER),
+            header(USER_AGENT)
+        );
+    }
+    if let Some(state) = state {
+        let class = match scanner {
+            true => "scanner",
+            false => "missing",
+        };
+        state
+            .metrics
+            .increment("rsvp_not_found_total", &[("class", class)]);
+    }
+}
+
+/// Name of the template configured for a status in `error_pages`
+pub fn error_template(status: StatusCode) -> String {
+    format!("error-{}.html", status.as_str())
+}
+
 // Generic error handler.
 fn get_error_response(
-    tt: Option<&TinyTemplate<'_>>,
+    tt: Option<&dyn Templates>,
     status: StatusCode,
+    request_id: &str,
+    template: &str,
     error: &str,
 ) -> HttpResponse<BoxBody> {
     // Provide a fallback to a simple plain text response in case an error occurs during the
@@ -155,13 +348,17 @@ fn get_error_response(
             let ctx = json!({
                 "error" : error.to_string(),
                 "status_code" : status.as_str().to_string(),
+                "request_id" : request_id,
+            });
+            let body = tt.render(template, &ctx).or_else(|e| {
+                error!("Could not render {}: {}", template, e);
+                tt.render("error.html", &ctx)
             });
-            let body = tt.render("error.html", &ctx);
 
             match body {
-                Ok(body) => HttpResponse::new(status)
-                    .set_body(body)
-                    .map_into_boxed_body(),
+                Ok(body) => HttpResponse::build(status)
+                    .content_type("text/html")
+                    .body(body),
                 Err(_) => fallback(error),
             }
         }
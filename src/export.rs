@@ -0,0 +1,416 @@
+use {
+    crate::{
+        config::ExportConfig,
+        csvdb::HEADER_LINE,
+        error::Error,
+        model::{EmailKind, ExportParams, RsvpModel},
+    },
+    csv::WriterBuilder,
+    serde::{Deserialize, Serialize},
+    serde_json::Value,
+};
+#[cfg(feature = "xlsx")]
+use {
+    crate::{dietary, timezone::Timezone},
+    chrono::{DateTime, Utc},
+    rust_xlsxwriter::{ExcelDateTime, Format, Workbook},
+};
+
+/// Mailing list services with their own CSV import format
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MailingList {
+    Mailchimp,
+    Buttondown,
+}
+impl MailingList {
+    pub fn filename(&self) -> &'static str {
+        match self {
+            Self::Mailchimp => "mailchimp.csv",
+            Self::Buttondown => "buttondown.csv",
+        }
+    }
+
+    fn header(&self) -> [&'static str; 3] {
+        match self {
+            Self::Mailchimp => ["Email Address", "First Name", "Tags"],
+            Self::Buttondown => ["email", "first_name", "tags"],
+        }
+    }
+}
+
+/// Guests attending any event who haven't opted out of updates, with a
+/// working email address
+pub fn subscribers(records: &[RsvpModel]) -> impl Iterator<Item = &RsvpModel> {
+    records
+        .iter()
+        .filter(|r| r.is_attending_any() && r.accepts_email(EmailKind::Announcement))
+}
+
+/// CSV of the subscribers, ready to import into the mailing list
+pub fn export(records: &[RsvpModel], list: MailingList) -> Result<String, Error> {
+    let mut wtr = WriterBuilder::new().from_writer(vec![]);
+    wtr.write_record(list.header())?;
+    for record in subscribers(records) {
+        let first_name = record.name.split_whitespace().next().unwrap_or_default();
+        wtr.write_record([record.email.trim(), first_name, record.tags.as_str()])?;
+    }
+    let bytes = wtr.into_inner().map_err(|e| Error::from(e.into_error()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Records in the same CSV format as the database file, with its header
+pub fn csv(records: &[RsvpModel]) -> Result<String, Error> {
+    let mut wtr = WriterBuilder::new().from_writer(vec![]);
+    for record in records {
+        wtr.serialize(record)?;
+    }
+    let bytes = wtr.into_inner().map_err(|e| Error::from(e.into_error()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// A column worked out from a party's answers rather than stored
+type DerivedColumn = (&'static str, fn(&RsvpModel) -> String);
+
+/// Columns for exports that only need totals, like the caterer's
+static DERIVED_COLUMNS: &[DerivedColumn] = &[
+    ("headcount", |r| {
+        (u32::from(r.is_attending_any()) + u32::from(r.plus_one_attending_any())).to_string()
+    }),
+    ("meals", |r| r.meals().join(", ")),
+];
+
+/// Every column an export can have, the CSV file's then the derived ones
+pub fn column_names() -> impl Iterator<Item = &'static str> {
+    HEADER_LINE
+        .split(',')
+        .chain(DERIVED_COLUMNS.iter().map(|(name, _)| *name))
+}
+
+/// Columns to export, from the query's comma-separated list or a preset,
+/// or `None` for the whole CSV file
+pub fn selected_columns(
+    config: &ExportConfig,
+    params: &ExportParams,
+) -> Result<Option<Vec<String>>, Error> {
+    let columns = match (&params.columns, &params.preset) {
+        (Some(_), Some(_)) => {
+            return Err(Error::Query(
+                "choose either columns or a preset, not both".to_string(),
+            ))
+        }
+        (Some(columns), None) => columns
+            .split(',')
+            .map(|column| column.trim().to_string())
+            .filter(|column| !column.is_empty())
+            .collect(),
+        (None, Some(preset)) => config
+            .presets
+            .get(preset)
+            .cloned()
+            .ok_or_else(|| Error::Query(format!("no export preset {:?}", preset)))?,
+        (None, None) => return Ok(None),
+    };
+    check_columns(&columns)?;
+    Ok(Some(columns))
+}
+
+/// Whether every column exists, so a typo doesn't export a blank column
+pub fn check_columns(columns: &[String]) -> Result<(), Error> {
+    if columns.is_empty() {
+        return Err(Error::Query("no columns to export".to_string()));
+    }
+    match columns
+        .iter()
+        .find(|column| !column_names().any(|name| name == column.as_str()))
+    {
+        Some(unknown) => Err(Error::Query(format!(
+            "no column {:?}, choose from {}",
+            unknown,
+            column_names().collect::<Vec<_>>().join(", ")
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Records with only the chosen columns, in the order given, named as in
+/// the CSV file
+pub fn columns_csv(records: &[RsvpModel], columns: &[String]) -> Result<String, Error> {
+    check_columns(columns)?;
+    let mut wtr = WriterBuilder::new().from_writer(vec![]);
+    wtr.write_record(columns)?;
+    for record in records {
+        let fields = serde_json::to_value(record)?;
+        wtr.write_record(columns.iter().map(|column| {
+            match DERIVED_COLUMNS.iter().find(|(name, _)| name == column) {
+                Some((_, cell)) => cell(record),
+                None => match &fields[column.as_str()] {
+                    Value::String(text) => text.clone(),
+                    Value::Null => String::default(),
+                    value => value.to_string(),
+                },
+            }
+        }))?;
+    }
+    let bytes = wtr.into_inner().map_err(|e| Error::from(e.into_error()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// A typed spreadsheet cell
+#[cfg(feature = "xlsx")]
+enum Cell<'a> {
+    Text(&'a str),
+    Bool(bool),
+    Number(u32),
+    Time(Option<DateTime<Utc>>),
+}
+
+/// A column's header, and its cell for a guest
+#[cfg(feature = "xlsx")]
+type Column = (&'static str, fn(&RsvpModel) -> Cell<'_>);
+
+/// Columns of the sheet with every guest
+#[cfg(feature = "xlsx")]
+static GUEST_COLUMNS: &[Column] = &[
+    ("Name", |r| Cell::Text(&r.name)),
+    ("Email", |r| Cell::Text(&r.email)),
+    ("Invited", |r| Cell::Number(r.invited)),
+    ("Attending", |r| Cell::Bool(r.attending)),
+    ("Attending secondary", |r| Cell::Bool(r.attending_secondary)),
+    ("Attending tertiary", |r| Cell::Bool(r.attending_tertiary)),
+    ("Attending private", |r| Cell::Bool(r.attending_private)),
+    ("Meal choice", |r| Cell::Text(&r.meal_choice)),
+    ("Dietary", |r| Cell::Text(&r.dietary)),
+    ("Dietary restrictions", |r| {
+        Cell::Text(&r.dietary_restrictions)
+    }),
+    ("Plus-one name", |r| Cell::Text(&r.plus_one_name)),
+    ("Plus-one email", |r| Cell::Text(&r.plus_one_email)),
+    ("Plus-one attending", |r| Cell::Bool(r.plus_one_attending)),
+    ("Plus-one attending secondary", |r| {
+        Cell::Bool(r.plus_one_attending_secondary)
+    }),
+    ("Plus-one attending tertiary", |r| {
+        Cell::Bool(r.plus_one_attending_tertiary)
+    }),
+    ("Plus-one attending private", |r| {
+        Cell::Bool(r.plus_one_attending_private)
+    }),
+    ("Plus-one meal choice", |r| {
+        Cell::Text(&r.plus_one_meal_choice)
+    }),
+    ("Plus-one dietary", |r| Cell::Text(&r.plus_one_dietary)),
+    ("Plus-one dietary restrictions", |r| {
+        Cell::Text(&r.plus_one_dietary_restrictions)
+    }),
+    ("Comments", |r| Cell::Text(&r.comments)),
+    ("Needs lodging", |r| Cell::Bool(r.needs_lodging)),
+    ("Shuttle", |r| Cell::Text(&r.shuttle)),
+    ("Kids", |r| Cell::Number(r.kids)),
+    ("Kids' ages", |r| Cell::Text(&r.kids_ages)),
+    ("Thank-you sent", |r| Cell::Bool(r.thank_you_sent)),
+    ("Gift", |r| Cell::Text(&r.gift)),
+    ("Tags", |r| Cell::Text(&r.tags)),
+    ("Notes", |r| Cell::Text(&r.notes)),
+    ("Email verified", |r| Cell::Bool(r.email_verified)),
+    ("Created", |r| Cell::Time(Some(r.created_at))),
+    ("Updated", |r| Cell::Time(Some(r.updated_at))),
+    ("Responded", |r| Cell::Time(r.responded_at)),
+    ("Source", |r| Cell::Text(r.source.map_or("", |s| s.value()))),
+    ("Checked in", |r| Cell::Time(r.checked_in_at)),
+];
+
+/// A sheet's name, and whether a guest and their plus-one are coming to its
+/// event
+#[cfg(feature = "xlsx")]
+type EventSheet = (&'static str, fn(&RsvpModel) -> (bool, bool));
+
+/// Sheets of the guests coming to each event
+#[cfg(feature = "xlsx")]
+static EVENT_SHEETS: &[EventSheet] = &[
+    ("Main event", |r| (r.attending, r.plus_one_attending)),
+    ("Secondary event", |r| {
+        (r.attending_secondary, r.plus_one_attending_secondary)
+    }),
+    ("Tertiary event", |r| {
+        (r.attending_tertiary, r.plus_one_attending_tertiary)
+    }),
+    ("Private event", |r| {
+        (r.attending_private, r.plus_one_attending_private)
+    }),
+];
+
+/// An Excel workbook of the guests, with a sheet of everyone and one of the
+/// guests coming to each event, and times in the event's time zone
+#[cfg(feature = "xlsx")]
+pub fn workbook(records: &[RsvpModel], timezone: Timezone) -> Result<Vec<u8>, Error> {
+    let mut workbook = Workbook::new();
+    let header = Format::new().set_bold();
+    let time_format = Format::new().set_num_format("yyyy-mm-dd hh:mm");
+    let time = |at: DateTime<Utc>| {
+        let local = at.with_timezone(&timezone.0).naive_local();
+        ExcelDateTime::from_timestamp(local.and_utc().timestamp())
+    };
+
+    let sheet = workbook.add_worksheet().set_name("Guests")?;
+    for (col, (name, _)) in (0..).zip(GUEST_COLUMNS) {
+        let name = match *name {
+            "Created" | "Updated" | "Responded" | "Checked in" => {
+                format!("{} ({})", name, timezone)
+            }
+            name => name.to_string(),
+        };
+        sheet.write_string_with_format(0, col, name, &header)?;
+    }
+    for (row, record) in (1..).zip(records) {
+        for (col, (_, cell)) in (0..).zip(GUEST_COLUMNS) {
+            match cell(record) {
+                Cell::Text(text) => sheet.write_string(row, col, text)?,
+                Cell::Bool(value) => sheet.write_boolean(row, col, value)?,
+                Cell::Number(number) => sheet.write_number(row, col, number)?,
+                Cell::Time(Some(at)) => {
+                    sheet.write_datetime_with_format(row, col, time(at)?, &time_format)?
+                }
+                Cell::Time(None) => sheet,
+            };
+        }
+    }
+    sheet.set_freeze_panes(1, 0)?.autofit();
+
+    for (name, coming) in EVENT_SHEETS {
+        let sheet = workbook.add_worksheet().set_name(*name)?;
+        let headers = [
+            "Name",
+            "Plus-one name",
+            "Seats",
+            "Meal choice",
+            "Dietary restrictions",
+            "Plus-one meal choice",
+            "Plus-one dietary restrictions",
+        ];
+        for (col, name) in (0..).zip(headers) {
+            sheet.write_string_with_format(0, col, name, &header)?;
+        }
+        let guests = records.iter().filter_map(|r| match coming(r) {
+            (true, plus_one) => Some((r, plus_one)),
+            (false, _) => None,
+        });
+        for (row, (record, plus_one)) in (1..).zip(guests) {
+            sheet.write_string(row, 0, &record.name)?;
+            sheet.write_number(row, 2, 1 + u32::from(plus_one))?;
+            sheet.write_string(row, 3, &record.meal_choice)?;
+            let dietary = dietary::describe(&record.dietary, &record.dietary_restrictions);
+            sheet.write_string(row, 4, &dietary)?;
+            if plus_one {
+                sheet.write_string(row, 1, &record.plus_one_name)?;
+                sheet.write_string(row, 5, &record.plus_one_meal_choice)?;
+                let dietary = dietary::describe(
+                    &record.plus_one_dietary,
+                    &record.plus_one_dietary_restrictions,
+                );
+                sheet.write_string(row, 6, &dietary)?;
+            }
+        }
+        sheet.set_freeze_panes(1, 0)?.autofit();
+    }
+    workbook.save_to_buffer().map_err(Error::from)
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            csvdb::{test::test_db, CsvDb},
+            model::EmailStatus,
+        },
+    };
+
+    #[test]
+    fn mailing_lists() {
+        let mut records = test_db(5).get_all().unwrap();
+        records[0].tags = "family,friends".to_string();
+        records[2].announcements_opt_out = true;
+        records[4].email_status = EmailStatus::Bounced;
+        records.push(RsvpModel {
+            name: "No Email".to_string(),
+            email: String::default(),
+            ..records[0].clone()
+        });
+
+        let csv = export(&records, MailingList::Mailchimp).unwrap();
+        assert_eq!(
+            csv,
+            "Email Address,First Name,Tags\n\
+             john0@john.john,John-0,\"family,friends\"\n\
+             john3@john.john,John-3,\n"
+        );
+        let csv = export(&records, MailingList::Buttondown).unwrap();
+        assert!(csv.starts_with("email,first_name,tags\njohn0@john.john,"));
+    }
+
+    #[test]
+    fn chosen_columns() {
+        let records = test_db(2).get_all().unwrap();
+        let columns = ["name", "headcount", "meal_choice", "meals"].map(String::from);
+        assert_eq!(
+            columns_csv(&records, &columns).unwrap(),
+            "name,headcount,meal_choice,meals\n\
+             John-0,2,Meat,\"Meat, Veggie\"\n\
+             John-1,0,,\n"
+        );
+        let csv = columns_csv(&records, &["checked_in_at".to_string()]).unwrap();
+        assert_eq!(csv, "checked_in_at\n\"\"\n\"\"\n");
+        assert!(matches!(
+            columns_csv(&records, &["nmae".to_string()]),
+            Err(Error::Query(_))
+        ));
+        assert!(matches!(columns_csv(&records, &[]), Err(Error::Query(_))));
+    }
+
+    #[test]
+    fn presets() {
+        let config = ExportConfig {
+            presets: [(
+                "caterer".to_string(),
+                vec!["name".to_string(), "headcount".to_string()],
+            )]
+            .into(),
+        };
+        let select = |columns: Option<&str>, preset: Option<&str>| {
+            let params = ExportParams {
+                columns: columns.map(String::from),
+                preset: preset.map(String::from),
+            };
+            selected_columns(&config, &params)
+        };
+        assert_eq!(select(None, None).unwrap(), None);
+        assert_eq!(
+            select(Some(" email, name,"), None).unwrap().unwrap(),
+            ["email", "name"]
+        );
+        assert_eq!(
+            select(None, Some("caterer")).unwrap().unwrap(),
+            ["name", "headcount"]
+        );
+        assert!(select(None, Some("florist")).is_err());
+        assert!(select(Some("name"), Some("caterer")).is_err());
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let records = test_db(2).get_all().unwrap();
+        assert_eq!(CsvDb::parse(&csv(&records).unwrap()).unwrap(), records);
+    }
+
+    #[cfg(feature = "xlsx")]
+    #[test]
+    fn workbook() {
+        let mut records = test_db(3).get_all().unwrap();
+        records[1].checked_in_at = Some(records[1].updated_at);
+        let bytes = super::workbook(&records, Timezone::default()).unwrap();
+        // a zip archive, like every .xlsx file
+        assert!(bytes.starts_with(b"PK\x03\x04"));
+        assert!(super::workbook(&[], Timezone::default()).is_ok());
+    }
+}
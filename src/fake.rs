@@ -0,0 +1,202 @@
+use {
+    crate::{
+        csvdb::CsvDb,
+        dietary::Severity,
+        error::Error,
+        model::{name_key, RsvpModel, RsvpParams},
+        source::Source,
+    },
+    chrono::Duration,
+    rand::{seq::SliceRandom, Rng},
+    std::collections::HashSet,
+    tracing::instrument,
+};
+
+const FIRST_NAMES: &[&str] = &[
+    "Alice", "Amir", "Ana", "Ben", "Carlos", "Chloe", "David", "Elena", "Emma", "Farah", "Grace",
+    "Hiro", "Isabel", "James", "Jin", "Kofi", "Laura", "Liam", "Maya", "Mohammed", "Nina",
+    "Olivia", "Omar", "Priya", "Rosa", "Sam", "Sofia", "Tom", "Yuki", "Zoe",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Adams", "Bauer", "Chen", "Costa", "Dubois", "Garcia", "Hansen", "Ibrahim", "Jones", "Kim",
+    "Kowalski", "Lopez", "Martin", "Mensah", "Murphy", "Nguyen", "Novak", "Okafor", "Patel",
+    "Rossi", "Santos", "Schmidt", "Silva", "Smith", "Tanaka", "Walsh", "Wong", "Yilmaz",
+];
+
+const MEALS: &[&str] = &["Meat", "Fish", "Veggie"];
+
+/// Checked off the default dietary checklist
+const DIETS: &[&str] = &["Vegetarian", "Vegan", "Gluten-free", "Nut allergy"];
+
+/// Written in under "Other"
+const RESTRICTIONS: &[&str] = &["Lactose free", "No shellfish", "Kosher"];
+
+const COMMENTS: &[&str] = &[
+    "Can't wait!",
+    "Congratulations!",
+    "We'll be there early to help set up.",
+    "Is there parking nearby?",
+    "So happy for you both!",
+];
+
+const TAGS: &[&str] = &["family", "friends", "work"];
+
+/// Guests responding over the past month, with names whose keys aren't in
+/// `taken`
+pub fn fake_guests<R: Rng>(count: usize, taken: &HashSet<String>, rng: &mut R) -> Vec<RsvpParams> {
+    let mut names = taken.clone();
+    (0..count)
+        .map(|i| {
+            let first = FIRST_NAMES.choose(rng).unwrap();
+            let last = LAST_NAMES.choose(rng).unwrap();
+            let mut name = format!("{} {}", first, last);
+            let mut suffix = i;
+            while names.contains(&name_key(&name)) {
+                suffix += count;
+                name = format!("{} {} {}", first, last, suffix);
+            }
+            names.insert(name_key(&name));
+            let attending = rng.gen_bool(0.8);
+            let plus_one_attending = attending && rng.gen_bool(0.4);
+            let attending_secondary = attending && rng.gen_bool(0.6);
+            let attending_tertiary = attending && rng.gen_bool(0.3);
+            let dietary = pick(rng, DIETS, 0.2);
+            let plus_one_dietary = if plus_one_attending {
+                pick(rng, DIETS, 0.2)
+            } else {
+                String::default()
+            };
+            let plus_one_name = if plus_one_attending {
+                format!(
+                    "{} {}",
+                    FIRST_NAMES.choose(rng).unwrap(),
+                    LAST_NAMES.choose(rng).unwrap()
+                )
+            } else {
+                String::default()
+            };
+            // some plus-ones share the guest's inbox
+            let plus_one_email = if plus_one_attending && rng.gen_bool(0.5) {
+                format!(
+                    "{}{}@example.com",
+                    plus_one_name.to_lowercase().replace(' ', "."),
+                    i
+                )
+            } else {
+                String::default()
+            };
+            RsvpParams {
+                email: format!(
+                    "{}.{}{}@example.com",
+                    first.to_lowercase(),
+                    last.to_lowercase(),
+                    i
+                ),
+                attending,
+                attending_secondary,
+                attending_tertiary,
+                meal_choice: pick(rng, MEALS, 1.0),
+                dietary_restrictions: pick(rng, RESTRICTIONS, 0.05),
+                plus_one_attending,
+                plus_one_attending_secondary: plus_one_attending
+                    && attending_secondary
+                    && rng.gen_bool(0.8),
+                plus_one_attending_tertiary: plus_one_attending
+                    && attending_tertiary
+                    && rng.gen_bool(0.8),
+                plus_one_name,
+                plus_one_meal_choice: if plus_one_attending {
+                    pick(rng, MEALS, 1.0)
+                } else {
+                    String::default()
+                },
+                plus_one_dietary_restrictions: if plus_one_attending {
+                    pick(rng, RESTRICTIONS, 0.05)
+                } else {
+                    String::default()
+                },
+                comments: pick(rng, COMMENTS, 0.3),
+                language: String::default(),
+                dietary_severity: severity(rng, &dietary),
+                plus_one_dietary_severity: severity(rng, &plus_one_dietary),
+                dietary,
+                plus_one_dietary,
+                plus_one_email,
+                needs_lodging: attending && rng.gen_bool(0.3),
+                shuttle: String::default(),
+                attending_private: false,
+                plus_one_attending_private: false,
+                kids: 0,
+                kids_ages: String::default(),
+                source: Some(Source::Cli),
+                name,
+            }
+        })
+        .collect()
+}
+
+/// How severe a fake guest's dietary restrictions are, if they have any
+fn severity<R: Rng>(rng: &mut R, dietary: &str) -> Severity {
+    if dietary.is_empty() {
+        Severity::default()
+    } else {
+        *Severity::ALL.choose(rng).unwrap()
+    }
+}
+
+/// One of `choices` with the given probability, or an empty string
+fn pick<R: Rng>(rng: &mut R, choices: &[&str], probability: f64) -> String {
+    if rng.gen_bool(probability) {
+        choices.choose(rng).unwrap().to_string()
+    } else {
+        String::default()
+    }
+}
+
+/// Add `count` fake guests to the file, for load tests and working on the
+/// dashboard with realistic data.  Existing guests are left alone.
+#[instrument(skip(db))]
+pub fn seed(db: &mut CsvDb, count: usize) -> Result<Vec<RsvpModel>, Error> {
+    let mut rng = rand::thread_rng();
+    let now = db.now();
+    let taken = db
+        .get_all()?
+        .into_iter()
+        .map(|r| r.name_key)
+        .collect::<HashSet<_>>();
+    let records = fake_guests(count, &taken, &mut rng)
+        .iter()
+        .map(|params| {
+            let datetime = now - Duration::minutes(rng.gen_range(0..60 * 24 * 30));
+            RsvpModel {
+                tags: pick(&mut rng, TAGS, 0.7),
+                ..RsvpModel::new_with_rsvp(params, datetime)
+            }
+        })
+        .collect::<Vec<_>>();
+    db.append(&records)?;
+    Ok(records)
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::csvdb::test::test_db};
+
+    #[test]
+    fn seed() {
+        let mut db = test_db(3);
+        let records = super::seed(&mut db, 100).unwrap();
+        assert_eq!(records.len(), 100);
+
+        let all = db.get_all().unwrap();
+        assert_eq!(all.len(), 103);
+        let names = all
+            .iter()
+            .map(|r| r.name_key.clone())
+            .collect::<HashSet<_>>();
+        assert_eq!(names.len(), 103);
+        assert!(records.iter().all(|r| r.email.ends_with("@example.com")));
+        assert!(records.iter().all(|r| r.created_at <= db.now()));
+    }
+}
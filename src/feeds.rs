@@ -0,0 +1,189 @@
+use {
+    crate::{
+        audit::AuditEntry,
+        lockout::LOCKOUT_ACTION,
+        model::{name_key, RsvpModel},
+    },
+    chrono::{DateTime, SecondsFormat, Utc},
+};
+
+/// Longest line allowed by iCalendar, in bytes, before folding
+const ICS_LINE_LIMIT: usize = 75;
+
+/// Most audit log entries in the Atom feed
+const FEED_ENTRIES: usize = 50;
+
+/// Short description of a guest's answer, e.g. "Maria +1 attending"
+pub fn summary(record: &RsvpModel) -> String {
+    if !record.is_attending_any() {
+        format!("{} not attending", record.name)
+    } else if record.plus_one_attending_any() {
+        format!("{} +1 attending", record.name)
+    } else {
+        format!("{} attending", record.name)
+    }
+}
+
+/// iCalendar feed with an all-day event for each RSVP, on the day it was
+/// last submitted
+pub fn ics(records: &[RsvpModel]) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//actix-web-rsvp//RSVPs//EN".to_string(),
+        "X-WR-CALNAME:RSVPs".to_string(),
+    ];
+    for record in records {
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}@actix-web-rsvp", record.token),
+            format!("DTSTAMP:{}", record.updated_at.format("%Y%m%dT%H%M%SZ")),
+            format!("DTSTART;VALUE=DATE:{}", record.updated_at.format("%Y%m%d")),
+            format!("SUMMARY:{}", ics_escape(&summary(record))),
+            format!(
+                "DESCRIPTION:{}",
+                ics_escape(&format!(
+                    "Submitted {}\n{}",
+                    record.updated_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    record.comments
+                ))
+            ),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| ics_fold(line)).collect()
+}
+
+/// Escape text for an iCalendar property value
+pub fn ics_escape(text: &str) -> String {
+    text.trim()
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Split a content line into lines of at most 75 bytes, each continuation
+/// starting with a space, and end it with CRLF
+pub fn ics_fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > ICS_LINE_LIMIT {
+            folded.push_str("\r\n ");
+            len = 1;
+        }
+        folded.push(c);
+        len += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+/// Atom feed of the latest changes in the audit log, newest first, each with
+/// the guest's current answer
+pub fn atom(entries: &[AuditEntry], records: &[RsvpModel], updated: DateTime<Utc>) -> String {
+    let updated = entries.last().map_or(updated, |entry| entry.at);
+    let mut feed = format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\">\n",
+            "  <title>RSVPs</title>\n",
+            "  <id>urn:actix-web-rsvp:feed</id>\n",
+            "  <link href=\"/admin\"/>\n",
+            "  <updated>{}</updated>\n",
+        ),
+        updated.to_rfc3339_opts(SecondsFormat::Secs, true)
+    );
+    // entries are never rewritten, so their position in the log is a stable id
+    let latest = entries.iter().enumerate().rev().take(FEED_ENTRIES);
+    for (index, entry) in latest {
+        let key = name_key(&entry.name);
+        let content = match entry.action.as_str() {
+            LOCKOUT_ACTION => format!("{} looked up too many names not on the list", entry.name),
+            _ => records
+                .iter()
+                .find(|record| record.name_key == key)
+                .map(summary)
+                .unwrap_or_else(|| format!("{} is no longer on the guest list", entry.name)),
+        };
+        feed.push_str(&format!(
+            concat!(
+                "  <entry>\n",
+                "    <title>{} for {} by {}</title>\n",
+                "    <id>urn:actix-web-rsvp:audit:{}</id>\n",
+                "    <updated>{}</updated>\n",
+                "    <content type=\"text\">{}</content>\n",
+                "  </entry>\n",
+            ),
+            xml_escape(&entry.action),
+            xml_escape(&entry.name),
+            entry.entered_by,
+            index,
+            entry.at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            xml_escape(&content),
+        ));
+    }
+    feed.push_str("</feed>\n");
+    feed
+}
+
+pub fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{audit::EnteredBy, csvdb::test::test_db},
+    };
+
+    #[test]
+    fn ics_feed() {
+        let mut records = test_db(2).get_all().unwrap();
+        records[1].comments = "Sorry, we can't; ".repeat(10);
+        let feed = ics(&records);
+        assert!(feed.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(feed.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(feed.matches("BEGIN:VEVENT").count(), 2);
+        assert!(feed.contains("SUMMARY:John-0 +1 attending\r\n"));
+        assert!(feed.contains("SUMMARY:John-1 not attending\r\n"));
+        assert!(feed.contains("Sorry\\, we can't\\; "));
+        assert!(feed.split("\r\n").all(|line| line.len() <= ICS_LINE_LIMIT));
+    }
+
+    #[test]
+    fn atom_feed() {
+        let records = test_db(2).get_all().unwrap();
+        let entry = AuditEntry {
+            at: records[0].updated_at,
+            request_id: "Ab12Cd34".to_string(),
+            action: "RSVP".to_string(),
+            name: "John-0".to_string(),
+            entered_by: EnteredBy::Guest,
+        };
+        let entries = vec![
+            entry.clone(),
+            AuditEntry {
+                name: "<Gone> & Co".to_string(),
+                entered_by: EnteredBy::Admin,
+                ..entry
+            },
+        ];
+        let feed = atom(&entries, &records, Utc::now());
+        assert_eq!(feed.matches("<entry>").count(), 2);
+        assert!(feed.find("&lt;Gone&gt; &amp; Co").unwrap() < feed.find("John-0").unwrap());
+        assert!(feed.contains("<title>RSVP for John-0 by guest</title>"));
+        assert!(feed.contains("<content type=\"text\">John-0 +1 attending</content>"));
+        assert!(feed.contains("<id>urn:actix-web-rsvp:audit:1</id>"));
+
+        let feed = atom(&[], &records, entry.at);
+        assert!(!feed.contains("<entry>"));
+        assert!(feed.contains(&entry.at.to_rfc3339_opts(SecondsFormat::Secs, true)));
+    }
+}
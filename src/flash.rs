@@ -0,0 +1,162 @@
+use {
+    crate::{banner, error::Error, state::AppState},
+    actix_session::{Session, SessionExt},
+    actix_web::{
+        body::{self, EitherBody, MessageBody},
+        dev::{ServiceRequest, ServiceResponse},
+        error::ErrorInternalServerError,
+        http::header::CONTENT_TYPE,
+        middleware::Next,
+        web, Error as ActixError,
+    },
+    serde::{Deserialize, Serialize},
+    serde_json::json,
+};
+
+const KEY: &str = "flash";
+
+/// Most notices kept for the next page, so a browser that never loads one
+/// doesn't fill its cookie; the oldest go first
+const MAX_FLASHES: usize = 5;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Success,
+    Error,
+}
+impl Level {
+    /// How screen readers should announce it
+    fn role(self) -> &'static str {
+        match self {
+            Level::Success => "status",
+            Level::Error => "alert",
+        }
+    }
+}
+
+/// A notice for the page after a redirect, like "Your RSVP was updated"
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Flash {
+    pub level: Level,
+    pub text: String,
+}
+
+/// Show a notice on the next page this browser loads
+pub fn push(session: &Session, level: Level, text: impl Into<String>) -> Result<(), Error> {
+    let mut flashes = session.get::<Vec<Flash>>(KEY)?.unwrap_or_default();
+    flashes.push(Flash {
+        level,
+        text: text.into(),
+    });
+    let extra = flashes.len().saturating_sub(MAX_FLASHES);
+    flashes.drain(..extra);
+    session.insert(KEY, flashes)?;
+    Ok(())
+}
+
+pub fn success(session: &Session, text: impl Into<String>) -> Result<(), Error> {
+    push(session, Level::Success, text)
+}
+
+pub fn error(session: &Session, text: impl Into<String>) -> Result<(), Error> {
+    push(session, Level::Error, text)
+}
+
+/// The notices waiting for this browser, forgetting them so they're only
+/// shown once
+pub fn take(session: &Session) -> Vec<Flash> {
+    match session.get::<Vec<Flash>>(KEY) {
+        Ok(Some(flashes)) => {
+            session.remove(KEY);
+            flashes
+        }
+        _ => vec![],
+    }
+}
+
+/// Middleware adding the waiting notices to the top of the next page,
+/// whichever template or engine rendered it, using the shared
+/// `partials/flashes.html` template.  Redirects and downloads leave them for
+/// the page after.
+pub async fn show_flashes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, ActixError> {
+    let state = req.app_data::<web::Data<AppState<'_>>>().cloned();
+    let res = next.call(req).await?;
+    let state = match &state {
+        Some(state) => state,
+        None => return Ok(res.map_into_left_body()),
+    };
+    let is_page = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    if !is_page || !res.status().is_success() {
+        return Ok(res.map_into_left_body());
+    }
+    let flashes = take(&res.request().get_session());
+    if flashes.is_empty() {
+        return Ok(res.map_into_left_body());
+    }
+    let ctx = json!({
+        "flashes": flashes
+            .iter()
+            .map(|flash| json!({
+                "level": flash.level,
+                "role": flash.level.role(),
+                "text": flash.text,
+            }))
+            .collect::<Vec<_>>(),
+    });
+    let html = state.tt.render("partials/flashes.html", &ctx)?;
+    let (req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let bytes = body::to_bytes(body)
+        .await
+        .map_err(|e| ErrorInternalServerError(e.into().to_string()))?;
+    let page = banner::insert(&String::from_utf8_lossy(&bytes), &html);
+    let res = res.set_body(page).map_into_boxed_body();
+    Ok(ServiceResponse::new(req, res).map_into_right_body())
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, actix_web::test::TestRequest};
+
+    #[test]
+    fn flashes() {
+        let session = TestRequest::default().to_http_request().get_session();
+        assert!(take(&session).is_empty());
+        success(&session, "Your RSVP was updated").unwrap();
+        error(&session, "That song is already on the list").unwrap();
+        assert_eq!(
+            take(&session),
+            [
+                Flash {
+                    level: Level::Success,
+                    text: "Your RSVP was updated".to_string(),
+                },
+                Flash {
+                    level: Level::Error,
+                    text: "That song is already on the list".to_string(),
+                },
+            ]
+        );
+        assert!(take(&session).is_empty());
+
+        for i in 0..7 {
+            success(&session, format!("Saved {}", i)).unwrap();
+        }
+        let texts = take(&session)
+            .into_iter()
+            .map(|flash| flash.text)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            texts,
+            ["Saved 2", "Saved 3", "Saved 4", "Saved 5", "Saved 6"]
+        );
+    }
+}
@@ -0,0 +1,379 @@
+use {
+    crate::{
+        config::{FieldRule, FieldsConfig},
+        dietary::{self, Severity},
+        error::Error,
+        model::RsvpParams,
+    },
+    actix_web::{dev::Payload, error::ErrorBadRequest, web, FromRequest, HttpRequest},
+    serde::Serialize,
+    serde_json::{Map, Value},
+    std::{future::Future, ops::Deref, pin::Pin},
+};
+
+/// Checklist fields, which the form sends once for each box checked
+const CHECKLISTS: &[&str] = &["dietary", "plus_one_dietary"];
+
+/// When a field is shown on the RSVP form
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    Always,
+    /// The guest is coming to at least one event
+    Attending,
+    /// The plus-one is coming to at least one event with the guest
+    PlusOneAttending,
+}
+impl Condition {
+    pub fn holds(self, params: &RsvpParams) -> bool {
+        let attending = params.attending
+            || params.attending_secondary
+            || params.attending_tertiary
+            || params.attending_private;
+        match self {
+            Self::Always => true,
+            Self::Attending => attending,
+            Self::PlusOneAttending => {
+                (params.attending && params.plus_one_attending)
+                    || (params.attending_secondary && params.plus_one_attending_secondary)
+                    || (params.attending_tertiary && params.plus_one_attending_tertiary)
+                    || (params.attending_private && params.plus_one_attending_private)
+            }
+        }
+    }
+}
+
+/// One of the optional fields of the RSVP form
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    pub name: &'static str,
+    /// How to ask for it in an error, e.g. "Please fill in your email"
+    pub label: &'static str,
+    pub shown_when: Condition,
+    pub rule: FieldRule,
+}
+
+/// How the template should render a field
+#[derive(Clone, Debug, Serialize, PartialEq)]
+struct FieldContext {
+    required: bool,
+    hidden: bool,
+    shown_when: Condition,
+}
+
+/// The RSVP form's optional fields, with when they're shown and whether
+/// they're required.  Templates render it and submissions are checked
+/// against it, so a field the guest never saw is never required or stored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Form {
+    pub fields: Vec<Field>,
+    pub dietary_options: Vec<String>,
+}
+impl Form {
+    pub fn new(config: &FieldsConfig) -> Self {
+        let field = |name, label, shown_when, rule| Field {
+            name,
+            label,
+            shown_when,
+            rule,
+        };
+        Self {
+            fields: vec![
+                field("email", "your email", Condition::Always, config.email),
+                field(
+                    "meal_choice",
+                    "your meal",
+                    Condition::Attending,
+                    config.meal_choice,
+                ),
+                field(
+                    "dietary_restrictions",
+                    "your dietary restrictions",
+                    Condition::Attending,
+                    config.dietary_restrictions,
+                ),
+                field(
+                    "plus_one_name",
+                    "your plus-one's name",
+                    Condition::Attending,
+                    config.plus_one_name,
+                ),
+                field(
+                    "plus_one_email",
+                    "your plus-one's email",
+                    Condition::PlusOneAttending,
+                    config.plus_one_email,
+                ),
+                field(
+                    "plus_one_meal_choice",
+                    "your plus-one's meal",
+                    Condition::PlusOneAttending,
+                    config.plus_one_meal_choice,
+                ),
+                field(
+                    "plus_one_dietary_restrictions",
+                    "your plus-one's dietary restrictions",
+                    Condition::PlusOneAttending,
+                    config.plus_one_dietary_restrictions,
+                ),
+                field(
+                    "comments",
+                    "your comments",
+                    Condition::Always,
+                    config.comments,
+                ),
+            ],
+            dietary_options: config.dietary_options.clone(),
+        }
+    }
+
+    /// Each field by name, for the templates
+    pub fn context(&self) -> Value {
+        let fields = self
+            .fields
+            .iter()
+            .map(|field| {
+                let ctx = FieldContext {
+                    required: field.rule == FieldRule::Required,
+                    hidden: field.rule == FieldRule::Hidden,
+                    shown_when: field.shown_when,
+                };
+                (field.name.to_string(), serde_json::to_value(ctx).unwrap())
+            })
+            .collect::<Map<_, _>>();
+        Value::Object(fields)
+    }
+
+    /// Clear the fields the guest couldn't see, either hidden or not shown
+    /// for their answers, and keep only the checklist's own choices
+    pub fn clear_unseen(&self, params: &mut RsvpParams) {
+        for field in &self.fields {
+            let shown = self.shown(field, params);
+            if !shown {
+                params.field_mut(field.name).clear();
+            }
+            if let Some(checklist) = params.checklist_mut(field.name) {
+                *checklist = if shown {
+                    dietary::normalize(checklist, &self.dietary_options)
+                } else {
+                    String::default()
+                };
+            }
+            // nothing to be careful about without any restrictions
+            let empty = params.field_mut(field.name).trim().is_empty()
+                && params
+                    .checklist_mut(field.name)
+                    .is_none_or(|c| c.is_empty());
+            if let Some(severity) = params.severity_mut(field.name).filter(|_| empty) {
+                *severity = Severity::default();
+            }
+        }
+        params.plus_one_attending &= params.attending;
+        params.plus_one_attending_secondary &= params.attending_secondary;
+        params.plus_one_attending_tertiary &= params.attending_tertiary;
+        if !Condition::Attending.holds(params) {
+            params.needs_lodging = false;
+            params.shuttle.clear();
+            params.kids = 0;
+            params.kids_ages.clear();
+        }
+    }
+
+    /// Clear the fields the guest couldn't see, then check the required ones
+    /// are filled in
+    pub fn validate(&self, params: &mut RsvpParams) -> Result<(), Error> {
+        match self.problems(params).into_iter().next() {
+            Some((_, error)) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Clear the fields the guest couldn't see, then list every required one
+    /// left empty, in form order, with the field's name
+    pub fn problems(&self, params: &mut RsvpParams) -> Vec<(&'static str, Error)> {
+        self.clear_unseen(params);
+        let mut problems = vec![];
+        for field in &self.fields {
+            if field.rule == FieldRule::Required
+                && self.shown(field, params)
+                && params.field_mut(field.name).trim().is_empty()
+                && params
+                    .checklist_mut(field.name)
+                    .is_none_or(|c| c.is_empty())
+            {
+                problems.push((field.name, Error::Required(field.label.to_string())));
+            }
+        }
+        problems
+    }
+
+    /// Every field an error can be about, for the templates
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names = self
+            .fields
+            .iter()
+            .map(|field| field.name)
+            .collect::<Vec<_>>();
+        names.push("shuttle");
+        names
+    }
+
+    fn shown(&self, field: &Field, params: &RsvpParams) -> bool {
+        field.rule != FieldRule::Hidden && field.shown_when.holds(params)
+    }
+}
+
+/// The field a problem saving an RSVP is about, or empty if it's about the
+/// whole form
+pub fn field_of(error: &Error, params: &RsvpParams) -> &'static str {
+    match error {
+        Error::MealFull(meal) if params.meal_choice == *meal => "meal_choice",
+        Error::MealFull(_) => "plus_one_meal_choice",
+        Error::ShuttleFull(_) => "shuttle",
+        _ => "",
+    }
+}
+
+/// An RSVP form as submitted, with each checklist's boxes joined into one
+/// comma-separated answer, which `web::Form` would reject as repeated
+#[derive(Clone, Debug, PartialEq)]
+pub struct RsvpForm(pub RsvpParams);
+
+impl RsvpForm {
+    pub fn into_inner(self) -> RsvpParams {
+        self.0
+    }
+}
+
+impl Deref for RsvpForm {
+    type Target = RsvpParams;
+
+    fn deref(&self) -> &RsvpParams {
+        &self.0
+    }
+}
+
+/// Join the values of each checklist field sent more than once
+fn join_checklists(pairs: Vec<(String, String)>) -> Vec<(String, String)> {
+    let mut joined: Vec<(String, String)> = vec![];
+    for (key, value) in pairs {
+        match joined.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, list)) if CHECKLISTS.contains(&key.as_str()) => {
+                list.push_str(", ");
+                list.push_str(&value);
+            }
+            _ => joined.push((key, value)),
+        }
+    }
+    joined
+}
+
+impl FromRequest for RsvpForm {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let pairs = web::Form::<Vec<(String, String)>>::from_request(req, payload);
+        Box::pin(async move {
+            let pairs = join_checklists(pairs.await?.into_inner());
+            let encoded = serde_urlencoded::to_string(pairs).map_err(ErrorBadRequest)?;
+            let params = serde_urlencoded::from_str(&encoded).map_err(ErrorBadRequest)?;
+            Ok(Self(params))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::csvdb::test::test_rsvp};
+
+    #[test]
+    fn validate() {
+        let form = Form::new(&FieldsConfig {
+            email: FieldRule::Required,
+            plus_one_name: FieldRule::Required,
+            comments: FieldRule::Hidden,
+            ..FieldsConfig::default()
+        });
+        assert_eq!(form.context()["comments"]["hidden"], true);
+        assert_eq!(form.context()["email"]["required"], true);
+        assert_eq!(form.context()["meal_choice"]["shown_when"], "attending");
+
+        let mut params = RsvpParams {
+            email: " ".to_string(),
+            plus_one_attending: false,
+            plus_one_attending_secondary: false,
+            ..test_rsvp()
+        };
+        assert!(matches!(
+            form.validate(&mut params),
+            Err(Error::Required(field)) if field == "your email"
+        ));
+        params.email = "john@john.john".to_string();
+        form.validate(&mut params).unwrap();
+        assert!(params.comments.is_empty());
+        assert_eq!(params.meal_choice, "Fish");
+        assert!(params.plus_one_meal_choice.is_empty());
+        assert_eq!(params.plus_one_name, "Johnson");
+
+        params.plus_one_name.clear();
+        params.plus_one_attending = true;
+        assert!(form.validate(&mut params).is_err());
+        params.email.clear();
+        assert_eq!(
+            form.problems(&mut params)
+                .into_iter()
+                .map(|(field, _)| field)
+                .collect::<Vec<_>>(),
+            ["email", "plus_one_name"]
+        );
+
+        // declining guests still need an email, but nothing about the meal
+        let mut params = RsvpParams {
+            attending: false,
+            attending_secondary: false,
+            ..test_rsvp()
+        };
+        form.validate(&mut params).unwrap();
+        assert_eq!(params.email, "john@john.john");
+        assert!(params.meal_choice.is_empty());
+        assert!(params.dietary_restrictions.is_empty());
+        assert!(params.plus_one_name.is_empty());
+        assert!(params.plus_one_meal_choice.is_empty());
+        params.email.clear();
+        assert!(form.validate(&mut params).is_err());
+    }
+
+    #[test]
+    fn dietary_checklist() {
+        let form = Form::new(&FieldsConfig {
+            dietary_restrictions: FieldRule::Required,
+            ..FieldsConfig::default()
+        });
+        let mut params = RsvpParams {
+            dietary: "vegan, Paleo".to_string(),
+            dietary_restrictions: String::default(),
+            plus_one_attending: false,
+            plus_one_attending_secondary: false,
+            plus_one_dietary: "Vegan".to_string(),
+            ..test_rsvp()
+        };
+        form.validate(&mut params).unwrap();
+        assert_eq!(params.dietary, "Vegan");
+        assert!(params.plus_one_dietary.is_empty());
+        params.dietary = "Paleo".to_string();
+        assert!(form.validate(&mut params).is_err());
+
+        let pairs = [
+            ("name", "John"),
+            ("dietary", "Vegan"),
+            ("dietary", "Nut allergy"),
+            ("comments", "Hi"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .to_vec();
+        let joined = join_checklists(pairs);
+        assert_eq!(joined.len(), 3);
+        assert_eq!(joined[1].1, "Vegan, Nut allergy");
+    }
+}
@@ -0,0 +1,162 @@
+use {
+    crate::{config::GitConfig, error::Error},
+    log::error,
+    std::{
+        fs,
+        path::{Path, PathBuf},
+        process::Command,
+        thread,
+    },
+};
+
+/// Local git repository holding a copy of the CSV file, committed after each
+/// change so there's a full history to diff and push somewhere safe
+#[derive(Clone, Debug)]
+pub struct GitHistory {
+    dir: PathBuf,
+    /// Name of the copy in the repository, the same as the CSV file's
+    file_name: String,
+    remote: Option<String>,
+    branch: String,
+}
+impl GitHistory {
+    /// Open the repository in the configured directory, creating it if
+    /// needed, or `None` if it isn't configured
+    pub fn open(config: &GitConfig, csv_filename: &str) -> Result<Option<Self>, Error> {
+        let dir = match &config.dir {
+            Some(dir) => PathBuf::from(dir),
+            None => return Ok(None),
+        };
+        fs::create_dir_all(&dir)?;
+        let history = Self {
+            dir,
+            file_name: Path::new(csv_filename)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| "rsvp.csv".to_string()),
+            remote: config.remote.clone(),
+            branch: config.branch.clone(),
+        };
+        if !history.dir.join(".git").exists() {
+            history.git(&["init", "--quiet", "--initial-branch", &history.branch])?;
+        }
+        Ok(Some(history))
+    }
+
+    /// Save the contents of the CSV file as a new commit, returning whether
+    /// anything changed since the last one
+    pub fn commit(&self, contents: &str, message: &str) -> Result<bool, Error> {
+        fs::write(self.dir.join(&self.file_name), contents)?;
+        self.git(&["add", "--", &self.file_name])?;
+        let unchanged = Command::new("git")
+            .args(["diff", "--cached", "--quiet"])
+            .current_dir(&self.dir)
+            .status()?
+            .success();
+        if unchanged {
+            return Ok(false);
+        }
+        self.git(&[
+            "-c",
+            "user.name=actix-web-rsvp",
+            "-c",
+            "user.email=rsvp@localhost",
+            "commit",
+            "--quiet",
+            "--message",
+            message,
+        ])?;
+        Ok(true)
+    }
+
+    /// Push the branch to the remote, if there is one
+    pub fn push(&self) -> Result<(), Error> {
+        match &self.remote {
+            Some(remote) => self.git(&["push", "--quiet", remote, &self.branch]),
+            None => Ok(()),
+        }
+    }
+
+    /// Commit, then push in the background so a slow remote doesn't hold up
+    /// the request, logging any failure instead of failing the change
+    pub fn record(&self, contents: &str, message: &str) {
+        match self.commit(contents, message) {
+            Ok(true) if self.remote.is_some() => {
+                let history = self.clone();
+                thread::spawn(move || {
+                    if let Err(error) = history.push() {
+                        error!("Could not push the CSV history: {}", error);
+                    }
+                });
+            }
+            Ok(_) => {}
+            Err(error) => error!("Could not commit the CSV file: {}", error),
+        }
+    }
+
+    fn git(&self, args: &[&str]) -> Result<(), Error> {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(&self.dir)
+            .output()?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(Error::Git(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn log(dir: &Path) -> String {
+        let output = Command::new("git")
+            .args(["log", "--format=%s", "main"])
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn commit_and_push() {
+        let dir = tempfile::tempdir().unwrap();
+        let remote = tempfile::tempdir().unwrap();
+        Command::new("git")
+            .args(["init", "--quiet", "--bare"])
+            .current_dir(remote.path())
+            .status()
+            .unwrap();
+        let config = GitConfig {
+            dir: Some(dir.path().to_str().unwrap().to_string()),
+            remote: Some(remote.path().to_str().unwrap().to_string()),
+            ..GitConfig::default()
+        };
+        assert!(GitHistory::open(&GitConfig::default(), "rsvp.csv")
+            .unwrap()
+            .is_none());
+        let history = GitHistory::open(&config, "data/guests.csv")
+            .unwrap()
+            .unwrap();
+        assert!(history.commit("name\nJohn\n", "RSVP for John").unwrap());
+        assert!(!history.commit("name\nJohn\n", "RSVP for John").unwrap());
+        assert!(history.commit("name\n", "remove for John").unwrap());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("guests.csv")).unwrap(),
+            "name\n"
+        );
+        assert_eq!(log(dir.path()), "remove for John\nRSVP for John\n");
+
+        history.push().unwrap();
+        assert_eq!(log(remote.path()), "remove for John\nRSVP for John\n");
+
+        // reopening keeps the history
+        let history = GitHistory::open(&config, "guests.csv").unwrap().unwrap();
+        assert!(history.commit("name\nJane\n", "RSVP for Jane").unwrap());
+        assert_eq!(log(dir.path()).lines().count(), 3);
+    }
+}
@@ -0,0 +1,450 @@
+use {
+    crate::{
+        config::HooksConfig,
+        error::Error,
+        fake::fake_guests,
+        model::{RsvpModel, RsvpResponse},
+    },
+    actix_web::http::{StatusCode, Uri},
+    awc::Client,
+    chrono::{DateTime, Utc},
+    log::{error, info, warn},
+    rand::{distributions::Alphanumeric, Rng},
+    serde::{Deserialize, Serialize},
+    std::{
+        cmp::Reverse,
+        collections::HashSet,
+        fs,
+        io::ErrorKind,
+        net::{IpAddr, Ipv4Addr},
+        path::PathBuf,
+        sync::{Arc, Mutex, MutexGuard},
+        time::Duration,
+    },
+};
+
+/// Most subscriptions at once, so a misbehaving automation can't make every
+/// RSVP wait on hundreds of deliveries
+const MAX_SUBSCRIPTIONS: usize = 50;
+
+/// How many recent RSVPs the sample shows, for mapping fields while setting
+/// up an automation
+const SAMPLES: usize = 3;
+
+/// What a subscriber can be told about
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Event {
+    /// A guest's RSVP was added or updated, from any form
+    #[default]
+    Rsvp,
+}
+
+/// A URL that gets each new RSVP, registered by an automation service like
+/// Zapier or Make when a workflow using it is turned on
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Subscription {
+    /// For unsubscribing when the workflow is turned off
+    pub id: String,
+    pub target_url: String,
+    pub event: Event,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What a subscriber gets, the guest's RSVP as the JSON API returns it, with
+/// an id for each version so automations can tell updates apart
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct HookPayload {
+    pub id: String,
+    pub event: Event,
+    #[serde(flatten)]
+    pub rsvp: RsvpResponse,
+}
+impl HookPayload {
+    pub fn new(record: &RsvpModel) -> Self {
+        Self {
+            id: format!("{}-{}", record.name_key, record.updated_at.timestamp()),
+            event: Event::Rsvp,
+            rsvp: RsvpResponse::from(record.clone()),
+        }
+    }
+}
+
+/// REST hook subscriptions
+pub struct Hooks {
+    path: Option<PathBuf>,
+    timeout: Duration,
+    allow_private: bool,
+    subscriptions: Mutex<Vec<Subscription>>,
+}
+impl Default for Hooks {
+    fn default() -> Self {
+        Self {
+            path: None,
+            timeout: Duration::from_secs(HooksConfig::default().timeout_secs),
+            allow_private: false,
+            subscriptions: Mutex::default(),
+        }
+    }
+}
+impl Hooks {
+    /// The subscriptions so far, or none if they're only kept in memory
+    pub fn open(config: &HooksConfig) -> Result<Self, Error> {
+        let path = config.file.as_ref().map(PathBuf::from);
+        let subscriptions = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => serde_json::from_str(&contents)?,
+                Err(error) if error.kind() == ErrorKind::NotFound => vec![],
+                Err(error) => return Err(error.into()),
+            },
+            None => vec![],
+        };
+        Ok(Self {
+            path,
+            timeout: Duration::from_secs(config.timeout_secs),
+            allow_private: config.allow_private,
+            subscriptions: Mutex::new(subscriptions),
+        })
+    }
+
+    /// Send an event to a URL from now on, or keep the existing subscription
+    /// if it's already there
+    pub async fn subscribe(
+        &self,
+        target_url: &str,
+        event: Event,
+        now: DateTime<Utc>,
+    ) -> Result<Subscription, Error> {
+        let target_url = target_url.trim();
+        if !target_url.starts_with("https://") && !target_url.starts_with("http://") {
+            return Err(Error::Invalid(format!(
+                "target_url must be an http or https URL, not {:?}",
+                target_url
+            )));
+        }
+        if !self.allow_private {
+            check_public(target_url).await?;
+        }
+        let mut subscriptions = self.lock();
+        if let Some(existing) = subscriptions
+            .iter()
+            .find(|s| s.target_url == target_url && s.event == event)
+        {
+            return Ok(existing.clone());
+        }
+        if subscriptions.len() >= MAX_SUBSCRIPTIONS {
+            return Err(Error::Invalid(format!(
+                "There are already {} subscriptions, unsubscribe some first",
+                MAX_SUBSCRIPTIONS
+            )));
+        }
+        let subscription = Subscription {
+            id: rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(16)
+                .map(char::from)
+                .collect(),
+            target_url: target_url.to_string(),
+            event,
+            created_at: now,
+        };
+        subscriptions.push(subscription.clone());
+        self.save(&subscriptions)?;
+        info!("Subscribed {} to {:?}", subscription.target_url, event);
+        Ok(subscription)
+    }
+
+    /// Stop sending to a subscription, or `false` if there's no such
+    /// subscription
+    pub fn unsubscribe(&self, id: &str) -> Result<bool, Error> {
+        let mut subscriptions = self.lock();
+        let before = subscriptions.len();
+        subscriptions.retain(|s| s.id != id);
+        if subscriptions.len() == before {
+            return Ok(false);
+        }
+        self.save(&subscriptions)?;
+        info!("Unsubscribed hook {}", id);
+        Ok(true)
+    }
+
+    /// Every subscription, oldest first
+    pub fn list(&self) -> Vec<Subscription> {
+        self.lock().clone()
+    }
+
+    /// Send a guest's RSVP to every subscriber without holding up the
+    /// caller.  Must be called from within the server's runtime.
+    pub fn deliver_in_background(self: &Arc<Self>, record: &RsvpModel) {
+        let targets = self
+            .list()
+            .into_iter()
+            .filter(|s| s.event == Event::Rsvp)
+            .collect::<Vec<_>>();
+        if targets.is_empty() {
+            return;
+        }
+        let hooks = Arc::clone(self);
+        let payload = HookPayload::new(record);
+        actix_web::rt::spawn(async move { hooks.deliver(&targets, &payload).await });
+    }
+
+    /// Send a payload to each subscriber, logging any failure, and dropping
+    /// subscriptions whose URL answers 410 Gone, as REST hooks expect
+    pub async fn deliver(&self, targets: &[Subscription], payload: &HookPayload) {
+        let client = Client::builder().timeout(self.timeout).finish();
+        for target in targets {
+            match client.post(&target.target_url).send_json(payload).await {
+                Ok(response) if response.status() == StatusCode::GONE => {
+                    info!("{} is gone, unsubscribing it", target.target_url);
+                    if let Err(error) = self.unsubscribe(&target.id) {
+                        error!("Could not unsubscribe {}: {}", target.target_url, error);
+                    }
+                }
+                Ok(response) if !response.status().is_success() => warn!(
+                    "Hook {} answered {} for {}",
+                    target.target_url,
+                    response.status(),
+                    payload.id
+                ),
+                Ok(_) => {}
+                Err(error) => warn!(
+                    "Could not send {} to hook {}: {}",
+                    payload.id, target.target_url, error
+                ),
+            }
+        }
+    }
+
+    fn save(&self, subscriptions: &[Subscription]) -> Result<(), Error> {
+        if let Some(path) = &self.path {
+            fs::write(path, serde_json::to_string(subscriptions)?)?;
+        }
+        Ok(())
+    }
+
+    /// The subscriptions, even if a request panicked while changing them
+    fn lock(&self) -> MutexGuard<'_, Vec<Subscription>> {
+        self.subscriptions.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the hook subscriptions after a panic");
+            poisoned.into_inner()
+        })
+    }
+}
+
+/// Payloads like subscribers get, from the latest RSVPs, newest first, or a
+/// made-up guest's if no one has responded yet
+pub fn samples(records: &[RsvpModel], now: DateTime<Utc>) -> Vec<HookPayload> {
+    let mut responded = records
+        .iter()
+        .filter(|r| r.responded_at.is_some())
+        .collect::<Vec<_>>();
+    responded.sort_by_key(|r| Reverse(r.updated_at));
+    if responded.is_empty() {
+        return fake_guests(1, &HashSet::new(), &mut rand::thread_rng())
+            .into_iter()
+            .map(|rsvp| HookPayload {
+                id: "sample".to_string(),
+                event: Event::Rsvp,
+                rsvp: RsvpResponse {
+                    rsvp,
+                    email_verified: false,
+                    pending_verification: false,
+                    created_at: now,
+                    updated_at: now,
+                },
+            })
+            .collect();
+    }
+    responded
+        .into_iter()
+        .take(SAMPLES)
+        .map(HookPayload::new)
+        .collect()
+}
+
+/// Refuse a URL whose host is, or resolves to, a loopback, private or
+/// link-local address, like the cloud metadata service at 169.254.169.254
+async fn check_public(target_url: &str) -> Result<(), Error> {
+    let invalid = |reason: &str| Error::Invalid(format!("target_url {:?} {}", target_url, reason));
+    let uri = target_url
+        .parse::<Uri>()
+        .map_err(|_| invalid("isn't a URL"))?;
+    let host = uri.host().unwrap_or_default();
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+        Some("https") => 443,
+        _ => 80,
+    });
+    let addrs = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|_| invalid("has a host that can't be resolved"))?;
+    for addr in addrs {
+        if is_private(addr.ip()) {
+            return Err(invalid(&format!(
+                "is on a private address, {}, set allow_private in [hooks] to send to one",
+                addr.ip()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Whether an address is only reachable from this machine or its network
+fn is_private(ip: IpAddr) -> bool {
+    let v4 = |ip: Ipv4Addr| {
+        ip.is_loopback()
+            || ip.is_private()
+            || ip.is_link_local()
+            || ip.is_unspecified()
+            || ip.is_broadcast()
+    };
+    match ip {
+        IpAddr::V4(ip) => v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => v4(ip),
+            None => {
+                let first = ip.segments()[0];
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    // unique local, fc00::/7
+                    || first & 0xfe00 == 0xfc00
+                    // link-local, fe80::/10
+                    || first & 0xffc0 == 0xfe80
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::csvdb::test::test_db,
+        actix_web::{web, App, HttpResponse, HttpServer},
+        std::sync::Mutex as StdMutex,
+        tempfile::tempdir,
+    };
+
+    #[actix_rt::test]
+    async fn subscriptions() {
+        let dir = tempdir().unwrap();
+        let config = HooksConfig {
+            file: Some(dir.path().join("hooks.json").to_str().unwrap().to_string()),
+            ..HooksConfig::default()
+        };
+        let hooks = Hooks::open(&config).unwrap();
+        let now = Utc::now();
+        let zap = hooks
+            .subscribe("https://203.0.113.7/1", Event::Rsvp, now)
+            .await
+            .unwrap();
+        let again = hooks
+            .subscribe(" https://203.0.113.7/1", Event::Rsvp, now)
+            .await
+            .unwrap();
+        assert_eq!(zap, again);
+        let make = hooks
+            .subscribe("https://[2001:db8::2]:8443/2", Event::Rsvp, now)
+            .await
+            .unwrap();
+        assert!(matches!(
+            hooks.subscribe("ftp://example.com", Event::Rsvp, now).await,
+            Err(Error::Invalid(_))
+        ));
+        assert_eq!(hooks.list(), [zap.clone(), make.clone()]);
+
+        assert!(hooks.unsubscribe(&zap.id).unwrap());
+        assert!(!hooks.unsubscribe(&zap.id).unwrap());
+        assert_eq!(Hooks::open(&config).unwrap().list(), [make]);
+    }
+
+    #[actix_rt::test]
+    async fn private_targets() {
+        let hooks = Hooks::open(&HooksConfig::default()).unwrap();
+        let now = Utc::now();
+        for target_url in [
+            "http://127.0.0.1/hook",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://10.0.0.2:8080/hook",
+            "https://[::1]/hook",
+            "http://[::ffff:192.168.1.1]/hook",
+            "http://localhost/hook",
+        ] {
+            assert!(
+                matches!(
+                    hooks.subscribe(target_url, Event::Rsvp, now).await,
+                    Err(Error::Invalid(_))
+                ),
+                "{}",
+                target_url
+            );
+        }
+        assert!(hooks.list().is_empty());
+    }
+
+    #[test]
+    fn sample() {
+        let mut records = test_db(4).get_all().unwrap();
+        let samples = samples(&records, Utc::now());
+        assert_eq!(samples.len(), SAMPLES);
+        assert!(samples[0].rsvp.updated_at >= samples[1].rsvp.updated_at);
+
+        for record in &mut records {
+            record.responded_at = None;
+        }
+        let samples = super::samples(&records, Utc::now());
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].id, "sample");
+        assert!(!samples[0].rsvp.rsvp.name.is_empty());
+    }
+
+    type Received = StdMutex<Vec<HookPayload>>;
+
+    #[actix_rt::test]
+    async fn deliver() {
+        let received = web::Data::new(Received::default());
+        let server = HttpServer::new({
+            let received = received.clone();
+            move || {
+                App::new()
+                    .app_data(received.clone())
+                    .route(
+                        "/live",
+                        web::post().to(
+                            |payload: web::Json<HookPayload>, received: web::Data<Received>| async move {
+                                received.lock().unwrap().push(payload.into_inner());
+                                HttpResponse::Ok().finish()
+                            },
+                        ),
+                    )
+                    .route("/gone", web::post().to(HttpResponse::Gone))
+            }
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        let hooks = Hooks::open(&HooksConfig {
+            allow_private: true,
+            ..HooksConfig::default()
+        })
+        .unwrap();
+        let now = Utc::now();
+        let live = hooks
+            .subscribe(&format!("http://{}/live", addr), Event::Rsvp, now)
+            .await
+            .unwrap();
+        hooks
+            .subscribe(&format!("http://{}/gone", addr), Event::Rsvp, now)
+            .await
+            .unwrap();
+        let record = &test_db(1).get_all().unwrap()[0];
+        let payload = HookPayload::new(record);
+        hooks.deliver(&hooks.list(), &payload).await;
+        assert_eq!(*received.lock().unwrap(), [payload]);
+        assert_eq!(hooks.list(), [live]);
+    }
+}
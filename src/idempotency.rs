@@ -0,0 +1,108 @@
+use {
+    chrono::{DateTime, Duration, Utc},
+    std::collections::HashMap,
+};
+
+/// Header that API clients set to safely retry a request
+pub const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+
+/// How long a response is kept for replays
+const TTL_HOURS: i64 = 24;
+
+/// Most responses kept at once, dropping the oldest first
+const CAPACITY: usize = 1000;
+
+/// What to do with a request carrying an idempotency key
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Replay {
+    /// First time this key is seen, so handle the request
+    Miss,
+    /// Seen before with the same request, so send back this response body
+    Hit(String),
+    /// Seen before with a different request, which is a client bug
+    Mismatch,
+}
+
+struct Entry {
+    created_at: DateTime<Utc>,
+    request: String,
+    response: String,
+}
+
+/// Successful responses by idempotency key, shared by all workers
+#[derive(Default)]
+pub struct ReplayCache {
+    entries: HashMap<String, Entry>,
+}
+impl ReplayCache {
+    /// Look up a key, where `request` identifies the request body
+    pub fn get(&mut self, key: &str, request: &str, now: DateTime<Utc>) -> Replay {
+        self.expire(now);
+        match self.entries.get(key) {
+            None => Replay::Miss,
+            Some(entry) if entry.request == request => Replay::Hit(entry.response.clone()),
+            Some(_) => Replay::Mismatch,
+        }
+    }
+
+    pub fn insert(&mut self, key: &str, request: String, response: String, now: DateTime<Utc>) {
+        self.expire(now);
+        if self.entries.len() >= CAPACITY {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.created_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(
+            key.to_string(),
+            Entry {
+                created_at: now,
+                request,
+                response,
+            },
+        );
+    }
+
+    /// Drop responses past their lifetime
+    pub fn expire(&mut self, now: DateTime<Utc>) {
+        let ttl = Duration::hours(TTL_HOURS);
+        self.entries.retain(|_, entry| now - entry.created_at < ttl);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn replay() {
+        let now = Utc::now();
+        let mut cache = ReplayCache::default();
+        assert_eq!(cache.get("key", "request", now), Replay::Miss);
+        cache.insert("key", "request".to_string(), "response".to_string(), now);
+        assert_eq!(
+            cache.get("key", "request", now),
+            Replay::Hit("response".to_string())
+        );
+        assert_eq!(cache.get("key", "other", now), Replay::Mismatch);
+        assert_eq!(cache.get("other", "request", now), Replay::Miss);
+        let later = now + Duration::hours(TTL_HOURS);
+        assert_eq!(cache.get("key", "request", later), Replay::Miss);
+    }
+
+    #[test]
+    fn capacity() {
+        let now = Utc::now();
+        let mut cache = ReplayCache::default();
+        for i in 0..=CAPACITY {
+            let time = now + Duration::seconds(i as i64);
+            cache.insert(&i.to_string(), String::new(), String::new(), time);
+        }
+        assert_eq!(cache.entries.len(), CAPACITY);
+        assert_eq!(cache.get("0", "", now), Replay::Miss);
+    }
+}
@@ -0,0 +1,310 @@
+use {
+    crate::{config::FormWebhookConfig, error::Error, model::RsvpParams},
+    base64::{engine::general_purpose::STANDARD, Engine},
+    hmac::{Hmac, Mac},
+    log::warn,
+    serde_json::{json, Map, Value},
+    sha2::{Digest, Sha256},
+    std::collections::BTreeMap,
+};
+
+/// Header Typeform signs its payloads in, as `sha256=<base64 HMAC>`
+pub const TYPEFORM_SIGNATURE: &str = "Typeform-Signature";
+
+/// Header a webhook's secret can be sent in instead of the URL, which ends
+/// up in proxy and access logs
+pub const WEBHOOK_SECRET: &str = "X-Webhook-Secret";
+
+/// Fields set by the path that saves an RSVP, never by the form tool
+const RESERVED: [&str; 1] = ["source"];
+
+/// Whether a secret is the expected one, comparing their hashes so the time
+/// taken doesn't give away how much of it matched
+pub fn same_secret(secret: &str, expected: &str) -> bool {
+    Sha256::digest(secret) == Sha256::digest(expected)
+}
+
+/// Whether a payload came from the configured form tool, by the secret in
+/// its URL or Typeform's signature of the body
+pub fn authorized(
+    config: &FormWebhookConfig,
+    secret: Option<&str>,
+    signature: Option<&str>,
+    body: &[u8],
+) -> bool {
+    let expected = match &config.secret {
+        Some(expected) if !expected.is_empty() => expected,
+        _ => return false,
+    };
+    if secret.is_some_and(|secret| same_secret(secret, expected)) {
+        return true;
+    }
+    signature
+        .and_then(|signature| signature.strip_prefix("sha256="))
+        .and_then(|signature| STANDARD.decode(signature).ok())
+        .is_some_and(|signature| {
+            let mut mac = Hmac::<Sha256>::new_from_slice(expected.as_bytes())
+                .expect("HMAC takes keys of any size");
+            mac.update(body);
+            mac.verify_slice(&signature).is_ok()
+        })
+}
+
+/// The answers in a form tool's payload, by question.
+///
+/// Supports Typeform's webhook, keyed by each question's `ref` or else its
+/// title, and a flat object of question to answer, like an Apps Script
+/// posts for a Google Form.
+pub fn answers(body: &Value) -> BTreeMap<String, Value> {
+    let response = &body["form_response"];
+    match response["answers"].as_array() {
+        Some(answers) => answers
+            .iter()
+            .filter_map(|answer| {
+                Some((
+                    question(response, &answer["field"])?,
+                    typeform_value(answer),
+                ))
+            })
+            .collect(),
+        None => body
+            .as_object()
+            .map(|answers| answers.clone().into_iter().collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// A Typeform question's `ref`, or its title from the form's definition
+fn question(response: &Value, field: &Value) -> Option<String> {
+    if let Some(reference) = field["ref"].as_str() {
+        return Some(reference.to_string());
+    }
+    response["definition"]["fields"]
+        .as_array()?
+        .iter()
+        .find(|definition| definition["id"] == field["id"])?["title"]
+        .as_str()
+        .map(str::to_string)
+}
+
+/// A Typeform answer's value, which is under a key named for its type
+fn typeform_value(answer: &Value) -> Value {
+    match answer["type"].as_str() {
+        Some("choice") => answer["choice"]["label"]
+            .as_str()
+            .or_else(|| answer["choice"]["other"].as_str())
+            .map(Value::from)
+            .unwrap_or_default(),
+        Some("choices") => answer["choices"]["labels"].clone(),
+        Some(kind) => answer[kind].clone(),
+        None => Value::Null,
+    }
+}
+
+/// An RSVP from the answers, each in the field `fields` maps its question
+/// to, or the field with the question's own name.  Text answers for the
+/// same field are kept on separate lines, and questions that don't match a
+/// field are left out.
+pub fn params(
+    answers: &BTreeMap<String, Value>,
+    fields: &BTreeMap<String, String>,
+) -> Result<RsvpParams, Error> {
+    let blank: RsvpParams = serde_json::from_value(json!({
+        "name": "",
+        "attending": false,
+        "attending_secondary": false,
+        "attending_tertiary": false,
+        "plus_one_attending": false,
+    }))?;
+    let mut rsvp = match serde_json::to_value(blank)? {
+        Value::Object(rsvp) => rsvp,
+        _ => Map::new(),
+    };
+    for (question, answer) in answers {
+        let field = fields.get(question).unwrap_or(question);
+        let value = match rsvp.get(field) {
+            Some(_) if RESERVED.contains(&field.as_str()) => None,
+            Some(Value::Bool(_)) => Some(Value::Bool(is_yes(answer))),
+            Some(Value::Number(_)) => Some(Value::from(number(answer))),
+            Some(Value::String(existing)) => {
+                let text = text(answer);
+                if existing.is_empty() || text.is_empty() {
+                    Some(Value::from(format!("{}{}", existing, text)))
+                } else {
+                    Some(Value::from(format!("{}\n{}", existing, text)))
+                }
+            }
+            _ => None,
+        };
+        match value {
+            Some(value) => {
+                rsvp.insert(field.clone(), value);
+            }
+            None => warn!(
+                "Skipping form question {:?}, it isn't an RSVP field",
+                question
+            ),
+        }
+    }
+    let params: RsvpParams = serde_json::from_value(Value::Object(rsvp))
+        .map_err(|e| Error::Invalid(format!("Could not read the form's answers: {}", e)))?;
+    if params.name.trim().is_empty() {
+        return Err(Error::Invalid(
+            "The form's answers have no name, map a question to \"name\"".to_string(),
+        ));
+    }
+    Ok(params)
+}
+
+/// Whether an answer says yes, like "Yes, I'll be there" or a ticked box
+fn is_yes(answer: &Value) -> bool {
+    match answer {
+        Value::Bool(yes) => *yes,
+        Value::Number(number) => number.as_f64().is_some_and(|number| number != 0.0),
+        Value::String(text) => {
+            let text = text.trim().to_lowercase();
+            matches!(text.as_str(), "true" | "1" | "y" | "on" | "x")
+                || [
+                    "yes",
+                    "accept",
+                    "joyfully",
+                    "attending",
+                    "coming",
+                    "will attend",
+                ]
+                .iter()
+                .any(|word| text.starts_with(word))
+        }
+        Value::Array(answers) => answers.iter().any(is_yes),
+        _ => false,
+    }
+}
+
+fn number(answer: &Value) -> u64 {
+    match answer {
+        Value::Number(number) => number.as_u64().unwrap_or_default(),
+        Value::String(text) => text.trim().parse().unwrap_or_default(),
+        _ => 0,
+    }
+}
+
+/// An answer as text, with the choices of a multiple choice question
+/// separated by commas
+fn text(answer: &Value) -> String {
+    match answer {
+        Value::String(text) => text.trim().to_string(),
+        Value::Bool(yes) => if *yes { "yes" } else { "no" }.to_string(),
+        Value::Number(number) => number.to_string(),
+        Value::Array(answers) => answers
+            .iter()
+            .map(text)
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => String::default(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn typeform() {
+        let body = json!({
+            "event_type": "form_response",
+            "form_response": {
+                "definition": {
+                    "fields": [
+                        { "id": "abc", "title": "Your full name" },
+                        { "id": "def", "title": "Will you be there?" },
+                        { "id": "ghi", "title": "Meal" },
+                    ],
+                },
+                "answers": [
+                    { "type": "text", "text": "Jane Guest", "field": { "id": "abc" } },
+                    { "type": "boolean", "boolean": true, "field": { "id": "def" } },
+                    {
+                        "type": "choice",
+                        "choice": { "label": "Fish" },
+                        "field": { "id": "ghi", "ref": "meal_choice" },
+                    },
+                    { "type": "email", "email": "jane@example.com", "field": { "id": "jkl", "ref": "email" } },
+                    { "type": "number", "number": 2, "field": { "id": "mno", "ref": "kids" } },
+                ],
+            },
+        });
+        let answers = answers(&body);
+        assert_eq!(answers["Your full name"], "Jane Guest");
+        assert_eq!(answers["meal_choice"], "Fish");
+        let fields = BTreeMap::from([
+            ("Your full name".to_string(), "name".to_string()),
+            ("Will you be there?".to_string(), "attending".to_string()),
+        ]);
+        let params = params(&answers, &fields).unwrap();
+        assert_eq!(params.name, "Jane Guest");
+        assert!(params.attending);
+        assert!(!params.plus_one_attending);
+        assert_eq!(params.meal_choice, "Fish");
+        assert_eq!(params.email, "jane@example.com");
+        assert_eq!(params.kids, 2);
+    }
+
+    #[test]
+    fn flat() {
+        let body = json!({
+            "Name": "John Guest",
+            "Coming?": "No, sadly",
+            "Plus one?": "Yes, with my partner",
+            "Allergies": ["Peanuts", "Shellfish"],
+            "Song request": "September",
+            "Message": "Congrats!",
+            "source": "admin",
+            "Timestamp": "2024-05-01",
+        });
+        let fields = BTreeMap::from([
+            ("Name".to_string(), "name".to_string()),
+            ("Coming?".to_string(), "attending".to_string()),
+            ("Plus one?".to_string(), "plus_one_attending".to_string()),
+            ("Allergies".to_string(), "dietary_restrictions".to_string()),
+            ("Song request".to_string(), "comments".to_string()),
+            ("Message".to_string(), "comments".to_string()),
+        ]);
+        let params = params(&answers(&body), &fields).unwrap();
+        assert_eq!(params.name, "John Guest");
+        assert!(!params.attending);
+        assert!(params.plus_one_attending);
+        assert_eq!(params.dietary_restrictions, "Peanuts, Shellfish");
+        assert_eq!(params.comments, "Congrats!\nSeptember");
+        assert_eq!(params.source, None);
+
+        assert!(matches!(
+            super::params(&answers(&json!({ "Coming?": "Yes" })), &fields),
+            Err(Error::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn secret() {
+        let config = FormWebhookConfig {
+            secret: Some("s3cret".to_string()),
+            ..FormWebhookConfig::default()
+        };
+        let body = br#"{"form_response":{}}"#;
+        assert!(authorized(&config, Some("s3cret"), None, body));
+        assert!(!authorized(&config, Some("wrong"), None, body));
+        assert!(!authorized(&config, None, None, body));
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"s3cret").unwrap();
+        mac.update(body);
+        let signature = format!("sha256={}", STANDARD.encode(mac.finalize().into_bytes()));
+        assert!(authorized(&config, None, Some(&signature), body));
+        assert!(!authorized(&config, None, Some(&signature), b"{}"));
+        assert!(!authorized(
+            &FormWebhookConfig::default(),
+            Some(""),
+            Some(&signature),
+            body
+        ));
+    }
+}
@@ -0,0 +1,164 @@
+use {
+    crate::{
+        config::{Config, InsertsConfig},
+        error::Error,
+        model::RsvpModel,
+    },
+    serde::{Deserialize, Serialize},
+};
+
+/// Which guests to print inserts for, like announcements, e.g.
+/// `?segment=tag:family`
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct InsertsParams {
+    #[serde(default)]
+    pub segment: String,
+}
+
+/// What's printed on one guest's insert
+#[derive(Clone, Debug, PartialEq)]
+pub struct Insert {
+    pub name: String,
+    pub plus_one_name: String,
+    pub short_url: String,
+}
+impl Insert {
+    pub fn new(record: &RsvpModel, config: &Config) -> Self {
+        Self {
+            name: record.name.clone(),
+            plus_one_name: record.plus_one_name.clone(),
+            short_url: config.short_url(&record.short_code),
+        }
+    }
+}
+
+/// Space between a card's edges and what's printed on it, in points
+#[cfg(feature = "pdf")]
+const MARGIN: f32 = 24.0;
+
+/// Lay out the inserts on as many pages as they take, `columns` by `rows`
+/// cards to a page with light dashed lines to cut along.  Each card has the
+/// heading, the guest's name, the message, their short link, and a QR code
+/// of it.
+#[cfg(feature = "pdf")]
+pub fn pdf(
+    inserts: &[Insert],
+    config: &InsertsConfig,
+    heading: &str,
+) -> Result<Option<Vec<u8>>, Error> {
+    use {
+        crate::pdf::{self, line},
+        pdf_writer::Content,
+        qrcode::{Color, QrCode},
+    };
+
+    let (page_width, page_height) = pdf::page_size(config.page_size);
+    let columns = config.columns.max(1);
+    let rows = config.rows.max(1);
+    let (card_width, card_height) = (page_width / columns as f32, page_height / rows as f32);
+    let mut pages = vec![];
+    for page in inserts.chunks(columns * rows) {
+        let mut content = Content::new();
+        // cut lines
+        content
+            .set_stroke_gray(0.8)
+            .set_line_width(0.5)
+            .set_dash_pattern([4.0, 4.0], 0.0);
+        for column in 1..columns {
+            let x = card_width * column as f32;
+            content.move_to(x, 0.0).line_to(x, page_height);
+        }
+        for row in 1..rows {
+            let y = card_height * row as f32;
+            content.move_to(0.0, y).line_to(page_width, y);
+        }
+        content.stroke();
+
+        for (i, insert) in page.iter().enumerate() {
+            let x = card_width * (i % columns) as f32 + MARGIN;
+            let top = page_height - card_height * (i / columns) as f32 - MARGIN;
+            let bottom = top - card_height + 2.0 * MARGIN;
+            let width = card_width - 2.0 * MARGIN;
+
+            // text, font size, and space taken including what's below it
+            let mut lines = vec![];
+            if !heading.is_empty() {
+                lines.push((heading.to_string(), 14.0, 28.0));
+            }
+            lines.push((insert.name.clone(), 18.0, 22.0));
+            if !insert.plus_one_name.is_empty() {
+                lines.push((format!("& {}", insert.plus_one_name), 12.0, 22.0));
+            }
+            lines.push((config.message.clone(), 10.0, 14.0));
+            lines.push((insert.short_url.clone(), 11.0, 20.0));
+
+            let mut y = top;
+            for (text, size, leading) in lines {
+                line(&mut content, &text, size, x, y - size, width);
+                y -= leading;
+            }
+
+            let qr = QrCode::new(insert.short_url.as_bytes())?;
+            let size = width.min(y - bottom);
+            let module = size / qr.width() as f32;
+            if module > 0.0 {
+                for (n, color) in qr.to_colors().into_iter().enumerate() {
+                    if color == Color::Dark {
+                        let (qx, qy) = (n % qr.width(), n / qr.width());
+                        content.rect(
+                            x + module * qx as f32,
+                            bottom + size - module * (qy + 1) as f32,
+                            module,
+                            module,
+                        );
+                    }
+                }
+                content.fill_nonzero();
+            }
+        }
+        pages.push(content);
+    }
+    Ok(Some(pdf::document(
+        "Invitation inserts",
+        config.page_size,
+        pages,
+    )))
+}
+
+#[cfg(not(feature = "pdf"))]
+pub fn pdf(
+    _inserts: &[Insert],
+    _config: &InsertsConfig,
+    _heading: &str,
+) -> Result<Option<Vec<u8>>, Error> {
+    log::warn!("Built without the pdf feature, invitation inserts can't be printed");
+    Ok(None)
+}
+
+#[cfg(all(test, feature = "pdf"))]
+mod test {
+    use {super::*, crate::csvdb::test::test_db};
+
+    #[test]
+    fn pdf_pages() {
+        let config = Config::default();
+        let inserts = test_db(5)
+            .get_all()
+            .unwrap()
+            .iter()
+            .map(|r| Insert::new(r, &config))
+            .collect::<Vec<_>>();
+        let pdf = pdf(&inserts, &config.inserts, "Jane & John")
+            .unwrap()
+            .unwrap();
+        let contents = String::from_utf8_lossy(&pdf);
+        assert!(contents.starts_with("%PDF-"));
+        // four cards to a page
+        assert!(contents.contains("/Count 2"));
+        assert!(contents.contains("(John-4) Tj"));
+        assert!(contents.contains(&format!("({}) Tj", inserts[0].short_url)));
+
+        let empty = super::pdf(&[], &config.inserts, "").unwrap().unwrap();
+        assert!(String::from_utf8_lossy(&empty).contains("/Count 1"));
+    }
+}
@@ -0,0 +1,208 @@
+use {
+    crate::{config::Config, session, state::AppState},
+    actix_web::{
+        body::MessageBody,
+        cookie::time::Duration,
+        dev::{ServiceRequest, ServiceResponse},
+        http::{header::ACCEPT_LANGUAGE, Uri},
+        middleware::Next,
+        web, Error as ActixError, HttpMessage, HttpRequest,
+    },
+    std::cmp::Ordering,
+};
+
+/// Cookie remembering the language a guest picked
+pub static COOKIE_NAME: &str = "rsvp_lang";
+
+/// The language of the pages for a request, as it's configured in
+/// `[languages.pages]`, when the guest picked one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Language(pub String);
+
+/// Language code as it's saved on the guest's record, like "fr" or "pt-br"
+pub fn normalize(language: &str) -> String {
+    language.trim().replace('_', "-").to_lowercase()
+}
+
+/// The part of a language code before any region, like "pt" for "pt-br"
+fn primary(language: &str) -> &str {
+    language.split('-').next().unwrap_or(language)
+}
+
+/// The language in `supported` the guest's browser asks for first, trying
+/// each exactly and then without its region, or `None` if it asks for none
+/// of them
+pub fn preferred(accept_language: &str, supported: &[&str]) -> Option<String> {
+    let mut wanted: Vec<(String, f32)> = accept_language
+        .split(',')
+        .filter_map(|part| {
+            let mut pieces = part.split(';');
+            let language = normalize(pieces.next()?);
+            let quality = pieces
+                .find_map(|piece| piece.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+            (!language.is_empty() && language != "*" && quality > 0.0)
+                .then_some((language, quality))
+        })
+        .collect();
+    // stable, so ties keep the browser's order
+    wanted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+    wanted.iter().find_map(|(language, _)| {
+        let matching = |candidate: &str| {
+            supported
+                .iter()
+                .find(|s| normalize(s) == candidate)
+                .map(|s| normalize(s))
+        };
+        matching(language).or_else(|| matching(primary(language)))
+    })
+}
+
+/// The language to save for a guest, the one they picked for the pages if
+/// they can get email in it, or else from their request's `Accept-Language`
+/// header, or an empty string if there's none they can get email in
+pub fn from_request(req: &HttpRequest, supported: &[&str]) -> String {
+    let picked = req.extensions().get::<Language>().and_then(|picked| {
+        supported
+            .iter()
+            .find(|s| normalize(s) == normalize(&picked.0))
+            .map(|s| normalize(s))
+    });
+    picked
+        .or_else(|| {
+            req.headers()
+                .get(ACCEPT_LANGUAGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| preferred(value, supported))
+        })
+        .unwrap_or_default()
+}
+
+/// The configured language a path starts with, and the rest of the path,
+/// like "fr" and "/rsvp" for "/fr/rsvp"
+fn split_language<'a>(path: &'a str, languages: &[&'a str]) -> Option<(&'a str, &'a str)> {
+    let rest = path.strip_prefix('/')?;
+    let (first, rest) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let language = languages
+        .iter()
+        .find(|language| normalize(language) == normalize(first))?;
+    Some((language, if rest.is_empty() { "/" } else { rest }))
+}
+
+/// Middleware serving `/{lang}/...` as the same page in that language and
+/// remembering the choice in a cookie, so links without the prefix stay in
+/// it too
+pub async fn localize(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, ActixError> {
+    let state = match req.app_data::<web::Data<AppState<'_>>>() {
+        Some(state) => state.clone(),
+        None => return next.call(req).await,
+    };
+    let page_languages = |config: &Config| -> Vec<String> {
+        let languages = config.languages.page_languages();
+        languages
+            .iter()
+            .map(|language| language.to_string())
+            .collect()
+    };
+    let languages = match state.reloader.generation() {
+        0 => page_languages(&state.config),
+        _ => state.reloader.with_latest(page_languages),
+    };
+    let languages = languages.iter().map(String::as_str).collect::<Vec<_>>();
+    if languages.len() < 2 {
+        return next.call(req).await;
+    }
+    let path = req.path().to_string();
+    let picked = match split_language(&path, &languages) {
+        Some((language, rest)) => {
+            let uri = match req.uri().query() {
+                Some(query) => format!("{}?{}", rest, query),
+                None => rest.to_string(),
+            };
+            if let Ok(uri) = uri.parse::<Uri>() {
+                req.match_info_mut().get_mut().update(&uri);
+                req.head_mut().uri = uri;
+            }
+            Some((language.to_string(), true))
+        }
+        None => req
+            .cookie(COOKIE_NAME)
+            .and_then(|cookie| {
+                languages
+                    .iter()
+                    .find(|language| **language == cookie.value())
+                    .map(|language| language.to_string())
+            })
+            .map(|language| (language, false)),
+    };
+    let Some((language, remember)) = picked else {
+        return next.call(req).await;
+    };
+    req.extensions_mut().insert(Language(language.clone()));
+    let mut res = next.call(req).await?;
+    if remember {
+        let cookie = session::cookie(COOKIE_NAME, language)
+            .max_age(Duration::days(365))
+            .finish();
+        res.response_mut().add_cookie(&cookie)?;
+    }
+    Ok(res)
+}
+
+/// Candidates for a guest's templates, from most to least specific: their
+/// own language, the same without its region, then the default
+pub fn fallbacks<'a>(language: &'a str, default: &'a str) -> Vec<&'a str> {
+    let mut candidates = vec![];
+    for candidate in [language, primary(language), default] {
+        if !candidate.is_empty() && !candidates.contains(&candidate) {
+            candidates.push(candidate);
+        }
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn preferred_language() {
+        let supported = ["en", "fr", "pt-BR"];
+        assert_eq!(
+            preferred("fr-CA,fr;q=0.9,en;q=0.8", &supported).unwrap(),
+            "fr"
+        );
+        assert_eq!(preferred("de, en;q=0.5", &supported).unwrap(), "en");
+        assert_eq!(preferred("en;q=0.5, fr", &supported).unwrap(), "fr");
+        assert_eq!(preferred("pt_br", &supported).unwrap(), "pt-br");
+        assert_eq!(preferred("fr;q=0, *", &supported), None);
+        assert_eq!(preferred("de;q=nope", &supported), None);
+        assert_eq!(preferred("", &supported), None);
+    }
+
+    #[test]
+    fn language_prefix() {
+        let languages = ["en", "fr", "pt-BR"];
+        assert_eq!(split_language("/fr/", &languages), Some(("fr", "/")));
+        assert_eq!(split_language("/fr", &languages), Some(("fr", "/")));
+        assert_eq!(
+            split_language("/pt-br/rsvp/abc", &languages),
+            Some(("pt-BR", "/rsvp/abc"))
+        );
+        assert_eq!(split_language("/fetch", &languages), None);
+        assert_eq!(split_language("/", &languages), None);
+    }
+
+    #[test]
+    fn fallback_languages() {
+        assert_eq!(fallbacks("pt-br", "en"), vec!["pt-br", "pt", "en"]);
+        assert_eq!(fallbacks("en", "en"), vec!["en"]);
+        assert_eq!(fallbacks("", "en"), vec!["en"]);
+    }
+}
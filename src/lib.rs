@@ -1,5 +1,69 @@
+pub mod access_log;
+pub mod acme;
+#[cfg(feature = "admin")]
+pub mod admin;
+pub mod analytics;
+pub mod announcement;
+#[cfg(feature = "api")]
+pub mod api;
+pub mod archive;
+pub mod audit;
+pub mod backup;
+pub mod banner;
+pub mod bounce;
+pub mod calendar;
+pub mod childcare;
+pub mod clock;
+pub mod config;
+pub mod contact;
 pub mod csvdb;
+#[cfg(feature = "admin")]
+pub mod dav;
+pub mod dedupe;
+pub mod dietary;
+pub mod doctor;
 pub mod email;
 pub mod error;
+pub mod export;
+pub mod fake;
+pub mod feeds;
+pub mod flash;
+pub mod form;
+pub mod git;
+pub mod hooks;
+pub mod idempotency;
+#[cfg(feature = "api")]
+pub mod inbound;
+pub mod inserts;
+pub mod language;
+#[cfg(unix)]
+pub mod listener;
+pub mod lockout;
+pub mod logs;
+pub mod metrics;
 pub mod model;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+pub mod photos;
+pub mod preview;
+pub mod quarantine;
+pub mod rate_limit;
+pub mod registry;
+pub mod reload;
+pub mod request;
+pub mod s3;
+pub mod seating;
+pub mod session;
+pub mod shuttle;
+pub mod site_password;
+pub mod songs;
+pub mod source;
 pub mod state;
+pub mod tasks;
+pub mod telemetry;
+pub mod thanks;
+pub mod throttle;
+pub mod timezone;
+pub mod tls;
+pub mod travel;
+pub mod waves;
@@ -0,0 +1,125 @@
+use {
+    log::warn,
+    std::{
+        env, fs, io,
+        net::TcpListener,
+        os::{
+            fd::{FromRawFd, IntoRawFd},
+            unix::{fs::FileTypeExt, net::UnixListener},
+        },
+        path::Path,
+        process,
+    },
+};
+
+/// First file descriptor passed by systemd socket activation
+const LISTEN_FDS_START: i32 = 3;
+
+/// A listening socket passed by systemd, when started by a `.socket` unit
+pub enum SystemdSocket {
+    Tcp(TcpListener),
+    Uds(UnixListener),
+}
+
+/// The socket passed by systemd, or `None` if it didn't start this process
+pub fn from_systemd() -> io::Result<Option<SystemdSocket>> {
+    let count = passed_fds(
+        env::var("LISTEN_PID").ok().as_deref(),
+        env::var("LISTEN_FDS").ok().as_deref(),
+        process::id(),
+    );
+    if count == 0 {
+        return Ok(None);
+    }
+    if count > 1 {
+        warn!(
+            "Only listening on the first of {} sockets from systemd",
+            count
+        );
+    }
+    // SAFETY: systemd passes this process open listening sockets starting
+    // at LISTEN_FDS_START, which nothing else owns
+    let uds = unsafe { UnixListener::from_raw_fd(LISTEN_FDS_START) };
+    if uds.local_addr().is_ok() {
+        return Ok(Some(SystemdSocket::Uds(uds)));
+    }
+    // SAFETY: the same socket, which wasn't a unix one
+    let tcp = unsafe { TcpListener::from_raw_fd(uds.into_raw_fd()) };
+    tcp.local_addr()?;
+    Ok(Some(SystemdSocket::Tcp(tcp)))
+}
+
+/// How many sockets systemd passed, given its environment variables, which
+/// are only meant for the process with `LISTEN_PID`
+fn passed_fds(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> usize {
+    match listen_pid.and_then(|p| p.parse::<u32>().ok()) {
+        Some(listen_pid) if listen_pid == pid => {
+            listen_fds.and_then(|n| n.parse().ok()).unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
+
+/// Remove a socket file left behind by a previous run, which would stop the
+/// server from binding to it again.  Anything else at the path is left alone.
+pub fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        )),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Refuse to listen on a unix socket unless "unix" is in `trusted_proxies`.
+/// Requests on it have no peer address, so otherwise every guest would share
+/// one, and one guest locked out would lock out everyone.
+pub fn check_trusted_socket(trusted_proxies: &[String]) -> io::Result<()> {
+    match trusted_proxies.iter().any(|proxy| proxy == "unix") {
+        true => Ok(()),
+        false => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Listening on a unix socket needs trusted_proxies = [\"unix\"], to tell guests apart",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn systemd_env() {
+        assert_eq!(passed_fds(Some("42"), Some("1"), 42), 1);
+        assert_eq!(passed_fds(Some("42"), Some("2"), 42), 2);
+        assert_eq!(passed_fds(Some("41"), Some("1"), 42), 0);
+        assert_eq!(passed_fds(None, Some("1"), 42), 0);
+        assert_eq!(passed_fds(Some("42"), None, 42), 0);
+    }
+
+    #[test]
+    fn trusted_socket() {
+        assert!(check_trusted_socket(&["unix".to_string()]).is_ok());
+        assert!(check_trusted_socket(&[]).is_err());
+        assert!(check_trusted_socket(&["127.0.0.1".to_string()]).is_err());
+    }
+
+    #[test]
+    fn stale_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rsvp.sock");
+        remove_stale_socket(&path).unwrap();
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(UnixListener::bind(&path).is_err());
+        remove_stale_socket(&path).unwrap();
+        UnixListener::bind(&path).unwrap();
+
+        let file = dir.path().join("rsvp.csv");
+        fs::write(&file, "name\n").unwrap();
+        assert!(remove_stale_socket(&file).is_err());
+        assert!(file.exists());
+    }
+}
@@ -0,0 +1,150 @@
+use {
+    crate::{config::FetchLockoutConfig, rate_limit::RateLimiter},
+    actix_web::{http::header::RETRY_AFTER, HttpResponse, HttpResponseBuilder},
+    chrono::{DateTime, Duration, Utc},
+    log::warn,
+    std::{
+        collections::HashMap,
+        sync::{Mutex, MutexGuard},
+    },
+};
+
+/// How lockouts show up in the audit log, with the IP address as the name
+pub const LOCKOUT_ACTION: &str = "fetch lockout";
+
+#[derive(Default)]
+struct Inner {
+    /// Recent names looked up on `/fetch` that aren't on the list, by IP
+    misses: RateLimiter,
+    /// IP addresses turned away, until when
+    locked: HashMap<String, DateTime<Utc>>,
+}
+
+/// Who's been guessing names on `/fetch`, verification codes or passwords,
+/// to slow down anyone going through a list of names to find who's invited
+/// or of passwords to get in
+#[derive(Default)]
+pub struct FetchLockout {
+    inner: Mutex<Inner>,
+}
+impl FetchLockout {
+    /// When the IP address may look names up again, or `None` if it isn't
+    /// locked out
+    pub fn locked_until(&self, ip: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut inner = self.lock();
+        inner.locked.retain(|_, until| *until > now);
+        inner.locked.get(ip).copied()
+    }
+
+    /// Count a wrong guess, locking the IP address out once it's
+    /// missed more than `max_misses` in the window, and returning until when
+    /// if this miss was the one that did it
+    pub fn miss(
+        &self,
+        ip: &str,
+        config: &FetchLockoutConfig,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let mut inner = self.lock();
+        let window = Duration::seconds(config.window_secs);
+        if inner.misses.allow(ip, config.max_misses, window, now) {
+            return None;
+        }
+        let until = now + Duration::seconds(config.lockout_secs);
+        inner.locked.insert(ip.to_string(), until);
+        Some(until)
+    }
+
+    /// The misses and lockouts, even if a request panicked while counting
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the name lookup lockouts after a panic");
+            poisoned.into_inner()
+        })
+    }
+}
+
+/// A 429 response telling a locked out IP address when to try again
+pub fn too_many_requests(until: DateTime<Utc>, now: DateTime<Utc>) -> HttpResponseBuilder {
+    let mut res = HttpResponse::TooManyRequests();
+    res.insert_header((RETRY_AFTER, (until - now).num_seconds().to_string()));
+    res
+}
+
+/// Wrong verification codes entered for each guest, by name key, to throw a
+/// code away before it can be guessed from many addresses
+#[derive(Default)]
+pub struct CodeAttempts {
+    misses: Mutex<HashMap<String, usize>>,
+}
+impl CodeAttempts {
+    /// Count a wrong code for the guest, returning whether that used up
+    /// their `max` attempts, or never if `max` is 0
+    pub fn miss(&self, key: &str, max: usize) -> bool {
+        let mut misses = self.lock();
+        let count = misses.entry(key.to_string()).or_default();
+        *count += 1;
+        if max == 0 || *count < max {
+            return false;
+        }
+        misses.remove(key);
+        true
+    }
+
+    /// Start counting again, once the guest has a new code
+    pub fn reset(&self, key: &str) {
+        self.lock().remove(key);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, usize>> {
+        self.misses.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the verification code attempts after a panic");
+            poisoned.into_inner()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn code_attempts() {
+        let attempts = CodeAttempts::default();
+        assert!(!attempts.miss("jane", 3));
+        assert!(!attempts.miss("jane", 3));
+        assert!(!attempts.miss("john", 3));
+        assert!(attempts.miss("jane", 3));
+        // counting starts over with the next code
+        assert!(!attempts.miss("jane", 3));
+        attempts.reset("john");
+        assert!(!attempts.miss("john", 2));
+        assert!(!attempts.miss("john", 0));
+    }
+
+    #[test]
+    fn lockout() {
+        let config = FetchLockoutConfig {
+            max_misses: 2,
+            ..FetchLockoutConfig::default()
+        };
+        let now = Utc::now();
+        let lockout = FetchLockout::default();
+        assert_eq!(lockout.miss("1.2.3.4", &config, now), None);
+        assert_eq!(lockout.miss("1.2.3.4", &config, now), None);
+        assert_eq!(lockout.locked_until("1.2.3.4", now), None);
+        let until = now + Duration::hours(1);
+        assert_eq!(lockout.miss("1.2.3.4", &config, now), Some(until));
+        assert_eq!(lockout.locked_until("1.2.3.4", now), Some(until));
+        assert_eq!(lockout.locked_until("5.6.7.8", now), None);
+        assert_eq!(lockout.locked_until("1.2.3.4", until), None);
+
+        let off = FetchLockoutConfig {
+            max_misses: 0,
+            ..config
+        };
+        assert_eq!(lockout.miss("5.6.7.8", &off, now), None);
+        assert_eq!(lockout.miss("5.6.7.8", &off, now), None);
+        assert_eq!(lockout.miss("5.6.7.8", &off, now), None);
+    }
+}
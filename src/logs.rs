@@ -0,0 +1,90 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::Mutex,
+};
+
+/// How many of the most recent log lines are kept in memory
+const CAPACITY: usize = 500;
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Log output that goes to stderr as usual, and also keeps the most recent
+/// lines around so they can be included in error emails
+#[derive(Clone, Copy, Default)]
+pub struct RecentLogs;
+
+impl Write for RecentLogs {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Ok(mut recent) = RECENT.lock() {
+            for line in String::from_utf8_lossy(buf).lines() {
+                if recent.len() == CAPACITY {
+                    recent.pop_front();
+                }
+                recent.push_back(line.to_string());
+            }
+        }
+        io::stderr().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Set up the default logger, writing through `RecentLogs`
+pub fn init() {
+    env_logger::Builder::from_default_env()
+        .target(env_logger::Target::Pipe(Box::new(RecentLogs)))
+        .init();
+}
+
+/// The last `count` log lines that are warnings or errors, or mention any of
+/// the given keys, like a request ID or guest name
+pub fn recent(keys: &[&str], count: usize) -> Vec<String> {
+    let recent = match RECENT.lock() {
+        Ok(recent) => recent,
+        Err(_) => return vec![],
+    };
+    let mut lines = recent
+        .iter()
+        .rev()
+        .filter(|line| {
+            line.contains(" ERROR ")
+                || line.contains(" WARN ")
+                || keys.iter().any(|key| !key.is_empty() && line.contains(key))
+        })
+        .take(count)
+        .cloned()
+        .collect::<Vec<_>>();
+    lines.reverse();
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recent_lines() {
+        let mut logs = RecentLogs;
+        writeln!(
+            logs,
+            "[2023-05-28T18:15:00Z INFO  rsvp] New RSVP for recent-test-guest"
+        )
+        .unwrap();
+        writeln!(logs, "[2023-05-28T18:15:00Z INFO  rsvp] Unrelated").unwrap();
+        writeln!(
+            logs,
+            "[2023-05-28T18:15:01Z ERROR rsvp] recent-test failure"
+        )
+        .unwrap();
+        let lines = recent(&["recent-test-guest"], 500);
+        assert!(lines
+            .iter()
+            .any(|l| l.ends_with("New RSVP for recent-test-guest")));
+        assert!(lines.iter().any(|l| l.ends_with("recent-test failure")));
+        assert!(!lines.iter().any(|l| l.ends_with("Unrelated")));
+        assert_eq!(recent(&[], 1).len(), 1);
+    }
+}
@@ -1,149 +1,981 @@
+// Without every optional part of the site, some of what they share goes unused
+#![cfg_attr(
+    not(all(feature = "admin", feature = "api", feature = "photos")),
+    allow(dead_code, unused_imports)
+)]
+
+mod access_log;
+mod acme;
+#[cfg(feature = "admin")]
+mod admin;
+mod analytics;
+mod announcement;
+#[cfg(feature = "api")]
+mod api;
+mod archive;
+mod audit;
+mod backup;
+mod banner;
+mod bounce;
+mod calendar;
+mod childcare;
+mod clock;
+mod config;
+mod contact;
 mod csvdb;
+#[cfg(feature = "admin")]
+mod dav;
+mod dedupe;
+mod dietary;
+mod doctor;
 mod email;
 mod error;
+mod export;
+mod fake;
+mod feeds;
+mod flash;
+mod form;
+mod git;
+mod hooks;
+mod idempotency;
+#[cfg(feature = "api")]
+mod inbound;
+mod inserts;
+mod language;
+#[cfg(unix)]
+mod listener;
+mod lockout;
+mod logs;
+mod metrics;
 mod model;
+#[cfg(feature = "pdf")]
+mod pdf;
+mod photos;
+mod preview;
+mod quarantine;
+mod rate_limit;
+mod registry;
+mod reload;
+mod request;
+mod s3;
+mod seating;
+mod session;
+mod shuttle;
+mod site_password;
+mod songs;
+mod source;
 mod state;
+mod tasks;
+mod telemetry;
+mod thanks;
+mod throttle;
+mod timezone;
+mod tls;
+mod travel;
+mod waves;
 
 use {
     crate::{
+        access_log::log_access,
+        acme::redirect_to_https,
+        analytics::count_views,
+        archive::read_only,
+        audit::EnteredBy,
+        banner::show_banners,
+        bounce::parse_notification,
+        config::Config,
+        contact::{ContactMessage, ContactParams},
         error::{error_handlers, Error},
+        flash::show_flashes,
+        form::{Form, RsvpForm},
+        language::localize,
         model::{
-            AddParams, ErrorContext, IndexContext, NameParams, PhotosContext, RsvpParams,
-            NUM_PHOTOS,
+            AddParams, AlbumContext, ConfirmContext, ContactContext, ErrorContext, FetchContext,
+            FetchParams, FormFor, IndexContext, PhotosContext, PreferencesContext,
+            PreferencesParams, RememberedGuest, RsvpModel, SongsContext, StatsContext,
+            TravelContext, VerifyContext, VerifyParams, WebhookParams, FETCH_FIELDS, VERIFY_FIELDS,
+        },
+        photos::Album,
+        reload::{current_state, LiveState},
+        request::{assign_request_id, client_ip, limit_writes, request_id},
+        site_password::{enter, handle_enter, require_site_password},
+        songs::{SongParams, VoteParams},
+        source::Source,
+        state::{AppState, Templates},
+    },
+    actix_files::{Files, NamedFile},
+    actix_session::Session,
+    actix_web::{
+        http::{
+            header::{HeaderValue, CACHE_CONTROL, LOCATION},
+            StatusCode,
         },
-        state::AppState,
+        middleware::{self, from_fn},
+        web, App, Error as ActixError, HttpRequest, HttpResponse, HttpServer, ResponseError,
+        Result,
     },
-    actix_files::Files,
-    actix_web::{middleware, web, App, Error as ActixError, HttpResponse, HttpServer, Result},
-    chrono::Utc,
+    chrono::{DateTime, Utc},
     clap::Parser,
-    log::{error, info},
-    tinytemplate::TinyTemplate,
+    log::{error, info, warn},
+    std::{sync::Arc, time::Duration},
+    tracing::instrument,
 };
 
 static NOT_FOUND_MESSAGE: &str = "That name was not found, sorry! Please use the exact full name from the invitation email, or contact us if you think something is wrong.";
+static SITEMAP_PAGES: &[&str] = &["/", "/photos", "/fetch"];
+static TOO_MANY_MESSAGES: &str =
+    "You have sent us a lot of messages, thank you! Please try again in an hour.";
+static LOCKED_OUT_MESSAGE: &str =
+    "You have looked up a lot of names, sorry! Please try again later, or contact us if you can't find yours.";
+static MESSAGE_NOT_SENT: &str =
+    "Sorry, your message could not be sent. Please try again in a little while.";
+static WRONG_CODE_MESSAGE: &str =
+    "That code does not match, sorry! Please check the latest email we sent and try again. After a few wrong tries the code stops working, so send your RSVP again for a new one.";
+
+fn name_not_found(tt: &dyn Templates) -> Result<HttpResponse, ActixError> {
+    let ctx = serde_json::to_value(FetchContext {
+        remembered: None,
+        errors: ErrorContext::new(FETCH_FIELDS, vec![("name", NOT_FOUND_MESSAGE.to_string())]),
+    })?;
+    let body = tt.render("fetch.html", &ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+fn guest_list_full(tt: &dyn Templates) -> Result<HttpResponse, ActixError> {
+    let body = tt.render("full.html", &serde_json::json!({}))?;
+    Ok(HttpResponse::InsufficientStorage()
+        .content_type("text/html")
+        .body(body))
+}
 
-fn name_not_found(tt: &TinyTemplate<'_>) -> Result<HttpResponse, ActixError> {
-    let ctx = serde_json::to_value(ErrorContext {
-        has_error: true,
-        error: NOT_FOUND_MESSAGE.to_string(),
+/// Turn away an IP address locked out of looking names up
+fn locked_out(
+    tt: &dyn Templates,
+    until: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Result<HttpResponse, ActixError> {
+    let ctx = serde_json::to_value(FetchContext {
+        remembered: None,
+        errors: ErrorContext::page(FETCH_FIELDS, LOCKED_OUT_MESSAGE),
     })?;
-    let body = tt.render("fetch.html", &ctx).map_err(Error::from)?;
+    let body = tt.render("fetch.html", &ctx)?;
+    Ok(lockout::too_many_requests(until, now)
+        .content_type("text/html")
+        .body(body))
+}
+
+fn confirm(tt: &dyn Templates, ctx: ConfirmContext) -> Result<HttpResponse, ActixError> {
+    let ctx = serde_json::to_value(ctx)?;
+    let body = tt.render("confirm.html", &ctx)?;
     Ok(HttpResponse::Ok().content_type("text/html").body(body))
 }
 
 fn app_config(config: &mut web::ServiceConfig) {
+    let scope = web::scope("")
+        .service(web::resource("/").route(web::get().to(index)))
+        .service(web::resource("/theme.css").route(web::get().to(theme_css)))
+        .service(web::resource("/version").route(web::get().to(version)))
+        .service(web::resource("/robots.txt").route(web::get().to(robots_txt)))
+        .service(web::resource("/sitemap.xml").route(web::get().to(sitemap)))
+        .service(web::resource("/stats").route(web::get().to(stats)))
+        .service(
+            web::resource("/contact")
+                .route(web::get().to(contact))
+                .route(web::post().to(handle_contact)),
+        )
+        .service(web::resource("/calendar/{number}.ics").route(web::get().to(event_ics)))
+        .service(web::resource("/go/{registry}").route(web::get().to(go_to_registry)))
+        .service(web::resource("/travel").route(web::get().to(travel_page)))
+        .service(
+            web::resource("/enter")
+                .route(web::get().to(enter))
+                .route(web::post().to(handle_enter)),
+        )
+        .service(
+            web::resource("/.well-known/acme-challenge/{token}")
+                .route(web::get().to(acme_challenge)),
+        )
+        .service(
+            web::resource("/fetch")
+                .route(web::get().to(fetch))
+                .route(web::post().to(handle_fetch)),
+        )
+        .service(web::resource("/forget").route(web::post().to(forget)))
+        .service(
+            web::resource("/rsvp")
+                .wrap(from_fn(limit_writes))
+                .route(web::post().to(handle_rsvp)),
+        )
+        .service(web::resource("/rsvp/{token}").route(web::get().to(rsvp_link)))
+        .service(web::resource("/i/{code}").route(web::get().to(short_link)))
+        .service(
+            web::resource("/verify")
+                .wrap(from_fn(limit_writes))
+                .route(web::post().to(handle_verify)),
+        )
+        .service(
+            web::resource("/add")
+                .wrap(from_fn(limit_writes))
+                .route(web::post().to(handle_add)),
+        )
+        .service(
+            web::resource("/unsubscribe/{token}")
+                .route(web::get().to(preferences))
+                .route(web::post().to(handle_preferences)),
+        )
+        .service(
+            web::resource("/songs/{token}")
+                .route(web::get().to(songs_page))
+                .route(web::post().to(handle_song)),
+        )
+        .service(web::resource("/songs/{token}/vote").route(web::post().to(handle_vote)))
+        .service(web::resource("/webhooks/bounce").route(web::post().to(handle_bounce)));
+    #[cfg(feature = "photos")]
+    let scope = scope.configure(photos_config);
+    #[cfg(feature = "admin")]
+    let scope = scope.configure(admin::admin_config);
+    #[cfg(feature = "admin")]
+    let scope = scope.configure(dav::dav_config);
+    #[cfg(feature = "api")]
+    let scope = scope.configure(api::api_config);
     config.service(
-        web::scope("")
-            .service(web::resource("/").route(web::get().to(index)))
-            .service(web::resource("/photos").route(web::get().to(photos)))
-            .service(
-                web::resource("/fetch")
-                    .route(web::get().to(fetch))
-                    .route(web::post().to(handle_fetch)),
-            )
-            .service(web::resource("/rsvp").route(web::post().to(handle_rsvp)))
-            .service(web::resource("/add").route(web::post().to(handle_add)))
-            .wrap(error_handlers()),
+        scope
+            .wrap(from_fn(read_only))
+            .wrap(from_fn(require_site_password))
+            .wrap(from_fn(show_flashes))
+            .wrap(session::middleware())
+            .wrap(error_handlers())
+            .wrap(from_fn(show_banners))
+            .wrap(from_fn(count_views))
+            .wrap(from_fn(assign_request_id)),
+    );
+}
+
+/// Routes for the photo albums
+#[cfg(feature = "photos")]
+fn photos_config(config: &mut web::ServiceConfig) {
+    config.service(
+        web::scope("/photos")
+            .guard(request::feature_enabled(|features| features.photos))
+            .service(web::resource("").route(web::get().to(photos)))
+            .service(web::resource("/{album}").route(web::get().to(album)))
+            .service(web::resource("/{album}/{file}").route(web::get().to(photo))),
     );
 }
 
 /// Return the index page
 async fn index(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
-    let admin = state.email.admin.clone();
-    let ctx = serde_json::to_value(IndexContext { admin })?;
-    let body = state.tt.render("index.html", &ctx).map_err(Error::from)?;
+    let contact = state.config.contact.enabled;
+    let admin = match contact {
+        true => String::default(),
+        false => state.email.admin.clone(),
+    };
+    let ctx = serde_json::to_value(IndexContext { admin, contact })?;
+    let body = state.tt.render("index.html", &ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Answer the ACME CA's challenge while it checks that the site is ours
+async fn acme_challenge(state: web::Data<AppState<'_>>, token: web::Path<String>) -> HttpResponse {
+    match state.acme.as_ref().and_then(|acme| acme.challenge(&token)) {
+        Some(key_authorization) => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .body(key_authorization),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Return which build is running
+async fn version(state: web::Data<AppState<'_>>) -> HttpResponse {
+    HttpResponse::Ok().json(state.build_info())
+}
+
+/// Return the stylesheet generated from the theme config
+async fn theme_css(state: web::Data<AppState<'_>>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/css")
+        .body(state.config.theme.css())
+}
+
+/// Return the configured robots.txt, pointing to the sitemap if enabled
+async fn robots_txt(state: web::Data<AppState<'_>>) -> HttpResponse {
+    let site = &state.config.site;
+    let mut body = site.robots_txt.clone();
+    if site.sitemap {
+        body.push_str(&format!("Sitemap: {}\n", state.config.url("/sitemap.xml")));
+    }
+    HttpResponse::Ok().content_type("text/plain").body(body)
+}
+
+/// Return a sitemap of the public pages, if enabled
+async fn sitemap(state: web::Data<AppState<'_>>) -> HttpResponse {
+    if !state.config.site.sitemap {
+        return HttpResponse::NotFound().finish();
+    }
+    let photos = cfg!(feature = "photos") && state.config.features.photos;
+    let urls = SITEMAP_PAGES
+        .iter()
+        .filter(|path| photos || **path != "/photos")
+        .map(|path| format!("  <url><loc>{}</loc></url>\n", state.config.url(path)))
+        .collect::<String>();
+    HttpResponse::Ok()
+        .content_type("application/xml")
+        .body(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n{urls}</urlset>\n"
+        ))
+}
+
+/// Return how many parties have responded and the headcount so far, if
+/// enabled, for the couple to share with guests who haven't answered yet
+async fn stats(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    if !state.config.site.stats {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let db = state.read_db().await?;
+    let headcount = db.headcount()?;
+    let ctx = serde_json::to_value(StatsContext {
+        parties: headcount.parties,
+        responded_parties: headcount.responded_parties,
+        attendance: db.attendance()?,
+    })?;
+    let body = state.tt.render("stats.html", &ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Return the page for writing to the couple, if it's enabled
+async fn contact(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    if !state.config.contact.enabled {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let ctx = serde_json::to_value(ContactContext::default())?;
+    let body = state.tt.render("contact.html", &ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Relay a message from the contact page to the admin, keeping it in the
+/// contact log too if there is one.  What looks like spam is held for the
+/// admin to approve or reject instead, with the same thanks as everyone
+/// else, so bots can't tell.
+#[instrument(skip_all, fields(name = %params.name))]
+async fn handle_contact(
+    state: web::Data<AppState<'_>>,
+    req: HttpRequest,
+    params: web::Form<ContactParams>,
+) -> Result<HttpResponse> {
+    let config = &state.config.contact;
+    if !config.enabled {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let params = params.into_inner();
+    let mut ctx = ContactContext {
+        name: params.name.clone(),
+        email: params.email.clone(),
+        message: params.message.clone(),
+        ..ContactContext::default()
+    };
+    let client_ip = client_ip(&req);
+    let now = state.clock.now();
+    let problems = params.problems(config);
+    let status = if let Some(reason) = params.spam_reason(config) {
+        warn!(
+            "Holding a message from the contact page as spam ({}), from {}",
+            reason, client_ip
+        );
+        let message = ContactMessage::new(&params, now, &request_id(&req).0, &client_ip);
+        state.quarantine.hold(message, &reason)?;
+        ctx.sent = true;
+        StatusCode::OK
+    } else if let Some((_, error)) = problems.first() {
+        let status = error.status_code();
+        ctx.errors = ErrorContext::new(
+            contact::FIELDS,
+            problems
+                .into_iter()
+                .map(|(field, error)| (field, error.to_string()))
+                .collect(),
+        );
+        status
+    } else if !state.contact_limiter().allow(
+        &client_ip,
+        config.max_per_hour,
+        chrono::Duration::hours(1),
+        now,
+    ) {
+        warn!(
+            "Too many messages from the contact page, from {}",
+            client_ip
+        );
+        ctx.errors = ErrorContext::page(contact::FIELDS, TOO_MANY_MESSAGES);
+        StatusCode::TOO_MANY_REQUESTS
+    } else {
+        let message = ContactMessage::new(&params, now, &request_id(&req).0, &client_ip);
+        match state.relay_contact(&message).await {
+            Err(error) => {
+                error!("Could not send the contact message: {:?}", error);
+                ctx.errors = ErrorContext::page(contact::FIELDS, MESSAGE_NOT_SENT);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            Ok(()) => {
+                info!("Relayed a message from the contact page");
+                ctx.sent = true;
+                StatusCode::OK
+            }
+        }
+    };
+    let ctx = serde_json::to_value(ctx)?;
+    let body = state.tt.render("contact.html", &ctx)?;
+    Ok(HttpResponse::build(status)
+        .content_type("text/html")
+        .body(body))
+}
+
+/// Return an iCalendar file with one of the events, numbered from 1, for
+/// guests to add to their calendar
+async fn event_ics(state: web::Data<AppState<'_>>, number: web::Path<usize>) -> HttpResponse {
+    match calendar::ics(&state.config, number.into_inner(), state.clock.now()) {
+        Some(ics) => HttpResponse::Ok()
+            .content_type("text/calendar; charset=utf-8")
+            .body(ics),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Send a guest on to one of the gift registries, counting the click
+async fn go_to_registry(state: web::Data<AppState<'_>>, name: web::Path<String>) -> HttpResponse {
+    match registry::find(&state.config.registry, &name) {
+        Some(link) => {
+            if let Err(e) = state.registry_clicks.record(&link.name) {
+                error!("Could not save the registry clicks: {}", e);
+            }
+            HttpResponse::Found()
+                .insert_header((LOCATION, link.url.clone()))
+                .finish()
+        }
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// The guest with the token, if song requests are on
+async fn song_guest(state: &AppState<'_>, token: &str) -> Result<Option<RsvpModel>, Error> {
+    if !state.config.songs.enabled {
+        return Ok(None);
+    }
+    state.read_db().await?.find_by_token(token)
+}
+
+/// Show the songs requested so far, most wanted first, for a guest to vote
+/// for or add to, with why their last request couldn't be added if it wasn't
+fn render_songs(
+    state: &AppState<'_>,
+    record: RsvpModel,
+    error: Option<Error>,
+) -> Result<HttpResponse, Error> {
+    let status = error
+        .as_ref()
+        .map_or(StatusCode::OK, ResponseError::status_code);
+    let ctx = serde_json::to_value(SongsContext {
+        songs: state.songs.ranked(Some(&record.token)),
+        name: record.name,
+        token: record.token,
+        has_error: error.is_some(),
+        error: error.map(|error| error.to_string()).unwrap_or_default(),
+    })?;
+    let body = state.tt.render("songs.html", &ctx)?;
+    Ok(HttpResponse::build(status)
+        .content_type("text/html")
+        .body(body))
+}
+
+/// Show a guest the song requests
+async fn songs_page(
+    state: web::Data<AppState<'_>>,
+    token: web::Path<String>,
+) -> Result<HttpResponse> {
+    match song_guest(&state, &token).await? {
+        Some(record) => Ok(render_songs(&state, record, None)?),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+/// Add a guest's song request, or their vote if it's already on the list
+#[instrument(skip_all)]
+async fn handle_song(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    token: web::Path<String>,
+    params: web::Form<SongParams>,
+) -> Result<HttpResponse> {
+    let record = match song_guest(&state, &token).await? {
+        Some(record) => record,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    match state
+        .songs
+        .request(&record.token, &params, state.config.songs.max_per_guest)
+    {
+        Ok(()) => {
+            info!("Song requested by {}: {:?}", record.name, params);
+            flash::success(&session, "Thanks for the request!")?;
+            Ok(HttpResponse::SeeOther()
+                .insert_header((LOCATION, format!("/songs/{}", record.token)))
+                .finish())
+        }
+        Err(error @ (Error::Required(_) | Error::Invalid(_))) => {
+            Ok(render_songs(&state, record, Some(error))?)
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Count a guest's vote for a song on the list
+#[instrument(skip_all)]
+async fn handle_vote(
+    state: web::Data<AppState<'_>>,
+    session: Session,
+    token: web::Path<String>,
+    params: web::Form<VoteParams>,
+) -> Result<HttpResponse> {
+    let record = match song_guest(&state, &token).await? {
+        Some(record) => record,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    if !state.songs.vote(&record.token, &params.song)? {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    flash::success(&session, "Thanks for voting!")?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, format!("/songs/{}", record.token)))
+        .finish())
+}
+
+/// Return the hotels, airports and parking for getting to the event
+async fn travel_page(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
+    let today = travel::today(&state.config, state.clock.now());
+    let ctx = serde_json::to_value(TravelContext::new(&state.config.travel, today))?;
+    let body = state.tt.render("travel.html", &ctx)?;
     Ok(HttpResponse::Ok().content_type("text/html").body(body))
 }
 
-/// Return the photos page
+/// Return the index of photo albums
+#[cfg(feature = "photos")]
 async fn photos(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
     let admin = state.email.admin.clone();
-    let photo_indices = (1..=NUM_PHOTOS)
-        .collect::<Vec<_>>()
-        .try_into()
-        .expect("Wrong size");
+    let albums = photos::albums(&state.config.photos)?;
+    let videos = photos::index_videos(&state.config.photos);
     let ctx = serde_json::to_value(PhotosContext {
         admin,
-        photo_indices,
+        albums,
+        videos,
+    })?;
+    let body = state.tt.render("photos.html", &ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Return the gallery of one album
+#[cfg(feature = "photos")]
+async fn album(state: web::Data<AppState<'_>>, name: web::Path<String>) -> Result<HttpResponse> {
+    let items = photos::gallery(&state.config.photos, &name)?.unwrap_or_default();
+    let photos = items
+        .iter()
+        .filter_map(|item| item.photo.as_ref())
+        .collect::<Vec<_>>();
+    let cover = match photos.first() {
+        Some(photo) => photo.url.clone(),
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let ctx = serde_json::to_value(AlbumContext {
+        admin: state.email.admin.clone(),
+        album: Album {
+            title: photos::title(&name),
+            cover,
+            count: photos.len(),
+            name: name.into_inner(),
+        },
+        items,
     })?;
-    let body = state.tt.render("photos.html", &ctx).map_err(Error::from)?;
+    let body = state.tt.render("album.html", &ctx)?;
     Ok(HttpResponse::Ok().content_type("text/html").body(body))
 }
 
-/// Return the fetch page
-async fn fetch(state: web::Data<AppState<'_>>) -> Result<HttpResponse> {
-    let ctx = serde_json::to_value(ErrorContext::default())?;
-    let body = state.tt.render("fetch.html", &ctx).map_err(Error::from)?;
+/// Return one photo of an album, or one of its variants.  Versioned URLs,
+/// as linked from the galleries, are cached for long.
+#[cfg(feature = "photos")]
+async fn photo(
+    req: HttpRequest,
+    state: web::Data<AppState<'_>>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse> {
+    let (album, file) = path.into_inner();
+    let path = photos::photo_path(&state.config.photos, &album, &file)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("no such photo"))?;
+    let mut res = NamedFile::open_async(path).await?.into_response(&req);
+    if req.query_string().starts_with("v=") {
+        let max_age = state.config.photos.cache_max_age_secs;
+        res.headers_mut().insert(
+            CACHE_CONTROL,
+            HeaderValue::from_str(&format!("public, max-age={}, immutable", max_age))?,
+        );
+    }
+    Ok(res)
+}
+
+/// Return the fetch page, offering the guest remembered on this browser
+/// their RSVP
+async fn fetch(state: web::Data<AppState<'_>>, session: Session) -> Result<HttpResponse> {
+    let mut remembered = None;
+    if let Some(token) = session::remembered_guest(&session) {
+        match state.read_db().await?.find_by_token(&token)? {
+            Some(record) => {
+                remembered = Some(RememberedGuest {
+                    name: record.name,
+                    token,
+                })
+            }
+            None => session::forget_guest(&session),
+        }
+    }
+    let ctx = serde_json::to_value(FetchContext {
+        remembered,
+        errors: ErrorContext::default(),
+    })?;
+    let body = state.tt.render("fetch.html", &ctx)?;
     Ok(HttpResponse::Ok().content_type("text/html").body(body))
 }
 
 /// Get an existing rsvp
+#[instrument(skip_all, fields(name = %params.name))]
 async fn handle_fetch(
     state: web::Data<AppState<'_>>,
-    params: web::Form<NameParams>,
+    req: HttpRequest,
+    session: Session,
+    params: web::Form<FetchParams>,
 ) -> Result<HttpResponse, ActixError> {
+    let client_ip = client_ip(&req);
+    let now = state.clock.now();
+    if let Some(until) = state.fetch_lockout.locked_until(&client_ip, now) {
+        state
+            .metrics
+            .increment("rsvp_fetch_total", &[("result", "locked_out")]);
+        return locked_out(state.tt.as_ref(), until, now);
+    }
     if params.name.is_empty() {
-        return name_not_found(&state.tt);
+        return name_not_found(state.tt.as_ref());
+    }
+    let db = state.read_db().await?;
+    if let Some(record) = db.get(&params.name)? {
+        state
+            .metrics
+            .increment("rsvp_fetch_total", &[("result", "found")]);
+        if params.remember {
+            session::remember_guest(&session, &record.token)?;
+        }
+        let body = state.render_rsvp_form(&db, record, FormFor::Guest, vec![])?;
+        return Ok(HttpResponse::Ok().content_type("text/html").body(body));
+    }
+    drop(db);
+    state
+        .metrics
+        .increment("rsvp_fetch_total", &[("result", "not_found")]);
+    let config = &state.config.fetch_lockout;
+    if let Some(until) = state.fetch_lockout.miss(&client_ip, config, now) {
+        state
+            .lock_out(&req, &client_ip, &params.into_inner(), until)
+            .await;
+    }
+    name_not_found(state.tt.as_ref())
+}
+
+/// Stop remembering the guest on this browser, like on a shared computer
+async fn forget(session: Session) -> Result<HttpResponse, ActixError> {
+    session::forget_guest(&session);
+    flash::success(&session, "We won't remember you on this device anymore")?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/fetch"))
+        .finish())
+}
+
+/// Show a guest their RSVP form straight from the link sent to them
+#[instrument(skip_all)]
+async fn rsvp_link(
+    state: web::Data<AppState<'_>>,
+    token: web::Path<String>,
+) -> Result<HttpResponse, ActixError> {
+    let db = state.read_db().await?;
+    match db.find_by_token(&token)? {
+        Some(record) => {
+            let body = state.render_rsvp_form(&db, record, FormFor::Guest, vec![])?;
+            Ok(HttpResponse::Ok().content_type("text/html").body(body))
+        }
+        None => name_not_found(state.tt.as_ref()),
+    }
+}
+
+/// Send a guest from their short link to their RSVP link.  Codes not found
+/// count towards the same lockout as `/fetch`, so the short codes can't be
+/// gone through to open guests' forms.
+#[instrument(skip_all)]
+async fn short_link(
+    state: web::Data<AppState<'_>>,
+    req: HttpRequest,
+    code: web::Path<String>,
+) -> Result<HttpResponse, ActixError> {
+    let client_ip = client_ip(&req);
+    let now = state.clock.now();
+    if let Some(until) = state.fetch_lockout.locked_until(&client_ip, now) {
+        return locked_out(state.tt.as_ref(), until, now);
     }
-    let mut db = state.db.write().await;
-    let record = db.get(&params.into_inner().name)?;
+    let record = state.read_db().await?.find_by_short_code(&code)?;
     if let Some(record) = record {
-        let ctx = serde_json::to_value(record)?;
-        let body = state.tt.render("rsvp.html", &ctx).map_err(Error::from)?;
-        Ok(HttpResponse::Ok().content_type("text/html").body(body))
-    } else {
-        name_not_found(&state.tt)
+        return Ok(HttpResponse::Found()
+            .insert_header((LOCATION, format!("/rsvp/{}", record.token)))
+            .finish());
+    }
+    let config = &state.config.fetch_lockout;
+    if let Some(until) = state.fetch_lockout.miss(&client_ip, config, now) {
+        let params = [("code", code.as_str())];
+        state.lock_out(&req, &client_ip, &params, until).await;
     }
+    name_not_found(state.tt.as_ref())
 }
 
 /// Add an rsvp to the csv file
+#[instrument(skip_all, fields(name = %params.name))]
 async fn handle_rsvp(
     state: web::Data<AppState<'_>>,
-    params: web::Form<RsvpParams>,
+    req: HttpRequest,
+    params: RsvpForm,
 ) -> Result<HttpResponse, ActixError> {
-    let mut db = state.db.write().await;
+    let mut db = state.write_db().await?;
     let email = &state.email;
-    db.update_time(Utc::now());
+    let mut params = params.into_inner();
+    params.source = Some(Source::Web);
+    info!("New RSVP! request {}: {:?}", request_id(&req), params);
+    let problems = Form::new(&state.config.fields).problems(&mut params);
+    if !problems.is_empty() {
+        return Ok(state.rsvp_form_error(&db, &params, FormFor::Guest, problems)?);
+    }
+    if params.language.is_empty() {
+        params.language = language::from_request(&req, &state.config.languages.supported());
+    }
+    if let Some(record) = state.find_duplicate(&db, &params)? {
+        // a new code, if the last one was thrown away after wrong tries
+        let record = match record.verification_code.is_empty() {
+            true => state.start_verification(&mut db, record).await?,
+            false => record,
+        };
+        return confirm(
+            state.tt.as_ref(),
+            ConfirmContext::new(record, &state.config),
+        );
+    }
+    let record = match db.upsert(&params) {
+        Err(error @ (Error::MealFull(_) | Error::ShuttleFull(_))) => {
+            let problems = vec![(form::field_of(&error, &params), error)];
+            return Ok(state.rsvp_form_error(&db, &params, FormFor::Guest, problems)?);
+        }
+        Err(Error::Full) => return guest_list_full(state.tt.as_ref()),
+        result => {
+            state
+                .notify_on_failure(&req, "RSVP", &params, result)
+                .await?
+        }
+    };
+    state.audit(&req, "RSVP", &record.name, EnteredBy::Guest);
+    state.hooks.deliver_in_background(&record);
+    let contents = db.dump();
+    let attendance = db.attendance()?;
+    if let Err(error) = email
+        .send_csv(
+            &params,
+            &record,
+            &attendance,
+            contents,
+            state.clock.now(),
+            state.test,
+        )
+        .await
+    {
+        error!("Could not send confirmation email: {:?}", error);
+    }
+    state.confirm_plus_one(&record).await;
+    let record = state.start_verification(&mut db, record).await?;
+    confirm(
+        state.tt.as_ref(),
+        ConfirmContext::new(record, &state.config),
+    )
+}
+
+/// Check the code sent to a guest's email, marking the record verified if it
+/// matches.  Wrong codes and names not on the list get the same answer and
+/// count towards the same lockout as `/fetch`, and a guest's code is thrown
+/// away after too many wrong tries, so codes can't be guessed.
+#[instrument(skip_all, fields(name = %params.name))]
+async fn handle_verify(
+    state: web::Data<AppState<'_>>,
+    req: HttpRequest,
+    params: web::Form<VerifyParams>,
+) -> Result<HttpResponse, ActixError> {
+    let client_ip = client_ip(&req);
+    let now = state.clock.now();
+    if let Some(until) = state.fetch_lockout.locked_until(&client_ip, now) {
+        return locked_out(state.tt.as_ref(), until, now);
+    }
     let params = params.into_inner();
-    info!("New RSVP! {:?}", params);
-    match db.upsert(&params) {
-        Ok(record) => {
-            let contents = db.dump();
-            let attendance = db.attendance()?;
-            if let Err(error) = email
-                .send_csv(&params, &attendance, contents, state.test)
-                .await
-            {
-                error!("Could not send confirmation email: {:?}", error);
-            }
-            let ctx = serde_json::to_value(record)?;
-            let body = state.tt.render("confirm.html", &ctx).map_err(Error::from)?;
-            Ok(HttpResponse::Ok().content_type("text/html").body(body))
+    let mut db = state.write_db().await?;
+    let record = db.get(&params.name)?;
+    let config = &state.config.fetch_lockout;
+    if let Some(record) = record.filter(|r| !r.verification_code.is_empty()) {
+        if record.verification_code == params.code.trim() {
+            info!("Verified email for {}", record.name);
+            state.code_attempts.reset(&record.name_key);
+            let record = db
+                .update_with(&record.name, |r| {
+                    r.email_verified = true;
+                    r.verification_code.clear();
+                })?
+                .unwrap_or(record);
+            return confirm(
+                state.tt.as_ref(),
+                ConfirmContext::new(record, &state.config),
+            );
+        }
+        if state
+            .code_attempts
+            .miss(&record.name_key, config.max_code_attempts)
+        {
+            warn!(
+                "Throwing away the verification code for {} after too many wrong tries",
+                record.name
+            );
+            db.update_with(&record.name, |r| r.verification_code.clear())?;
+        }
+    }
+    drop(db);
+    if let Some(until) = state.fetch_lockout.miss(&client_ip, config, now) {
+        state.lock_out(&req, &client_ip, &params, until).await;
+    }
+    let ctx = serde_json::to_value(VerifyContext {
+        name: params.name,
+        errors: ErrorContext::new(
+            VERIFY_FIELDS,
+            vec![("code", WRONG_CODE_MESSAGE.to_string())],
+        ),
+    })?;
+    let body = state.tt.render("verify.html", &ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
+/// Mark guests' email addresses as undeliverable from a provider's bounce or
+/// complaint notification.  The body is read as JSON whatever its content
+/// type, since SNS sends it as `text/plain`.
+#[instrument(skip_all)]
+async fn handle_bounce(
+    state: web::Data<AppState<'_>>,
+    req: HttpRequest,
+    query: web::Query<WebhookParams>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ActixError> {
+    let secret = req
+        .headers()
+        .get(inbound::WEBHOOK_SECRET)
+        .and_then(|secret| secret.to_str().ok())
+        .or(query.secret.as_deref());
+    let authorized = match (&state.config.webhook_secret, secret) {
+        (Some(expected), Some(secret)) if !expected.is_empty() => {
+            inbound::same_secret(secret, expected)
         }
+        _ => false,
+    };
+    if !authorized {
+        warn!("Rejected bounce notification with a missing or wrong secret");
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let body = match serde_json::from_slice(&body) {
+        Ok(body) => body,
         Err(error) => {
-            // it'd be better to do this generically, but oh well!
-            if let Err(send_error) = email.send_rsvp_error(&error, &params, state.test).await {
-                error!(
-                    "Could not send error email: {:?}, original error: {:?}",
-                    send_error, error
-                );
-            }
-            Err(error.into())
+            warn!("Could not read bounce notification: {}", error);
+            return Ok(HttpResponse::BadRequest().finish());
+        }
+    };
+    let mut db = state.write_db().await?;
+    for bounce in parse_notification(&body) {
+        for record in db.find_by_email(&bounce.email)? {
+            warn!("Marking {} as {:?}", record.email, bounce.status);
+            db.update_with(&record.name, |r| r.email_status = bounce.status)?;
+        }
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Show a guest's email preferences, reached from the unsubscribe link in
+/// emails, so announcements start unticked
+#[instrument(skip_all)]
+async fn preferences(
+    state: web::Data<AppState<'_>>,
+    token: web::Path<String>,
+) -> Result<HttpResponse, ActixError> {
+    let record = state.read_db().await?.find_by_token(&token)?;
+    match record {
+        Some(record) => {
+            let ctx = serde_json::to_value(PreferencesContext {
+                announcements: false,
+                transactional: !record.transactional_opt_out,
+                name: record.name,
+                token: record.token,
+                done: false,
+            })?;
+            let body = state.tt.render("preferences.html", &ctx)?;
+            Ok(HttpResponse::Ok().content_type("text/html").body(body))
         }
+        None => Ok(HttpResponse::NotFound().finish()),
     }
 }
 
+/// Update which kinds of email a guest wants to receive
+#[instrument(skip_all)]
+async fn handle_preferences(
+    state: web::Data<AppState<'_>>,
+    token: web::Path<String>,
+    params: web::Form<PreferencesParams>,
+) -> Result<HttpResponse, ActixError> {
+    let mut db = state.write_db().await?;
+    let record = match db.find_by_token(&token)? {
+        Some(record) => record,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let params = params.into_inner();
+    info!("New email preferences for {}: {:?}", record.name, params);
+    let record = db
+        .update_with(&record.name, |r| {
+            r.announcements_opt_out = params.announcements.is_none();
+            r.transactional_opt_out = params.transactional.is_none();
+        })?
+        .unwrap_or(record);
+    let ctx = serde_json::to_value(PreferencesContext {
+        announcements: !record.announcements_opt_out,
+        transactional: !record.transactional_opt_out,
+        name: record.name,
+        token: record.token,
+        done: true,
+    })?;
+    let body = state.tt.render("preferences.html", &ctx)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
 /// Add a person to the csv file
+#[instrument(skip_all, fields(name = %params.name))]
 async fn handle_add(
     state: web::Data<AppState<'_>>,
+    req: HttpRequest,
     params: web::Form<AddParams>,
 ) -> Result<HttpResponse, ActixError> {
-    let mut db = state.db.write().await;
-    db.update_time(Utc::now());
+    let mut db = state.write_db().await?;
     let params = params.into_inner();
-    info!("New person! {:?}", params);
-    let model = db.insert(&params)?;
+    info!("New person! request {}: {:?}", request_id(&req), params);
+    let model = state
+        .notify_on_failure(&req, "add", &params, db.insert(&params))
+        .await?;
     Ok(HttpResponse::Ok()
         .content_type("text/plain")
         .body(format!("Success adding!\n{:?}", model)))
@@ -173,53 +1005,243 @@ struct Args {
     #[arg(short, long, default_value_t = 8080)]
     port: u16,
 
-    /// Number of web worker threads to spawn
-    #[arg(short, long, default_value_t = 1)]
-    workers: usize,
+    /// Number of web worker threads to spawn, or 0 for one per CPU core.
+    /// Defaults to `server.workers` from the config, which is 1.
+    #[arg(short, long)]
+    workers: Option<usize>,
+
+    /// TOML file with optional configuration
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Add this many fake guests to the CSV file before starting, for load
+    /// tests and UI work
+    #[arg(long, value_name = "N")]
+    seed_fake: Option<usize>,
+
+    /// Check the config, files, templates and email setup, print what's
+    /// wrong, and exit instead of starting the server
+    #[arg(long)]
+    check: bool,
+
+    /// Listen on this unix socket instead of the port, e.g. behind a proxy
+    /// on the same host
+    #[cfg(unix)]
+    #[arg(long, value_name = "PATH")]
+    uds: Option<String>,
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let matches = Args::parse();
     std::env::set_var("RUST_LOG", "debug");
-    env_logger::init();
+
+    let config = match &matches.config {
+        Some(path) => match Config::from_file(path) {
+            Ok(config) => config,
+            Err(error) if matches.check => {
+                print!("{}", doctor::Report::bad_config(error));
+                std::process::exit(1);
+            }
+            Err(error) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    error.to_string(),
+                ))
+            }
+        },
+        None => Config::default(),
+    };
+    if matches.check {
+        let report = doctor::diagnose(
+            &config,
+            &matches.csv,
+            &matches.from,
+            &matches.admin,
+            matches.test,
+        );
+        print!("{}", report);
+        std::process::exit(if report.ok() { 0 } else { 1 });
+    }
+    let _telemetry = telemetry::init(&config.tracing);
+    session::init(&config)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+    let builder = AppState::builder()
+        .admin(&matches.admin)
+        .sender(&matches.from)
+        .csv(&matches.csv)
+        .test(matches.test)
+        .config(config.clone())
+        .config_file(matches.config.as_deref());
+    // checks the setup before anything starts, and opens the CSV file and the
+    // other files kept while running once, for every worker and the background
+    // tasks
+    let state = builder
+        .build()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+
+    if let Some(count) = matches.seed_fake {
+        let records = fake::seed(&mut *state.db.write().await, count)
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        info!("Added {} fake guests to {}", records.len(), matches.csv);
+    }
 
     // start http server
-    let bind_address = format!("127.0.0.1:{}", matches.port);
-    HttpServer::new(move || {
+    let bind_address = format!("{}:{}", config.server.host, matches.port);
+    let server_config = config.server.clone();
+    #[cfg(unix)]
+    let trusted_proxies = config.trusted_proxies.clone();
+    let workers = matches.workers.unwrap_or(server_config.workers);
+    let acme = state.acme.clone();
+    let shared = state.shared();
+    #[cfg(unix)]
+    reload::reload_on_hangup(Arc::clone(&shared))?;
+    let analytics = shared.analytics.clone();
+    let scheduler = Arc::clone(&state.scheduler);
+    scheduler.start(web::Data::new(state));
+    let tls_bind = config.tls.bind.clone();
+    let redirect = match (&acme, &config.tls.redirect_bind) {
+        (Some(acme), Some(bind)) => {
+            let acme = web::Data::from(Arc::clone(acme));
+            info!("Redirecting HTTP on {} to HTTPS", bind);
+            let server = HttpServer::new(move || {
+                App::new()
+                    .wrap(middleware::Logger::default())
+                    .app_data(acme.clone())
+                    .default_service(web::to(redirect_to_https))
+            })
+            .workers(1)
+            .bind(bind)?;
+            Some(server.run())
+        }
+        _ => None,
+    };
+    let tls = acme
+        .as_ref()
+        .map(|acme| acme.certificates.acceptor())
+        .transpose()
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let mut server = HttpServer::new(move || {
         App::new()
             .service(Files::new("/static", "./static").prefer_utf8(true))
             .wrap(middleware::Logger::default())
-            .app_data(web::Data::new(AppState::new(
-                &matches.admin,
-                &matches.csv,
-                &matches.from,
-                matches.test,
-            )))
+            .wrap(from_fn(log_access))
+            .wrap(from_fn(current_state))
+            .wrap(from_fn(localize))
+            .app_data(web::Data::new(LiveState::default()))
+            .app_data(web::Data::new(shared.worker()))
             .configure(app_config)
     })
-    .workers(matches.workers)
-    .bind(&bind_address)?
-    .run()
-    .await
+    .keep_alive(server_config.keep_alive())
+    .client_request_timeout(Duration::from_millis(
+        server_config.client_request_timeout_ms,
+    ))
+    .client_disconnect_timeout(Duration::from_millis(
+        server_config.client_disconnect_timeout_ms,
+    ))
+    .max_connections(server_config.max_connections)
+    .backlog(server_config.backlog);
+    if workers > 0 {
+        server = server.workers(workers);
+    }
+    #[cfg(unix)]
+    let server = match listener::from_systemd()? {
+        Some(listener::SystemdSocket::Tcp(socket)) => {
+            info!("Listening on {} from systemd", socket.local_addr()?);
+            server.listen(socket)?
+        }
+        Some(listener::SystemdSocket::Uds(socket)) => {
+            listener::check_trusted_socket(&trusted_proxies)?;
+            info!("Listening on a unix socket from systemd");
+            server.listen_uds(socket)?
+        }
+        None => match &matches.uds {
+            Some(path) => {
+                listener::check_trusted_socket(&trusted_proxies)?;
+                listener::remove_stale_socket(path.as_ref())?;
+                server.bind_uds(path)?
+            }
+            None => server.bind(&bind_address)?,
+        },
+    };
+    #[cfg(not(unix))]
+    let server = server.bind(&bind_address)?;
+    let server = match tls {
+        Some(acceptor) => {
+            info!("Listening for HTTPS on {}", tls_bind);
+            server.bind_openssl(&tls_bind, acceptor)?
+        }
+        None => server,
+    };
+    if let Some(redirect) = redirect {
+        actix_web::rt::spawn(redirect);
+    }
+    server.run().await?;
+    // views since the task last ran would be lost otherwise
+    if let Some(analytics) = analytics {
+        if let Err(e) = analytics.save() {
+            error!("Could not save the analytics: {}", e);
+        }
+    }
+    Ok(())
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "admin", feature = "api", feature = "photos"))]
 mod tests {
     use {
         super::*,
-        crate::csvdb::test::{test_add, test_db, test_rsvp},
+        crate::{
+            acme::Acme,
+            analytics::Analytics,
+            announcement::AnnouncementParams,
+            archive::Archive,
+            backup::Backups,
+            banner::Banners,
+            clock::{Clock, MockClock},
+            config::{
+                AdminConfig, ArchiveConfig, CorsConfig, EventConfig, FeaturesConfig,
+                FormWebhookConfig, HooksConfig, LimitsConfig, MealConfig, SiteConfig, ThemeConfig,
+                TlsConfig,
+            },
+            csvdb::{
+                test::{test_add, test_db, test_rsvp},
+                CsvDb,
+            },
+            dedupe::DuplicatePair,
+            dietary::Severity,
+            hooks::{HookPayload, Hooks, Subscription},
+            idempotency::Replay,
+            lockout::LOCKOUT_ACTION,
+            model::{
+                AnswersFrom, BuildInfo, EmailStatus, MergeParams, NameParams, NotesParams, Page,
+                RsvpParams, RsvpResponse, SeedParams, TableParams,
+            },
+            reload::Reloader,
+            request::REQUEST_ID_HEADER,
+            state::SharedState,
+            tasks::Task,
+            thanks::ThanksParams,
+        },
         actix_http::body::BoxBody,
+        actix_session::SessionExt,
         actix_web::{
             body::MessageBody,
             dev::{Service, ServiceResponse},
             http::{
-                header::{HeaderValue, CONTENT_TYPE},
-                StatusCode,
+                header::{
+                    HeaderValue, ACCEPT, ACCEPT_LANGUAGE, ACCESS_CONTROL_ALLOW_METHODS,
+                    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_METHOD,
+                    CONTENT_DISPOSITION, CONTENT_TYPE, LOCATION, ORIGIN, RETRY_AFTER, SET_COOKIE,
+                },
+                Method, StatusCode,
             },
             test::{self, TestRequest},
             web::Form,
         },
+        actix_web_httpauth::headers::authorization::{Authorization, Basic},
+        chrono::{Duration, NaiveDate, TimeZone, Utc},
+        serde_json::json,
+        tempfile::tempfile,
     };
 
     trait BodyTest {
@@ -235,7 +1257,7 @@ mod tests {
 
     #[actix_rt::test]
     async fn handle_fetch_unit_test() {
-        let mut db = test_db(10);
+        let db = test_db(10);
         let records = db.get_all().unwrap();
         let state = TestRequest::default()
             .data(AppState::new_with_db(db))
@@ -243,10 +1265,13 @@ mod tests {
         let data = state.app_data::<actix_web::web::Data<AppState>>().unwrap();
 
         // found
-        let params = Form(NameParams {
+        let params = Form(FetchParams {
             name: records[0].name.clone(),
+            remember: false,
         });
-        let resp = handle_fetch(data.clone(), params).await.unwrap();
+        let resp = handle_fetch(data.clone(), state.clone(), state.get_session(), params)
+            .await
+            .unwrap();
         assert_eq!(resp.status(), StatusCode::OK);
         assert_eq!(
             resp.headers().get(CONTENT_TYPE).unwrap(),
@@ -255,10 +1280,13 @@ mod tests {
         assert!(!resp.into_body().into_str().contains(NOT_FOUND_MESSAGE));
 
         // found plus-one
-        let params = Form(NameParams {
+        let params = Form(FetchParams {
             name: records[0].plus_one_name.clone(),
+            remember: false,
         });
-        let resp = handle_fetch(data.clone(), params).await.unwrap();
+        let resp = handle_fetch(data.clone(), state.clone(), state.get_session(), params)
+            .await
+            .unwrap();
         assert_eq!(resp.status(), StatusCode::OK);
         assert_eq!(
             resp.headers().get(CONTENT_TYPE).unwrap(),
@@ -267,10 +1295,13 @@ mod tests {
         assert!(!resp.into_body().into_str().contains(NOT_FOUND_MESSAGE));
 
         // not found
-        let params = Form(NameParams {
+        let params = Form(FetchParams {
             name: "something else".to_string(),
+            remember: false,
         });
-        let resp = handle_fetch(data.clone(), params).await.unwrap();
+        let resp = handle_fetch(data.clone(), state.clone(), state.get_session(), params)
+            .await
+            .unwrap();
         assert_eq!(resp.status(), StatusCode::OK);
         assert_eq!(
             resp.headers().get(CONTENT_TYPE).unwrap(),
@@ -279,10 +1310,13 @@ mod tests {
         assert!(resp.into_body().into_str().contains(NOT_FOUND_MESSAGE));
 
         // not found empty
-        let params = Form(NameParams {
+        let params = Form(FetchParams {
             name: "".to_string(),
+            remember: false,
         });
-        let resp = handle_fetch(data.clone(), params).await.unwrap();
+        let resp = handle_fetch(data.clone(), state.clone(), state.get_session(), params)
+            .await
+            .unwrap();
         assert_eq!(resp.status(), StatusCode::OK);
         assert_eq!(
             resp.headers().get(CONTENT_TYPE).unwrap(),
@@ -298,7 +1332,13 @@ mod tests {
             .to_http_request();
         let data = state.app_data::<web::Data<AppState>>().unwrap();
         let params = Form(test_add());
-        let resp = handle_add(data.clone(), params).await.unwrap();
+        let resp = handle_add(
+            data.clone(),
+            TestRequest::default().to_http_request(),
+            params,
+        )
+        .await
+        .unwrap();
 
         assert_eq!(resp.status(), StatusCode::OK);
         assert_eq!(
@@ -308,7 +1348,13 @@ mod tests {
         assert!(resp.into_body().into_str().contains("Success"));
 
         let params = Form(test_add());
-        let _error = handle_add(data.clone(), params).await.unwrap_err();
+        let _error = handle_add(
+            data.clone(),
+            TestRequest::default().to_http_request(),
+            params,
+        )
+        .await
+        .unwrap_err();
     }
 
     #[actix_rt::test]
@@ -317,8 +1363,14 @@ mod tests {
             .app_data(web::Data::new(AppState::default()))
             .to_http_request();
         let data = state.app_data::<web::Data<AppState>>().unwrap();
-        let params = Form(test_rsvp());
-        let resp = handle_rsvp(data.clone(), params).await.unwrap();
+        let params = RsvpForm(test_rsvp());
+        let resp = handle_rsvp(
+            data.clone(),
+            TestRequest::default().to_http_request(),
+            params,
+        )
+        .await
+        .unwrap();
 
         assert_eq!(resp.status(), StatusCode::OK);
         assert_eq!(
@@ -329,34 +1381,4311 @@ mod tests {
     }
 
     #[actix_rt::test]
-    async fn handle_rsvp_integration_test() {
-        let app = test::init_service(
-            App::new()
-                .app_data(web::Data::new(AppState::default()))
-                .configure(app_config),
+    async fn handle_verify_unit_test() {
+        let state = TestRequest::default()
+            .app_data(web::Data::new(AppState::new_with_config(
+                Config {
+                    verify_email: true,
+                    ..Config::default()
+                },
+                CsvDb::default(),
+            )))
+            .to_http_request();
+        let data = state.app_data::<web::Data<AppState>>().unwrap();
+        let rsvp = test_rsvp();
+        let resp = handle_rsvp(
+            data.clone(),
+            TestRequest::default().to_http_request(),
+            RsvpForm(rsvp.clone()),
         )
-        .await;
+        .await
+        .unwrap();
+        assert!(resp.into_body().into_str().contains("verification code"));
+        let record = data.db.write().await.get(&rsvp.name).unwrap().unwrap();
+        assert!(!record.email_verified);
+        assert_eq!(record.verification_code.len(), 6);
+
+        let verify = |name: &str, code: &str| {
+            handle_verify(
+                data.clone(),
+                TestRequest::default().to_http_request(),
+                Form(VerifyParams {
+                    name: name.to_string(),
+                    code: code.to_string(),
+                }),
+            )
+        };
+
+        // a wrong code and a name not on the list get the same answer,
+        // without the guest's details
+        let wrong = verify(&rsvp.name, "wrong").await.unwrap();
+        let unknown = verify("Nobody", "123456").await.unwrap();
+        assert_eq!(wrong.status(), unknown.status());
+        let wrong = wrong.into_body().into_str();
+        assert!(wrong.contains(WRONG_CODE_MESSAGE));
+        assert!(!wrong.contains(&rsvp.email));
+        assert_eq!(
+            wrong.replace(&rsvp.name, "Nobody"),
+            unknown.into_body().into_str()
+        );
+
+        // right code
+        let resp = verify(&rsvp.name, &format!(" {} ", record.verification_code))
+            .await
+            .unwrap();
+        assert!(resp.into_body().into_str().contains("(verified)"));
+        let record = data.db.write().await.get(&rsvp.name).unwrap().unwrap();
+        assert!(record.email_verified);
+        assert!(record.verification_code.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn verify_attempts_integration_test() {
+        let config =
+            Config::from_toml("verify_email = true\n[fetch_lockout]\nmax_code_attempts = 2")
+                .unwrap();
+        let data = web::Data::new(AppState::new_with_config(config, CsvDb::default()));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let rsvp = test_rsvp();
         let req = test::TestRequest::post()
             .uri("/rsvp")
-            .set_form(test_rsvp())
+            .set_form(rsvp.clone())
+            .to_request();
+        app.call(req).await.unwrap();
+        let code = |data: &web::Data<AppState<'static>>| {
+            let data = data.clone();
+            let name = rsvp.name.clone();
+            async move {
+                let record = data.db.write().await.get(&name).unwrap().unwrap();
+                record.verification_code
+            }
+        };
+        let first = code(&data).await;
+        let verify = |code: &str, ip: &str| {
+            test::TestRequest::post()
+                .uri("/verify")
+                .peer_addr(format!("{}:4000", ip).parse().unwrap())
+                .set_form(VerifyParams {
+                    name: rsvp.name.clone(),
+                    code: code.to_string(),
+                })
+                .to_request()
+        };
+
+        // too many wrong codes, even from different addresses, throw the
+        // code away
+        app.call(verify("wrong", "203.0.113.7")).await.unwrap();
+        app.call(verify("wrong", "198.51.100.2")).await.unwrap();
+        assert_eq!(code(&data).await, "");
+        let resp: ServiceResponse = app.call(verify(&first, "203.0.113.7")).await.unwrap();
+        assert!(resp.into_body().into_str().contains(WRONG_CODE_MESSAGE));
+
+        // sending the RSVP again sends a new one
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(rsvp.clone())
+            .to_request();
+        app.call(req).await.unwrap();
+        let second = code(&data).await;
+        assert_eq!(second.len(), 6);
+        let resp: ServiceResponse = app.call(verify(&second, "203.0.113.7")).await.unwrap();
+        assert!(resp.into_body().into_str().contains("(verified)"));
+    }
+
+    #[actix_rt::test]
+    async fn verify_lockout_integration_test() {
+        let config =
+            Config::from_toml("verify_email = true\n[fetch_lockout]\nmax_misses = 2").unwrap();
+        let data = web::Data::new(AppState::new_with_config(config, test_db(1)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let verify = |name: &str| {
+            test::TestRequest::post()
+                .uri("/verify")
+                .peer_addr("203.0.113.7:4000".parse().unwrap())
+                .set_form(VerifyParams {
+                    name: name.to_string(),
+                    code: "123456".to_string(),
+                })
+                .to_request()
+        };
+        for name in ["John-0", "Nobody", "Someone"] {
+            let resp: ServiceResponse = app.call(verify(name)).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        let entries = data.audit.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, LOCKOUT_ACTION);
+        let resp: ServiceResponse = app.call(verify("John-0")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        // and from /fetch too
+        let req = test::TestRequest::post()
+            .uri("/fetch")
+            .peer_addr("203.0.113.7:4000".parse().unwrap())
+            .set_form(NameParams {
+                name: "John-0".to_string(),
+            })
             .to_request();
         let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
 
+    #[actix_rt::test]
+    async fn handle_rsvp_duplicate_unit_test() {
+        let data = web::Data::new(AppState::new_with_db(CsvDb::default()));
+        let resp = handle_rsvp(
+            data.clone(),
+            TestRequest::default().to_http_request(),
+            RsvpForm(test_rsvp()),
+        )
+        .await
+        .unwrap();
         assert_eq!(resp.status(), StatusCode::OK);
-        assert_eq!(
-            resp.headers().get(CONTENT_TYPE).unwrap(),
-            HeaderValue::from_static("text/html")
-        );
-        let (_, resp) = resp.into_parts();
+        let first = data.db.write().await.get("John").unwrap().unwrap();
+
+        let resp = handle_rsvp(
+            data.clone(),
+            TestRequest::default().to_http_request(),
+            RsvpForm(test_rsvp()),
+        )
+        .await
+        .unwrap();
         assert!(resp.into_body().into_str().contains("Confirmation"));
+        let second = data.db.write().await.get("John").unwrap().unwrap();
+        assert_eq!(first.updated_at, second.updated_at);
+    }
+
+    #[actix_rt::test]
+    async fn handle_rsvp_language_unit_test() {
+        let config = Config::from_toml(
+            "[languages.emails.fr.\"email/verification.txt\"]\ntemplate = \"fr.txt\"",
+        )
+        .unwrap();
+        let data = web::Data::new(AppState::new_with_config(config, CsvDb::default()));
+        let french = || {
+            TestRequest::default()
+                .insert_header((ACCEPT_LANGUAGE, "fr-CA,fr;q=0.9,en;q=0.8"))
+                .to_http_request()
+        };
+        handle_rsvp(data.clone(), french(), RsvpForm(test_rsvp()))
+            .await
+            .unwrap();
+        let record = data.db.write().await.get("John").unwrap().unwrap();
+        assert_eq!(record.language, "fr");
+
+        // the form sends back the language already on the record
+        let german = || {
+            TestRequest::default()
+                .insert_header((ACCEPT_LANGUAGE, "de"))
+                .to_http_request()
+        };
+        let params = RsvpParams {
+            comments: "Changed my mind".to_string(),
+            ..RsvpParams::from(&record)
+        };
+        handle_rsvp(data.clone(), german(), RsvpForm(params))
+            .await
+            .unwrap();
+        let record = data.db.write().await.get("John").unwrap().unwrap();
+        assert_eq!(record.language, "fr");
+
+        let params = RsvpParams {
+            name: "Jane".to_string(),
+            ..test_rsvp()
+        };
+        handle_rsvp(data.clone(), german(), RsvpForm(params))
+            .await
+            .unwrap();
+        let record = data.db.write().await.get("Jane").unwrap().unwrap();
+        assert_eq!(record.language, "");
+    }
+
+    #[actix_rt::test]
+    async fn handle_rsvp_clock_unit_test() {
+        let datetime = Utc.with_ymd_and_hms(2023, 5, 28, 18, 15, 0).unwrap();
+        let clock = Arc::new(MockClock::new(datetime));
+        let mut db = CsvDb::new_with_clock(tempfile().unwrap(), clock.clone());
+        db.add_header();
+        let data = web::Data::new(AppState::new_with_db(db));
+        handle_rsvp(
+            data.clone(),
+            TestRequest::default().to_http_request(),
+            RsvpForm(test_rsvp()),
+        )
+        .await
+        .unwrap();
+        let first = data.db.write().await.get("John").unwrap().unwrap();
+        assert_eq!(first.created_at, datetime);
+        assert_eq!(first.updated_at, datetime);
+
+        clock.advance(Duration::seconds(data.config.duplicate_window_secs + 1));
+        handle_rsvp(
+            data.clone(),
+            TestRequest::default().to_http_request(),
+            RsvpForm(test_rsvp()),
+        )
+        .await
+        .unwrap();
+        let second = data.db.write().await.get("John").unwrap().unwrap();
+        assert_eq!(second.created_at, datetime);
+        assert_eq!(second.updated_at, clock.now());
+        assert_eq!(data.build_info().uptime_secs, 61);
+    }
+
+    #[actix_rt::test]
+    async fn meal_limits_integration_test() {
+        let config = Config {
+            meal_limits: [("Meat".to_string(), 1)].into(),
+            ..Config::default()
+        };
+        let data = web::Data::new(AppState::new_with_config(config, test_db(1)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::post()
+            .uri("/fetch")
+            .set_form(NameParams {
+                name: "John-0".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        assert!(!resp.into_body().into_str().contains("Meat (none left)"));
+
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(RsvpParams {
+                meal_choice: "Meat".to_string(),
+                ..test_rsvp()
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("no more Meat meals left"));
+        assert!(body.contains("Meat (none left)"));
+        assert!(data.db.write().await.get("John").unwrap().is_none());
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/rsvps")
+            .set_json(RsvpParams {
+                meal_choice: "Meat".to_string(),
+                ..test_rsvp()
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+
+    #[actix_rt::test]
+    async fn meal_descriptions_integration_test() {
+        let config = Config {
+            meals: [(
+                "Fish".to_string(),
+                MealConfig {
+                    label: "Option B: Salmon".to_string(),
+                    description: "With lemon butter".to_string(),
+                    allergens: "Fish, dairy".to_string(),
+                },
+            )]
+            .into(),
+            ..Config::default()
+        };
+        let data = web::Data::new(AppState::new_with_config(config, test_db(1)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::post()
+            .uri("/fetch")
+            .set_form(NameParams {
+                name: "John-0".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Option B: Salmon"));
+        assert!(body.contains("<small>With lemon butter</small>"));
+        assert!(body.contains("Allergens: Fish, dairy"));
+        assert!(body.contains(">Meat"));
+
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body
+            .contains("Meal Choice: Option B: Salmon (With lemon butter), allergens: Fish, dairy"));
+        assert!(body.contains("Meal Choice: Veggies"));
+    }
+
+    #[actix_rt::test]
+    async fn dietary_checklist_integration_test() {
+        let data = web::Data::new(admin_state(test_db(0)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let mut body = serde_urlencoded::to_string(RsvpParams {
+            dietary_restrictions: "no meat pls".to_string(),
+            ..test_rsvp()
+        })
+        .unwrap();
+        body.push_str("&dietary=Nut+allergy&dietary=Vegetarian&dietary=Paleo");
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .insert_header((CONTENT_TYPE, "application/x-www-form-urlencoded"))
+            .set_payload(body)
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Dietary Restrictions: Vegetarian, Nut allergy, no meat pls"));
+        let record = data.db.write().await.get("John").unwrap().unwrap();
+        assert_eq!(record.dietary, "Vegetarian, Nut allergy");
+        assert_eq!(record.dietary_restrictions, "no meat pls");
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/rsvp/{}", record.token))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let body = resp.into_body().into_str();
+        assert!(body.contains(r#"name="dietary" value="Vegetarian" checked"#));
+        assert!(body.contains(r#"name="dietary" value="Vegan" >"#));
+
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Vegetarian: 1"));
+        assert!(body.contains("Other: 2"));
+    }
+
+    #[actix_rt::test]
+    async fn required_fields_integration_test() {
+        let config = Config::from_toml(
+            "[fields]\nemail = \"required\"\nplus_one_name = \"required\"\ncomments = \"hidden\"",
+        )
+        .unwrap();
+        let data = web::Data::new(AppState::new_with_config(config, test_db(1)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::post()
+            .uri("/fetch")
+            .set_form(NameParams {
+                name: "John-0".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Email *"));
+        assert!(!body.contains(r#"name="comments""#));
+
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(RsvpParams {
+                email: String::default(),
+                plus_one_name: String::default(),
+                ..test_rsvp()
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains(r##"<a href="#email">Please fill in your email</a>"##));
+        assert!(body.contains(r##"<a href="#plus_one_name">"##));
+        assert!(body.contains(
+            r#"id="email" name="email" value="" aria-invalid="true" aria-describedby="email-error""#
+        ));
+        assert!(body.contains(r#"id="email-error""#));
+        assert!(!body.contains(r#"aria-describedby="plus_one_email-error""#));
+        assert!(data.db.write().await.get("John").unwrap().is_none());
+
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let record = data.db.write().await.get("John").unwrap().unwrap();
+        assert!(record.comments.is_empty());
+
+        let mut decline = json!(RsvpParams {
+            name: "John-0".to_string(),
+            attending: false,
+            attending_secondary: false,
+            attending_tertiary: false,
+            ..test_rsvp()
+        });
+        for field in ["email", "comments"] {
+            decline.as_object_mut().unwrap().remove(field);
+        }
+        let req = test::TestRequest::post()
+            .uri("/api/v1/rsvps")
+            .set_json(&decline)
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        decline["email"] = json!("john0@john.john");
+        let req = test::TestRequest::post()
+            .uri("/api/v1/rsvps")
+            .set_json(&decline)
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let record = data.db.write().await.get("John-0").unwrap().unwrap();
+        assert!(record.meal_choice.is_empty());
+        assert!(record.plus_one_meal_choice.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn handle_rsvp_integration_test() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::default()))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(CONTENT_TYPE).unwrap(),
+            HeaderValue::from_static("text/html")
+        );
+        let (_, resp) = resp.into_parts();
+        assert!(resp.into_body().into_str().contains("Confirmation"));
+    }
+
+    #[actix_rt::test]
+    async fn confirmation_extras_integration_test() {
+        let config = Config::from_toml(
+            r#"
+[confirmation]
+attending_message = "See you there!"
+declined_message = "We will miss you"
+[[confirmation.next_steps]]
+title = "Book a room"
+text = "Use code JJ2024"
+url = "https://hotel.example.com"
+"#,
+        )
+        .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new_with_config(
+                    config,
+                    test_db(0),
+                )))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("See you there!"));
+        assert!(body
+            .contains(r#"<a href="https://hotel.example.com">Book a room</a>: Use code JJ2024"#));
+
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(RsvpParams {
+                name: "Jane".to_string(),
+                attending: false,
+                attending_secondary: false,
+                attending_tertiary: false,
+                ..test_rsvp()
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("We will miss you"));
+        assert!(!body.contains("Next steps"));
+    }
+
+    #[actix_rt::test]
+    async fn timezone_integration_test() {
+        let datetime = Utc.with_ymd_and_hms(2023, 5, 28, 18, 15, 0).unwrap();
+        let clock = Arc::new(MockClock::new(datetime));
+        let mut db = CsvDb::new_with_clock(tempfile().unwrap(), clock.clone());
+        db.add_header();
+        let config = Config::from_toml("timezone = \"America/New_York\"").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new_with_config(config, db)))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("Last updated: Sun, May 28, 2023 at 2:15 PM EDT (just now)"));
+
+        clock.advance(Duration::days(3));
+        let req = test::TestRequest::post()
+            .uri("/fetch")
+            .set_form(NameParams {
+                name: "John".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("You last updated your RSVP 3 days ago."));
+    }
+
+    #[actix_rt::test]
+    async fn handle_bounce_integration_test() {
+        let data = web::Data::new(AppState::new_with_config(
+            Config {
+                webhook_secret: Some("secret".to_string()),
+                ..Config::default()
+            },
+            test_db(3),
+        ));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let notification = json!([{ "email": "john1@john.john", "event": "bounce" }]);
+
+        let req = test::TestRequest::post()
+            .uri("/webhooks/bounce?secret=wrong")
+            .set_json(&notification)
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::post()
+            .uri("/webhooks/bounce?secret=secret")
+            .set_json(&notification)
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let record = data.db.write().await.get("John-1").unwrap().unwrap();
+        assert_eq!(record.email_status, EmailStatus::Bounced);
+        let record = data.db.write().await.get("John-0").unwrap().unwrap();
+        assert_eq!(record.email_status, EmailStatus::Ok);
+
+        // SNS sends its JSON as plain text
+        let message = json!({
+            "notificationType": "Complaint",
+            "complaint": { "complainedRecipients": [{ "emailAddress": "john0@john.john" }] },
+        });
+        let notification = json!({ "Type": "Notification", "Message": message.to_string() });
+        let req = test::TestRequest::post()
+            .uri("/webhooks/bounce?secret=secret")
+            .insert_header((CONTENT_TYPE, "text/plain; charset=UTF-8"))
+            .set_payload(notification.to_string())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let record = data.db.write().await.get("John-0").unwrap().unwrap();
+        assert_eq!(record.email_status, EmailStatus::Complained);
+
+        let req = test::TestRequest::post()
+            .uri("/webhooks/bounce")
+            .insert_header((inbound::WEBHOOK_SECRET, "wrong"))
+            .set_json(&notification)
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::post()
+            .uri("/webhooks/bounce")
+            .insert_header((inbound::WEBHOOK_SECRET, "secret"))
+            .insert_header((CONTENT_TYPE, "text/plain"))
+            .set_payload("not json")
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn version_integration_test() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::default()))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/version").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        let info: BuildInfo = serde_json::from_str(&resp.into_body().into_str()).unwrap();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(!info.commit.is_empty());
+        assert!(!info.built_at.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn acme_challenge_integration_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let tls = TlsConfig {
+            domains: vec!["example.com".to_string()],
+            dir: dir.path().to_str().unwrap().to_string(),
+            ..TlsConfig::default()
+        };
+        for acme in [None, Some(Arc::new(Acme::new(&tls).unwrap()))] {
+            let data = web::Data::new(AppState {
+                shared: Arc::new(SharedState {
+                    acme,
+                    ..SharedState::default()
+                }),
+                ..AppState::default()
+            });
+            let app =
+                test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+            let req = test::TestRequest::get()
+                .uri("/.well-known/acme-challenge/token1")
+                .to_request();
+            let resp: ServiceResponse = app.call(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn theme_integration_test() {
+        let config = Config {
+            theme: ThemeConfig {
+                primary_color: "#336699".to_string(),
+                couple_names: "Jane and John".to_string(),
+                ..ThemeConfig::default()
+            },
+            ..Config::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new_with_config(
+                    config,
+                    CsvDb::default(),
+                )))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/theme.css").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        assert!(resp.into_body().into_str().contains("#336699"));
+
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("<title>Jane and John - RSVP</title>"));
+        assert!(body.contains("/theme.css"));
+    }
+
+    #[actix_rt::test]
+    async fn site_integration_test() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::default()))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/robots.txt").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        assert_eq!(resp.into_body().into_str(), "User-agent: *\nDisallow: /\n");
+        let req = test::TestRequest::get().uri("/sitemap.xml").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        assert!(!resp.into_body().into_str().contains("og:title"));
+
+        let config = Config {
+            site: SiteConfig {
+                sitemap: true,
+                title: "Jane and John".to_string(),
+                ..SiteConfig::default()
+            },
+            ..Config::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new_with_config(
+                    config,
+                    CsvDb::default(),
+                )))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/robots.txt").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("Sitemap: http://127.0.0.1:8080/sitemap.xml"));
+        let req = test::TestRequest::get().uri("/sitemap.xml").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("<loc>http://127.0.0.1:8080/photos</loc>"));
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains(r#"<meta property="og:title" content="Jane and John">"#));
+        assert!(body.contains("http://127.0.0.1:8080/static/photo1.jpg"));
+    }
+
+    #[actix_rt::test]
+    async fn calendar_integration_test() {
+        let config = Config::from_toml(
+            r#"
+base_url = "https://example.com"
+[[events]]
+title = "Ceremony"
+starts_at = "2024-06-01T16:00:00"
+ends_at = "2024-06-01T23:00:00"
+"#,
+        )
+        .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new_with_config(
+                    config,
+                    test_db(3),
+                )))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(RsvpParams {
+                attending: true,
+                ..test_rsvp()
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Add to your calendar"));
+        assert!(body.contains("https://calendar.google.com/calendar/render?action=TEMPLATE"));
+        assert!(body.contains("https://example.com/calendar/1.ics"));
+
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(RsvpParams {
+                attending: false,
+                attending_secondary: false,
+                attending_tertiary: false,
+                ..test_rsvp()
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert!(!resp.into_body().into_str().contains("Add to your calendar"));
+
+        let req = test::TestRequest::get().uri("/calendar/1.ics").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("DTSTART:20240601T160000Z"));
+        for uri in ["/calendar/2.ics", "/calendar/0.ics", "/calendar/one.ics"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let resp: ServiceResponse = app.call(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn travel_integration_test() {
+        let contents = r#"
+[bulk_email]
+interval_ms = 0
+
+[travel]
+parking = "Free lot behind the hall"
+
+[[travel.hotels]]
+name = "The Inn"
+block_code = "SMITHWEDDING"
+cutoff = "2024-05-01"
+
+[[travel.hotels]]
+name = "The Lodge"
+cutoff = "2024-06-15"
+"#;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rsvp.toml");
+        std::fs::write(&path, contents).unwrap();
+        let config = Config::from_toml(contents).unwrap();
+        let clock = Arc::new(MockClock::new(
+            Utc.with_ymd_and_hms(2024, 4, 25, 12, 0, 0).unwrap(),
+        ));
+        let mut db = CsvDb::new_with_clock(tempfile().unwrap(), clock.clone());
+        db.add_header();
+        let mut state = AppState::new_with_config(config.clone(), db);
+        state.shared_mut().email.from = "us@example.com".to_string();
+        state.shared_mut().reloader = Arc::new(Reloader::new(
+            Some(path.to_str().unwrap().to_string()),
+            config,
+        ));
+        let data = web::Data::new(state);
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::get().uri("/travel").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Booking code: SMITHWEDDING"));
+        assert!(body.contains("Book by 2024-05-01"));
+        assert!(body.contains("Free lot behind the hall"));
+
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(RsvpParams {
+                needs_lodging: true,
+                ..test_rsvp()
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let req = test::TestRequest::post()
+            .uri("/fetch")
+            .set_form(NameParams {
+                name: "John".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("name=\"needs_lodging\" value=\"true\" checked"));
+
+        let reminded = || async {
+            let record = data.db.write().await.get("John").unwrap().unwrap();
+            assert!(record.needs_lodging);
+            record.lodging_reminded
+        };
+        assert!(data.scheduler.has(Task::LodgingReminders));
+        data.scheduler.run(Task::LodgingReminders, &data).await;
+        assert_eq!(reminded().await, NaiveDate::from_ymd_opt(2024, 5, 1));
+        clock.set(Utc.with_ymd_and_hms(2024, 6, 5, 12, 0, 0).unwrap());
+        data.scheduler.run(Task::LodgingReminders, &data).await;
+        assert_eq!(reminded().await, NaiveDate::from_ymd_opt(2024, 6, 15));
+
+        // Hotels added by a reload are reminded about without a restart
+        let hotel = "\n[[travel.hotels]]\nname = \"The Barn\"\ncutoff = \"2024-07-01\"\n";
+        std::fs::write(&path, format!("{}{}", contents, hotel)).unwrap();
+        assert_eq!(
+            data.reloader.reload(&data).await.unwrap(),
+            Vec::<&str>::new()
+        );
+        clock.set(Utc.with_ymd_and_hms(2024, 6, 25, 12, 0, 0).unwrap());
+        data.scheduler.run(Task::LodgingReminders, &data).await;
+        assert_eq!(reminded().await, NaiveDate::from_ymd_opt(2024, 7, 1));
+        let status = data.scheduler.statuses().pop().unwrap();
+        assert_eq!(status.name, "lodging_reminders");
+        assert_eq!(status.runs, 3);
+        assert_eq!(status.last_result, "ok");
+    }
+
+    #[actix_rt::test]
+    async fn private_event_integration_test() {
+        let config = Config::from_toml(
+            r#"
+[admin]
+username = "admin"
+password = "secret"
+
+[private_event]
+title = "rehearsal dinner"
+tag = "rehearsal"
+"#,
+        )
+        .unwrap();
+        let mut db = test_db(1);
+        db.insert(&AddParams {
+            tags: "rehearsal".to_string(),
+            ..test_add()
+        })
+        .unwrap();
+        let data = web::Data::new(AppState::new_with_config(config, db));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let form = |name: &str| {
+            test::TestRequest::post()
+                .uri("/fetch")
+                .set_form(NameParams {
+                    name: name.to_string(),
+                })
+                .to_request()
+        };
+        let resp: ServiceResponse = app.call(form("John")).await.unwrap();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("join us for the rehearsal dinner"));
+        let resp: ServiceResponse = app.call(form("John-0")).await.unwrap();
+        assert!(!resp.into_body().into_str().contains("rehearsal dinner"));
+
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(RsvpParams {
+                attending_private: true,
+                ..test_rsvp()
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("Attending the rehearsal dinner: Yes"));
+
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("Attending the rehearsal dinner: 1"));
+    }
+
+    #[actix_rt::test]
+    async fn childcare_integration_test() {
+        let config = Config::from_toml(
+            r#"
+[admin]
+username = "admin"
+password = "secret"
+
+[childcare]
+tag = "kids"
+"#,
+        )
+        .unwrap();
+        let mut db = test_db(1);
+        db.insert(&AddParams {
+            tags: "kids".to_string(),
+            ..test_add()
+        })
+        .unwrap();
+        let data = web::Data::new(AppState::new_with_config(config, db));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let form = |name: &str| {
+            test::TestRequest::post()
+                .uri("/fetch")
+                .set_form(NameParams {
+                    name: name.to_string(),
+                })
+                .to_request()
+        };
+        let resp: ServiceResponse = app.call(form("John")).await.unwrap();
+        assert!(resp.into_body().into_str().contains("name=\"kids_ages\""));
+        let resp: ServiceResponse = app.call(form("John-0")).await.unwrap();
+        assert!(!resp.into_body().into_str().contains("name=\"kids_ages\""));
+
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(RsvpParams {
+                kids: 2,
+                kids_ages: "3 and 6".to_string(),
+                ..test_rsvp()
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("Kids in childcare: 2 (ages 3 and 6)"));
+
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("2 kids from 1 parties signed up"));
+        let req = test::TestRequest::get()
+            .uri("/admin/childcare.csv")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(
+            resp.into_body().into_str(),
+            "Parent,Kids,Ages,Email,Table\nJohn,2,3 and 6,john@john.john,\n"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn songs_integration_test() {
+        let data = web::Data::new(admin_state(test_db(2)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let tokens = {
+            let db = data.db.write().await;
+            ["John-0", "John-1"].map(|name| db.get(name).unwrap().unwrap().token)
+        };
+        let songs_uri = format!("/songs/{}", tokens[0]);
+        let req = test::TestRequest::get().uri(&songs_uri).to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let mut state = admin_state(test_db(0));
+        state.shared_mut().config.songs.enabled = true;
+        state.shared_mut().db = Arc::clone(&data.db);
+        let data = web::Data::new(state);
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let request = |token: &str, title: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/songs/{}", token))
+                .set_form(SongParams {
+                    title: title.to_string(),
+                    artist: "ABBA".to_string(),
+                })
+                .to_request()
+        };
+        let resp: ServiceResponse = app.call(request(&tokens[0], "Waterloo")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let resp: ServiceResponse = app
+            .call(request(&tokens[1], "Dancing Queen"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        // the same song again is a vote, not a copy
+        let resp: ServiceResponse = app
+            .call(request(&tokens[0], "dancing queen"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let resp: ServiceResponse = app.call(request(&tokens[0], " ")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let resp: ServiceResponse = app.call(request("unknown", "Fernando")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/songs/{}/vote", tokens[1]))
+            .set_form(VoteParams {
+                song: "waterloo|abba".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let req = test::TestRequest::post()
+            .uri(&format!("/songs/{}/vote", tokens[1]))
+            .set_form(VoteParams {
+                song: "fernando|abba".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::get().uri(&songs_uri).to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Dancing Queen by ABBA (2 votes)"));
+        assert!(!body.contains("dancing queen by"));
+
+        let req = test::TestRequest::get()
+            .uri("/admin/songs.csv")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(
+            resp.into_body().into_str(),
+            "Rank,Title,Artist,Votes\n1,Dancing Queen,ABBA,2\n2,Waterloo,ABBA,2\n"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn shuttle_integration_test() {
+        let config = Config::from_toml(
+            r#"
+[admin]
+username = "admin"
+password = "secret"
+
+[[shuttle.stops]]
+name = "downtown"
+title = "Downtown Hilton"
+time = "3:30pm"
+capacity = 2
+"#,
+        )
+        .unwrap();
+        let data = web::Data::new(AppState::new_with_config(config, test_db(0)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let rsvp = |name: &str| RsvpParams {
+            name: name.to_string(),
+            shuttle: "downtown".to_string(),
+            ..test_rsvp()
+        };
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(rsvp("John"))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // no room left, so the form comes back without the stop chosen
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(rsvp("Jane"))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body = resp.into_body().into_str();
+        assert!(body.contains("the shuttle from Downtown Hilton is full"));
+        assert!(body.contains("Downtown Hilton at 3:30pm (full)"));
+        assert!(data.db.write().await.get("Jane").unwrap().is_none());
+
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Downtown Hilton at 3:30pm: 2 of 2 riders"));
+
+        let req = test::TestRequest::get()
+            .uri("/admin/shuttle/downtown.csv")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.into_body().into_str(),
+            "Name,Plus-one,Riders,Email\nJohn,Johnson,2,john@john.john\n"
+        );
+        let req = test::TestRequest::get()
+            .uri("/admin/shuttle/pier.csv")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn registry_integration_test() {
+        let config = Config::from_toml(
+            r#"
+[[registry.links]]
+name = "zola"
+title = "Zola"
+url = "https://www.zola.com/registry/janeandjohn"
+"#,
+        )
+        .unwrap();
+        let state = web::Data::new(AppState::new_with_config(config, test_db(3)));
+        let app =
+            test::init_service(App::new().app_data(state.clone()).configure(app_config)).await;
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("<a href=\"/go/zola\">Zola</a>"));
+
+        for _ in 0..2 {
+            let req = test::TestRequest::get().uri("/go/zola").to_request();
+            let resp: ServiceResponse = app.call(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::FOUND);
+            assert_eq!(
+                resp.headers().get(LOCATION).unwrap(),
+                "https://www.zola.com/registry/janeandjohn"
+            );
+        }
+        let req = test::TestRequest::get().uri("/go/target").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let counts = state.registry_clicks.counts(&state.config.registry.links);
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[0].clicks, 2);
+
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert!(resp.into_body().into_str().contains("/go/zola"));
+    }
+
+    #[actix_rt::test]
+    async fn contact_integration_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("contact.jsonl");
+        let config = Config::from_toml(&format!(
+            "[contact]\nmax_per_hour = 2\nlog = {:?}\n\
+             [admin]\nusername = \"admin\"\npassword = \"secret\"",
+            log.to_str().unwrap()
+        ))
+        .unwrap();
+        let data = web::Data::new(AppState::new_with_config(config, test_db(1)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::get().uri("/contact").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.into_body().into_str().contains("<h3>Contact us</h3>"));
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("<a href=\"/contact\">send us a message</a>"));
+
+        let params = ContactParams {
+            name: "Maria".to_string(),
+            email: "maria@example.com".to_string(),
+            message: "Is there parking?".to_string(),
+            website: String::default(),
+        };
+        let post = |params: ContactParams| {
+            test::TestRequest::post()
+                .uri("/contact")
+                .peer_addr("203.0.113.7:4000".parse().unwrap())
+                .set_form(params)
+                .to_request()
+        };
+        let logged = || {
+            std::fs::read_to_string(&log)
+                .unwrap_or_default()
+                .lines()
+                .count()
+        };
+
+        let resp: ServiceResponse = app.call(post(params.clone())).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("Thanks for your message"));
+        assert_eq!(logged(), 1);
+
+        // bots are thanked too, but their messages are held for the admin
+        let spam = ContactParams {
+            website: "https://spam.example".to_string(),
+            ..params.clone()
+        };
+        let resp: ServiceResponse = app.call(post(spam.clone())).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("Thanks for your message"));
+        assert_eq!(logged(), 1);
+        let held = data.quarantine.list();
+        assert_eq!(held.len(), 1);
+        assert_eq!(held[0].reason, "filled in the hidden field");
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Held as spam"));
+        assert!(body.contains(&format!("/admin/quarantine/{}/approve", held[0].id)));
+
+        // a false positive is sent on once approved, without counting
+        // against the guest's limit
+        let req = test::TestRequest::post()
+            .uri(&format!("/admin/quarantine/{}/approve", held[0].id))
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(logged(), 2);
+        assert!(data.quarantine.list().is_empty());
+
+        app.call(post(spam)).await.unwrap();
+        let id = data.quarantine.list()[0].id.clone();
+        let reject = || {
+            test::TestRequest::post()
+                .uri(&format!("/admin/quarantine/{}/reject", id))
+                .insert_header(admin_auth())
+                .to_request()
+        };
+        let resp: ServiceResponse = app.call(reject()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert!(data.quarantine.list().is_empty());
+        let resp: ServiceResponse = app.call(reject()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(logged(), 2);
+
+        let blank = ContactParams {
+            message: String::default(),
+            ..params.clone()
+        };
+        let resp: ServiceResponse = app.call(post(blank)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Please fill in a message"));
+        assert!(body.contains(r#"aria-describedby="message-error""#));
+        assert!(!body.contains(r#"aria-describedby="email-error""#));
+        assert!(body.contains("value=\"maria@example.com\""));
+
+        let resp: ServiceResponse = app.call(post(params.clone())).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp: ServiceResponse = app.call(post(params.clone())).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        // a forwarded address doesn't get around the limit
+        let req = test::TestRequest::post()
+            .uri("/contact")
+            .peer_addr("203.0.113.7:4000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "198.51.100.2"))
+            .set_form(params)
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(logged(), 3);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new_with_config(
+                    Config::from_toml("[contact]\nenabled = false").unwrap(),
+                    test_db(1),
+                )))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/contact").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn fetch_lockout_integration_test() {
+        let config = Config::from_toml("[fetch_lockout]\nmax_misses = 2").unwrap();
+        let db = test_db(1);
+        let name = db.get_all().unwrap()[0].name.clone();
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let mut state = AppState::new_with_config(config, db);
+        state.shared_mut().clock = clock.clone();
+        let data = web::Data::new(state);
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let fetch = |name: &str, ip: &str| {
+            test::TestRequest::post()
+                .uri("/fetch")
+                .peer_addr(format!("{}:4000", ip).parse().unwrap())
+                .set_form(NameParams {
+                    name: name.to_string(),
+                })
+                .to_request()
+        };
+
+        // finding a name doesn't count as a miss
+        for _ in 0..3 {
+            let resp: ServiceResponse = app.call(fetch(&name, "203.0.113.7")).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        for _ in 0..3 {
+            let resp: ServiceResponse = app.call(fetch("Nobody", "203.0.113.7")).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert!(resp.into_body().into_str().contains(NOT_FOUND_MESSAGE));
+        }
+        let entries = data.audit.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, LOCKOUT_ACTION);
+        assert_eq!(entries[0].name, "203.0.113.7");
+
+        // even names on the list are turned away until the lockout ends,
+        // but only from that address
+        let resp: ServiceResponse = app.call(fetch(&name, "203.0.113.7")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get(RETRY_AFTER).unwrap(), "3600");
+        assert!(resp.into_body().into_str().contains("a lot of names"));
+        let req = test::TestRequest::post()
+            .uri("/fetch")
+            .peer_addr("203.0.113.7:4000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "198.51.100.2"))
+            .set_form(NameParams { name: name.clone() })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let resp: ServiceResponse = app.call(fetch(&name, "198.51.100.2")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        clock.advance(Duration::hours(1));
+        let resp: ServiceResponse = app.call(fetch(&name, "203.0.113.7")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let metrics = data.metrics.render();
+        assert!(metrics.contains("rsvp_fetch_total{result=\"found\"} 5\n"));
+        assert!(metrics.contains("rsvp_fetch_total{result=\"not_found\"} 3\n"));
+        assert!(metrics.contains("rsvp_fetch_total{result=\"locked_out\"} 2\n"));
+        assert!(metrics.contains("rsvp_fetch_lockouts_total 1\n"));
+    }
+
+    #[actix_rt::test]
+    async fn proxied_fetch_lockout_integration_test() {
+        let config =
+            Config::from_toml("trusted_proxies = [\"127.0.0.1\"]\n[fetch_lockout]\nmax_misses = 2")
+                .unwrap();
+        let db = test_db(1);
+        let name = db.get_all().unwrap()[0].name.clone();
+        let data = web::Data::new(AppState::new_with_config(config, db));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let fetch = |name: &str, client: &str| {
+            test::TestRequest::post()
+                .uri("/fetch")
+                .peer_addr("127.0.0.1:4000".parse().unwrap())
+                .insert_header(("X-Forwarded-For", client))
+                .set_form(NameParams {
+                    name: name.to_string(),
+                })
+                .to_request()
+        };
+
+        // one guest behind the proxy guessing names doesn't lock out the rest
+        for _ in 0..3 {
+            let resp: ServiceResponse = app.call(fetch("Nobody", "203.0.113.7")).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        let entries = data.audit.entries().unwrap();
+        assert_eq!(entries[0].name, "203.0.113.7");
+        let resp: ServiceResponse = app.call(fetch(&name, "203.0.113.7")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let resp: ServiceResponse = app.call(fetch(&name, "198.51.100.2")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn socket_fetch_lockout_integration_test() {
+        let config =
+            Config::from_toml("trusted_proxies = [\"unix\"]\n[fetch_lockout]\nmax_misses = 2")
+                .unwrap();
+        let db = test_db(1);
+        let name = db.get_all().unwrap()[0].name.clone();
+        let data = web::Data::new(AppState::new_with_config(config, db));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        // requests on the socket have no peer address
+        let fetch = |name: &str, client: &str| {
+            test::TestRequest::post()
+                .uri("/fetch")
+                .insert_header(("X-Forwarded-For", client))
+                .set_form(NameParams {
+                    name: name.to_string(),
+                })
+                .to_request()
+        };
+
+        for _ in 0..3 {
+            let resp: ServiceResponse = app.call(fetch("Nobody", "203.0.113.7")).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        let resp: ServiceResponse = app.call(fetch(&name, "203.0.113.7")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let resp: ServiceResponse = app.call(fetch(&name, "198.51.100.2")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn plus_one_email_integration_test() {
+        let data = web::Data::new(AppState::new_with_db(test_db(3)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(RsvpParams {
+                attending: true,
+                plus_one_attending: true,
+                plus_one_name: "Jane".to_string(),
+                plus_one_email: " jane@example.com ".to_string(),
+                ..test_rsvp()
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let record = data.db.write().await.get("John").unwrap().unwrap();
+        assert_eq!(record.plus_one_email, "jane@example.com");
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/rsvp/{}", record.token))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("name=\"plus_one_email\" value=\"jane@example.com\""));
+
+        // declining clears the plus-one along with everything else
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(RsvpParams {
+                attending: false,
+                attending_secondary: false,
+                attending_tertiary: false,
+                plus_one_email: "jane@example.com".to_string(),
+                ..test_rsvp()
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let record = data.db.write().await.get("John").unwrap().unwrap();
+        assert_eq!(record.plus_one_email, "");
+    }
+
+    #[actix_rt::test]
+    async fn stats_integration_test() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new_with_config(
+                    Config::default(),
+                    test_db(3),
+                )))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/stats").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let config = Config::from_toml("[site]\nstats = true").unwrap();
+        let mut db = test_db(3);
+        db.insert(&test_add()).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new_with_config(config, db)))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/stats").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.into_body().into_str();
+        assert!(body.contains("3 of 4 parties have responded"));
+        assert!(!body.contains("John"));
+    }
+
+    #[actix_rt::test]
+    async fn api_integration_test() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new_with_db(test_db(3))))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/rsvps")
+            .set_json(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        let rsvp: RsvpResponse = serde_json::from_str(&resp.into_body().into_str()).unwrap();
+        assert_eq!(
+            rsvp.rsvp,
+            RsvpParams {
+                source: Some(Source::Api),
+                ..test_rsvp()
+            }
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/rsvps/John-1")
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("John-1"));
+        assert!(!body.contains("token"));
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/rsvps/Nobody")
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let (_, resp) = resp.into_parts();
+        assert_eq!(resp.into_body().into_str(), r#"{"error":"name not found"}"#);
+    }
+
+    #[cfg(feature = "api")]
+    #[actix_rt::test]
+    async fn api_lockout_integration_test() {
+        let config = Config::from_toml("[fetch_lockout]\nmax_misses = 2").unwrap();
+        let data = web::Data::new(AppState::new_with_config(config, test_db(1)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let get = |name: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/api/v1/rsvps/{}", name))
+                .peer_addr("203.0.113.7:4000".parse().unwrap())
+                .to_request()
+        };
+        for _ in 0..3 {
+            let resp: ServiceResponse = app.call(get("Nobody")).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        }
+        let entries = data.audit.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, LOCKOUT_ACTION);
+        assert_eq!(entries[0].name, "203.0.113.7");
+
+        // locked out of the API and the form alike
+        let resp: ServiceResponse = app.call(get("John-0")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(resp.headers().contains_key(RETRY_AFTER));
+        let req = test::TestRequest::post()
+            .uri("/fetch")
+            .peer_addr("203.0.113.7:4000".parse().unwrap())
+            .set_form(NameParams {
+                name: "John-0".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_rt::test]
+    async fn error_pages_integration_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("404.html");
+        std::fs::write(&template, "<h1>{status_code}: {error}</h1>").unwrap();
+        let config = Config::from_toml(&format!(
+            "[error_pages.\"404\"]\nmessage = \"Nothing here\"\ntemplate = \"{}\"\n\
+             [error_pages.\"405\"]\nmessage = \"Not like that\"",
+            template.display()
+        ))
+        .unwrap();
+        let data = web::Data::new(AppState::new_with_config(config, test_db(1)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+
+        let req = test::TestRequest::get().uri("/missing").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let (_, resp) = resp.into_parts();
+        assert_eq!(resp.into_body().into_str(), "<h1>404: Nothing here</h1>");
+        let req = test::TestRequest::get().uri("/wp-login.php").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let metrics = data.metrics.render();
+        assert!(metrics.contains("rsvp_not_found_total{class=\"missing\"} 1\n"));
+        assert!(metrics.contains("rsvp_not_found_total{class=\"scanner\"} 1\n"));
+
+        let req = test::TestRequest::post().uri("/photos").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(resp.headers().get(CONTENT_TYPE).unwrap(), "text/html");
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("405 Not like that"));
+
+        let req = test::TestRequest::post()
+            .uri("/webhooks/bounce?secret=wrong")
+            .set_json(serde_json::json!({}))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let (_, resp) = resp.into_parts();
+        assert!(resp.into_body().into_str().contains("403 Forbidden"));
+    }
+
+    #[actix_rt::test]
+    async fn api_list_integration_test() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(admin_state(test_db(10))))
+                .configure(app_config),
+        )
+        .await;
+        let uri = "/api/v1/rsvps?page=2&per_page=2&sort=-name&filter=attending:true";
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .insert_header(admin_auth())
+            .to_request();
+        let page: Page<RsvpResponse> = test::call_and_read_body_json(&app, req).await;
+        let names = page
+            .items
+            .iter()
+            .map(|r| r.rsvp.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["John-4", "John-2"]);
+        assert_eq!((page.total, page.pages), (5, 3));
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/rsvps?sort=token")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get()
+            .uri("/admin?per_page=3&sort=name")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Page 1 of 4 (10 guests)"));
+        assert!(body.contains("/admin?page=2&amp;per_page=3&amp;sort=name&amp;filter="));
+        assert!(!body.contains(">John-9</a>"));
+    }
+
+    #[actix_rt::test]
+    async fn api_search_integration_test() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(admin_state(test_db(12))))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/api/v1/search?q=john1")
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/search?q=JOHN1")
+            .insert_header(admin_auth())
+            .to_request();
+        let rsvps: Vec<RsvpResponse> = test::call_and_read_body_json(&app, req).await;
+        let names = rsvps
+            .iter()
+            .map(|r| r.rsvp.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["John-1", "John-10", "John-11"]);
+
+        let req = test::TestRequest::get()
+            .uri("/admin?q=johnson-10")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("(1 guests)"));
+    }
+
+    #[actix_rt::test]
+    async fn api_idempotency_integration_test() {
+        let data = web::Data::new(AppState::new_with_db(test_db(1)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::post()
+            .uri("/api/v1/rsvps")
+            .insert_header(("Idempotency-Key", "abc"))
+            .set_json(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        let first = resp.into_body().into_str();
+
+        // a later change by the guest isn't undone by the retry
+        let changed = RsvpParams {
+            comments: "Changed".to_string(),
+            ..test_rsvp()
+        };
+        data.db.write().await.upsert(&changed).unwrap();
+        let req = test::TestRequest::post()
+            .uri("/api/v1/rsvps")
+            .insert_header(("Idempotency-Key", "abc"))
+            .set_json(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        assert_eq!(resp.into_body().into_str(), first);
+        let record = data.db.write().await.get("John").unwrap().unwrap();
+        assert_eq!(record.comments, "Changed");
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/rsvps")
+            .insert_header(("Idempotency-Key", "abc"))
+            .set_json(changed)
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[actix_rt::test]
+    async fn busy_integration_test() {
+        let config = Config {
+            max_pending_writes: 0,
+            ..Config::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new_with_config(
+                    config,
+                    CsvDb::default(),
+                )))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers().get(RETRY_AFTER).unwrap(), "5");
+        let (_, resp) = resp.into_parts();
+        assert!(resp.into_body().into_str().contains("submit it again"));
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/rsvps")
+            .set_json(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let req = test::TestRequest::get().uri("/fetch").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn stuck_lock_integration_test() {
+        let config = Config {
+            db_lock_timeout_ms: 10,
+            ..Config::default()
+        };
+        let data = web::Data::new(AppState::new_with_config(config, test_db(3)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let guard = data.db.write().await;
+        let req = test::TestRequest::post()
+            .uri("/fetch")
+            .set_form(NameParams {
+                name: "John-1".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let id = resp.headers().get(&REQUEST_ID_HEADER).unwrap().clone();
+        assert_eq!(id.len(), 8);
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Internal error"));
+        assert!(body.contains(&format!("<code>{}</code>", id.to_str().unwrap())));
+
+        drop(guard);
+        let req = test::TestRequest::post()
+            .uri("/fetch")
+            .set_form(NameParams {
+                name: "John-1".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
     }
 
     #[test]
-    fn index_array() {
-        let photo_indices: [usize; NUM_PHOTOS] = (1..=NUM_PHOTOS)
-            .collect::<Vec<_>>()
-            .try_into()
-            .expect("Wrong size");
-        assert_eq!(photo_indices.len(), NUM_PHOTOS);
+    fn poisoned_replays_unit_test() {
+        let state = AppState::default();
+        let replays = Arc::clone(&state.replays);
+        std::thread::spawn(move || {
+            let _guard = replays.lock().unwrap();
+            panic!("while holding the idempotency cache");
+        })
+        .join()
+        .unwrap_err();
+        assert!(state.replays.is_poisoned());
+        assert_eq!(
+            state.replays().get("key", "request", state.clock.now()),
+            Replay::Miss
+        );
+    }
+
+    #[actix_rt::test]
+    async fn form_webhook_integration_test() {
+        let data = web::Data::new(AppState::new_with_config(
+            Config {
+                form_webhook: FormWebhookConfig {
+                    secret: Some("secret".to_string()),
+                    fields: [
+                        ("Your name", "name"),
+                        ("Will you be there?", "attending"),
+                        ("Anything else?", "comments"),
+                    ]
+                    .into_iter()
+                    .map(|(question, field)| (question.to_string(), field.to_string()))
+                    .collect(),
+                },
+                ..Config::default()
+            },
+            test_db(3),
+        ));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let answers = json!({
+            "Your name": "John-1",
+            "Will you be there?": "Yes, wouldn't miss it",
+            "Anything else?": "See you there",
+            "Timestamp": "2024-05-01 10:00:00",
+        });
+
+        for uri in ["/webhooks/rsvp", "/webhooks/rsvp?secret=wrong"] {
+            let req = test::TestRequest::post()
+                .uri(uri)
+                .set_json(&answers)
+                .to_request();
+            let resp: ServiceResponse = app.call(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/webhooks/rsvp?secret=secret")
+            .set_json(&answers)
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let record = data.db.write().await.get("John-1").unwrap().unwrap();
+        assert!(record.attending);
+        assert_eq!(record.comments, "See you there");
+        assert_eq!(record.source, Some(Source::External));
+        assert_eq!(data.db.write().await.get_all().unwrap().len(), 3);
+
+        let req = test::TestRequest::post()
+            .uri("/webhooks/rsvp?secret=secret")
+            .set_json(json!({ "Will you be there?": "Yes" }))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[actix_rt::test]
+    async fn api_hooks_integration_test() {
+        let received = web::Data::new(std::sync::Mutex::new(Vec::<HookPayload>::new()));
+        let server = HttpServer::new({
+            let received = received.clone();
+            move || {
+                App::new().app_data(received.clone()).route(
+                    "/zap",
+                    web::post().to(
+                        |payload: web::Json<HookPayload>,
+                         received: web::Data<std::sync::Mutex<Vec<HookPayload>>>| async move {
+                            received.lock().unwrap().push(payload.into_inner());
+                            HttpResponse::Ok().finish()
+                        },
+                    ),
+                )
+            }
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        let mut state = admin_state(test_db(3));
+        let config = HooksConfig {
+            allow_private: true,
+            ..HooksConfig::default()
+        };
+        state.shared_mut().hooks = Arc::new(Hooks::open(&config).unwrap());
+        let data = web::Data::new(state);
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let target_url = format!("http://{}/zap", addr);
+        let req = test::TestRequest::post()
+            .uri("/api/v1/hooks")
+            .set_json(json!({ "target_url": target_url }))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/hooks")
+            .insert_header(admin_auth())
+            .set_json(json!({ "hookUrl": target_url, "event": "rsvp" }))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let (_, resp) = resp.into_parts();
+        let subscription: Subscription =
+            serde_json::from_str(&resp.into_body().into_str()).unwrap();
+        assert_eq!(subscription.target_url, target_url);
+        assert_eq!(data.hooks.list().len(), 1);
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/hooks/sample")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        let samples: Vec<HookPayload> = serde_json::from_str(&resp.into_body().into_str()).unwrap();
+        assert_eq!(samples.len(), 3);
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/rsvps")
+            .set_json(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        for _ in 0..50 {
+            if !received.lock().unwrap().is_empty() {
+                break;
+            }
+            actix_web::rt::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        let payloads = received.lock().unwrap().clone();
+        assert_eq!(payloads.len(), 1);
+        assert_eq!(payloads[0].rsvp.rsvp.name, test_rsvp().name);
+        assert_eq!(payloads[0].rsvp.rsvp.source, Some(Source::Api));
+
+        let uri = format!("/api/v1/hooks/{}", subscription.id);
+        let req = test::TestRequest::delete()
+            .uri(&uri)
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert!(data.hooks.list().is_empty());
+        let req = test::TestRequest::delete()
+            .uri(&uri)
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn api_cors_integration_test() {
+        let config = Config {
+            cors: CorsConfig {
+                allowed_origins: vec!["https://wedding.example.com".to_string()],
+                ..CorsConfig::default()
+            },
+            ..Config::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new_with_config(
+                    config,
+                    test_db(1),
+                )))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/api/v1/rsvps")
+            .insert_header((ORIGIN, "https://wedding.example.com"))
+            .insert_header((ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            resp.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://wedding.example.com"
+        );
+        assert_eq!(
+            resp.headers().get(ACCESS_CONTROL_ALLOW_METHODS).unwrap(),
+            "GET, POST"
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/rsvps/John-0")
+            .insert_header((ORIGIN, "https://wedding.example.com"))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/rsvps/John-0")
+            .insert_header((ORIGIN, "https://evil.example.com"))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert!(!resp.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    fn admin_state(db: CsvDb) -> AppState<'static> {
+        let mut state = AppState::new_with_db(db);
+        state.shared_mut().config = Config {
+            admin: AdminConfig {
+                username: "admin".to_string(),
+                password: "secret".to_string(),
+            },
+            ..Config::default()
+        };
+        state
+    }
+
+    fn admin_auth() -> Authorization<Basic> {
+        Authorization::from(Basic::new("admin", Some("secret")))
+    }
+
+    #[actix_rt::test]
+    async fn dav_integration_test() {
+        let mut data = admin_state(test_db(3));
+        data.shared_mut().config.events = vec![EventConfig {
+            title: "Ceremony".to_string(),
+            starts_at: Some("2024-06-01T16:00:00".parse().unwrap()),
+            ends_at: Some("2024-06-01T18:00:00".parse().unwrap()),
+            ..EventConfig::default()
+        }];
+        let data = web::Data::new(data);
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let propfind = Method::from_bytes(b"PROPFIND").unwrap();
+
+        let req = test::TestRequest::get()
+            .uri("/.well-known/caldav")
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(resp.headers().get(LOCATION).unwrap(), "/dav/");
+
+        let req = test::TestRequest::default()
+            .method(propfind.clone())
+            .uri("/dav/")
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::default()
+            .method(propfind.clone())
+            .uri("/dav/")
+            .insert_header(admin_auth())
+            .insert_header(("Depth", "0"))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::MULTI_STATUS);
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("<C:calendar-home-set><D:href>/dav/calendars/</D:href>"));
+        assert!(body.contains("<A:addressbook-home-set><D:href>/dav/contacts/</D:href>"));
+
+        let req = test::TestRequest::default()
+            .method(propfind.clone())
+            .uri("/dav/contacts/guests/")
+            .insert_header(admin_auth())
+            .insert_header(("Depth", "1"))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::MULTI_STATUS);
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("<A:addressbook/>"));
+        assert!(body.contains("<CS:getctag>"));
+        let records = data.write_db().await.unwrap().get_all().unwrap();
+        let coming = &records
+            .iter()
+            .find(|r| r.name == "John-0")
+            .unwrap()
+            .short_code;
+        let not_coming = &records
+            .iter()
+            .find(|r| r.name == "John-1")
+            .unwrap()
+            .short_code;
+        assert!(body.contains(&format!("/dav/contacts/guests/{}.vcf", coming)));
+        assert!(!body.contains(&format!("/dav/contacts/guests/{}.vcf", not_coming)));
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/dav/contacts/guests/{}.vcf", coming))
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().contains_key("ETag"));
+        let (_, resp) = resp.into_parts();
+        assert!(resp.into_body().into_str().contains("FN:John-0\r\n"));
+
+        let req = test::TestRequest::default()
+            .method(Method::from_bytes(b"REPORT").unwrap())
+            .uri("/dav/calendars/events/")
+            .insert_header(admin_auth())
+            .set_payload(concat!(
+                "<C:calendar-multiget xmlns:D=\"DAV:\" xmlns:C=\"urn:ietf:params:xml:ns:caldav\">",
+                "<D:href>/dav/calendars/events/event-1.ics</D:href>",
+                "<D:href>/dav/calendars/events/event-9.ics</D:href>",
+                "</C:calendar-multiget>",
+            ))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::MULTI_STATUS);
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("<C:calendar-data>BEGIN:VCALENDAR"));
+        assert!(body.contains("ATTENDEE"));
+        assert!(body.contains("HTTP/1.1 404 Not Found"));
+
+        let req = test::TestRequest::put()
+            .uri("/dav/calendars/events/event-1.ics")
+            .insert_header(admin_auth())
+            .set_payload("BEGIN:VCALENDAR")
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_rt::test]
+    async fn admin_dashboard_integration_test() {
+        let mut db = test_db(3);
+        db.update_with("John-1", |r| r.email_status = EmailStatus::Bounced)
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(admin_state(db)))
+                .configure(app_config),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/admin").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(Authorization::from(Basic::new("admin", Some("wrong"))))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("john1@john.john"));
+        assert!(!body.contains("john0@john.john"));
+    }
+
+    #[actix_rt::test]
+    async fn admin_quick_integration_test() {
+        let mut db = test_db(3);
+        let at = Utc.with_ymd_and_hms(2023, 5, 28, 18, 15, 0).unwrap();
+        for (i, name) in ["John-0", "John-1", "John-2"].into_iter().enumerate() {
+            db.update_with(name, |r| {
+                r.responded_at = Some(at + Duration::hours(i as i64));
+                r.attending = name != "John-1";
+                r.attending_secondary = false;
+                r.attending_tertiary = false;
+                r.plus_one_attending = false;
+            })
+            .unwrap();
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(admin_state(db)))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/admin/quick")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.into_body().into_str();
+        assert!(body.contains("<strong>2</strong> coming"));
+        assert!(body.contains("<strong>1</strong> parties declined"));
+        assert!(body.contains("<strong>3/3</strong> parties responded"));
+        let newest = body.find("John-2: coming (1)").unwrap();
+        let declined = body.find("John-1: not coming").unwrap();
+        assert!(newest < declined);
+        assert!(declined < body.find("John-0: coming (1)").unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn limits_integration_test() {
+        let config = Config {
+            admin: AdminConfig {
+                username: "admin".to_string(),
+                password: "secret".to_string(),
+            },
+            limits: LimitsConfig {
+                max_records: Some(1),
+                ..LimitsConfig::default()
+            },
+            ..Config::default()
+        };
+        let data = web::Data::new(AppState::new_with_config(config, test_db(1)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::INSUFFICIENT_STORAGE);
+        let (_, resp) = resp.into_parts();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("We can't take new RSVPs right now"));
+        assert!(data.db.write().await.get("John").unwrap().is_none());
+
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("The guest list has 1 of the 1 records allowed"));
+    }
+
+    #[actix_rt::test]
+    async fn admin_announce_integration_test() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(admin_state(test_db(3))))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/admin/announce")
+            .insert_header(admin_auth())
+            .set_form(AnnouncementParams {
+                segment: "attending".to_string(),
+                subject: "Venue changed".to_string(),
+                body: "Hi {name}!".to_string(),
+                action: "preview".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Preview (2 recipients)"));
+        assert!(body.contains("Hi John-0!"));
+    }
+
+    #[actix_rt::test]
+    async fn admin_preview_integration_test() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(admin_state(CsvDb::default())))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/admin/preview/confirm.html")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        assert!(resp.into_body().into_str().contains("Jane Guest"));
+
+        let req = test::TestRequest::get()
+            .uri("/admin/preview/email/verification.txt")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        assert!(resp.into_body().into_str().contains("123456"));
+
+        let req = test::TestRequest::get()
+            .uri("/admin/preview/unknown.html")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn admin_notes_integration_test() {
+        let data = web::Data::new(admin_state(test_db(3)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::post()
+            .uri("/admin/notes")
+            .insert_header(admin_auth())
+            .set_form(NotesParams {
+                name: "John-1".to_string(),
+                notes: "Seat away from the speakers".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("Seat away from the speakers"));
+        assert!(data
+            .db
+            .write()
+            .await
+            .dump()
+            .contains("Seat away from the speakers"));
+
+        let req = test::TestRequest::post()
+            .uri("/fetch")
+            .set_form(NameParams {
+                name: "John-1".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        assert!(!resp
+            .into_body()
+            .into_str()
+            .contains("Seat away from the speakers"));
+
+        let req = test::TestRequest::post()
+            .uri("/admin/notes")
+            .insert_header(admin_auth())
+            .set_form(NotesParams {
+                name: "Nobody".to_string(),
+                notes: "Not invited".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn seating_integration_test() {
+        let data = web::Data::new(admin_state(test_db(3)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::post()
+            .uri("/admin/table")
+            .insert_header(admin_auth())
+            .set_form(TableParams {
+                name: "John-2".to_string(),
+                table: " 4 ".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let record = data.db.write().await.get("John-2").unwrap().unwrap();
+        assert_eq!(record.table, "4");
+
+        let req = test::TestRequest::get()
+            .uri("/admin/seating.csv")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.into_body().into_str();
+        let lines = body.lines().collect::<Vec<_>>();
+        assert!(lines[1].starts_with("4,") && lines[1].contains("John-2"));
+        assert!(lines[2].starts_with("Unassigned,") && lines[2].contains("John-0"));
+        assert!(!body.contains("John-1"));
+
+        let req = test::TestRequest::get()
+            .uri("/admin/seating.pdf")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        if cfg!(feature = "pdf") {
+            assert_eq!(resp.status(), StatusCode::OK);
+        } else {
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/admin/table")
+            .insert_header(admin_auth())
+            .set_form(TableParams {
+                name: "Nobody".to_string(),
+                table: "1".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn thanks_integration_test() {
+        let data = web::Data::new(admin_state(test_db(3)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert!(resp.into_body().into_str().contains("/admin/thanks"));
+
+        let req = test::TestRequest::post()
+            .uri("/admin/thanks")
+            .insert_header(admin_auth())
+            .set_form(ThanksParams {
+                name: "John-2".to_string(),
+                thank_you_sent: true,
+                gift: " Salad bowl ".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let record = data.db.write().await.get("John-2").unwrap().unwrap();
+        assert!(record.thank_you_sent);
+        assert_eq!(record.gift, "Salad bowl");
+
+        let req = test::TestRequest::get()
+            .uri("/admin/thanks")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.into_body().into_str();
+        assert!(body.contains("1 of 2 parties thanked"));
+        assert!(body.contains("value=\"Salad bowl\""));
+        assert!(!body.contains("John-1"));
+
+        // an unticked checkbox isn't sent
+        let req = test::TestRequest::post()
+            .uri("/admin/thanks")
+            .insert_header(admin_auth())
+            .insert_header((CONTENT_TYPE, "application/x-www-form-urlencoded"))
+            .set_payload("name=John-2&gift=Salad+bowl")
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let record = data.db.write().await.get("John-2").unwrap().unwrap();
+        assert!(!record.thank_you_sent);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/thanks.csv")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("John-2,Johnson-2,"));
+        assert!(body.contains(",Salad bowl,no"));
+
+        let req = test::TestRequest::post()
+            .uri("/admin/thanks")
+            .insert_header(admin_auth())
+            .set_form(ThanksParams {
+                name: "Nobody".to_string(),
+                thank_you_sent: true,
+                gift: String::default(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn waves_integration_test() {
+        let data = web::Data::new(admin_state(test_db(0)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        for (name, wave) in [("Amy", "A"), ("Bob", "B"), ("Cal", "B")] {
+            let req = test::TestRequest::post()
+                .uri("/add")
+                .set_form(AddParams {
+                    name: name.to_string(),
+                    wave: wave.to_string(),
+                    ..test_add()
+                })
+                .to_request();
+            let resp: ServiceResponse = app.call(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(RsvpParams {
+                name: "Bob".to_string(),
+                ..test_rsvp()
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(data.db.write().await.get("Bob").unwrap().unwrap().wave, "B");
+
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Wave A: 0 of 1 parties responded (0%), 0 declined, 0 coming"));
+        assert!(body.contains("Wave B: 1 of 2 parties responded (50%), 0 declined, 2 coming"));
+
+        let req = test::TestRequest::get()
+            .uri("/admin/announce")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert!(resp.into_body().into_str().contains("wave:b"));
+    }
+
+    #[actix_rt::test]
+    async fn kitchen_alert_integration_test() {
+        let data = web::Data::new(admin_state(test_db(0)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(RsvpParams {
+                dietary: "Nut allergy".to_string(),
+                dietary_restrictions: "and sesame".to_string(),
+                dietary_severity: Severity::Severe,
+                ..test_rsvp()
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Dietary Restrictions: Nut allergy, and sesame (severe)"));
+
+        let req = test::TestRequest::get()
+            .uri("/admin/kitchen-alert.csv")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.into_body().into_str();
+        let lines = body.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("Unassigned,John,"));
+        assert!(lines[1].ends_with(r#""Nut allergy, and sesame""#));
+
+        let req = test::TestRequest::get()
+            .uri("/admin/kitchen-alert.pdf")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        if cfg!(feature = "pdf") {
+            assert_eq!(resp.status(), StatusCode::OK);
+        } else {
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn admin_undo_integration_test() {
+        let data = web::Data::new(admin_state(test_db(3)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::post()
+            .uri("/admin/notes")
+            .insert_header(admin_auth())
+            .set_form(NotesParams {
+                name: "John-1".to_string(),
+                notes: "Seat away from the speakers".to_string(),
+            })
+            .to_request();
+        app.call(req).await.unwrap();
+
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("Last change: change for John-1"));
+
+        let req = test::TestRequest::post()
+            .uri("/admin/undo")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let contents = data.db.write().await.dump();
+        assert!(!contents.contains("Seat away from the speakers"));
+        assert!(contents.contains("John-1"));
+        let entries = data.audit.entries().unwrap();
+        assert_eq!(entries.last().unwrap().action, "undo change");
+
+        // then the RSVPs that built the test file, until there's nothing left
+        for _ in 0..3 {
+            let req = test::TestRequest::post()
+                .uri("/admin/undo")
+                .insert_header(admin_auth())
+                .to_request();
+            let resp: ServiceResponse = app.call(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        }
+        assert!(data.db.write().await.get_all().unwrap().is_empty());
+        let req = test::TestRequest::post()
+            .uri("/admin/undo")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+
+    #[actix_rt::test]
+    async fn admin_undo_across_workers_integration_test() {
+        let shared = admin_state(test_db(3)).shared();
+        let first = web::Data::new(shared.worker());
+        let second = web::Data::new(shared.worker());
+        let first_app =
+            test::init_service(App::new().app_data(first.clone()).configure(app_config)).await;
+        let second_app =
+            test::init_service(App::new().app_data(second.clone()).configure(app_config)).await;
+        let req = test::TestRequest::post()
+            .uri("/admin/notes")
+            .insert_header(admin_auth())
+            .set_form(NotesParams {
+                name: "John-1".to_string(),
+                notes: "Seat away from the speakers".to_string(),
+            })
+            .to_request();
+        first_app.call(req).await.unwrap();
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = second_app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // undoes the other worker's RSVP, keeping this worker's notes
+        let req = test::TestRequest::post()
+            .uri("/admin/undo")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = first_app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let contents = second.db.write().await.dump();
+        assert!(contents.contains("Seat away from the speakers"));
+        assert!(second.db.write().await.get("John").unwrap().is_none());
+
+        let req = test::TestRequest::post()
+            .uri("/admin/undo")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = second_app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let contents = first.db.write().await.dump();
+        assert!(!contents.contains("Seat away from the speakers"));
+        assert_eq!(first.db.write().await.get_all().unwrap().len(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn archive_integration_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = admin_state(test_db(3));
+        state.shared_mut().config.backups.dir =
+            dir.path().join("backups").to_str().unwrap().to_string();
+        state.shared_mut().config.archive = ArchiveConfig {
+            dir: dir.path().join("archive").to_str().unwrap().to_string(),
+            key: Some("keepsake".to_string()),
+            scrub_pii: true,
+        };
+        state.shared_mut().archive = Arc::new(Archive::open(&state.config.archive).unwrap());
+        let backups = Backups::new(&state.config.backups);
+        backups.take("name\n", state.clock.now(), "manual").unwrap();
+        let data = web::Data::new(state);
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::post()
+            .uri("/admin/archive")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+
+        let frozen = data.archive.frozen().unwrap();
+        assert!(frozen.scrubbed);
+        let bundle = dir.path().join("archive").join(&frozen.bundle);
+        let csv = std::fs::read_to_string(bundle.join("rsvp.csv")).unwrap();
+        let guests = CsvDb::parse(&csv).unwrap();
+        assert_eq!(guests.len(), 3);
+        assert!(guests.iter().all(|r| r.email.is_empty()));
+        assert!(!csv.contains("@john.john"));
+        for file in ["audit.jsonl", "stats.json", "photos.json", "SHA256SUMS.sig"] {
+            assert!(bundle.join(file).is_file(), "{}", file);
+        }
+        let records = data.db.write().await.get_all().unwrap();
+        assert_eq!(records.len(), 3);
+        assert!(records
+            .iter()
+            .all(|r| r.email.is_empty() && r.comments.is_empty()));
+        assert!(backups.list().unwrap().is_empty());
+
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::GONE);
+        let req = test::TestRequest::get().uri("/fetch").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::GONE);
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("Thank you for celebrating with us"));
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("The site is read-only"));
+        let req = test::TestRequest::post()
+            .uri("/admin/archive")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::GONE);
+    }
+
+    #[actix_rt::test]
+    async fn features_integration_test() {
+        let config = Config {
+            admin: AdminConfig {
+                username: "admin".to_string(),
+                password: "secret".to_string(),
+            },
+            site: SiteConfig {
+                sitemap: true,
+                ..SiteConfig::default()
+            },
+            features: FeaturesConfig {
+                admin: false,
+                api: false,
+                photos: false,
+                email: false,
+            },
+            ..Config::default()
+        };
+        let state = AppState::new_with_config(config, test_db(3));
+        assert!(!state.email.enabled);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .configure(app_config),
+        )
+        .await;
+        for uri in ["/admin", "/api/v1/rsvps", "/photos", "/photos/wedding"] {
+            let req = test::TestRequest::get()
+                .uri(uri)
+                .insert_header(admin_auth())
+                .to_request();
+            let resp: ServiceResponse = app.call(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND, "{}", uri);
+        }
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(!resp.into_body().into_str().contains("href=\"/photos\""));
+        let req = test::TestRequest::get().uri("/sitemap.xml").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert!(!resp.into_body().into_str().contains("/photos"));
+    }
+
+    #[actix_rt::test]
+    async fn admin_doctor_integration_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = admin_state(test_db(1));
+        state.shared_mut().csv = dir.path().join("rsvp.csv").to_str().unwrap().to_string();
+        state.shared_mut().email.from = "not an address".to_string();
+        state.shared_mut().email.admin = "admin@example.com".to_string();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/admin/doctor.json")
+            .insert_header(admin_auth())
+            .to_request();
+        let report: doctor::Report = test::call_and_read_body_json(&app, req).await;
+        assert!(!report.ok());
+        let failed = report
+            .checks
+            .iter()
+            .filter(|check| check.status == doctor::Status::Fail)
+            .map(|check| check.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(failed, ["Sender address"]);
+    }
+
+    #[actix_rt::test]
+    async fn reload_integration_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rsvp.toml");
+        let contents = "[admin]\nusername = \"admin\"\npassword = \"secret\"\n";
+        std::fs::write(&path, contents).unwrap();
+        let mut state = admin_state(test_db(1));
+        state.shared_mut().reloader = Arc::new(Reloader::new(
+            Some(path.to_str().unwrap().to_string()),
+            state.config.clone(),
+        ));
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(current_state))
+                .app_data(web::Data::new(LiveState::default()))
+                .app_data(web::Data::new(state))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp = test::call_service(&app, req).await;
+        let body = test::read_body(resp).await;
+        assert!(!String::from_utf8_lossy(&body).contains("Jane and John"));
+
+        std::fs::write(
+            &path,
+            format!("{}[theme]\ncouple_names = \"Jane and John\"\n", contents),
+        )
+        .unwrap();
+        let req = test::TestRequest::post()
+            .uri("/admin/reload")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp = test::call_service(&app, req).await;
+        let body = test::read_body(resp).await;
+        assert!(String::from_utf8_lossy(&body).contains("Jane and John"));
+
+        std::fs::write(&path, "[theme\n").unwrap();
+        let req = test::TestRequest::post()
+            .uri("/admin/reload")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains("Reloaded 1 time(s)"));
+    }
+
+    #[actix_rt::test]
+    async fn languages_integration_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = dir.path().join("index.fr.html");
+        std::fs::write(
+            &index,
+            "<html lang=\"{lang}\">Bienvenue{{ for l in languages }} {l.href}{{ endfor }}</html>",
+        )
+        .unwrap();
+        let mut state = admin_state(test_db(1));
+        state.shared_mut().config.languages.pages.insert(
+            "fr".to_string(),
+            [(
+                "index.html".to_string(),
+                index.to_str().unwrap().to_string(),
+            )]
+            .into(),
+        );
+        let state = state.with_config(state.config.clone(), None);
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(current_state))
+                .wrap(from_fn(localize))
+                .app_data(web::Data::new(LiveState::default()))
+                .app_data(web::Data::new(state))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.headers().get(SET_COOKIE).is_none());
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains("<html lang=\"en\">"));
+        assert!(body.contains("href=\"/fr/\""));
+
+        let req = test::TestRequest::get().uri("/fr/").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let cookie = resp.response().cookies().next().unwrap().into_owned();
+        assert_eq!((cookie.name(), cookie.value()), ("rsvp_lang", "fr"));
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert_eq!(body, "<html lang=\"fr\">Bienvenue /en/ /fr/</html>");
+
+        // the cookie keeps the language on links without the prefix
+        let req = test::TestRequest::get()
+            .uri("/")
+            .cookie(cookie)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.starts_with("<html lang=\"fr\">Bienvenue"));
+
+        // pages it doesn't translate fall back to the built-in ones
+        let req = test::TestRequest::get()
+            .uri("/fr/fetch?name=Nobody")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let req = test::TestRequest::get().uri("/de/").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn admin_snapshots_integration_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = admin_state(test_db(3));
+        state.shared_mut().config.backups.dir = dir.path().to_str().unwrap().to_string();
+        let backups = Backups::new(&state.config.backups);
+        let data = web::Data::new(state);
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::post()
+            .uri("/admin/snapshots")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let name = backups.list().unwrap()[0].name.clone();
+
+        let req = test::TestRequest::get()
+            .uri("/admin/snapshots")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        assert!(resp.into_body().into_str().contains(&name));
+
+        data.db.write().await.remove("John-0").unwrap();
+        let req = test::TestRequest::get()
+            .uri(&format!("/admin/snapshots/{}", name))
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("3 guests in this snapshot"));
+        assert!(body.contains("John-0"));
+        assert!(body.contains("removed"));
+
+        for query in [format!("snapshot={}", name), "since=2100-01-01".to_string()] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/admin/changes.csv?{}", query))
+                .insert_header(admin_auth())
+                .to_request();
+            let resp: ServiceResponse = app.call(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            let (_, resp) = resp.into_parts();
+            let body = resp.into_body().into_str();
+            let lines = body.lines().collect::<Vec<_>>();
+            assert_eq!(lines.len(), 2);
+            assert!(lines[0].starts_with("change,changed_fields,name,"));
+            assert!(lines[1].starts_with("removed,,John-0,"));
+        }
+        for (query, status) in [
+            ("since=2000-01-01", StatusCode::NOT_FOUND),
+            ("", StatusCode::BAD_REQUEST),
+            ("snapshot=rsvp.csv", StatusCode::NOT_FOUND),
+        ] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/admin/changes.csv?{}", query))
+                .insert_header(admin_auth())
+                .to_request();
+            let resp: ServiceResponse = app.call(req).await.unwrap();
+            assert_eq!(resp.status(), status);
+        }
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/admin/snapshots/{}/restore", name))
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(data.db.write().await.get_all().unwrap().len(), 3);
+        let snapshots = backups.list().unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].reason, "before-restore");
+        assert_eq!(
+            data.audit.entries().unwrap().last().unwrap().action,
+            "restore"
+        );
+
+        for req in [
+            test::TestRequest::get().uri("/admin/snapshots/rsvp.csv"),
+            test::TestRequest::post()
+                .uri("/admin/snapshots/rsvp-20230528T181500.000Z-manual.csv/restore"),
+        ] {
+            let resp: ServiceResponse = app
+                .call(req.insert_header(admin_auth()).to_request())
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn analytics_integration_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = admin_state(test_db(1));
+        let config = config::AnalyticsConfig {
+            path: Some(
+                dir.path()
+                    .join("analytics.json")
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            ),
+            ..config::AnalyticsConfig::default()
+        };
+        state.shared_mut().analytics = Analytics::open(&config).unwrap().map(Arc::new);
+        let data = web::Data::new(state);
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        for uri in ["/", "/", "/photos", "/version", "/missing"] {
+            let req = test::TestRequest::get()
+                .uri(uri)
+                .peer_addr("203.0.113.7:51234".parse().unwrap())
+                .to_request();
+            app.call(req).await.unwrap();
+        }
+        let req = test::TestRequest::get()
+            .uri("/admin/analytics")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("<td>1</td>\n              <td>3</td>"));
+        assert!(body.contains(">/photos</td>"));
+        assert!(!body.contains(">/version</td>"));
+        assert!(!body.contains(">/admin/analytics</td>"));
+        let day = data.analytics.as_ref().unwrap().days();
+        assert_eq!(day.values().next().unwrap().total.views, 3);
+
+        // the counts are written to the file by the task
+        let path = dir.path().join("analytics.json");
+        assert!(!path.exists());
+        data.scheduler.run(Task::SaveAnalytics, &data).await;
+        let saved = std::fs::read_to_string(path).unwrap();
+        assert!(saved.contains("\"/photos\""));
+    }
+
+    #[actix_rt::test]
+    async fn photos_integration_test() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("post-wedding")).unwrap();
+        std::fs::write(dir.path().join("post-wedding/dance.jpg"), "jpeg").unwrap();
+        std::fs::write(dir.path().join("secret.jpg"), "jpeg").unwrap();
+        std::fs::create_dir_all(dir.path().join(".variants/post-wedding")).unwrap();
+        std::fs::write(
+            dir.path().join(".variants/post-wedding/dance.jpg.webp"),
+            "webp",
+        )
+        .unwrap();
+        let mut state = admin_state(test_db(1));
+        state.shared_mut().config.photos.dir = dir.path().to_str().unwrap().to_string();
+        state.shared_mut().config.photos.variants = vec![config::ImageVariant::Webp];
+        state.shared_mut().config.photos.videos = vec![
+            config::VideoConfig {
+                title: "Highlight reel".to_string(),
+                url: "/static/reel.mp4".to_string(),
+                album: Some("post-wedding".to_string()),
+                position: Some(0),
+            },
+            config::VideoConfig {
+                title: "Livestream".to_string(),
+                url: "https://vimeo.com/76979871".to_string(),
+                ..config::VideoConfig::default()
+            },
+        ];
+        let data = web::Data::new(state);
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+
+        let req = test::TestRequest::get().uri("/photos").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.into_parts().1.into_body().into_str();
+        assert!(body.contains("href=\"/photos/post-wedding\""));
+        assert!(body.contains("src=\"/photos/post-wedding/dance.jpg?v="));
+        assert!(body.contains("Post wedding"));
+        assert!(body.contains("src=\"https://player.vimeo.com/video/76979871?dnt=1\""));
+
+        let req = test::TestRequest::get()
+            .uri("/photos/post-wedding")
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.into_parts().1.into_body().into_str();
+        assert!(body.contains("alt=\"Post wedding photo 1\""));
+        assert!(body.contains("srcset=\"/photos/post-wedding/dance.jpg.webp?v="));
+        assert!(body.contains("type=\"image/webp\""));
+        let reel = body.find("src=\"/static/reel.mp4\"").unwrap();
+        assert!(reel < body.find("<picture>").unwrap());
+        assert!(!body.contains("Livestream"));
+
+        let req = test::TestRequest::get()
+            .uri("/photos/post-wedding/dance.jpg")
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().get(CACHE_CONTROL).is_none());
+        assert_eq!(test::read_body(resp).await, "jpeg");
+
+        let req = test::TestRequest::get()
+            .uri("/photos/post-wedding/dance.jpg.webp?v=1")
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(CACHE_CONTROL).unwrap(),
+            "public, max-age=31536000, immutable"
+        );
+        assert_eq!(resp.headers().get(CONTENT_TYPE).unwrap(), "image/webp");
+        assert_eq!(test::read_body(resp).await, "webp");
+
+        for uri in [
+            "/photos/venue",
+            "/photos/post-wedding/missing.jpg",
+            "/photos/%2E%2E/secret.jpg",
+            "/photos/post-wedding/..%2Fsecret.jpg",
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let resp: ServiceResponse = app.call(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND, "{}", uri);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn site_password_integration_test() {
+        let mut state = admin_state(test_db(1));
+        state.shared_mut().config.site_password.password = Some("confetti".to_string());
+        let data = web::Data::new(state);
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+
+        let req = test::TestRequest::get().uri("/photos?page=2").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            resp.headers().get(LOCATION).unwrap(),
+            "/enter?next=%2Fphotos%3Fpage%3D2"
+        );
+        // guests' answers aren't given out by the API without it either
+        let req = test::TestRequest::get()
+            .uri("/api/v1/rsvps/John-0")
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        // nor can it be used to send RSVPs
+        let req = test::TestRequest::post()
+            .uri("/api/v1/rsvps")
+            .set_json(test_rsvp())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(data.db.write().await.get_all().unwrap().len(), 1);
+        for uri in ["/enter?next=%2Fphotos", "/robots.txt", "/theme.css"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let resp: ServiceResponse = app.call(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK, "{}", uri);
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/enter")
+            .set_form([("password", "wrong"), ("next", "/photos")])
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert!(resp.response().cookies().next().is_none());
+
+        let req = test::TestRequest::post()
+            .uri("/enter")
+            .set_form([("password", "confetti"), ("next", "//evil.example")])
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(resp.headers().get(LOCATION).unwrap(), "/");
+        let cookie = resp.response().cookies().next().unwrap().into_owned();
+        assert!(cookie.http_only().unwrap());
+
+        let req = test::TestRequest::get()
+            .uri("/photos")
+            .cookie(cookie)
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let req = test::TestRequest::get()
+            .uri("/photos")
+            .cookie(actix_web::cookie::Cookie::new("rsvp_session", "00ff"))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    }
+
+    #[actix_rt::test]
+    async fn password_lockout_integration_test() {
+        let mut state = admin_state(test_db(1));
+        state.shared_mut().clock = Arc::new(MockClock::new(Utc::now()));
+        state.shared_mut().config.site_password.password = Some("confetti".to_string());
+        state.shared_mut().config.fetch_lockout.max_misses = 2;
+        let data = web::Data::new(state);
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let enter = |password: &str| {
+            test::TestRequest::post()
+                .uri("/enter")
+                .peer_addr("203.0.113.7:4000".parse().unwrap())
+                .set_form([("password", password), ("next", "/photos")])
+                .to_request()
+        };
+        let admin = |ip: &str| {
+            test::TestRequest::get()
+                .uri("/admin")
+                .peer_addr(format!("{}:4000", ip).parse().unwrap())
+                .insert_header(admin_auth())
+                .to_request()
+        };
+
+        // wrong site passwords and admin credentials count towards one lockout
+        for _ in 0..2 {
+            let resp: ServiceResponse = app.call(enter("wrong")).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        }
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .peer_addr("203.0.113.7:4000".parse().unwrap())
+            .insert_header(Authorization::from(Basic::new("admin", Some("guess"))))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let entries = data.audit.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, LOCKOUT_ACTION);
+        assert_eq!(entries[0].name, "203.0.113.7");
+
+        // even the right passwords are turned away until the lockout ends,
+        // but only from that address
+        let resp: ServiceResponse = app.call(enter("confetti")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get(RETRY_AFTER).unwrap(), "3600");
+        assert!(resp.response().cookies().next().is_none());
+        let req = test::TestRequest::post()
+            .uri("/admin/login")
+            .peer_addr("203.0.113.7:4000".parse().unwrap())
+            .set_form([("username", "admin"), ("password", "secret")])
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("Too many wrong passwords"));
+        let resp: ServiceResponse = app.call(admin("203.0.113.7")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // guessing names is locked out apart, so it doesn't lock the admin out
+        let req = test::TestRequest::post()
+            .uri("/enter")
+            .peer_addr("198.51.100.2:4000".parse().unwrap())
+            .set_form([("password", "confetti"), ("next", "/")])
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let entered = session_cookie(&resp);
+        for _ in 0..3 {
+            let req = test::TestRequest::post()
+                .uri("/fetch")
+                .peer_addr("198.51.100.2:4000".parse().unwrap())
+                .cookie(entered.clone())
+                .set_form(NameParams {
+                    name: "Nobody".to_string(),
+                })
+                .to_request();
+            let resp: ServiceResponse = app.call(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        assert_eq!(data.audit.entries().unwrap().len(), 2);
+        let resp: ServiceResponse = app.call(admin("198.51.100.2")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    fn session_cookie(resp: &ServiceResponse) -> actix_web::cookie::Cookie<'static> {
+        resp.response()
+            .cookies()
+            .find(|cookie| cookie.name() == "rsvp_session")
+            .expect("the session changed")
+            .into_owned()
+    }
+
+    #[actix_rt::test]
+    async fn admin_cross_site_integration_test() {
+        let data = web::Data::new(admin_state(test_db(1)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let notes = |header: Option<(&'static str, &'static str)>| {
+            let req = test::TestRequest::post()
+                .uri("/admin/notes")
+                .insert_header(admin_auth())
+                .set_form(NotesParams {
+                    name: "John-0".to_string(),
+                    notes: "Vegan cake".to_string(),
+                });
+            match header {
+                Some(header) => req.insert_header(header).to_request(),
+                None => req.to_request(),
+            }
+        };
+
+        // a browser remembering the credentials can't be used from elsewhere
+        for header in [
+            ("Sec-Fetch-Site", "cross-site"),
+            ("Origin", "https://evil.example"),
+            ("Origin", "null"),
+        ] {
+            let resp: ServiceResponse = app.call(notes(Some(header))).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN, "{:?}", header);
+        }
+        assert!(data.audit.entries().unwrap().is_empty());
+
+        for header in [
+            None,
+            Some(("Sec-Fetch-Site", "same-origin")),
+            Some(("Origin", "http://localhost:8080")),
+        ] {
+            let resp: ServiceResponse = app.call(notes(header)).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::SEE_OTHER, "{:?}", header);
+        }
+        let req = test::TestRequest::get()
+            .uri("/admin/rsvps.csv")
+            .insert_header(admin_auth())
+            .insert_header(("Sec-Fetch-Site", "cross-site"))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn admin_session_integration_test() {
+        let data = web::Data::new(admin_state(test_db(3)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/admin/duplicates")
+            .insert_header((ACCEPT, "text/html"))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            resp.headers().get(LOCATION).unwrap(),
+            "/admin/login?next=%2Fadmin%2Fduplicates"
+        );
+        let req = test::TestRequest::get().uri("/admin").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/admin/login")
+            .set_form([("username", "admin"), ("password", "wrong")])
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert!(resp.response().cookies().next().is_none());
+
+        let req = test::TestRequest::post()
+            .uri("/admin/login")
+            .set_form([
+                ("username", "admin"),
+                ("password", "secret"),
+                ("next", "/admin/duplicates"),
+            ])
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(resp.headers().get(LOCATION).unwrap(), "/admin/duplicates");
+        let cookie = session_cookie(&resp);
+        assert!(cookie.http_only().unwrap());
+
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .cookie(cookie)
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let cookie = session_cookie(&resp);
+        let body = resp.into_body().into_str();
+        let csrf = body
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap()
+            .to_string();
+        assert_eq!(csrf.len(), 32);
+
+        let notes = |csrf: Option<&str>| {
+            let mut form = vec![("name", "John-1"), ("notes", "Seat by the window")];
+            form.extend(csrf.map(|csrf| ("csrf", csrf)));
+            test::TestRequest::post()
+                .uri("/admin/notes")
+                .cookie(cookie.clone())
+                .set_form(form)
+                .to_request()
+        };
+        let resp: ServiceResponse = app.call(notes(None)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let resp: ServiceResponse = app.call(notes(Some("forged"))).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let resp: ServiceResponse = app.call(notes(Some(&csrf))).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let req = test::TestRequest::post()
+            .uri("/admin/notes")
+            .cookie(cookie.clone())
+            .insert_header((session::CSRF_HEADER, csrf.as_str()))
+            .set_form(NotesParams {
+                name: "John-1".to_string(),
+                notes: "Seat by the door".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            data.write_db()
+                .await
+                .unwrap()
+                .get("John-1")
+                .unwrap()
+                .unwrap()
+                .notes,
+            "Seat by the door"
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/admin/logout")
+            .cookie(cookie)
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(resp.headers().get(LOCATION).unwrap(), "/admin/login");
+        let cookie = session_cookie(&resp);
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .cookie(cookie.clone())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let req = test::TestRequest::get()
+            .uri("/admin/login")
+            .cookie(cookie)
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert!(resp.into_body().into_str().contains("Signed out"));
+    }
+
+    #[actix_rt::test]
+    async fn remember_guest_integration_test() {
+        let db = test_db(1);
+        let record = db.get_all().unwrap().remove(0);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new_with_db(db)))
+                .configure(app_config),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/fetch")
+            .set_form([("name", record.name.as_str()), ("remember", "true")])
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let cookie = session_cookie(&resp);
+
+        let req = test::TestRequest::get()
+            .uri("/fetch")
+            .cookie(cookie.clone())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let body = resp.into_body().into_str();
+        assert!(body.contains(&format!("Welcome back, {}!", record.name)));
+        assert!(body.contains(&format!("/rsvp/{}", record.token)));
+
+        let req = test::TestRequest::post()
+            .uri("/forget")
+            .cookie(cookie)
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let req = test::TestRequest::get()
+            .uri("/fetch")
+            .cookie(session_cookie(&resp))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let body = resp.into_body().into_str();
+        assert!(!body.contains("Welcome back"));
+        assert!(body.contains("remember you on this device anymore"));
+    }
+
+    #[actix_rt::test]
+    async fn banners_integration_test() {
+        let mut state = admin_state(test_db(1));
+        state.shared_mut().banners = Arc::new(Banners::new(&[config::BannerConfig {
+            text: "Photos from the party are up!".to_string(),
+            paths: vec!["/photos".to_string()],
+            ..config::BannerConfig::default()
+        }]));
+        let data = web::Data::new(state);
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let page = |uri: &'static str| {
+            let app = &app;
+            async move {
+                let req = test::TestRequest::get()
+                    .uri(uri)
+                    .insert_header(admin_auth())
+                    .to_request();
+                let resp: ServiceResponse = app.call(req).await.unwrap();
+                assert_eq!(resp.status(), StatusCode::OK);
+                resp.into_parts().1.into_body().into_str()
+            }
+        };
+        assert!(page("/photos")
+            .await
+            .contains("Photos from the party are up!"));
+        assert!(!page("/").await.contains("site-banner"));
+
+        let req = test::TestRequest::post()
+            .uri("/admin/banners")
+            .insert_header(admin_auth())
+            .set_form([
+                ("text", "Parking moved <b>next door</b>"),
+                ("severity", "warning"),
+                ("expires", ""),
+                ("paths", ""),
+            ])
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let body = page("/").await;
+        let banner = body
+            .find("<div class=\"site-banner site-banner--warning\" role=\"alert\">Parking moved &lt;b&gt;next door&lt;/b&gt;</div>")
+            .unwrap();
+        assert!(body.find("<body>").unwrap() < banner);
+        assert!(page("/admin/banners").await.contains("Parking moved"));
+        assert!(!page("/admin").await.contains("site-banner--warning"));
+
+        let req = test::TestRequest::post()
+            .uri("/admin/banners")
+            .insert_header(admin_auth())
+            .set_form([
+                ("text", "Rain plan"),
+                ("severity", "info"),
+                ("expires", "tomorrow"),
+                ("paths", ""),
+            ])
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(resp.headers().get(LOCATION).unwrap(), "/admin/banners");
+        let req = test::TestRequest::get()
+            .uri("/admin/banners")
+            .insert_header(admin_auth())
+            .cookie(session_cookie(&resp))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let body = resp.into_body().into_str();
+        let flash = body
+            .find("<p class=\"flash flash--error\" role=\"alert\">Could not put up the banner")
+            .unwrap();
+        assert!(body.find("<body>").unwrap() < flash);
+        assert!(!body.contains("Rain plan"));
+        assert!(!page("/admin/banners").await.contains("flash--error"));
+
+        let req = test::TestRequest::post()
+            .uri("/admin/banners/0/remove")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert!(!page("/photos").await.contains("party"));
+        let req = test::TestRequest::post()
+            .uri("/admin/banners/5/remove")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn admin_metrics_integration_test() {
+        let data = web::Data::new(admin_state(test_db(1)));
+        data.metrics
+            .increment("rsvp_not_found_total", &[("class", "scanner")]);
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::get().uri("/admin/metrics").to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let req = test::TestRequest::get()
+            .uri("/admin/metrics")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("rsvp_not_found_total{class=\"scanner\"} 1\n"));
+    }
+
+    #[actix_rt::test]
+    async fn admin_tasks_integration_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = admin_state(test_db(3));
+        state.shared_mut().config.backups.dir = dir.path().to_str().unwrap().to_string();
+        let backups = Backups::new(&state.config.backups);
+        let data = web::Data::new(state);
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::get()
+            .uri("/admin/tasks")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("snapshot"));
+        assert!(body.contains("Never"));
+
+        let req = test::TestRequest::post()
+            .uri("/admin/tasks/snapshot/run")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(backups.list().unwrap()[0].reason, "scheduled");
+        let status = &data.scheduler.statuses()[0];
+        assert_eq!(status.name, "snapshot");
+        assert_eq!(status.runs, 1);
+        assert_eq!(status.last_result, "ok");
+
+        // off without a bucket
+        for uri in ["/admin/tasks/upload/run", "/admin/tasks/unknown/run"] {
+            let req = test::TestRequest::post()
+                .uri(uri)
+                .insert_header(admin_auth())
+                .to_request();
+            let resp: ServiceResponse = app.call(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn short_link_integration_test() {
+        let mut state = admin_state(test_db(3));
+        state.shared_mut().config.short_links.base_url = Some("https://jnj.wed".to_string());
+        state.shared_mut().config.fetch_lockout.max_misses = 2;
+        let data = web::Data::new(state);
+        let record = data.db.write().await.get("John-1").unwrap().unwrap();
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/i/{}", record.short_code.to_lowercase()))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FOUND);
+        let location = resp.headers().get(LOCATION).unwrap().to_str().unwrap();
+        assert_eq!(location, format!("/rsvp/{}", record.token));
+
+        let req = test::TestRequest::get().uri(location).to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = resp.into_body().into_str();
+        assert!(body.contains("RSVP for John-1"));
+
+        for uri in ["/i/NOPE22", "/rsvp/nope"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let resp: ServiceResponse = app.call(req).await.unwrap();
+            assert!(resp.into_body().into_str().contains(NOT_FOUND_MESSAGE));
+        }
+
+        // codes not found count towards the name lookup lockout
+        let short_link = |code: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/i/{}", code))
+                .peer_addr("203.0.113.7:4000".parse().unwrap())
+                .to_request()
+        };
+        for code in ["NOPE23", "NOPE24", "NOPE25"] {
+            let resp: ServiceResponse = app.call(short_link(code)).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        let entries = data.audit.entries().unwrap();
+        assert_eq!(entries.last().unwrap().action, LOCKOUT_ACTION);
+        assert_eq!(entries.last().unwrap().name, "203.0.113.7");
+        let resp: ServiceResponse = app.call(short_link(&record.short_code)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/links")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let body = resp.into_body().into_str();
+        assert!(body.contains(&format!("https://jnj.wed/i/{}", record.short_code)));
+        assert!(body.contains(&format!("http://127.0.0.1:8080/rsvp/{}", record.token)));
+
+        let req = test::TestRequest::get()
+            .uri("/admin/inserts.pdf?segment=attending")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        if cfg!(feature = "pdf") {
+            assert_eq!(resp.status(), StatusCode::OK);
+            let content_type = resp.headers().get(CONTENT_TYPE).unwrap();
+            assert_eq!(content_type, "application/pdf");
+        } else {
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn source_integration_test() {
+        let data = web::Data::new(admin_state(test_db(3)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let record = |name: &str| {
+            let data = data.clone();
+            let name = name.to_string();
+            async move { data.db.write().await.get(&name).unwrap().unwrap() }
+        };
+
+        // guests can't claim another source
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(RsvpParams {
+                source: Some(Source::Paper),
+                comments: "See you there".to_string(),
+                ..RsvpParams::from(&record("John-0").await)
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(record("John-0").await.source, Some(Source::Web));
+
+        let req = test::TestRequest::post()
+            .uri("/admin/rsvp")
+            .insert_header(admin_auth())
+            .set_form(RsvpParams {
+                source: Some(Source::Paper),
+                ..RsvpParams::from(&record("John-1").await)
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(record("John-1").await.source, Some(Source::Paper));
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/rsvps")
+            .set_json(RsvpParams {
+                comments: "See you there".to_string(),
+                ..RsvpParams::from(&record("John-2").await)
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(record("John-2").await.source, Some(Source::Api));
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/admin/rsvp/{}", record("John-1").await.token))
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let body = resp.into_body().into_str();
+        assert!(body.contains(r#"name="source" value="paper" checked"#));
+        let req = test::TestRequest::get()
+            .uri(&format!("/rsvp/{}", record("John-1").await.token))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert!(!resp.into_body().into_str().contains(r#"name="source""#));
+
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("Web form: 1"));
+        assert!(body.contains("Paper card: 1"));
+        assert!(body.contains("API: 1"));
+    }
+
+    #[actix_rt::test]
+    async fn admin_rsvp_integration_test() {
+        let data = web::Data::new(admin_state(test_db(3)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let record = data.db.write().await.get("John-1").unwrap().unwrap();
+        let req = test::TestRequest::get()
+            .uri(&format!("/admin/rsvp/{}", record.token))
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        assert!(resp.into_body().into_str().contains("action=/admin/rsvp"));
+
+        let params = RsvpParams {
+            attending: true,
+            comments: "Said yes by phone".to_string(),
+            ..RsvpParams::from(&record)
+        };
+        let req = test::TestRequest::post()
+            .uri("/admin/rsvp")
+            .set_form(params.clone())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/admin/rsvp")
+            .insert_header(admin_auth())
+            .set_form(params)
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let record = data.db.write().await.get("John-1").unwrap().unwrap();
+        assert_eq!(record.comments, "Said yes by phone");
+        let entries = data.audit.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "John-1");
+        assert_eq!(entries[0].entered_by, EnteredBy::Admin);
+
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(RsvpParams::from(&record))
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let req = test::TestRequest::post()
+            .uri("/rsvp")
+            .set_form(RsvpParams {
+                comments: "Changed my mind".to_string(),
+                ..RsvpParams::from(&record)
+            })
+            .to_request();
+        app.call(req).await.unwrap();
+        let entries = data.audit.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].entered_by, EnteredBy::Guest);
+
+        let req = test::TestRequest::get()
+            .uri("/admin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        assert!(resp
+            .into_body()
+            .into_str()
+            .contains("RSVP for John-1 by admin"));
+    }
+
+    #[actix_rt::test]
+    async fn admin_checkin_integration_test() {
+        let data = web::Data::new(admin_state(test_db(3)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::get()
+            .uri("/admin/checkin")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert!(body.contains("0</span> of 2 checked in"));
+        assert!(body.contains("John-2 + Johnson-2"));
+        assert!(!body.contains("John-1"));
+
+        let checkin = || {
+            test::TestRequest::post()
+                .uri("/admin/checkin")
+                .insert_header(admin_auth())
+                .set_form(NameParams {
+                    name: "John-2".to_string(),
+                })
+                .to_request()
+        };
+        let resp: ServiceResponse = app.call(checkin()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let record = data.db.write().await.get("John-2").unwrap().unwrap();
+        assert!(record.checked_in_at.is_some());
+
+        let req = test::TestRequest::get()
+            .uri("/admin/checkin/count")
+            .insert_header(admin_auth())
+            .to_request();
+        let count: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(count, json!({ "attending": 2, "checked_in": 1 }));
+
+        app.call(checkin()).await.unwrap();
+        let record = data.db.write().await.get("John-2").unwrap().unwrap();
+        assert!(record.checked_in_at.is_none());
+        let entries = data.audit.entries().unwrap();
+        assert_eq!(entries[0].action, "check-in");
+        assert_eq!(entries[1].action, "undo check-in");
+    }
+
+    #[actix_rt::test]
+    async fn admin_merge_integration_test() {
+        let mut db = CsvDb::default();
+        db.insert(&AddParams {
+            name: "Jonathan Smith".to_string(),
+            ..test_add()
+        })
+        .unwrap();
+        db.upsert(&RsvpParams {
+            name: "Jonathon Smith".to_string(),
+            ..test_rsvp()
+        })
+        .unwrap();
+        let data = web::Data::new(admin_state(db));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/admin/duplicates.json")
+            .insert_header(admin_auth())
+            .to_request();
+        let pairs: Vec<DuplicatePair> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].reason, "same email");
+
+        let req = test::TestRequest::get()
+            .uri("/admin/duplicates")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        let (_, resp) = resp.into_parts();
+        assert!(resp.into_body().into_str().contains("Jonathon Smith"));
+
+        let req = test::TestRequest::post()
+            .uri("/admin/merge")
+            .insert_header(admin_auth())
+            .set_form(MergeParams {
+                keep: "Jonathan Smith".to_string(),
+                remove: "jonathon smith".to_string(),
+                answers_from: AnswersFrom::Latest,
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let records = data.db.write().await.get_all().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].name, "Jonathan Smith");
+        assert!(records[0].attending);
+        assert_eq!(records[0].meal_choice, "Fish");
+        assert_eq!(
+            data.audit.entries().unwrap()[0].action,
+            "merge from Jonathon Smith"
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/admin/merge")
+            .insert_header(admin_auth())
+            .set_form(MergeParams {
+                keep: "Jonathan Smith".to_string(),
+                remove: "Jonathan Smith".to_string(),
+                answers_from: AnswersFrom::Keep,
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn admin_export_integration_test() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(admin_state(test_db(3))))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/admin/export/buttondown")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(CONTENT_TYPE).unwrap(), "text/csv");
+        let (_, resp) = resp.into_parts();
+        assert_eq!(
+            resp.into_body().into_str(),
+            "email,first_name,tags\njohn0@john.john,John-0,\njohn2@john.john,John-2,\n"
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/admin/rsvps.csv")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(CONTENT_DISPOSITION).unwrap(),
+            "attachment; filename=\"rsvp.csv\""
+        );
+        let (_, resp) = resp.into_parts();
+        assert_eq!(resp.into_body().into_str().lines().count(), 4);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/rsvps.csv?columns=name,headcount")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        assert_eq!(
+            resp.into_body().into_str(),
+            "name,headcount\nJohn-0,2\nJohn-1,0\nJohn-2,2\n"
+        );
+        let req = test::TestRequest::get()
+            .uri("/admin/rsvps.csv?preset=caterer")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/export/unknown")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn admin_ics_integration_test() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(admin_state(test_db(3))))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/admin/rsvps.ics")
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/rsvps.ics")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert_eq!(body.matches("BEGIN:VEVENT").count(), 3);
+        assert!(body.contains("SUMMARY:John-2 +1 attending"));
+    }
+
+    #[actix_rt::test]
+    async fn admin_feed_integration_test() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(admin_state(test_db(3))))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/admin/checkin")
+            .insert_header(admin_auth())
+            .set_form(NameParams {
+                name: "John-2".to_string(),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/feed.xml")
+            .insert_header(admin_auth())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(CONTENT_TYPE).unwrap(),
+            "application/atom+xml; charset=utf-8"
+        );
+        let (_, resp) = resp.into_parts();
+        let body = resp.into_body().into_str();
+        assert_eq!(body.matches("<entry>").count(), 1);
+        assert!(body.contains("<title>check-in for John-2 by admin</title>"));
+    }
+
+    #[actix_rt::test]
+    async fn admin_seed_integration_test() {
+        let data = web::Data::new(admin_state(test_db(3)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let req = test::TestRequest::post()
+            .uri("/admin/seed")
+            .insert_header(admin_auth())
+            .set_form(SeedParams { count: 20 })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(data.db.write().await.get_all().unwrap().len(), 23);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new({
+                    let mut state = admin_state(test_db(3));
+                    state.shared_mut().test = false;
+                    state
+                }))
+                .configure(app_config),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/admin/seed")
+            .insert_header(admin_auth())
+            .set_form(SeedParams { count: 20 })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn preferences_integration_test() {
+        let data = web::Data::new(AppState::new_with_db(test_db(3)));
+        let app = test::init_service(App::new().app_data(data.clone()).configure(app_config)).await;
+        let record = data.db.write().await.get("John-1").unwrap().unwrap();
+        let uri = format!("/unsubscribe/{}", record.token);
+
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let record = data.db.write().await.get("John-1").unwrap().unwrap();
+        assert!(!record.announcements_opt_out);
+
+        let req = test::TestRequest::post()
+            .uri(&uri)
+            .set_form(PreferencesParams {
+                announcements: None,
+                transactional: Some("on".to_string()),
+            })
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let record = data.db.write().await.get("John-1").unwrap().unwrap();
+        assert!(record.announcements_opt_out);
+        assert!(!record.transactional_opt_out);
+
+        let req = test::TestRequest::post()
+            .uri(&uri)
+            .set_form(PreferencesParams::default())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let record = data.db.write().await.get("John-1").unwrap().unwrap();
+        assert!(record.announcements_opt_out);
+        assert!(record.transactional_opt_out);
+
+        let req = test::TestRequest::post()
+            .uri("/unsubscribe/unknown")
+            .set_form(PreferencesParams::default())
+            .to_request();
+        let resp: ServiceResponse = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
     }
 }
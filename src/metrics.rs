@@ -0,0 +1,97 @@
+use {
+    log::warn,
+    std::{
+        collections::BTreeMap,
+        fmt::Write,
+        sync::{Mutex, MutexGuard},
+    },
+};
+
+/// Counters kept since the server started, served at `/admin/metrics` in the
+/// Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    /// Totals by name, then by their labels as `key="value"` pairs
+    counters: Mutex<BTreeMap<String, BTreeMap<String, u64>>>,
+}
+impl Metrics {
+    /// Add one to a counter, e.g. `increment("rsvp_not_found_total",
+    /// &[("class", "scanner")])`
+    pub fn increment(&self, name: &str, labels: &[(&str, &str)]) {
+        let labels = label_key(labels);
+        *self
+            .lock()
+            .entry(name.to_string())
+            .or_default()
+            .entry(labels)
+            .or_default() += 1;
+    }
+
+    /// Every counter in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (name, counter) in self.lock().iter() {
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (labels, total) in counter {
+                let _ = match labels.is_empty() {
+                    true => writeln!(out, "{} {}", name, total),
+                    false => writeln!(out, "{}{{{}}} {}", name, labels, total),
+                };
+            }
+        }
+        out
+    }
+
+    /// The counters, even if a request panicked while updating them, since
+    /// they're only for monitoring
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, BTreeMap<String, u64>>> {
+        self.counters.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the metrics after a panic");
+            poisoned.into_inner()
+        })
+    }
+}
+
+/// Labels as they're written out, which also tells counters apart
+fn label_key(labels: &[(&str, &str)]) -> String {
+    labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Label values escaped as Prometheus expects
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counters() {
+        let metrics = Metrics::default();
+        assert_eq!(metrics.render(), "");
+        metrics.increment("rsvp_not_found_total", &[("class", "scanner")]);
+        metrics.increment("rsvp_not_found_total", &[("class", "scanner")]);
+        metrics.increment("rsvp_not_found_total", &[("class", "missing")]);
+        metrics.increment("rsvp_restarts_total", &[]);
+        assert_eq!(
+            metrics.render(),
+            "# TYPE rsvp_not_found_total counter\n\
+             rsvp_not_found_total{class=\"missing\"} 1\n\
+             rsvp_not_found_total{class=\"scanner\"} 2\n\
+             # TYPE rsvp_restarts_total counter\n\
+             rsvp_restarts_total 1\n"
+        );
+        metrics.increment("rsvp_odd_total", &[("path", "a\"b")]);
+        assert!(metrics
+            .render()
+            .contains("rsvp_odd_total{path=\"a\\\"b\"} 1"));
+    }
+}
@@ -1,20 +1,374 @@
 use {
-    crate::error::Error,
-    chrono::{DateTime, Utc},
+    crate::{
+        archive::Frozen,
+        audit::AuditEntry,
+        calendar::{self, CalendarLinks},
+        childcare::ChildcareSummary,
+        config::{
+            AirportConfig, ChildcareConfig, Config, HotelConfig, MealConfig, NextStep,
+            PrivateEventConfig, TravelConfig,
+        },
+        dietary::{Choice, DietaryCount, Severity},
+        error::Error,
+        form::Condition,
+        hooks::Event,
+        language,
+        photos::{Album, GalleryItem, Video},
+        quarantine::Held,
+        registry::RegistryCount,
+        shuttle::{ShuttleChoice, StopCount},
+        songs::RankedSong,
+        source::{Source, SourceCount},
+        waves::WaveStats,
+    },
+    chrono::{DateTime, NaiveDate, Utc},
+    clap::ValueEnum,
+    rand::{distributions::Alphanumeric, Rng},
     serde::{Deserialize, Serialize},
+    std::collections::{BTreeMap, HashSet},
+    unicode_normalization::{char::is_combining_mark, UnicodeNormalization},
 };
 
-pub const NUM_PHOTOS: usize = 1;
+/// Meals offered on the RSVP form
+pub const MEALS: [&str; 3] = ["Meat", "Fish", "Veggie"];
 
+/// How a meal is shown to guests, with its value as the label unless
+/// `meals` gives one
+pub fn describe_meal(meals: &BTreeMap<String, MealConfig>, meal: &str) -> MealConfig {
+    let mut described = meals.get(meal).cloned().unwrap_or_default();
+    if described.label.trim().is_empty() {
+        described.label = meal.to_string();
+    }
+    described
+}
+
+/// Every meal on the RSVP form, described
+pub fn meal_options(meals: &BTreeMap<String, MealConfig>) -> BTreeMap<String, MealConfig> {
+    MEALS
+        .iter()
+        .map(|meal| (meal.to_string(), describe_meal(meals, meal)))
+        .collect()
+}
+
+/// Guests per page when the request doesn't say
+pub const DEFAULT_PER_PAGE: usize = 50;
+
+/// Most guests per page, however many are asked for
+pub const MAX_PER_PAGE: usize = 200;
+
+/// Fields of the fetch page
+pub const FETCH_FIELDS: &[&str] = &["name"];
+
+/// Fields of the form for the code sent to a guest's email
+pub const VERIFY_FIELDS: &[&str] = &["code"];
+
+/// One problem with a submitted form
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct FieldError {
+    /// The input's `id`, or empty for a problem with the whole form
+    pub field: String,
+    /// `id` of the message shown by the input, for its `aria-describedby`
+    pub id: String,
+    pub message: String,
+}
+
+/// Why a submitted form couldn't be taken, as one message for the page and
+/// as each problem with the field it's about, so templates can mark inputs
+/// `aria-invalid` and point screen readers at their messages
 #[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
 pub struct ErrorContext {
     pub has_error: bool,
+    /// The first problem
     pub error: String,
+    /// Every problem in order, for a summary linking to each input
+    pub error_summary: Vec<FieldError>,
+    /// Each field's message, empty if it's fine, for every field of the
+    /// form whenever `has_error` is set
+    pub field_errors: BTreeMap<String, String>,
+}
+impl ErrorContext {
+    /// The problems with a form with these fields, each with the field it's
+    /// about, or empty for the whole form
+    pub fn new(fields: &[&str], problems: Vec<(&str, String)>) -> Self {
+        let mut field_errors = fields
+            .iter()
+            .map(|field| (field.to_string(), String::default()))
+            .collect::<BTreeMap<_, _>>();
+        let error_summary = problems
+            .into_iter()
+            .map(|(field, message)| {
+                if let Some(slot) = field_errors.get_mut(field).filter(|slot| slot.is_empty()) {
+                    *slot = message.clone();
+                }
+                FieldError {
+                    field: field.to_string(),
+                    id: match field.is_empty() {
+                        true => String::default(),
+                        false => format!("{}-error", field),
+                    },
+                    message,
+                }
+            })
+            .collect::<Vec<_>>();
+        Self {
+            has_error: !error_summary.is_empty(),
+            error: error_summary
+                .first()
+                .map(|problem| problem.message.clone())
+                .unwrap_or_default(),
+            error_summary,
+            field_errors,
+        }
+    }
+
+    /// One problem with the whole form
+    pub fn page(fields: &[&str], message: impl ToString) -> Self {
+        Self::new(fields, vec![("", message.to_string())])
+    }
 }
 
 #[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
 pub struct IndexContext {
+    /// Left empty when guests can use the contact page instead
     pub admin: String,
+    pub contact: bool,
+}
+
+/// Numbers for the public stats page, with nothing about any one guest
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct StatsContext {
+    pub parties: u32,
+    pub responded_parties: u32,
+    pub attendance: Attendance,
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct AdminContext {
+    pub responses: usize,
+    pub attendance: Attendance,
+    pub headcount: Headcount,
+    /// Guests and plus-ones coming with each dietary restriction
+    pub dietary: Vec<DietaryCount>,
+    /// Response rates of each round of invitations, if there are any
+    pub waves: Vec<WaveStats>,
+    /// How many parties responded through each channel
+    pub sources: Vec<SourceCount>,
+    /// Clicks on each gift registry link, if there are any
+    pub registries: Vec<RegistryCount>,
+    /// Riders signed up at each shuttle stop, if there's a shuttle
+    pub shuttle: Vec<StopCount>,
+    /// Kids signed up for childcare
+    pub childcare: ChildcareSummary,
+    /// The most requested songs, if guests can request them
+    pub songs: Vec<RankedSong>,
+    /// Messages from the contact page held as spam, newest first
+    pub quarantine: Vec<Held>,
+    /// Whether the last event has started, when thank-you cards are due
+    pub event_over: bool,
+    /// When the site was archived, if it has been
+    pub archive: Option<Frozen>,
+    /// How many times the config was reloaded since the server started
+    pub reloads: u64,
+    /// The change that undo would revert, if any
+    pub last_change: String,
+    /// Warning that the guest list is close to its `limits`, if it is
+    pub limits_warning: Option<String>,
+    pub bad_emails: Vec<RsvpModel>,
+    pub guests: Vec<RsvpModel>,
+    pub guest_pages: PageLinks,
+    pub recent_changes: Vec<AuditEntry>,
+    pub templates: Vec<String>,
+    /// Saved column choices for the guest list export
+    pub export_presets: Vec<String>,
+    pub build: BuildInfo,
+}
+
+/// Context for the condensed admin page, for checking in from a phone
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct QuickContext {
+    pub headcount: Headcount,
+    /// Parties that responded they aren't coming to anything
+    pub declined: usize,
+    /// The latest responses, newest first
+    pub recent: Vec<RecentResponse>,
+    /// Messages from the contact page held as spam
+    pub held: usize,
+    pub bad_emails: usize,
+}
+
+/// One party's response on the condensed admin page
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RecentResponse {
+    pub name: String,
+    pub attending: bool,
+    /// People coming, counting the plus-one
+    pub coming: u32,
+    pub responded_at: DateTime<Utc>,
+}
+impl From<&RsvpModel> for RecentResponse {
+    fn from(record: &RsvpModel) -> Self {
+        Self {
+            name: record.name.clone(),
+            attending: record.is_attending_any(),
+            coming: u32::from(record.is_attending_any())
+                + u32::from(record.plus_one_attending_any()),
+            responded_at: record.responded_at.unwrap_or(record.updated_at),
+        }
+    }
+}
+
+/// Context for the check-in page used at the door
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CheckinContext {
+    pub guests: Vec<CheckinGuest>,
+    pub attending: usize,
+    pub checked_in: usize,
+}
+
+/// An attending guest on the check-in page
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CheckinGuest {
+    pub name: String,
+    pub plus_one_name: String,
+    /// Lowercase names to match against the search box
+    pub search: String,
+    pub checked_in: bool,
+}
+impl From<&RsvpModel> for CheckinGuest {
+    fn from(record: &RsvpModel) -> Self {
+        let plus_one_name = if record.plus_one_attending {
+            record.plus_one_name.clone()
+        } else {
+            String::default()
+        };
+        Self {
+            search: format!("{} {}", record.name, plus_one_name).to_lowercase(),
+            name: record.name.clone(),
+            plus_one_name,
+            checked_in: record.checked_in_at.is_some(),
+        }
+    }
+}
+
+/// A guest's links on the admin's list, to print or text to them
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ShortLink {
+    pub name: String,
+    pub short_url: String,
+    pub rsvp_url: String,
+}
+
+/// Which build is running, for debugging deployments and confirming rollouts
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct BuildInfo {
+    pub version: String,
+    pub commit: String,
+    pub built_at: String,
+    pub uptime_secs: i64,
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct AnnouncementContext {
+    pub segment: String,
+    pub segments: Vec<String>,
+    pub subject: String,
+    pub body: String,
+    pub recipients: usize,
+    pub has_preview: bool,
+    pub preview_to: String,
+    pub preview_subject: String,
+    pub preview_body: String,
+    pub sent: bool,
+    pub has_error: bool,
+    pub error: String,
+}
+
+/// The page to look up an RSVP by name, offering the guest this browser
+/// remembers their RSVP straight away
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct FetchContext {
+    pub remembered: Option<RememberedGuest>,
+    #[serde(flatten)]
+    pub errors: ErrorContext,
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RememberedGuest {
+    pub name: String,
+    /// For the link to their RSVP
+    pub token: String,
+}
+
+/// The contact page, with what the guest wrote kept if it needs fixing
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ContactContext {
+    pub name: String,
+    pub email: String,
+    pub message: String,
+    pub sent: bool,
+    #[serde(flatten)]
+    pub errors: ErrorContext,
+}
+
+/// A hotel on the travel page
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct HotelContext {
+    #[serde(flatten)]
+    pub hotel: HotelConfig,
+    /// Whether the block's cutoff has passed, so the rate may be gone
+    pub closed: bool,
+}
+
+/// The travel page, with where to stay, fly into and park
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TravelContext {
+    pub hotels: Vec<HotelContext>,
+    pub airports: Vec<AirportConfig>,
+    pub parking: String,
+}
+impl TravelContext {
+    pub fn new(config: &TravelConfig, today: NaiveDate) -> Self {
+        Self {
+            hotels: config
+                .hotels
+                .iter()
+                .map(|hotel| HotelContext {
+                    hotel: hotel.clone(),
+                    closed: hotel.cutoff.is_some_and(|cutoff| cutoff < today),
+                })
+                .collect(),
+            airports: config.airports.clone(),
+            parking: config.parking.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PreferencesContext {
+    pub name: String,
+    pub token: String,
+    pub announcements: bool,
+    pub transactional: bool,
+    pub done: bool,
+}
+
+/// Context for a guest's page of song requests
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SongsContext {
+    pub name: String,
+    pub token: String,
+    /// Most votes first, marking those the guest voted for
+    pub songs: Vec<RankedSong>,
+    pub has_error: bool,
+    pub error: String,
+}
+
+/// Submitted email preferences, where checked boxes mean the guest wants
+/// that kind of email
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct PreferencesParams {
+    pub announcements: Option<String>,
+    pub transactional: Option<String>,
 }
 
 #[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
@@ -22,12 +376,75 @@ pub struct Attendance {
     pub attending: u32,
     pub attending_secondary: u32,
     pub attending_tertiary: u32,
+    /// Coming to the private event, out of those invited to it
+    pub attending_private: u32,
+}
+
+/// Invited seats compared with responses, to see how many are still
+/// uncertain
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Headcount {
+    pub parties: u32,
+    /// Seats invited across all parties
+    pub invited: u32,
+    pub responded_parties: u32,
+    /// Seats invited in the parties that responded
+    pub responded: u32,
+    /// People coming to at least one event
+    pub attending: u32,
+    /// Seats invited in the parties that haven't responded yet
+    pub uncertain: u32,
 }
 
 #[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
 pub struct PhotosContext {
     pub admin: String,
-    pub photo_indices: [usize; NUM_PHOTOS],
+    pub albums: Vec<Album>,
+    /// Videos that aren't in an album, like the livestream recording
+    pub videos: Vec<Video>,
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct AlbumContext {
+    pub admin: String,
+    pub album: Album,
+    pub items: Vec<GalleryItem>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct NotesParams {
+    pub name: String,
+    pub notes: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TableParams {
+    pub name: String,
+    pub table: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct MergeParams {
+    pub keep: String,
+    pub remove: String,
+    #[serde(default)]
+    pub answers_from: AnswersFrom,
+}
+
+/// Which record's RSVP answers survive a merge
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AnswersFrom {
+    /// Whichever was updated last
+    #[default]
+    Latest,
+    Keep,
+    Remove,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SeedParams {
+    pub count: usize,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -35,27 +452,317 @@ pub struct NameParams {
     pub name: String,
 }
 
+/// A guest looking up their RSVP, and whether to remember them on this
+/// browser
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct FetchParams {
+    pub name: String,
+    #[serde(default)]
+    pub remember: bool,
+}
+
+/// The admin signing in on the login page
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct LoginParams {
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    /// Where to go once signed in
+    #[serde(default)]
+    pub next: String,
+}
+
+/// What to compare the guest list against for the changes export: a
+/// snapshot by name, or the newest one from before a date
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ChangesParams {
+    #[serde(default)]
+    pub snapshot: Option<String>,
+    #[serde(default)]
+    pub since: Option<NaiveDate>,
+}
+
+/// Columns for the guest list export, e.g. `?columns=name,headcount,meals`
+/// or `?preset=caterer`, instead of the whole CSV file
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ExportParams {
+    #[serde(default)]
+    pub columns: Option<String>,
+    #[serde(default)]
+    pub preset: Option<String>,
+}
+
+/// Query for one page of the guest list, e.g.
+/// `?page=2&per_page=20&sort=-updated_at&filter=attending:true,tag:family`
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ListParams {
+    #[serde(default = "first_page")]
+    pub page: usize,
+    #[serde(default = "default_per_page")]
+    pub per_page: usize,
+    /// Field to sort by, descending if it starts with `-`
+    #[serde(default)]
+    pub sort: String,
+    /// Comma-separated `field:value` conditions that must all match
+    #[serde(default)]
+    pub filter: String,
+    /// Text to search for in names, emails and comments
+    #[serde(default)]
+    pub q: String,
+}
+impl Default for ListParams {
+    fn default() -> Self {
+        Self {
+            page: first_page(),
+            per_page: default_per_page(),
+            sort: String::default(),
+            filter: String::default(),
+            q: String::default(),
+        }
+    }
+}
+
+fn first_page() -> usize {
+    1
+}
+
+fn default_per_page() -> usize {
+    DEFAULT_PER_PAGE
+}
+
+/// One page of a list, with the totals needed to find the others
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: usize,
+    pub per_page: usize,
+    pub total: usize,
+    pub pages: usize,
+}
+impl<T> Page<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            page: self.page,
+            per_page: self.per_page,
+            total: self.total,
+            pages: self.pages,
+        }
+    }
+}
+
+/// Links between pages of the dashboard's guest table, as query strings
+/// keeping the same sort and filter
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PageLinks {
+    pub page: usize,
+    pub pages: usize,
+    pub total: usize,
+    pub prev: Option<String>,
+    pub next: Option<String>,
+}
+impl PageLinks {
+    pub fn new<T>(page: &Page<T>, params: &ListParams) -> Self {
+        let link = |page| {
+            serde_urlencoded::to_string(ListParams {
+                page,
+                ..params.clone()
+            })
+            .ok()
+        };
+        Self {
+            page: page.page,
+            pages: page.pages,
+            total: page.total,
+            prev: (page.page > 1).then(|| link(page.page - 1)).flatten(),
+            next: (page.page < page.pages)
+                .then(|| link(page.page + 1))
+                .flatten(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SearchParams {
+    pub q: String,
+}
+
+/// An automation service subscribing to REST hooks, as Zapier and Make
+/// send it
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SubscribeParams {
+    #[serde(alias = "hookUrl", alias = "url")]
+    pub target_url: String,
+    #[serde(default)]
+    pub event: Event,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct WebhookParams {
+    pub secret: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct VerifyParams {
+    pub name: String,
+    pub code: String,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct AddParams {
     pub name: String,
     pub email: String,
     pub plus_one_name: String,
+    #[serde(default)]
+    pub tags: String,
+    /// Seats for the whole party, defaulting to the guest and their plus-one
+    /// if they have one
+    #[serde(default)]
+    pub invited: Option<u32>,
+    /// Language code to email them in, like "fr", or empty for the default
+    #[serde(default)]
+    pub language: String,
+    /// Round of invitations they're in, like "A" or "2", to compare how
+    /// each round is responding
+    #[serde(default)]
+    pub wave: String,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct RsvpParams {
     pub name: String,
+    // fields that can be hidden from the form are left out when submitted
+    #[serde(default)]
     pub email: String,
     pub attending: bool,
     pub attending_secondary: bool,
     pub attending_tertiary: bool,
+    #[serde(default)]
     pub meal_choice: String,
+    #[serde(default)]
     pub dietary_restrictions: String,
+    /// Whether the plus-one is coming to the main event
     pub plus_one_attending: bool,
+    #[serde(default)]
+    pub plus_one_attending_secondary: bool,
+    #[serde(default)]
+    pub plus_one_attending_tertiary: bool,
+    #[serde(default)]
     pub plus_one_name: String,
+    #[serde(default)]
     pub plus_one_meal_choice: String,
+    #[serde(default)]
     pub plus_one_dietary_restrictions: String,
+    #[serde(default)]
     pub comments: String,
+    /// Language the guest gets email in, kept from their record by the form
+    /// or else taken from their browser
+    #[serde(default)]
+    pub language: String,
+    /// Checked dietary restrictions, comma-separated, with anything else in
+    /// `dietary_restrictions`
+    #[serde(default)]
+    pub dietary: String,
+    #[serde(default)]
+    pub plus_one_dietary: String,
+    #[serde(default)]
+    pub dietary_severity: Severity,
+    #[serde(default)]
+    pub plus_one_dietary_severity: Severity,
+    #[serde(default)]
+    pub plus_one_email: String,
+    /// Whether the party needs somewhere to stay, asked once there are
+    /// hotels on the travel page
+    #[serde(default)]
+    pub needs_lodging: bool,
+    /// Name of the shuttle stop the party is riding from, if any
+    #[serde(default)]
+    pub shuttle: String,
+    /// Answers for the private event, only kept for guests invited to it
+    #[serde(default)]
+    pub attending_private: bool,
+    #[serde(default)]
+    pub plus_one_attending_private: bool,
+    /// Kids the party is signing up for childcare, and how old they are,
+    /// only kept for parties with children
+    #[serde(default)]
+    pub kids: u32,
+    #[serde(default)]
+    pub kids_ages: String,
+    /// How the RSVP arrived, set by the path that saves it, whatever was
+    /// sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
+}
+
+impl RsvpParams {
+    /// One of the free-form fields, by name
+    pub fn field_mut(&mut self, name: &str) -> &mut String {
+        match name {
+            "email" => &mut self.email,
+            "meal_choice" => &mut self.meal_choice,
+            "dietary_restrictions" => &mut self.dietary_restrictions,
+            "plus_one_name" => &mut self.plus_one_name,
+            "plus_one_meal_choice" => &mut self.plus_one_meal_choice,
+            "plus_one_dietary_restrictions" => &mut self.plus_one_dietary_restrictions,
+            "plus_one_email" => &mut self.plus_one_email,
+            "comments" => &mut self.comments,
+            _ => panic!("no field named {}", name),
+        }
+    }
+
+    /// The checklist that goes with a free-form field, if it has one
+    pub fn checklist_mut(&mut self, name: &str) -> Option<&mut String> {
+        match name {
+            "dietary_restrictions" => Some(&mut self.dietary),
+            "plus_one_dietary_restrictions" => Some(&mut self.plus_one_dietary),
+            _ => None,
+        }
+    }
+
+    /// How severe the restrictions in a free-form field are, if it asks
+    pub fn severity_mut(&mut self, name: &str) -> Option<&mut Severity> {
+        match name {
+            "dietary_restrictions" => Some(&mut self.dietary_severity),
+            "plus_one_dietary_restrictions" => Some(&mut self.plus_one_dietary_severity),
+            _ => None,
+        }
+    }
+}
+
+impl From<&RsvpModel> for RsvpParams {
+    fn from(record: &RsvpModel) -> Self {
+        Self {
+            name: record.name.clone(),
+            email: record.email.clone(),
+            attending: record.attending,
+            attending_secondary: record.attending_secondary,
+            attending_tertiary: record.attending_tertiary,
+            meal_choice: record.meal_choice.clone(),
+            dietary_restrictions: record.dietary_restrictions.clone(),
+            plus_one_attending: record.plus_one_attending,
+            plus_one_attending_secondary: record.plus_one_attending_secondary,
+            plus_one_attending_tertiary: record.plus_one_attending_tertiary,
+            plus_one_name: record.plus_one_name.clone(),
+            plus_one_meal_choice: record.plus_one_meal_choice.clone(),
+            plus_one_dietary_restrictions: record.plus_one_dietary_restrictions.clone(),
+            comments: record.comments.clone(),
+            language: record.language.clone(),
+            dietary: record.dietary.clone(),
+            plus_one_dietary: record.plus_one_dietary.clone(),
+            dietary_severity: record.dietary_severity,
+            plus_one_dietary_severity: record.plus_one_dietary_severity,
+            plus_one_email: record.plus_one_email.clone(),
+            needs_lodging: record.needs_lodging,
+            shuttle: record.shuttle.clone(),
+            attending_private: record.attending_private,
+            plus_one_attending_private: record.plus_one_attending_private,
+            kids: record.kids,
+            kids_ages: record.kids_ages.clone(),
+            source: record.source,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -74,12 +781,251 @@ pub struct RsvpModel {
     pub comments: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub email_verified: bool,
+    #[serde(default)]
+    pub verification_code: String,
+    #[serde(default)]
+    pub email_status: EmailStatus,
+    /// Random per-guest secret used in links sent to the guest
+    #[serde(default)]
+    pub token: String,
+    /// Comma-separated labels set by the admin, e.g. "family"
+    #[serde(default)]
+    pub tags: String,
+    #[serde(default)]
+    pub announcements_opt_out: bool,
+    #[serde(default)]
+    pub transactional_opt_out: bool,
+    /// Private notes from the admin, never shown to the guest
+    #[serde(default)]
+    pub notes: String,
+    /// When the guest arrived on the day, if they have
+    #[serde(default)]
+    pub checked_in_at: Option<DateTime<Utc>>,
+    /// `name` normalized for matching, see `name_key`
+    #[serde(default)]
+    pub name_key: String,
+    #[serde(default)]
+    pub plus_one_attending_secondary: bool,
+    #[serde(default)]
+    pub plus_one_attending_tertiary: bool,
+    /// Seats invited for the party, set when they're added
+    #[serde(default)]
+    pub invited: u32,
+    /// When the guest last sent their RSVP, if they have
+    #[serde(default)]
+    pub responded_at: Option<DateTime<Utc>>,
+    /// Language code the guest gets email in, like "fr", or empty for the
+    /// default
+    #[serde(default)]
+    pub language: String,
+    /// Code in the guest's short link, `/i/{short_code}`, for printed
+    /// invitations and texts
+    #[serde(default)]
+    pub short_code: String,
+    /// Table the guest and their plus-one are seated at, assigned by the
+    /// admin, like "1" or "Head table"
+    #[serde(default)]
+    pub table: String,
+    /// Dietary restrictions checked off the list, comma-separated, with
+    /// anything written in kept in `dietary_restrictions`
+    #[serde(default)]
+    pub dietary: String,
+    #[serde(default)]
+    pub plus_one_dietary: String,
+    /// How severe the guest's dietary restrictions are, with severe allergies
+    /// on the kitchen alert sheet
+    #[serde(default)]
+    pub dietary_severity: Severity,
+    #[serde(default)]
+    pub plus_one_dietary_severity: Severity,
+    /// Round of invitations the guest was added in, like "A" or "2"
+    #[serde(default)]
+    pub wave: String,
+    /// How the guest's latest RSVP arrived, empty if they haven't responded
+    /// or did before this was recorded
+    #[serde(default)]
+    pub source: Option<Source>,
+    /// Where the plus-one gets their own confirmation and announcements,
+    /// unless it's the guest's address too
+    #[serde(default)]
+    pub plus_one_email: String,
+    #[serde(default)]
+    pub needs_lodging: bool,
+    /// Latest hotel block cutoff the guest was reminded of, so each reminder
+    /// only goes out once
+    #[serde(default)]
+    pub lodging_reminded: Option<NaiveDate>,
+    /// Name of the shuttle stop the guest and their plus-one are riding from,
+    /// empty if they're not taking it
+    #[serde(default)]
+    pub shuttle: String,
+    /// Whether the guest and their plus-one are coming to the private event,
+    /// which only guests with its tag are asked about
+    #[serde(default)]
+    pub attending_private: bool,
+    #[serde(default)]
+    pub plus_one_attending_private: bool,
+    /// Kids signed up for childcare, and their ages as the guest wrote them,
+    /// which only parties tagged as having children are asked
+    #[serde(default)]
+    pub kids: u32,
+    #[serde(default)]
+    pub kids_ages: String,
+    /// Whether the couple has sent the party a thank-you card, and what they
+    /// gave, both kept by the admin after the event
+    #[serde(default)]
+    pub thank_you_sent: bool,
+    #[serde(default)]
+    pub gift: String,
+}
+
+/// Kinds of email sent to guests, which they can opt out of separately
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmailKind {
+    /// About the guest's own RSVP, e.g. verification codes
+    Transactional,
+    /// Updates sent by the admin to many guests
+    Announcement,
+}
+
+/// Deliverability of a guest's email address, as reported by the email provider
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailStatus {
+    #[default]
+    Ok,
+    Bounced,
+    Complained,
+}
+
+/// A guest's RSVP as returned by the JSON API, leaving out the tokens and
+/// codes that are only meant for the guest's inbox
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RsvpResponse {
+    #[serde(flatten)]
+    pub rsvp: RsvpParams,
+    pub email_verified: bool,
+    pub pending_verification: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+impl From<RsvpModel> for RsvpResponse {
+    fn from(record: RsvpModel) -> Self {
+        Self {
+            rsvp: RsvpParams::from(&record),
+            pending_verification: !record.verification_code.is_empty(),
+            email_verified: record.email_verified,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+        }
+    }
+}
+
+/// Context for the RSVP form, which the admin can also fill in for a guest
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RsvpFormContext {
+    #[serde(flatten)]
+    pub record: RsvpModel,
+    pub form_action: String,
+    /// The session's token for the admin's copy of the form, empty on the
+    /// guest's own
+    pub csrf: String,
+    /// Whether each meal has reached its limit, so it can't be chosen
+    pub meals_full: BTreeMap<String, bool>,
+    /// Each meal's label, description and allergens
+    pub meals: BTreeMap<String, MealConfig>,
+    /// The dietary checklists, with what the guest and plus-one checked
+    pub dietary_choices: Vec<Choice>,
+    pub plus_one_dietary_choices: Vec<Choice>,
+    /// How severe the guest's and plus-one's restrictions are
+    pub dietary_severities: Vec<Choice>,
+    pub plus_one_dietary_severities: Vec<Choice>,
+    /// How the answer arrived, for the admin to pick when entering it for
+    /// the guest, and empty on the guest's own form
+    pub sources: Vec<Choice>,
+    /// Shuttle pickups the guest can choose, empty when there's no shuttle
+    pub shuttle_stops: Vec<ShuttleChoice>,
+    /// Whether the guest is asked about the private event
+    pub invited_private: bool,
+    /// How many kids to sign up for childcare, empty unless the party has
+    /// children
+    pub kids_choices: Vec<Choice>,
+    #[serde(flatten)]
+    pub errors: ErrorContext,
+}
+
+/// Who the RSVP form is for, which decides where it's sent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FormFor<'a> {
+    Guest,
+    /// The admin entering it on the guest's behalf, with the session's CSRF
+    /// token
+    Admin {
+        csrf: &'a str,
+    },
+}
+impl FormFor<'_> {
+    pub fn action(&self) -> &'static str {
+        match self {
+            Self::Guest => "/rsvp",
+            Self::Admin { .. } => "/admin/rsvp",
+        }
+    }
+}
+
+/// Context for the confirmation page, which may also ask for a verification code
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ConfirmContext {
+    #[serde(flatten)]
+    pub record: RsvpModel,
+    pub pending_verification: bool,
+    #[serde(flatten)]
+    pub errors: ErrorContext,
+    /// From `confirmation`, for whether the guest is coming
+    pub message: String,
+    pub next_steps: Vec<NextStep>,
+    /// For each event the guest is coming to
+    pub calendar_links: Vec<CalendarLinks>,
+    /// Whether the guest was asked about the private event
+    pub invited_private: bool,
+    /// What the guest and plus-one chose to eat, described
+    pub meal: MealConfig,
+    pub plus_one_meal: MealConfig,
+}
+/// Context for asking for a verification code again, which shows nothing
+/// about the guest, so it reads the same whether or not the name is on the
+/// list
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct VerifyContext {
+    pub name: String,
+    #[serde(flatten)]
+    pub errors: ErrorContext,
+}
+
+impl ConfirmContext {
+    pub fn new(record: RsvpModel, config: &Config) -> Self {
+        let attending = record.is_attending_any();
+        Self {
+            pending_verification: !record.verification_code.is_empty(),
+            errors: ErrorContext::default(),
+            message: config.confirmation.message(attending).to_string(),
+            next_steps: config.confirmation.next_steps(attending),
+            calendar_links: calendar::links(config, &record),
+            invited_private: record.invited_to(&config.private_event),
+            meal: describe_meal(&config.meals, &record.meal_choice),
+            plus_one_meal: describe_meal(&config.meals, &record.plus_one_meal_choice),
+            record,
+        }
+    }
 }
 
 impl RsvpModel {
     pub fn new_with_rsvp(params: &RsvpParams, datetime: DateTime<Utc>) -> Self {
-        Self {
-            name: params.name.clone(),
+        let mut record = Self {
+            name: normalize_name(&params.name),
+            name_key: name_key(&params.name),
             email: params.email.clone(),
             attending: params.attending,
             attending_secondary: params.attending_secondary,
@@ -87,41 +1033,134 @@ impl RsvpModel {
             meal_choice: params.meal_choice.clone(),
             dietary_restrictions: params.dietary_restrictions.clone(),
             plus_one_attending: params.plus_one_attending,
-            plus_one_name: params.plus_one_name.clone(),
+            plus_one_attending_secondary: params.plus_one_attending_secondary,
+            plus_one_attending_tertiary: params.plus_one_attending_tertiary,
+            plus_one_name: normalize_name(&params.plus_one_name),
             plus_one_meal_choice: params.plus_one_meal_choice.clone(),
             plus_one_dietary_restrictions: params.plus_one_dietary_restrictions.clone(),
             comments: params.comments.clone(),
             created_at: datetime,
             updated_at: datetime,
-        }
+            email_verified: false,
+            verification_code: String::default(),
+            email_status: EmailStatus::default(),
+            token: new_token(),
+            tags: String::default(),
+            announcements_opt_out: false,
+            transactional_opt_out: false,
+            notes: String::default(),
+            checked_in_at: None,
+            invited: 0,
+            responded_at: Some(datetime),
+            language: language::normalize(&params.language),
+            short_code: String::default(),
+            table: String::default(),
+            dietary: params.dietary.clone(),
+            plus_one_dietary: params.plus_one_dietary.clone(),
+            dietary_severity: params.dietary_severity,
+            plus_one_dietary_severity: params.plus_one_dietary_severity,
+            wave: String::default(),
+            source: params.source,
+            plus_one_email: params.plus_one_email.trim().to_string(),
+            needs_lodging: params.needs_lodging,
+            lodging_reminded: None,
+            shuttle: params.shuttle.trim().to_string(),
+            attending_private: params.attending_private,
+            plus_one_attending_private: params.plus_one_attending_private,
+            kids: params.kids,
+            kids_ages: params.kids_ages.trim().to_string(),
+            thank_you_sent: false,
+            gift: String::default(),
+        };
+        record.clear_declined();
+        // walk-ins weren't added beforehand, so they're invited as they came
+        record.invited = 1 + u32::from(!record.plus_one_name.is_empty());
+        record
     }
 
     pub fn update(&mut self, params: &RsvpParams, datetime: DateTime<Utc>) -> Result<(), Error> {
-        if self.name != params.name {
-            return Err(Error::Update(params.clone()));
+        if name_key(&self.name) != name_key(&params.name) {
+            return Err(Error::Update(Box::new(params.clone())));
+        }
+        if self.email != params.email {
+            self.email_verified = false;
+            self.verification_code.clear();
+            self.email_status = EmailStatus::default();
         }
         self.email = params.email.clone();
         self.attending = params.attending;
         self.attending_secondary = params.attending_secondary;
         self.attending_tertiary = params.attending_tertiary;
-        if !params.meal_choice.is_empty() {
+        // an empty meal keeps the current choice, unless the form didn't
+        // show it because they're not coming
+        if !params.meal_choice.is_empty() || !Condition::Attending.holds(params) {
             self.meal_choice = params.meal_choice.clone();
         }
         self.dietary_restrictions = params.dietary_restrictions.clone();
+        self.dietary = params.dietary.clone();
+        self.dietary_severity = params.dietary_severity;
         self.plus_one_attending = params.plus_one_attending;
-        self.plus_one_name = params.plus_one_name.clone();
-        if !params.plus_one_meal_choice.is_empty() {
+        self.plus_one_attending_secondary = params.plus_one_attending_secondary;
+        self.plus_one_attending_tertiary = params.plus_one_attending_tertiary;
+        self.plus_one_name = normalize_name(&params.plus_one_name);
+        if !params.plus_one_meal_choice.is_empty() || !Condition::PlusOneAttending.holds(params) {
             self.plus_one_meal_choice = params.plus_one_meal_choice.clone();
         }
         self.plus_one_dietary_restrictions = params.plus_one_dietary_restrictions.clone();
+        self.plus_one_dietary = params.plus_one_dietary.clone();
+        self.plus_one_dietary_severity = params.plus_one_dietary_severity;
+        self.plus_one_email = params.plus_one_email.trim().to_string();
+        self.needs_lodging = params.needs_lodging;
+        self.shuttle = params.shuttle.trim().to_string();
+        self.attending_private = params.attending_private;
+        self.plus_one_attending_private = params.plus_one_attending_private;
+        self.kids = params.kids;
+        self.kids_ages = params.kids_ages.trim().to_string();
+        self.source = params.source.or(self.source);
         self.comments = params.comments.clone();
+        if !params.language.is_empty() {
+            self.language = language::normalize(&params.language);
+        }
         self.updated_at = datetime;
+        self.responded_at = Some(datetime);
+        self.clear_declined();
         Ok(())
     }
 
+    /// Drop the meal and plus-one answers of a guest who isn't coming to
+    /// anything, and the plus-one's answers for events the guest skips, so
+    /// they can't end up in headcounts
+    fn clear_declined(&mut self) {
+        self.plus_one_attending &= self.attending;
+        self.plus_one_attending_secondary &= self.attending_secondary;
+        self.plus_one_attending_tertiary &= self.attending_tertiary;
+        self.plus_one_attending_private &= self.attending_private;
+        if self.kids == 0 {
+            self.kids_ages.clear();
+        }
+        if self.is_attending_any() {
+            return;
+        }
+        self.meal_choice.clear();
+        self.dietary_restrictions.clear();
+        self.dietary.clear();
+        self.dietary_severity = Severity::default();
+        self.plus_one_name.clear();
+        self.plus_one_meal_choice.clear();
+        self.plus_one_dietary_restrictions.clear();
+        self.plus_one_dietary.clear();
+        self.plus_one_dietary_severity = Severity::default();
+        self.plus_one_email.clear();
+        self.needs_lodging = false;
+        self.shuttle.clear();
+        self.kids = 0;
+        self.kids_ages.clear();
+    }
+
     pub fn new_with_add(params: &AddParams, datetime: DateTime<Utc>) -> Self {
         Self {
-            name: params.name.clone(),
+            name: normalize_name(&params.name),
+            name_key: name_key(&params.name),
             email: params.email.clone(),
             attending: false,
             attending_secondary: false,
@@ -129,12 +1168,202 @@ impl RsvpModel {
             meal_choice: String::default(),
             dietary_restrictions: String::default(),
             plus_one_attending: false,
-            plus_one_name: params.plus_one_name.clone(),
+            plus_one_attending_secondary: false,
+            plus_one_attending_tertiary: false,
+            plus_one_name: normalize_name(&params.plus_one_name),
             plus_one_meal_choice: String::default(),
             plus_one_dietary_restrictions: String::default(),
             comments: String::default(),
             created_at: datetime,
             updated_at: datetime,
+            email_verified: false,
+            verification_code: String::default(),
+            email_status: EmailStatus::default(),
+            token: new_token(),
+            tags: params.tags.clone(),
+            announcements_opt_out: false,
+            transactional_opt_out: false,
+            notes: String::default(),
+            checked_in_at: None,
+            invited: params
+                .invited
+                .unwrap_or(1 + u32::from(!params.plus_one_name.trim().is_empty())),
+            responded_at: None,
+            language: language::normalize(&params.language),
+            short_code: String::default(),
+            table: String::default(),
+            dietary: String::default(),
+            plus_one_dietary: String::default(),
+            dietary_severity: Severity::default(),
+            plus_one_dietary_severity: Severity::default(),
+            wave: params.wave.trim().to_string(),
+            source: None,
+            plus_one_email: String::default(),
+            needs_lodging: false,
+            lodging_reminded: None,
+            shuttle: String::default(),
+            attending_private: false,
+            plus_one_attending_private: false,
+            kids: 0,
+            kids_ages: String::default(),
+            thank_you_sent: false,
+            gift: String::default(),
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        let tag = tag.trim().to_lowercase();
+        self.tags.split(',').any(|t| t.trim().to_lowercase() == tag)
+    }
+
+    /// Whether the guest is asked about the private event
+    pub fn invited_to(&self, config: &PrivateEventConfig) -> bool {
+        !config.tag.trim().is_empty() && self.has_tag(&config.tag)
+    }
+
+    /// Whether the party is tagged as having children, so it's asked about
+    /// childcare
+    pub fn has_children(&self, config: &ChildcareConfig) -> bool {
+        !config.tag.trim().is_empty() && self.has_tag(&config.tag)
+    }
+
+    /// Whether the guest can and wants to receive this kind of email
+    pub fn accepts_email(&self, kind: EmailKind) -> bool {
+        let opted_out = match kind {
+            EmailKind::Transactional => self.transactional_opt_out,
+            EmailKind::Announcement => self.announcements_opt_out,
+        };
+        !self.email.is_empty() && self.email_status == EmailStatus::Ok && !opted_out
+    }
+
+    /// The plus-one's address, unless it's empty or the same inbox as the
+    /// guest's, so couples sharing one don't get everything twice
+    pub fn plus_one_inbox(&self) -> Option<&str> {
+        let address = self.plus_one_email.trim();
+        (!address.is_empty() && !address.eq_ignore_ascii_case(self.email.trim())).then_some(address)
+    }
+
+    /// Where to send the party's email: the guest's address, then the
+    /// plus-one's own
+    pub fn email_addresses(&self) -> Vec<&str> {
+        Some(self.email.trim())
+            .filter(|address| !address.is_empty())
+            .into_iter()
+            .chain(self.plus_one_inbox())
+            .collect()
+    }
+
+    pub fn is_attending_any(&self) -> bool {
+        self.attending
+            || self.attending_secondary
+            || self.attending_tertiary
+            || self.attending_private
+    }
+
+    /// Whether the plus-one is coming to any event the guest is coming to
+    pub fn plus_one_attending_any(&self) -> bool {
+        (self.attending && self.plus_one_attending)
+            || (self.attending_secondary && self.plus_one_attending_secondary)
+            || (self.attending_tertiary && self.plus_one_attending_tertiary)
+            || (self.attending_private && self.plus_one_attending_private)
+    }
+
+    /// Seats the party takes on the shuttle: the guest and their plus-one if
+    /// they're coming
+    pub fn shuttle_riders(&self) -> usize {
+        if self.shuttle.is_empty() || !self.is_attending_any() {
+            return 0;
+        }
+        1 + usize::from(self.plus_one_attending_any())
+    }
+
+    /// Meals chosen for the guest and their plus-one, only counting those
+    /// who are coming
+    pub fn meals(&self) -> Vec<&str> {
+        let mut meals = vec![];
+        if self.is_attending_any() {
+            meals.push(self.meal_choice.as_str());
+            if self.plus_one_attending_any() {
+                meals.push(self.plus_one_meal_choice.as_str());
+            }
         }
+        meals.retain(|meal| !meal.is_empty());
+        meals
     }
+
+    /// Whether the names, email or comments contain the query, which must
+    /// already be a `search_key`
+    pub fn matches(&self, query: &str) -> bool {
+        [&self.name, &self.plus_one_name, &self.email, &self.comments]
+            .iter()
+            .any(|text| search_key(text).contains(query))
+    }
+}
+
+/// Name as it should be stored and shown: Unicode NFC, with single spaces
+/// between words
+pub fn normalize_name(name: &str) -> String {
+    name.nfc()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Name for matching, so the same guest is found regardless of spacing,
+/// case, or how accents were typed
+pub fn name_key(name: &str) -> String {
+    normalize_name(name).to_lowercase().nfc().collect()
+}
+
+/// Text to compare search queries against: lowercase, without accents, so
+/// "nguyen" finds "Nguyễn"
+pub fn search_key(text: &str) -> String {
+    normalize_name(text)
+        .to_lowercase()
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect()
+}
+
+/// Generate a random token for links sent to guests
+pub fn new_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(16)
+        .map(char::from)
+        .collect()
+}
+
+/// Letters and digits in short codes, leaving out ones easily mistaken for
+/// each other when typed from paper, like 0 and O
+const SHORT_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// Tries at one length before short codes get a character longer
+const SHORT_CODE_TRIES: usize = 10;
+
+/// Generate a random code for a guest's short link, like "K7QX4M", that
+/// isn't `taken` yet.  Codes get longer if too many tries collide, so this
+/// always finds one.
+pub fn new_short_code(length: usize, taken: &HashSet<String>) -> String {
+    let mut rng = rand::thread_rng();
+    (0..)
+        .map(|attempt| {
+            (0..length.max(1) + attempt / SHORT_CODE_TRIES)
+                .map(|_| {
+                    char::from(SHORT_CODE_ALPHABET[rng.gen_range(0..SHORT_CODE_ALPHABET.len())])
+                })
+                .collect::<String>()
+        })
+        .find(|code| !taken.contains(code))
+        .unwrap()
+}
+
+/// Short code as guests might type it, in any case and with spaces or
+/// dashes
+pub fn normalize_short_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
 }
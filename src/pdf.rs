@@ -0,0 +1,103 @@
+use {
+    crate::config::PageSize,
+    pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str, TextStr},
+};
+
+/// Name of the one font every page uses, Helvetica
+pub const FONT: Name<'static> = Name(b"F1");
+
+/// Page width and height in points, portrait
+pub fn page_size(size: PageSize) -> (f32, f32) {
+    match size {
+        PageSize::Letter => (612.0, 792.0),
+        PageSize::A4 => (595.0, 842.0),
+    }
+}
+
+/// Text as bytes in the PDF's standard WinAnsi encoding, which covers Latin-1
+/// letters, with a `?` for anything else
+pub fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match u32::from(c) {
+            code @ (0x20..=0x7e | 0xa0..=0xff) => code as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// Largest font size up to `size` that fits `text` in `width`, guessing an
+/// average Helvetica character at half the font size
+pub fn fitting_size(text: &str, size: f32, width: f32) -> f32 {
+    let chars = text.chars().count().max(1) as f32;
+    size.min(width / (chars * 0.5))
+}
+
+/// Show a line of text with its baseline at `x`, `y`, shrinking it to fit in
+/// `width`
+pub fn line(content: &mut Content, text: &str, size: f32, x: f32, y: f32, width: f32) {
+    content
+        .begin_text()
+        .set_font(FONT, fitting_size(text, size, width))
+        .next_line(x, y)
+        .show(Str(&win_ansi(text)))
+        .end_text();
+}
+
+/// A PDF document with a page for each content stream, or one blank page if
+/// there are none
+pub fn document(title: &str, size: PageSize, mut pages: Vec<Content>) -> Vec<u8> {
+    if pages.is_empty() {
+        pages.push(Content::new());
+    }
+    let (width, height) = page_size(size);
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let font_id = Ref::new(3);
+    let info_id = Ref::new(4);
+    // each page is followed by its content stream
+    let page_ids = (0..pages.len())
+        .map(|i| Ref::new(5 + 2 * i as i32))
+        .collect::<Vec<_>>();
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id)
+        .kids(page_ids.iter().copied())
+        .count(pages.len() as i32);
+    pdf.type1_font(font_id)
+        .base_font(Name(b"Helvetica"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.document_info(info_id).title(TextStr(title));
+    for (content, page_id) in pages.into_iter().zip(page_ids) {
+        let content_id = Ref::new(page_id.get() + 1);
+        let mut page = pdf.page(page_id);
+        page.parent(page_tree_id)
+            .media_box(Rect::new(0.0, 0.0, width, height))
+            .contents(content_id);
+        page.resources().fonts().pair(FONT, font_id);
+        page.finish();
+        pdf.stream(content_id, &content.finish());
+    }
+    pdf.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        assert_eq!(win_ansi("Zoë ✓"), b"Zo\xeb ?");
+        assert_eq!(fitting_size("Jane", 18.0, 500.0), 18.0);
+        assert_eq!(fitting_size("Jane", 18.0, 10.0), 5.0);
+    }
+
+    #[test]
+    fn blank_document() {
+        let pdf = document("Blank", PageSize::A4, vec![]);
+        let contents = String::from_utf8_lossy(&pdf);
+        assert!(contents.starts_with("%PDF-"));
+        assert!(contents.contains("/Count 1"));
+        assert!(contents.contains("/MediaBox [0 0 595 842]"));
+    }
+}
@@ -0,0 +1,582 @@
+use {
+    crate::{
+        config::{ImageVariant, PhotosConfig, VideoConfig},
+        error::Error,
+    },
+    serde::{Deserialize, Serialize},
+    std::{
+        fs, io,
+        path::{Path, PathBuf},
+        time::UNIX_EPOCH,
+    },
+};
+
+/// File extensions shown in the galleries
+static IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "avif"];
+
+/// Hidden directory of the photos directory where variants are generated, as
+/// `.variants/engagement/ring.jpg.webp`
+static VARIANTS_DIR: &str = ".variants";
+
+/// A subdirectory of the photos directory, like "engagement" or "venue"
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Album {
+    /// Directory name, used in the album's URL
+    pub name: String,
+    /// Name for guests, e.g. "Post wedding" for "post-wedding"
+    pub title: String,
+    /// URL of the first photo
+    pub cover: String,
+    pub count: usize,
+}
+
+/// One photo of an album
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Photo {
+    pub url: String,
+    pub alt: String,
+    /// Variants that have been generated, for a `<picture>` element
+    pub sources: Vec<Source>,
+}
+
+/// A generated variant of a photo
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Source {
+    pub url: String,
+    /// e.g. "image/webp"
+    pub mime: String,
+}
+
+/// A video, played from a file or embedded from YouTube or Vimeo
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Video {
+    pub title: String,
+    /// URL of a file for a `<video>` element
+    pub file: Option<String>,
+    /// URL of the player for an `<iframe>`
+    pub embed: Option<String>,
+}
+impl From<&VideoConfig> for Video {
+    fn from(config: &VideoConfig) -> Self {
+        let embed = embed_url(&config.url);
+        Self {
+            title: config.title.clone(),
+            file: embed.is_none().then(|| config.url.clone()),
+            embed,
+        }
+    }
+}
+
+/// One entry of an album's gallery, either a photo or a video
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct GalleryItem {
+    pub photo: Option<Photo>,
+    pub video: Option<Video>,
+}
+
+/// Every album with at least one photo, by name
+pub fn albums(config: &PhotosConfig) -> io::Result<Vec<Album>> {
+    let entries = match fs::read_dir(&config.dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(error),
+    };
+    let mut names = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            if let Some(name) = entry.file_name().to_str().filter(|n| is_safe(n)) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+    let mut albums = vec![];
+    for name in names {
+        let photos = photos(config, &name)?.unwrap_or_default();
+        if let Some(first) = photos.first() {
+            albums.push(Album {
+                title: title(&name),
+                cover: first.url.clone(),
+                count: photos.len(),
+                name,
+            });
+        }
+    }
+    Ok(albums)
+}
+
+/// The album's photos, by file name, or `None` if there's no such album.
+/// URLs end with the file's version, so browsers can cache them for long.
+pub fn photos(config: &PhotosConfig, album: &str) -> io::Result<Option<Vec<Photo>>> {
+    let dir = Path::new(&config.dir);
+    let files = match files(dir, album)? {
+        Some(files) => files,
+        None => return Ok(None),
+    };
+    let mut photos = vec![];
+    for (i, file) in files.iter().enumerate() {
+        let version = version(&dir.join(album).join(file))?;
+        let sources = config
+            .variants
+            .iter()
+            .filter(|variant| variant_path(dir, album, file, **variant).is_file())
+            .map(|variant| Source {
+                url: format!(
+                    "/photos/{}/{}.{}?v={}",
+                    album,
+                    file,
+                    variant.extension(),
+                    version
+                ),
+                mime: variant.mime().to_string(),
+            })
+            .collect();
+        photos.push(Photo {
+            url: format!("/photos/{}/{}?v={}", album, file, version),
+            alt: format!("{} photo {}", title(album), i + 1),
+            sources,
+        });
+    }
+    Ok(Some(photos))
+}
+
+/// The album's photos with its videos placed among them, or `None` if
+/// there's no such album
+pub fn gallery(config: &PhotosConfig, album: &str) -> io::Result<Option<Vec<GalleryItem>>> {
+    let photos = match photos(config, album)? {
+        Some(photos) => photos,
+        None => return Ok(None),
+    };
+    let mut items = photos
+        .into_iter()
+        .map(|photo| GalleryItem {
+            photo: Some(photo),
+            video: None,
+        })
+        .collect::<Vec<_>>();
+    let mut videos = config
+        .videos
+        .iter()
+        .filter(|video| video.album.as_deref() == Some(album))
+        .rev()
+        .collect::<Vec<_>>();
+    // Inserted from the last, so each goes before the ones after it
+    videos.sort_by_key(|video| std::cmp::Reverse(video.position.unwrap_or(usize::MAX)));
+    let photo_count = items.len();
+    for video in videos {
+        let at = video.position.unwrap_or(photo_count).min(photo_count);
+        items.insert(
+            at,
+            GalleryItem {
+                photo: None,
+                video: Some(video.into()),
+            },
+        );
+    }
+    Ok(Some(items))
+}
+
+/// Videos for the album index, which aren't in any album
+pub fn index_videos(config: &PhotosConfig) -> Vec<Video> {
+    config
+        .videos
+        .iter()
+        .filter(|video| video.album.is_none())
+        .map(Video::from)
+        .collect()
+}
+
+/// The privacy-friendly player URL for a YouTube or Vimeo link, or `None`
+/// for anything else
+fn embed_url(url: &str) -> Option<String> {
+    let rest = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.");
+    let id = |rest: &str| {
+        rest.split(['?', '&', '/', '#'])
+            .next()
+            .filter(|id| {
+                !id.is_empty()
+                    && id
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            })
+            .map(str::to_string)
+    };
+    if let Some(rest) = rest.strip_prefix("youtube.com/watch?") {
+        let id = rest
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("v="))
+            .and_then(id)?;
+        Some(format!("https://www.youtube-nocookie.com/embed/{}", id))
+    } else if let Some(rest) = rest
+        .strip_prefix("youtu.be/")
+        .or_else(|| rest.strip_prefix("youtube.com/embed/"))
+    {
+        Some(format!(
+            "https://www.youtube-nocookie.com/embed/{}",
+            id(rest)?
+        ))
+    } else if let Some(rest) = rest
+        .strip_prefix("vimeo.com/")
+        .or_else(|| rest.strip_prefix("player.vimeo.com/video/"))
+    {
+        Some(format!(
+            "https://player.vimeo.com/video/{}?dnt=1",
+            id(rest)?
+        ))
+    } else {
+        None
+    }
+}
+
+/// Images in an album, by name, or `None` if there's no such album
+fn files(dir: &Path, album: &str) -> io::Result<Option<Vec<String>>> {
+    if !is_safe(album) {
+        return Ok(None);
+    }
+    let entries = match fs::read_dir(dir.join(album)) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    let mut files = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            if let Some(file) = entry.file_name().to_str().filter(|f| is_image(f)) {
+                files.push(file.to_string());
+            }
+        }
+    }
+    files.sort();
+    Ok(Some(files))
+}
+
+/// Where a photo or one of its variants is on disk, if there is one by that
+/// name
+pub fn photo_path(config: &PhotosConfig, album: &str, file: &str) -> Option<PathBuf> {
+    if !(is_safe(album) && is_safe(file) && is_image(file)) {
+        return None;
+    }
+    let dir = Path::new(&config.dir);
+    let original = dir.join(album).join(file);
+    if original.is_file() {
+        return Some(original);
+    }
+    config.variants.iter().find_map(|variant| {
+        let original = file.strip_suffix(&format!(".{}", variant.extension()))?;
+        let path = variant_path(dir, album, original, *variant);
+        path.is_file().then_some(path)
+    })
+}
+
+fn variant_path(dir: &Path, album: &str, file: &str, variant: ImageVariant) -> PathBuf {
+    dir.join(VARIANTS_DIR)
+        .join(album)
+        .join(format!("{}.{}", file, variant.extension()))
+}
+
+/// Changes whenever the file does, from when it was last modified
+fn version(path: &Path) -> io::Result<String> {
+    let modified = fs::metadata(path)?.modified()?;
+    let secs = modified
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Ok(format!("{:x}", secs))
+}
+
+/// Generate each configured variant of every photo that doesn't have one, or
+/// whose photo changed since, returning how many were made.  Slow, so should
+/// be run on a blocking thread.
+#[cfg(feature = "images")]
+pub fn generate_variants(config: &PhotosConfig) -> Result<usize, Error> {
+    let dir = Path::new(&config.dir);
+    let mut generated = 0;
+    for album in albums(config)? {
+        for file in files(dir, &album.name)?.unwrap_or_default() {
+            let original = dir.join(&album.name).join(&file);
+            let modified = fs::metadata(&original)?.modified()?;
+            let mut image = None;
+            for variant in &config.variants {
+                let path = variant_path(dir, &album.name, &file, *variant);
+                let fresh = fs::metadata(&path)
+                    .and_then(|m| m.modified())
+                    .is_ok_and(|m| m >= modified);
+                if fresh {
+                    continue;
+                }
+                if image.is_none() {
+                    image = Some(load(&original, config.variant_max_width)?);
+                }
+                if let Some(image) = &image {
+                    fs::create_dir_all(dir.join(VARIANTS_DIR).join(&album.name))?;
+                    encode(image, &path, *variant)?;
+                    generated += 1;
+                }
+            }
+        }
+    }
+    Ok(generated)
+}
+
+#[cfg(not(feature = "images"))]
+pub fn generate_variants(_config: &PhotosConfig) -> Result<usize, Error> {
+    log::warn!("Built without the images feature, photo variants will not be generated");
+    Ok(0)
+}
+
+/// The photo, scaled down to fit the width if it's wider
+#[cfg(feature = "images")]
+fn load(path: &Path, max_width: u32) -> Result<image::DynamicImage, Error> {
+    let image = image::open(path)?;
+    let image = match max_width > 0 && image.width() > max_width {
+        true => image.resize(max_width, u32::MAX, image::imageops::FilterType::Lanczos3),
+        false => image,
+    };
+    Ok(match image.color().has_alpha() {
+        true => image::DynamicImage::ImageRgba8(image.to_rgba8()),
+        false => image::DynamicImage::ImageRgb8(image.to_rgb8()),
+    })
+}
+
+/// Write the variant to a temporary file first, so a half-written one is
+/// never served
+#[cfg(feature = "images")]
+fn encode(image: &image::DynamicImage, path: &Path, variant: ImageVariant) -> Result<(), Error> {
+    use image::codecs::{avif::AvifEncoder, webp::WebPEncoder};
+    let partial = path.with_extension("partial");
+    let mut writer = io::BufWriter::new(fs::File::create(&partial)?);
+    match variant {
+        ImageVariant::Avif => {
+            image.write_with_encoder(AvifEncoder::new_with_speed_quality(&mut writer, 8, 70))?
+        }
+        ImageVariant::Webp => image.write_with_encoder(WebPEncoder::new_lossless(&mut writer))?,
+    }
+    io::Write::flush(&mut writer)?;
+    drop(writer);
+    fs::rename(partial, path)?;
+    Ok(())
+}
+
+/// Album name for guests, from its directory name
+pub fn title(name: &str) -> String {
+    let words = name.replace(['-', '_'], " ");
+    let mut chars = words.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => words,
+    }
+}
+
+/// Whether a name from a URL stays within its directory, and isn't hidden
+fn is_safe(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
+
+fn is_image(file: &str) -> bool {
+    Path::new(file)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(dir: &Path) -> PhotosConfig {
+        PhotosConfig {
+            dir: dir.to_str().unwrap().to_string(),
+            variants: vec![ImageVariant::Avif, ImageVariant::Webp],
+            ..PhotosConfig::default()
+        }
+    }
+
+    #[test]
+    fn list() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(albums(&config(&dir.path().join("missing")))
+            .unwrap()
+            .is_empty());
+        for (album, file) in [
+            ("post-wedding", "b.JPG"),
+            ("post-wedding", "a.png"),
+            ("post-wedding", "notes.txt"),
+            ("engagement", "ring.webp"),
+            ("empty", "notes.txt"),
+            (".hidden", "x.jpg"),
+        ] {
+            fs::create_dir_all(dir.path().join(album)).unwrap();
+            fs::write(dir.path().join(album).join(file), "").unwrap();
+        }
+        fs::write(dir.path().join("loose.jpg"), "").unwrap();
+        let variants = dir.path().join(".variants/post-wedding");
+        fs::create_dir_all(&variants).unwrap();
+        fs::write(variants.join("a.png.webp"), "").unwrap();
+        let config = config(dir.path());
+
+        let albums = albums(&config).unwrap();
+        let version = |file: &str| version(&dir.path().join(file)).unwrap();
+        assert_eq!(
+            albums,
+            vec![
+                Album {
+                    name: "engagement".to_string(),
+                    title: "Engagement".to_string(),
+                    cover: format!(
+                        "/photos/engagement/ring.webp?v={}",
+                        version("engagement/ring.webp")
+                    ),
+                    count: 1,
+                },
+                Album {
+                    name: "post-wedding".to_string(),
+                    title: "Post wedding".to_string(),
+                    cover: format!(
+                        "/photos/post-wedding/a.png?v={}",
+                        version("post-wedding/a.png")
+                    ),
+                    count: 2,
+                },
+            ]
+        );
+        let photos = photos(&config, "post-wedding").unwrap().unwrap();
+        let a = version("post-wedding/a.png");
+        assert_eq!(
+            photos[0].sources,
+            vec![Source {
+                url: format!("/photos/post-wedding/a.png.webp?v={}", a),
+                mime: "image/webp".to_string(),
+            }]
+        );
+        assert_eq!(
+            photos[1].url,
+            format!(
+                "/photos/post-wedding/b.JPG?v={}",
+                version("post-wedding/b.JPG")
+            )
+        );
+        assert_eq!(photos[1].alt, "Post wedding photo 2");
+        assert!(photos[1].sources.is_empty());
+        assert!(super::photos(&config, "missing").unwrap().is_none());
+        assert!(super::photos(&config, "..").unwrap().is_none());
+
+        assert!(photo_path(&config, "engagement", "ring.webp").is_some());
+        assert_eq!(
+            photo_path(&config, "post-wedding", "a.png.webp"),
+            Some(variants.join("a.png.webp"))
+        );
+        assert!(photo_path(&config, "post-wedding", "b.JPG.webp").is_none());
+        assert!(photo_path(&config, "engagement", "missing.jpg").is_none());
+        assert!(photo_path(&config, "..", "ring.webp").is_none());
+        assert!(photo_path(&config, "engagement", "notes.txt").is_none());
+        assert!(photo_path(&config, "engagement", ".env.jpg").is_none());
+    }
+
+    #[test]
+    fn videos() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("wedding")).unwrap();
+        for file in ["1.jpg", "2.jpg", "3.jpg"] {
+            fs::write(dir.path().join("wedding").join(file), "").unwrap();
+        }
+        let video = |title: &str, url: &str, album: Option<&str>, position| VideoConfig {
+            title: title.to_string(),
+            url: url.to_string(),
+            album: album.map(str::to_string),
+            position,
+        };
+        let config = PhotosConfig {
+            dir: dir.path().to_str().unwrap().to_string(),
+            videos: vec![
+                video("Reel", "/static/reel.mp4", Some("wedding"), None),
+                video(
+                    "Toast",
+                    "https://youtu.be/abc_123?t=4",
+                    Some("wedding"),
+                    Some(1),
+                ),
+                video(
+                    "Vows",
+                    "https://vimeo.com/76979871",
+                    Some("wedding"),
+                    Some(1),
+                ),
+                video(
+                    "Livestream",
+                    "https://www.youtube.com/watch?v=xyz&t=1",
+                    None,
+                    None,
+                ),
+            ],
+            ..PhotosConfig::default()
+        };
+        let items = gallery(&config, "wedding").unwrap().unwrap();
+        let kinds = items
+            .iter()
+            .map(|item| match (&item.photo, &item.video) {
+                (Some(_), _) => "photo".to_string(),
+                (_, Some(video)) => video.title.clone(),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, ["photo", "Toast", "Vows", "photo", "photo", "Reel"]);
+        let videos = items
+            .iter()
+            .filter_map(|item| item.video.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            videos[0].embed.as_deref(),
+            Some("https://www.youtube-nocookie.com/embed/abc_123")
+        );
+        assert_eq!(
+            videos[1].embed.as_deref(),
+            Some("https://player.vimeo.com/video/76979871?dnt=1")
+        );
+        assert_eq!(videos[2].file.as_deref(), Some("/static/reel.mp4"));
+        assert!(videos[2].embed.is_none());
+        assert!(gallery(&config, "missing").unwrap().is_none());
+
+        let index = index_videos(&config);
+        assert_eq!(index.len(), 1);
+        assert_eq!(
+            index[0].embed.as_deref(),
+            Some("https://www.youtube-nocookie.com/embed/xyz")
+        );
+    }
+
+    #[cfg(feature = "images")]
+    #[test]
+    fn generate() {
+        use image::RgbImage;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("venue")).unwrap();
+        RgbImage::from_fn(64, 32, |x, y| image::Rgb([x as u8 * 4, y as u8 * 8, 128]))
+            .save(dir.path().join("venue/hall.png"))
+            .unwrap();
+        let config = PhotosConfig {
+            dir: dir.path().to_str().unwrap().to_string(),
+            variants: vec![ImageVariant::Avif, ImageVariant::Webp],
+            variant_max_width: 16,
+            ..PhotosConfig::default()
+        };
+        assert_eq!(generate_variants(&config).unwrap(), 2);
+        assert_eq!(generate_variants(&config).unwrap(), 0);
+
+        let webp = image::open(dir.path().join(".variants/venue/hall.png.webp")).unwrap();
+        assert_eq!((webp.width(), webp.height()), (16, 8));
+        assert!(dir.path().join(".variants/venue/hall.png.avif").is_file());
+        let photos = photos(&config, "venue").unwrap().unwrap();
+        let mimes = photos[0]
+            .sources
+            .iter()
+            .map(|source| source.mime.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(mimes, vec!["image/avif", "image/webp"]);
+    }
+}
@@ -0,0 +1,698 @@
+use {
+    crate::{
+        analytics::{DayRow, RouteRow},
+        audit::{AuditEntry, EnteredBy},
+        backup::{RecordDiff, Snapshot},
+        calendar::CalendarLinks,
+        childcare,
+        config::{
+            AirportConfig, Audience, Config, ConfirmationConfig, EventConfig, FieldsConfig,
+            HotelConfig, MealConfig, NextStep, ShuttleStop, TravelConfig,
+        },
+        contact::{self, ContactMessage},
+        dedupe::DuplicatePair,
+        dietary::{self, DietaryCount, Severity},
+        form::Form,
+        model::{
+            describe_meal, meal_options, AdminContext, AlbumContext, AnnouncementContext,
+            Attendance, BuildInfo, CheckinContext, CheckinGuest, ConfirmContext, ContactContext,
+            EmailStatus, ErrorContext, FetchContext, Headcount, IndexContext, PageLinks,
+            PhotosContext, PreferencesContext, QuickContext, RecentResponse, RememberedGuest,
+            RsvpFormContext, RsvpModel, ShortLink, SongsContext, StatsContext, TravelContext,
+            VerifyContext, FETCH_FIELDS, MEALS, VERIFY_FIELDS,
+        },
+        photos::{Album, GalleryItem, Photo, Source, Video},
+        quarantine::Held,
+        registry::RegistryCount,
+        shuttle,
+        songs::RankedSong,
+        source::{self, SourceCount},
+        tasks::TaskStatus,
+        thanks::{self, ThanksContext},
+        waves::WaveStats,
+    },
+    chrono::{NaiveDate, TimeZone, Utc},
+    serde_json::{json, Value},
+    std::collections::BTreeMap,
+};
+
+fn sample_album() -> Album {
+    Album {
+        name: "engagement".to_string(),
+        title: "Engagement".to_string(),
+        cover: "/photos/engagement/1.jpg".to_string(),
+        count: 2,
+    }
+}
+
+/// A representative guest, with every field filled in
+pub fn sample_record() -> RsvpModel {
+    let datetime = Utc.with_ymd_and_hms(2023, 5, 28, 18, 15, 0).unwrap();
+    RsvpModel {
+        name: "Jane Guest".to_string(),
+        email: "jane@example.com".to_string(),
+        attending: true,
+        attending_secondary: true,
+        attending_tertiary: false,
+        meal_choice: "Fish".to_string(),
+        dietary_restrictions: "No shellfish".to_string(),
+        plus_one_attending: true,
+        plus_one_name: "John Plusone".to_string(),
+        plus_one_meal_choice: "Veggie".to_string(),
+        plus_one_dietary_restrictions: "Vegetarian".to_string(),
+        comments: "Can't wait!".to_string(),
+        created_at: datetime,
+        updated_at: datetime,
+        email_verified: false,
+        verification_code: "123456".to_string(),
+        email_status: EmailStatus::Ok,
+        token: "SampleToken12345".to_string(),
+        tags: "family".to_string(),
+        announcements_opt_out: false,
+        transactional_opt_out: false,
+        notes: "Seat near the dance floor".to_string(),
+        checked_in_at: None,
+        name_key: "jane guest".to_string(),
+        plus_one_attending_secondary: true,
+        plus_one_attending_tertiary: false,
+        invited: 2,
+        responded_at: Some(datetime),
+        language: "en".to_string(),
+        short_code: "K7QX4M".to_string(),
+        table: "3".to_string(),
+        dietary: "Gluten-free".to_string(),
+        plus_one_dietary: "Vegetarian".to_string(),
+        dietary_severity: Severity::Allergy,
+        plus_one_dietary_severity: Severity::Preference,
+        wave: "A".to_string(),
+        source: Some(source::Source::Web),
+        plus_one_email: "john@example.com".to_string(),
+        needs_lodging: true,
+        lodging_reminded: None,
+        shuttle: "downtown".to_string(),
+        attending_private: true,
+        plus_one_attending_private: false,
+        kids: 2,
+        kids_ages: "4 and 7".to_string(),
+        thank_you_sent: false,
+        gift: "Stand mixer".to_string(),
+    }
+}
+
+fn sample_travel() -> TravelConfig {
+    TravelConfig {
+        hotels: vec![
+            HotelConfig {
+                name: "The Grand Hotel".to_string(),
+                address: "2 Main St, Springfield".to_string(),
+                url: "https://grandhotel.example.com".to_string(),
+                phone: "555-0100".to_string(),
+                block_code: "GUESTWEDDING".to_string(),
+                cutoff: NaiveDate::from_ymd_opt(2023, 6, 1),
+                notes: "Five minutes' walk from the venue".to_string(),
+            },
+            HotelConfig {
+                name: "Springfield Inn".to_string(),
+                cutoff: NaiveDate::from_ymd_opt(2023, 5, 1),
+                ..HotelConfig::default()
+            },
+        ],
+        airports: vec![AirportConfig {
+            name: "Springfield International".to_string(),
+            code: "SPI".to_string(),
+            notes: "30 minutes by car".to_string(),
+        }],
+        parking: "Free parking behind the hall".to_string(),
+        ..TravelConfig::default()
+    }
+}
+
+fn sample_meals() -> BTreeMap<String, MealConfig> {
+    BTreeMap::from([(
+        "Fish".to_string(),
+        MealConfig {
+            label: "Option B: Salmon".to_string(),
+            description: "Pan-seared, with lemon butter and greens".to_string(),
+            allergens: "Fish, dairy".to_string(),
+        },
+    )])
+}
+
+fn sample_shuttle() -> Vec<ShuttleStop> {
+    vec![
+        ShuttleStop {
+            name: "downtown".to_string(),
+            title: "The Grand Hotel".to_string(),
+            time: "3:30pm".to_string(),
+            capacity: 40,
+        },
+        ShuttleStop {
+            name: "airport".to_string(),
+            title: "Springfield International".to_string(),
+            time: "2:45pm".to_string(),
+            capacity: 12,
+        },
+    ]
+}
+
+fn sample_contact_message() -> ContactMessage {
+    ContactMessage {
+        at: Utc.with_ymd_and_hms(2023, 5, 28, 18, 15, 0).unwrap(),
+        request_id: "3f9a1c2e".to_string(),
+        client_ip: "203.0.113.7".to_string(),
+        name: "Jane Guest".to_string(),
+        email: "jane@example.com".to_string(),
+        message: "Is there parking at the venue?".to_string(),
+    }
+}
+
+fn sample_songs() -> Vec<RankedSong> {
+    [
+        ("Dancing Queen", "ABBA", 15, true),
+        ("September", "Earth, Wind & Fire", 9, false),
+        ("Africa", "", 2, false),
+    ]
+    .map(|(title, artist, votes, voted)| RankedSong {
+        key: format!("{}|{}", title.to_lowercase(), artist.to_lowercase()),
+        title: title.to_string(),
+        artist: artist.to_string(),
+        votes,
+        voted,
+    })
+    .to_vec()
+}
+
+fn sample_attendance() -> Attendance {
+    Attendance {
+        attending: 42,
+        attending_secondary: 30,
+        attending_tertiary: 12,
+        attending_private: 14,
+    }
+}
+
+/// Sample context for any page or email template, or `None` if the template
+/// doesn't exist
+pub fn sample_context(name: &str) -> Option<Value> {
+    let admin = "admin@example.com".to_string();
+    let ctx = match name {
+        "fetch.html" => serde_json::to_value(FetchContext {
+            remembered: Some(RememberedGuest {
+                name: "Jane Guest".to_string(),
+                token: "Ab12Cd34Ef56Gh78".to_string(),
+            }),
+            errors: ErrorContext::new(
+                FETCH_FIELDS,
+                vec![("name", "That name was not found, sorry!".to_string())],
+            ),
+        }),
+        "index.html" => serde_json::to_value(IndexContext {
+            admin,
+            contact: false,
+        }),
+        "rsvp.html" => serde_json::to_value(RsvpFormContext {
+            dietary_choices: dietary::checklist(
+                &sample_record().dietary,
+                &FieldsConfig::default().dietary_options,
+            ),
+            plus_one_dietary_choices: dietary::checklist(
+                &sample_record().plus_one_dietary,
+                &FieldsConfig::default().dietary_options,
+            ),
+            dietary_severities: dietary::severities(sample_record().dietary_severity),
+            plus_one_dietary_severities: dietary::severities(
+                sample_record().plus_one_dietary_severity,
+            ),
+            shuttle_stops: shuttle::choices(
+                &sample_shuttle(),
+                &BTreeMap::from([("airport".to_string(), 12)]),
+                &sample_record().shuttle,
+            ),
+            invited_private: true,
+            kids_choices: childcare::choices(6, sample_record().kids),
+            record: sample_record(),
+            sources: vec![],
+            form_action: "/rsvp".to_string(),
+            csrf: String::default(),
+            meals_full: MEALS
+                .iter()
+                .map(|meal| (meal.to_string(), *meal == "Fish"))
+                .collect(),
+            meals: meal_options(&sample_meals()),
+            errors: ErrorContext::new(
+                &Form::new(&FieldsConfig::default()).names(),
+                vec![(
+                    "plus_one_email",
+                    "Please fill in your plus-one's email".to_string(),
+                )],
+            ),
+        }),
+        "error.html" => Ok(json!({
+            "error": "Page not found",
+            "status_code": "404",
+            "request_id": "3f9a1c2e",
+        })),
+        "verify.html" => serde_json::to_value(VerifyContext {
+            name: "Jane Guest".to_string(),
+            errors: ErrorContext::new(
+                VERIFY_FIELDS,
+                vec![("code", "That code does not match, sorry!".to_string())],
+            ),
+        }),
+        "confirm.html" => serde_json::to_value(ConfirmContext {
+            pending_verification: true,
+            ..ConfirmContext::new(
+                sample_record(),
+                &Config {
+                    confirmation: ConfirmationConfig {
+                        attending_message: "We can't wait to celebrate with you!".to_string(),
+                        next_steps: vec![NextStep {
+                            title: "Book a room".to_string(),
+                            text: "Use code JJ2024 for the hotel block".to_string(),
+                            url: "https://hotel.example.com".to_string(),
+                            show_to: Audience::Attending,
+                        }],
+                        ..ConfirmationConfig::default()
+                    },
+                    meals: sample_meals(),
+                    events: vec![EventConfig {
+                        title: "Ceremony & reception".to_string(),
+                        starts_at: NaiveDate::from_ymd_opt(2024, 6, 1)
+                            .and_then(|day| day.and_hms_opt(16, 0, 0)),
+                        ends_at: NaiveDate::from_ymd_opt(2024, 6, 1)
+                            .and_then(|day| day.and_hms_opt(23, 0, 0)),
+                        location: "The Grand Hall, 1 Main St".to_string(),
+                        description: String::default(),
+                    }],
+                    ..Config::default()
+                },
+            )
+        }),
+        "banners.html" => Ok(json!({
+            "banners": [{
+                "index": 0,
+                "text": "Venue parking changed, see the Travel section",
+                "severity": "warning",
+                "expires": "2024-06-01 18:00 UTC",
+                "expired": false,
+                "paths": "/, /photos",
+            }],
+        })),
+        "enter.html" => Ok(json!({
+            "next": "/photos",
+            "has_error": true,
+            "error": "Sorry, that's not the password",
+        })),
+        "partials/flashes.html" => Ok(json!({
+            "flashes": [
+                {"level": "success", "role": "status", "text": "Your RSVP was updated"},
+                {"level": "error", "role": "alert", "text": "Sorry, that code has expired"},
+            ],
+        })),
+        "login.html" => Ok(json!({
+            "next": "/admin/checkin",
+            "has_error": true,
+            "error": "Sorry, that's not the username and password",
+        })),
+        "photos.html" => serde_json::to_value(PhotosContext {
+            admin,
+            albums: vec![sample_album()],
+            videos: vec![Video {
+                title: "Livestream".to_string(),
+                file: None,
+                embed: Some("https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ".to_string()),
+            }],
+        }),
+        "album.html" => serde_json::to_value(AlbumContext {
+            admin,
+            album: sample_album(),
+            items: (1..=2)
+                .map(|i| GalleryItem {
+                    photo: Some(Photo {
+                        url: format!("/photos/engagement/{}.jpg", i),
+                        alt: format!("Engagement photo {}", i),
+                        sources: vec![Source {
+                            url: format!("/photos/engagement/{}.jpg.webp", i),
+                            mime: "image/webp".to_string(),
+                        }],
+                    }),
+                    video: None,
+                })
+                .chain([GalleryItem {
+                    photo: None,
+                    video: Some(Video {
+                        title: "Highlights".to_string(),
+                        file: Some("/static/highlights.mp4".to_string()),
+                        embed: None,
+                    }),
+                }])
+                .collect(),
+        }),
+        "admin.html" => serde_json::to_value(AdminContext {
+            responses: 50,
+            attendance: sample_attendance(),
+            headcount: Headcount {
+                parties: 60,
+                invited: 100,
+                responded_parties: 50,
+                responded: 84,
+                attending: 42,
+                uncertain: 16,
+            },
+            dietary: [
+                ("Vegetarian", 6),
+                ("Vegan", 2),
+                ("Gluten-free", 3),
+                ("Other", 4),
+            ]
+            .map(|(label, count)| DietaryCount {
+                label: label.to_string(),
+                count,
+            })
+            .to_vec(),
+            sources: [
+                ("Web form", 40),
+                ("Paper card", 8),
+                ("Entered by the admin", 2),
+            ]
+            .map(|(label, count)| SourceCount {
+                label: label.to_string(),
+                count,
+            })
+            .to_vec(),
+            registries: [("zola", "Zola", 23), ("amazon", "Amazon", 9)]
+                .map(|(name, title, clicks)| RegistryCount {
+                    name: name.to_string(),
+                    title: title.to_string(),
+                    clicks,
+                })
+                .to_vec(),
+            shuttle: shuttle::counts(&[sample_record()], &sample_shuttle()),
+            childcare: childcare::summary(&[sample_record()]),
+            songs: sample_songs(),
+            quarantine: vec![Held {
+                id: "Xk2pQ9rT4mLw".to_string(),
+                reason: "filled in the hidden field".to_string(),
+                message: sample_contact_message(),
+            }],
+            event_over: true,
+            archive: None,
+            reloads: 0,
+            waves: vec![
+                WaveStats {
+                    wave: "A".to_string(),
+                    parties: 40,
+                    responded_parties: 38,
+                    declined_parties: 6,
+                    attending: 58,
+                    response_rate: 95,
+                },
+                WaveStats {
+                    wave: "B".to_string(),
+                    parties: 20,
+                    responded_parties: 12,
+                    declined_parties: 2,
+                    attending: 16,
+                    response_rate: 60,
+                },
+            ],
+            last_change: "RSVP for Jane Guest at 2023-05-28T18:15:00Z".to_string(),
+            limits_warning: Some(
+                "The guest list has 900 of the 1000 records allowed: \
+                 raise `limits` before new guests are turned away"
+                    .to_string(),
+            ),
+            bad_emails: vec![RsvpModel {
+                email_status: EmailStatus::Bounced,
+                ..sample_record()
+            }],
+            guests: vec![sample_record()],
+            guest_pages: PageLinks {
+                page: 2,
+                pages: 3,
+                total: 120,
+                prev: Some("page=1&per_page=50&sort=name&filter=".to_string()),
+                next: Some("page=3&per_page=50&sort=name&filter=".to_string()),
+            },
+            recent_changes: vec![AuditEntry {
+                at: Utc.with_ymd_and_hms(2023, 5, 28, 18, 15, 0).unwrap(),
+                request_id: "Ab12Cd34".to_string(),
+                action: "RSVP".to_string(),
+                name: "Jane Guest".to_string(),
+                entered_by: EnteredBy::Admin,
+            }],
+            templates: vec!["index.html".to_string(), "email/new_rsvp.txt".to_string()],
+            export_presets: vec!["caterer".to_string()],
+            build: BuildInfo {
+                version: "0.0.1".to_string(),
+                commit: "abc1234".to_string(),
+                built_at: "2023-05-28T18:15:00+00:00".to_string(),
+                uptime_secs: 3600,
+            },
+        }),
+        "announce.html" => serde_json::to_value(AnnouncementContext {
+            segment: "attending".to_string(),
+            segments: vec!["all".to_string(), "attending".to_string()],
+            subject: "Venue changed".to_string(),
+            body: "Hi {name}, the venue changed!".to_string(),
+            recipients: 42,
+            has_preview: true,
+            preview_to: "jane@example.com".to_string(),
+            preview_subject: "Venue changed".to_string(),
+            preview_body: "Hi Jane Guest, the venue changed!".to_string(),
+            ..AnnouncementContext::default()
+        }),
+        "preferences.html" => serde_json::to_value(PreferencesContext {
+            name: "Jane Guest".to_string(),
+            token: "SampleToken12345".to_string(),
+            announcements: false,
+            transactional: true,
+            done: false,
+        }),
+        "quick.html" => serde_json::to_value(QuickContext {
+            headcount: Headcount {
+                parties: 60,
+                invited: 100,
+                responded_parties: 50,
+                responded: 84,
+                attending: 42,
+                uncertain: 16,
+            },
+            declined: 6,
+            recent: vec![RecentResponse::from(&sample_record())],
+            held: 1,
+            bad_emails: 0,
+        }),
+        "checkin.html" => serde_json::to_value(CheckinContext {
+            guests: vec![
+                CheckinGuest::from(&sample_record()),
+                CheckinGuest::from(&RsvpModel {
+                    name: "Sam Early".to_string(),
+                    plus_one_attending: false,
+                    checked_in_at: Some(sample_record().created_at),
+                    ..sample_record()
+                }),
+            ],
+            attending: 2,
+            checked_in: 1,
+        }),
+        "duplicates.html" => Ok(json!({
+            "pairs": [DuplicatePair {
+                first: "Jane Guest".to_string(),
+                first_email: "jane@example.com".to_string(),
+                second: "Jane Gest".to_string(),
+                second_email: String::default(),
+                reason: "similar names (89%)".to_string(),
+            }],
+        })),
+        "busy.html" => Ok(json!({ "retry_after": 5 })),
+        "full.html" => Ok(json!({})),
+        "archived.html" => Ok(json!({})),
+        "stats.html" => serde_json::to_value(StatsContext {
+            parties: 60,
+            responded_parties: 50,
+            attendance: sample_attendance(),
+        }),
+        "contact.html" => serde_json::to_value(ContactContext {
+            name: "Jane Guest".to_string(),
+            email: "jane@example.com".to_string(),
+            errors: ErrorContext::new(
+                contact::FIELDS,
+                vec![("message", "Please fill in a message".to_string())],
+            ),
+            ..ContactContext::default()
+        }),
+        "songs.html" => serde_json::to_value(SongsContext {
+            name: "Jane Guest".to_string(),
+            token: "SampleToken12345".to_string(),
+            songs: sample_songs(),
+            has_error: true,
+            error: "You can add up to 5 songs, but you can still vote for any on the list"
+                .to_string(),
+        }),
+        "thanks.html" => serde_json::to_value(ThanksContext {
+            parties: vec![
+                thanks::ThankYou::from(&sample_record()),
+                thanks::ThankYou::from(&RsvpModel {
+                    name: "Sam Early".to_string(),
+                    plus_one_attending: false,
+                    plus_one_attending_secondary: false,
+                    thank_you_sent: true,
+                    gift: "Picture frame".to_string(),
+                    ..sample_record()
+                }),
+            ],
+            total: 2,
+            sent: 1,
+            event_over: false,
+        }),
+        "travel.html" => serde_json::to_value(TravelContext::new(
+            &sample_travel(),
+            NaiveDate::from_ymd_opt(2023, 5, 28).unwrap(),
+        )),
+        "snapshots.html" => Ok(json!({
+            "snapshots": [Snapshot {
+                name: "rsvp-20230528T181500.000Z-manual.csv".to_string(),
+                taken_at: Utc.with_ymd_and_hms(2023, 5, 28, 18, 15, 0).unwrap(),
+                reason: "manual".to_string(),
+            }],
+        })),
+        "snapshot.html" => Ok(json!({
+            "name": "rsvp-20230528T181500.000Z-manual.csv",
+            "records": 120,
+            "changes": [
+                RecordDiff {
+                    name: "Jane Guest".to_string(),
+                    change: "changed".to_string(),
+                    fields: "attending, meal_choice".to_string(),
+                },
+                RecordDiff {
+                    name: "Sam Early".to_string(),
+                    change: "added".to_string(),
+                    fields: String::default(),
+                },
+            ],
+        })),
+        "tasks.html" => Ok(json!({
+            "tasks": [TaskStatus {
+                name: "snapshot".to_string(),
+                every_secs: 86400,
+                runs: 3,
+                last_run: Some(Utc.with_ymd_and_hms(2023, 5, 28, 18, 15, 0).unwrap()),
+                last_result: "ok".to_string(),
+                next_run: Some(Utc.with_ymd_and_hms(2023, 5, 29, 18, 15, 0).unwrap()),
+            }],
+        })),
+        "links.html" => Ok(json!({
+            "links": [ShortLink {
+                name: "Jane Guest".to_string(),
+                short_url: "https://example.com/i/K7QX4M".to_string(),
+                rsvp_url: "https://example.com/rsvp/SampleToken12345".to_string(),
+            }],
+        })),
+        "analytics.html" => Ok(json!({
+            "enabled": true,
+            "days": [
+                DayRow {
+                    date: NaiveDate::from_ymd_opt(2023, 5, 29).unwrap(),
+                    views: 12,
+                    visitors: 5,
+                    percent: 30,
+                },
+                DayRow {
+                    date: NaiveDate::from_ymd_opt(2023, 5, 28).unwrap(),
+                    views: 40,
+                    visitors: 21,
+                    percent: 100,
+                },
+            ],
+            "routes": [RouteRow {
+                route: "/".to_string(),
+                views: 52,
+                visitors: 26,
+            }],
+        })),
+        "email/new_rsvp.txt" => Ok(json!({
+            "attendance": serde_json::to_string_pretty(&sample_attendance()).unwrap(),
+            "received_at": "Sun, May 28, 2023 at 2:15 PM EDT",
+            "download_url": "https://example.com/admin/rsvps.csv",
+            "rsvp": serde_json::to_string_pretty(&sample_record()).unwrap(),
+        })),
+        "email/write_error.txt" => Ok(json!({
+            "action": "RSVP",
+            "error": "Error with io: disk full",
+            "params": serde_json::to_string_pretty(&sample_record()).unwrap(),
+            "request_id": "Ab12Cd34",
+            "client_ip": "203.0.113.7",
+            "body": "name=Jane+Guest&email=jane%40example.com&attending=true",
+            "logs": "[2023-05-28T18:15:00Z ERROR actix_web_rsvp] Error with io: disk full",
+        })),
+        "email/verification.txt" => Ok(json!({
+            "code": "123456",
+            "calendar": [CalendarLinks {
+                title: "Ceremony & reception".to_string(),
+                google: "https://calendar.google.com/calendar/render?action=TEMPLATE".to_string(),
+                outlook: "https://outlook.live.com/calendar/0/deeplink/compose".to_string(),
+                ics: "https://example.com/calendar/1.ics".to_string(),
+            }],
+        })),
+        "email/contact.txt" => serde_json::to_value(sample_contact_message()),
+        "email/lockout.txt" => Ok(json!({
+            "client_ip": "203.0.113.7",
+            "max_misses": 10,
+            "window_minutes": 60,
+            "locked_until": "Sun, May 28, 2023 at 3:15 PM EDT",
+            "request_id": "Ab12Cd34",
+        })),
+        "email/lodging_reminder.txt" => Ok(json!({
+            "name": "Jane Guest",
+            "hotels": &sample_travel().hotels[..1],
+            "travel_url": "https://example.com/travel",
+        })),
+        "email/plus_one.txt" => Ok(json!({
+            "name": "Jane Guest",
+            "plus_one_name": "John Plusone",
+            "meal": describe_meal(&sample_meals(), "Veggie"),
+            "calendar": [CalendarLinks {
+                title: "Ceremony & reception".to_string(),
+                google: "https://calendar.google.com/calendar/render?action=TEMPLATE".to_string(),
+                outlook: "https://outlook.live.com/calendar/0/deeplink/compose".to_string(),
+                ics: "https://example.com/calendar/1.ics".to_string(),
+            }],
+        })),
+        _ => return None,
+    };
+    // the admin pages' forms carry the session's CSRF token
+    let mut ctx = ctx.ok()?;
+    if let Value::Object(fields) = &mut ctx {
+        fields
+            .entry("csrf")
+            .or_insert_with(|| json!("SampleCsrfToken1"));
+    }
+    Some(ctx)
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            email::{render_email, EMAIL_TEMPLATES},
+            state::{AppState, PAGE_TEMPLATES},
+        },
+    };
+
+    #[test]
+    fn every_template_renders() {
+        let state = AppState::default();
+        for (name, _) in PAGE_TEMPLATES {
+            let ctx = sample_context(name).expect(name);
+            state.tt.render(name, &ctx).expect(name);
+        }
+        for (name, _) in EMAIL_TEMPLATES {
+            let ctx = sample_context(name).expect(name);
+            render_email(name, &ctx).expect(name);
+        }
+        assert!(sample_context("unknown.html").is_none());
+    }
+}
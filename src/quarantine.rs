@@ -0,0 +1,158 @@
+use {
+    crate::{config::ContactConfig, contact::ContactMessage, error::Error},
+    log::warn,
+    rand::{distributions::Alphanumeric, Rng},
+    serde::{Deserialize, Serialize},
+    std::{
+        fs,
+        io::ErrorKind,
+        path::PathBuf,
+        sync::{Mutex, MutexGuard},
+    },
+};
+
+/// Most messages held at once, so a flood of bots can't fill the disk;
+/// the oldest go first
+const MAX_HELD: usize = 500;
+
+/// A message from the contact page that looked like spam, waiting for the
+/// admin to approve or reject it
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Held {
+    /// For the admin's approve and reject buttons
+    pub id: String,
+    /// Why it was taken for spam
+    pub reason: String,
+    #[serde(flatten)]
+    pub message: ContactMessage,
+}
+
+/// Messages held as spam, since a false positive is a guest who never hears
+/// back
+#[derive(Default)]
+pub struct Quarantine {
+    path: Option<PathBuf>,
+    held: Mutex<Vec<Held>>,
+}
+impl Quarantine {
+    /// The messages held so far, or none if they're only kept in memory
+    pub fn open(config: &ContactConfig) -> Result<Self, Error> {
+        let path = config.quarantine.as_ref().map(PathBuf::from);
+        let held = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => serde_json::from_str(&contents)?,
+                Err(error) if error.kind() == ErrorKind::NotFound => vec![],
+                Err(error) => return Err(error.into()),
+            },
+            None => vec![],
+        };
+        Ok(Self {
+            path,
+            held: Mutex::new(held),
+        })
+    }
+
+    /// Hold a message for the admin, returning its id
+    pub fn hold(&self, message: ContactMessage, reason: &str) -> Result<String, Error> {
+        let id = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(12)
+            .map(char::from)
+            .collect::<String>();
+        let mut held = self.lock();
+        held.push(Held {
+            id: id.clone(),
+            reason: reason.to_string(),
+            message,
+        });
+        if held.len() > MAX_HELD {
+            let dropped = held.len() - MAX_HELD;
+            warn!("Dropping the {} oldest message(s) held as spam", dropped);
+            held.drain(..dropped);
+        }
+        self.save(&held)?;
+        Ok(id)
+    }
+
+    /// Every message held, newest first
+    pub fn list(&self) -> Vec<Held> {
+        self.lock().iter().rev().cloned().collect()
+    }
+
+    pub fn get(&self, id: &str) -> Option<Held> {
+        self.lock().iter().find(|held| held.id == id).cloned()
+    }
+
+    /// Stop holding a message, once it's approved or rejected, or `false` if
+    /// there's no such message
+    pub fn remove(&self, id: &str) -> Result<bool, Error> {
+        let mut held = self.lock();
+        let before = held.len();
+        held.retain(|held| held.id != id);
+        if held.len() == before {
+            return Ok(false);
+        }
+        self.save(&held)?;
+        Ok(true)
+    }
+
+    fn save(&self, held: &[Held]) -> Result<(), Error> {
+        if let Some(path) = &self.path {
+            fs::write(path, serde_json::to_string(held)?)?;
+        }
+        Ok(())
+    }
+
+    /// The held messages, even if a request panicked while changing them
+    fn lock(&self) -> MutexGuard<'_, Vec<Held>> {
+        self.held.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the quarantined messages after a panic");
+            poisoned.into_inner()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, chrono::Utc, tempfile::tempdir};
+
+    fn message(name: &str) -> ContactMessage {
+        ContactMessage {
+            at: Utc::now(),
+            request_id: "3f9a1c2e".to_string(),
+            client_ip: "127.0.0.1".to_string(),
+            name: name.to_string(),
+            email: "maria@example.com".to_string(),
+            message: "See https://a.example and https://b.example".to_string(),
+        }
+    }
+
+    #[test]
+    fn hold() {
+        let dir = tempdir().unwrap();
+        let config = ContactConfig {
+            quarantine: Some(dir.path().join("held.json").to_str().unwrap().to_string()),
+            ..ContactConfig::default()
+        };
+        let quarantine = Quarantine::open(&config).unwrap();
+        let first = quarantine.hold(message("Maria"), "3 links").unwrap();
+        let second = quarantine
+            .hold(message("Bot"), "filled in the hidden field")
+            .unwrap();
+        assert_ne!(first, second);
+        let names = |quarantine: &Quarantine| {
+            quarantine
+                .list()
+                .into_iter()
+                .map(|held| held.message.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&quarantine), ["Bot", "Maria"]);
+        assert_eq!(quarantine.get(&first).unwrap().reason, "3 links");
+
+        assert!(quarantine.remove(&second).unwrap());
+        assert!(!quarantine.remove(&second).unwrap());
+        let reopened = Quarantine::open(&config).unwrap();
+        assert_eq!(names(&reopened), ["Maria"]);
+    }
+}
@@ -0,0 +1,56 @@
+use {
+    chrono::{DateTime, Duration, Utc},
+    std::collections::HashMap,
+};
+
+/// Recent attempts by key, like a client's IP address, to turn away anyone
+/// trying too often.  Shared by all workers.
+#[derive(Default)]
+pub struct RateLimiter {
+    attempts: HashMap<String, Vec<DateTime<Utc>>>,
+}
+impl RateLimiter {
+    /// Count an attempt and say whether it's allowed: no more than `max` in
+    /// any `window`, or any number if `max` is 0.  Attempts turned away
+    /// aren't counted, so waiting out the window always works.
+    pub fn allow(&mut self, key: &str, max: usize, window: Duration, now: DateTime<Utc>) -> bool {
+        self.expire(window, now);
+        if max == 0 {
+            return true;
+        }
+        let attempts = self.attempts.entry(key.to_string()).or_default();
+        if attempts.len() >= max {
+            return false;
+        }
+        attempts.push(now);
+        true
+    }
+
+    /// Forget attempts from before the window, and keys with none left
+    fn expire(&mut self, window: Duration, now: DateTime<Utc>) {
+        self.attempts.retain(|_, attempts| {
+            attempts.retain(|at| now - *at < window);
+            !attempts.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        let now = Utc::now();
+        let hour = Duration::hours(1);
+        let mut limiter = RateLimiter::default();
+        assert!(limiter.allow("1.2.3.4", 2, hour, now));
+        assert!(limiter.allow("1.2.3.4", 2, hour, now));
+        assert!(!limiter.allow("1.2.3.4", 2, hour, now));
+        assert!(limiter.allow("5.6.7.8", 2, hour, now));
+        assert!(limiter.allow("1.2.3.4", 0, hour, now));
+        assert!(!limiter.allow("1.2.3.4", 2, hour, now + Duration::minutes(59)));
+        assert!(limiter.allow("1.2.3.4", 2, hour, now + hour));
+        assert_eq!(limiter.attempts.len(), 1);
+    }
+}
@@ -0,0 +1,143 @@
+use {
+    crate::{
+        config::{RegistryConfig, RegistryLink},
+        error::Error,
+    },
+    log::warn,
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::BTreeMap,
+        fs,
+        path::PathBuf,
+        sync::{Mutex, MutexGuard},
+    },
+};
+
+/// Clicks on one registry, for the admin dashboard
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct RegistryCount {
+    pub name: String,
+    pub title: String,
+    pub clicks: u64,
+}
+
+/// How many times guests followed each registry link, by its name
+#[derive(Default)]
+pub struct RegistryClicks {
+    path: Option<PathBuf>,
+    clicks: Mutex<BTreeMap<String, u64>>,
+}
+impl RegistryClicks {
+    /// The clicks saved so far, or none if they're only kept in memory
+    pub fn open(config: &RegistryConfig) -> Result<Self, Error> {
+        let path = config.clicks.as_ref().map(PathBuf::from);
+        let clicks = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => serde_json::from_str(&contents)?,
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => BTreeMap::default(),
+                Err(error) => return Err(error.into()),
+            },
+            None => BTreeMap::default(),
+        };
+        Ok(Self {
+            path,
+            clicks: Mutex::new(clicks),
+        })
+    }
+
+    /// Count a click on a registry, then save the counts
+    pub fn record(&self, name: &str) -> Result<(), Error> {
+        let mut clicks = self.lock();
+        *clicks.entry(name.to_string()).or_default() += 1;
+        if let Some(path) = &self.path {
+            fs::write(path, serde_json::to_string(&*clicks)?)?;
+        }
+        Ok(())
+    }
+
+    /// Clicks on each registry in the config's order, then any no longer in
+    /// it
+    pub fn counts(&self, links: &[RegistryLink]) -> Vec<RegistryCount> {
+        let clicks = self.lock();
+        let mut counts = links
+            .iter()
+            .map(|link| RegistryCount {
+                name: link.name.clone(),
+                title: link.title.clone(),
+                clicks: clicks.get(&link.name).copied().unwrap_or(0),
+            })
+            .collect::<Vec<_>>();
+        for (name, count) in clicks.iter() {
+            if !links.iter().any(|link| &link.name == name) {
+                counts.push(RegistryCount {
+                    name: name.clone(),
+                    title: name.clone(),
+                    clicks: *count,
+                });
+            }
+        }
+        counts
+    }
+
+    /// The counts, even if a request panicked while updating them, since
+    /// they're only for display
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, u64>> {
+        self.clicks.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the registry clicks after a panic");
+            poisoned.into_inner()
+        })
+    }
+}
+
+/// The registry a `/go/{name}` link is for
+pub fn find<'a>(config: &'a RegistryConfig, name: &str) -> Option<&'a RegistryLink> {
+    config.links.iter().find(|link| link.name == name)
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, tempfile::tempdir};
+
+    fn links() -> Vec<RegistryLink> {
+        ["zola", "amazon"]
+            .map(|name| RegistryLink {
+                name: name.to_string(),
+                title: name.to_uppercase(),
+                url: format!("https://{}.example.com", name),
+            })
+            .to_vec()
+    }
+
+    #[test]
+    fn saved_counts() {
+        let dir = tempdir().unwrap();
+        let config = RegistryConfig {
+            links: links(),
+            clicks: Some(dir.path().join("clicks.json").to_str().unwrap().to_string()),
+        };
+        assert_eq!(find(&config, "amazon").unwrap().title, "AMAZON");
+        assert!(find(&config, "target").is_none());
+
+        let clicks = RegistryClicks::open(&config).unwrap();
+        clicks.record("amazon").unwrap();
+        clicks.record("amazon").unwrap();
+        clicks.record("target").unwrap();
+
+        let reopened = RegistryClicks::open(&config).unwrap();
+        let counts = reopened
+            .counts(&config.links)
+            .into_iter()
+            .map(|c| (c.title, c.clicks))
+            .collect::<Vec<_>>();
+        let expected = [("ZOLA", 0), ("AMAZON", 2), ("target", 1)]
+            .map(|(title, clicks)| (title.to_string(), clicks));
+        assert_eq!(counts, expected);
+    }
+
+    #[test]
+    fn in_memory() {
+        let clicks = RegistryClicks::open(&RegistryConfig::default()).unwrap();
+        clicks.record("zola").unwrap();
+        assert_eq!(clicks.counts(&links())[0].clicks, 1);
+    }
+}
@@ -0,0 +1,241 @@
+use {
+    crate::{
+        clock::SystemClock,
+        config::Config,
+        error::Error,
+        language::Language,
+        state::{check_templates, configure_db, templates, AppState, SharedState},
+    },
+    actix_web::{
+        body::MessageBody,
+        dev::{Extensions, ServiceRequest, ServiceResponse},
+        middleware::Next,
+        web, Error as ActixError, HttpMessage,
+    },
+    log::{info, warn},
+    std::{
+        cell::RefCell,
+        collections::BTreeMap,
+        rc::Rc,
+        sync::{Arc, Mutex, MutexGuard},
+    },
+};
+
+/// The latest config, and how many times it's been reloaded
+#[derive(Default)]
+struct Loaded {
+    generation: u64,
+    config: Config,
+}
+
+/// The config file the server was started with, reloaded on SIGHUP or from
+/// the admin dashboard
+#[derive(Default)]
+pub struct Reloader {
+    path: Option<String>,
+    loaded: Mutex<Loaded>,
+}
+impl Reloader {
+    pub fn new(path: Option<String>, config: Config) -> Self {
+        Self {
+            path,
+            loaded: Mutex::new(Loaded {
+                generation: 0,
+                config,
+            }),
+        }
+    }
+
+    /// How many times the config has been reloaded, 0 if it's the one the
+    /// server started with
+    pub fn generation(&self) -> u64 {
+        self.lock().generation
+    }
+
+    /// Look something up in the latest config without copying it
+    pub fn with_latest<T>(&self, f: impl FnOnce(&Config) -> T) -> T {
+        f(&self.lock().config)
+    }
+
+    /// The latest config, with its generation
+    pub fn latest(&self) -> (u64, Config) {
+        let loaded = self.lock();
+        (loaded.generation, loaded.config.clone())
+    }
+
+    /// Read the config file again for requests from now on, if it loads and
+    /// every page still renders with it, returning the sections whose
+    /// changes wait for a restart.  The CSV file's settings are changed here,
+    /// once for every worker, before any of them sees the new generation.
+    pub async fn reload(&self, state: &SharedState) -> Result<Vec<&'static str>, Error> {
+        let path = self.path.as_ref().ok_or_else(|| {
+            Error::Invalid(
+                "The server was started without --config, so there's no file to reload".to_string(),
+            )
+        })?;
+        let config = Config::from_file(path)?;
+        check_templates(templates(&config, Arc::new(SystemClock)).as_ref(), &config)?;
+        let mut db = state.write_db().await?;
+        configure_db(&mut db, &config);
+        let mut loaded = self.lock();
+        let pending = restart_only(&loaded.config, &config);
+        for section in &pending {
+            warn!("Changes to {} take effect after a restart", section);
+        }
+        loaded.generation += 1;
+        loaded.config = config;
+        info!(
+            "Reloaded the config from {}, generation {}",
+            path, loaded.generation
+        );
+        Ok(pending)
+    }
+
+    /// The config, even if a request panicked while reloading it
+    fn lock(&self) -> MutexGuard<'_, Loaded> {
+        self.loaded.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the reloaded config after a panic");
+            poisoned.into_inner()
+        })
+    }
+}
+
+/// Sections that changed but are only read at startup, like the listening
+/// address or what's kept in files shared between workers
+fn restart_only(old: &Config, new: &Config) -> Vec<&'static str> {
+    [
+        ("[server]", old.server != new.server),
+        ("[tls]", old.tls != new.tls),
+        ("[tasks]", old.tasks != new.tasks),
+        ("[access_log]", old.access_log != new.access_log),
+        ("[analytics]", old.analytics != new.analytics),
+        ("[git]", old.git != new.git),
+        ("[s3]", old.s3 != new.s3),
+        ("[archive]", old.archive != new.archive),
+        ("[[banners]]", old.banners != new.banners),
+        ("[bulk_email]", old.bulk_email != new.bulk_email),
+        ("[session]", old.session != new.session),
+        ("hooks.file", old.hooks.file != new.hooks.file),
+        (
+            "contact.quarantine",
+            old.contact.quarantine != new.contact.quarantine,
+        ),
+        ("audit_log", old.audit_log != new.audit_log),
+        (
+            "max_pending_writes",
+            old.max_pending_writes != new.max_pending_writes,
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(section, changed)| changed.then_some(section))
+    .collect()
+}
+
+/// Reload the config whenever the server gets a SIGHUP, as from
+/// `systemctl reload`
+#[cfg(unix)]
+pub fn reload_on_hangup(state: Arc<SharedState>) -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangups = signal(SignalKind::hangup())?;
+    actix_web::rt::spawn(async move {
+        while hangups.recv().await.is_some() {
+            if let Err(error) = state.reloader.reload(&state).await {
+                log::error!(
+                    "Could not reload the config, keeping the last one: {}",
+                    error
+                );
+            }
+        }
+    });
+    Ok(())
+}
+
+/// A state built for the config of some generation
+type Built = (u64, web::Data<AppState<'static>>);
+
+/// A worker's state with the latest config it's seen, for each language
+/// guests picked
+#[derive(Default)]
+pub struct LiveState {
+    current: RefCell<BTreeMap<Option<String>, Built>>,
+}
+
+/// Middleware handing requests the state with the latest config and the
+/// pages in the guest's language, rebuilt once per worker after each reload.
+/// Requests already in progress finish with the state they started with.
+pub async fn current_state(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, ActixError> {
+    let live = req.app_data::<web::Data<LiveState>>().cloned();
+    let started = req.app_data::<web::Data<AppState<'static>>>().cloned();
+    let language = req
+        .extensions()
+        .get::<Language>()
+        .map(|language| language.0.clone());
+    if let (Some(live), Some(started)) = (live, started) {
+        let generation = started.reloader.generation();
+        if generation > 0 || language.is_some() {
+            let seen = live.current.borrow().get(&language).cloned();
+            let state = match seen {
+                Some((seen, state)) if seen == generation => state,
+                _ => {
+                    let (generation, config) = started.reloader.latest();
+                    let config = match generation {
+                        0 => started.config.clone(),
+                        _ => config,
+                    };
+                    let state = started.with_config(config, language.as_deref());
+                    let state = web::Data::new(state);
+                    live.current
+                        .borrow_mut()
+                        .insert(language, (generation, state.clone()));
+                    state
+                }
+            };
+            let mut data = Extensions::new();
+            data.insert(state);
+            req.add_data_container(Rc::new(data));
+        }
+    }
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, std::fs, tempfile::tempdir};
+
+    #[actix_rt::test]
+    async fn reload() {
+        let state = SharedState::default();
+        assert!(matches!(
+            Reloader::default().reload(&state).await,
+            Err(Error::Invalid(_))
+        ));
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("rsvp.toml");
+        fs::write(&path, "[theme]\ncouple_names = \"Jane and John\"\n").unwrap();
+        let config = Config::from_file(path.to_str().unwrap()).unwrap();
+        let reloader = Reloader::new(Some(path.to_str().unwrap().to_string()), config);
+        assert_eq!(reloader.generation(), 0);
+
+        fs::write(
+            &path,
+            "undo_history = 3\n[theme]\ncouple_names = \"Jane and Jim\"\n[server]\nworkers = 4\n",
+        )
+        .unwrap();
+        assert_eq!(reloader.reload(&state).await.unwrap(), ["[server]"]);
+        let (generation, config) = reloader.latest();
+        assert_eq!(generation, 1);
+        assert_eq!(config.theme.couple_names, "Jane and Jim");
+        assert_eq!(state.db.read().await.undo_history, 3);
+
+        fs::write(&path, "[theme\n").unwrap();
+        assert!(matches!(
+            reloader.reload(&state).await,
+            Err(Error::Config(_))
+        ));
+        assert_eq!(reloader.latest().1.theme.couple_names, "Jane and Jim");
+    }
+}
@@ -0,0 +1,268 @@
+use {
+    crate::{config::FeaturesConfig, model::new_token, state::AppState},
+    actix_web::{
+        body::{EitherBody, MessageBody},
+        dev::{Payload, ServiceRequest, ServiceResponse},
+        guard::{self, Guard},
+        http::header::{HeaderName, HeaderValue, FORWARDED, RETRY_AFTER, X_FORWARDED_FOR},
+        middleware::Next,
+        web, Error as ActixError, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+    },
+    log::warn,
+    serde::{Deserialize, Serialize},
+    serde_json::json,
+    std::{
+        fmt,
+        future::{ready, Ready},
+        net::IpAddr,
+        sync::Arc,
+    },
+};
+
+/// Response header with the request's ID, for guests to quote when reporting
+/// a problem
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Random ID given to each request, to find its log lines later
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = ActixError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(request_id(req)))
+    }
+}
+
+/// Middleware giving every request an ID, returned in the `X-Request-Id`
+/// header
+pub async fn assign_request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, ActixError> {
+    let id = new_token()[..8].to_string();
+    req.extensions_mut().insert(RequestId(id.clone()));
+    let mut res = next.call(req).await?;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(REQUEST_ID_HEADER.clone(), value);
+    }
+    Ok(res)
+}
+
+/// Guard for a subsystem's routes, which only match while it's turned on in
+/// the config, so they're not found otherwise
+pub fn feature_enabled(feature: fn(&FeaturesConfig) -> bool) -> impl Guard {
+    guard::fn_guard(move |ctx| {
+        ctx.app_data::<web::Data<AppState<'_>>>()
+            .is_none_or(|state| feature(&state.config.features))
+    })
+}
+
+/// Middleware turning away writes once too many are in progress, with a
+/// 503 and a "busy" page or JSON error
+pub async fn limit_writes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, ActixError> {
+    let state = match req.app_data::<web::Data<AppState<'_>>>() {
+        Some(state) => state.clone(),
+        None => return next.call(req).await.map(|res| res.map_into_left_body()),
+    };
+    let permit = match Arc::clone(&state.write_permits).try_acquire_owned() {
+        Ok(permit) => permit,
+        Err(_) => {
+            warn!("Too many pending writes, turning away {}", req.path());
+            let retry_after = state.config.busy_retry_after_secs;
+            let mut resp = HttpResponse::ServiceUnavailable();
+            resp.insert_header((RETRY_AFTER, retry_after.to_string()));
+            let resp = if req.path().starts_with("/api/") {
+                resp.json(json!({ "error": "busy, try again", "retry_after": retry_after }))
+            } else {
+                let body = state
+                    .tt
+                    .render("busy.html", &json!({ "retry_after": retry_after }))?;
+                resp.content_type("text/html").body(body)
+            };
+            return Ok(req.into_response(resp).map_into_right_body());
+        }
+    };
+    let res = next.call(req).await;
+    drop(permit);
+    res.map(|res| res.map_into_left_body())
+}
+
+/// The request's ID, or a new one if the middleware wasn't used
+pub fn request_id(req: &HttpRequest) -> RequestId {
+    req.extensions()
+        .get::<RequestId>()
+        .cloned()
+        .unwrap_or_else(|| RequestId(new_token()[..8].to_string()))
+}
+
+/// The address of the client that made the request, for keying limits and
+/// lockouts and for logs.  The `Forwarded` and `X-Forwarded-For` headers,
+/// which anyone can set, are only read when the request came through one of
+/// `trusted_proxies`, taking the last address in them that isn't a proxy.
+pub fn client_ip(req: &HttpRequest) -> String {
+    let trusted = req
+        .app_data::<web::Data<AppState<'_>>>()
+        .map(|state| state.config.trusted_proxies.as_slice())
+        .unwrap_or_default();
+    let mut client = req.peer_addr().map(|addr| addr.ip().to_string());
+    if is_trusted(trusted, client.as_deref()) {
+        for addr in forwarded_for(req).into_iter().rev() {
+            client = Some(addr);
+            if !is_trusted(trusted, client.as_deref()) {
+                break;
+            }
+        }
+    }
+    client.unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Whether an address is one of the proxies, with `None` for a unix socket,
+/// trusted as "unix"
+fn is_trusted(trusted: &[String], addr: Option<&str>) -> bool {
+    trusted.iter().any(|proxy| match addr {
+        Some(addr) => match (proxy.parse::<IpAddr>(), addr.parse::<IpAddr>()) {
+            (Ok(proxy), Ok(addr)) => proxy == addr,
+            _ => false,
+        },
+        None => proxy == "unix",
+    })
+}
+
+/// The addresses the request was forwarded for, from the client to the last
+/// proxy, from `Forwarded` or else `X-Forwarded-For`
+fn forwarded_for(req: &HttpRequest) -> Vec<String> {
+    let values = |name| {
+        req.headers()
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>()
+    };
+    let forwarded = values(FORWARDED)
+        .into_iter()
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                key.eq_ignore_ascii_case("for").then(|| strip_port(value))
+            })
+        })
+        .collect::<Vec<_>>();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+    values(X_FORWARDED_FOR)
+        .into_iter()
+        .map(strip_port)
+        .collect()
+}
+
+/// An address from a forwarding header without quotes, IPv6 brackets or a
+/// port, e.g. `"[2001:db8::1]:4711"` as `2001:db8::1`
+fn strip_port(addr: &str) -> String {
+    let addr = addr.trim_matches('"');
+    if let Some(rest) = addr.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or(rest).to_string();
+    }
+    match addr.split_once(':') {
+        Some((ip, port)) if !port.contains(':') => ip.to_string(),
+        _ => addr.to_string(),
+    }
+}
+
+/// Details about a failed request, so the admin can diagnose it and enter
+/// the RSVP by hand
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct RequestContext {
+    pub request_id: String,
+    pub client_ip: String,
+    /// What the guest submitted, form-encoded
+    pub body: String,
+}
+
+impl RequestContext {
+    pub fn new<T: Serialize>(req: &HttpRequest, params: &T) -> Self {
+        Self {
+            request_id: request_id(req).0,
+            client_ip: client_ip(req),
+            body: serde_urlencoded::to_string(params).unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{config::Config, csvdb::CsvDb},
+        actix_web::test::TestRequest,
+    };
+
+    fn state(trusted_proxies: &[&str]) -> web::Data<AppState<'static>> {
+        let config = Config {
+            trusted_proxies: trusted_proxies.iter().map(|p| p.to_string()).collect(),
+            ..Config::default()
+        };
+        web::Data::new(AppState::new_with_config(config, CsvDb::default()))
+    }
+
+    #[test]
+    fn proxied() {
+        let state = state(&["127.0.0.1", "::1"]);
+        let req = |peer: &str, header: (HeaderName, &str)| {
+            TestRequest::get()
+                .app_data(state.clone())
+                .peer_addr(peer.parse().unwrap())
+                .insert_header(header)
+                .to_http_request()
+        };
+        let forwarded = (X_FORWARDED_FOR, "198.51.100.2, 127.0.0.1");
+        assert_eq!(client_ip(&req("127.0.0.1:4000", forwarded)), "198.51.100.2");
+        let forwarded = (FORWARDED, r#"for="[2001:db8::7]:4711";proto=https"#);
+        assert_eq!(client_ip(&req("[::1]:4000", forwarded)), "2001:db8::7");
+
+        // only the proxy's own entry is skipped, not what the client claims
+        let forwarded = (X_FORWARDED_FOR, "10.0.0.1, 203.0.113.7");
+        assert_eq!(client_ip(&req("127.0.0.1:4000", forwarded)), "203.0.113.7");
+
+        // anyone else's headers are ignored
+        let forwarded = (X_FORWARDED_FOR, "198.51.100.2");
+        assert_eq!(
+            client_ip(&req("203.0.113.7:4000", forwarded)),
+            "203.0.113.7"
+        );
+        let req = TestRequest::get()
+            .peer_addr("127.0.0.1:4000".parse().unwrap())
+            .insert_header((X_FORWARDED_FOR, "198.51.100.2"))
+            .to_http_request();
+        assert_eq!(client_ip(&req), "127.0.0.1");
+    }
+
+    #[test]
+    fn unix_socket() {
+        let req = |state: web::Data<AppState<'static>>| {
+            TestRequest::get()
+                .app_data(state)
+                .insert_header((FORWARDED, "for=198.51.100.2"))
+                .to_http_request()
+        };
+        assert_eq!(client_ip(&req(state(&["unix"]))), "198.51.100.2");
+        assert_eq!(client_ip(&req(state(&["127.0.0.1"]))), "unknown");
+        let req = TestRequest::get()
+            .app_data(state(&["unix"]))
+            .to_http_request();
+        assert_eq!(client_ip(&req), "unknown");
+    }
+}
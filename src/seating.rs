@@ -0,0 +1,475 @@
+use {
+    crate::{
+        config::PageSize,
+        dietary::{self, Severity},
+        error::Error,
+        model::RsvpModel,
+    },
+    csv::WriterBuilder,
+    std::{
+        cmp::Ordering,
+        collections::{BTreeMap, BTreeSet},
+    },
+};
+
+/// Table shown for guests coming who haven't been seated yet
+pub const UNASSIGNED: &str = "Unassigned";
+
+/// Meal shown for guests coming who haven't chosen one
+pub const NO_MEAL: &str = "Not chosen";
+
+/// One person at a table, the guest or their plus-one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Seat {
+    pub name: String,
+    pub meal: String,
+    pub dietary_restrictions: String,
+    pub severity: Severity,
+}
+impl Seat {
+    /// Their dietary restrictions, marked if they're more than a preference
+    pub fn restrictions(&self) -> String {
+        match self.severity {
+            Severity::Preference => self.dietary_restrictions.clone(),
+            severity => format!(
+                "{} ({})",
+                self.dietary_restrictions,
+                severity.label().to_lowercase()
+            ),
+        }
+    }
+}
+
+/// Everyone seated at a table, with how many of each meal it needs
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Table {
+    pub name: String,
+    pub seats: Vec<Seat>,
+    pub meals: BTreeMap<String, usize>,
+}
+impl Table {
+    /// Seats with dietary restrictions, for the caterer to flag
+    pub fn restricted(&self) -> impl Iterator<Item = &Seat> {
+        self.seats
+            .iter()
+            .filter(|seat| !seat.dietary_restrictions.is_empty())
+    }
+
+    fn seat(&mut self, name: &str, meal: &str, dietary_restrictions: &str, severity: Severity) {
+        let meal = if meal.is_empty() { NO_MEAL } else { meal };
+        *self.meals.entry(meal.to_string()).or_default() += 1;
+        self.seats.push(Seat {
+            name: name.to_string(),
+            meal: meal.to_string(),
+            dietary_restrictions: dietary_restrictions.trim().to_string(),
+            severity,
+        });
+    }
+}
+
+/// Order tables like people number them, "2" before "10", then named tables
+/// alphabetically, with the unassigned guests last
+fn table_order(a: &str, b: &str) -> Ordering {
+    let key = |table: &str| {
+        let number = table.parse::<u64>().ok();
+        (
+            table == UNASSIGNED,
+            number.is_none(),
+            number,
+            table.to_lowercase(),
+        )
+    };
+    key(a).cmp(&key(b))
+}
+
+/// Guests coming to any event and their plus-ones, grouped by table, each
+/// table's guests by name
+pub fn tables(records: &[RsvpModel]) -> Vec<Table> {
+    let mut records = records
+        .iter()
+        .filter(|r| r.is_attending_any())
+        .collect::<Vec<_>>();
+    records.sort_by_key(|r| r.name_key.clone());
+    let mut tables = BTreeMap::<String, Table>::new();
+    for record in records {
+        let name = match record.table.trim() {
+            "" => UNASSIGNED,
+            table => table,
+        };
+        let table = tables.entry(name.to_string()).or_insert_with(|| Table {
+            name: name.to_string(),
+            ..Table::default()
+        });
+        table.seat(
+            &record.name,
+            &record.meal_choice,
+            &dietary::describe(&record.dietary, &record.dietary_restrictions),
+            record.dietary_severity,
+        );
+        if record.plus_one_attending_any() {
+            let plus_one = if record.plus_one_name.is_empty() {
+                format!("Guest of {}", record.name)
+            } else {
+                record.plus_one_name.clone()
+            };
+            table.seat(
+                &plus_one,
+                &record.plus_one_meal_choice,
+                &dietary::describe(
+                    &record.plus_one_dietary,
+                    &record.plus_one_dietary_restrictions,
+                ),
+                record.plus_one_dietary_severity,
+            );
+        }
+    }
+    let mut tables = tables.into_values().collect::<Vec<_>>();
+    tables.sort_by(|a, b| table_order(&a.name, &b.name));
+    tables
+}
+
+/// CSV with a row per table: its seat count, a column counting each meal,
+/// the dietary restrictions by guest, and everyone seated there
+pub fn csv(tables: &[Table]) -> Result<String, Error> {
+    let meals = tables
+        .iter()
+        .flat_map(|t| t.meals.keys().cloned())
+        .collect::<BTreeSet<_>>();
+    let mut wtr = WriterBuilder::new().from_writer(vec![]);
+    let mut header = vec!["Table".to_string(), "Seats".to_string()];
+    header.extend(meals.iter().cloned());
+    header.push("Dietary restrictions".to_string());
+    header.push("Guests".to_string());
+    wtr.write_record(&header)?;
+    for table in tables {
+        let mut row = vec![table.name.clone(), table.seats.len().to_string()];
+        row.extend(meals.iter().map(|meal| {
+            table
+                .meals
+                .get(meal)
+                .copied()
+                .unwrap_or_default()
+                .to_string()
+        }));
+        row.push(
+            table
+                .restricted()
+                .map(|seat| format!("{}: {}", seat.name, seat.restrictions()))
+                .collect::<Vec<_>>()
+                .join("; "),
+        );
+        row.push(
+            table
+                .seats
+                .iter()
+                .map(|seat| seat.name.as_str())
+                .collect::<Vec<_>>()
+                .join("; "),
+        );
+        wtr.write_record(&row)?;
+    }
+    let bytes = wtr.into_inner().map_err(|e| Error::from(e.into_error()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Space around the page, in points
+#[cfg(feature = "pdf")]
+const MARGIN: f32 = 48.0;
+
+/// A page per table with its meal counts, the guests with dietary
+/// restrictions, and everyone seated there with their meal, continuing onto
+/// more pages for long tables
+#[cfg(feature = "pdf")]
+pub fn pdf(tables: &[Table], page_size: PageSize) -> Result<Option<Vec<u8>>, Error> {
+    use {
+        crate::pdf::{self, line},
+        pdf_writer::Content,
+    };
+
+    let (page_width, page_height) = pdf::page_size(page_size);
+    let width = page_width - 2.0 * MARGIN;
+    let mut pages = vec![];
+    for table in tables {
+        // text, font size, and indent
+        let mut lines = vec![
+            (format!("{} seats", table.seats.len()), 14.0, 0.0),
+            (String::default(), 12.0, 0.0),
+            ("Meals".to_string(), 14.0, 0.0),
+        ];
+        for (meal, count) in &table.meals {
+            lines.push((format!("{}: {}", meal, count), 12.0, 12.0));
+        }
+        if table.restricted().next().is_some() {
+            lines.push((String::default(), 12.0, 0.0));
+            lines.push(("Dietary restrictions".to_string(), 14.0, 0.0));
+            for seat in table.restricted() {
+                let text = format!("{}: {}", seat.name, seat.restrictions());
+                lines.push((text, 12.0, 12.0));
+            }
+        }
+        lines.push((String::default(), 12.0, 0.0));
+        lines.push(("Guests".to_string(), 14.0, 0.0));
+        for seat in &table.seats {
+            let flag = if seat.dietary_restrictions.is_empty() {
+                ""
+            } else {
+                " *"
+            };
+            let text = format!("{}: {}{}", seat.name, seat.meal, flag);
+            lines.push((text, 12.0, 12.0));
+        }
+
+        let heading = match table.name.as_str() {
+            UNASSIGNED => UNASSIGNED.to_string(),
+            name => format!("Table {}", name),
+        };
+        let mut title = heading.clone();
+        let mut lines = lines.into_iter().peekable();
+        while lines.peek().is_some() {
+            let mut content = Content::new();
+            let mut y = page_height - MARGIN - 24.0;
+            line(&mut content, &title, 24.0, MARGIN, y, width);
+            y -= 36.0;
+            while y > MARGIN {
+                let Some((text, size, indent)) = lines.next() else {
+                    break;
+                };
+                if !text.is_empty() {
+                    line(
+                        &mut content,
+                        &text,
+                        size,
+                        MARGIN + indent,
+                        y,
+                        width - indent,
+                    );
+                }
+                y -= size * 1.5;
+            }
+            pages.push(content);
+            title = format!("{} (continued)", heading);
+        }
+    }
+    Ok(Some(pdf::document("Seating", page_size, pages)))
+}
+
+#[cfg(not(feature = "pdf"))]
+pub fn pdf(_tables: &[Table], _page_size: PageSize) -> Result<Option<Vec<u8>>, Error> {
+    log::warn!("Built without the pdf feature, the seating chart can't be printed");
+    Ok(None)
+}
+
+/// Someone with a severe allergy, for the kitchen alert sheet
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alert {
+    pub table: String,
+    pub name: String,
+    pub meal: String,
+    pub dietary_restrictions: String,
+}
+
+/// Everyone coming with a severe allergy, in table order
+pub fn alerts(tables: &[Table]) -> Vec<Alert> {
+    tables
+        .iter()
+        .flat_map(|table| {
+            table
+                .restricted()
+                .filter(|seat| seat.severity == Severity::Severe)
+                .map(|seat| Alert {
+                    table: table.name.clone(),
+                    name: seat.name.clone(),
+                    meal: seat.meal.clone(),
+                    dietary_restrictions: seat.dietary_restrictions.clone(),
+                })
+        })
+        .collect()
+}
+
+/// CSV with a row per severe allergy: table, guest, meal, and restrictions
+pub fn alert_csv(alerts: &[Alert]) -> Result<String, Error> {
+    let mut wtr = WriterBuilder::new().from_writer(vec![]);
+    wtr.write_record(["Table", "Guest", "Meal", "Dietary restrictions"])?;
+    for alert in alerts {
+        wtr.write_record([
+            &alert.table,
+            &alert.name,
+            &alert.meal,
+            &alert.dietary_restrictions,
+        ])?;
+    }
+    let bytes = wtr.into_inner().map_err(|e| Error::from(e.into_error()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// The kitchen alert sheet: one page listing every severe allergy with its
+/// table, squeezing the lines closer together to keep it to one page
+#[cfg(feature = "pdf")]
+pub fn alert_pdf(alerts: &[Alert], page_size: PageSize) -> Result<Option<Vec<u8>>, Error> {
+    use {
+        crate::pdf::{self, line},
+        pdf_writer::Content,
+    };
+
+    let (page_width, page_height) = pdf::page_size(page_size);
+    let width = page_width - 2.0 * MARGIN;
+    let mut content = Content::new();
+    let mut y = page_height - MARGIN - 24.0;
+    line(&mut content, "Kitchen alert", 24.0, MARGIN, y, width);
+    y -= 24.0;
+    let summary = match alerts.len() {
+        0 => "No severe allergies".to_string(),
+        1 => "1 guest with a severe allergy".to_string(),
+        n => format!("{} guests with severe allergies", n),
+    };
+    line(&mut content, &summary, 12.0, MARGIN, y, width);
+    y -= 30.0;
+    let leading = ((y - MARGIN) / alerts.len().max(1) as f32).min(30.0);
+    let size = (leading / 1.5).min(14.0);
+    for alert in alerts {
+        let table = match alert.table.as_str() {
+            UNASSIGNED => UNASSIGNED.to_string(),
+            name => format!("Table {}", name),
+        };
+        let text = format!(
+            "{}: {}, {}, meal {}",
+            table, alert.name, alert.dietary_restrictions, alert.meal
+        );
+        line(&mut content, &text, size, MARGIN, y, width);
+        y -= leading;
+    }
+    Ok(Some(pdf::document(
+        "Kitchen alert",
+        page_size,
+        vec![content],
+    )))
+}
+
+#[cfg(not(feature = "pdf"))]
+pub fn alert_pdf(_alerts: &[Alert], _page_size: PageSize) -> Result<Option<Vec<u8>>, Error> {
+    log::warn!("Built without the pdf feature, the kitchen alert sheet can't be printed");
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{csvdb::test::test_rsvp, model::RsvpParams},
+        chrono::Utc,
+    };
+
+    fn seated(name: &str, table: &str, params: RsvpParams) -> RsvpModel {
+        RsvpModel {
+            table: table.to_string(),
+            ..RsvpModel::new_with_rsvp(
+                &RsvpParams {
+                    name: name.to_string(),
+                    ..params
+                },
+                Utc::now(),
+            )
+        }
+    }
+
+    fn test_records() -> Vec<RsvpModel> {
+        let alone = RsvpParams {
+            plus_one_attending: false,
+            plus_one_attending_secondary: false,
+            dietary_restrictions: String::default(),
+            ..test_rsvp()
+        };
+        let severe = RsvpParams {
+            dietary: "Nut allergy".to_string(),
+            dietary_severity: Severity::Severe,
+            ..alone.clone()
+        };
+        let declined = RsvpParams {
+            attending: false,
+            attending_secondary: false,
+            ..test_rsvp()
+        };
+        vec![
+            seated("Zed", "10", test_rsvp()),
+            seated("Amy", "2", alone.clone()),
+            seated("Bob", "Head", alone.clone()),
+            seated("Cal", "", alone),
+            seated("Dee", "2", declined),
+            seated("Eve", "2", test_rsvp()),
+            seated("Fay", "10", severe),
+        ]
+    }
+
+    #[test]
+    fn grouped_tables() {
+        let tables = tables(&test_records());
+        let names = tables.iter().map(|t| t.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["2", "10", "Head", UNASSIGNED]);
+        let seats = tables[0]
+            .seats
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(seats, vec!["Amy", "Eve", "Johnson"]);
+        assert_eq!(tables[0].meals["Fish"], 2);
+        assert_eq!(tables[0].meals["Veggies"], 1);
+        let restricted = tables[0]
+            .restricted()
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(restricted, vec!["Eve", "Johnson"]);
+    }
+
+    #[test]
+    fn caterer_csv() {
+        let csv = csv(&tables(&test_records())).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "Table,Seats,Fish,Veggies,Dietary restrictions,Guests"
+        );
+        assert_eq!(lines[1], "2,3,2,1,Eve: Yes; Johnson: No,Amy; Eve; Johnson");
+        assert_eq!(lines[4], "Unassigned,1,1,0,,Cal");
+    }
+
+    #[test]
+    fn kitchen_alerts() {
+        let tables = tables(&test_records());
+        let alerts = alerts(&tables);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].table, "10");
+        assert_eq!(alerts[0].name, "Fay");
+        assert_eq!(
+            tables[1].seats[0].restrictions(),
+            "Nut allergy (severe allergy)"
+        );
+        let csv = alert_csv(&alerts).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                "Table,Guest,Meal,Dietary restrictions",
+                "10,Fay,Fish,Nut allergy",
+            ]
+        );
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn page_per_table() {
+        let pdf = pdf(&tables(&test_records()), PageSize::Letter)
+            .unwrap()
+            .unwrap();
+        let contents = String::from_utf8_lossy(&pdf);
+        assert!(contents.contains("/Count 4"));
+        assert!(contents.contains("(Table Head) Tj"));
+        assert!(contents.contains("(Eve: Fish *) Tj"));
+
+        let pdf = alert_pdf(&alerts(&tables(&test_records())), PageSize::A4)
+            .unwrap()
+            .unwrap();
+        let contents = String::from_utf8_lossy(&pdf);
+        assert!(contents.contains("/Count 1"));
+        assert!(contents.contains("(Table 10: Fay, Nut allergy, meal Fish) Tj"));
+    }
+}
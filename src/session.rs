@@ -0,0 +1,254 @@
+use {
+    crate::{
+        config::{AdminConfig, Config},
+        error::Error,
+    },
+    actix_session::{
+        config::{CookieContentSecurity, PersistentSession},
+        storage::CookieSessionStore,
+        Session, SessionMiddleware,
+    },
+    actix_web::cookie::{time::Duration, Cookie, CookieBuilder, Key, SameSite},
+    chrono::{DateTime, Utc},
+    hmac::{Hmac, Mac},
+    log::warn,
+    rand::{distributions::Alphanumeric, Rng},
+    serde::{Deserialize, Serialize},
+    sha2::{Digest, Sha256, Sha512},
+    std::sync::OnceLock,
+};
+
+/// Shortest `session.key` accepted, since it's all that keeps sessions from
+/// being forged
+const MIN_KEY_CHARS: usize = 32;
+
+/// Form field and header the admin pages' forms send their CSRF token in
+pub const CSRF_FIELD: &str = "csrf";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+const CSRF: &str = "csrf";
+const GUEST: &str = "guest";
+const ADMIN: &str = "admin";
+const SITE: &str = "site";
+
+/// How session cookies are made, the same for every worker
+struct Settings {
+    key: Key,
+    cookie_name: String,
+    secure: bool,
+    remember_days: i64,
+}
+impl Settings {
+    fn new(config: &Config) -> Result<Self, Error> {
+        let key = match &config.session.key {
+            Some(key) if key.chars().count() < MIN_KEY_CHARS => {
+                return Err(Error::Startup(format!(
+                    "session.key must be at least {} characters, try `openssl rand -hex 32`",
+                    MIN_KEY_CHARS
+                )))
+            }
+            Some(key) => Key::from(&Sha512::digest(key.as_bytes())),
+            None => Key::generate(),
+        };
+        Ok(Self {
+            key,
+            cookie_name: config.session.cookie_name.clone(),
+            secure: config.session.secure,
+            remember_days: config.session.remember_days,
+        })
+    }
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// Whether the session settings in the config would start, without setting
+/// them up
+pub fn check(config: &Config) -> Result<(), Error> {
+    Settings::new(config).map(|_| ())
+}
+
+/// Set up sessions from the config, once at startup before any worker, so
+/// they all sign and encrypt cookies with the same key
+pub fn init(config: &Config) -> Result<(), Error> {
+    if config.session.key.is_none() {
+        warn!("No session.key set, so everyone is signed out when the server restarts");
+    }
+    let settings = Settings::new(config)?;
+    if SETTINGS.set(settings).is_err() {
+        warn!("Sessions were already set up, keeping the first settings");
+    }
+    Ok(())
+}
+
+/// The settings from startup, or ones with a random key if there weren't
+/// any, like in tests
+fn settings() -> &'static Settings {
+    SETTINGS.get_or_init(|| {
+        Settings::new(&Config::default()).expect("the default config has no session key")
+    })
+}
+
+/// Middleware keeping each browser's session in a cookie, signed and
+/// encrypted so guests can neither read nor change it
+pub fn middleware() -> SessionMiddleware<CookieSessionStore> {
+    let settings = settings();
+    SessionMiddleware::builder(CookieSessionStore::default(), settings.key.clone())
+        .cookie_name(settings.cookie_name.clone())
+        .cookie_secure(settings.secure)
+        .cookie_http_only(true)
+        .cookie_same_site(SameSite::Lax)
+        .cookie_content_security(CookieContentSecurity::Private)
+        .session_lifecycle(
+            PersistentSession::default().session_ttl(Duration::days(settings.remember_days)),
+        )
+        .build()
+}
+
+/// Any other cookie the site keeps in browsers, made like the session's
+pub fn cookie(name: &'static str, value: String) -> CookieBuilder<'static> {
+    Cookie::build(name, value)
+        .path("/")
+        .secure(settings().secure)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+}
+
+/// The session's token for the admin pages' forms, made the first time
+/// it's asked for
+pub fn csrf_token(session: &Session) -> Result<String, Error> {
+    if let Some(token) = session.get::<String>(CSRF)? {
+        return Ok(token);
+    }
+    let token = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect::<String>();
+    session.insert(CSRF, &token)?;
+    Ok(token)
+}
+
+/// Whether a form came with the session's CSRF token, so it was sent from
+/// one of the site's own pages
+pub fn verify_csrf(session: &Session, token: &str) -> bool {
+    match session.get::<String>(CSRF) {
+        Ok(Some(expected)) => {
+            !token.is_empty() && Sha256::digest(expected) == Sha256::digest(token)
+        }
+        _ => false,
+    }
+}
+
+/// Remember the guest whose RSVP this browser looked up, by their RSVP
+/// link's token, so they can come back to it without typing their name
+pub fn remember_guest(session: &Session, token: &str) -> Result<(), Error> {
+    session.insert(GUEST, token)?;
+    Ok(())
+}
+
+/// The token of the guest this browser remembers, if any
+pub fn remembered_guest(session: &Session) -> Option<String> {
+    session.get(GUEST).ok().flatten()
+}
+
+pub fn forget_guest(session: &Session) {
+    session.remove(GUEST);
+}
+
+/// Stand-in for the admin credentials kept in the session, so changing the
+/// password signs every admin out without the password itself being kept
+fn admin_token(config: &AdminConfig) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(config.password.as_bytes())
+        .expect("HMAC takes keys of any size");
+    mac.update(b"admin:");
+    mac.update(config.username.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Sign the admin in on this browser, with a new session so one set before
+/// logging in can't be reused
+pub fn log_in_admin(session: &Session, config: &AdminConfig) -> Result<(), Error> {
+    session.renew();
+    session.insert(ADMIN, admin_token(config))?;
+    Ok(())
+}
+
+/// Whether the admin signed in on this browser with the current credentials
+pub fn is_admin(session: &Session, config: &AdminConfig) -> bool {
+    !config.password.is_empty()
+        && session.get::<String>(ADMIN).ok().flatten() == Some(admin_token(config))
+}
+
+pub fn log_out_admin(session: &Session) {
+    session.remove(ADMIN);
+    session.remove(CSRF);
+    session.renew();
+}
+
+/// A site password entered on this browser, and until when it's remembered
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+struct Entered {
+    token: String,
+    until: DateTime<Utc>,
+}
+
+/// Remember that the site password, by its token, was entered
+pub fn enter_site(session: &Session, token: String, until: DateTime<Utc>) -> Result<(), Error> {
+    session.insert(SITE, Entered { token, until })?;
+    Ok(())
+}
+
+/// The token of the site password entered on this browser, if it's still
+/// remembered
+pub fn entered_site(session: &Session, now: DateTime<Utc>) -> Option<String> {
+    session
+        .get::<Entered>(SITE)
+        .ok()
+        .flatten()
+        .filter(|entered| entered.until > now)
+        .map(|entered| entered.token)
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::config::SessionConfig};
+
+    #[test]
+    fn key() {
+        let config = |key: &str| Config {
+            session: SessionConfig {
+                key: Some(key.to_string()),
+                ..SessionConfig::default()
+            },
+            ..Config::default()
+        };
+        assert!(matches!(
+            Settings::new(&config("too short")),
+            Err(Error::Startup(_))
+        ));
+        let key = "0123456789abcdef0123456789abcdef";
+        let first = Settings::new(&config(key)).unwrap();
+        let second = Settings::new(&config(key)).unwrap();
+        assert_eq!(first.key.master(), second.key.master());
+        assert_eq!(first.remember_days, 90);
+        assert!(first.secure);
+        let plain = Config {
+            session: SessionConfig {
+                secure: false,
+                ..SessionConfig::default()
+            },
+            ..Config::default()
+        };
+        assert!(!Settings::new(&plain).unwrap().secure);
+
+        let admin = AdminConfig {
+            password: "secret".to_string(),
+            ..AdminConfig::default()
+        };
+        let changed = AdminConfig {
+            password: "Secret".to_string(),
+            ..AdminConfig::default()
+        };
+        assert_ne!(admin_token(&admin), admin_token(&changed));
+    }
+}
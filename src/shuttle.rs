@@ -0,0 +1,174 @@
+use {
+    crate::{config::ShuttleStop, error::Error, model::RsvpModel},
+    csv::WriterBuilder,
+    serde::{Deserialize, Serialize},
+    std::collections::BTreeMap,
+};
+
+/// A pickup point on the RSVP form
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ShuttleChoice {
+    pub name: String,
+    pub title: String,
+    pub time: String,
+    pub checked: bool,
+    /// No seats left for the guest, unless they already have one there
+    pub full: bool,
+}
+
+/// The pickup points for the form, given how many riders other parties
+/// have signed up at each, with the guest's own checked
+pub fn choices(
+    stops: &[ShuttleStop],
+    others: &BTreeMap<String, usize>,
+    selected: &str,
+) -> Vec<ShuttleChoice> {
+    stops
+        .iter()
+        .map(|stop| {
+            let taken = others.get(&stop.name).copied().unwrap_or(0);
+            ShuttleChoice {
+                name: stop.name.clone(),
+                title: stop.title.clone(),
+                time: stop.time.clone(),
+                checked: stop.name == selected,
+                full: stop.capacity > 0 && taken >= stop.capacity && stop.name != selected,
+            }
+        })
+        .collect()
+}
+
+/// How full one pickup point is, for the admin
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct StopCount {
+    pub name: String,
+    pub title: String,
+    pub time: String,
+    pub riders: usize,
+    /// 0 for no limit
+    pub capacity: usize,
+}
+
+/// Riders signed up at each stop, in the config's order
+pub fn counts(records: &[RsvpModel], stops: &[ShuttleStop]) -> Vec<StopCount> {
+    stops
+        .iter()
+        .map(|stop| StopCount {
+            name: stop.name.clone(),
+            title: stop.title.clone(),
+            time: stop.time.clone(),
+            riders: riders(records, &stop.name)
+                .map(RsvpModel::shuttle_riders)
+                .sum(),
+            capacity: stop.capacity,
+        })
+        .collect()
+}
+
+/// Parties riding from a stop, by name
+fn riders<'a>(records: &'a [RsvpModel], stop: &'a str) -> impl Iterator<Item = &'a RsvpModel> {
+    records
+        .iter()
+        .filter(move |r| r.shuttle == stop && r.shuttle_riders() > 0)
+}
+
+/// CSV for the transport company with a row per party riding from a stop:
+/// who they are, how many seats they take, and how to reach them
+pub fn manifest(records: &[RsvpModel], stop: &ShuttleStop) -> Result<String, Error> {
+    let mut parties = riders(records, &stop.name).collect::<Vec<_>>();
+    parties.sort_by_key(|r| r.name_key.clone());
+    let mut wtr = WriterBuilder::new().from_writer(vec![]);
+    wtr.write_record(["Name", "Plus-one", "Riders", "Email"])?;
+    for record in parties {
+        let plus_one = match record.plus_one_attending_any() {
+            true => record.plus_one_name.as_str(),
+            false => "",
+        };
+        wtr.write_record([
+            record.name.as_str(),
+            plus_one,
+            &record.shuttle_riders().to_string(),
+            record.email.as_str(),
+        ])?;
+    }
+    let bytes = wtr.into_inner().map_err(|e| Error::from(e.into_error()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            csvdb::test::{test_db, test_rsvp},
+            model::RsvpParams,
+        },
+    };
+
+    fn stops() -> Vec<ShuttleStop> {
+        vec![
+            ShuttleStop {
+                name: "downtown".to_string(),
+                title: "Downtown Hilton".to_string(),
+                time: "3:30pm".to_string(),
+                capacity: 3,
+            },
+            ShuttleStop {
+                name: "airport".to_string(),
+                title: "Airport".to_string(),
+                ..ShuttleStop::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn manifests() {
+        let mut db = test_db(0);
+        db.shuttle_stops = stops();
+        let mut add = |name: &str, shuttle: &str, plus_one: bool| {
+            db.upsert(&RsvpParams {
+                name: name.to_string(),
+                email: format!("{}@example.com", name.to_lowercase()),
+                plus_one_attending: plus_one,
+                plus_one_attending_secondary: plus_one,
+                shuttle: shuttle.to_string(),
+                ..test_rsvp()
+            })
+            .unwrap();
+        };
+        add("Jane", "downtown", true);
+        add("John", "downtown", false);
+        add("Jim", "airport", true);
+        add("Joan", "", true);
+        let records = db.get_all().unwrap();
+        let riders = counts(&records, &stops())
+            .into_iter()
+            .map(|c| (c.name, c.riders))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            riders,
+            [("downtown".to_string(), 3), ("airport".to_string(), 2)]
+        );
+
+        // full for everyone except those already riding from it
+        let full = |name: &str, selected: &str| {
+            choices(&stops(), &db.shuttle_counts(name).unwrap(), selected)
+                .into_iter()
+                .map(|c| (c.checked, c.full))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(full("Joan", ""), [(false, true), (false, false)]);
+        assert_eq!(full("John", "downtown"), [(true, false), (false, false)]);
+
+        assert_eq!(
+            manifest(&records, &stops()[0]).unwrap(),
+            "Name,Plus-one,Riders,Email\n\
+             Jane,Johnson,2,jane@example.com\n\
+             John,,1,john@example.com\n"
+        );
+        assert_eq!(
+            manifest(&records, &stops()[1]).unwrap(),
+            "Name,Plus-one,Riders,Email\nJim,Johnson,2,jim@example.com\n"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use {
+    crate::{lockout, request::client_ip, session, state::AppState},
+    actix_session::{Session, SessionExt},
+    actix_web::{
+        body::{EitherBody, MessageBody},
+        dev::{ServiceRequest, ServiceResponse},
+        http::{header::LOCATION, Method},
+        middleware::Next,
+        web, Error as ActixError, HttpRequest, HttpResponse, HttpResponseBuilder, Result,
+    },
+    chrono::{DateTime, Duration, Utc},
+    hmac::{Hmac, Mac},
+    log::{info, warn},
+    serde::{Deserialize, Serialize},
+    serde_json::json,
+    sha2::{Digest, Sha256},
+};
+
+/// Paths anyone can see without the password, along with everything under
+/// them: what the splash page needs, links from emails, and endpoints with
+/// their own authentication
+static EXEMPT_SECTIONS: &[&str] = &[
+    "/static",
+    "/.well-known",
+    "/unsubscribe",
+    "/webhooks",
+    "/admin",
+    "/api/v1/hooks",
+    "/dav",
+];
+
+/// Exact paths anyone can see without the password: the splash page and
+/// what it needs, and the API's search, behind its own authentication
+static EXEMPT_PATHS: &[&str] = &["/enter", "/theme.css", "/robots.txt", "/api/v1/search"];
+
+/// The API's list, behind its own authentication.  Sending an RSVP to the
+/// same path, or looking a guest up by name under `/api/v1/rsvps/`, still
+/// needs the password.
+static EXEMPT_GETS: &[&str] = &["/api/v1/rsvps"];
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct EnterParams {
+    #[serde(default)]
+    pub password: String,
+    /// Where to go once the password is right
+    #[serde(default)]
+    pub next: String,
+}
+
+/// Shown on the splash page to an IP address locked out for guessing
+static LOCKED_OUT_MESSAGE: &str = "Too many wrong passwords, please try again later";
+
+/// Token kept in the session for a password, so changing the password signs everyone out
+/// without the password itself being stored in browsers
+fn mac(password: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(password.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(b"site password");
+    mac
+}
+
+pub fn token(password: &str) -> String {
+    hex::encode(mac(password).finalize().into_bytes())
+}
+
+/// Whether the session has the token for the password
+fn has_entered(session: &Session, password: &str, now: DateTime<Utc>) -> bool {
+    session::entered_site(session, now)
+        .and_then(|token| hex::decode(token).ok())
+        .is_some_and(|bytes| mac(password).verify_slice(&bytes).is_ok())
+}
+
+fn is_exempt(method: &Method, path: &str) -> bool {
+    EXEMPT_PATHS.contains(&path)
+        || (*method == Method::GET && EXEMPT_GETS.contains(&path))
+        || EXEMPT_SECTIONS.iter().any(|section| {
+            path.strip_prefix(section)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+}
+
+/// Only paths on this site, so the splash page can't send guests elsewhere
+pub fn local_path(next: &str) -> &str {
+    match next.starts_with('/') && !next.starts_with("//") && !next.starts_with("/\\") {
+        true => next,
+        false => "/",
+    }
+}
+
+/// Middleware sending guests to the splash page until they've entered the
+/// site password, if one is set
+pub async fn require_site_password(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, ActixError> {
+    let state = req.app_data::<web::Data<AppState<'_>>>().cloned();
+    let (password, now) = match state {
+        Some(state) => match state.config.site_password.password.clone() {
+            Some(password) => (password, state.clock.now()),
+            None => return next.call(req).await.map(|res| res.map_into_left_body()),
+        },
+        None => return next.call(req).await.map(|res| res.map_into_left_body()),
+    };
+    if is_exempt(req.method(), req.path()) || has_entered(&req.get_session(), &password, now) {
+        return next.call(req).await.map(|res| res.map_into_left_body());
+    }
+    let target = req.uri().path_and_query().map_or("/", |p| p.as_str());
+    let location = format!(
+        "/enter?{}",
+        serde_urlencoded::to_string([("next", target)]).unwrap_or_default()
+    );
+    let res = HttpResponse::SeeOther()
+        .insert_header((LOCATION, location))
+        .finish();
+    Ok(req.into_response(res).map_into_right_body())
+}
+
+/// Return the splash page asking for the site password
+pub async fn enter(
+    state: web::Data<AppState<'_>>,
+    query: web::Query<EnterParams>,
+) -> Result<HttpResponse> {
+    render(&state, HttpResponse::Ok(), local_path(&query.next), None)
+}
+
+/// Check the site password, remembering it in the session if it's right, and
+/// counting wrong ones towards the IP address's lockout
+pub async fn handle_enter(
+    state: web::Data<AppState<'_>>,
+    req: HttpRequest,
+    session: Session,
+    params: web::Form<EnterParams>,
+) -> Result<HttpResponse> {
+    let next = local_path(&params.next);
+    let password = match &state.config.site_password.password {
+        Some(password) => password,
+        None => {
+            return Ok(HttpResponse::SeeOther()
+                .insert_header((LOCATION, next))
+                .finish())
+        }
+    };
+    let ip = client_ip(&req);
+    let now = state.clock.now();
+    if let Some(until) = state.password_lockout.locked_until(&ip, now) {
+        warn!("Refusing the site password from {}, locked out", ip);
+        let res = lockout::too_many_requests(until, now);
+        return render(&state, res, next, Some(LOCKED_OUT_MESSAGE));
+    }
+    if Sha256::digest(&params.password) != Sha256::digest(password) {
+        info!("Wrong site password entered");
+        let config = &state.config.fetch_lockout;
+        if let Some(until) = state.password_lockout.miss(&ip, config, now) {
+            state.lock_out(&req, &ip, &[("next", next)], until).await;
+        }
+        let res = HttpResponse::Unauthorized();
+        return render(&state, res, next, Some("Sorry, that's not the password"));
+    }
+    let until = now + Duration::days(state.config.site_password.remember_days);
+    session::enter_site(&session, token(password), until)?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, next))
+        .finish())
+}
+
+fn render(
+    state: &AppState<'_>,
+    mut res: HttpResponseBuilder,
+    next: &str,
+    error: Option<&str>,
+) -> Result<HttpResponse> {
+    let ctx = json!({
+        "next": next,
+        "has_error": error.is_some(),
+        "error": error.unwrap_or_default(),
+    });
+    let body = state.tt.render("enter.html", &ctx)?;
+    Ok(res.content_type("text/html").body(body))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        let get = |path| is_exempt(&Method::GET, path);
+        assert!(get("/enter"));
+        assert!(get("/static/styles.css"));
+        assert!(get("/admin"));
+        assert!(get("/admin/analytics"));
+        assert!(!get("/administrivia"));
+        assert!(!get("/dav-photos"));
+        assert!(!get("/"));
+        assert!(!get("/photos/venue"));
+        assert!(get("/api/v1/rsvps"));
+        assert!(!is_exempt(&Method::POST, "/api/v1/rsvps"));
+        assert!(get("/api/v1/hooks/1"));
+        assert!(!get("/api/v1/rsvps/John"));
+        assert_eq!(local_path("/photos?page=2"), "/photos?page=2");
+        assert_eq!(local_path("//evil.example"), "/");
+        assert_eq!(local_path("https://evil.example"), "/");
+        assert_eq!(local_path(""), "/");
+        assert_ne!(token("secret"), token("Secret"));
+    }
+}
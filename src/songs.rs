@@ -0,0 +1,251 @@
+use {
+    crate::{config::SongsConfig, error::Error, model::search_key},
+    csv::WriterBuilder,
+    log::warn,
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::BTreeSet,
+        fs,
+        path::PathBuf,
+        sync::{Mutex, MutexGuard},
+    },
+};
+
+/// A requested song, with the tokens of the guests who voted for it,
+/// starting with whoever asked for it
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Song {
+    pub title: String,
+    pub artist: String,
+    pub voters: BTreeSet<String>,
+    /// Token of the guest who first asked for it
+    pub requested_by: String,
+}
+impl Song {
+    /// What makes two requests the same song, ignoring case, accents and
+    /// spacing
+    pub fn key(&self) -> String {
+        song_key(&self.title, &self.artist)
+    }
+}
+
+fn song_key(title: &str, artist: &str) -> String {
+    format!("{}|{}", search_key(title), search_key(artist))
+}
+
+/// A song on the list, as one guest sees it, or for the DJ
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct RankedSong {
+    pub key: String,
+    pub title: String,
+    pub artist: String,
+    pub votes: usize,
+    /// Whether this guest already voted for it
+    pub voted: bool,
+}
+
+/// A song a guest asks for
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SongParams {
+    pub title: String,
+    #[serde(default)]
+    pub artist: String,
+}
+
+/// A guest's vote for a song already on the list, by its key
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct VoteParams {
+    pub song: String,
+}
+
+/// Every song requested so far
+#[derive(Default)]
+pub struct SongRequests {
+    path: Option<PathBuf>,
+    songs: Mutex<Vec<Song>>,
+}
+impl SongRequests {
+    /// The songs saved so far, or none if they're only kept in memory
+    pub fn open(config: &SongsConfig) -> Result<Self, Error> {
+        let path = config.file.as_ref().map(PathBuf::from);
+        let songs = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => serde_json::from_str(&contents)?,
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => vec![],
+                Err(error) => return Err(error.into()),
+            },
+            None => vec![],
+        };
+        Ok(Self {
+            path,
+            songs: Mutex::new(songs),
+        })
+    }
+
+    /// Add a song for a guest, or count it as their vote if someone already
+    /// asked for it, then save the list
+    pub fn request(&self, token: &str, params: &SongParams, max: usize) -> Result<(), Error> {
+        let title = params.title.trim();
+        let artist = params.artist.trim();
+        if title.is_empty() {
+            return Err(Error::Required("the song's title".to_string()));
+        }
+        let mut songs = self.lock();
+        let key = song_key(title, artist);
+        match songs.iter_mut().find(|song| song.key() == key) {
+            Some(song) => {
+                song.voters.insert(token.to_string());
+            }
+            None => {
+                if songs.iter().filter(|s| s.requested_by == token).count() >= max {
+                    return Err(Error::Invalid(format!(
+                        "You can add up to {} songs, but you can still vote for any on the list",
+                        max
+                    )));
+                }
+                songs.push(Song {
+                    title: title.to_string(),
+                    artist: artist.to_string(),
+                    voters: BTreeSet::from([token.to_string()]),
+                    requested_by: token.to_string(),
+                });
+            }
+        }
+        self.save(&songs)
+    }
+
+    /// Count a guest's vote for a song on the list, once however many times
+    /// they vote, or `false` if there's no such song
+    pub fn vote(&self, token: &str, key: &str) -> Result<bool, Error> {
+        let mut songs = self.lock();
+        match songs.iter_mut().find(|song| song.key() == key) {
+            Some(song) => {
+                if song.voters.insert(token.to_string()) {
+                    self.save(&songs)?;
+                }
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// The songs with the most votes first, then by title, marking those the
+    /// guest with the token voted for
+    pub fn ranked(&self, token: Option<&str>) -> Vec<RankedSong> {
+        let mut ranked = self
+            .lock()
+            .iter()
+            .map(|song| RankedSong {
+                key: song.key(),
+                title: song.title.clone(),
+                artist: song.artist.clone(),
+                votes: song.voters.len(),
+                voted: token.is_some_and(|t| song.voters.contains(t)),
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_by(|a, b| b.votes.cmp(&a.votes).then_with(|| a.key.cmp(&b.key)));
+        ranked
+    }
+
+    fn save(&self, songs: &[Song]) -> Result<(), Error> {
+        if let Some(path) = &self.path {
+            fs::write(path, serde_json::to_string(songs)?)?;
+        }
+        Ok(())
+    }
+
+    /// The songs, even if a request panicked while updating them
+    fn lock(&self) -> MutexGuard<'_, Vec<Song>> {
+        self.songs.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the song requests after a panic");
+            poisoned.into_inner()
+        })
+    }
+}
+
+/// CSV for the DJ, most wanted first
+pub fn csv(songs: &[RankedSong]) -> Result<String, Error> {
+    let mut wtr = WriterBuilder::new().from_writer(vec![]);
+    wtr.write_record(["Rank", "Title", "Artist", "Votes"])?;
+    for (rank, song) in songs.iter().enumerate() {
+        wtr.write_record([
+            &(rank + 1).to_string(),
+            song.title.as_str(),
+            song.artist.as_str(),
+            &song.votes.to_string(),
+        ])?;
+    }
+    let bytes = wtr.into_inner().map_err(|e| Error::from(e.into_error()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, tempfile::tempdir};
+
+    fn song(title: &str, artist: &str) -> SongParams {
+        SongParams {
+            title: title.to_string(),
+            artist: artist.to_string(),
+        }
+    }
+
+    #[test]
+    fn votes() {
+        let dir = tempdir().unwrap();
+        let config = SongsConfig {
+            enabled: true,
+            file: Some(dir.path().join("songs.json").to_str().unwrap().to_string()),
+            max_per_guest: 2,
+        };
+        let songs = SongRequests::open(&config).unwrap();
+        songs
+            .request("a", &song("Dancing Queen", "ABBA"), 2)
+            .unwrap();
+        songs
+            .request("b", &song(" dancing  queen", "abba "), 2)
+            .unwrap();
+        songs
+            .request("b", &song("September", "Earth, Wind & Fire"), 2)
+            .unwrap();
+        songs.request("a", &song("Africa", "Toto"), 2).unwrap();
+        assert!(matches!(
+            songs.request("a", &song("Hey Ya!", "Outkast"), 2),
+            Err(Error::Invalid(_))
+        ));
+        // already on the list, so it's a vote
+        songs
+            .request("a", &song("september", "earth, wind & fire"), 2)
+            .unwrap();
+        assert!(matches!(
+            songs.request("c", &song(" ", "Toto"), 2),
+            Err(Error::Required(_))
+        ));
+        let key = song_key("Africa", "Toto");
+        assert!(songs.vote("c", &key).unwrap());
+        assert!(songs.vote("c", &key).unwrap());
+        assert!(!songs.vote("c", "unknown|song").unwrap());
+
+        let reopened = SongRequests::open(&config).unwrap();
+        let ranked = reopened.ranked(Some("b"));
+        let summary = ranked
+            .iter()
+            .map(|s| (s.title.as_str(), s.votes, s.voted))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("Africa", 2, false),
+                ("Dancing Queen", 2, true),
+                ("September", 2, true),
+            ]
+        );
+        assert_eq!(
+            csv(&ranked).unwrap(),
+            "Rank,Title,Artist,Votes\n\
+             1,Africa,Toto,2\n\
+             2,Dancing Queen,ABBA,2\n\
+             3,September,\"Earth, Wind & Fire\",2\n"
+        );
+    }
+}
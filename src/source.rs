@@ -0,0 +1,140 @@
+use {
+    crate::{dietary::Choice, model::RsvpModel},
+    serde::{Deserialize, Serialize},
+};
+
+/// Counted for guests who responded before sources were recorded
+pub const UNKNOWN: &str = "Unknown";
+
+/// How a guest's RSVP arrived, set by whichever path saved it
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// The RSVP form on the site
+    Web,
+    /// Entered by the admin on the guest's behalf, e.g. for an answer by
+    /// phone
+    Admin,
+    /// Entered by the admin from a paper reply card
+    Paper,
+    /// The JSON API
+    Api,
+    /// From the command line, like fake guests from `--seed-fake`
+    Cli,
+    /// Another form tool, like Typeform or a Google Form, through the form
+    /// webhook
+    External,
+}
+impl Source {
+    pub const ALL: [Self; 6] = [
+        Self::Web,
+        Self::Admin,
+        Self::Paper,
+        Self::Api,
+        Self::Cli,
+        Self::External,
+    ];
+
+    /// How the admin can say an answer they're entering arrived
+    pub const ENTERED: [Self; 2] = [Self::Admin, Self::Paper];
+
+    pub fn value(&self) -> &'static str {
+        match self {
+            Self::Web => "web",
+            Self::Admin => "admin",
+            Self::Paper => "paper",
+            Self::Api => "api",
+            Self::Cli => "cli",
+            Self::External => "external",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Web => "Web form",
+            Self::Admin => "Entered by the admin",
+            Self::Paper => "Paper card",
+            Self::Api => "API",
+            Self::Cli => "Command line",
+            Self::External => "Another form tool",
+        }
+    }
+
+    /// The source of an answer the admin entered, who can only pick one of
+    /// `ENTERED`
+    pub fn entered(source: Option<Self>) -> Self {
+        source
+            .filter(|source| Self::ENTERED.contains(source))
+            .unwrap_or(Self::Admin)
+    }
+}
+
+/// The sources the admin can pick from on the RSVP form, with the record's
+/// checked
+pub fn choices(selected: Option<Source>) -> Vec<Choice> {
+    let selected = Source::entered(selected);
+    Source::ENTERED
+        .iter()
+        .map(|source| Choice {
+            value: source.value().to_string(),
+            label: source.label().to_string(),
+            checked: *source == selected,
+        })
+        .collect()
+}
+
+/// How many parties responded through each source
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SourceCount {
+    pub label: String,
+    pub count: usize,
+}
+
+/// How many parties that responded came through each source, leaving out
+/// any no one used, then those from before sources were recorded under
+/// `UNKNOWN`
+pub fn counts(records: &[RsvpModel]) -> Vec<SourceCount> {
+    let responded = records
+        .iter()
+        .filter(|r| r.responded_at.is_some())
+        .collect::<Vec<_>>();
+    let count = |source: Option<Source>| responded.iter().filter(|r| r.source == source).count();
+    Source::ALL
+        .iter()
+        .map(|source| (source.label(), count(Some(*source))))
+        .chain(Some((UNKNOWN, count(None))))
+        .filter(|(_, count)| *count > 0)
+        .map(|(label, count)| SourceCount {
+            label: label.to_string(),
+            count,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::csvdb::test::test_db};
+
+    #[test]
+    fn response_channels() {
+        assert_eq!(Source::entered(Some(Source::Paper)), Source::Paper);
+        assert_eq!(Source::entered(Some(Source::Web)), Source::Admin);
+        assert_eq!(Source::entered(None), Source::Admin);
+        let choices = choices(Some(Source::Paper));
+        assert_eq!(choices.len(), 2);
+        assert!(!choices[0].checked && choices[1].checked);
+
+        let mut records = test_db(4).get_all().unwrap();
+        records[0].source = Some(Source::Paper);
+        records[1].source = None;
+        records[2].source = Some(Source::Web);
+        records[3].responded_at = None;
+        let counts = counts(&records)
+            .into_iter()
+            .map(|c| (c.label, c.count))
+            .collect::<Vec<_>>();
+        let expected = [("Web form", 1), ("Paper card", 1), (UNKNOWN, 1)]
+            .map(|(label, count)| (label.to_string(), count));
+        assert_eq!(counts, expected);
+    }
+}
@@ -1,71 +1,1246 @@
 use {
-    crate::{csvdb::CsvDb, email::Email},
-    std::{fs::OpenOptions, sync::Arc},
+    crate::{
+        access_log::AccessLog,
+        acme::Acme,
+        analytics::Analytics,
+        archive::Archive,
+        audit::{AuditEntry, AuditLog, EnteredBy},
+        backup::Backups,
+        banner::Banners,
+        calendar, childcare,
+        clock::{Clock, SystemClock},
+        config::{Config, TemplateEngine},
+        contact::{self, ContactMessage},
+        csvdb::CsvDb,
+        dietary,
+        email::Email,
+        error::{error_template, Error},
+        export,
+        form::Form,
+        git::GitHistory,
+        hooks::Hooks,
+        idempotency::ReplayCache,
+        language,
+        lockout::{CodeAttempts, FetchLockout, LOCKOUT_ACTION},
+        metrics::Metrics,
+        model::{
+            describe_meal, meal_options, BuildInfo, EmailStatus, ErrorContext, FormFor,
+            RsvpFormContext, RsvpModel, RsvpParams,
+        },
+        preview::sample_context,
+        quarantine::Quarantine,
+        rate_limit::RateLimiter,
+        registry::RegistryClicks,
+        reload::Reloader,
+        request::{request_id, RequestContext},
+        s3::S3Backup,
+        shuttle,
+        songs::SongRequests,
+        source,
+        tasks::Scheduler,
+        throttle::Throttle,
+        timezone::TimeFormats,
+    },
+    actix_web::{http::StatusCode, HttpRequest, HttpResponse, ResponseError},
+    chrono::{DateTime, Duration, Utc},
+    lettre::message::Mailbox,
+    log::{error, info, warn},
+    rand::Rng,
+    serde::Serialize,
+    serde_json::{json, Value},
+    std::{
+        collections::BTreeMap,
+        fs::{self, OpenOptions},
+        ops::Deref,
+        sync::{Arc, Mutex, MutexGuard},
+        time::Duration as StdDuration,
+    },
     tinytemplate::TinyTemplate,
-    tokio::sync::RwLock,
+    tokio::{
+        sync::{RwLock, RwLockReadGuard, RwLockWriteGuard, Semaphore},
+        time::timeout,
+    },
 };
 
+static ADMIN: &str = include_str!("../templates/admin.html");
+static ALBUM: &str = include_str!("../templates/album.html");
+static ANALYTICS: &str = include_str!("../templates/analytics.html");
+static ANNOUNCE: &str = include_str!("../templates/announce.html");
+static ARCHIVED: &str = include_str!("../templates/archived.html");
+static BANNERS: &str = include_str!("../templates/banners.html");
+static BUSY: &str = include_str!("../templates/busy.html");
+static CHECKIN: &str = include_str!("../templates/checkin.html");
+static DUPLICATES: &str = include_str!("../templates/duplicates.html");
+static ENTER: &str = include_str!("../templates/enter.html");
 static ERROR: &str = include_str!("../templates/error.html");
+static FULL: &str = include_str!("../templates/full.html");
 static FETCH: &str = include_str!("../templates/fetch.html");
 static INDEX: &str = include_str!("../templates/index.html");
+static PREFERENCES: &str = include_str!("../templates/preferences.html");
+static QUICK: &str = include_str!("../templates/quick.html");
 static RSVP: &str = include_str!("../templates/rsvp.html");
+static SNAPSHOT: &str = include_str!("../templates/snapshot.html");
+static STATS: &str = include_str!("../templates/stats.html");
+static CONTACT: &str = include_str!("../templates/contact.html");
+static TRAVEL: &str = include_str!("../templates/travel.html");
+static SONGS: &str = include_str!("../templates/songs.html");
+static SNAPSHOTS: &str = include_str!("../templates/snapshots.html");
+static TASKS: &str = include_str!("../templates/tasks.html");
+static THANKS: &str = include_str!("../templates/thanks.html");
+static LINKS: &str = include_str!("../templates/links.html");
+static LOGIN: &str = include_str!("../templates/login.html");
 static CONFIRM: &str = include_str!("../templates/confirm.html");
+static VERIFY: &str = include_str!("../templates/verify.html");
 static PHOTOS: &str = include_str!("../templates/photos.html");
+static FLASHES: &str = include_str!("../templates/partials/flashes.html");
 
-pub struct AppState<'a> {
+/// All page templates, by name
+pub static PAGE_TEMPLATES: &[(&str, &str)] = &[
+    ("fetch.html", FETCH),
+    ("enter.html", ENTER),
+    ("index.html", INDEX),
+    ("rsvp.html", RSVP),
+    ("error.html", ERROR),
+    ("confirm.html", CONFIRM),
+    ("verify.html", VERIFY),
+    ("photos.html", PHOTOS),
+    ("album.html", ALBUM),
+    ("login.html", LOGIN),
+    ("admin.html", ADMIN),
+    ("quick.html", QUICK),
+    ("announce.html", ANNOUNCE),
+    ("preferences.html", PREFERENCES),
+    ("busy.html", BUSY),
+    ("full.html", FULL),
+    ("checkin.html", CHECKIN),
+    ("duplicates.html", DUPLICATES),
+    ("snapshots.html", SNAPSHOTS),
+    ("snapshot.html", SNAPSHOT),
+    ("tasks.html", TASKS),
+    ("links.html", LINKS),
+    ("analytics.html", ANALYTICS),
+    ("banners.html", BANNERS),
+    ("stats.html", STATS),
+    ("contact.html", CONTACT),
+    ("travel.html", TRAVEL),
+    ("songs.html", SONGS),
+    ("thanks.html", THANKS),
+    ("archived.html", ARCHIVED),
+    ("partials/flashes.html", FLASHES),
+];
+
+/// Tera versions of the pages, which share their chrome through inheritance
+/// and partials.  Any page missing here is rendered by TinyTemplate instead.
+#[cfg(feature = "tera")]
+pub static TERA_TEMPLATES: &[(&str, &str)] = &[
+    ("base.html", include_str!("../templates/tera/base.html")),
+    (
+        "partials/nav.html",
+        include_str!("../templates/tera/partials/nav.html"),
+    ),
+    ("index.html", include_str!("../templates/tera/index.html")),
+    ("photos.html", include_str!("../templates/tera/photos.html")),
+    ("album.html", include_str!("../templates/tera/album.html")),
+    ("error.html", include_str!("../templates/tera/error.html")),
+    ("fetch.html", include_str!("../templates/tera/fetch.html")),
+    ("rsvp.html", include_str!("../templates/tera/rsvp.html")),
+    (
+        "confirm.html",
+        include_str!("../templates/tera/confirm.html"),
+    ),
+];
+
+/// Renders a page template by name with a JSON context
+pub trait Templates {
+    fn render(&self, name: &str, ctx: &Value) -> Result<String, Error>;
+}
+
+impl Templates for TinyTemplate<'_> {
+    fn render(&self, name: &str, ctx: &Value) -> Result<String, Error> {
+        TinyTemplate::render(self, name, ctx).map_err(Error::from)
+    }
+}
+
+/// Adds site-wide values, like the theme, to the context of every page
+pub struct WithGlobals<'a> {
+    inner: Box<dyn Templates + 'a>,
+    globals: Value,
+}
+
+impl Templates for WithGlobals<'_> {
+    fn render(&self, name: &str, ctx: &Value) -> Result<String, Error> {
+        match (ctx, &self.globals) {
+            (Value::Object(ctx), Value::Object(globals)) => {
+                let mut ctx = ctx.clone();
+                for (key, value) in globals {
+                    ctx.entry(key).or_insert_with(|| value.clone());
+                }
+                self.inner.render(name, &Value::Object(ctx))
+            }
+            _ => self.inner.render(name, ctx),
+        }
+    }
+}
+
+/// Pages loaded from files named in the config, like error pages, rendered
+/// with TinyTemplate whatever the engine
+pub struct WithFiles<'a> {
+    inner: Box<dyn Templates + 'a>,
+    files: BTreeMap<String, String>,
+    times: TimeFormats,
+}
+
+impl<'a> WithFiles<'a> {
+    /// Load each template, leaving out any that can't be read so the
+    /// built-in page is used instead
+    pub fn new(
+        inner: Box<dyn Templates + 'a>,
+        paths: BTreeMap<String, String>,
+        times: TimeFormats,
+    ) -> Self {
+        let files = paths
+            .into_iter()
+            .filter_map(|(name, path)| match fs::read_to_string(&path) {
+                Ok(contents) => Some((name, contents)),
+                Err(error) => {
+                    error!("Could not read template {}: {}", path, error);
+                    None
+                }
+            })
+            .collect();
+        Self {
+            inner,
+            files,
+            times,
+        }
+    }
+}
+
+impl Templates for WithFiles<'_> {
+    fn render(&self, name: &str, ctx: &Value) -> Result<String, Error> {
+        match self.files.get(name) {
+            Some(contents) => {
+                let mut tt = TinyTemplate::new();
+                self.times.add_formatters(&mut tt);
+                tt.add_template(name, contents)?;
+                TinyTemplate::render(&tt, name, ctx).map_err(Error::from)
+            }
+            None => self.inner.render(name, ctx),
+        }
+    }
+}
+
+#[cfg(feature = "tera")]
+pub struct TeraTemplates<'a> {
+    tera: tera::Tera,
+    fallback: TinyTemplate<'a>,
+}
+
+#[cfg(feature = "tera")]
+impl<'a> TeraTemplates<'a> {
+    pub fn new(times: TimeFormats) -> Result<Self, Error> {
+        let mut tera = tera::Tera::default();
+        tera.add_raw_templates(TERA_TEMPLATES.iter().copied())?;
+        times.register_filters(&mut tera);
+        Ok(Self {
+            tera,
+            fallback: tiny_templates(&times),
+        })
+    }
+}
+
+#[cfg(feature = "tera")]
+impl Templates for TeraTemplates<'_> {
+    fn render(&self, name: &str, ctx: &Value) -> Result<String, Error> {
+        if self.tera.get_template_names().any(|n| n == name) {
+            let ctx = tera::Context::from_value(ctx.clone())?;
+            self.tera.render(name, &ctx).map_err(Error::from)
+        } else {
+            Templates::render(&self.fallback, name, ctx)
+        }
+    }
+}
+
+/// Everything the workers share, built once at startup and handed to each of
+/// them: the CSV file, the config, and the rest of the state kept between
+/// requests
+#[derive(Clone)]
+pub struct SharedState {
     pub test: bool,
+    pub config: Config,
     pub db: Arc<RwLock<CsvDb>>,
-    pub tt: TinyTemplate<'a>,
+    /// Path of the CSV file, for checking the setup while running
+    pub csv: String,
     pub email: Email,
+    pub audit: Arc<AuditLog>,
+    /// Time for handlers, shared with the CSV file
+    pub clock: Arc<dyn Clock>,
+    pub started_at: DateTime<Utc>,
+    /// API responses by idempotency key
+    pub replays: Arc<Mutex<ReplayCache>>,
+    /// Submissions being saved or waiting to be.  Once all are taken, new
+    /// submissions are turned away.
+    pub write_permits: Arc<Semaphore>,
+    /// Background tasks and how they went
+    pub scheduler: Arc<Scheduler>,
+    /// HTTPS certificates and pending ACME challenges, when TLS is
+    /// configured
+    pub acme: Option<Arc<Acme>>,
+    /// Counters for monitoring
+    pub metrics: Arc<Metrics>,
+    /// Line per request, when configured
+    pub access_log: Option<Arc<AccessLog>>,
+    /// Page views, when configured
+    pub analytics: Option<Arc<Analytics>>,
+    /// Notices on the guests' pages
+    pub banners: Arc<Banners>,
+    /// Pacing of bulk emails
+    pub email_throttle: Arc<Throttle>,
+    /// Recent messages from the contact page by IP address
+    pub contact_limiter: Arc<Mutex<RateLimiter>>,
+    /// Messages from the contact page held as spam
+    pub quarantine: Arc<Quarantine>,
+    /// Automations subscribed to new RSVPs
+    pub hooks: Arc<Hooks>,
+    /// Names not found on `/fetch` and the IP addresses locked out for them
+    pub fetch_lockout: Arc<FetchLockout>,
+    /// Wrong verification codes by guest
+    pub code_attempts: Arc<CodeAttempts>,
+    /// Wrong site and admin passwords and the IP addresses locked out for
+    /// them, apart from names so guessing names doesn't lock the admin out
+    pub password_lockout: Arc<FetchLockout>,
+    /// Clicks on each gift registry
+    pub registry_clicks: Arc<RegistryClicks>,
+    /// Songs guests requested and voted for
+    pub songs: Arc<SongRequests>,
+    /// Whether the site is archived and read-only
+    pub archive: Arc<Archive>,
+    /// The config file to reload, and the latest loaded from it
+    pub reloader: Arc<Reloader>,
 }
-impl<'a> Default for AppState<'a> {
+impl Default for SharedState {
     fn default() -> Self {
         Self {
             test: true,
+            config: Config::default(),
             db: Arc::new(RwLock::new(CsvDb::default())),
-            tt: templates(),
+            csv: String::default(),
             email: Email::default(),
+            audit: Arc::default(),
+            clock: Arc::new(SystemClock),
+            started_at: Utc::now(),
+            replays: Arc::default(),
+            write_permits: write_permits(&Config::default()),
+            scheduler: Arc::default(),
+            acme: None,
+            metrics: Arc::default(),
+            access_log: None,
+            analytics: None,
+            banners: Arc::default(),
+            email_throttle: Arc::default(),
+            contact_limiter: Arc::default(),
+            quarantine: Arc::default(),
+            hooks: Arc::default(),
+            fetch_lockout: Arc::default(),
+            code_attempts: Arc::default(),
+            password_lockout: Arc::default(),
+            registry_clicks: Arc::default(),
+            songs: Arc::default(),
+            archive: Arc::default(),
+            reloader: Arc::default(),
         }
     }
 }
-impl<'a> AppState<'a> {
-    pub fn new<'arg>(
-        admin: &'arg str,
-        csv_filename: &'arg str,
-        from: &'arg str,
-        test: bool,
-    ) -> Self {
+impl SharedState {
+    /// The state for a worker, with its own templates
+    pub fn worker(self: &Arc<Self>) -> AppState<'static> {
+        AppState {
+            tt: templates(&self.config, Arc::clone(&self.clock)),
+            shared: Arc::clone(self),
+        }
+    }
+
+    /// Lock the CSV file for reading or writing.  If it isn't available in
+    /// time, whoever holds it is probably stuck, so log it and fail this
+    /// request instead of piling up behind it.
+    pub async fn write_db(&self) -> Result<RwLockWriteGuard<'_, CsvDb>, Error> {
+        let limit = StdDuration::from_millis(self.config.db_lock_timeout_ms);
+        timeout(limit, self.db.write()).await.map_err(|_| {
+            error!(
+                "CSV file still locked after {}ms, a write may be stuck",
+                self.config.db_lock_timeout_ms
+            );
+            Error::Lock
+        })
+    }
+
+    /// Lock the CSV file for lookups that don't change it, alongside other
+    /// lookups, failing like `write_db` if it isn't available in time
+    pub async fn read_db(&self) -> Result<RwLockReadGuard<'_, CsvDb>, Error> {
+        let limit = StdDuration::from_millis(self.config.db_lock_timeout_ms);
+        timeout(limit, self.db.read()).await.map_err(|_| {
+            error!(
+                "CSV file still locked after {}ms, a write may be stuck",
+                self.config.db_lock_timeout_ms
+            );
+            Error::Lock
+        })
+    }
+}
+
+/// A worker's state: its own templates, which aren't `Send`, and everything
+/// it shares with the other workers, which it derefs to
+pub struct AppState<'a> {
+    pub tt: Box<dyn Templates + 'a>,
+    pub shared: Arc<SharedState>,
+}
+impl Deref for AppState<'_> {
+    type Target = SharedState;
+
+    fn deref(&self) -> &SharedState {
+        &self.shared
+    }
+}
+impl AppState<'_> {
+    /// For setting the state up in tests, before anything else holds it,
+    /// failing loudly instead of copying it for just this worker
+    #[cfg(test)]
+    pub fn shared_mut(&mut self) -> &mut SharedState {
+        Arc::get_mut(&mut self.shared).expect("the state is already shared")
+    }
+}
+impl<'a> Default for AppState<'a> {
+    fn default() -> Self {
         Self {
-            test,
-            db: Arc::new(RwLock::new(CsvDb::new(
-                OpenOptions::new()
-                    .read(true)
-                    .write(true)
-                    .create(true)
-                    .open(csv_filename)
-                    .unwrap(),
-            ))),
-            tt: templates(),
-            email: Email::new(from, admin),
+            tt: templates(&Config::default(), Arc::new(SystemClock)),
+            shared: Arc::default(),
+        }
+    }
+}
+impl<'a> AppState<'a> {
+    /// Start building the state from the command line's addresses and CSV
+    /// file and the config
+    pub fn builder() -> AppStateBuilder {
+        AppStateBuilder::default()
+    }
+
+    /// What every worker shares, to build each one's state from
+    pub fn shared(&self) -> Arc<SharedState> {
+        Arc::clone(&self.shared)
+    }
+
+    /// The same state with a reloaded config, for requests from now on, and
+    /// the pages in a language from `[languages.pages]` if given, sharing the
+    /// CSV file, which `Reloader::reload` already set up, and everything else
+    pub fn with_config(&self, config: Config, language: Option<&str>) -> AppState<'a> {
+        AppState {
+            tt: localized_templates(&config, Arc::clone(&self.clock), language),
+            shared: Arc::new(SharedState {
+                email: configured_email(&config, self.email.clone()),
+                config,
+                ..SharedState::clone(&self.shared)
+            }),
+        }
+    }
+
+    pub fn build_info(&self) -> BuildInfo {
+        let built_at = env!("RSVP_BUILD_TIMESTAMP")
+            .parse()
+            .ok()
+            .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0))
+            .map(|built_at| built_at.to_rfc3339())
+            .unwrap_or_default();
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            commit: env!("RSVP_GIT_COMMIT").to_string(),
+            built_at,
+            uptime_secs: (self.clock.now() - self.started_at).num_seconds(),
+        }
+    }
+
+    /// The idempotency cache, even if a request panicked while using it,
+    /// since the worst case is a missed replay
+    pub fn replays(&self) -> MutexGuard<'_, ReplayCache> {
+        self.replays.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the idempotency cache after a panic");
+            poisoned.into_inner()
+        })
+    }
+
+    pub fn contact_limiter(&self) -> MutexGuard<'_, RateLimiter> {
+        self.contact_limiter.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the contact page limits after a panic");
+            poisoned.into_inner()
+        })
+    }
+
+    /// Email a message from the contact page to the admin, keeping it in the
+    /// contact log too if there is one, which is enough if the email fails
+    pub async fn relay_contact(&self, message: &ContactMessage) -> Result<(), Error> {
+        let stored = match &self.config.contact.log {
+            Some(path) => match contact::store(path, message) {
+                Ok(()) => true,
+                Err(error) => {
+                    error!(
+                        "Could not keep the contact message in {}: {:?}",
+                        path, error
+                    );
+                    false
+                }
+            },
+            None => false,
+        };
+        match self.email.send_contact(message, self.test).await {
+            Err(error) if !stored => Err(error),
+            result => {
+                if let Err(error) = result {
+                    error!(
+                        "Could not send the contact message, kept in the log: {:?}",
+                        error
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// An identical RSVP saved by the same guest within the configured window,
+    /// which shouldn't be saved or sent to the admin again
+    pub fn find_duplicate(
+        &self,
+        db: &CsvDb,
+        params: &RsvpParams,
+    ) -> Result<Option<RsvpModel>, Error> {
+        if self.config.duplicate_window_secs <= 0 {
+            return Ok(None);
+        }
+        let window = Duration::seconds(self.config.duplicate_window_secs);
+        let record = db.find_duplicate(params, window)?;
+        if let Some(record) = &record {
+            info!("Duplicate RSVP from {}, not saving again", record.name);
+        }
+        Ok(record)
+    }
+
+    /// Pass through the result of a write, first emailing the admin with the
+    /// request details if it failed, so every failed write can be redone by
+    /// hand
+    pub async fn notify_on_failure<T, P: Serialize>(
+        &self,
+        req: &HttpRequest,
+        action: &str,
+        params: &P,
+        result: Result<T, Error>,
+    ) -> Result<T, Error> {
+        if let Err(error) = &result {
+            if matches!(error, Error::Full) {
+                // already on the dashboard, and spam would flood the inbox
+                return result;
+            }
+            let context = RequestContext::new(req, params);
+            if let Err(send_error) = self
+                .email
+                .send_write_error(action, error, params, &context, self.test)
+                .await
+            {
+                error!(
+                    "Could not send error email: {:?}, original error: {:?}",
+                    send_error, error
+                );
+            }
+        }
+        result
+    }
+
+    /// Deal with an IP address that just got locked out for guessing names,
+    /// codes or passwords: log and count it, note it in the audit log, and
+    /// email the admin if asked to
+    pub async fn lock_out<P: Serialize>(
+        &self,
+        req: &HttpRequest,
+        ip: &str,
+        params: &P,
+        locked_until: DateTime<Utc>,
+    ) {
+        let config = &self.config.fetch_lockout;
+        let context = RequestContext {
+            client_ip: ip.to_string(),
+            ..RequestContext::new(req, params)
+        };
+        warn!(
+            "Locking {} out until {}, after more than {} wrong names, codes or passwords",
+            context.client_ip, locked_until, config.max_misses
+        );
+        self.metrics.increment("rsvp_fetch_lockouts_total", &[]);
+        self.audit(req, LOCKOUT_ACTION, &context.client_ip, EnteredBy::Guest);
+        if config.alert {
+            if let Err(error) = self
+                .email
+                .send_lockout(config, &context, locked_until, self.test)
+                .await
+            {
+                error!("Could not send the lockout email: {:?}", error);
+            }
+        }
+    }
+
+    /// Render the RSVP form for a guest, marking any meals they can no
+    /// longer choose, and the problems with what they submitted by field
+    pub fn render_rsvp_form(
+        &self,
+        db: &CsvDb,
+        mut record: RsvpModel,
+        form_for: FormFor<'_>,
+        problems: Vec<(&str, Error)>,
+    ) -> Result<String, Error> {
+        let meals_full = db.meals_full(&record.name)?;
+        for (_, error) in &problems {
+            if let Error::MealFull(meal) = error {
+                if &record.meal_choice == meal {
+                    record.meal_choice.clear();
+                }
+                if &record.plus_one_meal_choice == meal {
+                    record.plus_one_meal_choice.clear();
+                }
+            }
+            if let Error::ShuttleFull(_) = error {
+                record.shuttle.clear();
+            }
+        }
+        let errors = ErrorContext::new(
+            &Form::new(&self.config.fields).names(),
+            problems
+                .into_iter()
+                .map(|(field, error)| (field, error.to_string()))
+                .collect(),
+        );
+        let shuttle_stops = shuttle::choices(
+            &self.config.shuttle.stops,
+            &db.shuttle_counts(&record.name)?,
+            &record.shuttle,
+        );
+        let options = &self.config.fields.dietary_options;
+        let ctx = serde_json::to_value(RsvpFormContext {
+            dietary_choices: dietary::checklist(&record.dietary, options),
+            plus_one_dietary_choices: dietary::checklist(&record.plus_one_dietary, options),
+            dietary_severities: dietary::severities(record.dietary_severity),
+            plus_one_dietary_severities: dietary::severities(record.plus_one_dietary_severity),
+            sources: match form_for {
+                FormFor::Guest => vec![],
+                FormFor::Admin { .. } => source::choices(record.source),
+            },
+            shuttle_stops,
+            invited_private: db.invited_to_private(&record.name)?,
+            kids_choices: match db.has_children(&record.name)? {
+                true => childcare::choices(self.config.childcare.max_kids, record.kids),
+                false => vec![],
+            },
+            record,
+            form_action: form_for.action().to_string(),
+            csrf: match form_for {
+                FormFor::Guest => String::default(),
+                FormFor::Admin { csrf } => csrf.to_string(),
+            },
+            meals_full,
+            meals: meal_options(&self.config.meals),
+            errors,
+        })?;
+        self.tt.render("rsvp.html", &ctx)
+    }
+
+    /// Show the RSVP form again with what the guest submitted and why it
+    /// couldn't be saved, with the field each problem is about
+    pub fn rsvp_form_error(
+        &self,
+        db: &CsvDb,
+        params: &RsvpParams,
+        form_for: FormFor<'_>,
+        problems: Vec<(&str, Error)>,
+    ) -> Result<HttpResponse, Error> {
+        let record = RsvpModel::new_with_rsvp(params, db.now());
+        let status = problems
+            .first()
+            .map_or(StatusCode::OK, |(_, error)| error.status_code());
+        let body = self.render_rsvp_form(db, record, form_for, problems)?;
+        Ok(HttpResponse::build(status)
+            .content_type("text/html")
+            .body(body))
+    }
+
+    /// Add a change to a guest's record to the audit log, which shouldn't
+    /// stop the change if it fails
+    pub fn audit(&self, req: &HttpRequest, action: &str, name: &str, entered_by: EnteredBy) {
+        let entry = AuditEntry {
+            at: self.clock.now(),
+            request_id: request_id(req).to_string(),
+            action: action.to_string(),
+            name: name.to_string(),
+            entered_by,
+        };
+        if let Err(error) = self.audit.record(&entry) {
+            error!("Could not write to the audit log: {:?}, {:?}", error, entry);
+        }
+    }
+
+    /// If the guest's email needs verifying, store a verification code on the
+    /// record and send it to the guest's email, reusing any code that's
+    /// already pending, along with calendar links for the events they're
+    /// coming to
+    pub async fn start_verification(
+        &self,
+        db: &mut CsvDb,
+        record: RsvpModel,
+    ) -> Result<RsvpModel, Error> {
+        let needed = self.config.verifies_email()
+            && !record.email_verified
+            && !record.email.is_empty()
+            && record.email_status == EmailStatus::Ok;
+        if !needed {
+            return Ok(record);
+        }
+        let code = if record.verification_code.is_empty() {
+            self.code_attempts.reset(&record.name_key);
+            format!("{:06}", rand::thread_rng().gen_range(0..1_000_000))
+        } else {
+            record.verification_code.clone()
+        };
+        let record = db
+            .update_with(&record.name, |r| r.verification_code = code.clone())?
+            .unwrap_or(record);
+        if let Err(error) = self
+            .email
+            .send_verification(
+                &record,
+                &code,
+                &calendar::links(&self.config, &record),
+                self.test,
+            )
+            .await
+        {
+            error!("Could not send verification email: {:?}", error);
+        }
+        Ok(record)
+    }
+
+    /// Let the plus-one know they're coming, if they have their own address
+    pub async fn confirm_plus_one(&self, record: &RsvpModel) {
+        if let Err(error) = self
+            .email
+            .send_plus_one_confirmation(
+                record,
+                &calendar::plus_one_links(&self.config, record),
+                &describe_meal(&self.config.meals, &record.plus_one_meal_choice),
+                self.test,
+            )
+            .await
+        {
+            error!("Could not send plus-one confirmation email: {:?}", error);
         }
     }
 
     #[cfg(test)]
     pub fn new_with_db(db: CsvDb) -> Self {
         Self {
-            db: Arc::new(RwLock::new(db)),
+            shared: Arc::new(SharedState {
+                clock: Arc::clone(&db.clock),
+                started_at: db.now(),
+                db: Arc::new(RwLock::new(db)),
+                ..SharedState::default()
+            }),
             ..Self::default()
         }
     }
+
+    #[cfg(test)]
+    pub fn new_with_config(config: Config, mut db: CsvDb) -> Self {
+        configure_db(&mut db, &config);
+        let tt = templates(&config, Arc::clone(&db.clock));
+        let state = Self::new_with_db(db);
+        Self {
+            tt,
+            shared: Arc::new(SharedState {
+                write_permits: write_permits(&config),
+                scheduler: Arc::new(Scheduler::new(&config)),
+                banners: Arc::new(Banners::new(&config.banners)),
+                email_throttle: Arc::new(Throttle::new(&config.bulk_email)),
+                archive: Arc::new(Archive::open(&config.archive).unwrap()),
+                email: configured_email(&config, Email::default()),
+                config,
+                ..SharedState::clone(&state.shared)
+            }),
+        }
+    }
 }
 
-fn templates<'a>() -> TinyTemplate<'a> {
+/// Builds the `AppState` once from the command line and config, checking
+/// them first, so a typo in an address, a CSV file the server can't write or
+/// a template that doesn't render stops the server at startup, saying what to
+/// fix, instead of failing on the first guest
+#[derive(Clone, Debug)]
+pub struct AppStateBuilder {
+    admin: String,
+    from: String,
+    csv: String,
+    test: bool,
+    config: Config,
+    config_file: Option<String>,
+}
+impl Default for AppStateBuilder {
+    fn default() -> Self {
+        Self {
+            admin: String::default(),
+            from: String::default(),
+            csv: "rsvp.csv".to_string(),
+            test: false,
+            config: Config::default(),
+            config_file: None,
+        }
+    }
+}
+impl AppStateBuilder {
+    /// Address which receives the notifications
+    pub fn admin(mut self, admin: &str) -> Self {
+        self.admin = admin.to_string();
+        self
+    }
+
+    /// Address which sends the emails
+    pub fn sender(mut self, from: &str) -> Self {
+        self.from = from.to_string();
+        self
+    }
+
+    /// CSV file of the RSVPs, created if it doesn't exist yet
+    pub fn csv(mut self, csv: &str) -> Self {
+        self.csv = csv.to_string();
+        self
+    }
+
+    /// Log emails instead of sending them
+    pub fn test(mut self, test: bool) -> Self {
+        self.test = test;
+        self
+    }
+
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// File the config was read from, to reload it without a restart
+    pub fn config_file(mut self, path: Option<&str>) -> Self {
+        self.config_file = path.map(str::to_string);
+        self
+    }
+
+    pub fn build<'a>(self) -> Result<AppState<'a>, Error> {
+        let Self {
+            admin,
+            from,
+            csv,
+            test,
+            config,
+            config_file,
+        } = self;
+        check_address("sender", &from)?;
+        check_address("admin", &admin)?;
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let tt = templates(&config, Arc::clone(&clock));
+        check_templates(tt.as_ref(), &config)?;
+        for (name, columns) in &config.export.presets {
+            export::check_columns(columns).map_err(|error| {
+                Error::Startup(format!(
+                    "The export preset {} can't be used ({}), check `[export.presets]` in \
+                     the config",
+                    name, error
+                ))
+            })?;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&csv)
+            .map_err(|error| {
+                Error::Startup(format!(
+                    "Could not open the CSV file {} for reading and writing ({}), check that \
+                     its directory exists and the server's user can write to both",
+                    csv, error
+                ))
+            })?;
+        let mut db = CsvDb::new_with_clock(file, Arc::clone(&clock));
+        configure_db(&mut db, &config);
+        db.migrate().map_err(|error| {
+            Error::Startup(format!(
+                "Could not read the guest list in {} ({}), check that it's a CSV file \
+                 with a header line",
+                csv, error
+            ))
+        })?;
+        db.git = GitHistory::open(&config.git, &csv).unwrap_or_else(|error| {
+            error!("Could not open the git history: {}", error);
+            None
+        });
+        db.s3 = S3Backup::new(&config.s3, &csv, &config.audit_log);
+        let contents = db.dump();
+        // picks up any edits made to the file while the server was stopped
+        if let Some(git) = &db.git {
+            git.record(&contents, "startup");
+        }
+        if let Err(error) = Backups::new(&config.backups).take(&contents, db.now(), "startup") {
+            error!("Could not take a snapshot at startup: {}", error);
+        }
+        let audit = AuditLog::open(&config.audit_log).map_err(|error| {
+            Error::Startup(format!(
+                "Could not open the audit log {} ({}), check `audit_log` in the config",
+                config.audit_log, error
+            ))
+        })?;
+        #[cfg(not(feature = "xlsx"))]
+        if config.attachment_format == crate::config::AttachmentFormat::Xlsx {
+            log::warn!("Built without the xlsx feature, attaching the CSV file instead");
+        }
+        let archive = Archive::open(&config.archive)?;
+        if archive.frozen().is_some() {
+            info!("The site is archived, so it's read-only");
+        }
+        let acme = match config.tls.domains.is_empty() {
+            true => None,
+            false => Some(Arc::new(Acme::new(&config.tls)?)),
+        };
+        Ok(AppState {
+            tt,
+            shared: Arc::new(SharedState {
+                audit: Arc::new(audit),
+                test,
+                write_permits: write_permits(&config),
+                scheduler: Arc::new(Scheduler::new(&config)),
+                banners: Arc::new(Banners::new(&config.banners)),
+                email_throttle: Arc::new(Throttle::new(&config.bulk_email)),
+                db: Arc::new(RwLock::new(db)),
+                csv,
+                started_at: clock.now(),
+                clock,
+                replays: Arc::default(),
+                acme,
+                metrics: Arc::default(),
+                access_log: AccessLog::open(&config.access_log)?.map(Arc::new),
+                analytics: Analytics::open(&config.analytics)?.map(Arc::new),
+                contact_limiter: Arc::default(),
+                quarantine: Arc::new(Quarantine::open(&config.contact)?),
+                hooks: Arc::new(Hooks::open(&config.hooks)?),
+                fetch_lockout: Arc::default(),
+                code_attempts: Arc::default(),
+                password_lockout: Arc::default(),
+                registry_clicks: Arc::new(RegistryClicks::open(&config.registry)?),
+                songs: Arc::new(SongRequests::open(&config.songs)?),
+                archive: Arc::new(archive),
+                reloader: Arc::new(Reloader::new(config_file, config.clone())),
+                email: configured_email(&config, Email::new(&from, &admin)),
+                config,
+            }),
+        })
+    }
+}
+
+/// An address the emails are sent from or to, which lettre has to parse
+pub fn check_address(role: &str, address: &str) -> Result<(), Error> {
+    address.parse::<Mailbox>().map(|_| ()).map_err(|error| {
+        Error::Startup(format!(
+            "The {} address {:?} isn't a valid email address ({}), use one like \
+             rsvp@example.com or \"Jane and John <rsvp@example.com>\"",
+            role, address, error
+        ))
+    })
+}
+
+/// Render every page with its preview context, and each error page from the
+/// config, so a broken template shows up now rather than to a guest
+pub fn check_templates(tt: &dyn Templates, config: &Config) -> Result<(), Error> {
+    for (name, _) in PAGE_TEMPLATES {
+        let ctx = sample_context(name).unwrap_or_else(|| json!({}));
+        tt.render(name, &ctx).map_err(|error| {
+            Error::Startup(format!("Could not render the {} page: {}", name, error))
+        })?;
+    }
+    for (status, page) in &config.error_pages {
+        let status = StatusCode::from_bytes(status.as_bytes()).map_err(|_| {
+            Error::Startup(format!(
+                "[error_pages.\"{}\"] isn't an HTTP status code, use one like \"404\"",
+                status
+            ))
+        })?;
+        let Some(path) = &page.template else {
+            continue;
+        };
+        fs::File::open(path).map_err(|error| {
+            Error::Startup(format!(
+                "Could not read the template {} for {} errors ({})",
+                path,
+                status.as_str(),
+                error
+            ))
+        })?;
+        let ctx = json!({
+            "error": page.message.clone().unwrap_or_default(),
+            "status_code": status.as_str(),
+            "request_id": "",
+        });
+        tt.render(&error_template(status), &ctx).map_err(|error| {
+            Error::Startup(format!(
+                "Could not render the template {} for {} errors: {}",
+                path,
+                status.as_str(),
+                error
+            ))
+        })?;
+    }
+    for (language, pages) in &config.languages.pages {
+        let tt = localized_templates(config, Arc::new(SystemClock), Some(language));
+        for (name, path) in pages {
+            let ctx = sample_context(name)
+                .filter(|_| PAGE_TEMPLATES.iter().any(|(page, _)| page == name))
+                .ok_or_else(|| {
+                    Error::Startup(format!(
+                        "[languages.pages.{}] has {:?}, which isn't a page, use one like \
+                         \"index.html\"",
+                        language, name
+                    ))
+                })?;
+            fs::File::open(path).map_err(|error| {
+                Error::Startup(format!(
+                    "Could not read the {} template {} for {} ({})",
+                    language, path, name, error
+                ))
+            })?;
+            tt.render(name, &ctx).map_err(|error| {
+                Error::Startup(format!(
+                    "Could not render the {} template {} for {}: {}",
+                    language, path, name, error
+                ))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Settings from the config that the CSV file enforces
+pub fn configure_db(db: &mut CsvDb, config: &Config) {
+    db.short_code_length = config.short_links.length;
+    db.meal_limits = config.meal_limits.clone();
+    db.shuttle_stops = config.shuttle.stops.clone();
+    db.private_event = config.private_event.clone();
+    db.childcare = config.childcare.clone();
+    db.undo_history = config.undo_history;
+    db.limits = config.limits.clone();
+}
+
+/// The email settings from the config, keeping the addresses
+fn configured_email(config: &Config, email: Email) -> Email {
+    Email {
+        timezone: config.timezone,
+        max_attachment_bytes: config.max_attachment_bytes,
+        attachment_format: config.attachment_format,
+        dkim: Email::load_dkim(&config.dkim).unwrap_or_else(|error| {
+            error!(
+                "Could not load the DKIM key, sending unsigned email: {}",
+                error
+            );
+            None
+        }),
+        download_url: config.url("/admin/rsvps.csv"),
+        default_language: language::normalize(&config.languages.default),
+        translations: Arc::new(Email::load_translations(&config.languages)),
+        enabled: config.features.email,
+        ..email
+    }
+}
+
+pub fn write_permits(config: &Config) -> Arc<Semaphore> {
+    Arc::new(Semaphore::new(config.max_pending_writes))
+}
+
+fn tiny_templates<'a>(times: &TimeFormats) -> TinyTemplate<'a> {
     let mut tt = TinyTemplate::new();
-    tt.add_template("fetch.html", FETCH).unwrap();
-    tt.add_template("index.html", INDEX).unwrap();
-    tt.add_template("rsvp.html", RSVP).unwrap();
-    tt.add_template("error.html", ERROR).unwrap();
-    tt.add_template("confirm.html", CONFIRM).unwrap();
-    tt.add_template("photos.html", PHOTOS).unwrap();
+    times.add_formatters(&mut tt);
+    for (name, template) in PAGE_TEMPLATES {
+        tt.add_template(name, template).unwrap();
+    }
     tt
 }
+
+pub fn templates<'a>(config: &Config, clock: Arc<dyn Clock>) -> Box<dyn Templates + 'a> {
+    localized_templates(config, clock, None)
+}
+
+/// The pages in one of `[languages.pages]`, with the built-in ones for any
+/// it doesn't translate, or in the default language for `None`
+pub fn localized_templates<'a>(
+    config: &Config,
+    clock: Arc<dyn Clock>,
+    language: Option<&str>,
+) -> Box<dyn Templates + 'a> {
+    let times = TimeFormats::new(config.timezone, clock);
+    let mut site = config.site.clone();
+    site.image = config.absolute_url(&site.image);
+    let lang = language.unwrap_or(&config.languages.default);
+    let mut files = config
+        .languages
+        .pages
+        .get(lang)
+        .cloned()
+        .unwrap_or_default();
+    files.extend(config.error_pages.iter().filter_map(|(status, page)| {
+        let status = StatusCode::from_bytes(status.as_bytes()).ok()?;
+        Some((error_template(status), page.template.clone()?))
+    }));
+    let page_languages = config.languages.page_languages();
+    let switcher = match page_languages.len() {
+        1 => vec![],
+        _ => page_languages
+            .iter()
+            .map(|code| {
+                json!({
+                    "code": code,
+                    "href": format!("/{}/", code),
+                    "current": *code == lang,
+                })
+            })
+            .collect(),
+    };
+    Box::new(WithGlobals {
+        inner: Box::new(WithFiles::new(
+            engine(config.template_engine, &times),
+            files,
+            times,
+        )),
+        globals: json!({
+            "theme": config.theme,
+            "site": site,
+            "base_url": config.base_url,
+            "fields": Form::new(&config.fields).context(),
+            "registries": config.registry.links,
+            "lodging": !config.travel.hotels.is_empty(),
+            "song_requests": config.songs.enabled,
+            "photos": cfg!(feature = "photos") && config.features.photos,
+            "lang": lang,
+            "languages": switcher,
+            "private_event": match config.private_event.tag.trim().is_empty() {
+                true => "",
+                false => config.private_event.title.as_str(),
+            },
+        }),
+    })
+}
+
+#[cfg(feature = "tera")]
+fn engine<'a>(engine: TemplateEngine, times: &TimeFormats) -> Box<dyn Templates + 'a> {
+    match engine {
+        TemplateEngine::TinyTemplate => Box::new(tiny_templates(times)),
+        TemplateEngine::Tera => Box::new(TeraTemplates::new(times.clone()).unwrap()),
+    }
+}
+
+#[cfg(not(feature = "tera"))]
+fn engine<'a>(engine: TemplateEngine, times: &TimeFormats) -> Box<dyn Templates + 'a> {
+    if engine == TemplateEngine::Tera {
+        log::warn!("Built without the tera feature, using TinyTemplate instead");
+    }
+    Box::new(tiny_templates(times))
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::config::{BackupConfig, ErrorPageConfig},
+        tempfile::tempdir,
+    };
+
+    #[test]
+    fn builder() {
+        let dir = tempdir().unwrap();
+        let csv = dir.path().join("rsvp.csv");
+        let mut config = Config {
+            audit_log: dir.path().join("audit.jsonl").to_str().unwrap().to_string(),
+            backups: BackupConfig {
+                dir: dir.path().join("backups").to_str().unwrap().to_string(),
+                ..BackupConfig::default()
+            },
+            ..Config::default()
+        };
+        let builder = AppState::builder()
+            .admin("admin@example.com")
+            .sender("Jane and John <rsvp@example.com>")
+            .csv(csv.to_str().unwrap())
+            .test(true)
+            .config(config.clone());
+        let state = builder.clone().build().unwrap();
+        assert_eq!(state.email.from, "Jane and John <rsvp@example.com>");
+        assert!(csv.is_file());
+        assert_eq!(fs::read_dir(dir.path().join("backups")).unwrap().count(), 1);
+
+        // every worker shares the one CSV file and audit log
+        let shared = state.shared();
+        let (first, second) = (shared.worker(), shared.worker());
+        assert!(Arc::ptr_eq(&first.db, &state.db));
+        assert!(Arc::ptr_eq(&second.db, &state.db));
+        assert!(Arc::ptr_eq(&second.audit, &state.audit));
+        assert_eq!(fs::read_dir(dir.path().join("backups")).unwrap().count(), 1);
+
+        let error = builder.clone().sender("rsvp at example.com").build();
+        assert!(matches!(error, Err(Error::Startup(e)) if e.contains("sender address")));
+        let missing = dir.path().join("missing").join("rsvp.csv");
+        let error = builder.clone().csv(missing.to_str().unwrap()).build();
+        assert!(matches!(error, Err(Error::Startup(e)) if e.contains("missing")));
+
+        let page = |path: &str| ErrorPageConfig {
+            message: None,
+            template: Some(path.to_string()),
+        };
+        let broken = dir.path().join("404.html");
+        fs::write(&broken, "<p>{ error</p>").unwrap();
+        config
+            .error_pages
+            .insert("404".to_string(), page(broken.to_str().unwrap()));
+        let error = builder.clone().config(config.clone()).build();
+        assert!(matches!(error, Err(Error::Startup(e)) if e.contains("for 404 errors")));
+        fs::write(&broken, "<p>{error}</p>").unwrap();
+        builder.clone().config(config.clone()).build().unwrap();
+        config
+            .error_pages
+            .insert("4O4".to_string(), page(broken.to_str().unwrap()));
+        let error = builder.config(config).build();
+        assert!(matches!(error, Err(Error::Startup(e)) if e.contains("4O4")));
+    }
+
+    #[cfg(feature = "tera")]
+    #[test]
+    fn tera_pages_render() {
+        let tt = templates(
+            &Config {
+                template_engine: TemplateEngine::Tera,
+                ..Config::default()
+            },
+            Arc::new(SystemClock),
+        );
+        for (name, _) in PAGE_TEMPLATES {
+            let ctx = sample_context(name).expect(name);
+            tt.render(name, &ctx).expect(name);
+        }
+        let ctx = sample_context("index.html").unwrap();
+        let body = tt.render("index.html", &ctx).unwrap();
+        assert!(body.contains("admin@example.com"));
+        assert!(body.contains("index-drawer"));
+
+        // the guests' pages all share the chrome, the RSVP flow included
+        for name in ["index.html", "fetch.html", "rsvp.html", "confirm.html"] {
+            assert!(TERA_TEMPLATES.iter().any(|(n, _)| *n == name), "{}", name);
+        }
+        let ctx = sample_context("rsvp.html").unwrap();
+        let body = tt.render("rsvp.html", &ctx).unwrap();
+        assert!(body.contains("function plusOneChanged()"));
+        assert!(body.contains(r#"id="option-dietary-0""#));
+    }
+}
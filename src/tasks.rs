@@ -0,0 +1,316 @@
+use {
+    crate::{backup::Backups, config::Config, error::Error, photos, state::AppState, travel},
+    actix_web::web,
+    chrono::{DateTime, Duration, Utc},
+    derive_more::Display,
+    log::{error, info, warn},
+    serde::Serialize,
+    std::{
+        sync::{Arc, Mutex, MutexGuard},
+        time::Duration as StdDuration,
+    },
+    tokio::time::{interval_at, Instant},
+};
+
+/// Work done in the background on a schedule
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum Task {
+    /// Take a snapshot of the CSV file, on top of the one at startup
+    #[display(fmt = "snapshot")]
+    Snapshot,
+    /// Upload to the S3 bucket, in case an upload after a change failed
+    #[display(fmt = "upload")]
+    Upload,
+    /// Drop API responses kept for replays once they're too old, even when
+    /// no API requests come in to do it
+    #[display(fmt = "expire_replays")]
+    ExpireReplays,
+    /// Get a new HTTPS certificate when it's close to expiring
+    #[display(fmt = "renew_certificate")]
+    RenewCertificate,
+    /// Make WebP and AVIF copies of photos that don't have them yet
+    #[display(fmt = "photo_variants")]
+    PhotoVariants,
+    /// Email guests who need lodging before the hotel blocks close
+    #[display(fmt = "lodging_reminders")]
+    LodgingReminders,
+    /// Write the page view counts to their file
+    #[display(fmt = "save_analytics")]
+    SaveAnalytics,
+}
+impl Task {
+    pub const ALL: &'static [Task] = &[
+        Task::Snapshot,
+        Task::Upload,
+        Task::ExpireReplays,
+        Task::RenewCertificate,
+        Task::PhotoVariants,
+        Task::LodgingReminders,
+        Task::SaveAnalytics,
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|task| task.to_string() == name)
+            .copied()
+    }
+
+    /// Seconds between runs, or 0 if the task is off
+    fn every_secs(&self, config: &Config) -> u64 {
+        match self {
+            Self::Snapshot => config.tasks.snapshot_every_secs,
+            Self::Upload if config.s3.bucket.is_none() => 0,
+            Self::Upload => config.tasks.upload_every_secs,
+            Self::ExpireReplays => config.tasks.expire_replays_every_secs,
+            Self::RenewCertificate if config.tls.domains.is_empty() => 0,
+            Self::RenewCertificate => config.tasks.renew_certificate_every_secs,
+            Self::PhotoVariants if config.photos.variants.is_empty() => 0,
+            Self::PhotoVariants => config.tasks.photo_variants_every_secs,
+            Self::LodgingReminders
+                if config.travel.reminder_days <= 0
+                    || config.travel.hotels.iter().all(|h| h.cutoff.is_none()) =>
+            {
+                0
+            }
+            Self::LodgingReminders => config.tasks.lodging_reminders_every_secs,
+            Self::SaveAnalytics if config.analytics.path.is_none() => 0,
+            Self::SaveAnalytics => config.tasks.save_analytics_every_secs,
+        }
+    }
+
+    /// Whether the first run is right at startup, rather than after one
+    /// interval
+    fn at_startup(&self) -> bool {
+        matches!(
+            self,
+            Self::RenewCertificate | Self::PhotoVariants | Self::LodgingReminders
+        )
+    }
+
+    async fn run(&self, state: &AppState<'_>) -> Result<(), Error> {
+        match self {
+            Self::Snapshot => {
+                let mut db = state.write_db().await?;
+                Backups::new(&state.config.backups).take(&db.dump(), db.now(), "scheduled")?;
+            }
+            Self::Upload => {
+                let s3 = state.write_db().await?.s3.clone();
+                if let Some(s3) = s3 {
+                    s3.upload().await?;
+                }
+            }
+            Self::ExpireReplays => state.replays().expire(state.clock.now()),
+            Self::RenewCertificate => {
+                if let Some(acme) = &state.acme {
+                    acme.renew_if_needed().await?;
+                }
+            }
+            Self::PhotoVariants => {
+                let config = state.config.photos.clone();
+                let generated = web::block(move || photos::generate_variants(&config))
+                    .await
+                    .map_err(|e| Error::Io(std::io::Error::other(e)))??;
+                if generated > 0 {
+                    info!("Generated {} photo variants", generated);
+                }
+            }
+            // the guests' details may be gone once the site is archived
+            Self::LodgingReminders if state.archive.frozen().is_some() => {}
+            Self::LodgingReminders => travel::send_reminders(state).await?,
+            Self::SaveAnalytics => {
+                if let Some(analytics) = state.analytics.clone() {
+                    web::block(move || analytics.save())
+                        .await
+                        .map_err(|e| Error::Io(std::io::Error::other(e)))??;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a scheduled task has been doing, for the admin
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct TaskStatus {
+    pub name: String,
+    pub every_secs: u64,
+    pub runs: u64,
+    pub last_run: Option<DateTime<Utc>>,
+    /// "ok", or the error from the last run
+    pub last_result: String,
+    /// Unknown until the scheduler starts
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+/// Runs each task that's on at its configured interval, keeping track of how
+/// they went
+pub struct Scheduler {
+    statuses: Mutex<Vec<TaskStatus>>,
+}
+impl Scheduler {
+    pub fn new(config: &Config) -> Self {
+        let statuses = Task::ALL
+            .iter()
+            .map(|task| (task, task.every_secs(config)))
+            .filter(|(_, every_secs)| *every_secs > 0)
+            .map(|(task, every_secs)| TaskStatus {
+                name: task.to_string(),
+                every_secs,
+                runs: 0,
+                last_run: None,
+                last_result: String::default(),
+                next_run: None,
+            })
+            .collect();
+        Self {
+            statuses: Mutex::new(statuses),
+        }
+    }
+
+    /// Every task that's on, in the order they're defined
+    pub fn statuses(&self) -> Vec<TaskStatus> {
+        self.lock().clone()
+    }
+
+    /// Whether the task is on, so can be run
+    pub fn has(&self, task: Task) -> bool {
+        self.lock().iter().any(|s| s.name == task.to_string())
+    }
+
+    /// Run every task that's on in the background, the first time after
+    /// waiting one interval, except for those run at startup.  Must be called
+    /// from within the server's runtime.
+    pub fn start(self: &Arc<Self>, state: web::Data<AppState<'static>>) {
+        for status in self.statuses() {
+            let task = match Task::parse(&status.name) {
+                Some(task) => task,
+                None => continue,
+            };
+            let scheduler = Arc::clone(self);
+            let state = state.clone();
+            let every = StdDuration::from_secs(status.every_secs);
+            actix_web::rt::spawn(async move {
+                let start = match task.at_startup() {
+                    true => Instant::now(),
+                    false => Instant::now() + every,
+                };
+                let mut interval = interval_at(start, every);
+                loop {
+                    scheduler.update(task, |s| {
+                        s.next_run =
+                            Some(state.clock.now() + Duration::seconds(s.every_secs as i64))
+                    });
+                    interval.tick().await;
+                    scheduler.run(task, &state).await;
+                }
+            });
+            info!("Running task {} every {}s", task, status.every_secs);
+        }
+    }
+
+    /// Run a task now with the latest config, so reloads reach it too,
+    /// recording how it went
+    pub async fn run(&self, task: Task, state: &AppState<'_>) {
+        let result = match state.reloader.generation() {
+            0 => task.run(state).await,
+            _ => {
+                let (_, config) = state.reloader.latest();
+                task.run(&state.with_config(config, None)).await
+            }
+        };
+        if let Err(error) = &result {
+            error!("Task {} failed: {}", task, error);
+        }
+        let now = state.clock.now();
+        self.update(task, |status| {
+            status.runs += 1;
+            status.last_run = Some(now);
+            status.last_result = match &result {
+                Ok(()) => "ok".to_string(),
+                Err(error) => error.to_string(),
+            };
+        });
+    }
+
+    fn update<F: FnOnce(&mut TaskStatus)>(&self, task: Task, f: F) {
+        if let Some(status) = self.lock().iter_mut().find(|s| s.name == task.to_string()) {
+            f(status);
+        }
+    }
+
+    /// The statuses, even if a task panicked while updating them, since
+    /// they're only for display
+    fn lock(&self) -> MutexGuard<'_, Vec<TaskStatus>> {
+        self.statuses.lock().unwrap_or_else(|poisoned| {
+            warn!("Recovering the task statuses after a panic");
+            poisoned.into_inner()
+        })
+    }
+}
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(&Config::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::config::{S3Config, TasksConfig, TlsConfig},
+    };
+
+    fn names(scheduler: &Scheduler) -> Vec<String> {
+        scheduler.statuses().into_iter().map(|s| s.name).collect()
+    }
+
+    #[test]
+    fn schedules() {
+        assert_eq!(
+            names(&Scheduler::default()),
+            vec!["snapshot", "expire_replays"]
+        );
+        let config = Config {
+            tasks: TasksConfig {
+                snapshot_every_secs: 0,
+                ..TasksConfig::default()
+            },
+            s3: S3Config {
+                bucket: Some("backups".to_string()),
+                ..S3Config::default()
+            },
+            ..Config::default()
+        };
+        let scheduler = Scheduler::new(&config);
+        assert_eq!(names(&scheduler), vec!["upload", "expire_replays"]);
+        assert!(!scheduler.has(Task::Snapshot));
+        assert_eq!(Task::parse("upload"), Some(Task::Upload));
+        assert_eq!(Task::parse("unknown"), None);
+
+        let config = Config {
+            tls: TlsConfig {
+                domains: vec!["example.com".to_string()],
+                ..TlsConfig::default()
+            },
+            ..Config::default()
+        };
+        assert_eq!(
+            names(&Scheduler::new(&config)),
+            vec!["snapshot", "expire_replays", "renew_certificate"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn run() {
+        let state = AppState::default();
+        let scheduler = Scheduler::default();
+        scheduler.run(Task::ExpireReplays, &state).await;
+        let status = scheduler.statuses().pop().unwrap();
+        assert_eq!(status.name, "expire_replays");
+        assert_eq!(status.runs, 1);
+        assert_eq!(status.last_result, "ok");
+        assert!(status.last_run.is_some());
+        assert!(status.next_run.is_none());
+    }
+}
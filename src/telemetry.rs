@@ -0,0 +1,76 @@
+use crate::{config::TracingConfig, logs};
+
+/// Flushes any pending traces when dropped, so keep it alive until the server
+/// stops
+pub struct Telemetry {
+    #[cfg(feature = "otlp")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(error) = provider.shutdown() {
+                log::error!("Could not flush traces: {:?}", error);
+            }
+        }
+    }
+}
+
+/// Set up logging, and the OTLP trace exporter if configured
+#[cfg(feature = "otlp")]
+pub fn init(config: &TracingConfig) -> Telemetry {
+    use {
+        crate::logs::RecentLogs,
+        opentelemetry::{trace::TracerProvider as _, KeyValue},
+        opentelemetry_otlp::{SpanExporter, WithExportConfig},
+        opentelemetry_sdk::{runtime, trace::TracerProvider, Resource},
+        tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter},
+    };
+
+    let endpoint = match &config.otlp_endpoint {
+        Some(endpoint) => endpoint,
+        None => {
+            logs::init();
+            return Telemetry { provider: None };
+        }
+    };
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .expect("Could not create the OTLP exporter");
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            config.service_name.clone(),
+        )]))
+        .build();
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    // log records from `log` macros are forwarded to the subscriber too
+    tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(|| RecentLogs),
+        )
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .init();
+    log::info!("Exporting traces to {}", endpoint);
+    Telemetry {
+        provider: Some(provider),
+    }
+}
+
+/// Set up logging, and the OTLP trace exporter if configured
+#[cfg(not(feature = "otlp"))]
+pub fn init(config: &TracingConfig) -> Telemetry {
+    logs::init();
+    if config.otlp_endpoint.is_some() {
+        log::warn!("Built without the otlp feature, traces will not be exported");
+    }
+    Telemetry {}
+}
@@ -0,0 +1,162 @@
+use {
+    crate::{config::Config, error::Error, model::RsvpModel},
+    chrono::{DateTime, TimeZone, Utc},
+    csv::WriterBuilder,
+    serde::{Deserialize, Serialize},
+};
+
+/// A party the couple owes a thank-you card
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ThankYou {
+    pub name: String,
+    pub plus_one_name: String,
+    pub email: String,
+    pub gift: String,
+    pub thank_you_sent: bool,
+}
+impl From<&RsvpModel> for ThankYou {
+    fn from(record: &RsvpModel) -> Self {
+        Self {
+            name: record.name.clone(),
+            plus_one_name: match record.plus_one_attending_any() {
+                true => record.plus_one_name.clone(),
+                false => String::default(),
+            },
+            email: record.email.clone(),
+            gift: record.gift.clone(),
+            thank_you_sent: record.thank_you_sent,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ThanksContext {
+    pub parties: Vec<ThankYou>,
+    pub total: usize,
+    pub sent: usize,
+    /// Whether the last event has started, before which the list is only a
+    /// preview
+    pub event_over: bool,
+}
+
+/// A party's card and gift, as ticked off by the admin.  An unticked
+/// checkbox isn't sent at all.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ThanksParams {
+    pub name: String,
+    #[serde(default)]
+    pub thank_you_sent: bool,
+    #[serde(default)]
+    pub gift: String,
+}
+
+/// Whether the last dated event has started, or `true` if none has a date
+pub fn event_over(config: &Config, now: DateTime<Utc>) -> bool {
+    config
+        .events
+        .iter()
+        .filter_map(|event| event.starts_at)
+        .max()
+        .and_then(|local| config.timezone.0.from_local_datetime(&local).earliest())
+        .is_none_or(|starts_at| starts_at <= now)
+}
+
+/// Parties who came to anything, by name
+fn attended(records: &[RsvpModel]) -> Vec<&RsvpModel> {
+    let mut parties = records
+        .iter()
+        .filter(|r| r.is_attending_any())
+        .collect::<Vec<_>>();
+    parties.sort_by_key(|r| r.name_key.clone());
+    parties
+}
+
+pub fn context(records: &[RsvpModel], event_over: bool) -> ThanksContext {
+    let parties = attended(records)
+        .into_iter()
+        .map(ThankYou::from)
+        .collect::<Vec<_>>();
+    ThanksContext {
+        total: parties.len(),
+        sent: parties.iter().filter(|p| p.thank_you_sent).count(),
+        parties,
+        event_over,
+    }
+}
+
+/// CSV of the same list, for writing the cards away from the site
+pub fn csv(records: &[RsvpModel]) -> Result<String, Error> {
+    let mut wtr = WriterBuilder::new().from_writer(vec![]);
+    wtr.write_record(["Name", "Plus-one", "Email", "Gift", "Thank-you sent"])?;
+    for party in attended(records).into_iter().map(ThankYou::from) {
+        wtr.write_record([
+            party.name.as_str(),
+            party.plus_one_name.as_str(),
+            party.email.as_str(),
+            party.gift.as_str(),
+            if party.thank_you_sent { "yes" } else { "no" },
+        ])?;
+    }
+    let bytes = wtr.into_inner().map_err(|e| Error::from(e.into_error()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            csvdb::test::{test_db, test_rsvp},
+            model::RsvpParams,
+        },
+    };
+
+    #[test]
+    fn tracker() {
+        let config = Config::from_toml(
+            "timezone = \"America/New_York\"\n\
+             [[events]]\nstarts_at = \"2024-06-01T16:00:00\"\n\
+             [[events]]\nstarts_at = \"2024-05-31T19:00:00\"\n",
+        )
+        .unwrap();
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        assert!(!event_over(&config, at("2024-06-01T19:59:00Z")));
+        assert!(event_over(&config, at("2024-06-01T20:00:00Z")));
+        assert!(event_over(&Config::default(), at("2000-01-01T00:00:00Z")));
+
+        let mut db = test_db(0);
+        for (name, attending) in [("Jane", true), ("John", false), ("Jim", true)] {
+            db.upsert(&RsvpParams {
+                name: name.to_string(),
+                email: format!("{}@example.com", name.to_lowercase()),
+                attending,
+                attending_secondary: attending,
+                plus_one_attending: name == "Jim",
+                plus_one_attending_secondary: name == "Jim",
+                ..test_rsvp()
+            })
+            .unwrap();
+        }
+        db.update_with("Jim", |r| {
+            r.thank_you_sent = true;
+            r.gift = "Toaster, blue".to_string();
+        })
+        .unwrap();
+        let records = db.get_all().unwrap();
+        let ctx = context(&records, true);
+        assert_eq!(
+            ctx.parties
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>(),
+            ["Jane", "Jim"]
+        );
+        assert_eq!(ctx.sent, 1);
+        assert_eq!(
+            csv(&records).unwrap(),
+            "Name,Plus-one,Email,Gift,Thank-you sent\n\
+             Jane,,jane@example.com,,no\n\
+             Jim,Johnson,jim@example.com,\"Toaster, blue\",yes\n"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use {
+    crate::config::BulkEmailConfig,
+    actix_web::rt::time::sleep,
+    log::info,
+    rand::Rng,
+    std::time::{Duration, Instant},
+    tokio::sync::Mutex,
+};
+
+/// Paces emails sent to many guests at once, like announcements, so the
+/// provider doesn't start rejecting them or flag the sender as spam halfway
+/// through
+#[derive(Debug, Default)]
+pub struct Throttle {
+    config: BulkEmailConfig,
+    pace: Mutex<Pace>,
+}
+
+/// When the last email went out, and how many since the last batch pause
+#[derive(Debug, Default)]
+struct Pace {
+    last_sent: Option<Instant>,
+    in_batch: usize,
+}
+
+impl Pace {
+    /// How long to wait before sending the next email at `now`, counting it
+    /// as sent once the wait is over
+    fn next(&mut self, config: &BulkEmailConfig, now: Instant, jitter: Duration) -> Duration {
+        let pause = match self.last_sent {
+            None => Duration::ZERO,
+            Some(last_sent) => {
+                let elapsed = now.saturating_duration_since(last_sent);
+                let batch_pause = Duration::from_millis(config.batch_pause_ms);
+                if config.batch_size > 0 && elapsed >= batch_pause {
+                    // already rested as long as a batch pause
+                    self.in_batch = 0;
+                }
+                let wait = if config.batch_size > 0 && self.in_batch >= config.batch_size {
+                    info!(
+                        "Sent a batch of {} emails, pausing for {}s",
+                        self.in_batch,
+                        batch_pause.as_secs()
+                    );
+                    self.in_batch = 0;
+                    batch_pause
+                } else {
+                    Duration::from_millis(config.interval_ms)
+                };
+                (last_sent + wait + jitter).saturating_duration_since(now)
+            }
+        };
+        self.last_sent = Some(now + pause);
+        self.in_batch += 1;
+        pause
+    }
+}
+
+impl Throttle {
+    pub fn new(config: &BulkEmailConfig) -> Self {
+        Self {
+            config: config.clone(),
+            pace: Mutex::default(),
+        }
+    }
+
+    /// Wait for the next email's turn.  Callers queue up behind each other,
+    /// so hold on to nothing else while waiting.
+    pub async fn wait(&self) {
+        let mut pace = self.pace.lock().await;
+        let jitter = match self.config.jitter_ms {
+            0 => Duration::ZERO,
+            max => Duration::from_millis(rand::thread_rng().gen_range(0..=max)),
+        };
+        let pause = pace.next(&self.config, Instant::now(), jitter);
+        sleep(pause).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pace() {
+        let config = BulkEmailConfig {
+            interval_ms: 1000,
+            batch_size: 3,
+            batch_pause_ms: 60_000,
+            jitter_ms: 0,
+        };
+        let mut pace = Pace::default();
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        assert_eq!(pace.next(&config, start, Duration::ZERO), Duration::ZERO);
+        assert_eq!(pace.next(&config, start, Duration::ZERO), second);
+        // time already spent counts towards the pause, and jitter adds to it
+        let at = start + Duration::from_millis(1500);
+        let jitter = Duration::from_millis(200);
+        assert_eq!(pace.next(&config, at, jitter), Duration::from_millis(700));
+        // the fourth email waits out the batch
+        let at = start + Duration::from_millis(2200);
+        assert_eq!(
+            pace.next(&config, at, Duration::ZERO),
+            Duration::from_secs(60)
+        );
+        let at = start + Duration::from_millis(62_200);
+        assert_eq!(pace.next(&config, at, Duration::ZERO), second);
+
+        // resting as long as a batch pause starts a new batch
+        let at = start + Duration::from_secs(200);
+        assert_eq!(pace.next(&config, at, Duration::ZERO), Duration::ZERO);
+        assert_eq!(pace.next(&config, at, Duration::ZERO), second);
+        assert_eq!(pace.next(&config, at, Duration::ZERO), 2 * second);
+        assert_eq!(
+            pace.next(&config, at, Duration::ZERO),
+            Duration::from_secs(62)
+        );
+
+        let unbatched = BulkEmailConfig {
+            batch_size: 0,
+            ..config
+        };
+        let mut pace = Pace::default();
+        for _ in 0..100 {
+            pace.next(&unbatched, start, Duration::ZERO);
+        }
+        assert_eq!(
+            pace.next(&unbatched, start, Duration::ZERO),
+            Duration::from_secs(100)
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use {
+    crate::clock::Clock,
+    chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc},
+    chrono_tz::Tz,
+    serde::{de, Deserialize, Deserializer},
+    serde_json::Value,
+    std::{fmt, sync::Arc},
+    tinytemplate::TinyTemplate,
+};
+
+/// How times are shown to guests and the admin, e.g. "Sat, Jun 1, 2024 at
+/// 2:15 PM EDT"
+const DISPLAY_FORMAT: &str = "%a, %b %-d, %Y at %-I:%M %p %Z";
+
+/// The event's time zone, for showing times stored in UTC the way guests and
+/// the admin read them.  Configured by IANA name, like "America/New_York".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timezone(pub Tz);
+impl Default for Timezone {
+    fn default() -> Self {
+        Self(Tz::UTC)
+    }
+}
+impl<'de> Deserialize<'de> for Timezone {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map(Self).map_err(|_| {
+            de::Error::custom(format!(
+                "unknown time zone {:?}, expected a name like \"America/New_York\"",
+                name
+            ))
+        })
+    }
+}
+impl fmt::Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0.name())
+    }
+}
+impl Timezone {
+    pub fn format(&self, at: DateTime<Utc>) -> String {
+        at.with_timezone(&self.0).format(DISPLAY_FORMAT).to_string()
+    }
+
+    /// When a day in the event's time zone begins, like the date in a
+    /// "since" field
+    pub fn start_of_day(&self, date: NaiveDate) -> DateTime<Utc> {
+        let midnight = date.and_time(NaiveTime::MIN);
+        self.0
+            .from_local_datetime(&midnight)
+            .earliest()
+            .map(|at| at.with_timezone(&Utc))
+            .unwrap_or_else(|| midnight.and_utc())
+    }
+
+    /// A time from a template context, which is an RFC 3339 string once
+    /// serialized, or `None` if it isn't one
+    pub fn format_value(&self, value: &Value) -> Option<String> {
+        parse_value(value).map(|at| self.format(at))
+    }
+}
+
+/// How long ago, or how long until, `at` is from `now`, like "3 days ago",
+/// counting in whole units
+pub fn relative(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let secs = (now - at).num_seconds();
+    let (count, unit) = match secs.abs() {
+        s if s < 60 => return "just now".to_string(),
+        s if s < 60 * 60 => (s / 60, "minute"),
+        s if s < 24 * 60 * 60 => (s / (60 * 60), "hour"),
+        s if s < 30 * 24 * 60 * 60 => (s / (24 * 60 * 60), "day"),
+        s if s < 365 * 24 * 60 * 60 => (s / (30 * 24 * 60 * 60), "month"),
+        s => (s / (365 * 24 * 60 * 60), "year"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    if secs < 0 {
+        format!("in {} {}{}", count, unit, plural)
+    } else {
+        format!("{} {}{} ago", count, unit, plural)
+    }
+}
+
+/// A time from a template context, which is an RFC 3339 string once
+/// serialized, or `None` if it isn't one
+fn parse_value(value: &Value) -> Option<DateTime<Utc>> {
+    let at = DateTime::parse_from_rfc3339(value.as_str()?).ok()?;
+    Some(at.with_timezone(&Utc))
+}
+
+/// Formatter output for anything that isn't a time: left as it is, or empty
+/// if it's missing
+fn format_other(value: &Value, output: &mut String) -> tinytemplate::error::Result<()> {
+    match value {
+        Value::Null => Ok(()),
+        value => tinytemplate::format(value, output),
+    }
+}
+
+/// The time formatters available to every page template
+#[derive(Clone, Debug)]
+pub struct TimeFormats {
+    pub timezone: Timezone,
+    /// For how long ago things were, so tests can control it
+    pub clock: Arc<dyn Clock>,
+}
+impl TimeFormats {
+    pub fn new(timezone: Timezone, clock: Arc<dyn Clock>) -> Self {
+        Self { timezone, clock }
+    }
+
+    /// `{record.updated_at | local_time}` for the time in the event's time
+    /// zone, and `{record.updated_at | time_ago}` for how long ago it was
+    pub fn add_formatters(&self, tt: &mut TinyTemplate) {
+        let timezone = self.timezone;
+        tt.add_formatter("local_time", move |value, output| {
+            match timezone.format_value(value) {
+                Some(formatted) => {
+                    output.push_str(&formatted);
+                    Ok(())
+                }
+                None => format_other(value, output),
+            }
+        });
+        let clock = Arc::clone(&self.clock);
+        tt.add_formatter("time_ago", move |value, output| match parse_value(value) {
+            Some(at) => {
+                output.push_str(&relative(at, clock.now()));
+                Ok(())
+            }
+            None => format_other(value, output),
+        });
+    }
+
+    /// The same formatters as Tera filters
+    #[cfg(feature = "tera")]
+    pub fn register_filters(&self, tera: &mut tera::Tera) {
+        type Args<'a> = &'a std::collections::HashMap<String, Value>;
+        let timezone = self.timezone;
+        tera.register_filter("local_time", move |value: &Value, _: Args| {
+            Ok(timezone
+                .format_value(value)
+                .map(Value::String)
+                .unwrap_or_else(|| value.clone()))
+        });
+        let clock = Arc::clone(&self.clock);
+        tera.register_filter("time_ago", move |value: &Value, _: Args| {
+            Ok(parse_value(value)
+                .map(|at| Value::String(relative(at, clock.now())))
+                .unwrap_or_else(|| value.clone()))
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::clock::MockClock,
+        chrono::{Duration, TimeZone},
+        serde_json::json,
+    };
+
+    #[test]
+    fn format() {
+        let timezone: Timezone = serde_json::from_value(json!("America/New_York")).unwrap();
+        let at = Utc.with_ymd_and_hms(2024, 6, 1, 18, 15, 0).unwrap();
+        assert_eq!(timezone.format(at), "Sat, Jun 1, 2024 at 2:15 PM EDT");
+        let winter = Utc.with_ymd_and_hms(2024, 12, 1, 18, 15, 0).unwrap();
+        assert_eq!(timezone.format(winter), "Sun, Dec 1, 2024 at 1:15 PM EST");
+        assert_eq!(
+            Timezone::default().format(at),
+            "Sat, Jun 1, 2024 at 6:15 PM UTC"
+        );
+        assert!(serde_json::from_value::<Timezone>(json!("Mars/Olympus")).is_err());
+    }
+
+    #[test]
+    fn relative_times() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 18, 15, 0).unwrap();
+        let ago = |duration| relative(now - duration, now);
+        assert_eq!(ago(Duration::seconds(30)), "just now");
+        assert_eq!(ago(Duration::minutes(1)), "1 minute ago");
+        assert_eq!(ago(Duration::minutes(59)), "59 minutes ago");
+        assert_eq!(ago(Duration::hours(5)), "5 hours ago");
+        assert_eq!(ago(Duration::days(3)), "3 days ago");
+        assert_eq!(ago(Duration::days(65)), "2 months ago");
+        assert_eq!(ago(Duration::days(400)), "1 year ago");
+        assert_eq!(ago(Duration::days(-1)), "in 1 day");
+    }
+
+    #[test]
+    fn formatters() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 4, 18, 15, 0).unwrap();
+        let formats = TimeFormats::new(
+            serde_json::from_value(json!("America/New_York")).unwrap(),
+            Arc::new(MockClock::new(now)),
+        );
+        let mut tt = TinyTemplate::new();
+        formats.add_formatters(&mut tt);
+        tt.add_template(
+            "times",
+            "{at | local_time}, {at | time_ago}.{missing | time_ago}{other | local_time}",
+        )
+        .unwrap();
+        let out = tt
+            .render(
+                "times",
+                &json!({ "at": "2024-06-01T18:15:00Z", "missing": null, "other": " & later" }),
+            )
+            .unwrap();
+        assert_eq!(
+            out,
+            "Sat, Jun 1, 2024 at 2:15 PM EDT, 3 days ago. &amp; later"
+        );
+    }
+}
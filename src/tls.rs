@@ -0,0 +1,216 @@
+use {
+    crate::error::Error,
+    log::warn,
+    openssl::{
+        asn1::Asn1Time,
+        bn::{BigNum, MsbOption},
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::{PKey, Private},
+        ssl::{SniError, SslAcceptor, SslAcceptorBuilder, SslMethod},
+        x509::{extension::SubjectAlternativeName, X509NameBuilder, X509},
+    },
+    std::{
+        fs::{self, OpenOptions},
+        io::{ErrorKind, Write},
+        path::Path,
+        sync::{Arc, RwLock},
+    },
+};
+
+/// A certificate chain, the server's own first, with its private key
+pub struct Certificate {
+    pub chain: Vec<X509>,
+    pub key: PKey<Private>,
+}
+impl Certificate {
+    /// Read a PEM certificate chain and private key, or `None` if either
+    /// file is missing
+    pub fn load(cert_path: &Path, key_path: &Path) -> Result<Option<Self>, Error> {
+        let (chain, key) = match (fs::read(cert_path), fs::read(key_path)) {
+            (Ok(chain), Ok(key)) => (chain, key),
+            (Err(error), _) | (_, Err(error)) if error.kind() == ErrorKind::NotFound => {
+                return Ok(None)
+            }
+            (Err(error), _) | (_, Err(error)) => return Err(error.into()),
+        };
+        let certificate = Self {
+            chain: X509::stack_from_pem(&chain)?,
+            key: PKey::private_key_from_pem(&key)?,
+        };
+        certificate.check()?;
+        Ok(Some(certificate))
+    }
+
+    /// Write the chain and key as PEM, with the key only readable by the
+    /// server's user
+    pub fn save(&self, cert_path: &Path, key_path: &Path) -> Result<(), Error> {
+        let mut chain = vec![];
+        for cert in &self.chain {
+            chain.extend(cert.to_pem()?);
+        }
+        fs::write(cert_path, chain)?;
+        write_private(key_path, &self.key.private_key_to_pem_pkcs8()?)
+    }
+
+    /// Placeholder for the domains until a real certificate is issued, which
+    /// browsers will warn about
+    pub fn self_signed(domains: &[String]) -> Result<Self, Error> {
+        let key = PKey::from_ec_key(new_key()?)?;
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_nid(
+            Nid::COMMONNAME,
+            domains.first().map_or("localhost", String::as_str),
+        )?;
+        let name = name.build();
+        let mut serial = BigNum::new()?;
+        serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+        let mut builder = X509::builder()?;
+        builder.set_version(2)?;
+        let serial = serial.to_asn1_integer()?;
+        builder.set_serial_number(&serial)?;
+        builder.set_subject_name(&name)?;
+        builder.set_issuer_name(&name)?;
+        builder.set_pubkey(&key)?;
+        let (not_before, not_after) = (Asn1Time::days_from_now(0)?, Asn1Time::days_from_now(30)?);
+        builder.set_not_before(&not_before)?;
+        builder.set_not_after(&not_after)?;
+        if !domains.is_empty() {
+            let mut names = SubjectAlternativeName::new();
+            for domain in domains {
+                names.dns(domain);
+            }
+            let names = names.build(&builder.x509v3_context(None, None))?;
+            builder.append_extension(names)?;
+        }
+        builder.sign(&key, MessageDigest::sha256())?;
+        Ok(Self {
+            chain: vec![builder.build()],
+            key,
+        })
+    }
+
+    /// Whether the certificate is no longer valid in this many days
+    pub fn expires_within(&self, days: u32) -> Result<bool, Error> {
+        Ok(self.chain[0].not_after() < Asn1Time::days_from_now(days)?)
+    }
+
+    /// Make sure there's a certificate, and that it goes with the key
+    pub fn check(&self) -> Result<(), Error> {
+        match self.chain.first() {
+            Some(cert) if cert.public_key()?.public_eq(&self.key) => Ok(()),
+            Some(_) => Err(Error::Acme(
+                "the certificate doesn't match its private key".to_string(),
+            )),
+            None => Err(Error::Acme("no certificate in the chain".to_string())),
+        }
+    }
+}
+
+/// The certificate currently served for HTTPS, which can be replaced when
+/// renewed without restarting the server
+pub struct Certificates {
+    current: RwLock<Arc<Certificate>>,
+}
+impl Certificates {
+    pub fn new(certificate: Certificate) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(certificate)),
+        }
+    }
+
+    pub fn current(&self) -> Arc<Certificate> {
+        let current = self.current.read().unwrap_or_else(|poisoned| {
+            warn!("Recovering the certificate after a panic");
+            poisoned.into_inner()
+        });
+        Arc::clone(&current)
+    }
+
+    /// Serve this certificate for new connections from now on
+    pub fn set(&self, certificate: Certificate) {
+        let mut current = self.current.write().unwrap_or_else(|poisoned| {
+            warn!("Recovering the certificate after a panic");
+            poisoned.into_inner()
+        });
+        *current = Arc::new(certificate);
+    }
+
+    /// TLS settings for the server, picking up the current certificate for
+    /// each connection
+    pub fn acceptor(self: &Arc<Self>) -> Result<SslAcceptorBuilder, Error> {
+        let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
+        let certificate = self.current();
+        builder.set_private_key(&certificate.key)?;
+        builder.set_certificate(&certificate.chain[0])?;
+        for cert in &certificate.chain[1..] {
+            builder.add_extra_chain_cert(cert.clone())?;
+        }
+        let certificates = Arc::clone(self);
+        // called for every handshake, with or without a server name
+        builder.set_servername_callback(move |ssl, _| {
+            let certificate = certificates.current();
+            ssl.set_private_key(&certificate.key)
+                .and_then(|_| ssl.set_certificate(&certificate.chain[0]))
+                .and_then(|_| {
+                    certificate.chain[1..]
+                        .iter()
+                        .try_for_each(|cert| ssl.add_chain_cert(cert.clone()))
+                })
+                .map_err(|_| SniError::ALERT_FATAL)
+        });
+        Ok(builder)
+    }
+}
+
+/// A new P-256 key, which ACME CAs all accept for accounts and certificates
+pub fn new_key() -> Result<EcKey<Private>, Error> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    Ok(EcKey::generate(&group)?)
+}
+
+/// Write a file only readable by the server's user, like a private key
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn certificates() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert_path, key_path) = (dir.path().join("cert.pem"), dir.path().join("key.pem"));
+        assert!(Certificate::load(&cert_path, &key_path).unwrap().is_none());
+
+        let domains = vec!["example.com".to_string()];
+        let certificate = Certificate::self_signed(&domains).unwrap();
+        certificate.check().unwrap();
+        assert!(!certificate.expires_within(29).unwrap());
+        assert!(certificate.expires_within(31).unwrap());
+        certificate.save(&cert_path, &key_path).unwrap();
+        let loaded = Certificate::load(&cert_path, &key_path).unwrap().unwrap();
+        assert_eq!(
+            loaded.chain[0].to_der().unwrap(),
+            certificate.chain[0].to_der().unwrap()
+        );
+
+        let other = Certificate::self_signed(&domains).unwrap();
+        let mismatched = Certificate {
+            chain: other.chain,
+            key: certificate.key.clone(),
+        };
+        assert!(mismatched.check().is_err());
+
+        let certificates = Arc::new(Certificates::new(certificate));
+        certificates.acceptor().unwrap();
+        certificates.set(loaded);
+    }
+}
@@ -0,0 +1,217 @@
+use {
+    crate::{
+        config::{Config, HotelConfig, TravelConfig},
+        error::Error,
+        model::{EmailKind, RsvpModel},
+        state::AppState,
+    },
+    chrono::{DateTime, Duration, NaiveDate, Utc},
+    log::{error, info},
+};
+
+/// The day it is where the event is, which cutoffs are counted in
+pub fn today(config: &Config, now: DateTime<Utc>) -> NaiveDate {
+    now.with_timezone(&config.timezone.0).date_naive()
+}
+
+/// Hotels whose block closes within `reminder_days`, including today
+pub fn closing_soon(config: &TravelConfig, today: NaiveDate) -> Vec<&HotelConfig> {
+    if config.reminder_days <= 0 {
+        return vec![];
+    }
+    let last = today + Duration::days(config.reminder_days);
+    config
+        .hotels
+        .iter()
+        .filter(|hotel| hotel.cutoff.is_some_and(|c| c >= today && c <= last))
+        .collect()
+}
+
+/// A reminder to book before the blocks close, for one guest
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reminder {
+    pub recipient: RsvpModel,
+    /// The blocks closing soon that the guest wasn't reminded of yet
+    pub hotels: Vec<HotelConfig>,
+}
+impl Reminder {
+    /// The latest cutoff in the reminder, kept on the guest's record
+    pub fn cutoff(&self) -> Option<NaiveDate> {
+        self.hotels.iter().filter_map(|hotel| hotel.cutoff).max()
+    }
+}
+
+/// Guests coming who need lodging, with the blocks closing soon that they
+/// haven't been reminded of
+pub fn reminders(
+    records: Vec<RsvpModel>,
+    config: &TravelConfig,
+    today: NaiveDate,
+) -> Vec<Reminder> {
+    let hotels = closing_soon(config, today);
+    records
+        .into_iter()
+        .filter(|r| {
+            r.needs_lodging && r.is_attending_any() && r.accepts_email(EmailKind::Transactional)
+        })
+        .filter_map(|recipient| {
+            let hotels = hotels
+                .iter()
+                .filter(|hotel| hotel.cutoff > recipient.lodging_reminded)
+                .map(|hotel| (*hotel).clone())
+                .collect::<Vec<_>>();
+            (!hotels.is_empty()).then_some(Reminder { recipient, hotels })
+        })
+        .collect()
+}
+
+/// Email every guest due a reminder, paced like other bulk email, and mark
+/// each one reminded once it's sent so they don't get it again
+pub async fn send_reminders(state: &AppState<'_>) -> Result<(), Error> {
+    let today = today(&state.config, state.clock.now());
+    let records = state.write_db().await?.get_all()?;
+    let reminders = reminders(records, &state.config.travel, today);
+    let travel_url = state.config.url("/travel");
+    let mut sent = 0;
+    for reminder in &reminders {
+        state.email_throttle.wait().await;
+        let result = state
+            .email
+            .send_lodging_reminder(
+                &reminder.recipient,
+                &reminder.hotels,
+                &travel_url,
+                state.test,
+            )
+            .await;
+        match result {
+            Ok(()) => {
+                let cutoff = reminder.cutoff();
+                state
+                    .write_db()
+                    .await?
+                    .update_with(&reminder.recipient.name, |r| r.lodging_reminded = cutoff)?;
+                sent += 1;
+            }
+            Err(error) => error!(
+                "Could not send lodging reminder to {}: {:?}",
+                reminder.recipient.email, error
+            ),
+        }
+    }
+    if sent > 0 {
+        info!("Sent {} lodging reminders", sent);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            csvdb::test::{test_db, test_rsvp},
+            email::Email,
+            model::RsvpParams,
+        },
+        chrono::Days,
+    };
+
+    fn hotel(name: &str, cutoff: Option<NaiveDate>) -> HotelConfig {
+        HotelConfig {
+            name: name.to_string(),
+            cutoff,
+            ..HotelConfig::default()
+        }
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, day).unwrap()
+    }
+
+    #[test]
+    fn due() {
+        let config = TravelConfig {
+            hotels: vec![
+                hotel("Past", Some(date(1))),
+                hotel("Soon", Some(date(10))),
+                hotel("Later", Some(date(30))),
+                hotel("Open", None),
+            ],
+            reminder_days: 7,
+            ..TravelConfig::default()
+        };
+        let names = |hotels: Vec<&HotelConfig>| {
+            hotels
+                .into_iter()
+                .map(|h| h.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(closing_soon(&config, date(5))), ["Soon"]);
+        assert_eq!(names(closing_soon(&config, date(10))), ["Soon"]);
+        assert!(closing_soon(&config, date(11)).is_empty());
+        let off = TravelConfig {
+            reminder_days: 0,
+            ..config.clone()
+        };
+        assert!(closing_soon(&off, date(5)).is_empty());
+
+        let mut db = test_db(0);
+        let mut add = |name: &str, attending: bool, needs_lodging: bool| {
+            db.upsert(&RsvpParams {
+                name: name.to_string(),
+                email: format!("{}@example.com", name.to_lowercase()),
+                attending,
+                attending_secondary: attending,
+                needs_lodging,
+                ..test_rsvp()
+            })
+            .unwrap();
+        };
+        add("Jane", true, true);
+        add("John", true, false);
+        add("Jim", false, true);
+        add("Joan", true, true);
+        db.update_with("Joan", |r| r.lodging_reminded = Some(date(10)))
+            .unwrap();
+        let reminders = reminders(db.get_all().unwrap(), &config, date(5));
+        assert_eq!(reminders.len(), 1);
+        assert_eq!(reminders[0].recipient.name, "Jane");
+        assert_eq!(reminders[0].cutoff(), Some(date(10)));
+    }
+
+    #[actix_rt::test]
+    async fn reminders_share_the_workers_db() {
+        let mut db = test_db(0);
+        db.upsert(&RsvpParams {
+            name: "Jane".to_string(),
+            needs_lodging: true,
+            ..test_rsvp()
+        })
+        .unwrap();
+        let mut state = AppState::new_with_db(db);
+        let soon = today(&state.config, state.clock.now())
+            .checked_add_days(Days::new(3))
+            .unwrap();
+        state.shared_mut().config.travel.hotels = vec![hotel("Inn", Some(soon))];
+        state.shared_mut().email = Email::new("us@example.com", "admin@example.com");
+        let worker = state.shared().worker();
+        worker
+            .write_db()
+            .await
+            .unwrap()
+            .upsert(&RsvpParams {
+                name: "John".to_string(),
+                ..test_rsvp()
+            })
+            .unwrap();
+
+        send_reminders(&state).await.unwrap();
+        let db = worker.write_db().await.unwrap();
+        assert_eq!(
+            db.get("Jane").unwrap().unwrap().lodging_reminded,
+            Some(soon)
+        );
+        assert!(db.get("John").unwrap().is_some());
+    }
+}
@@ -0,0 +1,118 @@
+use {
+    crate::model::RsvpModel,
+    serde::{Deserialize, Serialize},
+    std::collections::BTreeMap,
+};
+
+/// Wave shown for guests added without one, or who RSVPed without being
+/// added first
+pub const NO_WAVE: &str = "None";
+
+/// How one round of invitations is doing
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct WaveStats {
+    pub wave: String,
+    pub parties: u32,
+    pub responded_parties: u32,
+    /// Parties that responded but aren't coming to anything
+    pub declined_parties: u32,
+    /// People coming to at least one event
+    pub attending: u32,
+    /// Percentage of the parties that responded
+    pub response_rate: u32,
+}
+
+/// Response rates for each wave, in the order they're named, with guests
+/// in no wave last.  Empty if no one is in a wave.
+pub fn stats(records: &[RsvpModel]) -> Vec<WaveStats> {
+    if records.iter().all(|r| r.wave.is_empty()) {
+        return vec![];
+    }
+    let mut waves = BTreeMap::<(bool, String), WaveStats>::new();
+    for record in records {
+        let name = if record.wave.is_empty() {
+            NO_WAVE
+        } else {
+            &record.wave
+        };
+        let wave = waves
+            .entry((record.wave.is_empty(), name.to_lowercase()))
+            .or_insert_with(|| WaveStats {
+                wave: name.to_string(),
+                ..WaveStats::default()
+            });
+        wave.parties += 1;
+        if record.responded_at.is_some() {
+            wave.responded_parties += 1;
+            if record.is_attending_any() {
+                wave.attending += 1 + u32::from(record.plus_one_attending_any());
+            } else {
+                wave.declined_parties += 1;
+            }
+        }
+    }
+    waves
+        .into_values()
+        .map(|wave| WaveStats {
+            response_rate: wave.responded_parties * 100 / wave.parties,
+            ..wave
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{csvdb::test::test_add, model::AddParams},
+        chrono::Utc,
+    };
+
+    fn invited(name: &str, wave: &str) -> RsvpModel {
+        RsvpModel::new_with_add(
+            &AddParams {
+                name: name.to_string(),
+                wave: wave.to_string(),
+                ..test_add()
+            },
+            Utc::now(),
+        )
+    }
+
+    fn responded(record: RsvpModel, attending: bool) -> RsvpModel {
+        RsvpModel {
+            attending,
+            responded_at: Some(Utc::now()),
+            ..record
+        }
+    }
+
+    #[test]
+    fn response_rates() {
+        assert_eq!(stats(&[invited("Amy", "")]), vec![]);
+
+        let records = vec![
+            responded(invited("Amy", "B"), true),
+            invited("Bob", "B"),
+            responded(invited("Cal", "A"), false),
+            responded(invited("Dee", "a"), true),
+            responded(invited("Eve", ""), true),
+        ];
+        let stats = stats(&records);
+        let waves = stats.iter().map(|w| w.wave.as_str()).collect::<Vec<_>>();
+        assert_eq!(waves, vec!["A", "B", NO_WAVE]);
+        assert_eq!(
+            stats[0],
+            WaveStats {
+                wave: "A".to_string(),
+                parties: 2,
+                responded_parties: 2,
+                declined_parties: 1,
+                attending: 1,
+                response_rate: 100,
+            }
+        );
+        assert_eq!(stats[1].response_rate, 50);
+        assert_eq!(stats[1].attending, 1);
+    }
+}